@@ -0,0 +1,166 @@
+#import shared::ibl::hammersley
+#import shared::noise::hash
+#import shared::pbr::PI
+
+// This should match BakeSettings in ao_bake.rs
+struct BakeSettings {
+    first_vertex: u32,
+    // Vertices in this dispatch
+    vertex_count: u32,
+    sample_count: u32,
+    // Occluders further than this don't darken the vertex
+    max_distance: f32,
+    // Rays start this far above the surface, so that they don't hit the triangles of the vertex
+    normal_offset: f32,
+}
+
+// A leaf if count is non-zero, otherwise the children are at first and first + 1. This should
+// match BvhNode in ao_bake.rs.
+struct BvhNode {
+    min: vec3<f32>,
+    first: u32,
+    max: vec3<f32>,
+    count: u32,
+}
+
+// World space corners, w is unused
+struct Triangle {
+    a: vec4<f32>,
+    b: vec4<f32>,
+    c: vec4<f32>,
+}
+
+// World space position and normal, w is unused
+struct BakeVertex {
+    position: vec4<f32>,
+    normal: vec4<f32>,
+}
+
+// Enough for a tree built from a few million triangles
+const STACK_SIZE: u32 = 32u;
+
+@group(0) @binding(0)
+var<uniform> settings: BakeSettings;
+@group(0) @binding(1)
+var<storage, read> nodes: array<BvhNode>;
+@group(0) @binding(2)
+var<storage, read> triangles: array<Triangle>;
+@group(0) @binding(3)
+var<storage, read> vertices: array<BakeVertex>;
+@group(0) @binding(4)
+var<storage, read_write> occlusion: array<f32>;
+
+// Distance along the ray to where it enters the box, or a negative value if it misses
+fn intersect_box(origin: vec3<f32>, inverse_direction: vec3<f32>, box_min: vec3<f32>, box_max: vec3<f32>, max_distance: f32) -> f32 {
+    let to_min = (box_min - origin) * inverse_direction;
+    let to_max = (box_max - origin) * inverse_direction;
+    let near = min(to_min, to_max);
+    let far = max(to_min, to_max);
+    let enter = max(max(near.x, near.y), max(near.z, 0.0));
+    let exit = min(min(far.x, far.y), min(far.z, max_distance));
+    return select(-1.0, enter, enter <= exit);
+}
+
+// Möller-Trumbore, for both sides of the triangle
+fn hits_triangle(origin: vec3<f32>, direction: vec3<f32>, triangle: Triangle, max_distance: f32) -> bool {
+    let edge1 = triangle.b.xyz - triangle.a.xyz;
+    let edge2 = triangle.c.xyz - triangle.a.xyz;
+    let p = cross(direction, edge2);
+    let determinant = dot(edge1, p);
+
+    if abs(determinant) < 1e-8 {
+        return false;
+    }
+
+    let inverse_determinant = 1.0 / determinant;
+    let to_origin = origin - triangle.a.xyz;
+    let u = dot(to_origin, p) * inverse_determinant;
+    if u < 0.0 || u > 1.0 {
+        return false;
+    }
+
+    let q = cross(to_origin, edge1);
+    let v = dot(direction, q) * inverse_determinant;
+    if v < 0.0 || u + v > 1.0 {
+        return false;
+    }
+
+    let distance = dot(edge2, q) * inverse_determinant;
+    return distance > 0.0 && distance < max_distance;
+}
+
+// Stops at the first hit, since it doesn't matter which occluder is the closest
+fn is_occluded(origin: vec3<f32>, direction: vec3<f32>, max_distance: f32) -> bool {
+    let inverse_direction = 1.0 / direction;
+    var stack: array<u32, STACK_SIZE>;
+    var stack_size = 1u;
+    stack[0] = 0u;
+
+    while stack_size > 0u {
+        stack_size -= 1u;
+        let node = nodes[stack[stack_size]];
+
+        if intersect_box(origin, inverse_direction, node.min, node.max, max_distance) < 0.0 {
+            continue;
+        }
+
+        if node.count > 0u {
+            for (var i = node.first; i < node.first + node.count; i++) {
+                if hits_triangle(origin, direction, triangles[i], max_distance) {
+                    return true;
+                }
+            }
+        } else if stack_size + 2u <= STACK_SIZE {
+            stack[stack_size] = node.first;
+            stack[stack_size + 1u] = node.first + 1u;
+            stack_size += 2u;
+        }
+    }
+
+    return false;
+}
+
+// Cosine weighted, so that the fraction of unoccluded rays is the ambient occlusion
+fn sample_hemisphere(xi: vec2<f32>, normal: vec3<f32>) -> vec3<f32> {
+    let phi = 2.0 * PI * xi.x;
+    let sin_theta = sqrt(xi.y);
+    let cos_theta = sqrt(1.0 - xi.y);
+    let local = vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+
+    let up = select(vec3<f32>(1.0, 0.0, 0.0), vec3<f32>(0.0, 0.0, 1.0), abs(normal.z) < 0.999);
+    let tangent = normalize(cross(up, normal));
+    let bitangent = cross(normal, tangent);
+
+    return normalize(tangent * local.x + bitangent * local.y + normal * local.z);
+}
+
+@compute @workgroup_size(64)
+fn main(
+    @builtin(global_invocation_id) global_id: vec3<u32>
+) {
+    let index = settings.first_vertex + global_id.x;
+
+    if global_id.x >= settings.vertex_count || index >= arrayLength(&vertices) {
+        return;
+    }
+
+    let vertex = vertices[index];
+    let normal = normalize(vertex.normal.xyz);
+    let origin = vertex.position.xyz + normal * settings.normal_offset;
+
+    // Every vertex rotates the sequence differently, which turns banding into noise
+    let rotation = f32(hash(index)) / 4294967295.0;
+
+    var unoccluded = 0u;
+    for (var i = 0u; i < settings.sample_count; i++) {
+        var xi = hammersley(i, settings.sample_count);
+        xi.x = fract(xi.x + rotation);
+
+        let direction = sample_hemisphere(xi, normal);
+        if !is_occluded(origin, direction, settings.max_distance) {
+            unoccluded += 1u;
+        }
+    }
+
+    occlusion[index] = f32(unoccluded) / f32(settings.sample_count);
+}
@@ -0,0 +1,136 @@
+#import shared::camera::CameraUniform
+#import shared::material_info::{
+    MaterialInfo, material_uv, material_sampler, decode_normal, SAMPLER_BASE_COLOR, SAMPLER_NORMAL
+}
+
+struct Decal {
+    model_matrix: mat4x4<f32>,
+    inverse_model_matrix: mat4x4<f32>,
+    // Linear color and opacity
+    color: vec4<f32>,
+    material_id: u32,
+    normal_opacity: f32,
+    edge_fade: f32,
+    angle_fade: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+@group(1) @binding(0)
+var<storage, read> decals: array<Decal>;
+@group(1) @binding(1)
+var g_depth: texture_depth_2d;
+
+@group(2) @binding(0)
+var<storage, read> material_info: array<MaterialInfo>;
+@group(2) @binding(1)
+var textures: binding_array<texture_2d<f32>>;
+@group(2) @binding(2)
+var samplers: binding_array<sampler>;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) @interpolate(flat) instance_index: u32,
+}
+
+// Only the color and normal are blended into, the alpha channels keep the roughness and
+// metallic of the surface
+struct DecalOutput {
+    @location(0) color: vec4<f32>,
+    @location(1) normal: vec4<f32>,
+}
+
+// Corner N of the unit cube has X in bit 0, Y in bit 1 and Z in bit 2. Counter-clockwise
+// from the outside.
+const CUBE_INDICES = array<u32, 36>(
+    1u, 3u, 7u, 1u, 7u, 5u,
+    0u, 4u, 6u, 0u, 6u, 2u,
+    2u, 6u, 7u, 2u, 7u, 3u,
+    0u, 1u, 5u, 0u, 5u, 4u,
+    4u, 5u, 7u, 4u, 7u, 6u,
+    0u, 2u, 3u, 0u, 3u, 1u,
+);
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32,
+    @builtin(instance_index) instance_index: u32,
+) -> VertexOutput {
+    var out: VertexOutput;
+
+    var indices = CUBE_INDICES;
+    let corner = indices[vertex_index];
+    let local_position = vec3<f32>(
+        f32(corner & 1u),
+        f32((corner >> 1u) & 1u),
+        f32((corner >> 2u) & 1u),
+    ) - 0.5;
+
+    let world_position = decals[instance_index].model_matrix * vec4<f32>(local_position, 1.0);
+    out.clip_position = camera.view_proj * world_position;
+    out.instance_index = instance_index;
+
+    return out;
+}
+
+fn reconstruct_world_position(pixel: vec2<f32>, size: vec2<f32>, depth: f32) -> vec3<f32> {
+    let uv = pixel / size;
+    // Pixel coordinates grow downwards, NDC Y grows upwards
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let world = camera.inverse_view_proj * ndc;
+
+    return world.xyz / world.w;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> DecalOutput {
+    var out: DecalOutput;
+    let decal = decals[in.instance_index];
+
+    let pixel = vec2<i32>(in.clip_position.xy);
+    let depth = textureLoad(g_depth, pixel, 0);
+    let size = vec2<f32>(textureDimensions(g_depth));
+    let world_position = reconstruct_world_position(in.clip_position.xy, size, depth);
+
+    // The G-buffer normal is being written to, so the surface normal comes from the depth.
+    // Derivatives need uniform control flow, so this is done before discarding.
+    var surface_normal = normalize(cross(dpdy(world_position), dpdx(world_position)));
+    if dot(surface_normal, camera.position.xyz - world_position) < 0.0 {
+        surface_normal = -surface_normal;
+    }
+
+    // The decal is projected along the -Y axis of its box
+    let local_position = (decal.inverse_model_matrix * vec4<f32>(world_position, 1.0)).xyz;
+    if depth >= 1.0 || any(abs(local_position) > vec3<f32>(0.5)) {
+        discard;
+    }
+
+    let material = material_info[decal.material_id];
+    // Same orientation as a plane facing +Y, where V runs towards -Z
+    let uv = material_uv(material, vec2<f32>(local_position.x + 0.5, 0.5 - local_position.z));
+    let base_color = textureSampleGrad(textures[material.base_color], samplers[material_sampler(material, SAMPLER_BASE_COLOR)], uv.uv, uv.ddx, uv.ddy);
+    let normal_sample = textureSampleGrad(textures[material.normal], samplers[material_sampler(material, SAMPLER_NORMAL)], uv.uv, uv.ddx, uv.ddy);
+
+    let edge_weight = saturate((0.5 - abs(local_position.y)) / max(decal.edge_fade * 0.5, 0.0001));
+    let decal_up = normalize(decal.model_matrix[1].xyz);
+    let facing = dot(surface_normal, decal_up);
+    let angle_weight = saturate((facing - decal.angle_fade) / max(1.0 - decal.angle_fade, 0.0001));
+    let alpha = base_color.a * decal.color.a * edge_weight * angle_weight;
+
+    if alpha <= 0.0 {
+        discard;
+    }
+
+    // Tangent space follows the X axis of the box, like the UVs
+    let decal_right = normalize(decal.model_matrix[0].xyz);
+    let tangent = normalize(decal_right - surface_normal * dot(surface_normal, decal_right));
+    let bitangent = cross(surface_normal, tangent);
+    let tbn = mat3x3<f32>(tangent, bitangent, surface_normal);
+    let normal = normalize(tbn * decode_normal(material, normal_sample));
+
+    out.color = vec4<f32>(base_color.rgb * decal.color.rgb, alpha);
+    out.normal = vec4<f32>(normal, alpha * decal.normal_opacity);
+
+    return out;
+}
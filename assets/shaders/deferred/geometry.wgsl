@@ -1,7 +1,12 @@
 #import shared::camera::CameraUniform
 #import shared::drawable::VisibleDrawable
-#import shared::mesh_info::MeshInfo
-#import shared::material_info::MaterialInfo
+#import shared::vertex::octahedral_decode
+#import shared::mesh_info::{MeshInfo, MeshletInfo, VisibleMeshlet, CORNER_BITS, CORNER_MASK}
+#import shared::material_info::{
+    MaterialInfo, TerrainLayer, ALPHA_MODE_MASK, material_uv, material_sampler, decode_normal,
+    terrain_layer_weight, audit_highlight, SAMPLER_BASE_COLOR, SAMPLER_NORMAL, SAMPLER_AO_ROUGHNESS_METALLIC,
+    SAMPLER_EMISSIVE, DEFAULT_SAMPLER
+}
 
 @group(0) @binding(0)
 var<uniform> camera: CameraUniform;
@@ -14,13 +19,63 @@ var<storage, read> material_info: array<MaterialInfo>;
 @group(2) @binding(1)
 var textures: binding_array<texture_2d<f32>>;
 @group(2) @binding(2)
-var default_sampler: sampler;
+var samplers: binding_array<sampler>;
+@group(2) @binding(3)
+var<storage, read> terrain_layers: array<TerrainLayer>;
+
+// Where the baked AO of a drawable's vertices is. This should match BakedAoRange in
+// baked_ao_buffers.rs.
+struct BakedAoRange {
+    vertex_offset: u32,
+    vertex_count: u32,
+    first_value: u32,
+}
+
+// Indexed by drawable slot
+@group(3) @binding(0)
+var<storage, read> baked_ao_ranges: array<BakedAoRange>;
+@group(3) @binding(1)
+var<storage, read> baked_ao: array<f32>;
+
+#ifdef MESHLETS
+@group(4) @binding(0)
+var<storage, read> meshes: array<MeshInfo>;
+@group(4) @binding(1)
+var<storage, read> meshlets: array<MeshletInfo>;
+@group(4) @binding(2)
+var<storage, read> visible_meshlets: array<VisibleMeshlet>;
+@group(4) @binding(3)
+var<storage, read> mesh_indices: array<u32>;
+@group(4) @binding(4)
+var<storage, read> vertices: array<u32>;
+
+// Vertices are read as raw words, like in the skinning shader. These should match the layout of
+// Vertex, or QuantizedVertex.
+#ifdef QUANTIZED_VERTICES
+const VERTEX_STRIDE: u32 = 9u;
+const NORMAL: u32 = 3u;
+const TANGENT: u32 = 4u;
+const UV: u32 = 5u;
+#else
+const VERTEX_STRIDE: u32 = 17u;
+const NORMAL: u32 = 3u;
+const UV: u32 = 6u;
+const TANGENT: u32 = 8u;
+#endif
+#endif
 
 struct VertexInput {
     @location(0) position: vec3<f32>,
+#ifdef QUANTIZED_VERTICES
+    // Octahedral encoded
+    @location(1) normal: vec2<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(3) tangent: vec2<f32>,
+#else
     @location(1) normal: vec3<f32>,
     @location(2) uv: vec2<f32>,
     @location(3) tangent: vec3<f32>,
+#endif
 }
 
 struct VertexOutput {
@@ -28,32 +83,174 @@ struct VertexOutput {
     @location(0) normal: vec3<f32>,
     @location(1) uv: vec2<f32>,
     @location(2) @interpolate(flat) instance_index: u32,
+    @location(3) tangent: vec3<f32>,
+    // Unjittered clip positions of this and the previous frame, for motion vectors
+    @location(4) current_position: vec4<f32>,
+    @location(5) previous_position: vec4<f32>,
+    @location(6) world_position: vec3<f32>,
+    @location(7) baked_ao: f32,
 }
 
 struct GBufferOutput {
     @location(0) color_roughness: vec4<f32>,
     @location(1) normal_metallic: vec4<f32>,
+    @location(2) emission: vec4<f32>,
+    @location(3) velocity: vec2<f32>,
+}
+
+struct TerrainSample {
+    base_color: vec3<f32>,
+    normal: vec4<f32>,
+    ao_roughness_metallic: vec4<f32>,
+}
+
+// Blends the layers of a terrain material, projected from above. The layers are picked per pixel,
+// so the samples use gradients from outside the loop.
+fn sample_terrain(
+    material: MaterialInfo,
+    world_position: vec3<f32>,
+    slope: f32,
+    uv_dx: vec2<f32>,
+    uv_dy: vec2<f32>,
+) -> TerrainSample {
+    var result: TerrainSample;
+    var total_weight = 0.0;
+    let world_uv = vec2<f32>(world_position.x, -world_position.z);
+
+    for (var i = 0u; i < material.terrain_layer_count; i++) {
+        let layer = terrain_layers[material.terrain_layer_start + i];
+        var weight = terrain_layer_weight(layer, world_position.y, slope);
+        // The first layer covers the gaps between the others
+        if i == 0u {
+            weight = max(weight, 0.001);
+        }
+
+        if weight <= 0.0 {
+            continue;
+        }
+
+        let scale = 1.0 / layer.tile_size;
+        let uv = world_uv * scale;
+        let dx = uv_dx * scale;
+        let dy = uv_dy * scale;
+
+        result.base_color += textureSampleGrad(textures[layer.base_color], samplers[DEFAULT_SAMPLER], uv, dx, dy).rgb * weight;
+        result.normal += textureSampleGrad(textures[layer.normal], samplers[DEFAULT_SAMPLER], uv, dx, dy) * weight;
+        result.ao_roughness_metallic += textureSampleGrad(textures[layer.ao_roughness_metallic], samplers[DEFAULT_SAMPLER], uv, dx, dy) * weight;
+        total_weight += weight;
+    }
+
+    let inverse_weight = 1.0 / max(total_weight, 0.0001);
+    result.base_color *= inverse_weight;
+    result.normal *= inverse_weight;
+    result.ao_roughness_metallic *= inverse_weight;
+    return result;
+}
+
+fn clip_to_uv(clip: vec4<f32>) -> vec2<f32> {
+    let ndc = clip.xy / clip.w;
+    return vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+}
+
+#ifdef MESHLETS
+fn read_vec3(offset: u32) -> vec3<f32> {
+    return vec3<f32>(
+        bitcast<f32>(vertices[offset]),
+        bitcast<f32>(vertices[offset + 1u]),
+        bitcast<f32>(vertices[offset + 2u]),
+    );
 }
 
+fn load_vertex(vertex_index: u32) -> VertexInput {
+    let offset = vertex_index * VERTEX_STRIDE;
+    var vertex: VertexInput;
+    vertex.position = read_vec3(offset);
+#ifdef QUANTIZED_VERTICES
+    vertex.normal = unpack2x16snorm(vertices[offset + NORMAL]);
+    vertex.uv = unpack2x16float(vertices[offset + UV]);
+    vertex.tangent = unpack2x16snorm(vertices[offset + TANGENT]);
+#else
+    vertex.normal = read_vec3(offset + NORMAL);
+    vertex.uv = vec2<f32>(bitcast<f32>(vertices[offset + UV]), bitcast<f32>(vertices[offset + UV + 1u]));
+    vertex.tangent = read_vec3(offset + TANGENT);
+#endif
+    return vertex;
+}
+
+// Drawn from the meshlet index buffer, whose entries refer to a corner of a visible meshlet
+@vertex
+fn vs_main(@builtin(vertex_index) packed_index: u32) -> VertexOutput {
+    let visible_meshlet = visible_meshlets[packed_index >> CORNER_BITS];
+    let meshlet = meshlets[visible_meshlet.meshlet_index];
+    let mesh = meshes[drawables[visible_meshlet.drawable_index].mesh_index];
+    let index = mesh_indices[meshlet.first_index + (packed_index & CORNER_MASK)];
+    let vertex_index = mesh.vertex_offset + index;
+
+    return transform_vertex(load_vertex(vertex_index), vertex_index, visible_meshlet.drawable_index);
+}
+#else
 @vertex
 fn vs_main(
     model: VertexInput,
+    // Includes the vertex offset of the mesh
+    @builtin(vertex_index) vertex_index: u32,
     @builtin(instance_index) instance_index: u32,
 ) -> VertexOutput {
+    return transform_vertex(model, vertex_index, instance_index);
+}
+#endif
+
+// 1 for vertices without baked AO
+fn load_baked_ao(slot: u32, vertex_index: u32) -> f32 {
+    if slot >= arrayLength(&baked_ao_ranges) {
+        return 1.0;
+    }
+
+    let range = baked_ao_ranges[slot];
+    // Wraps around for vertices before the range
+    let local_index = vertex_index - range.vertex_offset;
+    if local_index >= range.vertex_count {
+        return 1.0;
+    }
+
+    return baked_ao[range.first_value + local_index];
+}
+
+// `vertex_index` is the index of the vertex in the vertex megabuffer
+fn transform_vertex(model: VertexInput, vertex_index: u32, instance_index: u32) -> VertexOutput {
     var out: VertexOutput;
 
     let drawable = drawables[instance_index];
     let world_position = drawable.model_matrix * vec4<f32>(model.position, 1.0);
 
     out.clip_position = camera.view_proj * world_position;
-    let normal_matrix = mat3x3<f32>(
+    out.current_position = camera.unjittered_view_proj * world_position;
+    // Skinned vertices are only moved by the transform of their object, not by the animation
+    let previous_world_position = drawable.previous_model_matrix * vec4<f32>(model.position, 1.0);
+    out.previous_position = camera.previous_view_proj * previous_world_position;
+    let model_matrix = mat3x3<f32>(
         drawable.model_matrix[0].xyz,
         drawable.model_matrix[1].xyz,
         drawable.model_matrix[2].xyz
     );
-    out.normal = normalize(normal_matrix * model.normal);
+    let normal_matrix = mat3x3<f32>(
+        drawable.inverse_transpose_model_matrix[0].xyz,
+        drawable.inverse_transpose_model_matrix[1].xyz,
+        drawable.inverse_transpose_model_matrix[2].xyz
+    );
+#ifdef QUANTIZED_VERTICES
+    let normal = octahedral_decode(model.normal);
+    let tangent = octahedral_decode(model.tangent);
+#else
+    let normal = model.normal;
+    let tangent = model.tangent;
+#endif
+    out.normal = normalize(normal_matrix * normal);
+    out.tangent = normalize(model_matrix * tangent);
     out.uv = model.uv;
+    out.world_position = world_position.xyz;
     out.instance_index = instance_index;
+    out.baked_ao = load_baked_ao(drawable.slot, vertex_index);
 
     return out;
 }
@@ -62,25 +259,59 @@ fn vs_main(
 fn fs_main(in: VertexOutput) -> GBufferOutput {
     var out: GBufferOutput;
 
-    let material_id = drawables[in.instance_index].material_id;
-    let material = material_info[material_id];
+    let drawable = drawables[in.instance_index];
+    let material = material_info[drawable.material_id];
+    let uv = material_uv(material, in.uv);
+
+    let geometry_normal = normalize(in.normal);
+    // Derivatives need uniform control flow, so these are taken before the terrain branch
+    let terrain_uv_dx = dpdx(vec2<f32>(in.world_position.x, -in.world_position.z));
+    let terrain_uv_dy = dpdy(vec2<f32>(in.world_position.x, -in.world_position.z));
 
     let base_texture_index = material.base_color;
-    let base_texture_sample = textureSample(textures[base_texture_index], default_sampler, in.uv);
-    let base_color = base_texture_sample.rgb;
+    let base_texture_sample = textureSampleGrad(textures[base_texture_index], samplers[material_sampler(material, SAMPLER_BASE_COLOR)], uv.uv, uv.ddx, uv.ddy);
+    var base_color = base_texture_sample.rgb;
+
+    if material.alpha_mode == ALPHA_MODE_MASK && base_texture_sample.a < material.alpha_cutoff {
+        discard;
+    }
 
     let normal_index = material.normal;
-    let normal_texture_sample = textureSample(textures[normal_index], default_sampler, in.uv);
-    let normal = normalize(normal_texture_sample.rgb * 2.0 - 1.0);
+    var normal_texture_sample = textureSampleGrad(textures[normal_index], samplers[material_sampler(material, SAMPLER_NORMAL)], uv.uv, uv.ddx, uv.ddy);
 
+    // Same channel layout as glTF's occlusionRoughnessMetallic
     let ao_roughness_metallic_index = material.ao_roughness_metallic;
-    let ao_roughness_metallic_sample = textureSample(textures[ao_roughness_metallic_index], default_sampler, in.uv);
-    let ao = ao_roughness_metallic_sample.r;
-    let metallic = ao_roughness_metallic_sample.g;
-    let roughness = ao_roughness_metallic_sample.b;
+    var ao_roughness_metallic_sample = textureSampleGrad(textures[ao_roughness_metallic_index], samplers[material_sampler(material, SAMPLER_AO_ROUGHNESS_METALLIC)], uv.uv, uv.ddx, uv.ddy);
 
-    out.color_roughness = vec4<f32>(base_color, roughness);
+    // Terrain materials replace the samples above with their own blended layers
+    if material.terrain_layer_count > 0u {
+        let slope = 1.0 - saturate(geometry_normal.y);
+        let terrain = sample_terrain(material, in.world_position, slope, terrain_uv_dx, terrain_uv_dy);
+        base_color = terrain.base_color;
+        normal_texture_sample = terrain.normal;
+        ao_roughness_metallic_sample = terrain.ao_roughness_metallic;
+    }
+
+    let tangent_space_normal = decode_normal(material, normal_texture_sample);
+
+    // Transform the normal map sample from tangent space to world space
+    let tangent = normalize(in.tangent - geometry_normal * dot(geometry_normal, in.tangent));
+    let bitangent = cross(geometry_normal, tangent);
+    let tbn = mat3x3<f32>(tangent, bitangent, geometry_normal);
+    let normal = normalize(tbn * tangent_space_normal);
+
+    let roughness = ao_roughness_metallic_sample.g;
+    let metallic = ao_roughness_metallic_sample.b;
+
+    out.color_roughness = vec4<f32>(base_color * drawable.tint, roughness);
     out.normal_metallic = vec4<f32>(normal, metallic);
 
+    let emissive_sample = textureSampleGrad(textures[material.emissive], samplers[material_sampler(material, SAMPLER_EMISSIVE)], uv.uv, uv.ddx, uv.ddy);
+    // Alpha is the baked AO, which the lighting pass applies to ambient light
+    out.emission = vec4<f32>(emissive_sample.rgb * material.emissive_factor.rgb * drawable.emission, in.baked_ao);
+    // Emissive, so the stripes show up regardless of lighting
+    out.emission = mix(out.emission, vec4<f32>(4.0, 0.0, 4.0, 1.0), audit_highlight(material, in.clip_position.xy));
+    out.velocity = clip_to_uv(in.current_position) - clip_to_uv(in.previous_position);
+
     return out;
 }
\ No newline at end of file
@@ -0,0 +1,207 @@
+#import shared::camera::CameraUniform
+#import shared::fullscreen::VertexOutput
+#import shared::fullscreen::vs_main as fullscreen_vs_main
+#import shared::pbr::brdf
+#import shared::light::{
+    Lights, LightClusters, sample_light, casts_shadows, cluster_index, CLUSTER_STRIDE
+}
+#import shared::shadow::{ShadowUniform, select_cascade, cascade_debug_color}
+#import shared::lighting::{sample_shadow, combine_ambient_light}
+#import shared::reflection_probe::{ReflectionProbes, probe_weight, sample_probe}
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+@group(1) @binding(0)
+var g_color_roughness: texture_2d<f32>;
+@group(1) @binding(1)
+var g_normal_metallic: texture_2d<f32>;
+@group(1) @binding(2)
+var g_depth: texture_depth_2d;
+@group(1) @binding(3)
+var g_emission: texture_2d<f32>;
+@group(1) @binding(4)
+var ambient_occlusion: texture_2d<f32>;
+@group(1) @binding(5)
+var ambient_occlusion_sampler: sampler;
+
+@group(2) @binding(0)
+var<storage, read> lights: Lights;
+@group(2) @binding(1)
+var<uniform> light_clusters: LightClusters;
+@group(2) @binding(2)
+var<storage, read> cluster_lights: array<u32>;
+
+@group(3) @binding(0)
+var<uniform> shadow: ShadowUniform;
+@group(3) @binding(1)
+var shadow_map: texture_depth_2d_array;
+@group(3) @binding(2)
+var shadow_sampler: sampler_comparison;
+
+@group(4) @binding(0)
+var environment_map: texture_cube<f32>;
+@group(4) @binding(1)
+var environment_sampler: sampler;
+@group(4) @binding(2)
+var irradiance_map: texture_cube<f32>;
+@group(4) @binding(3)
+var prefiltered_map: texture_cube<f32>;
+@group(4) @binding(4)
+var brdf_lut: texture_2d<f32>;
+
+@group(5) @binding(0)
+var<storage, read> reflection_probes: ReflectionProbes;
+@group(5) @binding(1)
+var probe_irradiance_maps: texture_cube_array<f32>;
+@group(5) @binding(2)
+var probe_prefiltered_maps: texture_cube_array<f32>;
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32,
+) -> VertexOutput {
+    return fullscreen_vs_main(vertex_index);
+}
+
+fn reconstruct_world_position(pixel: vec2<f32>, size: vec2<f32>, depth: f32) -> vec3<f32> {
+    let uv = pixel / size;
+    // Pixel coordinates grow downwards, NDC Y grows upwards
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let world = camera.inverse_view_proj * ndc;
+
+    return world.xyz / world.w;
+}
+
+// Blends the reflection probes whose boxes contain the position, smallest first, and fills the
+// remaining weight with the environment map
+fn ambient_light(
+    world_position: vec3<f32>,
+    normal: vec3<f32>,
+    view_direction: vec3<f32>,
+    base_color: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+) -> vec3<f32> {
+    let reflection = reflect(-view_direction, normal);
+
+    var irradiance = vec3<f32>(0.0);
+    var prefiltered = vec3<f32>(0.0);
+    var remaining = 1.0;
+
+    for (var i = 0u; i < reflection_probes.count && remaining > 0.0; i++) {
+        let probe = reflection_probes.probes[i];
+        let box_position = (probe.world_to_box * vec4<f32>(world_position, 1.0)).xyz;
+        let weight = probe_weight(probe, box_position) * remaining;
+
+        if weight > 0.0 {
+            let probe_sample = sample_probe(
+                probe,
+                probe_irradiance_maps,
+                probe_prefiltered_maps,
+                environment_sampler,
+                box_position,
+                normal,
+                reflection,
+                roughness,
+            );
+            irradiance += probe_sample.irradiance * weight;
+            prefiltered += probe_sample.prefiltered * weight;
+            remaining -= weight;
+        }
+    }
+
+    if remaining > 0.0 {
+        let max_lod = f32(textureNumLevels(prefiltered_map) - 1u);
+        irradiance += textureSampleLevel(irradiance_map, environment_sampler, normal, 0.0).rgb * remaining;
+        prefiltered += textureSampleLevel(prefiltered_map, environment_sampler, reflection, roughness * max_lod).rgb * remaining;
+    }
+
+    return combine_ambient_light(
+        irradiance,
+        prefiltered,
+        brdf_lut,
+        environment_sampler,
+        normal,
+        view_direction,
+        base_color,
+        metallic,
+        roughness,
+    );
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(in.clip_position.xy);
+    let depth = textureLoad(g_depth, pixel, 0);
+
+    // Nothing was drawn here, keep the background
+    if depth >= 1.0 {
+        discard;
+    }
+
+    let color_roughness = textureLoad(g_color_roughness, pixel, 0);
+    let normal_metallic = textureLoad(g_normal_metallic, pixel, 0);
+
+    let base_color = color_roughness.rgb;
+    let roughness = color_roughness.a;
+    let normal = normalize(normal_metallic.xyz);
+    let metallic = normal_metallic.a;
+
+    let size = vec2<f32>(textureDimensions(g_depth));
+    let world_position = reconstruct_world_position(in.clip_position.xy, size, depth);
+    let view_direction = normalize(camera.position.xyz - world_position);
+
+    var direct = vec3<f32>(0.0);
+
+    // Only the lights whose range reaches the cluster of this pixel
+    let view_depth = (light_clusters.view * vec4<f32>(world_position, 1.0)).z;
+    let cluster = cluster_index(light_clusters, in.clip_position.xy / size, view_depth);
+    let cluster_offset = cluster * CLUSTER_STRIDE;
+    let cluster_light_count = cluster_lights[cluster_offset];
+
+    for (var i = 0u; i < cluster_light_count; i++) {
+        let light_data = lights.lights[cluster_lights[cluster_offset + 1u + i]];
+        let light = sample_light(light_data, world_position);
+        var radiance = light.radiance;
+
+        if casts_shadows(light_data) {
+            radiance *= sample_shadow(
+                shadow_map,
+                shadow_sampler,
+                shadow,
+                world_position,
+                normal,
+            );
+        }
+
+        direct += brdf(normal, view_direction, light.direction, base_color, metallic, roughness) * radiance;
+    }
+
+    let ambient = ambient_light(
+        world_position,
+        normal,
+        view_direction,
+        base_color,
+        metallic,
+        roughness,
+    );
+    // Half resolution, upsampled with bilinear filtering
+    let occlusion = textureSampleLevel(
+        ambient_occlusion,
+        ambient_occlusion_sampler,
+        in.clip_position.xy / size,
+        0.0,
+    ).r;
+    let emission = textureLoad(g_emission, pixel, 0);
+    // Alpha is the AO baked for static objects, and 1 for everything else
+    let baked_occlusion = emission.a;
+
+    var color = direct + ambient * occlusion * baked_occlusion + emission.rgb;
+
+    if shadow.visualize_cascades != 0u {
+        color *= cascade_debug_color(select_cascade(shadow, world_position));
+    }
+
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,99 @@
+#import shared::camera::CameraUniform
+#import shared::fullscreen::VertexOutput
+#import shared::fullscreen::vs_main as fullscreen_vs_main
+
+const KERNEL_SIZE: u32 = 32u;
+
+struct SsaoSettings {
+    // Offsets in a tangent space hemisphere, W is unused
+    kernel: array<vec4<f32>, KERNEL_SIZE>,
+    radius: f32,
+    bias: f32,
+    intensity: f32,
+    sample_count: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+@group(1) @binding(0)
+var g_depth: texture_depth_2d;
+@group(1) @binding(1)
+var g_normal_metallic: texture_2d<f32>;
+@group(1) @binding(2)
+var noise: texture_2d<f32>;
+@group(1) @binding(3)
+var<uniform> settings: SsaoSettings;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    return fullscreen_vs_main(vertex_index);
+}
+
+fn reconstruct_world_position(uv: vec2<f32>, depth: f32) -> vec3<f32> {
+    // Pixel coordinates grow downwards, NDC Y grows upwards
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let world = camera.inverse_view_proj * ndc;
+
+    return world.xyz / world.w;
+}
+
+// Rendered at half resolution, so each output pixel covers 2x2 G-buffer pixels
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let full_size = vec2<f32>(textureDimensions(g_depth));
+    let pixel = vec2<i32>(in.clip_position.xy) * 2;
+    let depth = textureLoad(g_depth, pixel, 0);
+
+    // Nothing was drawn here
+    if depth >= 1.0 {
+        return vec4<f32>(1.0);
+    }
+
+    let uv = (vec2<f32>(pixel) + 0.5) / full_size;
+    let position = reconstruct_world_position(uv, depth);
+    let normal = normalize(textureLoad(g_normal_metallic, pixel, 0).xyz);
+
+    // Rotate the kernel around the normal with a tiled random vector, the blur hides the pattern
+    let noise_size = vec2<i32>(textureDimensions(noise));
+    let random = textureLoad(noise, vec2<i32>(in.clip_position.xy) % noise_size, 0).xyz;
+    let tangent = normalize(random - normal * dot(random, normal));
+    let bitangent = cross(normal, tangent);
+    let tbn = mat3x3<f32>(tangent, bitangent, normal);
+
+    let distance_to_surface = distance(camera.position.xyz, position);
+    let sample_count = min(settings.sample_count, KERNEL_SIZE);
+    var occlusion = 0.0;
+
+    for (var i = 0u; i < sample_count; i++) {
+        let sample_position = position + tbn * settings.kernel[i].xyz * settings.radius;
+
+        let clip = camera.view_proj * vec4<f32>(sample_position, 1.0);
+        let ndc = clip.xy / clip.w;
+        let sample_uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+
+        if any(sample_uv < vec2<f32>(0.0)) || any(sample_uv >= vec2<f32>(1.0)) {
+            continue;
+        }
+
+        let scene_depth = textureLoad(g_depth, vec2<i32>(sample_uv * full_size), 0);
+        let scene_position = reconstruct_world_position(sample_uv, scene_depth);
+
+        let sample_distance = distance(camera.position.xyz, sample_position);
+        let scene_distance = distance(camera.position.xyz, scene_position);
+
+        // Occluders much closer to the camera than the surface are unrelated geometry
+        let range_check = smoothstep(
+            0.0,
+            1.0,
+            settings.radius / max(abs(distance_to_surface - scene_distance), 1e-4),
+        );
+
+        if scene_distance <= sample_distance - settings.bias {
+            occlusion += range_check;
+        }
+    }
+
+    let visibility = 1.0 - occlusion / f32(max(sample_count, 1u)) * settings.intensity;
+    return vec4<f32>(clamp(visibility, 0.0, 1.0));
+}
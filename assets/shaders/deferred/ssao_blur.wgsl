@@ -0,0 +1,27 @@
+#import shared::fullscreen::VertexOutput
+#import shared::fullscreen::vs_main as fullscreen_vs_main
+
+@group(0) @binding(0)
+var occlusion: texture_2d<f32>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    return fullscreen_vs_main(vertex_index);
+}
+
+// 4x4 box blur, matching the size of the tiled noise texture so its pattern averages out
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = vec2<i32>(textureDimensions(occlusion));
+    let pixel = vec2<i32>(in.clip_position.xy);
+    var sum = 0.0;
+
+    for (var y = -2; y < 2; y++) {
+        for (var x = -2; x < 2; x++) {
+            let coords = clamp(pixel + vec2<i32>(x, y), vec2<i32>(0), size - 1);
+            sum += textureLoad(occlusion, coords, 0).r;
+        }
+    }
+
+    return vec4<f32>(sum / 16.0);
+}
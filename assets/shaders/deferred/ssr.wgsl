@@ -0,0 +1,166 @@
+#import shared::camera::CameraUniform
+#import shared::fullscreen::VertexOutput
+#import shared::fullscreen::vs_main as fullscreen_vs_main
+#import shared::ibl::fresnel_schlick_roughness
+
+// Binary search steps to refine a hit between the last two march positions
+const REFINE_STEPS: u32 = 6u;
+// Reflections fade out this close to the screen edges, in UV units
+const EDGE_FADE: f32 = 0.1;
+
+struct SsrSettings {
+    max_distance: f32,
+    max_steps: u32,
+    thickness: f32,
+    max_roughness: f32,
+    intensity: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+@group(1) @binding(0)
+var g_color_roughness: texture_2d<f32>;
+@group(1) @binding(1)
+var g_normal_metallic: texture_2d<f32>;
+@group(1) @binding(2)
+var g_depth: texture_depth_2d;
+@group(1) @binding(3)
+var scene_color: texture_2d<f32>;
+@group(1) @binding(4)
+var<uniform> settings: SsrSettings;
+
+@group(2) @binding(1)
+var environment_sampler: sampler;
+@group(2) @binding(3)
+var prefiltered_map: texture_cube<f32>;
+@group(2) @binding(4)
+var brdf_lut: texture_2d<f32>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    return fullscreen_vs_main(vertex_index);
+}
+
+fn reconstruct_world_position(uv: vec2<f32>, depth: f32) -> vec3<f32> {
+    // Pixel coordinates grow downwards, NDC Y grows upwards
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let world = camera.inverse_view_proj * ndc;
+
+    return world.xyz / world.w;
+}
+
+fn project_to_uv(position: vec3<f32>) -> vec2<f32> {
+    let clip = camera.view_proj * vec4<f32>(position, 1.0);
+    let ndc = clip.xy / clip.w;
+
+    return vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+}
+
+fn on_screen(uv: vec2<f32>) -> bool {
+    return all(uv >= vec2<f32>(0.0)) && all(uv < vec2<f32>(1.0));
+}
+
+// How far behind the depth buffer the position is, measured as distance from the camera.
+// Positive when the ray has passed behind the visible surface.
+fn depth_difference(position: vec3<f32>, size: vec2<f32>) -> f32 {
+    let uv = project_to_uv(position);
+    let depth = textureLoad(g_depth, vec2<i32>(uv * size), 0);
+
+    // The background never occludes anything
+    if depth >= 1.0 {
+        return -1e9;
+    }
+
+    let scene_position = reconstruct_world_position(uv, depth);
+    return distance(camera.position.xyz, position) - distance(camera.position.xyz, scene_position);
+}
+
+// Outputs the traced reflection minus the environment reflection the lighting pass used, both
+// weighted by the same specular response. Misses output zero, which keeps the environment.
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(in.clip_position.xy);
+    let depth = textureLoad(g_depth, pixel, 0);
+
+    if depth >= 1.0 {
+        return vec4<f32>(0.0);
+    }
+
+    let color_roughness = textureLoad(g_color_roughness, pixel, 0);
+    let roughness = color_roughness.a;
+
+    if roughness >= settings.max_roughness {
+        return vec4<f32>(0.0);
+    }
+
+    let normal_metallic = textureLoad(g_normal_metallic, pixel, 0);
+    let normal = normalize(normal_metallic.xyz);
+    let metallic = normal_metallic.a;
+
+    let size = vec2<f32>(textureDimensions(g_depth));
+    let position = reconstruct_world_position(in.clip_position.xy / size, depth);
+    let view_direction = normalize(camera.position.xyz - position);
+    let reflection = reflect(-view_direction, normal);
+
+    // Fixed size world space steps, starting one step away to avoid hitting the surface itself
+    let step_length = settings.max_distance / f32(max(settings.max_steps, 1u));
+    var previous = position;
+    var current = position + reflection * step_length;
+    var hit = false;
+    var travelled = step_length;
+
+    for (var i = 0u; i < settings.max_steps; i++) {
+        if !on_screen(project_to_uv(current)) {
+            break;
+        }
+
+        let difference = depth_difference(current, size);
+
+        if difference > 0.0 && difference < settings.thickness {
+            hit = true;
+            break;
+        }
+
+        previous = current;
+        current += reflection * step_length;
+        travelled += step_length;
+    }
+
+    if !hit {
+        return vec4<f32>(0.0);
+    }
+
+    for (var i = 0u; i < REFINE_STEPS; i++) {
+        let middle = (previous + current) * 0.5;
+
+        if depth_difference(middle, size) > 0.0 {
+            current = middle;
+        } else {
+            previous = middle;
+        }
+    }
+
+    let hit_uv = project_to_uv(current);
+    let traced = textureLoad(scene_color, vec2<i32>(hit_uv * size), 0).rgb;
+
+    let max_lod = f32(textureNumLevels(prefiltered_map) - 1u);
+    let environment = textureSampleLevel(prefiltered_map, environment_sampler, reflection, roughness * max_lod).rgb;
+
+    // Same specular weight as ambient_light in shared::lighting
+    let n_dot_v = max(dot(normal, view_direction), 0.0);
+    let f0 = mix(vec3<f32>(0.04), color_roughness.rgb, metallic);
+    let fresnel = fresnel_schlick_roughness(n_dot_v, f0, roughness);
+    let environment_brdf = textureSampleLevel(brdf_lut, environment_sampler, vec2<f32>(n_dot_v, roughness), 0.0).rg;
+    let specular_weight = fresnel * environment_brdf.x + environment_brdf.y;
+
+    // Hide the hard cutoffs at the screen edges, at the maximum distance and at the roughness limit
+    let edge_distances = min(hit_uv, vec2<f32>(1.0) - hit_uv);
+    let edge_distance = min(edge_distances.x, edge_distances.y);
+    let edge_fade = saturate(edge_distance / EDGE_FADE);
+    let distance_fade = 1.0 - saturate(travelled / settings.max_distance);
+    let roughness_fade = 1.0 - roughness / settings.max_roughness;
+    let confidence = edge_fade * distance_fade * roughness_fade * settings.intensity;
+
+    return vec4<f32>((traced - environment) * specular_weight * confidence, 1.0);
+}
@@ -0,0 +1,17 @@
+#import shared::fullscreen::VertexOutput
+#import shared::fullscreen::vs_main as fullscreen_vs_main
+
+@group(0) @binding(0)
+var reflections: texture_2d<f32>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    return fullscreen_vs_main(vertex_index);
+}
+
+// Added on top of the lit scene. Negative values remove the environment reflection that the
+// traced reflection replaces.
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(textureLoad(reflections, vec2<i32>(in.clip_position.xy), 0).rgb, 0.0);
+}
@@ -0,0 +1,133 @@
+#import shared::camera::CameraUniform
+#import shared::fullscreen::VertexOutput
+#import shared::fullscreen::vs_main as fullscreen_vs_main
+#import shared::shadow::{ShadowUniform, select_cascade, cascade_coords}
+
+const PI: f32 = 3.14159265359;
+
+struct VolumetricFogSettings {
+    // Direction the light is shining towards, W is unused
+    light_direction: vec4<f32>,
+    // Color times intensity, W = ambient
+    light_radiance: vec4<f32>,
+    // W = anisotropy
+    albedo: vec4<f32>,
+    density: f32,
+    height_falloff: f32,
+    base_height: f32,
+    max_distance: f32,
+    step_count: u32,
+    frame_index: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+@group(1) @binding(0)
+var g_depth: texture_depth_2d;
+@group(1) @binding(1)
+var<uniform> settings: VolumetricFogSettings;
+
+@group(2) @binding(0)
+var<uniform> shadow: ShadowUniform;
+@group(2) @binding(1)
+var shadow_map: texture_depth_2d_array;
+@group(2) @binding(2)
+var shadow_sampler: sampler_comparison;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    return fullscreen_vs_main(vertex_index);
+}
+
+fn reconstruct_world_position(uv: vec2<f32>, depth: f32) -> vec3<f32> {
+    // Pixel coordinates grow downwards, NDC Y grows upwards
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let world = camera.inverse_view_proj * ndc;
+
+    return world.xyz / world.w;
+}
+
+// Jimenez 2014, a per-pixel offset that is well distributed over the screen and over frames
+fn interleaved_gradient_noise(pixel: vec2<f32>, frame_index: u32) -> f32 {
+    let offset_pixel = pixel + 5.588238 * f32(frame_index % 64u);
+    return fract(52.9829189 * fract(dot(offset_pixel, vec2<f32>(0.06711056, 0.00583715))));
+}
+
+fn henyey_greenstein(cos_theta: f32, g: f32) -> f32 {
+    let g2 = g * g;
+    let denominator = 1.0 + g2 - 2.0 * g * cos_theta;
+    return (1.0 - g2) / (4.0 * PI * denominator * sqrt(denominator));
+}
+
+fn fog_density(world_position: vec3<f32>) -> f32 {
+    let height = max(world_position.y - settings.base_height, 0.0);
+    return settings.density * exp(-settings.height_falloff * height);
+}
+
+// A single comparison tap in the most detailed cascade; the noise of the ray march hides the
+// hard edges
+fn sun_visibility(world_position: vec3<f32>) -> f32 {
+    let cascade = select_cascade(shadow, world_position);
+
+    // Outside of the shadow map
+    if cascade >= shadow.cascade_count {
+        return 1.0;
+    }
+
+    let coords = cascade_coords(shadow.cascades[cascade], world_position);
+    return textureSampleCompareLevel(shadow_map, shadow_sampler, coords.xy, cascade, coords.z);
+}
+
+// Rendered at half resolution. RGB = light scattered towards the camera, A = transmittance.
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let full_size = vec2<f32>(textureDimensions(g_depth));
+    let pixel = vec2<i32>(in.clip_position.xy) * 2;
+    let depth = textureLoad(g_depth, pixel, 0);
+    let uv = (vec2<f32>(pixel) + 0.5) / full_size;
+
+    let eye = camera.position.xyz;
+    // The background is marched up to the maximum distance
+    let far_position = reconstruct_world_position(uv, min(depth, 1.0));
+    let to_far = far_position - eye;
+    let direction = normalize(to_far);
+    var ray_length = settings.max_distance;
+    if depth < 1.0 {
+        ray_length = min(length(to_far), settings.max_distance);
+    }
+
+    let step_count = max(settings.step_count, 1u);
+    let step_length = ray_length / f32(step_count);
+    let jitter = interleaved_gradient_noise(in.clip_position.xy, settings.frame_index);
+
+    let to_light = -normalize(settings.light_direction.xyz);
+    let phase = henyey_greenstein(dot(direction, to_light), settings.albedo.w);
+    let ambient = settings.light_radiance.w;
+
+    var scattering = vec3<f32>(0.0);
+    var transmittance = 1.0;
+
+    for (var i = 0u; i < step_count; i++) {
+        let position = eye + direction * (f32(i) + jitter) * step_length;
+        let extinction = fog_density(position);
+        if extinction <= 0.0 {
+            continue;
+        }
+
+        let in_scattering = settings.light_radiance.rgb * settings.albedo.rgb * extinction
+            * (sun_visibility(position) * phase + ambient);
+
+        // Integrates the in-scattering over the step analytically, which keeps dense fog from
+        // gaining energy with long steps (Hillaire 2015)
+        let step_transmittance = exp(-extinction * step_length);
+        scattering += transmittance * in_scattering * (1.0 - step_transmittance) / extinction;
+        transmittance *= step_transmittance;
+
+        if transmittance < 0.001 {
+            break;
+        }
+    }
+
+    return vec4<f32>(scattering, transmittance);
+}
@@ -0,0 +1,20 @@
+#import shared::fullscreen::VertexOutput
+#import shared::fullscreen::vs_main as fullscreen_vs_main
+#import shared::fullscreen::texture_uv
+
+@group(0) @binding(0)
+var scattering: texture_2d<f32>;
+@group(0) @binding(1)
+var linear_sampler: sampler;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    return fullscreen_vs_main(vertex_index);
+}
+
+// Blended so that the lit scene is multiplied by the transmittance, and the in-scattered light
+// is added on top. The fog is smooth enough that bilinear upsampling is sufficient.
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSampleLevel(scattering, linear_sampler, texture_uv(in.uv), 0.0);
+}
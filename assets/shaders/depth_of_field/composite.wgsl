@@ -0,0 +1,43 @@
+#import shared::fullscreen::VertexOutput
+#import shared::fullscreen::vs_main as fullscreen_vs_main
+#import shared::depth_of_field::{DepthOfFieldSettings, circle_of_confusion}
+
+@group(0) @binding(0)
+var<uniform> settings: DepthOfFieldSettings;
+@group(0) @binding(1)
+var depth_texture: texture_depth_2d;
+@group(0) @binding(2)
+var linear_sampler: sampler;
+
+@group(1) @binding(0)
+var far_field: texture_2d<f32>;
+@group(1) @binding(1)
+var near_field: texture_2d<f32>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    return fullscreen_vs_main(vertex_index);
+}
+
+// Blended over the sharp scene with premultiplied alpha: the far field replaces out of focus
+// background pixels, and the near field is layered on top by its coverage
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = vec2<f32>(textureDimensions(depth_texture));
+    let uv = in.clip_position.xy / size;
+
+    let depth = textureLoad(depth_texture, vec2<i32>(in.clip_position.xy), 0);
+    let coc = circle_of_confusion(settings, depth, size.y * 0.5);
+
+    // Pixels with a CoC under half a pixel are considered sharp
+    let far_alpha = smoothstep(0.5, 1.5, coc);
+    let far = textureSampleLevel(far_field, linear_sampler, uv, 0.0).rgb;
+
+    let near = textureSampleLevel(near_field, linear_sampler, uv, 0.0);
+    let near_alpha = near.a;
+
+    let color = far * far_alpha * (1.0 - near_alpha) + near.rgb * near_alpha;
+    let alpha = 1.0 - (1.0 - far_alpha) * (1.0 - near_alpha);
+
+    return vec4<f32>(color, alpha);
+}
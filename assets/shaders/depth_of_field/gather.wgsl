@@ -0,0 +1,72 @@
+#import shared::fullscreen::VertexOutput
+#import shared::fullscreen::vs_main as fullscreen_vs_main
+#import shared::depth_of_field::DepthOfFieldSettings
+
+const SAMPLE_COUNT: u32 = 48u;
+const GOLDEN_ANGLE: f32 = 2.39996323;
+
+@group(0) @binding(0)
+var<uniform> settings: DepthOfFieldSettings;
+
+@group(1) @binding(0)
+var prepared: texture_2d<f32>;
+
+struct FieldOutput {
+    // Color of the blurred far field, A is unused
+    @location(0) far: vec4<f32>,
+    // Color of the blurred near field, A is its coverage
+    @location(1) near: vec4<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    return fullscreen_vs_main(vertex_index);
+}
+
+// Scatter-as-gather: each sample on a spiral disc contributes if its own CoC is large enough to
+// reach this pixel. The near and far fields are gathered separately, so that blurry foreground
+// can spread over sharp background but not the other way around.
+@fragment
+fn fs_main(in: VertexOutput) -> FieldOutput {
+    let size = vec2<i32>(textureDimensions(prepared));
+    let pixel = vec2<i32>(in.clip_position.xy);
+    let center = textureLoad(prepared, pixel, 0);
+
+    var far_sum = vec3<f32>(0.0);
+    var far_weight = 0.0;
+    var near_sum = vec3<f32>(0.0);
+    var near_weight = 0.0;
+    var max_near_coc = 0.0;
+
+    for (var i = 0u; i < SAMPLE_COUNT; i++) {
+        let radius = settings.max_coc * sqrt((f32(i) + 0.5) / f32(SAMPLE_COUNT));
+        let angle = f32(i) * GOLDEN_ANGLE;
+        let offset = vec2<f32>(cos(angle), sin(angle)) * radius;
+        let coords = clamp(pixel + vec2<i32>(round(offset)), vec2<i32>(0), size - 1);
+        let tap = textureLoad(prepared, coords, 0);
+
+        // Soft edge of one pixel, to avoid banding between the rings
+        let far_contribution = saturate(tap.a - radius + 1.0);
+        far_sum += tap.rgb * far_contribution;
+        far_weight += far_contribution;
+
+        let near_contribution = saturate(-tap.a - radius + 1.0);
+        near_sum += tap.rgb * near_contribution;
+        near_weight += near_contribution;
+
+        if near_contribution > 0.0 {
+            max_near_coc = max(max_near_coc, -tap.a);
+        }
+    }
+
+    var out: FieldOutput;
+    out.far = vec4<f32>(select(center.rgb, far_sum / far_weight, far_weight > 0.0), 1.0);
+
+    // Inside a uniformly blurred foreground object, only the samples within its CoC contribute
+    let near_extent = saturate(max_near_coc / settings.max_coc);
+    let expected_near_weight = f32(SAMPLE_COUNT) * near_extent * near_extent;
+    let coverage = saturate(near_weight / max(expected_near_weight, 1.0));
+    out.near = vec4<f32>(select(center.rgb, near_sum / near_weight, near_weight > 0.0), coverage);
+
+    return out;
+}
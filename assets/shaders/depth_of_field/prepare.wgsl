@@ -0,0 +1,37 @@
+#import shared::fullscreen::VertexOutput
+#import shared::fullscreen::vs_main as fullscreen_vs_main
+#import shared::depth_of_field::{DepthOfFieldSettings, circle_of_confusion}
+
+@group(0) @binding(0)
+var<uniform> settings: DepthOfFieldSettings;
+@group(0) @binding(1)
+var depth_texture: texture_depth_2d;
+@group(0) @binding(2)
+var linear_sampler: sampler;
+
+@group(1) @binding(0)
+var scene_color: texture_2d<f32>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    return fullscreen_vs_main(vertex_index);
+}
+
+// Downsamples the scene to half resolution, and stores the signed CoC in alpha. The CoC of the
+// closest of the 2x2 pixels is used, so that foreground edges aren't lost.
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let half_size = vec2<f32>(textureDimensions(scene_color)) * 0.5;
+    let pixel = vec2<i32>(in.clip_position.xy) * 2;
+
+    // Bilinear filtering at the shared corner averages the 2x2 pixels
+    let uv = (in.clip_position.xy) / half_size;
+    let color = textureSampleLevel(scene_color, linear_sampler, uv, 0.0).rgb;
+
+    let depth = min(
+        min(textureLoad(depth_texture, pixel, 0), textureLoad(depth_texture, pixel + vec2<i32>(1, 0), 0)),
+        min(textureLoad(depth_texture, pixel + vec2<i32>(0, 1), 0), textureLoad(depth_texture, pixel + vec2<i32>(1, 1), 0)),
+    );
+
+    return vec4<f32>(color, circle_of_confusion(settings, depth, half_size.y));
+}
@@ -0,0 +1,52 @@
+#import shared::ibl::{hammersley, importance_sample_ggx, geometry_smith_ibl}
+
+// Precomputes the scale (R) and bias (G) applied to F0 in the split sum approximation,
+// indexed by N dot V (U) and roughness (V)
+
+const SAMPLE_COUNT: u32 = 1024u;
+
+@group(0) @binding(0)
+var brdf_lut: texture_storage_2d<rgba16float, write>;
+
+@compute @workgroup_size(8, 8, 1)
+fn main(
+    @builtin(global_invocation_id) global_id: vec3<u32>
+) {
+    let size = textureDimensions(brdf_lut);
+
+    if global_id.x >= size.x || global_id.y >= size.y {
+        return;
+    }
+
+    let uv = (vec2<f32>(global_id.xy) + 0.5) / vec2<f32>(size);
+    let n_dot_v = uv.x;
+    let roughness = uv.y;
+
+    let view_direction = vec3<f32>(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+    let normal = vec3<f32>(0.0, 0.0, 1.0);
+
+    var scale = 0.0;
+    var bias = 0.0;
+
+    for (var i = 0u; i < SAMPLE_COUNT; i++) {
+        let xi = hammersley(i, SAMPLE_COUNT);
+        let halfway = importance_sample_ggx(xi, normal, roughness);
+        let light_direction = normalize(2.0 * dot(view_direction, halfway) * halfway - view_direction);
+
+        let n_dot_l = max(light_direction.z, 0.0);
+        let n_dot_h = max(halfway.z, 0.0);
+        let v_dot_h = max(dot(view_direction, halfway), 0.0);
+
+        if n_dot_l > 0.0 {
+            let geometry = geometry_smith_ibl(n_dot_v, n_dot_l, roughness);
+            let visibility = (geometry * v_dot_h) / (n_dot_h * n_dot_v);
+            let fresnel = pow(1.0 - v_dot_h, 5.0);
+
+            scale += (1.0 - fresnel) * visibility;
+            bias += fresnel * visibility;
+        }
+    }
+
+    let result = vec2<f32>(scale, bias) / f32(SAMPLE_COUNT);
+    textureStore(brdf_lut, global_id.xy, vec4<f32>(result, 0.0, 1.0));
+}
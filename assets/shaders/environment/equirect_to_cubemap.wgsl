@@ -0,0 +1,51 @@
+#import shared::cubemap::cube_face_direction
+
+const PI: f32 = 3.14159265359;
+
+@group(0) @binding(0)
+var equirect: texture_2d<f32>;
+@group(0) @binding(1)
+var cubemap: texture_storage_2d_array<rgba16float, write>;
+
+@compute @workgroup_size(8, 8, 1)
+fn main(
+    @builtin(global_invocation_id) global_id: vec3<u32>
+) {
+    let size = textureDimensions(cubemap);
+
+    if global_id.x >= size.x || global_id.y >= size.y {
+        return;
+    }
+
+    let uv = (vec2<f32>(global_id.xy) + 0.5) / vec2<f32>(size);
+    let direction = cube_face_direction(global_id.z, uv);
+
+    let equirect_uv = vec2<f32>(
+        atan2(direction.z, direction.x) / (2.0 * PI) + 0.5,
+        acos(clamp(direction.y, -1.0, 1.0)) / PI,
+    );
+
+    textureStore(cubemap, global_id.xy, global_id.z, sample_bilinear(equirect_uv));
+}
+
+// 32-bit float textures aren't filterable without an optional feature, so filter manually
+fn sample_bilinear(uv: vec2<f32>) -> vec4<f32> {
+    let size = vec2<i32>(textureDimensions(equirect));
+    let position = uv * vec2<f32>(size) - 0.5;
+    let base = vec2<i32>(floor(position));
+    let t = fract(position);
+
+    let a = load_wrapped(base, size);
+    let b = load_wrapped(base + vec2<i32>(1, 0), size);
+    let c = load_wrapped(base + vec2<i32>(0, 1), size);
+    let d = load_wrapped(base + vec2<i32>(1, 1), size);
+
+    return mix(mix(a, b, t.x), mix(c, d, t.x), t.y);
+}
+
+// Wraps horizontally around the sphere and clamps at the poles
+fn load_wrapped(texel: vec2<i32>, size: vec2<i32>) -> vec4<f32> {
+    let x = (texel.x % size.x + size.x) % size.x;
+    let y = clamp(texel.y, 0, size.y - 1);
+    return textureLoad(equirect, vec2<i32>(x, y), 0);
+}
@@ -0,0 +1,49 @@
+#import shared::cubemap::cube_face_direction
+#import shared::pbr::PI
+
+// Convolves the environment with a cosine lobe for diffuse ambient lighting
+
+const SAMPLE_DELTA: f32 = 0.05;
+
+@group(0) @binding(0)
+var environment_map: texture_cube<f32>;
+@group(0) @binding(1)
+var environment_sampler: sampler;
+@group(0) @binding(2)
+var irradiance_map: texture_storage_2d_array<rgba16float, write>;
+
+@compute @workgroup_size(8, 8, 1)
+fn main(
+    @builtin(global_invocation_id) global_id: vec3<u32>
+) {
+    let size = textureDimensions(irradiance_map);
+
+    if global_id.x >= size.x || global_id.y >= size.y {
+        return;
+    }
+
+    let uv = (vec2<f32>(global_id.xy) + 0.5) / vec2<f32>(size);
+    let normal = cube_face_direction(global_id.z, uv);
+
+    let up = select(vec3<f32>(1.0, 0.0, 0.0), vec3<f32>(0.0, 1.0, 0.0), abs(normal.y) < 0.999);
+    let right = normalize(cross(up, normal));
+    let tangent_up = cross(normal, right);
+
+    var irradiance = vec3<f32>(0.0);
+    var sample_count = 0.0;
+
+    for (var phi = 0.0; phi < 2.0 * PI; phi += SAMPLE_DELTA) {
+        for (var theta = 0.0; theta < 0.5 * PI; theta += SAMPLE_DELTA) {
+            let tangent_sample = vec3<f32>(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            let direction = tangent_sample.x * right + tangent_sample.y * tangent_up + tangent_sample.z * normal;
+
+            let radiance = textureSampleLevel(environment_map, environment_sampler, direction, 0.0).rgb;
+            irradiance += radiance * cos(theta) * sin(theta);
+            sample_count += 1.0;
+        }
+    }
+
+    irradiance = PI * irradiance / sample_count;
+
+    textureStore(irradiance_map, global_id.xy, global_id.z, vec4<f32>(irradiance, 1.0));
+}
@@ -0,0 +1,54 @@
+#import shared::cubemap::cube_face_direction
+#import shared::ibl::{hammersley, importance_sample_ggx}
+
+// Convolves the environment with the GGX lobe of one roughness, one mip at a time.
+// Assumes that the view direction equals the normal, as in the split sum approximation.
+
+const SAMPLE_COUNT: u32 = 1024u;
+
+struct PrefilterSettings {
+    roughness: f32,
+}
+
+@group(0) @binding(0)
+var environment_map: texture_cube<f32>;
+@group(0) @binding(1)
+var environment_sampler: sampler;
+@group(0) @binding(2)
+var<uniform> settings: PrefilterSettings;
+@group(0) @binding(3)
+var prefiltered_map: texture_storage_2d_array<rgba16float, write>;
+
+@compute @workgroup_size(8, 8, 1)
+fn main(
+    @builtin(global_invocation_id) global_id: vec3<u32>
+) {
+    let size = textureDimensions(prefiltered_map);
+
+    if global_id.x >= size.x || global_id.y >= size.y {
+        return;
+    }
+
+    let uv = (vec2<f32>(global_id.xy) + 0.5) / vec2<f32>(size);
+    let normal = cube_face_direction(global_id.z, uv);
+    let view_direction = normal;
+
+    var color = vec3<f32>(0.0);
+    var total_weight = 0.0;
+
+    for (var i = 0u; i < SAMPLE_COUNT; i++) {
+        let xi = hammersley(i, SAMPLE_COUNT);
+        let halfway = importance_sample_ggx(xi, normal, settings.roughness);
+        let light_direction = normalize(2.0 * dot(view_direction, halfway) * halfway - view_direction);
+
+        let n_dot_l = dot(normal, light_direction);
+        if n_dot_l > 0.0 {
+            color += textureSampleLevel(environment_map, environment_sampler, light_direction, 0.0).rgb * n_dot_l;
+            total_weight += n_dot_l;
+        }
+    }
+
+    color /= max(total_weight, 0.0001);
+
+    textureStore(prefiltered_map, global_id.xy, global_id.z, vec4<f32>(color, 1.0));
+}
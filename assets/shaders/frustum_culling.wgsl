@@ -1,8 +1,25 @@
-#import shared::drawable::InputDrawable
+#import shared::drawable::{InputDrawable, NO_MESH}
 #import shared::mesh_info::MeshInfo
 #import shared::frustum::Frustum
 #import shared::commands::DrawIndexedIndirectCommand
 
+struct CullingView {
+    frustum: Frustum,
+    // xyz: position LOD distances are measured from, w: multiplier for the LOD distances
+    lod_origin: vec4<f32>,
+    // One of the CULLING_BOUNDS_* constants
+    culling_bounds: u32,
+    // Drawables outside these layers are culled
+    layers: u32,
+    padding0: u32,
+    padding1: u32,
+}
+
+// These should match CullingBounds::shader_index
+const CULLING_BOUNDS_AABB: u32 = 0u;
+const CULLING_BOUNDS_SPHERE: u32 = 1u;
+const CULLING_BOUNDS_SPHERE_THEN_AABB: u32 = 2u;
+
 struct AABB {
     // W coordinates are unused, but required for alignment
     min: vec4<f32>,
@@ -10,12 +27,14 @@ struct AABB {
 }
 
 @group(0) @binding(0)
-var<uniform> frustum: Frustum;
+var<uniform> view: CullingView;
 @group(0) @binding(1)
 var<storage, read> meshes: array<MeshInfo>;
 @group(0) @binding(2)
 var<storage, read> drawables: array<InputDrawable>;
 
+// Mesh of the selected LOD for visible drawables, NO_MESH for culled ones. Every drawable is
+// written to, so this doesn't need to be cleared between frames.
 @group(0) @binding(3)
 var<storage, read_write> drawable_visibility: array<u32>;
 @group(0) @binding(4)
@@ -34,17 +53,79 @@ fn main(
     let drawable = drawables[index];
 
     let mesh_index = drawable.mesh_index;
+    if mesh_index == NO_MESH || (drawable.layers & view.layers) == 0u {
+        drawable_visibility[index] = NO_MESH;
+        return;
+    }
+
     let mesh = meshes[mesh_index];
-    let aabb = AABB(mesh.aabb_min, mesh.aabb_max);
 
-    if is_inside_frustum_transformed(aabb, drawable.model_matrix, frustum) {
-        drawable_visibility[index] = 1;
-        atomicAdd(&visible_drawables_by_mesh[mesh_index], 1u);
+    if is_visible(mesh, drawable.model_matrix) {
+        let center = (drawable.model_matrix * vec4<f32>((mesh.aabb_min + mesh.aabb_max.xyz) * 0.5, 1.0)).xyz;
+        let lod_mesh_index = select_lod(mesh_index, distance(center, view.lod_origin.xyz));
+
+        drawable_visibility[index] = lod_mesh_index;
+        atomicAdd(&visible_drawables_by_mesh[lod_mesh_index], 1u);
     } else {
-        drawable_visibility[index] = 0;
+        drawable_visibility[index] = NO_MESH;
     }
 }
 
+fn is_visible(mesh: MeshInfo, model_matrix: mat4x4<f32>) -> bool {
+    let aabb = AABB(vec4<f32>(mesh.aabb_min, 0.0), mesh.aabb_max);
+
+    switch view.culling_bounds {
+        case CULLING_BOUNDS_SPHERE: {
+            return is_sphere_inside_frustum_transformed(mesh.bounding_sphere, model_matrix, view.frustum);
+        }
+        case CULLING_BOUNDS_SPHERE_THEN_AABB: {
+            return is_sphere_inside_frustum_transformed(mesh.bounding_sphere, model_matrix, view.frustum)
+                && is_inside_frustum_transformed(aabb, model_matrix, view.frustum);
+        }
+        default: {
+            return is_inside_frustum_transformed(aabb, model_matrix, view.frustum);
+        }
+    }
+}
+
+// The radius is scaled by the largest axis scale, so the sphere stays conservative under
+// non-uniform scale
+fn is_sphere_inside_frustum_transformed(sphere: vec4<f32>, transform: mat4x4<f32>, frustum: Frustum) -> bool {
+    let center = (transform * vec4<f32>(sphere.xyz, 1.0)).xyz;
+    let scale = sqrt(max(
+        dot(transform[0].xyz, transform[0].xyz),
+        max(dot(transform[1].xyz, transform[1].xyz), dot(transform[2].xyz, transform[2].xyz))
+    ));
+    let radius = sphere.w * scale;
+
+    for (var plane_idx = 0; plane_idx < 6; plane_idx++) {
+        let plane = frustum.planes[plane_idx];
+
+        if dot(plane.xyz, center) + plane.w > radius {
+            return false;
+        }
+    }
+
+    return true;
+}
+
+// Follows the LOD chain of the mesh until the distance is within the range of a LOD
+fn select_lod(mesh_index: u32, distance: f32) -> u32 {
+    var selected = mesh_index;
+
+    loop {
+        let mesh = meshes[selected];
+
+        if mesh.next_lod == NO_MESH || distance <= mesh.lod_distance * view.lod_origin.w {
+            break;
+        }
+
+        selected = mesh.next_lod;
+    }
+
+    return selected;
+}
+
 fn get_aabb_corners(aabb: AABB) -> array<vec3<f32>, 8> {
     return array<vec3<f32>, 8>(
         vec3<f32>(aabb.min.x, aabb.min.y, aabb.min.z),
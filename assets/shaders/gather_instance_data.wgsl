@@ -1,5 +1,5 @@
 
-#import shared::drawable::InputDrawable
+#import shared::drawable::{InputDrawable, NO_MESH}
 #import shared::drawable::VisibleDrawable
 
 @group(0) @binding(0)
@@ -23,22 +23,27 @@ fn main(
         return;
     }
 
-    let drawable = drawables[index];
-    let visibility = drawable_visibility[index];
+    // Mesh of the selected LOD, which may differ from the mesh of the drawable
+    let mesh_index = drawable_visibility[index];
 
-    if visibility == 0 {
+    if mesh_index == NO_MESH {
         return;
     }
 
-    let mesh_index = drawable.mesh_index;
+    let drawable = drawables[index];
     let base_offset = base_offsets[mesh_index];
     let local_offset = atomicAdd(&drawable_local_indices[mesh_index], 1u);
 
     visible_drawables[base_offset + local_offset] = VisibleDrawable(
         drawable.model_matrix,
         drawable.inverse_transpose_model_matrix,
+        drawable.previous_model_matrix,
         mesh_index,
         drawable.material_id,
-        array<u32, 2>(0, 0)
+        drawable.joint_offset,
+        index,
+        drawable.tint,
+        drawable.emission,
+        drawable.params
     );
 }
@@ -0,0 +1,18 @@
+@group(0) @binding(0)
+var depth_texture: texture_depth_2d;
+@group(0) @binding(1)
+var output: texture_storage_2d<r32float, write>;
+
+@compute @workgroup_size(8, 8)
+fn main(
+    @builtin(global_invocation_id) global_id: vec3<u32>
+) {
+    let size = textureDimensions(output);
+
+    if global_id.x >= size.x || global_id.y >= size.y {
+        return;
+    }
+
+    let depth = textureLoad(depth_texture, global_id.xy, 0);
+    textureStore(output, global_id.xy, vec4<f32>(depth, 0.0, 0.0, 0.0));
+}
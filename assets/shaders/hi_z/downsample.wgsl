@@ -0,0 +1,37 @@
+@group(0) @binding(0)
+var input: texture_2d<f32>;
+@group(0) @binding(1)
+var output: texture_storage_2d<r32float, write>;
+
+@compute @workgroup_size(8, 8)
+fn main(
+    @builtin(global_invocation_id) global_id: vec3<u32>
+) {
+    let output_size = textureDimensions(output);
+
+    if global_id.x >= output_size.x || global_id.y >= output_size.y {
+        return;
+    }
+
+    let input_size = textureDimensions(input, 0);
+    let first = global_id.xy * 2u;
+    var last = min(first + 1u, input_size - 1u);
+
+    // When the input size is odd, the last output texel also has to cover the extra texel
+    // or the pyramid wouldn't be conservative
+    if global_id.x == output_size.x - 1u && (input_size.x & 1u) == 1u {
+        last.x = input_size.x - 1u;
+    }
+    if global_id.y == output_size.y - 1u && (input_size.y & 1u) == 1u {
+        last.y = input_size.y - 1u;
+    }
+
+    var farthest = 0.0;
+    for (var y = first.y; y <= last.y; y++) {
+        for (var x = first.x; x <= last.x; x++) {
+            farthest = max(farthest, textureLoad(input, vec2<u32>(x, y), 0).r);
+        }
+    }
+
+    textureStore(output, global_id.xy, vec4<f32>(farthest, 0.0, 0.0, 0.0));
+}
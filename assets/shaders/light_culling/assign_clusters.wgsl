@@ -0,0 +1,71 @@
+#import shared::light::{
+    Lights, LightClusters, light_type, cluster_slice_depth, LIGHT_TYPE_DIRECTIONAL, CLUSTER_GRID_X,
+    CLUSTER_GRID_Y, CLUSTER_COUNT, CLUSTER_STRIDE, MAX_LIGHTS_PER_CLUSTER
+}
+
+struct VisibleLights {
+    count: atomic<u32>,
+    indices: array<u32>,
+}
+
+@group(0) @binding(0)
+var<storage, read> lights: Lights;
+@group(0) @binding(1)
+var<uniform> clusters: LightClusters;
+@group(0) @binding(2)
+var<storage, read_write> visible_lights: VisibleLights;
+// Every cluster is written to, so this doesn't need to be cleared between frames
+@group(0) @binding(3)
+var<storage, read_write> cluster_lights: array<u32>;
+
+@compute @workgroup_size(64)
+fn main(
+    @builtin(global_invocation_id) global_id: vec3<u32>
+) {
+    let cluster = global_id.x;
+
+    if cluster >= CLUSTER_COUNT {
+        return;
+    }
+
+    let tile = vec2<u32>(cluster % CLUSTER_GRID_X, (cluster / CLUSTER_GRID_X) % CLUSTER_GRID_Y);
+    let slice = cluster / (CLUSTER_GRID_X * CLUSTER_GRID_Y);
+    let grid = vec2<f32>(f32(CLUSTER_GRID_X), f32(CLUSTER_GRID_Y));
+
+    // Tile corners on the plane one unit in front of the camera. UV Y grows downwards, view
+    // space Y grows upwards.
+    let uv_min = vec2<f32>(tile) / grid;
+    let uv_max = vec2<f32>(tile + 1u) / grid;
+    let unit_min = vec2<f32>(uv_min.x * 2.0 - 1.0, 1.0 - uv_max.y * 2.0) * clusters.projection.xy;
+    let unit_max = vec2<f32>(uv_max.x * 2.0 - 1.0, 1.0 - uv_min.y * 2.0) * clusters.projection.xy;
+
+    // The tile widens with depth, so the bounds are at either end of the slice
+    let near = cluster_slice_depth(clusters, slice);
+    let far = cluster_slice_depth(clusters, slice + 1u);
+    let aabb_min = vec3<f32>(min(unit_min * near, unit_min * far), near);
+    let aabb_max = vec3<f32>(max(unit_max * near, unit_max * far), far);
+
+    let visible_count = atomicLoad(&visible_lights.count);
+    let offset = cluster * CLUSTER_STRIDE;
+    var count = 0u;
+
+    for (var i = 0u; i < visible_count && count < MAX_LIGHTS_PER_CLUSTER; i++) {
+        let light_index = visible_lights.indices[i];
+        let light = lights.lights[light_index];
+
+        if light_type(light) != LIGHT_TYPE_DIRECTIONAL {
+            let center = (clusters.view * vec4<f32>(light.position_type.xyz, 1.0)).xyz;
+            let radius = light.direction_range.w;
+            let to_closest = clamp(center, aabb_min, aabb_max) - center;
+
+            if dot(to_closest, to_closest) > radius * radius {
+                continue;
+            }
+        }
+
+        cluster_lights[offset + 1u + count] = light_index;
+        count++;
+    }
+
+    cluster_lights[offset] = count;
+}
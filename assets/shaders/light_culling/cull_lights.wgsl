@@ -0,0 +1,46 @@
+#import shared::light::{Lights, LightClusters, light_type, LIGHT_TYPE_DIRECTIONAL}
+
+struct VisibleLights {
+    count: atomic<u32>,
+    indices: array<u32>,
+}
+
+@group(0) @binding(0)
+var<storage, read> lights: Lights;
+@group(0) @binding(1)
+var<uniform> clusters: LightClusters;
+// The count is cleared before this runs
+@group(0) @binding(2)
+var<storage, read_write> visible_lights: VisibleLights;
+@group(0) @binding(3)
+var<storage, read_write> cluster_lights: array<u32>;
+
+@compute @workgroup_size(64)
+fn main(
+    @builtin(global_invocation_id) global_id: vec3<u32>
+) {
+    let index = global_id.x;
+
+    if index >= lights.count {
+        return;
+    }
+
+    let light = lights.lights[index];
+
+    // Directional lights reach everything. Spot lights are bounded by the sphere of their range.
+    if light_type(light) != LIGHT_TYPE_DIRECTIONAL {
+        let center = light.position_type.xyz;
+        let radius = light.direction_range.w;
+
+        for (var plane_idx = 0; plane_idx < 6; plane_idx++) {
+            let plane = clusters.frustum.planes[plane_idx];
+
+            if dot(plane.xyz, center) + plane.w > radius {
+                return;
+            }
+        }
+    }
+
+    let slot = atomicAdd(&visible_lights.count, 1u);
+    visible_lights.indices[slot] = index;
+}
@@ -0,0 +1,154 @@
+#import shared::drawable::VisibleDrawable
+#import shared::frustum::Frustum
+#import shared::mesh_info::{MeshInfo, MeshletInfo, VisibleMeshlet, CORNER_BITS}
+
+// This should match the same struct in frustum_culling.wgsl
+struct CullingView {
+    frustum: Frustum,
+    // xyz: camera position, w: multiplier for the LOD distances
+    lod_origin: vec4<f32>,
+    culling_bounds: u32,
+    layers: u32,
+    padding0: u32,
+    padding1: u32,
+}
+
+// This should match MeshletDrawArgs. The indirect draw starts from index_count.
+struct MeshletDrawArgs {
+    visible_meshlet_count: atomic<u32>,
+    index_count: atomic<u32>,
+    instance_count: u32,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+}
+
+const WORKGROUP_SIZE: u32 = 64u;
+
+@group(0) @binding(0)
+var<uniform> view: CullingView;
+@group(0) @binding(1)
+var<storage, read> meshes: array<MeshInfo>;
+@group(0) @binding(2)
+var<storage, read> meshlets: array<MeshletInfo>;
+@group(0) @binding(3)
+var<storage, read> drawables: array<VisibleDrawable>;
+@group(0) @binding(4)
+var<storage, read> visible_drawables_by_mesh: array<u32>;
+@group(0) @binding(5)
+var<storage, read> base_offsets: array<u32>;
+
+@group(0) @binding(6)
+var<storage, read_write> visible_meshlets: array<VisibleMeshlet>;
+@group(0) @binding(7)
+var<storage, read_write> meshlet_indices: array<u32>;
+@group(0) @binding(8)
+var<storage, read_write> draw_args: MeshletDrawArgs;
+
+// One workgroup per visible drawable, each thread tests every 64th meshlet
+@compute @workgroup_size(64)
+fn main(
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+) {
+    let drawable_index = workgroup_id.x + workgroup_id.y * num_workgroups.x;
+
+    if drawable_index == 0u && local_index == 0u {
+        draw_args.instance_count = 1u;
+    }
+
+    // Visible drawables are stored by mesh, so the last mesh ends where they end
+    let last_mesh = arrayLength(&visible_drawables_by_mesh) - 1u;
+    let visible_count = base_offsets[last_mesh] + visible_drawables_by_mesh[last_mesh];
+
+    if drawable_index >= visible_count {
+        return;
+    }
+
+    let drawable = drawables[drawable_index];
+    let mesh = meshes[drawable.mesh_index];
+    let cone_culling = mesh.cull_meshlets != 0u && has_uniform_scale(drawable.model_matrix);
+
+    for (var i = local_index; i < mesh.meshlet_count; i += WORKGROUP_SIZE) {
+        let meshlet_index = mesh.first_meshlet + i;
+        let meshlet = meshlets[meshlet_index];
+
+        if mesh.cull_meshlets == 0u
+            || (is_inside_frustum(meshlet, drawable.model_matrix)
+                && !(cone_culling && is_facing_away(meshlet, drawable)))
+        {
+            emit_meshlet(drawable_index, meshlet_index, meshlet);
+        }
+    }
+}
+
+fn emit_meshlet(drawable_index: u32, meshlet_index: u32, meshlet: MeshletInfo) {
+    let slot = atomicAdd(&draw_args.visible_meshlet_count, 1u);
+    if slot >= arrayLength(&visible_meshlets) {
+        return;
+    }
+
+    // The indices are only reserved if they fit, so the draw never reads unwritten indices
+    var first_index = atomicLoad(&draw_args.index_count);
+    loop {
+        if first_index + meshlet.index_count > arrayLength(&meshlet_indices) {
+            return;
+        }
+
+        let result = atomicCompareExchangeWeak(
+            &draw_args.index_count,
+            first_index,
+            first_index + meshlet.index_count
+        );
+        if result.exchanged {
+            break;
+        }
+        first_index = result.old_value;
+    }
+
+    visible_meshlets[slot] = VisibleMeshlet(drawable_index, meshlet_index);
+
+    for (var corner = 0u; corner < meshlet.index_count; corner++) {
+        meshlet_indices[first_index + corner] = (slot << CORNER_BITS) | corner;
+    }
+}
+
+// Same test as for whole drawables in frustum_culling.wgsl
+fn is_inside_frustum(meshlet: MeshletInfo, transform: mat4x4<f32>) -> bool {
+    let center = (transform * vec4<f32>(meshlet.bounding_sphere.xyz, 1.0)).xyz;
+    let scale = sqrt(max(
+        dot(transform[0].xyz, transform[0].xyz),
+        max(dot(transform[1].xyz, transform[1].xyz), dot(transform[2].xyz, transform[2].xyz))
+    ));
+    let radius = meshlet.bounding_sphere.w * scale;
+
+    for (var plane_idx = 0; plane_idx < 6; plane_idx++) {
+        let plane = view.frustum.planes[plane_idx];
+
+        if dot(plane.xyz, center) + plane.w > radius {
+            return false;
+        }
+    }
+
+    return true;
+}
+
+// Non-uniform scale changes the angles between the normals, which the cone can't account for
+fn has_uniform_scale(transform: mat4x4<f32>) -> bool {
+    let scale = vec3<f32>(
+        dot(transform[0].xyz, transform[0].xyz),
+        dot(transform[1].xyz, transform[1].xyz),
+        dot(transform[2].xyz, transform[2].xyz)
+    );
+    let min_scale = min(scale.x, min(scale.y, scale.z));
+    let max_scale = max(scale.x, max(scale.y, scale.z));
+    return max_scale <= min_scale * 1.01;
+}
+
+// True if every triangle of the meshlet faces away from the camera
+fn is_facing_away(meshlet: MeshletInfo, drawable: VisibleDrawable) -> bool {
+    let apex = (drawable.model_matrix * vec4<f32>(meshlet.cone_apex.xyz, 1.0)).xyz;
+    let axis = normalize((drawable.model_matrix * vec4<f32>(meshlet.cone_axis_cutoff.xyz, 0.0)).xyz);
+    return dot(normalize(apex - view.lod_origin.xyz), axis) >= meshlet.cone_axis_cutoff.w;
+}
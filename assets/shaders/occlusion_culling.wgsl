@@ -0,0 +1,96 @@
+#import shared::drawable::{InputDrawable, NO_MESH}
+#import shared::mesh_info::MeshInfo
+
+// Runs after frustum culling and rejects drawables that are hidden behind the previous
+// frame's depth buffer. Drawables are tested against last frame's view, which matches the
+// contents of the pyramid.
+
+struct OcclusionUniform {
+    view_proj: mat4x4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> occlusion: OcclusionUniform;
+@group(0) @binding(1)
+var<storage, read> meshes: array<MeshInfo>;
+@group(0) @binding(2)
+var<storage, read> drawables: array<InputDrawable>;
+
+@group(0) @binding(3)
+var<storage, read_write> drawable_visibility: array<u32>;
+@group(0) @binding(4)
+var<storage, read_write> visible_drawables_by_mesh: array<atomic<u32>>;
+
+@group(1) @binding(0)
+var hi_z: texture_2d<f32>;
+
+@compute @workgroup_size(64)
+fn main(
+    @builtin(global_invocation_id) global_id: vec3<u32>
+) {
+    let index = global_id.x;
+
+    if index >= arrayLength(&drawables) {
+        return;
+    }
+
+    // Mesh of the LOD selected by frustum culling
+    let mesh_index = drawable_visibility[index];
+    if mesh_index == NO_MESH {
+        return;
+    }
+
+    let drawable = drawables[index];
+    let mesh = meshes[mesh_index];
+
+    if is_occluded(mesh.aabb_min, mesh.aabb_max.xyz, drawable.model_matrix) {
+        drawable_visibility[index] = NO_MESH;
+        atomicSub(&visible_drawables_by_mesh[mesh_index], 1u);
+    }
+}
+
+fn is_occluded(aabb_min: vec3<f32>, aabb_max: vec3<f32>, model_matrix: mat4x4<f32>) -> bool {
+    let transform = occlusion.view_proj * model_matrix;
+
+    var uv_min = vec2<f32>(1.0);
+    var uv_max = vec2<f32>(0.0);
+    var nearest_depth = 1.0;
+
+    for (var i = 0u; i < 8u; i++) {
+        let corner = select(aabb_min, aabb_max, vec3<bool>((i & 1u) != 0u, (i & 2u) != 0u, (i & 4u) != 0u));
+        let clip = transform * vec4<f32>(corner, 1.0);
+
+        // The bounds cross the near plane, so the projected rectangle is meaningless
+        if clip.w <= 0.0 {
+            return false;
+        }
+
+        let ndc = clip.xyz / clip.w;
+        let uv = ndc.xy * vec2<f32>(0.5, -0.5) + 0.5;
+
+        uv_min = min(uv_min, uv);
+        uv_max = max(uv_max, uv);
+        nearest_depth = min(nearest_depth, ndc.z);
+    }
+
+    uv_min = clamp(uv_min, vec2<f32>(0.0), vec2<f32>(1.0));
+    uv_max = clamp(uv_max, vec2<f32>(0.0), vec2<f32>(1.0));
+
+    // Pick the mip where the rectangle covers at most 2x2 texels
+    let extent = (uv_max - uv_min) * vec2<f32>(textureDimensions(hi_z, 0));
+    let max_level = i32(textureNumLevels(hi_z)) - 1;
+    let level = clamp(i32(ceil(log2(max(max(extent.x, extent.y), 1.0)))), 0, max_level);
+
+    let level_size = vec2<i32>(textureDimensions(hi_z, level));
+    let min_texel = clamp(vec2<i32>(uv_min * vec2<f32>(level_size)), vec2<i32>(0), level_size - 1);
+    let max_texel = clamp(vec2<i32>(uv_max * vec2<f32>(level_size)), vec2<i32>(0), level_size - 1);
+
+    var farthest = 0.0;
+    for (var y = min_texel.y; y <= max_texel.y; y++) {
+        for (var x = min_texel.x; x <= max_texel.x; x++) {
+            farthest = max(farthest, textureLoad(hi_z, vec2<i32>(x, y), level).r);
+        }
+    }
+
+    return nearest_depth > farthest;
+}
@@ -0,0 +1,31 @@
+#import shared::fullscreen::VertexOutput
+#import shared::fullscreen::vs_main as fullscreen_vs_main
+
+@group(0) @binding(0)
+var accumulation: texture_2d<f32>;
+@group(0) @binding(1)
+var revealage: texture_2d<f32>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    return fullscreen_vs_main(vertex_index);
+}
+
+// Blended over the scene with (1 - alpha, alpha), so the output alpha is the revealage, i.e. how
+// much of the opaque scene is still visible
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let coords = vec2<i32>(in.clip_position.xy);
+    let revealage = textureLoad(revealage, coords, 0).r;
+
+    // Nothing transparent was drawn on this pixel
+    if revealage >= 1.0 {
+        discard;
+    }
+
+    // Very bright or very near surfaces can overflow 16-bit floats
+    let accumulation = min(textureLoad(accumulation, coords, 0), vec4<f32>(65504.0));
+
+    let average_color = accumulation.rgb / max(accumulation.a, 1e-5);
+    return vec4<f32>(average_color, revealage);
+}
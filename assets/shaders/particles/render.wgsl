@@ -0,0 +1,75 @@
+#import shared::camera::CameraUniform
+#import shared::particle::{Particle, is_alive}
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+@group(1) @binding(0)
+var<storage, read> particles: array<Particle>;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    // -1 to 1 across the billboard
+    @location(1) corner: vec2<f32>,
+}
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32,
+    @builtin(instance_index) instance_index: u32,
+) -> VertexOutput {
+    var out: VertexOutput;
+
+    let particle = particles[instance_index];
+    if !is_alive(particle) {
+        // Outside the clip volume, so the triangles are discarded
+        out.clip_position = vec4<f32>(2.0, 2.0, 2.0, 1.0);
+        return out;
+    }
+
+    // Two triangles per particle, without an index buffer
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, 1.0),
+    );
+    let corner = corners[vertex_index];
+    let position = particle.position_age.xyz;
+
+    // Facing the camera position rather than the view plane, which stays stable when the
+    // camera turns
+    let to_camera = normalize(camera.position.xyz - position);
+    var up = vec3<f32>(0.0, 1.0, 0.0);
+    if abs(to_camera.y) > 0.999 {
+        up = vec3<f32>(0.0, 0.0, 1.0);
+    }
+    let right = normalize(cross(up, to_camera));
+    let billboard_up = cross(to_camera, right);
+
+    let half_size = particle.gravity_size.w * 0.5;
+    let world_position = position + (right * corner.x + billboard_up * corner.y) * half_size;
+
+    let life = particle.position_age.w / particle.velocity_lifetime.w;
+    out.clip_position = camera.view_proj * vec4<f32>(world_position, 1.0);
+    out.color = mix(particle.color_start, particle.color_end, life);
+    out.corner = corner;
+
+    return out;
+}
+
+// Soft round particles, output with premultiplied alpha
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let falloff = saturate(1.0 - dot(in.corner, in.corner));
+    let alpha = in.color.a * falloff;
+
+    if alpha <= 0.0 {
+        discard;
+    }
+
+    return vec4<f32>(in.color.rgb * alpha, alpha);
+}
@@ -0,0 +1,119 @@
+#import shared::particle::{Particle, is_alive}
+
+const PI: f32 = 3.14159265359;
+
+// This should match GpuEmitter
+struct Emitter {
+    position: vec3<f32>,
+    spawn_offset: u32,
+    direction: vec3<f32>,
+    spawn_count: u32,
+    gravity: vec3<f32>,
+    speed: f32,
+    color_start: vec4<f32>,
+    color_end: vec4<f32>,
+    lifetime: f32,
+    spread: f32,
+    size: f32,
+}
+
+// This should match GpuSimulation
+struct Simulation {
+    delta_time: f32,
+    spawn_start: u32,
+    spawn_count: u32,
+    emitter_count: u32,
+    seed: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> simulation: Simulation;
+@group(0) @binding(1)
+var<storage, read> emitters: array<Emitter>;
+@group(0) @binding(2)
+var<storage, read_write> particles: array<Particle>;
+
+// PCG hash, see "Hash Functions for GPU Rendering" by Jarzynski & Olano
+fn hash(value: u32) -> u32 {
+    let state = value * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+fn random(state: ptr<function, u32>) -> f32 {
+    *state = hash(*state);
+    return f32(*state) / 4294967295.0;
+}
+
+// Uniformly distributed direction within `spread` radians of `direction`
+fn random_direction(direction: vec3<f32>, spread: f32, state: ptr<function, u32>) -> vec3<f32> {
+    let cos_theta = mix(1.0, cos(spread), random(state));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    let phi = 2.0 * PI * random(state);
+
+    var up = vec3<f32>(0.0, 1.0, 0.0);
+    if abs(direction.y) > 0.999 {
+        up = vec3<f32>(1.0, 0.0, 0.0);
+    }
+    let tangent = normalize(cross(up, direction));
+    let bitangent = cross(direction, tangent);
+
+    return tangent * (sin_theta * cos(phi)) + bitangent * (sin_theta * sin(phi)) + direction * cos_theta;
+}
+
+fn spawn(spawn_index: u32, state: ptr<function, u32>) -> Particle {
+    // There are only a few emitters, so a linear search is fine
+    var emitter = emitters[0];
+    for (var i = 0u; i < simulation.emitter_count; i++) {
+        let offset = emitters[i].spawn_offset;
+        if spawn_index >= offset && spawn_index < offset + emitters[i].spawn_count {
+            emitter = emitters[i];
+            break;
+        }
+    }
+
+    let velocity = random_direction(emitter.direction, emitter.spread, state) * emitter.speed;
+
+    return Particle(
+        vec4<f32>(emitter.position, 0.0),
+        vec4<f32>(velocity, emitter.lifetime),
+        vec4<f32>(emitter.gravity, emitter.size),
+        emitter.color_start,
+        emitter.color_end,
+    );
+}
+
+@compute @workgroup_size(64)
+fn main(
+    @builtin(global_invocation_id) global_id: vec3<u32>
+) {
+    let index = global_id.x;
+    let particle_count = arrayLength(&particles);
+
+    if index >= particle_count {
+        return;
+    }
+
+    // Particles spawned this step form a range in the ring buffer
+    let spawn_index = (index + particle_count - simulation.spawn_start) % particle_count;
+    if spawn_index < simulation.spawn_count {
+        var state = hash(index ^ hash(simulation.seed));
+        particles[index] = spawn(spawn_index, &state);
+        return;
+    }
+
+    var particle = particles[index];
+    if !is_alive(particle) {
+        return;
+    }
+
+    let delta_time = simulation.delta_time;
+    let velocity = particle.velocity_lifetime.xyz + particle.gravity_size.xyz * delta_time;
+    particle.position_age = vec4<f32>(
+        particle.position_age.xyz + velocity * delta_time,
+        particle.position_age.w + delta_time,
+    );
+    particle.velocity_lifetime = vec4<f32>(velocity, particle.velocity_lifetime.w);
+
+    particles[index] = particle;
+}
@@ -0,0 +1,47 @@
+#import shared::fullscreen::{VertexOutput, texture_uv}
+#import shared::fullscreen::vs_main as fullscreen_vs_main
+
+struct BloomSettings {
+    threshold: f32,
+    intensity: f32,
+}
+
+@group(0) @binding(0)
+var input_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var input_sampler: sampler;
+
+@group(1) @binding(0)
+var<uniform> settings: BloomSettings;
+
+// Only used by the composite pass. The blur is done by the shared blur pass.
+@group(2) @binding(0)
+var bloom_texture: texture_2d<f32>;
+@group(2) @binding(1)
+var bloom_sampler: sampler;
+
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32,
+) -> VertexOutput {
+    return fullscreen_vs_main(vertex_index);
+}
+
+@fragment
+fn fs_bright(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(input_texture, input_sampler, texture_uv(in.uv)).rgb;
+    let brightness = max(color.r, max(color.g, color.b));
+    let contribution = max(brightness - settings.threshold, 0.0) / max(brightness, 0.0001);
+
+    return vec4<f32>(color * contribution, 1.0);
+}
+
+@fragment
+fn fs_composite(in: VertexOutput) -> @location(0) vec4<f32> {
+    let uv = texture_uv(in.uv);
+    let scene = textureSample(input_texture, input_sampler, uv).rgb;
+    let bloom = textureSample(bloom_texture, bloom_sampler, uv).rgb;
+
+    return vec4<f32>(scene + bloom * settings.intensity, 1.0);
+}
@@ -0,0 +1,66 @@
+#import shared::fullscreen::{VertexOutput, texture_uv}
+#import shared::fullscreen::vs_main as fullscreen_vs_main
+#import shared::globals::GlobalUniforms
+
+// The grain pattern changes at the frame rate of film, regardless of the display
+const GRAIN_FPS: f32 = 24.0;
+const LUMINANCE: vec3<f32> = vec3<f32>(0.2126, 0.7152, 0.0722);
+
+struct LensArtifactsSettings {
+    film_grain: f32,
+    vignette: f32,
+    chromatic_aberration: f32,
+}
+
+@group(0) @binding(0)
+var input_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var input_sampler: sampler;
+
+@group(1) @binding(0)
+var<uniform> settings: LensArtifactsSettings;
+
+@group(2) @binding(0)
+var<uniform> globals: GlobalUniforms;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    return fullscreen_vs_main(vertex_index);
+}
+
+// PCG hash
+fn hash(value: u32) -> u32 {
+    let state = value * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let uv = texture_uv(in.uv);
+    let from_center = uv - 0.5;
+
+    // Each channel is refracted by a slightly different amount, growing towards the edges
+    let offset = from_center * 2.0 * settings.chromatic_aberration;
+    let color = vec3<f32>(
+        textureSampleLevel(input_texture, input_sampler, uv - offset, 0.0).r,
+        textureSampleLevel(input_texture, input_sampler, uv, 0.0).g,
+        textureSampleLevel(input_texture, input_sampler, uv + offset, 0.0).b,
+    );
+
+    // Round regardless of the aspect ratio, reaching full strength at the corners
+    let aspect = globals.resolution.x / globals.resolution.y;
+    let corner_distance = length(from_center * vec2<f32>(aspect, 1.0)) / length(vec2<f32>(aspect, 1.0) * 0.5);
+    let vignette = 1.0 - settings.vignette * smoothstep(0.4, 1.0, corner_distance);
+
+    // Multiplicative, so that it survives tonemapping. Strongest in the midtones like real film.
+    let pixel = vec2<u32>(in.clip_position.xy);
+    let grain_frame = u32(globals.now * GRAIN_FPS);
+    let seed = hash(pixel.x + hash(pixel.y + hash(grain_frame)));
+    let noise = f32(seed) / 4294967295.0 - 0.5;
+    let luminance = dot(color, LUMINANCE);
+    let midtones = 1.0 - abs(clamp(luminance, 0.0, 1.0) * 2.0 - 1.0);
+    let grain = 1.0 + noise * 2.0 * settings.film_grain * mix(0.5, 1.0, midtones);
+
+    return vec4<f32>(color * vignette * grain, 1.0);
+}
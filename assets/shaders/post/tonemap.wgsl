@@ -0,0 +1,111 @@
+#import shared::fullscreen::{VertexOutput, texture_uv}
+#import shared::fullscreen::vs_main as fullscreen_vs_main
+
+const TONEMAPPER_ACES: u32 = 0u;
+const TONEMAPPER_REINHARD: u32 = 1u;
+
+const MIDDLE_GRAY: f32 = 0.18;
+const LUMINANCE: vec3<f32> = vec3<f32>(0.2126, 0.7152, 0.0722);
+
+struct TonemapSettings {
+    exposure: f32,
+    tonemapper: u32,
+    contrast: f32,
+    saturation: f32,
+    // W is unused
+    lift: vec4<f32>,
+    // W is unused
+    gamma: vec4<f32>,
+    // W = LUT strength
+    gain_lut_strength: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var input_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var input_sampler: sampler;
+
+@group(1) @binding(0)
+var<uniform> settings: TonemapSettings;
+@group(1) @binding(1)
+var lut: texture_3d<f32>;
+@group(1) @binding(2)
+var lut_sampler: sampler;
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32,
+) -> VertexOutput {
+    return fullscreen_vs_main(vertex_index);
+}
+
+// https://knarkowicz.wordpress.com/2016/01/06/aces-filmic-tone-mapping-curve/
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+
+    return (color * (a * color + b)) / (color * (c * color + d) + e);
+}
+
+fn reinhard(color: vec3<f32>) -> vec3<f32> {
+    return color / (1.0 + color);
+}
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+    let low = color * 12.92;
+    let high = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, color <= vec3<f32>(0.0031308));
+}
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, color <= vec3<f32>(0.04045));
+}
+
+// Contrast pivots around middle gray, so that it doesn't change the overall exposure
+fn grade_hdr(color: vec3<f32>) -> vec3<f32> {
+    let contrasted = pow(max(color, vec3<f32>(0.0)) / MIDDLE_GRAY, vec3<f32>(settings.contrast)) * MIDDLE_GRAY;
+    let luminance = dot(contrasted, LUMINANCE);
+    return max(mix(vec3<f32>(luminance), contrasted, settings.saturation), vec3<f32>(0.0));
+}
+
+// Lift, gamma and gain in the ASC CDL style, then the LUT. LUTs are authored for sRGB encoded
+// colors, so the lookup is done in that space.
+fn grade_ldr(color: vec3<f32>) -> vec3<f32> {
+    let gain = settings.gain_lut_strength.rgb;
+    let lifted = gain * (color + settings.lift.rgb * (1.0 - color));
+    let graded = pow(clamp(lifted, vec3<f32>(0.0), vec3<f32>(1.0)), 1.0 / max(settings.gamma.rgb, vec3<f32>(0.01)));
+
+    let lut_size = f32(textureDimensions(lut).x);
+    // Maps [0, 1] to the centers of the first and the last texel
+    let coordinates = linear_to_srgb(graded) * ((lut_size - 1.0) / lut_size) + 0.5 / lut_size;
+    let looked_up = srgb_to_linear(textureSampleLevel(lut, lut_sampler, coordinates, 0.0).rgb);
+
+    return mix(graded, looked_up, settings.gain_lut_strength.w);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let hdr_color = grade_hdr(textureSample(input_texture, input_sampler, texture_uv(in.uv)).rgb * settings.exposure);
+
+    var color: vec3<f32>;
+
+    switch settings.tonemapper {
+        case TONEMAPPER_ACES: {
+            color = aces(hdr_color);
+        }
+        case TONEMAPPER_REINHARD: {
+            color = reinhard(hdr_color);
+        }
+        default: {
+            color = hdr_color;
+        }
+    }
+
+    // The swapchain is sRGB, so the hardware takes care of gamma
+    return vec4<f32>(grade_ldr(clamp(color, vec3<f32>(0.0), vec3<f32>(1.0))), 1.0);
+}
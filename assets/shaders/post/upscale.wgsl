@@ -0,0 +1,88 @@
+#import shared::fullscreen::{VertexOutput, texture_uv}
+#import shared::fullscreen::vs_main as fullscreen_vs_main
+
+struct UpscaleSettings {
+    sharpness: f32,
+}
+
+@group(0) @binding(0)
+var input_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var input_sampler: sampler;
+
+@group(1) @binding(0)
+var<uniform> settings: UpscaleSettings;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    return fullscreen_vs_main(vertex_index);
+}
+
+fn sample_input(uv: vec2<f32>) -> vec3<f32> {
+    return textureSampleLevel(input_texture, input_sampler, uv, 0.0).rgb;
+}
+
+// Catmull-Rom filter with 9 bilinear taps instead of 16 point samples
+fn sample_catmull_rom(uv: vec2<f32>, texel_size: vec2<f32>) -> vec3<f32> {
+    let sample_position = uv / texel_size;
+    let texel_center = floor(sample_position - 0.5) + 0.5;
+    let f = sample_position - texel_center;
+
+    let w0 = f * (-0.5 + f * (1.0 - 0.5 * f));
+    let w1 = 1.0 + f * f * (-2.5 + 1.5 * f);
+    let w2 = f * (0.5 + f * (2.0 - 1.5 * f));
+    let w3 = f * f * (-0.5 + 0.5 * f);
+
+    // The middle two taps are merged into a single bilinear sample
+    let w12 = w1 + w2;
+    let offset12 = w2 / w12;
+
+    let uv0 = (texel_center - 1.0) * texel_size;
+    let uv3 = (texel_center + 2.0) * texel_size;
+    let uv12 = (texel_center + offset12) * texel_size;
+
+    var color = vec3<f32>(0.0);
+    color += sample_input(vec2<f32>(uv0.x, uv0.y)) * w0.x * w0.y;
+    color += sample_input(vec2<f32>(uv12.x, uv0.y)) * w12.x * w0.y;
+    color += sample_input(vec2<f32>(uv3.x, uv0.y)) * w3.x * w0.y;
+    color += sample_input(vec2<f32>(uv0.x, uv12.y)) * w0.x * w12.y;
+    color += sample_input(vec2<f32>(uv12.x, uv12.y)) * w12.x * w12.y;
+    color += sample_input(vec2<f32>(uv3.x, uv12.y)) * w3.x * w12.y;
+    color += sample_input(vec2<f32>(uv0.x, uv3.y)) * w0.x * w3.y;
+    color += sample_input(vec2<f32>(uv12.x, uv3.y)) * w12.x * w3.y;
+    color += sample_input(vec2<f32>(uv3.x, uv3.y)) * w3.x * w3.y;
+    return color;
+}
+
+// Contrast adaptive sharpening, like the RCAS pass of FSR 1. Sharpens less where the neighbourhood
+// is already close to clipping, which keeps edges from ringing.
+fn sharpen(color: vec3<f32>, uv: vec2<f32>, texel_size: vec2<f32>) -> vec3<f32> {
+    let north = sample_input(uv + vec2<f32>(0.0, -texel_size.y));
+    let south = sample_input(uv + vec2<f32>(0.0, texel_size.y));
+    let east = sample_input(uv + vec2<f32>(texel_size.x, 0.0));
+    let west = sample_input(uv + vec2<f32>(-texel_size.x, 0.0));
+
+    let min_color = min(min(min(north, south), min(east, west)), color);
+    let max_color = max(max(max(north, south), max(east, west)), color);
+
+    let headroom = min(min_color, 1.0 - max_color) / max(max_color, vec3<f32>(1e-4));
+    let amount = sqrt(clamp(headroom, vec3<f32>(0.0), vec3<f32>(1.0)));
+    let weight = -amount / mix(8.0, 5.0, settings.sharpness);
+
+    let sharpened = (color + (north + south + east + west) * weight) / (1.0 + 4.0 * weight);
+    // Also removes the overshoot of the Catmull-Rom filter
+    return clamp(sharpened, vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let uv = texture_uv(in.uv);
+
+#ifdef SHARPENED
+    let texel_size = 1.0 / vec2<f32>(textureDimensions(input_texture));
+    let color = sample_catmull_rom(uv, texel_size);
+    return vec4<f32>(sharpen(color, uv, texel_size), 1.0);
+#else
+    return vec4<f32>(sample_input(uv), 1.0);
+#endif
+}
@@ -0,0 +1,85 @@
+// Example SDF scene: a field of pillars with a blob pulsing to the beat. SDF scenes define
+// `scene_distance` and `scene_surface`, and march with the same loop.
+
+#import shared::fullscreen::VertexOutput
+#import shared::fullscreen::vs_main as fullscreen_vs_main
+#import shared::camera::CameraUniform
+#import shared::globals::GlobalUniforms
+#import shared::raymarch::{SdfSurface, SdfOutput, camera_ray, surface_output, sd_box, sd_sphere, op_smooth_union, op_repeat_xz}
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+@group(1) @binding(0)
+var<uniform> globals: GlobalUniforms;
+
+const MAX_STEPS: i32 = 128;
+const HIT_DISTANCE: f32 = 0.001;
+
+fn scene_distance(p: vec3<f32>) -> f32 {
+    // The pillars stand on y = 0
+    let cell = op_repeat_xz(p, vec2<f32>(6.0));
+    let pillar = sd_box(cell - vec3<f32>(0.0, 2.0, 0.0), vec3<f32>(0.4, 2.0, 0.4));
+
+    let pulse = 1.0 - fract(globals.beat);
+    let blob_center = vec3<f32>(0.0, 2.0 + sin(globals.now) * 0.5, 0.0);
+    let blob = sd_sphere(p - blob_center, 1.0 + pulse * 0.2);
+
+    return op_smooth_union(pillar, blob, 0.8);
+}
+
+fn scene_surface(p: vec3<f32>) -> SdfSurface {
+    var surface: SdfSurface;
+    surface.base_color = vec3<f32>(0.8, 0.78, 0.75);
+    surface.roughness = 0.4;
+    surface.metallic = 0.0;
+    // The blob glows on each beat
+    let glow = 1.0 - fract(globals.beat);
+    let near_blob = 1.0 - smoothstep(1.0, 1.5, length(p - vec3<f32>(0.0, 2.0, 0.0)));
+    surface.emission = vec3<f32>(1.0, 0.4, 0.1) * glow * near_blob * 4.0;
+    return surface;
+}
+
+fn scene_normal(p: vec3<f32>) -> vec3<f32> {
+    // Tetrahedron technique, four samples instead of six
+    let e = vec2<f32>(1.0, -1.0) * 0.0005;
+    return normalize(
+        e.xyy * scene_distance(p + e.xyy) +
+        e.yyx * scene_distance(p + e.yyx) +
+        e.yxy * scene_distance(p + e.yxy) +
+        e.xxx * scene_distance(p + e.xxx)
+    );
+}
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32,
+) -> VertexOutput {
+    return fullscreen_vs_main(vertex_index);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> SdfOutput {
+    let ray = camera_ray(camera, in.uv);
+    let far = camera.projection.z;
+
+    var t = camera.projection.y;
+    for (var i = 0; i < MAX_STEPS; i++) {
+        let p = ray.origin + ray.direction * t;
+        let distance = scene_distance(p);
+
+        if distance < HIT_DISTANCE * t {
+            return surface_output(camera, p, scene_normal(p), scene_surface(p));
+        }
+
+        t += distance;
+        if t > far {
+            break;
+        }
+    }
+
+    // Rasterized geometry or the background shows through
+    discard;
+    var missed: SdfOutput;
+    return missed;
+}
@@ -0,0 +1,23 @@
+#import shared::drawable::VisibleDrawable
+#import shared::shadow::ShadowCascade
+
+@group(0) @binding(0)
+var<uniform> cascade: ShadowCascade;
+
+@group(1) @binding(0)
+var<storage, read> drawables: array<VisibleDrawable>;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+}
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+    @builtin(instance_index) instance_index: u32,
+) -> @builtin(position) vec4<f32> {
+    let drawable = drawables[instance_index];
+    let world_position = drawable.model_matrix * vec4<f32>(model.position, 1.0);
+
+    return cascade.light_view_proj * world_position;
+}
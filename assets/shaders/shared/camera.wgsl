@@ -1,5 +1,17 @@
 #define_import_path shared::camera
 
 struct CameraUniform {
-    view_proj: mat4x4<f32>
+    // Includes the sub-pixel jitter of temporal anti-aliasing
+    view_proj: mat4x4<f32>,
+    inverse_view_proj: mat4x4<f32>,
+    // W is unused
+    position: vec4<f32>,
+    // X = vertical FOV in radians, Y = near plane, Z = far plane, W is unused
+    projection: vec4<f32>,
+    // Without jitter, for motion vectors
+    unjittered_view_proj: mat4x4<f32>,
+    // Unjittered view projection of the previous frame
+    previous_view_proj: mat4x4<f32>,
+    // XY = jitter of this frame in NDC, ZW = jitter of the previous frame
+    jitter: vec4<f32>,
 }
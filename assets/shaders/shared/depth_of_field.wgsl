@@ -0,0 +1,25 @@
+#define_import_path shared::depth_of_field
+
+struct DepthOfFieldSettings {
+    focus_distance: f32,
+    // Blur radius of infinitely distant points relative to the screen height
+    aperture: f32,
+    // In half resolution pixels, also the radius of the gather kernel
+    max_coc: f32,
+    near: f32,
+    far: f32,
+}
+
+// Inverse of the depth mapping of perspective_lh
+fn linear_depth(settings: DepthOfFieldSettings, depth: f32) -> f32 {
+    return settings.near * settings.far / (settings.far - depth * (settings.far - settings.near));
+}
+
+// Signed circle of confusion radius in half resolution pixels. Negative in front of the focus
+// plane (near field), positive behind it (far field).
+fn circle_of_confusion(settings: DepthOfFieldSettings, depth: f32, half_height: f32) -> f32 {
+    let distance = linear_depth(settings, depth);
+    let coc = settings.aperture * (1.0 - settings.focus_distance / distance) * half_height;
+
+    return clamp(coc, -settings.max_coc, settings.max_coc);
+}
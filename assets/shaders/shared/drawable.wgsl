@@ -1,18 +1,41 @@
 #define_import_path shared::drawable
 
+// Mesh index of unused drawable slots and culled drawables, and the end of LOD chains. This
+// should match Drawable::NO_PRIMITIVE and MeshInfo::NO_LOD.
+const NO_MESH: u32 = 0xffffffffu;
+
 struct InputDrawable {
     model_matrix: mat4x4<f32>,
     inverse_transpose_model_matrix: mat4x4<f32>,
+    // Model matrix of the previous frame, for motion vectors
+    previous_model_matrix: mat4x4<f32>,
     mesh_index: u32,
     material_id: u32,
-    padding: array<u32, 2>,
+    // Offset into the joint matrix buffer, or NO_JOINTS if the mesh isn't skinned
+    joint_offset: u32,
+    // Bitmask of the layers of the object
+    layers: u32,
+    // Multiplied with the base color and the emission of the material
+    tint: vec3<f32>,
+    emission: f32,
+    // Shader parameters of the object, in the order the scene file names them
+    params: vec4<f32>,
 }
 
 // This is just a copy, for now
 struct VisibleDrawable {
     model_matrix: mat4x4<f32>,
     inverse_transpose_model_matrix: mat4x4<f32>,
+    // Model matrix of the previous frame, for motion vectors
+    previous_model_matrix: mat4x4<f32>,
     mesh_index: u32,
     material_id: u32,
-    padding: array<u32, 2>,
+    // Offset into the joint matrix buffer, or NO_JOINTS if the mesh isn't skinned
+    joint_offset: u32,
+    // Slot of the drawable in the input drawables. Drawables that aren't culled on the GPU, like
+    // transparent ones, are uploaded as they are and have their layers here instead.
+    slot: u32,
+    tint: vec3<f32>,
+    emission: f32,
+    params: vec4<f32>,
 }
@@ -0,0 +1,193 @@
+#define_import_path shared::easing
+
+// The easing curves of `math::easing::Easing`. The Out curves are the In curves played
+// backwards, and the InOut curves play the In curve for the first half and the Out curve for the
+// second. Inputs are clamped to 0 to 1.
+
+const PI: f32 = 3.14159265359;
+const BACK_OVERSHOOT: f32 = 1.70158;
+
+fn ease_in_sine_unclamped(t: f32) -> f32 {
+    return 1.0 - cos(t * PI / 2.0);
+}
+
+fn ease_in_expo_unclamped(t: f32) -> f32 {
+    return select(pow(2.0, 10.0 * t - 10.0), 0.0, t <= 0.0);
+}
+
+fn ease_in_circ_unclamped(t: f32) -> f32 {
+    return 1.0 - sqrt(max(1.0 - t * t, 0.0));
+}
+
+fn ease_in_back_unclamped(t: f32) -> f32 {
+    return (BACK_OVERSHOOT + 1.0) * t * t * t - BACK_OVERSHOOT * t * t;
+}
+
+fn ease_in_elastic_unclamped(t: f32) -> f32 {
+    let elastic = -pow(2.0, 10.0 * t - 10.0) * sin((t * 10.0 - 10.75) * (2.0 * PI / 3.0));
+    return select(elastic, t, t <= 0.0 || t >= 1.0);
+}
+
+// Bounces of decreasing height, each a parabola
+fn ease_out_bounce_unclamped(t: f32) -> f32 {
+    let n = 7.5625;
+    let d = 2.75;
+
+    if t < 1.0 / d {
+        return n * t * t;
+    } else if t < 2.0 / d {
+        let u = t - 1.5 / d;
+        return n * u * u + 0.75;
+    } else if t < 2.5 / d {
+        let u = t - 2.25 / d;
+        return n * u * u + 0.9375;
+    }
+    let u = t - 2.625 / d;
+    return n * u * u + 0.984375;
+}
+
+fn ease_in_bounce_unclamped(t: f32) -> f32 {
+    return 1.0 - ease_out_bounce_unclamped(1.0 - t);
+}
+
+// The halves of an InOut curve, from the In curve sampled at `2t` and `2 - 2t`
+fn in_out(first: f32, second: f32, t: f32) -> f32 {
+    return select(1.0 - second / 2.0, first / 2.0, t < 0.5);
+}
+
+fn ease_in_sine(t: f32) -> f32 {
+    return ease_in_sine_unclamped(saturate(t));
+}
+
+fn ease_out_sine(t: f32) -> f32 {
+    return 1.0 - ease_in_sine_unclamped(1.0 - saturate(t));
+}
+
+fn ease_in_out_sine(t: f32) -> f32 {
+    let x = saturate(t);
+    return in_out(ease_in_sine_unclamped(2.0 * x), ease_in_sine_unclamped(2.0 - 2.0 * x), x);
+}
+
+fn ease_in_quad(t: f32) -> f32 {
+    let x = saturate(t);
+    return x * x;
+}
+
+fn ease_out_quad(t: f32) -> f32 {
+    let x = 1.0 - saturate(t);
+    return 1.0 - x * x;
+}
+
+fn ease_in_out_quad(t: f32) -> f32 {
+    let x = saturate(t);
+    return in_out(pow(2.0 * x, 2.0), pow(2.0 - 2.0 * x, 2.0), x);
+}
+
+fn ease_in_cubic(t: f32) -> f32 {
+    return pow(saturate(t), 3.0);
+}
+
+fn ease_out_cubic(t: f32) -> f32 {
+    return 1.0 - pow(1.0 - saturate(t), 3.0);
+}
+
+fn ease_in_out_cubic(t: f32) -> f32 {
+    let x = saturate(t);
+    return in_out(pow(2.0 * x, 3.0), pow(2.0 - 2.0 * x, 3.0), x);
+}
+
+fn ease_in_quart(t: f32) -> f32 {
+    return pow(saturate(t), 4.0);
+}
+
+fn ease_out_quart(t: f32) -> f32 {
+    return 1.0 - pow(1.0 - saturate(t), 4.0);
+}
+
+fn ease_in_out_quart(t: f32) -> f32 {
+    let x = saturate(t);
+    return in_out(pow(2.0 * x, 4.0), pow(2.0 - 2.0 * x, 4.0), x);
+}
+
+fn ease_in_quint(t: f32) -> f32 {
+    return pow(saturate(t), 5.0);
+}
+
+fn ease_out_quint(t: f32) -> f32 {
+    return 1.0 - pow(1.0 - saturate(t), 5.0);
+}
+
+fn ease_in_out_quint(t: f32) -> f32 {
+    let x = saturate(t);
+    return in_out(pow(2.0 * x, 5.0), pow(2.0 - 2.0 * x, 5.0), x);
+}
+
+fn ease_in_expo(t: f32) -> f32 {
+    return ease_in_expo_unclamped(saturate(t));
+}
+
+fn ease_out_expo(t: f32) -> f32 {
+    return 1.0 - ease_in_expo_unclamped(1.0 - saturate(t));
+}
+
+fn ease_in_out_expo(t: f32) -> f32 {
+    let x = saturate(t);
+    return in_out(ease_in_expo_unclamped(2.0 * x), ease_in_expo_unclamped(2.0 - 2.0 * x), x);
+}
+
+fn ease_in_circ(t: f32) -> f32 {
+    return ease_in_circ_unclamped(saturate(t));
+}
+
+fn ease_out_circ(t: f32) -> f32 {
+    return 1.0 - ease_in_circ_unclamped(1.0 - saturate(t));
+}
+
+fn ease_in_out_circ(t: f32) -> f32 {
+    let x = saturate(t);
+    return in_out(ease_in_circ_unclamped(2.0 * x), ease_in_circ_unclamped(2.0 - 2.0 * x), x);
+}
+
+fn ease_in_back(t: f32) -> f32 {
+    return ease_in_back_unclamped(saturate(t));
+}
+
+fn ease_out_back(t: f32) -> f32 {
+    return 1.0 - ease_in_back_unclamped(1.0 - saturate(t));
+}
+
+fn ease_in_out_back(t: f32) -> f32 {
+    let x = saturate(t);
+    return in_out(ease_in_back_unclamped(2.0 * x), ease_in_back_unclamped(2.0 - 2.0 * x), x);
+}
+
+fn ease_in_elastic(t: f32) -> f32 {
+    return ease_in_elastic_unclamped(saturate(t));
+}
+
+fn ease_out_elastic(t: f32) -> f32 {
+    return 1.0 - ease_in_elastic_unclamped(1.0 - saturate(t));
+}
+
+fn ease_in_out_elastic(t: f32) -> f32 {
+    let x = saturate(t);
+    return in_out(ease_in_elastic_unclamped(2.0 * x), ease_in_elastic_unclamped(2.0 - 2.0 * x), x);
+}
+
+fn ease_in_bounce(t: f32) -> f32 {
+    return ease_in_bounce_unclamped(saturate(t));
+}
+
+fn ease_out_bounce(t: f32) -> f32 {
+    return ease_out_bounce_unclamped(saturate(t));
+}
+
+fn ease_in_out_bounce(t: f32) -> f32 {
+    let x = saturate(t);
+    return in_out(ease_in_bounce_unclamped(2.0 * x), ease_in_bounce_unclamped(2.0 - 2.0 * x), x);
+}
+
+fn smooth_step(t: f32) -> f32 {
+    let x = saturate(t);
+    return x * x * (3.0 - 2.0 * x);
+}
@@ -17,4 +17,9 @@ fn vs_main(
     out.clip_position = vec4<f32>(x, y, 0.0, 1.0);
     out.uv = vec2<f32>(x * 0.5 + 0.5, y * 0.5 + 0.5);
     return out;
-}
\ No newline at end of file
+}
+
+// Converts the UV of the fullscreen triangle (Y up) to texture coordinates (Y down)
+fn texture_uv(uv: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(uv.x, 1.0 - uv.y);
+}
@@ -3,4 +3,8 @@
 struct GlobalUniforms {
     resolution: vec2<f32>,
     now: f32,
+    // Fractional beat number of the soundtrack, use fract() for the phase within a beat
+    beat: f32,
+    // Free-form parameters, usually animated by the timeline
+    params: vec4<f32>,
 }
@@ -0,0 +1,113 @@
+#define_import_path shared::light
+
+#import shared::frustum::Frustum
+
+const LIGHT_TYPE_DIRECTIONAL: u32 = 0u;
+const LIGHT_TYPE_POINT: u32 = 1u;
+const LIGHT_TYPE_SPOT: u32 = 2u;
+
+struct Light {
+    // W: light type
+    position_type: vec4<f32>,
+    // Direction the light is shining towards. W: range
+    direction_range: vec4<f32>,
+    // W: intensity
+    color_intensity: vec4<f32>,
+    // X: cosine of the inner cone angle, Y: cosine of the outer cone angle,
+    // Z: 1 if the light casts shadows
+    spot: vec4<f32>,
+}
+
+struct Lights {
+    count: u32,
+    lights: array<Light>,
+}
+
+// These should match CLUSTER_GRID and MAX_LIGHTS_PER_CLUSTER in light_buffer.rs
+const CLUSTER_GRID_X: u32 = 16u;
+const CLUSTER_GRID_Y: u32 = 9u;
+const CLUSTER_GRID_Z: u32 = 24u;
+const CLUSTER_COUNT: u32 = CLUSTER_GRID_X * CLUSTER_GRID_Y * CLUSTER_GRID_Z;
+const MAX_LIGHTS_PER_CLUSTER: u32 = 63u;
+// Each cluster's list is a count followed by the light indices
+const CLUSTER_STRIDE: u32 = MAX_LIGHTS_PER_CLUSTER + 1u;
+
+// The clusters split the screen into tiles, and the depth into slices that grow exponentially
+// from the near plane to the far plane
+struct LightClusters {
+    view: mat4x4<f32>,
+    frustum: Frustum,
+    // X = tangent of half the horizontal FOV, Y = same for vertical, Z = near, W = far
+    projection: vec4<f32>,
+}
+
+// View space depth where a depth slice starts
+fn cluster_slice_depth(clusters: LightClusters, slice: u32) -> f32 {
+    let near = clusters.projection.z;
+    let far = clusters.projection.w;
+
+    return near * pow(far / near, f32(slice) / f32(CLUSTER_GRID_Z));
+}
+
+// UV (0, 0) is the top left corner of the screen
+fn cluster_index(clusters: LightClusters, uv: vec2<f32>, view_depth: f32) -> u32 {
+    let near = clusters.projection.z;
+    let far = clusters.projection.w;
+
+    let tile = min(
+        vec2<u32>(saturate(uv) * vec2<f32>(f32(CLUSTER_GRID_X), f32(CLUSTER_GRID_Y))),
+        vec2<u32>(CLUSTER_GRID_X - 1u, CLUSTER_GRID_Y - 1u),
+    );
+    let slice_position = log(max(view_depth, near) / near) / log(far / near) * f32(CLUSTER_GRID_Z);
+    let slice = min(u32(slice_position), CLUSTER_GRID_Z - 1u);
+
+    return tile.x + tile.y * CLUSTER_GRID_X + slice * CLUSTER_GRID_X * CLUSTER_GRID_Y;
+}
+
+struct LightSample {
+    // Direction from the surface towards the light
+    direction: vec3<f32>,
+    radiance: vec3<f32>,
+}
+
+fn light_type(light: Light) -> u32 {
+    return u32(light.position_type.w);
+}
+
+fn casts_shadows(light: Light) -> bool {
+    return light.spot.z > 0.5;
+}
+
+// Smooth windowed inverse square falloff
+fn distance_attenuation(distance: f32, range: f32) -> f32 {
+    let ratio = distance / range;
+    let window = clamp(1.0 - ratio * ratio * ratio * ratio, 0.0, 1.0);
+
+    return window * window / max(distance * distance, 0.0001);
+}
+
+fn sample_light(light: Light, world_position: vec3<f32>) -> LightSample {
+    var out: LightSample;
+    let radiance = light.color_intensity.rgb * light.color_intensity.w;
+    let ty = light_type(light);
+
+    if ty == LIGHT_TYPE_DIRECTIONAL {
+        out.direction = -normalize(light.direction_range.xyz);
+        out.radiance = radiance;
+        return out;
+    }
+
+    let to_light = light.position_type.xyz - world_position;
+    let distance = length(to_light);
+    out.direction = to_light / max(distance, 0.0001);
+
+    var attenuation = distance_attenuation(distance, light.direction_range.w);
+
+    if ty == LIGHT_TYPE_SPOT {
+        let cos_angle = dot(-out.direction, normalize(light.direction_range.xyz));
+        attenuation *= smoothstep(light.spot.y, light.spot.x, cos_angle);
+    }
+
+    out.radiance = radiance * attenuation;
+    return out;
+}
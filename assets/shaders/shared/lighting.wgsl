@@ -0,0 +1,127 @@
+#define_import_path shared::lighting
+
+#import shared::ibl::fresnel_schlick_roughness
+#import shared::shadow::{ShadowUniform, cascade_coords, cascade_edge_distance}
+
+// Offsets the sampling position along the normal to reduce shadow acne, in shadow map texels
+const SHADOW_NORMAL_OFFSET: f32 = 1.0;
+
+// 3x3 PCF in one cascade, returns 1.0 for fully lit and 0.0 for fully shadowed
+fn sample_cascade(
+    shadow_map: texture_depth_2d_array,
+    shadow_sampler: sampler_comparison,
+    index: u32,
+    coords: vec3<f32>,
+) -> f32 {
+    let texel_size = 1.0 / vec2<f32>(textureDimensions(shadow_map));
+    var lit = 0.0;
+
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let offset = vec2<f32>(f32(x), f32(y)) * texel_size;
+            lit += textureSampleCompareLevel(shadow_map, shadow_sampler, coords.xy + offset, index, coords.z);
+        }
+    }
+
+    return lit / 9.0;
+}
+
+// Samples the most detailed cascade that contains the position, and fades into the next one at
+// its edges. Positions outside of every cascade are lit.
+fn sample_shadow(
+    shadow_map: texture_depth_2d_array,
+    shadow_sampler: sampler_comparison,
+    shadow: ShadowUniform,
+    world_position: vec3<f32>,
+    normal: vec3<f32>,
+) -> f32 {
+    // Arrays can only be indexed dynamically through a variable
+    var cascades = shadow.cascades;
+
+    for (var i = 0u; i < shadow.cascade_count; i++) {
+        let cascade = cascades[i];
+        let offset_position = world_position + normal * cascade.texel_size * SHADOW_NORMAL_OFFSET;
+        let coords = cascade_coords(cascade, offset_position);
+        let edge_distance = cascade_edge_distance(coords);
+
+        if edge_distance < 0.0 {
+            continue;
+        }
+
+        let lit = sample_cascade(shadow_map, shadow_sampler, i, coords);
+        let blend = saturate(edge_distance / max(shadow.blend_fraction, 1e-4));
+        if blend >= 1.0 || i + 1u >= shadow.cascade_count {
+            return lit;
+        }
+
+        let next = cascades[i + 1u];
+        let next_position = world_position + normal * next.texel_size * SHADOW_NORMAL_OFFSET;
+        let next_coords = cascade_coords(next, next_position);
+        let next_lit = select(
+            1.0,
+            sample_cascade(shadow_map, shadow_sampler, i + 1u, next_coords),
+            cascade_edge_distance(next_coords) >= 0.0,
+        );
+
+        return mix(next_lit, lit, blend);
+    }
+
+    return 1.0;
+}
+
+// Split sum approximation of the environment's diffuse and specular contribution
+fn ambient_light(
+    irradiance_map: texture_cube<f32>,
+    prefiltered_map: texture_cube<f32>,
+    brdf_lut: texture_2d<f32>,
+    environment_sampler: sampler,
+    normal: vec3<f32>,
+    view_direction: vec3<f32>,
+    base_color: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+) -> vec3<f32> {
+    let irradiance = textureSampleLevel(irradiance_map, environment_sampler, normal, 0.0).rgb;
+
+    let reflection = reflect(-view_direction, normal);
+    let max_lod = f32(textureNumLevels(prefiltered_map) - 1u);
+    let prefiltered = textureSampleLevel(prefiltered_map, environment_sampler, reflection, roughness * max_lod).rgb;
+
+    return combine_ambient_light(
+        irradiance,
+        prefiltered,
+        brdf_lut,
+        environment_sampler,
+        normal,
+        view_direction,
+        base_color,
+        metallic,
+        roughness,
+    );
+}
+
+// Same as ambient_light, for irradiance and prefiltered radiance that have already been sampled,
+// e.g. blended from several reflection probes
+fn combine_ambient_light(
+    irradiance: vec3<f32>,
+    prefiltered: vec3<f32>,
+    brdf_lut: texture_2d<f32>,
+    environment_sampler: sampler,
+    normal: vec3<f32>,
+    view_direction: vec3<f32>,
+    base_color: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+) -> vec3<f32> {
+    let n_dot_v = max(dot(normal, view_direction), 0.0);
+    let f0 = mix(vec3<f32>(0.04), base_color, metallic);
+    let fresnel = fresnel_schlick_roughness(n_dot_v, f0, roughness);
+
+    let diffuse_weight = (vec3<f32>(1.0) - fresnel) * (1.0 - metallic);
+    let diffuse = diffuse_weight * irradiance * base_color;
+
+    let environment_brdf = textureSampleLevel(brdf_lut, environment_sampler, vec2<f32>(n_dot_v, roughness), 0.0).rg;
+    let specular = prefiltered * (fresnel * environment_brdf.x + environment_brdf.y);
+
+    return diffuse + specular;
+}
@@ -1,8 +1,107 @@
 #define_import_path shared::material_info
 
+const ALPHA_MODE_OPAQUE: u32 = 0u;
+const ALPHA_MODE_MASK: u32 = 1u;
+const ALPHA_MODE_BLEND: u32 = 2u;
+
+// Bit offsets of the sampler indices in MaterialInfo.samplers
+const SAMPLER_BASE_COLOR: u32 = 0u;
+const SAMPLER_NORMAL: u32 = 8u;
+const SAMPLER_AO_ROUGHNESS_METALLIC: u32 = 16u;
+const SAMPLER_EMISSIVE: u32 = 24u;
+// Repeats with trilinear and anisotropic filtering. Used for terrain layers.
+const DEFAULT_SAMPLER: u32 = 0u;
+
 struct MaterialInfo {
     base_color: u32,
     normal: u32,
     ao_roughness_metallic: u32,
-    _padding: u32,
+    emissive: u32,
+    // Multiplied with the emissive texture, W is unused
+    emissive_factor: vec4<f32>,
+    alpha_mode: u32,
+    alpha_cutoff: f32,
+    normal_strength: f32,
+    flip_normal_green: u32,
+    // XY: UV scale, ZW: UV offset
+    uv_transform: vec4<f32>,
+    // Range in the terrain layer buffer. Terrain materials have no textures of their own.
+    terrain_layer_start: u32,
+    terrain_layer_count: u32,
+    // Indices to the sampler array, 8 bits per texture
+    samplers: u32,
+    // 1 while the color space audit highlights the material
+    highlight: u32,
+    // XY: offset, ZW: size of the material's rectangle in its atlas page. (0, 0, 1, 1) for
+    // materials with textures of their own.
+    atlas_rect: vec4<f32>,
+}
+
+// Texture coordinates of a material, with their screen space derivatives for textureSampleGrad
+struct MaterialUv {
+    uv: vec2<f32>,
+    ddx: vec2<f32>,
+    ddy: vec2<f32>,
+}
+
+struct TerrainLayer {
+    base_color: u32,
+    normal: u32,
+    ao_roughness_metallic: u32,
+    tile_size: f32,
+    // XY: height range, ZW: slope range
+    ranges: vec4<f32>,
+    // X: height blend, Y: slope blend, ZW are unused
+    blend: vec4<f32>,
+}
+
+// How much a terrain layer covers a point, before normalizing. Slope is 0 for flat ground and 1
+// for vertical cliffs.
+fn terrain_layer_weight(layer: TerrainLayer, height: f32, slope: f32) -> f32 {
+    let height_blend = max(layer.blend.x, 0.0001);
+    let slope_blend = max(layer.blend.y, 0.0001);
+    let height_weight = saturate(1.0 - max(layer.ranges.x - height, height - layer.ranges.y) / height_blend);
+    let slope_weight = saturate(1.0 - max(layer.ranges.z - slope, slope - layer.ranges.w) / slope_blend);
+    return height_weight * slope_weight;
+}
+
+// Atlased materials repeat inside their rectangle, which the sampler can't do on its own. Other
+// materials are left to wrap as their samplers do. The derivatives are taken before wrapping, so
+// that the wrap doesn't pick the smallest mip. Must be called in uniform control flow.
+fn material_uv(material: MaterialInfo, uv: vec2<f32>) -> MaterialUv {
+    let transformed = uv * material.uv_transform.xy + material.uv_transform.zw;
+    let atlased = any(material.atlas_rect.zw != vec2<f32>(1.0));
+
+    var out: MaterialUv;
+    out.uv = material.atlas_rect.xy + select(transformed, fract(transformed), atlased) * material.atlas_rect.zw;
+    out.ddx = dpdx(transformed) * material.atlas_rect.zw;
+    out.ddy = dpdy(transformed) * material.atlas_rect.zw;
+    return out;
+}
+
+// Index of the sampler of one of the material's textures, with one of the SAMPLER constants
+fn material_sampler(material: MaterialInfo, texture: u32) -> u32 {
+    return (material.samplers >> texture) & 0xffu;
+}
+
+// 1 on the diagonal stripes drawn over materials highlighted by the color space audit, 0
+// elsewhere
+fn audit_highlight(material: MaterialInfo, frag_coord: vec2<f32>) -> f32 {
+    if material.highlight == 0u {
+        return 0.0;
+    }
+
+    return step(0.5, fract((frag_coord.x + frag_coord.y) / 16.0));
+}
+
+// Decodes a normal map sample to a tangent space normal
+fn decode_normal(material: MaterialInfo, normal_sample: vec4<f32>) -> vec3<f32> {
+    var normal_xy = normal_sample.rg * 2.0 - 1.0;
+    if material.flip_normal_green != 0u {
+        normal_xy.y = -normal_xy.y;
+    }
+
+    // Z is reconstructed, because two channel (BC5) normal maps don't store it
+    let normal_z = sqrt(max(1.0 - dot(normal_xy, normal_xy), 0.0));
+    return normalize(vec3<f32>(normal_xy * material.normal_strength, normal_z));
 }
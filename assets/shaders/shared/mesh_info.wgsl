@@ -4,7 +4,41 @@ struct MeshInfo {
     index_count: u32,
     first_index: u32,
     vertex_offset: u32,
-    _padding: u32,
-    aabb_min: vec4<f32>,
+    // Mesh which is drawn instead of this one beyond lod_distance, or NO_MESH
+    next_lod: u32,
+    aabb_min: vec3<f32>,
+    lod_distance: f32,
     aabb_max: vec4<f32>,
+    // Center in xyz and radius in w
+    bounding_sphere: vec4<f32>,
+    // Range in the meshlet buffer, empty if the meshes were baked without meshlets
+    first_meshlet: u32,
+    meshlet_count: u32,
+    // Zero for skinned meshes, whose meshlet bounds are only valid in the bind pose
+    cull_meshlets: u32,
+    padding: u32,
 }
+
+// This should match MeshletInfo
+struct MeshletInfo {
+    first_index: u32,
+    index_count: u32,
+    padding0: u32,
+    padding1: u32,
+    // Center in xyz and radius in w
+    bounding_sphere: vec4<f32>,
+    cone_apex: vec4<f32>,
+    // Axis in xyz, cutoff in w
+    cone_axis_cutoff: vec4<f32>,
+}
+
+// A meshlet of a visible drawable, which is referred to by the meshlet index buffer
+struct VisibleMeshlet {
+    drawable_index: u32,
+    meshlet_index: u32,
+}
+
+// Entries of the meshlet index buffer have the visible meshlet in the high bits, and the corner
+// within the meshlet in the low bits. This should match MESHLET_CORNER_BITS.
+const CORNER_BITS: u32 = 9u;
+const CORNER_MASK: u32 = 0x1ffu;
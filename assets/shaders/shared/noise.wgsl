@@ -0,0 +1,212 @@
+#define_import_path shared::noise
+
+// Gradient noise, the same functions as `math::noise` on the CPU with the same hash, so both see
+// the same values up to floating point precision. All noise is roughly in -1 to 1, and the
+// Perlin noise is zero at integer coordinates.
+
+// PCG hash, see "Hash Functions for GPU Rendering" by Jarzynski & Olano
+fn hash(value: u32) -> u32 {
+    let state = value * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+fn hash2(cell: vec2<i32>) -> u32 {
+    return hash(bitcast<u32>(cell.x) ^ hash(bitcast<u32>(cell.y)));
+}
+
+fn hash3(cell: vec3<i32>) -> u32 {
+    return hash(bitcast<u32>(cell.x) ^ hash(bitcast<u32>(cell.y) ^ hash(bitcast<u32>(cell.z))));
+}
+
+// Gradient from -1 to 1
+fn gradient1(h: u32, offset: f32) -> f32 {
+    return (f32(h & 15u) / 7.5 - 1.0) * offset;
+}
+
+// One of eight directions along the axes and diagonals
+fn gradient2(h: u32, offset: vec2<f32>) -> f32 {
+    var gradients = array<vec2<f32>, 8>(
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, 1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(-1.0, 0.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(0.0, -1.0),
+    );
+    return dot(gradients[h & 7u], offset);
+}
+
+// One of the twelve edge directions of a cube, as in improved Perlin noise
+fn gradient3(value: u32, offset: vec3<f32>) -> f32 {
+    let h = value & 15u;
+    let u = select(offset.y, offset.x, h < 8u);
+    let v = select(select(offset.z, offset.x, h == 12u || h == 14u), offset.y, h < 4u);
+    return select(-u, u, (h & 1u) == 0u) + select(-v, v, (h & 2u) == 0u);
+}
+
+// Quintic curve with zero first and second derivatives at the ends
+fn fade(t: f32) -> f32 {
+    return t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+}
+
+fn perlin1(x: f32) -> f32 {
+    let cell = floor(x);
+    let offset = x - cell;
+    let i = i32(cell);
+
+    let n0 = gradient1(hash(bitcast<u32>(i)), offset);
+    let n1 = gradient1(hash(bitcast<u32>(i + 1)), offset - 1.0);
+    // The largest value is reached halfway between cells with opposite gradients
+    return mix(n0, n1, fade(offset)) * 2.0;
+}
+
+fn perlin2(p: vec2<f32>) -> f32 {
+    let cell = floor(p);
+    let offset = p - cell;
+    let i = vec2<i32>(cell);
+
+    let n00 = gradient2(hash2(i), offset);
+    let n10 = gradient2(hash2(i + vec2<i32>(1, 0)), offset - vec2<f32>(1.0, 0.0));
+    let n01 = gradient2(hash2(i + vec2<i32>(0, 1)), offset - vec2<f32>(0.0, 1.0));
+    let n11 = gradient2(hash2(i + vec2<i32>(1, 1)), offset - vec2<f32>(1.0, 1.0));
+
+    let f = vec2<f32>(fade(offset.x), fade(offset.y));
+    return mix(mix(n00, n10, f.x), mix(n01, n11, f.x), f.y);
+}
+
+fn perlin3_corner(i: vec3<i32>, offset: vec3<f32>, corner: vec3<i32>) -> f32 {
+    return gradient3(hash3(i + corner), offset - vec3<f32>(corner));
+}
+
+fn perlin3(p: vec3<f32>) -> f32 {
+    let cell = floor(p);
+    let offset = p - cell;
+    let i = vec3<i32>(cell);
+    let f = vec3<f32>(fade(offset.x), fade(offset.y), fade(offset.z));
+
+    let near = mix(
+        mix(perlin3_corner(i, offset, vec3<i32>(0, 0, 0)), perlin3_corner(i, offset, vec3<i32>(1, 0, 0)), f.x),
+        mix(perlin3_corner(i, offset, vec3<i32>(0, 1, 0)), perlin3_corner(i, offset, vec3<i32>(1, 1, 0)), f.x),
+        f.y,
+    );
+    let far = mix(
+        mix(perlin3_corner(i, offset, vec3<i32>(0, 0, 1)), perlin3_corner(i, offset, vec3<i32>(1, 0, 1)), f.x),
+        mix(perlin3_corner(i, offset, vec3<i32>(0, 1, 1)), perlin3_corner(i, offset, vec3<i32>(1, 1, 1)), f.x),
+        f.y,
+    );
+    return mix(near, far, f.z);
+}
+
+// Contribution of a simplex corner, which fades out at `radius_squared`
+fn simplex_falloff(radius_squared: f32, offset: vec2<f32>) -> f32 {
+    let t = max(radius_squared - dot(offset, offset), 0.0);
+    return t * t * t * t;
+}
+
+fn simplex_falloff3(radius_squared: f32, offset: vec3<f32>) -> f32 {
+    let t = max(radius_squared - dot(offset, offset), 0.0);
+    return t * t * t * t;
+}
+
+// Simplex noise from "Simplex noise demystified" by Stefan Gustavson
+fn simplex2(p: vec2<f32>) -> f32 {
+    let skew = 0.5 * (sqrt(3.0) - 1.0);
+    let unskew = (3.0 - sqrt(3.0)) / 6.0;
+
+    let cell = floor(p + (p.x + p.y) * skew);
+    let offset0 = p - (cell - (cell.x + cell.y) * unskew);
+    let i = vec2<i32>(cell);
+
+    // Which of the two triangles of the skewed cell the point is in
+    let middle = select(vec2<i32>(0, 1), vec2<i32>(1, 0), offset0.x > offset0.y);
+    let offset1 = offset0 - vec2<f32>(middle) + unskew;
+    let offset2 = offset0 - 1.0 + 2.0 * unskew;
+
+    let n0 = simplex_falloff(0.5, offset0) * gradient2(hash2(i), offset0);
+    let n1 = simplex_falloff(0.5, offset1) * gradient2(hash2(i + middle), offset1);
+    let n2 = simplex_falloff(0.5, offset2) * gradient2(hash2(i + 1), offset2);
+
+    return 70.0 * (n0 + n1 + n2);
+}
+
+fn simplex3(p: vec3<f32>) -> f32 {
+    let skew = 1.0 / 3.0;
+    let unskew = 1.0 / 6.0;
+
+    let cell = floor(p + (p.x + p.y + p.z) * skew);
+    let offset0 = p - (cell - (cell.x + cell.y + cell.z) * unskew);
+    let i = vec3<i32>(cell);
+
+    // The skewed cell is split into six tetrahedra by the order of the offsets
+    var corner1: vec3<i32>;
+    var corner2: vec3<i32>;
+    if offset0.x >= offset0.y {
+        if offset0.y >= offset0.z {
+            corner1 = vec3<i32>(1, 0, 0);
+            corner2 = vec3<i32>(1, 1, 0);
+        } else if offset0.x >= offset0.z {
+            corner1 = vec3<i32>(1, 0, 0);
+            corner2 = vec3<i32>(1, 0, 1);
+        } else {
+            corner1 = vec3<i32>(0, 0, 1);
+            corner2 = vec3<i32>(1, 0, 1);
+        }
+    } else if offset0.y < offset0.z {
+        corner1 = vec3<i32>(0, 0, 1);
+        corner2 = vec3<i32>(0, 1, 1);
+    } else if offset0.x < offset0.z {
+        corner1 = vec3<i32>(0, 1, 0);
+        corner2 = vec3<i32>(0, 1, 1);
+    } else {
+        corner1 = vec3<i32>(0, 1, 0);
+        corner2 = vec3<i32>(1, 1, 0);
+    }
+
+    let offset1 = offset0 - vec3<f32>(corner1) + unskew;
+    let offset2 = offset0 - vec3<f32>(corner2) + 2.0 * unskew;
+    let offset3 = offset0 - 1.0 + 3.0 * unskew;
+
+    let n0 = simplex_falloff3(0.6, offset0) * gradient3(hash3(i), offset0);
+    let n1 = simplex_falloff3(0.6, offset1) * gradient3(hash3(i + corner1), offset1);
+    let n2 = simplex_falloff3(0.6, offset2) * gradient3(hash3(i + corner2), offset2);
+    let n3 = simplex_falloff3(0.6, offset3) * gradient3(hash3(i + 1), offset3);
+
+    return 32.0 * (n0 + n1 + n2 + n3);
+}
+
+// Fractal Brownian motion of simplex noise, normalized by the total amplitude like `Fbm::sample`.
+// The defaults of `Fbm` are 5 octaves, a lacunarity of 2 and a gain of 0.5.
+fn fbm2(p: vec2<f32>, octaves: u32, lacunarity: f32, gain: f32) -> f32 {
+    var sum = 0.0;
+    var amplitude = 1.0;
+    var frequency = 1.0;
+    var total_amplitude = 0.0;
+
+    for (var octave = 0u; octave < octaves; octave++) {
+        sum += simplex2(p * frequency) * amplitude;
+        total_amplitude += amplitude;
+        amplitude *= gain;
+        frequency *= lacunarity;
+    }
+
+    return select(0.0, sum / total_amplitude, total_amplitude > 0.0);
+}
+
+fn fbm3(p: vec3<f32>, octaves: u32, lacunarity: f32, gain: f32) -> f32 {
+    var sum = 0.0;
+    var amplitude = 1.0;
+    var frequency = 1.0;
+    var total_amplitude = 0.0;
+
+    for (var octave = 0u; octave < octaves; octave++) {
+        sum += simplex3(p * frequency) * amplitude;
+        total_amplitude += amplitude;
+        amplitude *= gain;
+        frequency *= lacunarity;
+    }
+
+    return select(0.0, sum / total_amplitude, total_amplitude > 0.0);
+}
@@ -0,0 +1,17 @@
+#define_import_path shared::particle
+
+// This should match GpuParticle
+struct Particle {
+    // W: age in seconds
+    position_age: vec4<f32>,
+    // W: lifetime in seconds, the particle is dead once its age exceeds this
+    velocity_lifetime: vec4<f32>,
+    // W: size
+    gravity_size: vec4<f32>,
+    color_start: vec4<f32>,
+    color_end: vec4<f32>,
+}
+
+fn is_alive(particle: Particle) -> bool {
+    return particle.position_age.w < particle.velocity_lifetime.w;
+}
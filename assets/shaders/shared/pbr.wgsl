@@ -0,0 +1,57 @@
+#define_import_path shared::pbr
+
+const PI: f32 = 3.14159265359;
+
+// Trowbridge-Reitz GGX normal distribution function
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let denominator = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+
+    return a2 / (PI * denominator * denominator);
+}
+
+fn geometry_schlick_ggx(n_dot_v: f32, roughness: f32) -> f32 {
+    let r = roughness + 1.0;
+    let k = (r * r) / 8.0;
+
+    return n_dot_v / (n_dot_v * (1.0 - k) + k);
+}
+
+fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    return geometry_schlick_ggx(n_dot_v, roughness) * geometry_schlick_ggx(n_dot_l, roughness);
+}
+
+fn fresnel_schlick(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+    return f0 + (1.0 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+// Cook-Torrance BRDF for a single light, multiplied by the cosine term.
+// The result should be multiplied by the light's radiance.
+fn brdf(
+    normal: vec3<f32>,
+    view_direction: vec3<f32>,
+    light_direction: vec3<f32>,
+    base_color: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+) -> vec3<f32> {
+    let halfway = normalize(view_direction + light_direction);
+
+    let n_dot_v = max(dot(normal, view_direction), 0.0001);
+    let n_dot_l = max(dot(normal, light_direction), 0.0);
+    let n_dot_h = max(dot(normal, halfway), 0.0);
+    let h_dot_v = max(dot(halfway, view_direction), 0.0);
+
+    let f0 = mix(vec3<f32>(0.04), base_color, metallic);
+    let fresnel = fresnel_schlick(h_dot_v, f0);
+    let distribution = distribution_ggx(n_dot_h, roughness);
+    let geometry = geometry_smith(n_dot_v, n_dot_l, roughness);
+
+    let specular = (distribution * geometry * fresnel) / (4.0 * n_dot_v * max(n_dot_l, 0.0001));
+
+    let diffuse_weight = (vec3<f32>(1.0) - fresnel) * (1.0 - metallic);
+    let diffuse = diffuse_weight * base_color / PI;
+
+    return (diffuse + specular) * n_dot_l;
+}
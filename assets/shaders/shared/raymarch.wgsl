@@ -0,0 +1,94 @@
+#define_import_path shared::raymarch
+
+#import shared::camera::CameraUniform
+
+struct Ray {
+    origin: vec3<f32>,
+    direction: vec3<f32>,
+}
+
+// Material of a raymarched surface, same channels as the G-buffer
+struct SdfSurface {
+    base_color: vec3<f32>,
+    roughness: f32,
+    metallic: f32,
+    emission: vec3<f32>,
+}
+
+// Written to the G-buffer like rasterized geometry. The depth decides which one is in front.
+struct SdfOutput {
+    @location(0) color_roughness: vec4<f32>,
+    @location(1) normal_metallic: vec4<f32>,
+    @location(2) emission: vec4<f32>,
+    @location(3) velocity: vec2<f32>,
+    @builtin(frag_depth) depth: f32,
+}
+
+// Ray from the camera through a pixel, from the UV of the fullscreen triangle
+fn camera_ray(camera: CameraUniform, uv: vec2<f32>) -> Ray {
+    let clip = vec4<f32>(uv * 2.0 - 1.0, 1.0, 1.0);
+    let world = camera.inverse_view_proj * clip;
+
+    var ray: Ray;
+    ray.origin = camera.position.xyz;
+    ray.direction = normalize(world.xyz / world.w - camera.position.xyz);
+    return ray;
+}
+
+fn clip_to_uv(clip: vec4<f32>) -> vec2<f32> {
+    let ndc = clip.xy / clip.w;
+    return vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+}
+
+// G-buffer values for a hit. SDF scenes are treated as static for motion vectors.
+fn surface_output(
+    camera: CameraUniform,
+    position: vec3<f32>,
+    normal: vec3<f32>,
+    surface: SdfSurface,
+) -> SdfOutput {
+    let world = vec4<f32>(position, 1.0);
+    let clip = camera.view_proj * world;
+
+    var out: SdfOutput;
+    out.color_roughness = vec4<f32>(surface.base_color, surface.roughness);
+    out.normal_metallic = vec4<f32>(normal, surface.metallic);
+    out.emission = vec4<f32>(surface.emission, 1.0);
+    out.velocity = clip_to_uv(camera.unjittered_view_proj * world)
+        - clip_to_uv(camera.previous_view_proj * world);
+    out.depth = clip.z / clip.w;
+    return out;
+}
+
+// Distance functions for building scenes, centered on the origin
+
+fn sd_sphere(p: vec3<f32>, radius: f32) -> f32 {
+    return length(p) - radius;
+}
+
+fn sd_box(p: vec3<f32>, half_size: vec3<f32>) -> f32 {
+    let q = abs(p) - half_size;
+    return length(max(q, vec3<f32>(0.0))) + min(max(q.x, max(q.y, q.z)), 0.0);
+}
+
+// Torus around the Y axis
+fn sd_torus(p: vec3<f32>, major_radius: f32, minor_radius: f32) -> f32 {
+    let q = vec2<f32>(length(p.xz) - major_radius, p.y);
+    return length(q) - minor_radius;
+}
+
+fn op_smooth_union(a: f32, b: f32, k: f32) -> f32 {
+    let h = clamp(0.5 + 0.5 * (b - a) / k, 0.0, 1.0);
+    return mix(b, a, h) - k * h * (1.0 - h);
+}
+
+// Repeats space in cells of `period`, centered on the origin
+fn op_repeat(p: vec3<f32>, period: vec3<f32>) -> vec3<f32> {
+    return p - period * round(p / period);
+}
+
+// Repeats space on the XZ plane only, e.g. for things standing on the ground
+fn op_repeat_xz(p: vec3<f32>, period: vec2<f32>) -> vec3<f32> {
+    let xz = p.xz - period * round(p.xz / period);
+    return vec3<f32>(xz.x, p.y, xz.y);
+}
@@ -0,0 +1,69 @@
+#define_import_path shared::reflection_probe
+
+// This should match GpuReflectionProbe in reflection_probes.rs
+struct ReflectionProbe {
+    // The box is centered on the capture position and has no scale
+    world_to_box: mat4x4<f32>,
+    box_to_world: mat4x4<f32>,
+    // XYZ: half size of the box, W: blend distance
+    extents_blend: vec4<f32>,
+    // Index of the probe's cubemap in the cubemap arrays
+    cube: u32,
+    intensity: f32,
+}
+
+// Sorted from the smallest box to the largest, so that nested probes take precedence
+struct ReflectionProbes {
+    count: u32,
+    probes: array<ReflectionProbe>,
+}
+
+// Weight of the probe at a position in its box space. Fades from 1 to 0 over the blend distance
+// towards the faces of the box.
+fn probe_weight(probe: ReflectionProbe, box_position: vec3<f32>) -> f32 {
+    let distance_inside = probe.extents_blend.xyz - abs(box_position);
+    let nearest_face = min(min(distance_inside.x, distance_inside.y), distance_inside.z);
+    return saturate(nearest_face / max(probe.extents_blend.w, 1e-4));
+}
+
+// Finds where a ray from inside the box exits it, and returns the direction from the capture
+// position to that point. This makes the reflections line up with the walls the box follows,
+// instead of appearing infinitely far away.
+fn box_project(probe: ReflectionProbe, box_position: vec3<f32>, world_direction: vec3<f32>) -> vec3<f32> {
+    let direction = (probe.world_to_box * vec4<f32>(world_direction, 0.0)).xyz;
+    let extents = probe.extents_blend.xyz;
+
+    // Distances to the planes of the faces the ray is heading towards
+    let to_positive = (extents - box_position) / direction;
+    let to_negative = (-extents - box_position) / direction;
+    let exits = max(to_positive, to_negative);
+    let distance = min(min(exits.x, exits.y), exits.z);
+
+    let hit = box_position + direction * distance;
+    return (probe.box_to_world * vec4<f32>(hit, 0.0)).xyz;
+}
+
+struct ProbeSample {
+    irradiance: vec3<f32>,
+    prefiltered: vec3<f32>,
+}
+
+fn sample_probe(
+    probe: ReflectionProbe,
+    irradiance_maps: texture_cube_array<f32>,
+    prefiltered_maps: texture_cube_array<f32>,
+    probe_sampler: sampler,
+    box_position: vec3<f32>,
+    normal: vec3<f32>,
+    reflection: vec3<f32>,
+    roughness: f32,
+) -> ProbeSample {
+    let max_lod = f32(textureNumLevels(prefiltered_maps) - 1u);
+    let direction = box_project(probe, box_position, reflection);
+
+    var out: ProbeSample;
+    // Irradiance varies slowly enough that it isn't projected
+    out.irradiance = textureSampleLevel(irradiance_maps, probe_sampler, normal, probe.cube, 0.0).rgb * probe.intensity;
+    out.prefiltered = textureSampleLevel(prefiltered_maps, probe_sampler, direction, probe.cube, roughness * max_lod).rgb * probe.intensity;
+    return out;
+}
@@ -0,0 +1,74 @@
+#define_import_path shared::shadow
+
+// This should match MAX_SHADOW_CASCADES in shadow_pass.rs
+const MAX_SHADOW_CASCADES: u32 = 4u;
+
+// This should match ShadowCascadeData in shadow_pass.rs
+struct ShadowCascade {
+    light_view_proj: mat4x4<f32>,
+    // World space size of a shadow map texel
+    texel_size: f32,
+}
+
+// This should match ShadowUniformData in shadow_pass.rs
+struct ShadowUniform {
+    // Ordered from the nearest and most detailed to the furthest
+    cascades: array<ShadowCascade, MAX_SHADOW_CASCADES>,
+    cascade_count: u32,
+    // Fraction of each cascade at its edges where it fades into the next one
+    blend_fraction: f32,
+    // Non-zero to tint lit surfaces by their cascade
+    visualize_cascades: u32,
+}
+
+// Shadow map UV and depth of a position in a cascade
+fn cascade_coords(cascade: ShadowCascade, world_position: vec3<f32>) -> vec3<f32> {
+    let light_clip = cascade.light_view_proj * vec4<f32>(world_position, 1.0);
+    let light_ndc = light_clip.xyz / light_clip.w;
+    return vec3<f32>(light_ndc.xy * vec2<f32>(0.5, -0.5) + 0.5, light_ndc.z);
+}
+
+// From 0 at the edges of the cascade to 1 at its center, negative outside of it
+fn cascade_edge_distance(coords: vec3<f32>) -> f32 {
+    if coords.z > 1.0 {
+        return -1.0;
+    }
+
+    let to_edge = min(coords.xy, vec2<f32>(1.0) - coords.xy);
+    return min(to_edge.x, to_edge.y) * 2.0;
+}
+
+// The first cascade that contains the position, or cascade_count if none does
+fn select_cascade(shadow: ShadowUniform, world_position: vec3<f32>) -> u32 {
+    // Arrays can only be indexed dynamically through a variable
+    var cascades = shadow.cascades;
+
+    for (var i = 0u; i < shadow.cascade_count; i++) {
+        if cascade_edge_distance(cascade_coords(cascades[i], world_position)) >= 0.0 {
+            return i;
+        }
+    }
+
+    return shadow.cascade_count;
+}
+
+// This should match CASCADE_COLORS in shadow_pass.rs. White outside of the cascades.
+fn cascade_debug_color(cascade: u32) -> vec3<f32> {
+    switch cascade {
+        case 0u: {
+            return vec3<f32>(1.0, 0.3, 0.3);
+        }
+        case 1u: {
+            return vec3<f32>(0.3, 1.0, 0.3);
+        }
+        case 2u: {
+            return vec3<f32>(0.3, 0.5, 1.0);
+        }
+        case 3u: {
+            return vec3<f32>(1.0, 1.0, 0.3);
+        }
+        default: {
+            return vec3<f32>(1.0);
+        }
+    }
+}
@@ -0,0 +1,15 @@
+#define_import_path shared::skinning
+
+// This should match Drawable::NO_JOINTS
+const NO_JOINTS: u32 = 0xffffffffu;
+
+// Weighted sum of the four joint matrices affecting a vertex
+fn blend_joint_matrices(
+    joint_0: mat4x4<f32>,
+    joint_1: mat4x4<f32>,
+    joint_2: mat4x4<f32>,
+    joint_3: mat4x4<f32>,
+    weights: vec4<f32>,
+) -> mat4x4<f32> {
+    return joint_0 * weights.x + joint_1 * weights.y + joint_2 * weights.z + joint_3 * weights.w;
+}
@@ -0,0 +1,16 @@
+#define_import_path shared::transform
+
+// Parent of root objects. This should match TransformNode::NO_PARENT.
+const NO_PARENT: u32 = 0xffffffffu;
+
+// This should match TransformNode
+struct TransformNode {
+    // Starts as the local matrix, and is multiplied by the matrices of more and more ancestors
+    // until it's the world matrix
+    matrix: mat4x4<f32>,
+    // Nearest ancestor whose matrix hasn't been applied yet, or NO_PARENT when done
+    parent: u32,
+    padding0: u32,
+    padding1: u32,
+    padding2: u32,
+}
@@ -0,0 +1,22 @@
+#define_import_path shared::vertex
+
+// Inverse of octahedral_encode in model.rs. Returns a unit vector.
+fn octahedral_decode(encoded: vec2<f32>) -> vec3<f32> {
+    var direction = vec3<f32>(encoded, 1.0 - abs(encoded.x) - abs(encoded.y));
+    // Unfold the lower half
+    let fold = max(-direction.z, 0.0);
+    direction.x += select(fold, -fold, direction.x >= 0.0);
+    direction.y += select(fold, -fold, direction.y >= 0.0);
+    return normalize(direction);
+}
+
+fn octahedral_encode(direction: vec3<f32>) -> vec2<f32> {
+    let projected = direction / (abs(direction.x) + abs(direction.y) + abs(direction.z));
+
+    if projected.z >= 0.0 {
+        return projected.xy;
+    }
+
+    let sign = select(vec2<f32>(-1.0), vec2<f32>(1.0), projected.xy >= vec2<f32>(0.0));
+    return (1.0 - abs(projected.yx)) * sign;
+}
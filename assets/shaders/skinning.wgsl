@@ -0,0 +1,122 @@
+#import shared::skinning::blend_joint_matrices
+#import shared::vertex::{octahedral_decode, octahedral_encode}
+
+// This should match SkinningJob
+struct SkinningJob {
+    source_vertex_offset: u32,
+    destination_vertex_offset: u32,
+    vertex_count: u32,
+    joint_offset: u32,
+}
+
+// Vertices are read as raw words, because vertex attributes can't be bound as a storage buffer.
+// These should match the layout of Vertex, or QuantizedVertex.
+#ifdef QUANTIZED_VERTICES
+const VERTEX_STRIDE: u32 = 9u;
+const POSITION: u32 = 0u;
+const NORMAL: u32 = 3u;
+const TANGENT: u32 = 4u;
+const JOINTS: u32 = 6u;
+const WEIGHTS: u32 = 8u;
+#else
+const VERTEX_STRIDE: u32 = 17u;
+const POSITION: u32 = 0u;
+const NORMAL: u32 = 3u;
+const TANGENT: u32 = 8u;
+const JOINTS: u32 = 11u;
+const WEIGHTS: u32 = 13u;
+#endif
+
+@group(0) @binding(0)
+var<storage, read> jobs: array<SkinningJob>;
+@group(0) @binding(1)
+var<storage, read_write> vertices: array<u32>;
+
+@group(1) @binding(0)
+var<storage, read> joint_matrices: array<mat4x4<f32>>;
+
+fn read_vec3(offset: u32) -> vec3<f32> {
+    return vec3<f32>(
+        bitcast<f32>(vertices[offset]),
+        bitcast<f32>(vertices[offset + 1u]),
+        bitcast<f32>(vertices[offset + 2u]),
+    );
+}
+
+fn write_vec3(offset: u32, value: vec3<f32>) {
+    vertices[offset] = bitcast<u32>(value.x);
+    vertices[offset + 1u] = bitcast<u32>(value.y);
+    vertices[offset + 2u] = bitcast<u32>(value.z);
+}
+
+fn read_direction(offset: u32) -> vec3<f32> {
+#ifdef QUANTIZED_VERTICES
+    return octahedral_decode(unpack2x16snorm(vertices[offset]));
+#else
+    return read_vec3(offset);
+#endif
+}
+
+fn write_direction(offset: u32, value: vec3<f32>) {
+#ifdef QUANTIZED_VERTICES
+    vertices[offset] = pack2x16snorm(octahedral_encode(value));
+#else
+    write_vec3(offset, value);
+#endif
+}
+
+// One row of workgroups per job, each thread skins one vertex
+@compute @workgroup_size(64)
+fn main(
+    @builtin(global_invocation_id) global_id: vec3<u32>
+) {
+    let job = jobs[global_id.y];
+    let vertex = global_id.x;
+
+    if vertex >= job.vertex_count {
+        return;
+    }
+
+    let source = (job.source_vertex_offset + vertex) * VERTEX_STRIDE;
+    let destination = (job.destination_vertex_offset + vertex) * VERTEX_STRIDE;
+
+    // UVs, joints and weights are copied as is
+    for (var i = 0u; i < VERTEX_STRIDE; i++) {
+        vertices[destination + i] = vertices[source + i];
+    }
+
+    // Joint indices are packed as u16s
+    let packed_joints = vec2<u32>(vertices[source + JOINTS], vertices[source + JOINTS + 1u]);
+    let joints = vec4<u32>(
+        packed_joints.x & 0xffffu,
+        packed_joints.x >> 16u,
+        packed_joints.y & 0xffffu,
+        packed_joints.y >> 16u,
+    );
+#ifdef QUANTIZED_VERTICES
+    let weights = unpack4x8unorm(vertices[source + WEIGHTS]);
+#else
+    let weights = vec4<f32>(
+        bitcast<f32>(vertices[source + WEIGHTS]),
+        bitcast<f32>(vertices[source + WEIGHTS + 1u]),
+        bitcast<f32>(vertices[source + WEIGHTS + 2u]),
+        bitcast<f32>(vertices[source + WEIGHTS + 3u]),
+    );
+#endif
+
+    // Joint matrices are relative to the skinned object, so the result is still in object space
+    let skin = blend_joint_matrices(
+        joint_matrices[job.joint_offset + joints.x],
+        joint_matrices[job.joint_offset + joints.y],
+        joint_matrices[job.joint_offset + joints.z],
+        joint_matrices[job.joint_offset + joints.w],
+        weights,
+    );
+    let skin_3x3 = mat3x3<f32>(skin[0].xyz, skin[1].xyz, skin[2].xyz);
+
+    let position = skin * vec4<f32>(read_vec3(source + POSITION), 1.0);
+    write_vec3(destination + POSITION, position.xyz);
+    // Normalized in the vertex shaders
+    write_direction(destination + NORMAL, skin_3x3 * read_direction(source + NORMAL));
+    write_direction(destination + TANGENT, skin_3x3 * read_direction(source + TANGENT));
+}
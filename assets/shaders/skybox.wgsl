@@ -0,0 +1,29 @@
+#import shared::fullscreen::VertexOutput
+#import shared::fullscreen::vs_main as fullscreen_vs_main
+#import shared::camera::CameraUniform
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+@group(1) @binding(0)
+var environment_map: texture_cube<f32>;
+@group(1) @binding(1)
+var environment_sampler: sampler;
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32,
+) -> VertexOutput {
+    return fullscreen_vs_main(vertex_index);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Unproject a point on the far plane; only the direction from the camera matters
+    let clip = vec4<f32>(in.uv * 2.0 - 1.0, 1.0, 1.0);
+    let world = camera.inverse_view_proj * clip;
+    let direction = normalize(world.xyz / world.w - camera.position.xyz);
+
+    let color = textureSampleLevel(environment_map, environment_sampler, direction, 0.0).rgb;
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,110 @@
+#import shared::camera::CameraUniform
+#import shared::fullscreen::VertexOutput
+#import shared::fullscreen::vs_main as fullscreen_vs_main
+
+struct TaaSettings {
+    // How much of the current frame is blended into the history, or 1 to discard the history
+    current_weight: f32,
+}
+
+@group(0) @binding(0)
+var history_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var history_sampler: sampler;
+
+@group(1) @binding(0)
+var<uniform> camera: CameraUniform;
+@group(1) @binding(1)
+var scene_color: texture_2d<f32>;
+@group(1) @binding(2)
+var velocity_texture: texture_2d<f32>;
+@group(1) @binding(3)
+var depth_texture: texture_depth_2d;
+@group(1) @binding(4)
+var<uniform> settings: TaaSettings;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    return fullscreen_vs_main(vertex_index);
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+// Bright HDR samples are weighted down so that single pixel highlights don't flicker
+fn tonemap_weight(color: vec3<f32>) -> f32 {
+    return 1.0 / (1.0 + luminance(color));
+}
+
+// The background has no motion vectors, so its motion comes from the camera alone
+fn background_velocity(uv: vec2<f32>) -> vec2<f32> {
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 1.0, 1.0);
+    let world = camera.inverse_view_proj * ndc;
+    let previous_clip = camera.previous_view_proj * vec4<f32>(world.xyz / world.w, 1.0);
+    let previous_ndc = previous_clip.xy / previous_clip.w;
+    let previous_uv = vec2<f32>(previous_ndc.x * 0.5 + 0.5, 0.5 - previous_ndc.y * 0.5);
+
+    return uv - previous_uv;
+}
+
+@fragment
+fn fs_resolve(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = vec2<i32>(textureDimensions(scene_color));
+    let pixel = vec2<i32>(in.clip_position.xy);
+    let uv = in.clip_position.xy / vec2<f32>(size);
+
+    let current = textureLoad(scene_color, pixel, 0).rgb;
+
+    // Bounds of the 3x3 neighborhood, and the closest surface in it. Using the velocity of the
+    // closest surface keeps the edges of moving objects from trailing.
+    var neighborhood_min = current;
+    var neighborhood_max = current;
+    var closest_depth = 1.0;
+    var closest_pixel = pixel;
+
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let coords = clamp(pixel + vec2<i32>(x, y), vec2<i32>(0), size - 1);
+            let color = textureLoad(scene_color, coords, 0).rgb;
+            neighborhood_min = min(neighborhood_min, color);
+            neighborhood_max = max(neighborhood_max, color);
+
+            let depth = textureLoad(depth_texture, coords, 0);
+            if depth < closest_depth {
+                closest_depth = depth;
+                closest_pixel = coords;
+            }
+        }
+    }
+
+    var velocity: vec2<f32>;
+    if closest_depth >= 1.0 {
+        velocity = background_velocity(uv);
+    } else {
+        velocity = textureLoad(velocity_texture, closest_pixel, 0).xy;
+    }
+
+    let history_uv = uv - velocity;
+    var current_weight = settings.current_weight;
+
+    // Newly revealed parts of the screen have no history
+    if any(history_uv < vec2<f32>(0.0)) || any(history_uv > vec2<f32>(1.0)) {
+        current_weight = 1.0;
+    }
+
+    let history_sample = textureSampleLevel(history_texture, history_sampler, history_uv, 0.0).rgb;
+    let history = clamp(history_sample, neighborhood_min, neighborhood_max);
+
+    let weight_current = current_weight * tonemap_weight(current);
+    let weight_history = (1.0 - current_weight) * tonemap_weight(history);
+    let resolved = (current * weight_current + history * weight_history) / max(weight_current + weight_history, 1e-5);
+
+    return vec4<f32>(resolved, 1.0);
+}
+
+// Writes the resolved frame back to the scene color
+@fragment
+fn fs_copy(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureLoad(history_texture, vec2<i32>(in.clip_position.xy), 0);
+}
@@ -0,0 +1,110 @@
+#import shared::camera::CameraUniform
+#import shared::globals::GlobalUniforms
+
+const TEXT_MODE_WORLD: u32 = 0u;
+const TEXT_MODE_BILLBOARD: u32 = 1u;
+
+struct Glyph {
+    // World position of the text origin, or the screen position from 0 to 1 for screen text
+    origin: vec3<f32>,
+    mode: u32,
+    // One em along the X and Y axes of the text, in world units or fractions of the screen
+    // height
+    right: vec3<f32>,
+    up: vec3<f32>,
+    // XY = bottom left, ZW = top right, in ems
+    rect: vec4<f32>,
+    // XY = top left, ZW = bottom right, in atlas texture coordinates
+    uv_rect: vec4<f32>,
+    // Linear color and opacity
+    color: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+@group(1) @binding(0)
+var<uniform> globals: GlobalUniforms;
+
+@group(2) @binding(0)
+var<storage, read> glyphs: array<Glyph>;
+@group(2) @binding(1)
+var atlas: texture_2d<f32>;
+@group(2) @binding(2)
+var atlas_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32,
+    @builtin(instance_index) instance_index: u32,
+) -> VertexOutput {
+    var out: VertexOutput;
+    let glyph = glyphs[instance_index];
+
+    // Two triangles per glyph, without an index buffer
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 1.0),
+    );
+    let corner = corners[vertex_index];
+    let position = mix(glyph.rect.xy, glyph.rect.zw, corner);
+
+#ifdef SCREEN_SPACE
+    let aspect = globals.resolution.x / globals.resolution.y;
+    let origin = vec2<f32>(glyph.origin.x * 2.0 - 1.0, 1.0 - glyph.origin.y * 2.0);
+    // Sizes are fractions of the screen height, which spans two units of clip space
+    let offset = (glyph.right.xy * position.x + glyph.up.xy * position.y) * 2.0;
+    out.clip_position = vec4<f32>(origin + vec2<f32>(offset.x / aspect, offset.y), 0.0, 1.0);
+#else
+    var right = glyph.right;
+    var up = glyph.up;
+
+    if glyph.mode == TEXT_MODE_BILLBOARD {
+        // Same orientation as particles, facing the camera position
+        let to_camera = normalize(camera.position.xyz - glyph.origin);
+        var world_up = vec3<f32>(0.0, 1.0, 0.0);
+        if abs(to_camera.y) > 0.999 {
+            world_up = vec3<f32>(0.0, 0.0, 1.0);
+        }
+        right = normalize(cross(world_up, to_camera)) * length(glyph.right);
+        up = normalize(cross(to_camera, right)) * length(glyph.up);
+    }
+
+    let world_position = glyph.origin + right * position.x + up * position.y;
+    out.clip_position = camera.view_proj * vec4<f32>(world_position, 1.0);
+#endif
+
+    // The atlas is stored with Y down
+    out.uv = vec2<f32>(
+        mix(glyph.uv_rect.x, glyph.uv_rect.z, corner.x),
+        mix(glyph.uv_rect.w, glyph.uv_rect.y, corner.y),
+    );
+    out.color = glyph.color;
+
+    return out;
+}
+
+// Output with premultiplied alpha
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let distance = textureSample(atlas, atlas_sampler, in.uv).r;
+    // About one pixel of antialiasing at any size
+    let width = max(fwidth(distance) * 0.7, 0.001);
+    let alpha = smoothstep(0.5 - width, 0.5 + width, distance) * in.color.a;
+
+    if alpha <= 0.0 {
+        discard;
+    }
+
+    return vec4<f32>(in.color.rgb * alpha, alpha);
+}
@@ -0,0 +1,52 @@
+#import shared::drawable::InputDrawable
+#import shared::transform::TransformNode
+
+// Slots which don't belong to an object. This should match GpuTransforms::NO_OBJECT.
+const NO_OBJECT: u32 = 0xffffffffu;
+
+@group(0) @binding(0)
+var<storage, read> nodes: array<TransformNode>;
+@group(0) @binding(1)
+var<storage, read> drawable_objects: array<u32>;
+@group(0) @binding(2)
+var<storage, read_write> drawables: array<InputDrawable>;
+
+// Normals only use the upper 3x3 part, whose inverse transpose is its cofactor matrix divided by
+// the determinant
+fn normal_matrix(model_matrix: mat4x4<f32>) -> mat4x4<f32> {
+    let x = model_matrix[0].xyz;
+    let y = model_matrix[1].xyz;
+    let z = model_matrix[2].xyz;
+
+    let inverse_determinant = 1.0 / dot(x, cross(y, z));
+
+    return mat4x4<f32>(
+        vec4<f32>(cross(y, z) * inverse_determinant, 0.0),
+        vec4<f32>(cross(z, x) * inverse_determinant, 0.0),
+        vec4<f32>(cross(x, y) * inverse_determinant, 0.0),
+        vec4<f32>(0.0, 0.0, 0.0, 1.0),
+    );
+}
+
+// Runs once per frame, so the current matrix becomes the previous one
+@compute @workgroup_size(64)
+fn main(
+    @builtin(global_invocation_id) global_id: vec3<u32>
+) {
+    let slot = global_id.x;
+
+    if slot >= arrayLength(&drawable_objects) {
+        return;
+    }
+
+    let object = drawable_objects[slot];
+
+    if object == NO_OBJECT {
+        return;
+    }
+
+    let model_matrix = nodes[object].matrix;
+    drawables[slot].previous_model_matrix = drawables[slot].model_matrix;
+    drawables[slot].model_matrix = model_matrix;
+    drawables[slot].inverse_transpose_model_matrix = normal_matrix(model_matrix);
+}
@@ -0,0 +1,30 @@
+#import shared::transform::{TransformNode, NO_PARENT}
+
+@group(0) @binding(0)
+var<storage, read> input_nodes: array<TransformNode>;
+@group(0) @binding(1)
+var<storage, read_write> output_nodes: array<TransformNode>;
+
+// Pointer jumping: each pass applies the matrix of the nearest unapplied ancestor, which already
+// includes as many levels as this node, and skips to that ancestor's parent. A hierarchy with N
+// levels is resolved in ceil(log2(N)) passes.
+@compute @workgroup_size(64)
+fn main(
+    @builtin(global_invocation_id) global_id: vec3<u32>
+) {
+    let index = global_id.x;
+
+    if index >= arrayLength(&input_nodes) {
+        return;
+    }
+
+    var node = input_nodes[index];
+
+    if node.parent != NO_PARENT {
+        let parent = input_nodes[node.parent];
+        node.matrix = parent.matrix * node.matrix;
+        node.parent = parent.parent;
+    }
+
+    output_nodes[index] = node;
+}
@@ -0,0 +1,217 @@
+#import shared::camera::CameraUniform
+#import shared::drawable::VisibleDrawable
+#import shared::vertex::octahedral_decode
+#import shared::material_info::{
+    MaterialInfo, ALPHA_MODE_MASK, material_uv, material_sampler, decode_normal, audit_highlight, SAMPLER_BASE_COLOR,
+    SAMPLER_NORMAL, SAMPLER_AO_ROUGHNESS_METALLIC, SAMPLER_EMISSIVE
+}
+#import shared::pbr::brdf
+#import shared::light::{Lights, sample_light, casts_shadows}
+#import shared::shadow::ShadowUniform
+#import shared::lighting::{sample_shadow, ambient_light}
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+@group(1) @binding(0)
+var<storage, read> drawables: array<VisibleDrawable>;
+
+@group(2) @binding(0)
+var<storage, read> material_info: array<MaterialInfo>;
+@group(2) @binding(1)
+var textures: binding_array<texture_2d<f32>>;
+@group(2) @binding(2)
+var samplers: binding_array<sampler>;
+
+@group(3) @binding(0)
+var<storage, read> lights: Lights;
+
+@group(4) @binding(0)
+var<uniform> shadow: ShadowUniform;
+@group(4) @binding(1)
+var shadow_map: texture_depth_2d_array;
+@group(4) @binding(2)
+var shadow_sampler: sampler_comparison;
+
+@group(5) @binding(0)
+var environment_map: texture_cube<f32>;
+@group(5) @binding(1)
+var environment_sampler: sampler;
+@group(5) @binding(2)
+var irradiance_map: texture_cube<f32>;
+@group(5) @binding(3)
+var prefiltered_map: texture_cube<f32>;
+@group(5) @binding(4)
+var brdf_lut: texture_2d<f32>;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+#ifdef QUANTIZED_VERTICES
+    // Octahedral encoded
+    @location(1) normal: vec2<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(3) tangent: vec2<f32>,
+#else
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(3) tangent: vec3<f32>,
+#endif
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) normal: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) @interpolate(flat) instance_index: u32,
+    @location(3) tangent: vec3<f32>,
+    @location(4) world_position: vec3<f32>,
+}
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+    @builtin(instance_index) instance_index: u32,
+) -> VertexOutput {
+    var out: VertexOutput;
+
+    let drawable = drawables[instance_index];
+    let world_position = drawable.model_matrix * vec4<f32>(model.position, 1.0);
+
+    out.clip_position = camera.view_proj * world_position;
+    let model_matrix = mat3x3<f32>(
+        drawable.model_matrix[0].xyz,
+        drawable.model_matrix[1].xyz,
+        drawable.model_matrix[2].xyz
+    );
+    let normal_matrix = mat3x3<f32>(
+        drawable.inverse_transpose_model_matrix[0].xyz,
+        drawable.inverse_transpose_model_matrix[1].xyz,
+        drawable.inverse_transpose_model_matrix[2].xyz
+    );
+#ifdef QUANTIZED_VERTICES
+    let normal = octahedral_decode(model.normal);
+    let tangent = octahedral_decode(model.tangent);
+#else
+    let normal = model.normal;
+    let tangent = model.tangent;
+#endif
+    out.normal = normalize(normal_matrix * normal);
+    out.tangent = normalize(model_matrix * tangent);
+    out.uv = model.uv;
+    out.instance_index = instance_index;
+    out.world_position = world_position.xyz;
+
+    return out;
+}
+
+// Forward shaded version of the geometry and lighting passes. Returns unpremultiplied color and
+// alpha.
+fn shade(in: VertexOutput) -> vec4<f32> {
+    let drawable = drawables[in.instance_index];
+    let material = material_info[drawable.material_id];
+    let uv = material_uv(material, in.uv);
+
+    let base_texture_sample = textureSampleGrad(textures[material.base_color], samplers[material_sampler(material, SAMPLER_BASE_COLOR)], uv.uv, uv.ddx, uv.ddy);
+    let base_color = base_texture_sample.rgb * drawable.tint;
+    let alpha = base_texture_sample.a;
+
+    let normal_texture_sample = textureSampleGrad(textures[material.normal], samplers[material_sampler(material, SAMPLER_NORMAL)], uv.uv, uv.ddx, uv.ddy);
+    let tangent_space_normal = decode_normal(material, normal_texture_sample);
+
+    let geometry_normal = normalize(in.normal);
+    let tangent = normalize(in.tangent - geometry_normal * dot(geometry_normal, in.tangent));
+    let bitangent = cross(geometry_normal, tangent);
+    let tbn = mat3x3<f32>(tangent, bitangent, geometry_normal);
+    let normal = normalize(tbn * tangent_space_normal);
+
+    let ao_roughness_metallic_sample = textureSampleGrad(textures[material.ao_roughness_metallic], samplers[material_sampler(material, SAMPLER_AO_ROUGHNESS_METALLIC)], uv.uv, uv.ddx, uv.ddy);
+    let roughness = ao_roughness_metallic_sample.g;
+    let metallic = ao_roughness_metallic_sample.b;
+
+    let view_direction = normalize(camera.position.xyz - in.world_position);
+
+    var direct = vec3<f32>(0.0);
+
+    for (var i = 0u; i < lights.count; i++) {
+        let light_data = lights.lights[i];
+        let light = sample_light(light_data, in.world_position);
+        var radiance = light.radiance;
+
+        if casts_shadows(light_data) {
+            radiance *= sample_shadow(
+                shadow_map,
+                shadow_sampler,
+                shadow,
+                in.world_position,
+                normal,
+            );
+        }
+
+        direct += brdf(normal, view_direction, light.direction, base_color, metallic, roughness) * radiance;
+    }
+
+    let ambient = ambient_light(
+        irradiance_map,
+        prefiltered_map,
+        brdf_lut,
+        environment_sampler,
+        normal,
+        view_direction,
+        base_color,
+        metallic,
+        roughness,
+    );
+
+    let emissive_sample = textureSampleGrad(textures[material.emissive], samplers[material_sampler(material, SAMPLER_EMISSIVE)], uv.uv, uv.ddx, uv.ddy);
+    let emission = emissive_sample.rgb * material.emissive_factor.rgb * drawable.emission;
+
+    let color = direct + ambient + emission;
+    let highlight = audit_highlight(material, in.clip_position.xy);
+    return mix(vec4<f32>(color, alpha), vec4<f32>(4.0, 0.0, 4.0, 1.0), highlight);
+}
+
+// Sorted back to front and alpha blended
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return shade(in);
+}
+
+// Reflection probe captures draw every drawable with this shader instead of going through the
+// deferred passes. Alpha tested drawables are cut out, everything else is opaque.
+@fragment
+fn fs_capture(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = shade(in);
+
+    let material = material_info[drawables[in.instance_index].material_id];
+    if material.alpha_mode == ALPHA_MODE_MASK && color.a < material.alpha_cutoff {
+        discard;
+    }
+
+    return vec4<f32>(color.rgb, 1.0);
+}
+
+struct AccumulationOutput {
+    @location(0) accumulation: vec4<f32>,
+    @location(1) revealage: f32,
+}
+
+// Weighted blended order-independent transparency, see McGuire & Bavoil 2013. Surfaces are
+// weighted by their distance, so that nearer ones dominate the average without sorting.
+@fragment
+fn fs_accumulate(in: VertexOutput) -> AccumulationOutput {
+    let color = shade(in);
+    let alpha = color.a;
+
+    // Equation 7 of the paper, with the distance from the camera as depth
+    let depth = distance(camera.position.xyz, in.world_position);
+    let weight = alpha * clamp(
+        10.0 / (1e-5 + pow(depth / 5.0, 2.0) + pow(depth / 200.0, 6.0)),
+        1e-2,
+        3e3,
+    );
+
+    var out: AccumulationOutput;
+    out.accumulation = vec4<f32>(color.rgb * alpha, alpha) * weight;
+    out.revealage = alpha;
+    return out;
+}
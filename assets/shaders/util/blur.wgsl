@@ -0,0 +1,71 @@
+// This should match BlurParams in blur_pass.rs
+struct BlurParams {
+    // Gaussian: step between two taps, either (1, 0) or (0, 1)
+    direction: vec2<i32>,
+    // Gaussian: number of taps on each side of the center
+    radius: i32,
+    // Kawase: distance of the samples from the center, in input texels
+    offset: f32,
+}
+
+@group(0) @binding(0)
+var input: texture_2d<f32>;
+@group(0) @binding(1)
+var input_sampler: sampler;
+@group(0) @binding(2)
+var output: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(3)
+var<uniform> params: BlurParams;
+
+// One direction of a separable gaussian blur. The input has the same size as the output.
+@compute @workgroup_size(8, 8)
+fn gaussian(
+    @builtin(global_invocation_id) global_id: vec3<u32>
+) {
+    let output_size = textureDimensions(output);
+
+    if global_id.x >= output_size.x || global_id.y >= output_size.y {
+        return;
+    }
+
+    // The kernel is cut off at three standard deviations
+    let sigma = max(f32(params.radius) / 3.0, 0.5);
+    let max_coords = vec2<i32>(textureDimensions(input, 0)) - 1;
+    let pixel = vec2<i32>(global_id.xy);
+
+    var sum = vec4<f32>(0.0);
+    var weight_sum = 0.0;
+
+    for (var i = -params.radius; i <= params.radius; i++) {
+        let weight = exp(-f32(i * i) / (2.0 * sigma * sigma));
+        let coords = clamp(pixel + params.direction * i, vec2<i32>(0), max_coords);
+
+        sum += textureLoad(input, coords, 0) * weight;
+        weight_sum += weight;
+    }
+
+    textureStore(output, global_id.xy, sum / weight_sum);
+}
+
+// A single iteration of a Kawase blur. Each of the four samples lands between texels, so the
+// bilinear filter averages 16 texels in total.
+@compute @workgroup_size(8, 8)
+fn kawase(
+    @builtin(global_invocation_id) global_id: vec3<u32>
+) {
+    let output_size = textureDimensions(output);
+
+    if global_id.x >= output_size.x || global_id.y >= output_size.y {
+        return;
+    }
+
+    let uv = (vec2<f32>(global_id.xy) + 0.5) / vec2<f32>(output_size);
+    let offset = params.offset / vec2<f32>(textureDimensions(input, 0));
+
+    var sum = textureSampleLevel(input, input_sampler, uv + vec2<f32>(-offset.x, -offset.y), 0.0);
+    sum += textureSampleLevel(input, input_sampler, uv + vec2<f32>(offset.x, -offset.y), 0.0);
+    sum += textureSampleLevel(input, input_sampler, uv + vec2<f32>(-offset.x, offset.y), 0.0);
+    sum += textureSampleLevel(input, input_sampler, uv + vec2<f32>(offset.x, offset.y), 0.0);
+
+    textureStore(output, global_id.xy, sum * 0.25);
+}
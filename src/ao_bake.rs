@@ -0,0 +1,561 @@
+use std::{sync::mpsc, time::Instant};
+
+use anyhow::Context;
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec3, Vec4};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    asset_loader::AssetLoader,
+    asset_pipeline::{
+        baked_ao::{
+            is_baked, is_baked_primitive, name_occurrence, BakedAoFile, BakedObjectAo,
+            BakedPrimitiveAo,
+        },
+        mesh_baker::BakedMeshes,
+    },
+    demo::{DemoAssets, DemoOptions, DemoState},
+    math::bounds::AABB,
+    rendering::{
+        adapter::{log_adapter_info, request_adapter},
+        config::RendererOptions,
+        shader_loader::{
+            ComputePipelineId, PipelineCacheBuilder, ShaderDefinition, ShaderErrors, ShaderLoader,
+        },
+        util::bind_group_builder::BindGroupBuilder,
+    },
+    scene_graph::{object3d::ObjectId, scene::Scene},
+};
+
+const AO_SHADER: ShaderDefinition = ShaderDefinition {
+    name: "Ambient occlusion bake compute shader",
+    path: "baking/ambient_occlusion.wgsl",
+    defines: &[],
+};
+
+/// Must match the workgroup size in baking/ambient_occlusion.wgsl
+const WORKGROUP_SIZE: u32 = 64;
+/// Each dispatch is waited for before the next one, so that a long bake doesn't trip the
+/// driver's timeout
+const VERTICES_PER_DISPATCH: u32 = 8192;
+
+const SAMPLE_COUNT: u32 = 256;
+/// Occluders further away than this don't darken a vertex. The scenes are in meters.
+const MAX_DISTANCE: f32 = 2.0;
+const NORMAL_OFFSET: f32 = 0.002;
+
+/// Leaves with more triangles than this are split
+const MAX_LEAF_TRIANGLES: usize = 4;
+
+/// This should match BakeSettings in baking/ambient_occlusion.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct BakeSettings {
+    first_vertex: u32,
+    vertex_count: u32,
+    sample_count: u32,
+    max_distance: f32,
+    normal_offset: f32,
+    _padding: [u32; 3],
+}
+
+/// This should match BvhNode in baking/ambient_occlusion.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct BvhNode {
+    min: Vec3,
+    /// First triangle of a leaf, or the first of the two children of an inner node
+    first: u32,
+    max: Vec3,
+    /// Zero for inner nodes
+    count: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct Triangle {
+    corners: [Vec4; 3],
+}
+
+impl Triangle {
+    fn bounds(&self) -> AABB {
+        let [a, b, c] = self.corners.map(|corner| corner.truncate());
+        AABB::new(a.min(b).min(c), a.max(b).max(c))
+    }
+
+    fn centroid(&self) -> Vec3 {
+        let [a, b, c] = self.corners.map(|corner| corner.truncate());
+        (a + b + c) / 3.0
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct BakeVertex {
+    position: Vec4,
+    normal: Vec4,
+}
+
+/// A primitive whose vertices are baked, and where they are in the vertex list
+struct BakeTarget {
+    object: ObjectId,
+    primitive: usize,
+    first_vertex: usize,
+    vertex_count: usize,
+}
+
+/// The static geometry of a scene in world space
+#[derive(Default)]
+struct BakeScene {
+    triangles: Vec<Triangle>,
+    vertices: Vec<BakeVertex>,
+    targets: Vec<BakeTarget>,
+}
+
+impl BakeScene {
+    /// Vertices come from the baked meshes, so that the results follow their vertex order
+    fn gather(scene: &Scene, baked_meshes: &BakedMeshes) -> Self {
+        let mut bake_scene = Self::default();
+
+        for (id, object) in scene.objects.iter() {
+            if !is_baked(object) {
+                continue;
+            }
+
+            let Some(model) = object
+                .model_id
+                .and_then(|model_id| scene.models.get(model_id))
+            else {
+                continue;
+            };
+
+            let matrix = world_matrix(scene, id);
+
+            for (primitive_index, primitive) in model.model.primitives.iter().enumerate() {
+                if !is_baked_primitive(primitive.alpha_mode) {
+                    continue;
+                }
+
+                let mesh_index = primitive.global_index;
+                let vertex_offset = baked_meshes.meshes[mesh_index].vertex_offset as usize;
+                let indices = baked_meshes.mesh_indices(mesh_index);
+                let vertex_count = baked_meshes.mesh_vertex_count(mesh_index);
+
+                let positions = (0..vertex_count)
+                    .map(|vertex| {
+                        let position = baked_meshes.buffers.position(vertex_offset + vertex);
+                        matrix.transform_point3(position)
+                    })
+                    .collect::<Vec<_>>();
+
+                // Area weighted face normals in world space, which don't depend on the vertex
+                // format and follow mirrored transforms
+                let mut normals = vec![Vec3::ZERO; vertex_count];
+                for triangle in indices.chunks_exact(3) {
+                    let [a, b, c] = [0, 1, 2].map(|corner| positions[triangle[corner] as usize]);
+                    let normal = (b - a).cross(c - a);
+                    for index in triangle {
+                        normals[*index as usize] += normal;
+                    }
+
+                    bake_scene.triangles.push(Triangle {
+                        corners: [a, b, c].map(|corner| corner.extend(1.0)),
+                    });
+                }
+
+                bake_scene.targets.push(BakeTarget {
+                    object: id,
+                    primitive: primitive_index,
+                    first_vertex: bake_scene.vertices.len(),
+                    vertex_count,
+                });
+                bake_scene
+                    .vertices
+                    .extend(
+                        positions
+                            .iter()
+                            .zip(&normals)
+                            .map(|(position, normal)| BakeVertex {
+                                position: position.extend(1.0),
+                                normal: normal.normalize_or(Vec3::Y).extend(0.0),
+                            }),
+                    );
+            }
+        }
+
+        bake_scene
+    }
+
+    /// Groups the bake results by object
+    fn to_file(&self, scene: &Scene, occlusion: &[f32]) -> BakedAoFile {
+        let mut objects: Vec<BakedObjectAo> = Vec::new();
+
+        for target in &self.targets {
+            let values = occlusion[target.first_vertex..target.first_vertex + target.vertex_count]
+                .iter()
+                .map(|value| (value.clamp(0.0, 1.0) * 255.0).round() as u8)
+                .collect();
+            let primitive = BakedPrimitiveAo {
+                primitive: target.primitive,
+                values,
+            };
+
+            let name = scene.objects[target.object].name();
+            let occurrence = name_occurrence(scene, target.object, name);
+            // Targets are gathered object by object
+            match objects.last_mut() {
+                Some(object) if object.name == *name && object.occurrence == occurrence => {
+                    object.primitives.push(primitive)
+                }
+                _ => objects.push(BakedObjectAo {
+                    name: name.to_string(),
+                    occurrence,
+                    primitives: vec![primitive],
+                }),
+            }
+        }
+
+        BakedAoFile { objects }
+    }
+}
+
+/// World transforms aren't resolved until the first frame, so they're computed from the local
+/// ones
+fn world_matrix(scene: &Scene, id: ObjectId) -> Mat4 {
+    let object = &scene.objects[id];
+    let local_matrix = *object.transform.get_local_matrix();
+
+    match object.parent_id {
+        Some(parent_id) => world_matrix(scene, parent_id) * local_matrix,
+        None => local_matrix,
+    }
+}
+
+/// Builds a bounding volume hierarchy by splitting the triangles at the median of their
+/// centroids along the longest axis. Reorders the triangles so that every leaf refers to a
+/// contiguous range.
+fn build_bvh(triangles: &mut [Triangle]) -> Vec<BvhNode> {
+    let mut nodes = vec![BvhNode {
+        min: Vec3::ZERO,
+        first: 0,
+        max: Vec3::ZERO,
+        count: triangles.len() as u32,
+    }];
+    subdivide(&mut nodes, triangles, 0);
+    nodes
+}
+
+fn subdivide(nodes: &mut Vec<BvhNode>, triangles: &mut [Triangle], node_index: usize) {
+    let first = nodes[node_index].first as usize;
+    let count = nodes[node_index].count as usize;
+    let node_triangles = &mut triangles[first..first + count];
+
+    let bounds = node_triangles
+        .iter()
+        .map(Triangle::bounds)
+        .reduce(|a, b| a.union(&b))
+        .unwrap_or(AABB::new(Vec3::ZERO, Vec3::ZERO));
+    nodes[node_index].min = bounds.min;
+    nodes[node_index].max = bounds.max;
+
+    if count <= MAX_LEAF_TRIANGLES {
+        return;
+    }
+
+    let centroid_bounds = node_triangles
+        .iter()
+        .map(|triangle| AABB::new(triangle.centroid(), triangle.centroid()))
+        .reduce(|a, b| a.union(&b))
+        .unwrap();
+    let extents = centroid_bounds.max - centroid_bounds.min;
+    let axis = if extents.x >= extents.y && extents.x >= extents.z {
+        0
+    } else if extents.y >= extents.z {
+        1
+    } else {
+        2
+    };
+
+    let half = count / 2;
+    node_triangles.select_nth_unstable_by(half, |a, b| {
+        a.centroid()[axis].total_cmp(&b.centroid()[axis])
+    });
+
+    let left = nodes.len();
+    for (child_first, child_count) in [(first, half), (first + half, count - half)] {
+        nodes.push(BvhNode {
+            min: Vec3::ZERO,
+            first: child_first as u32,
+            max: Vec3::ZERO,
+            count: child_count as u32,
+        });
+    }
+    nodes[node_index].first = left as u32;
+    nodes[node_index].count = 0;
+
+    subdivide(nodes, triangles, left);
+    subdivide(nodes, triangles, left + 1);
+}
+
+/// The geometry of a scene on the GPU, and where the results are written
+struct BakeBuffers {
+    settings: wgpu::Buffer,
+    nodes: wgpu::Buffer,
+    triangles: wgpu::Buffer,
+    vertices: wgpu::Buffer,
+    occlusion: wgpu::Buffer,
+    readback: wgpu::Buffer,
+}
+
+impl BakeBuffers {
+    fn new(device: &wgpu::Device, nodes: &[BvhNode], bake_scene: &BakeScene) -> Self {
+        // Empty storage buffers can't be bound
+        fn storage_buffer<T: Pod>(
+            device: &wgpu::Device,
+            label: &str,
+            contents: &[T],
+        ) -> wgpu::Buffer {
+            let placeholder = [T::zeroed()];
+            let contents = if contents.is_empty() {
+                &placeholder[..]
+            } else {
+                contents
+            };
+
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents: bytemuck::cast_slice(contents),
+                usage: wgpu::BufferUsages::STORAGE,
+            })
+        }
+
+        let occlusion_size =
+            bake_scene.vertices.len().max(1) as u64 * std::mem::size_of::<f32>() as u64;
+
+        Self {
+            settings: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("AO bake settings buffer"),
+                size: std::mem::size_of::<BakeSettings>() as u64,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            nodes: storage_buffer(device, "AO bake BVH node buffer", nodes),
+            triangles: storage_buffer(device, "AO bake triangle buffer", &bake_scene.triangles),
+            vertices: storage_buffer(device, "AO bake vertex buffer", &bake_scene.vertices),
+            occlusion: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("AO bake occlusion buffer"),
+                size: occlusion_size,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            readback: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("AO bake readback buffer"),
+                size: occlusion_size,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+        }
+    }
+
+    fn bind_group_builder(&self) -> BindGroupBuilder<'_> {
+        BindGroupBuilder::new("AO bake", wgpu::ShaderStages::COMPUTE)
+            .uniform(0, "Bake settings", self.settings.as_entire_binding())
+            .storage_r(1, "BVH nodes", self.nodes.as_entire_binding())
+            .storage_r(2, "Triangles", self.triangles.as_entire_binding())
+            .storage_r(3, "Vertices", self.vertices.as_entire_binding())
+            .storage_rw(4, "Occlusion", self.occlusion.as_entire_binding())
+    }
+}
+
+/// Traces the ambient occlusion of static geometry in compute shaders
+struct AoBaker {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    shader_loader: ShaderLoader,
+    pipeline_id: ComputePipelineId,
+    bind_group_layout: wgpu::BindGroupLayout,
+}
+
+impl AoBaker {
+    async fn new(renderer_options: &RendererOptions) -> anyhow::Result<Self> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+        let adapter =
+            request_adapter(&instance, None, renderer_options.adapter_name.as_deref()).await?;
+        log_adapter_info(&adapter);
+
+        // Big scenes need big buffers
+        let supported = adapter.limits();
+        let (device, queue) = adapter
+            .request_device(&wgpu::DeviceDescriptor {
+                required_features: wgpu::Features::empty(),
+                required_limits: wgpu::Limits {
+                    max_storage_buffer_binding_size: supported.max_storage_buffer_binding_size,
+                    max_buffer_size: supported.max_buffer_size,
+                    ..Default::default()
+                },
+                label: Some("AO bake device"),
+                memory_hints: Default::default(),
+                trace: wgpu::Trace::Off,
+            })
+            .await
+            .context("Failed to create device")?;
+
+        // The layout doesn't depend on the sizes of the buffers
+        let bind_group_layout = BakeBuffers::new(&device, &[], &BakeScene::default())
+            .bind_group_builder()
+            .build_layout(&device);
+
+        let mut cache_builder = PipelineCacheBuilder::new();
+        let pipeline_bind_group_layout = bind_group_layout.clone();
+        let pipeline_id = cache_builder.add_shader(
+            AO_SHADER,
+            Box::new(move |device, shader_module, cache| {
+                let compute_pipeline =
+                    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: Some("AO bake compute pipeline"),
+                        layout: Some(&device.create_pipeline_layout(
+                            &wgpu::PipelineLayoutDescriptor {
+                                label: Some("AO bake pipeline layout"),
+                                bind_group_layouts: &[&pipeline_bind_group_layout],
+                                push_constant_ranges: &[],
+                            },
+                        )),
+                        module: &shader_module,
+                        entry_point: Some("main"),
+                        compilation_options: wgpu::PipelineCompilationOptions::default(),
+                        cache,
+                    });
+
+                Ok(compute_pipeline)
+            }),
+        );
+
+        let mut shader_loader =
+            ShaderLoader::new(device.clone(), cache_builder, ShaderErrors::default(), None);
+        shader_loader.wait_until_ready()?;
+
+        Ok(Self {
+            device,
+            queue,
+            shader_loader,
+            pipeline_id,
+            bind_group_layout,
+        })
+    }
+
+    /// Returns the unoccluded fraction of every vertex of the scene
+    fn bake(&self, bake_scene: &mut BakeScene) -> anyhow::Result<Vec<f32>> {
+        if bake_scene.vertices.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let nodes = build_bvh(&mut bake_scene.triangles);
+        let buffers = BakeBuffers::new(&self.device, &nodes, bake_scene);
+        let bind_group = buffers
+            .bind_group_builder()
+            .build_with_layout(&self.device, &self.bind_group_layout);
+        let pipeline = self.shader_loader.cache.get(self.pipeline_id);
+
+        let vertex_count = bake_scene.vertices.len() as u32;
+        for first_vertex in (0..vertex_count).step_by(VERTICES_PER_DISPATCH as usize) {
+            let batch_size = VERTICES_PER_DISPATCH.min(vertex_count - first_vertex);
+            let settings = BakeSettings {
+                first_vertex,
+                vertex_count: batch_size,
+                sample_count: SAMPLE_COUNT,
+                max_distance: MAX_DISTANCE,
+                normal_offset: NORMAL_OFFSET,
+                _padding: [0; 3],
+            };
+            self.queue
+                .write_buffer(&buffers.settings, 0, bytemuck::bytes_of(&settings));
+
+            let mut encoder = self
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("AO bake encoder"),
+                });
+            {
+                let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("AO bake pass"),
+                    timestamp_writes: None,
+                });
+                compute_pass.set_pipeline(pipeline);
+                compute_pass.set_bind_group(0, &bind_group, &[]);
+                compute_pass.dispatch_workgroups(batch_size.div_ceil(WORKGROUP_SIZE), 1, 1);
+            }
+            self.queue.submit(Some(encoder.finish()));
+            self.device.poll(wgpu::PollType::Wait)?;
+
+            log::info!(
+                "Baked {}/{} vertices",
+                first_vertex + batch_size,
+                vertex_count
+            );
+        }
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("AO bake readback encoder"),
+            });
+        encoder.copy_buffer_to_buffer(
+            &buffers.occlusion,
+            0,
+            &buffers.readback,
+            0,
+            buffers.readback.size(),
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = buffers.readback.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::PollType::Wait)?;
+        receiver.recv()??;
+
+        let occlusion = bytemuck::cast_slice::<u8, f32>(&slice.get_mapped_range())
+            [..bake_scene.vertices.len()]
+            .to_vec();
+
+        Ok(occlusion)
+    }
+}
+
+/// Bakes the ambient occlusion of the static objects of every part into assets/baked
+pub async fn run(renderer_options: RendererOptions) -> anyhow::Result<()> {
+    let bake_options = renderer_options.bake_options();
+    let assets = AssetLoader::spawn(DemoAssets::LOADING_STEPS, move |progress| {
+        DemoAssets::load(progress, bake_options)
+    })?
+    .wait()?;
+    let (state, _, baked_meshes) = DemoState::new_offline(assets, DemoOptions::default());
+
+    let baker = AoBaker::new(&renderer_options).await?;
+
+    for part in state.parts.iter() {
+        let start = Instant::now();
+        let mut bake_scene = BakeScene::gather(&part.scene, &baked_meshes);
+        log::info!(
+            "Baking AO for part '{}': {} vertices, {} triangles",
+            part.name,
+            bake_scene.vertices.len(),
+            bake_scene.triangles.len()
+        );
+
+        let occlusion = baker.bake(&mut bake_scene)?;
+        let file = bake_scene.to_file(&part.scene, &occlusion);
+
+        let path = BakedAoFile::path(&part.name);
+        file.save(&path)?;
+        log::info!(
+            "Wrote {} in {:.1} s",
+            path.display(),
+            start.elapsed().as_secs_f32()
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver, TryRecvError},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use anyhow::Context;
+
+/// Shared between the loading thread and the main thread
+pub struct LoadingProgress {
+    total_steps: usize,
+    completed_steps: AtomicUsize,
+    current_step: Mutex<String>,
+}
+
+impl LoadingProgress {
+    fn new(total_steps: usize) -> Self {
+        Self {
+            total_steps,
+            completed_steps: AtomicUsize::new(0),
+            current_step: Mutex::new(String::new()),
+        }
+    }
+
+    /// Runs one step of loading, updating the progress before and after it
+    pub fn step<T>(&self, name: impl Into<String>, f: impl FnOnce() -> T) -> T {
+        *self.current_step.lock().unwrap() = name.into();
+        let result = f();
+        self.completed_steps.fetch_add(1, Ordering::Relaxed);
+        result
+    }
+
+    /// From 0.0 to 1.0
+    pub fn fraction(&self) -> f32 {
+        let completed = self.completed_steps.load(Ordering::Relaxed);
+        (completed as f32 / self.total_steps.max(1) as f32).min(1.0)
+    }
+
+    pub fn current_step(&self) -> String {
+        self.current_step.lock().unwrap().clone()
+    }
+}
+
+/// Runs a loading function on a background thread, so that the main thread can keep
+/// rendering (e.g. a loading screen) in the meantime.
+pub struct AssetLoader<T> {
+    progress: Arc<LoadingProgress>,
+    receiver: Receiver<anyhow::Result<T>>,
+}
+
+impl<T: Send + 'static> AssetLoader<T> {
+    /// `total_steps` should match the number of `LoadingProgress::step` calls made by `load`
+    pub fn spawn(
+        total_steps: usize,
+        load: impl FnOnce(&LoadingProgress) -> anyhow::Result<T> + Send + 'static,
+    ) -> anyhow::Result<Self> {
+        let progress = Arc::new(LoadingProgress::new(total_steps));
+        let (sender, receiver) = mpsc::channel();
+
+        let thread_progress = progress.clone();
+        thread::Builder::new()
+            .name("Asset loader".to_string())
+            .spawn(move || {
+                let result = load(&thread_progress);
+                // The receiver is gone if the app was closed during loading
+                let _ = sender.send(result);
+            })
+            .context("Failed to spawn asset loader thread")?;
+
+        Ok(Self { progress, receiver })
+    }
+
+    pub fn progress(&self) -> &LoadingProgress {
+        &self.progress
+    }
+
+    /// Returns the result once loading has finished
+    pub fn poll(&self) -> Option<anyhow::Result<T>> {
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                Some(Err(anyhow::anyhow!("Asset loader thread panicked")))
+            }
+        }
+    }
+
+    /// Blocks until loading has finished, for when there's nothing to show in the meantime
+    pub fn wait(self) -> anyhow::Result<T> {
+        self.receiver
+            .recv()
+            .unwrap_or_else(|_| Err(anyhow::anyhow!("Asset loader thread panicked")))
+    }
+}
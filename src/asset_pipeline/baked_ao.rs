@@ -0,0 +1,184 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    asset_pipeline::{materials::AlphaMode, mesh_baker::BakedMeshes},
+    rendering::instancing::InstanceType,
+    scene_graph::{
+        object3d::{Object3D, ObjectId},
+        scene::Scene,
+    },
+};
+
+/// Written by `--bake-ao`, one file per demo part
+const BAKED_AO_FOLDER: &str = "assets/baked";
+
+/// Ambient occlusion of one primitive of an object
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BakedPrimitiveAo {
+    /// Index of the primitive in the object's model
+    pub primitive: usize,
+    /// One per vertex in the order of the baked mesh, from 0 for fully occluded to 255 for
+    /// unoccluded
+    pub values: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BakedObjectAo {
+    pub name: String,
+    /// Objects can share a name, so they're told apart by the order they were added to the
+    /// scene in
+    pub occurrence: usize,
+    pub primitives: Vec<BakedPrimitiveAo>,
+}
+
+/// Ambient occlusion baked for the static objects of a part. The values follow the vertex order
+/// of the baked meshes, so the bake has to be redone when the models change.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BakedAoFile {
+    pub objects: Vec<BakedObjectAo>,
+}
+
+impl BakedAoFile {
+    pub fn path(part_name: &str) -> PathBuf {
+        Path::new(BAKED_AO_FOLDER).join(format!("{}.ao.ron", part_name.to_lowercase()))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read baked AO: {}", path.display()))?;
+
+        ron::from_str(&contents)
+            .with_context(|| format!("Failed to parse baked AO: {}", path.display()))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory)
+                .with_context(|| format!("Failed to create directory {}", directory.display()))?;
+        }
+
+        // Not pretty printed, which would put every value on its own line
+        let contents = ron::to_string(self).context("Failed to serialize baked AO")?;
+        std::fs::write(path, contents)
+            .with_context(|| format!("Failed to write baked AO: {}", path.display()))
+    }
+}
+
+/// Whether the bake includes the object, both as a receiver and as an occluder. Skinned
+/// objects move even if they're static.
+pub fn is_baked(object: &Object3D) -> bool {
+    object.enabled
+        && object.instance_type == InstanceType::Static
+        && object.model_id.is_some()
+        && object.skin_id.is_none()
+}
+
+/// Blended primitives are drawn in the transparent pass, which doesn't use baked AO
+pub fn is_baked_primitive(alpha_mode: AlphaMode) -> bool {
+    alpha_mode != AlphaMode::Blend
+}
+
+/// The order of the object among the objects with the same name
+pub fn name_occurrence(scene: &Scene, id: ObjectId, name: &str) -> usize {
+    scene
+        .objects_with_name(name)
+        .iter()
+        .position(|other| *other == id)
+        .unwrap_or(0)
+}
+
+/// Baked ambient occlusion of one mesh of an object
+#[derive(Debug, Clone)]
+pub struct BakedMeshAo {
+    pub object: ObjectId,
+    pub mesh_index: u32,
+    /// One per vertex, 0 to 1
+    pub values: Vec<f32>,
+}
+
+/// Baked ambient occlusion of a part, matched with the objects of its scene
+#[derive(Debug, Clone, Default)]
+pub struct BakedAo {
+    pub meshes: Vec<BakedMeshAo>,
+}
+
+impl BakedAo {
+    /// Loads the baked AO of a part if it has been baked. Primitives whose meshes have changed
+    /// since the bake are skipped.
+    pub fn load(part_name: &str, scene: &Scene, baked_meshes: &BakedMeshes) -> Self {
+        let path = BakedAoFile::path(part_name);
+        if !path.exists() {
+            log::debug!("No baked AO for part '{}'", part_name);
+            return Self::default();
+        }
+
+        match BakedAoFile::load(&path) {
+            Ok(file) => Self::resolve(&file, scene, baked_meshes),
+            Err(e) => {
+                log::warn!("Failed to load baked AO, using none: {:?}", e);
+                Self::default()
+            }
+        }
+    }
+
+    fn resolve(file: &BakedAoFile, scene: &Scene, baked_meshes: &BakedMeshes) -> Self {
+        let mut meshes = Vec::new();
+        let mut stale_count = 0;
+
+        for baked_object in &file.objects {
+            let Some(&id) = scene
+                .objects_with_name(&baked_object.name)
+                .get(baked_object.occurrence)
+            else {
+                stale_count += baked_object.primitives.len();
+                continue;
+            };
+
+            let object = &scene.objects[id];
+            let Some(model) = object
+                .model_id
+                .and_then(|model_id| scene.models.get(model_id))
+            else {
+                stale_count += baked_object.primitives.len();
+                continue;
+            };
+
+            for baked_primitive in &baked_object.primitives {
+                let Some(primitive) = model.model.primitives.get(baked_primitive.primitive) else {
+                    stale_count += 1;
+                    continue;
+                };
+
+                let mesh_index = primitive.global_index;
+                if baked_meshes.mesh_vertex_count(mesh_index) != baked_primitive.values.len() {
+                    stale_count += 1;
+                    continue;
+                }
+
+                meshes.push(BakedMeshAo {
+                    object: id,
+                    mesh_index: mesh_index as u32,
+                    values: baked_primitive
+                        .values
+                        .iter()
+                        .map(|value| *value as f32 / 255.0)
+                        .collect(),
+                });
+            }
+        }
+
+        if stale_count > 0 {
+            log::warn!(
+                "{} baked AO primitives no longer match the scene, run --bake-ao again",
+                stale_count
+            );
+        }
+
+        Self { meshes }
+    }
+}
@@ -0,0 +1,91 @@
+// Distant objects are drawn with simplified copies of their primitives. LODs can be authored in
+// the glTF file (see `scene_graph::scene`), or generated here at load time.
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::{
+    asset_pipeline::simplify::simplify,
+    model::{ModelPrimitive, PrimitiveLod},
+};
+
+/// Used for the first LOD when the glTF file doesn't specify a distance
+pub const DEFAULT_LOD_DISTANCE: f32 = 25.0;
+
+/// LODs which don't remove at least this fraction of the previous triangles are not worth it
+const MIN_TRIANGLE_REDUCTION: f32 = 0.25;
+
+/// LOD settings, read from the extras of glTF meshes (generated LODs) and nodes (authored LODs)
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct LodExtras {
+    /// Number of LODs to generate, each with half the triangles of the previous one. Ignored if
+    /// the mesh has authored LODs.
+    pub generate_lods: Option<u32>,
+    /// Target triangle ratios of the generated LODs, relative to the full mesh, e.g.
+    /// `[0.5, 0.2, 0.05]`. Overrides `generate_lods`.
+    pub lod_ratios: Option<Vec<f32>>,
+    /// Camera distance at which the LOD is used. For generated LODs this is the distance of the
+    /// first LOD, and each following LOD is used at twice the distance of the previous one.
+    pub lod_distance: Option<f32>,
+}
+
+impl LodExtras {
+    /// Unrelated extras are ignored, so this only fails if a known field has the wrong type
+    pub fn from_gltf_extras(extras: &gltf::json::Extras) -> anyhow::Result<Self> {
+        match extras {
+            Some(raw) => serde_json::from_str(raw.get()).context("Invalid LOD extras"),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Triangle ratios of the LODs to generate, empty if the mesh doesn't want any
+    pub fn lod_ratios(&self) -> Vec<f32> {
+        match (&self.lod_ratios, self.generate_lods) {
+            (Some(ratios), _) => ratios.clone(),
+            (None, Some(count)) => (1..=count).map(|i| 0.5f32.powi(i as i32)).collect(),
+            (None, None) => Vec::new(),
+        }
+    }
+}
+
+impl ModelPrimitive {
+    /// Replaces the LODs of this primitive with simplified ones, which have roughly `ratios` of
+    /// its triangles. LODs which can't be simplified much further than the previous one are
+    /// skipped, along with all the LODs after them.
+    pub fn generate_lods(&mut self, ratios: &[f32], first_distance: f32) {
+        self.lods.clear();
+
+        let mut indices = self.indices.clone();
+        let mut distance = first_distance;
+
+        for ratio in ratios {
+            let target_index_count = (self.indices.len() as f32 * ratio) as usize / 3 * 3;
+
+            // Each LOD is simplified from the previous one, which is much cheaper than starting
+            // over from the full mesh
+            let simplified = simplify(&self.vertices, &indices, target_index_count);
+
+            if simplified.is_empty()
+                || simplified.len() as f32 > indices.len() as f32 * (1.0 - MIN_TRIANGLE_REDUCTION)
+            {
+                break;
+            }
+
+            log::debug!(
+                "Generated LOD with {} / {} triangles",
+                simplified.len() / 3,
+                self.indices.len() / 3
+            );
+
+            self.lods.push(PrimitiveLod {
+                vertices: None,
+                indices: simplified.clone(),
+                distance,
+            });
+
+            indices = simplified;
+            distance *= 2.0;
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver},
+    time::Duration,
+};
+
+use anyhow::Context;
+use notify_debouncer_mini::{
+    new_debouncer_opt, notify::*, DebounceEventResult, DebouncedEventKind, Debouncer,
+};
+
+const ASSET_FOLDER: &str = "assets";
+
+pub struct ReloadedGltf {
+    pub file_name: String,
+    pub document: gltf::Document,
+    pub buffers: Vec<gltf::buffer::Data>,
+}
+
+// Watches glTF files for changes and re-imports them in a worker thread.
+pub struct GltfWatcher {
+    receiver: Receiver<ReloadedGltf>,
+    _debouncer: Debouncer<RecommendedWatcher>,
+}
+
+impl GltfWatcher {
+    /// `files` maps the file names used by the material manager and the scene to paths
+    pub fn new(files: &HashMap<String, PathBuf>) -> anyhow::Result<Self> {
+        let files = files
+            .iter()
+            .map(|(file_name, path)| {
+                let path = path
+                    .canonicalize()
+                    .with_context(|| format!("Failed to find glTF file: {}", path.display()))?;
+                Ok((file_name.clone(), path))
+            })
+            .collect::<anyhow::Result<Vec<(String, PathBuf)>>>()?;
+
+        let (sender, receiver) = channel();
+
+        let mut debouncer = new_debouncer_opt(
+            notify_debouncer_mini::Config::default().with_timeout(Duration::from_millis(100)),
+            move |res: DebounceEventResult| match res {
+                Ok(events) => {
+                    for event in events {
+                        if event.kind != DebouncedEventKind::Any {
+                            continue;
+                        }
+
+                        let Some((file_name, path)) =
+                            files.iter().find(|(_, path)| *path == event.path)
+                        else {
+                            continue;
+                        };
+
+                        // Images are not reloaded, materials would need to be re-uploaded too
+                        match gltf::import(path) {
+                            Ok((document, buffers, _)) => {
+                                let _ = sender.send(ReloadedGltf {
+                                    file_name: file_name.clone(),
+                                    document,
+                                    buffers,
+                                });
+                            }
+                            Err(e) => log::error!("Failed to import {}: {:?}", path.display(), e),
+                        }
+                    }
+                }
+                Err(e) => log::error!("Error debouncing glTF changes: {}", e),
+            },
+        )
+        .context("Failed to create glTF file watcher")?;
+
+        let absolute_asset_folder = Path::new(ASSET_FOLDER)
+            .canonicalize()
+            .context("Failed to find asset folder")?;
+
+        debouncer
+            .watcher()
+            .watch(&absolute_asset_folder, RecursiveMode::Recursive)
+            .context("Failed to watch asset folder")?;
+
+        Ok(Self {
+            receiver,
+            _debouncer: debouncer,
+        })
+    }
+
+    pub fn poll(&self) -> impl Iterator<Item = ReloadedGltf> + '_ {
+        self.receiver.try_iter()
+    }
+}
@@ -1,7 +1,485 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use glam::{Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::asset_pipeline::texture_atlas::AtlasPlacement;
+
+/// How the alpha channel of the base color is used, same as in glTF
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AlphaMode {
+    /// Alpha is ignored
+    #[default]
+    Opaque,
+    /// Fully transparent below the cutoff, fully opaque above it
+    Mask,
+    /// Blended with whatever is behind, in a separate forward pass
+    Blend,
+}
+
+impl From<gltf::material::AlphaMode> for AlphaMode {
+    fn from(mode: gltf::material::AlphaMode) -> Self {
+        match mode {
+            gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+            gltf::material::AlphaMode::Mask => AlphaMode::Mask,
+            gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct PbrMaterialData {
     pub name: String,
-    pub base_color: Option<gltf::image::Data>,
-    pub normal: Option<gltf::image::Data>,
-    pub ao_roughness_metallic: Option<gltf::image::Data>,
+    pub base_color: Option<MaterialTexture>,
+    pub normal: Option<MaterialTexture>,
+    pub ao_roughness_metallic: Option<MaterialTexture>,
+    pub emissive: Option<MaterialTexture>,
+    /// Multiplied with the emissive texture. Includes KHR_materials_emissive_strength, so the
+    /// components can be larger than 1.
+    pub emissive_factor: Vec3,
+    pub alpha_mode: AlphaMode,
+    /// Only used with `AlphaMode::Mask`
+    pub alpha_cutoff: f32,
+    pub parameters: MaterialParameters,
+    pub dynamic_textures: DynamicMaterialTextures,
+    /// When not empty, the textures above are ignored and these layers are blended together
+    /// instead, see `TerrainLayer`
+    pub terrain_layers: Vec<TerrainLayer>,
+    /// Set when the textures above have been moved to an atlas page of the `MaterialManager`
+    pub atlas: Option<AtlasPlacement>,
+}
+
+/// Texture layer of a terrain material. Layers are projected from above in world space and
+/// weighted by the height and slope of the surface, so that e.g. grass gives way to rock on
+/// steep slopes and to snow on the peaks.
+#[derive(Debug, Clone)]
+pub struct TerrainLayer {
+    pub base_color: Option<MaterialTexture>,
+    pub normal: Option<MaterialTexture>,
+    pub ao_roughness_metallic: Option<MaterialTexture>,
+    /// World space heights where the layer is fully visible
+    pub height_range: Vec2,
+    /// Slopes where the layer is fully visible, from 0 (flat) to 1 (vertical)
+    pub slope_range: Vec2,
+    /// How far outside the ranges the layer fades out, in world units for height and slope
+    /// units for slope
+    pub height_blend: f32,
+    pub slope_blend: f32,
+    /// Size of one texture repeat in world units
+    pub tile_size: f32,
+}
+
+/// Render targets that are sampled instead of the textures from the glTF file, by the names
+/// they are declared with in the scene file. The emissive factor still applies to a dynamic
+/// emissive texture.
+#[derive(Debug, Default, Clone)]
+pub struct DynamicMaterialTextures {
+    pub base_color: Option<String>,
+    pub emissive: Option<String>,
+}
+
+/// Controls how a material samples its textures
+#[derive(Debug, Clone, Copy)]
+pub struct MaterialParameters {
+    /// Scales the XY components of the tangent space normal
+    pub normal_strength: f32,
+    pub uv_scale: Vec2,
+    pub uv_offset: Vec2,
+    /// For normal maps using the DirectX convention, where green points down
+    pub flip_normal_green: bool,
+}
+
+impl Default for MaterialParameters {
+    fn default() -> Self {
+        Self {
+            normal_strength: 1.0,
+            uv_scale: Vec2::ONE,
+            uv_offset: Vec2::ZERO,
+            flip_normal_green: false,
+        }
+    }
+}
+
+/// Partial `MaterialParameters`, read from glTF extras and material override files. Fields that
+/// aren't set keep their previous value.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct MaterialOverrides {
+    pub normal_strength: Option<f32>,
+    pub uv_scale: Option<Vec2>,
+    pub uv_offset: Option<Vec2>,
+    pub flip_normal_green: Option<bool>,
+    /// Name of a render target to use as the base color texture
+    pub dynamic_base_color: Option<String>,
+    /// Name of a render target to use as the emissive texture
+    pub dynamic_emissive: Option<String>,
+    pub color_spaces: ColorSpaceOverrides,
+}
+
+impl MaterialOverrides {
+    /// Unrelated extras are ignored, so this only fails if a known field has the wrong type
+    pub fn from_gltf_extras(extras: &gltf::json::Extras) -> anyhow::Result<Self> {
+        match extras {
+            Some(raw) => serde_json::from_str(raw.get()).context("Invalid material extras"),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn apply(&self, parameters: &mut MaterialParameters) {
+        if let Some(normal_strength) = self.normal_strength {
+            parameters.normal_strength = normal_strength;
+        }
+
+        if let Some(uv_scale) = self.uv_scale {
+            parameters.uv_scale = uv_scale;
+        }
+
+        if let Some(uv_offset) = self.uv_offset {
+            parameters.uv_offset = uv_offset;
+        }
+
+        if let Some(flip_normal_green) = self.flip_normal_green {
+            parameters.flip_normal_green = flip_normal_green;
+        }
+    }
+
+    pub fn apply_dynamic_textures(&self, textures: &mut DynamicMaterialTextures) {
+        if let Some(base_color) = &self.dynamic_base_color {
+            textures.base_color = Some(base_color.clone());
+        }
+
+        if let Some(emissive) = &self.dynamic_emissive {
+            textures.emissive = Some(emissive.clone());
+        }
+    }
+}
+
+/// Material that isn't part of a glTF file, declared in a scene file with paths to its textures
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaterialDescription {
+    pub base_color: Option<PathBuf>,
+    pub normal: Option<PathBuf>,
+    pub ao_roughness_metallic: Option<PathBuf>,
+    pub emissive: Option<PathBuf>,
+    pub emissive_factor: Vec3,
+    pub normal_strength: Option<f32>,
+    pub uv_scale: Option<Vec2>,
+    pub uv_offset: Option<Vec2>,
+    pub flip_normal_green: Option<bool>,
+    pub color_spaces: ColorSpaceOverrides,
+}
+
+impl MaterialDescription {
+    pub fn load(&self, name: &str) -> anyhow::Result<PbrMaterialData> {
+        let load_texture = |path: &Option<PathBuf>, color_space| {
+            path.as_deref()
+                .map(|path| MaterialTexture::load(path, color_space))
+                .transpose()
+        };
+
+        let mut parameters = MaterialParameters::default();
+        MaterialOverrides {
+            normal_strength: self.normal_strength,
+            uv_scale: self.uv_scale,
+            uv_offset: self.uv_offset,
+            flip_normal_green: self.flip_normal_green,
+            ..Default::default()
+        }
+        .apply(&mut parameters);
+
+        let mut material = PbrMaterialData {
+            name: name.to_string(),
+            base_color: load_texture(&self.base_color, ColorSpace::Srgb)?,
+            normal: load_texture(&self.normal, ColorSpace::Linear)?,
+            ao_roughness_metallic: load_texture(&self.ao_roughness_metallic, ColorSpace::Linear)?,
+            emissive: load_texture(&self.emissive, ColorSpace::Srgb)?,
+            emissive_factor: self.emissive_factor,
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+            parameters,
+            dynamic_textures: DynamicMaterialTextures::default(),
+            terrain_layers: Vec::new(),
+            atlas: None,
+        };
+
+        self.color_spaces.apply(&mut material);
+        Ok(material)
+    }
+}
+
+/// Material overrides by material name
+pub type MaterialOverrideFile = HashMap<String, MaterialOverrides>;
+
+/// Loads the overrides for a glTF file from a RON file next to it, e.g. `model.materials.ron`
+/// for `model.gltf`. Returns an empty set if there is no such file.
+pub fn load_material_overrides(gltf_path: &Path) -> anyhow::Result<MaterialOverrideFile> {
+    let path = gltf_path.with_extension("materials.ron");
+
+    if !path.exists() {
+        return Ok(MaterialOverrideFile::new());
+    }
+
+    let contents = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read material overrides: {}", path.display()))?;
+
+    ron::from_str(&contents)
+        .with_context(|| format!("Failed to parse material overrides: {}", path.display()))
+}
+
+/// How the values of a texture are encoded. Colors are usually sRGB and data such as normals
+/// and roughness linear, so textures get the color space of what they are used for unless a
+/// material overrides it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+/// Color spaces of the textures of a material, for textures whose contents don't match what
+/// they are used for
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColorSpaceOverrides {
+    pub base_color: Option<ColorSpace>,
+    pub normal: Option<ColorSpace>,
+    pub ao_roughness_metallic: Option<ColorSpace>,
+    pub emissive: Option<ColorSpace>,
+}
+
+impl ColorSpaceOverrides {
+    pub fn apply(&self, material: &mut PbrMaterialData) {
+        let textures = [
+            (self.base_color, &mut material.base_color),
+            (self.normal, &mut material.normal),
+            (
+                self.ao_roughness_metallic,
+                &mut material.ao_roughness_metallic,
+            ),
+            (self.emissive, &mut material.emissive),
+        ];
+
+        for (color_space, texture) in textures {
+            if let (Some(color_space), Some(texture)) = (color_space, texture) {
+                texture.color_space = color_space;
+            }
+        }
+    }
+}
+
+/// How texture coordinates outside of 0..1 are handled, same as in glTF
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextureWrap {
+    #[default]
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextureFilter {
+    Nearest,
+    #[default]
+    Linear,
+}
+
+/// Sampler settings of a texture. The defaults are used for textures without a glTF sampler.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextureSampler {
+    pub wrap_u: TextureWrap,
+    pub wrap_v: TextureWrap,
+    pub mag_filter: TextureFilter,
+    pub min_filter: TextureFilter,
+    pub mipmap_filter: TextureFilter,
+}
+
+impl TextureSampler {
+    pub fn from_gltf(sampler: &gltf::texture::Sampler) -> Self {
+        use gltf::texture::{MagFilter, MinFilter, WrappingMode};
+
+        let wrap = |mode| match mode {
+            WrappingMode::Repeat => TextureWrap::Repeat,
+            WrappingMode::MirroredRepeat => TextureWrap::MirroredRepeat,
+            WrappingMode::ClampToEdge => TextureWrap::ClampToEdge,
+        };
+
+        // Unset filters are up to the renderer, and trilinear looks the best
+        let (min_filter, mipmap_filter) = match sampler.min_filter() {
+            Some(MinFilter::Nearest | MinFilter::NearestMipmapNearest) => {
+                (TextureFilter::Nearest, TextureFilter::Nearest)
+            }
+            Some(MinFilter::Linear | MinFilter::LinearMipmapNearest) => {
+                (TextureFilter::Linear, TextureFilter::Nearest)
+            }
+            Some(MinFilter::NearestMipmapLinear) => (TextureFilter::Nearest, TextureFilter::Linear),
+            Some(MinFilter::LinearMipmapLinear) | None => {
+                (TextureFilter::Linear, TextureFilter::Linear)
+            }
+        };
+
+        Self {
+            wrap_u: wrap(sampler.wrap_s()),
+            wrap_v: wrap(sampler.wrap_t()),
+            mag_filter: match sampler.mag_filter() {
+                Some(MagFilter::Nearest) => TextureFilter::Nearest,
+                Some(MagFilter::Linear) | None => TextureFilter::Linear,
+            },
+            min_filter,
+            mipmap_filter,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MaterialTexture {
+    /// Always available, used when the compressed version is missing or unsupported
+    pub rgba: gltf::image::Data,
+    pub compressed: Option<CompressedTextureData>,
+    pub sampler: TextureSampler,
+    pub color_space: ColorSpace,
+}
+
+impl MaterialTexture {
+    /// Loads a standalone image file. Like with glTF textures, a KTX2 file next to the image is
+    /// used as the compressed version.
+    pub fn load(path: &Path, color_space: ColorSpace) -> anyhow::Result<Self> {
+        let image = image::open(path)
+            .with_context(|| format!("Failed to load texture {}", path.display()))?
+            .into_rgba8();
+
+        let rgba = gltf::image::Data {
+            width: image.width(),
+            height: image.height(),
+            format: gltf::image::Format::R8G8B8A8,
+            pixels: image.into_raw(),
+        };
+
+        let ktx2_path = path.with_extension("ktx2");
+        let compressed = if ktx2_path.exists() {
+            CompressedTextureData::load_ktx2(&ktx2_path)
+                .inspect_err(|e| {
+                    log::warn!(
+                        "Failed to load compressed texture {}: {:?}",
+                        ktx2_path.display(),
+                        e
+                    )
+                })
+                .ok()
+        } else {
+            None
+        };
+
+        Ok(Self {
+            rgba,
+            compressed,
+            sampler: TextureSampler::default(),
+            color_space,
+        })
+    }
+
+    /// Why the texture might be sampled in the wrong color space, or None if nothing looks off.
+    /// Only a guess, used to highlight materials in the color space audit.
+    pub fn color_space_issue(&self) -> Option<&'static str> {
+        let declared = self
+            .compressed
+            .as_ref()
+            .and_then(CompressedTextureData::declared_color_space);
+        if declared.is_some_and(|declared| declared != self.color_space) {
+            return Some("the KTX2 format declares a different color space");
+        }
+
+        // 0.5 is 0.735 after sRGB encoding
+        match self.color_space {
+            ColorSpace::Srgb if looks_like_normal_map(&self.rgba, 0.5) => {
+                Some("sRGB texture looks like a normal map")
+            }
+            ColorSpace::Linear if looks_like_normal_map(&self.rgba, 0.735) => {
+                Some("linear texture looks like an sRGB encoded normal map")
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Tangent space normal maps are mostly blue, with red and green averaging to `center`. Checks a
+/// few thousand texels spread over the image.
+fn looks_like_normal_map(data: &gltf::image::Data, center: f32) -> bool {
+    let texels = data.pixels.chunks_exact(4);
+    let step = (texels.len() / 4096).max(1);
+
+    let mut sum = [0.0; 3];
+    let mut count = 0;
+    for texel in texels.step_by(step) {
+        for (channel, sum) in sum.iter_mut().enumerate() {
+            *sum += texel[channel] as f32 / 255.0;
+        }
+        count += 1;
+    }
+
+    if count == 0 {
+        return false;
+    }
+
+    let [red, green, blue] = sum.map(|sum| sum / count as f32);
+    (red - center).abs() < 0.08 && (green - center).abs() < 0.08 && blue > 0.75
+}
+
+/// Block compressed texture with a precomputed mip chain, loaded from a KTX2 file
+#[derive(Debug, Clone)]
+pub struct CompressedTextureData {
+    pub format: ktx2::Format,
+    pub width: u32,
+    pub height: u32,
+    /// Largest mip first
+    pub mips: Vec<Vec<u8>>,
+}
+
+impl CompressedTextureData {
+    pub fn load_ktx2(path: &Path) -> anyhow::Result<Self> {
+        let bytes = std::fs::read(path).context("Failed to read KTX2 file")?;
+        let reader = ktx2::Reader::new(&bytes[..])
+            .map_err(|e| anyhow::anyhow!("Failed to parse KTX2 file: {:?}", e))?;
+        let header = reader.header();
+
+        // Basis Universal and zstd payloads would need transcoding or decompressing first
+        if let Some(scheme) = header.supercompression_scheme {
+            anyhow::bail!("Unsupported KTX2 supercompression scheme: {:?}", scheme);
+        }
+
+        let format = header.format.context("KTX2 file has no format")?;
+
+        if header.pixel_depth > 1 || header.layer_count > 1 || header.face_count > 1 {
+            anyhow::bail!("Only single 2D KTX2 textures are supported");
+        }
+
+        Ok(Self {
+            format,
+            width: header.pixel_width,
+            height: header.pixel_height,
+            mips: reader.levels().map(|level| level.to_vec()).collect(),
+        })
+    }
+
+    /// Color space of the file's format, for the formats that come in both variants
+    pub fn declared_color_space(&self) -> Option<ColorSpace> {
+        use ktx2::Format;
+
+        match self.format {
+            Format::BC1_RGB_SRGB_BLOCK
+            | Format::BC1_RGBA_SRGB_BLOCK
+            | Format::BC2_SRGB_BLOCK
+            | Format::BC3_SRGB_BLOCK
+            | Format::BC7_SRGB_BLOCK => Some(ColorSpace::Srgb),
+            Format::BC1_RGB_UNORM_BLOCK
+            | Format::BC1_RGBA_UNORM_BLOCK
+            | Format::BC2_UNORM_BLOCK
+            | Format::BC3_UNORM_BLOCK
+            | Format::BC7_UNORM_BLOCK => Some(ColorSpace::Linear),
+            _ => None,
+        }
+    }
 }
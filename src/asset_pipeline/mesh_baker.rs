@@ -1,61 +1,381 @@
 use bytemuck::{Pod, Zeroable};
-use glam::Vec4;
+use glam::{Vec3, Vec4};
 
-use crate::model::{Model, Vertex};
+use crate::{
+    asset_pipeline::{
+        meshlets::{build_meshlets, MeshletInfo},
+        optimize_mesh::{
+            optimize_overdraw, optimize_vertex_cache, optimize_vertex_fetch, remap_indices,
+            OptimizationStats,
+        },
+    },
+    math::bounds::{BoundingSphere, AABB},
+    model::{Model, ModelPrimitive, QuantizedVertex, Vertex},
+};
+
+/// Layout of the vertices in the megabuffer. Shaders that read vertices have a variant for each.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum VertexFormat {
+    /// `Vertex`
+    #[default]
+    Full,
+    /// `QuantizedVertex`, about half the size
+    Quantized,
+}
+
+impl VertexFormat {
+    pub const fn stride(self) -> usize {
+        match self {
+            VertexFormat::Full => std::mem::size_of::<Vertex>(),
+            VertexFormat::Quantized => std::mem::size_of::<QuantizedVertex>(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BakeOptions {
+    /// Reorders triangles for the vertex cache and vertices for fetch locality
+    pub optimize: bool,
+    /// Additionally sorts clusters of triangles to reduce overdraw, at a small cost in vertex
+    /// cache efficiency. Only applies to full detail primitives.
+    pub optimize_overdraw: bool,
+    /// Meshes baked again at runtime must use the same format as the first bake
+    pub vertex_format: VertexFormat,
+    /// Splits every mesh into meshlets, for the meshlet rendering path
+    pub meshlets: bool,
+}
+
+impl Default for BakeOptions {
+    fn default() -> Self {
+        Self {
+            optimize: true,
+            optimize_overdraw: true,
+            vertex_format: VertexFormat::Full,
+            meshlets: false,
+        }
+    }
+}
 
 pub struct PrimitiveBuffers {
-    pub vertices: Vec<Vertex>,
+    pub vertex_format: VertexFormat,
+    /// Vertices in `vertex_format`
+    pub vertices: Vec<u8>,
     pub indices: Vec<u32>,
 }
 
+impl PrimitiveBuffers {
+    pub fn vertex_count(&self) -> usize {
+        self.vertices.len() / self.vertex_format.stride()
+    }
+
+    /// Positions are full floats at the start of both vertex formats
+    pub fn position(&self, vertex: usize) -> Vec3 {
+        let offset = vertex * self.vertex_format.stride();
+        let position: [f32; 3] = bytemuck::pod_read_unaligned(&self.vertices[offset..offset + 12]);
+        Vec3::from(position)
+    }
+
+    /// Returns the offset of the first appended vertex
+    fn append_vertices(&mut self, vertices: &[Vertex]) -> u32 {
+        let vertex_offset = self.vertex_count() as u32;
+
+        match self.vertex_format {
+            VertexFormat::Full => self
+                .vertices
+                .extend_from_slice(bytemuck::cast_slice(vertices)),
+            VertexFormat::Quantized => {
+                for vertex in vertices {
+                    let quantized = QuantizedVertex::new(vertex);
+                    self.vertices
+                        .extend_from_slice(bytemuck::bytes_of(&quantized));
+                }
+            }
+        }
+
+        vertex_offset
+    }
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Pod, Zeroable)]
 pub struct MeshInfo {
     pub index_count: u32,
     pub first_index: u32,
     pub vertex_offset: u32,
-    _padding: u32,
+    /// Mesh which is drawn instead of this one beyond `lod_distance`, or `NO_LOD`
+    pub next_lod: u32,
 
-    // w coordinates are unused
-    pub aabb_min: Vec4,
+    pub aabb_min: Vec3,
+    pub lod_distance: f32,
+    // w coordinate is unused
     pub aabb_max: Vec4,
+    /// Center in xyz and radius in w, in the same space as the bounding box
+    pub bounding_sphere: Vec4,
+
+    /// Range in the meshlet buffer, empty if the meshes were baked without meshlets
+    pub first_meshlet: u32,
+    pub meshlet_count: u32,
+    /// Zero for meshes whose meshlet bounds don't match their vertices, which are drawn without
+    /// culling their meshlets
+    pub cull_meshlets: u32,
+    pub _padding: u32,
+}
+
+impl MeshInfo {
+    /// This should match NO_MESH in the shaders
+    pub const NO_LOD: u32 = u32::MAX;
+
+    /// Placeholder for mesh entries which haven't been assigned yet
+    pub const EMPTY: MeshInfo = MeshInfo {
+        index_count: 0,
+        first_index: 0,
+        vertex_offset: 0,
+        next_lod: Self::NO_LOD,
+        aabb_min: Vec3::ZERO,
+        lod_distance: f32::INFINITY,
+        aabb_max: Vec4::ZERO,
+        bounding_sphere: Vec4::ZERO,
+        first_meshlet: 0,
+        meshlet_count: 0,
+        cull_meshlets: 0,
+        _padding: 0,
+    };
+
+    /// Copy of a mesh which uses skinned vertices at `vertex_offset`. Skinned meshes have no LODs.
+    /// Their meshlets are shared with the bind pose, so they're not culled.
+    pub fn skinned(&self, vertex_offset: u32) -> Self {
+        Self {
+            vertex_offset,
+            next_lod: Self::NO_LOD,
+            lod_distance: f32::INFINITY,
+            cull_meshlets: 0,
+            ..*self
+        }
+    }
+
+    /// Appends the indices to the buffers. The vertices must have been appended already.
+    fn new(
+        buffers: &mut PrimitiveBuffers,
+        vertex_offset: u32,
+        indices: &[u32],
+        bounding_box: &AABB,
+        bounding_sphere: &BoundingSphere,
+    ) -> Self {
+        let first_index = buffers.indices.len() as u32;
+        buffers.indices.extend(indices.iter());
+
+        Self {
+            first_index,
+            index_count: indices.len() as u32,
+            vertex_offset,
+            next_lod: Self::NO_LOD,
+            aabb_min: bounding_box.min,
+            lod_distance: f32::INFINITY,
+            aabb_max: bounding_box.max.extend(0.0),
+            bounding_sphere: bounding_sphere.center.extend(bounding_sphere.radius),
+            first_meshlet: 0,
+            meshlet_count: 0,
+            cull_meshlets: 0,
+            _padding: 0,
+        }
+    }
+
+    /// Splits the indices of the mesh into meshlets, which are appended to `meshlets`
+    fn build_meshlets(
+        &mut self,
+        meshlets: &mut Vec<MeshletInfo>,
+        vertices: &[Vertex],
+        indices: &[u32],
+    ) {
+        let mesh_meshlets = build_meshlets(vertices, indices, self.first_index);
+        self.first_meshlet = meshlets.len() as u32;
+        self.meshlet_count = mesh_meshlets.len() as u32;
+        self.cull_meshlets = 1;
+        meshlets.extend(mesh_meshlets);
+    }
 }
 
 pub struct BakedMeshes {
     pub buffers: PrimitiveBuffers,
     pub meshes: Vec<MeshInfo>,
+    /// Empty unless baked with `BakeOptions::meshlets`
+    pub meshlets: Vec<MeshletInfo>,
+}
+
+impl BakedMeshes {
+    pub fn has_meshlets(&self) -> bool {
+        !self.meshlets.is_empty()
+    }
+
+    /// Indices of the mesh, relative to its vertex offset
+    pub fn mesh_indices(&self, mesh_index: usize) -> &[u32] {
+        let mesh = &self.meshes[mesh_index];
+        let first = mesh.first_index as usize;
+        &self.buffers.indices[first..first + mesh.index_count as usize]
+    }
+
+    /// Number of vertices the mesh indexes from its vertex offset onwards
+    pub fn mesh_vertex_count(&self, mesh_index: usize) -> usize {
+        self.mesh_indices(mesh_index)
+            .iter()
+            .max()
+            .map_or(0, |max| *max as usize + 1)
+    }
+}
+
+/// Geometry of a primitive and its LODs, copied so that it can be optimized for baking
+struct PrimitiveGeometry<'a> {
+    primitive: &'a ModelPrimitive,
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+    /// In the same order as `primitive.lods`
+    lods: Vec<(Option<Vec<Vertex>>, Vec<u32>)>,
+    bounding_sphere: BoundingSphere,
 }
 
-pub fn bake_models(models: &[&Model]) -> BakedMeshes {
+impl<'a> PrimitiveGeometry<'a> {
+    fn new(primitive: &'a ModelPrimitive) -> Self {
+        let bounding_sphere = BoundingSphere::from_points(
+            &primitive.bounding_box,
+            primitive.vertices.iter().map(|vertex| vertex.position),
+        );
+
+        Self {
+            primitive,
+            vertices: primitive.vertices.clone(),
+            indices: primitive.indices.clone(),
+            lods: primitive
+                .lods
+                .iter()
+                .map(|lod| (lod.vertices.clone(), lod.indices.clone()))
+                .collect(),
+            bounding_sphere,
+        }
+    }
+
+    fn optimize(&mut self, options: &BakeOptions, stats: &mut OptimizationStats) {
+        let before = self.indices.clone();
+        self.indices = optimize_vertex_cache(&self.indices, self.vertices.len());
+
+        if options.optimize_overdraw {
+            self.indices = optimize_overdraw(&self.vertices, &self.indices);
+        }
+
+        stats.record(self.vertices.len(), &before, &self.indices);
+        let remap = optimize_vertex_fetch(&mut self.vertices, &mut self.indices);
+
+        for (vertices, indices) in &mut self.lods {
+            let before = indices.clone();
+
+            match vertices {
+                // Authored LODs have their own vertices, which are optimized separately
+                Some(vertices) => {
+                    *indices = optimize_vertex_cache(indices, vertices.len());
+                    stats.record(vertices.len(), &before, indices);
+                    optimize_vertex_fetch(vertices, indices);
+                }
+                // Generated LODs follow the new vertex order of the primitive
+                None => {
+                    remap_indices(indices, &remap);
+                    *indices = optimize_vertex_cache(indices, self.vertices.len());
+                    stats.record(self.vertices.len(), &before, indices);
+                }
+            }
+        }
+    }
+}
+
+/// Primitives are baked in the order of their global indices, so that the mesh index of a
+/// primitive is its global index.
+/// LODs are appended after all primitives, and chained from their primitive with `next_lod`.
+pub fn bake_models(models: &[&Model], options: &BakeOptions) -> BakedMeshes {
     let mut buffers = PrimitiveBuffers {
+        vertex_format: options.vertex_format,
         vertices: Vec::new(),
         indices: Vec::new(),
     };
-    let mut primitives = Vec::new();
 
-    for (_, model) in models.iter().enumerate() {
-        for (_, primitive) in model.primitives.iter().enumerate() {
-            let vertex_offset = buffers.vertices.len() as u32;
-            let first_index = buffers.indices.len() as u32;
+    let mut primitives = models
+        .iter()
+        .flat_map(|model| &model.primitives)
+        .map(PrimitiveGeometry::new)
+        .collect::<Vec<_>>();
+    // Models spawned at runtime can be in an earlier scene than their primitive indices
+    primitives.sort_by_key(|geometry| geometry.primitive.global_index);
 
-            buffers.vertices.extend(primitive.vertices.iter());
-            buffers.indices.extend(primitive.indices.iter());
+    if options.optimize {
+        let mut stats = OptimizationStats::default();
+        for primitive in &mut primitives {
+            primitive.optimize(options, &mut stats);
+        }
+        stats.log();
+    }
 
-            let primitive = MeshInfo {
-                first_index,
-                index_count: primitive.indices.len() as u32,
+    let mut meshlets = Vec::new();
+
+    let mut meshes = primitives
+        .iter()
+        .map(|geometry| {
+            let vertex_offset = buffers.append_vertices(&geometry.vertices);
+            let mut mesh = MeshInfo::new(
+                &mut buffers,
                 vertex_offset,
-                _padding: 0,
-                aabb_min: primitive.bounding_box.min.extend(0.0),
-                aabb_max: primitive.bounding_box.max.extend(0.0),
+                &geometry.indices,
+                &geometry.primitive.bounding_box,
+                &geometry.bounding_sphere,
+            );
+
+            if options.meshlets {
+                mesh.build_meshlets(&mut meshlets, &geometry.vertices, &geometry.indices);
+            }
+
+            mesh
+        })
+        .collect::<Vec<_>>();
+
+    for (primitive_mesh, geometry) in primitives.iter().enumerate() {
+        let mut previous = primitive_mesh;
+
+        for ((vertices, indices), lod) in geometry.lods.iter().zip(&geometry.primitive.lods) {
+            // Generated LODs only add indices to the megabuffer
+            let vertex_offset = match vertices {
+                Some(vertices) => buffers.append_vertices(vertices),
+                None => meshes[primitive_mesh].vertex_offset,
             };
 
-            primitives.push(primitive);
+            // LODs are culled with the bounds of the full detail primitive, so that all LODs of
+            // a drawable have the same visibility
+            let mut mesh = MeshInfo::new(
+                &mut buffers,
+                vertex_offset,
+                indices,
+                &geometry.primitive.bounding_box,
+                &geometry.bounding_sphere,
+            );
+
+            if options.meshlets {
+                let lod_vertices = vertices.as_deref().unwrap_or(&geometry.vertices);
+                mesh.build_meshlets(&mut meshlets, lod_vertices, indices);
+            }
+
+            meshes[previous].next_lod = meshes.len() as u32;
+            meshes[previous].lod_distance = lod.distance;
+            previous = meshes.len();
+            meshes.push(mesh);
         }
     }
 
+    if options.meshlets {
+        log::info!(
+            "Built {} meshlets for {} meshes",
+            meshlets.len(),
+            meshes.len()
+        );
+    }
+
     BakedMeshes {
         buffers,
-        meshes: primitives,
+        meshes,
+        meshlets,
     }
 }
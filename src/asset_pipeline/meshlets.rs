@@ -0,0 +1,168 @@
+// Splits baked primitives into meshlets: small clusters of triangles with their own bounds, which
+// are culled individually on the GPU. A meshlet is a contiguous run of the primitive's indices,
+// so the vertex cache order of the baked indices is kept.
+
+use bytemuck::{Pod, Zeroable};
+use glam::{Vec3, Vec4};
+
+use crate::{
+    math::bounds::{BoundingSphere, AABB},
+    model::Vertex,
+};
+
+/// Unique vertices per meshlet, matching the usual mesh shader limits
+pub const MAX_MESHLET_VERTICES: usize = 64;
+/// Triangles per meshlet. The corners of a meshlet must fit in `MESHLET_CORNER_BITS`.
+pub const MAX_MESHLET_TRIANGLES: usize = 124;
+/// Bits of the meshlet index buffer used for the corner within a meshlet, the rest is the
+/// visible meshlet. This should match CORNER_BITS in the shaders.
+pub const MESHLET_CORNER_BITS: u32 = 9;
+
+/// Normal cones wider than this can't be culled from any direction, so they're not built
+const MIN_CONE_DOT: f32 = 0.1;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct MeshletInfo {
+    /// Offset into the index megabuffer. The vertex offset comes from the mesh.
+    pub first_index: u32,
+    pub index_count: u32,
+    pub _padding: [u32; 2],
+    /// Center in xyz and radius in w, in the space of the mesh
+    pub bounding_sphere: Vec4,
+    /// Apex of the normal cone in xyz, w is unused
+    pub cone_apex: Vec4,
+    /// Axis of the normal cone in xyz, and the cutoff in w. The meshlet faces away from the
+    /// viewer when `dot(normalize(apex - eye), axis) >= cutoff`.
+    pub cone_axis_cutoff: Vec4,
+}
+
+impl MeshletInfo {
+    /// Cutoff of meshlets that are never cone culled
+    pub const NO_CONE_CUTOFF: f32 = 2.0;
+}
+
+/// Splits the triangles of `indices` greedily in their current order, so they should have been
+/// optimized for the vertex cache first. `first_index` is the offset of `indices` in the index
+/// megabuffer.
+pub fn build_meshlets(vertices: &[Vertex], indices: &[u32], first_index: u32) -> Vec<MeshletInfo> {
+    let mut meshlets = Vec::new();
+    let mut meshlet_vertices: Vec<u32> = Vec::with_capacity(MAX_MESHLET_VERTICES);
+    let mut start = 0;
+
+    for (triangle, corners) in indices.chunks_exact(3).enumerate() {
+        let new_vertices = corners
+            .iter()
+            .filter(|vertex| !meshlet_vertices.contains(vertex))
+            .count();
+        let triangle_count = triangle - start / 3;
+
+        if meshlet_vertices.len() + new_vertices > MAX_MESHLET_VERTICES
+            || triangle_count == MAX_MESHLET_TRIANGLES
+        {
+            let end = triangle * 3;
+            meshlets.push(meshlet_bounds(
+                vertices,
+                &indices[start..end],
+                first_index + start as u32,
+            ));
+            meshlet_vertices.clear();
+            start = end;
+        }
+
+        for vertex in corners {
+            if !meshlet_vertices.contains(vertex) {
+                meshlet_vertices.push(*vertex);
+            }
+        }
+    }
+
+    if start < indices.len() {
+        meshlets.push(meshlet_bounds(
+            vertices,
+            &indices[start..],
+            first_index + start as u32,
+        ));
+    }
+
+    meshlets
+}
+
+fn meshlet_bounds(vertices: &[Vertex], indices: &[u32], first_index: u32) -> MeshletInfo {
+    let positions = indices
+        .iter()
+        .map(|&index| vertices[index as usize].position);
+    let (min, max) = positions.clone().fold(
+        (Vec3::INFINITY, Vec3::NEG_INFINITY),
+        |(min, max), position| (min.min(position), max.max(position)),
+    );
+    let bounding_sphere = BoundingSphere::from_points(&AABB { min, max }, positions);
+
+    let normals = indices
+        .chunks_exact(3)
+        .filter_map(|corners| {
+            let [a, b, c] = [0, 1, 2].map(|i| &vertices[corners[i] as usize]);
+            let normal = (b.position - a.position)
+                .cross(c.position - a.position)
+                .try_normalize()?;
+            // The winding is flipped by the front face convention, so the vertex normals decide
+            // which way the triangle faces
+            let vertex_normal = a.normal + b.normal + c.normal;
+            let normal = if normal.dot(vertex_normal) < 0.0 {
+                -normal
+            } else {
+                normal
+            };
+
+            Some((a.position, normal))
+        })
+        .collect::<Vec<_>>();
+
+    let (cone_apex, cone_axis_cutoff) = normal_cone(bounding_sphere.center, &normals);
+
+    MeshletInfo {
+        first_index,
+        index_count: indices.len() as u32,
+        _padding: [0; 2],
+        bounding_sphere: bounding_sphere.center.extend(bounding_sphere.radius),
+        cone_apex: cone_apex.extend(0.0),
+        cone_axis_cutoff,
+    }
+}
+
+/// Cone containing the normals of the triangles, with its apex placed so that every triangle is
+/// on the positive side of the apex along its normal. `triangles` has a point and the normal of
+/// each triangle.
+fn normal_cone(center: Vec3, triangles: &[(Vec3, Vec3)]) -> (Vec3, Vec4) {
+    let no_cone = (center, Vec3::Z.extend(MeshletInfo::NO_CONE_CUTOFF));
+
+    let Some(axis) = triangles
+        .iter()
+        .map(|(_, normal)| *normal)
+        .sum::<Vec3>()
+        .try_normalize()
+    else {
+        return no_cone;
+    };
+
+    let min_dot = triangles
+        .iter()
+        .map(|(_, normal)| normal.dot(axis))
+        .fold(1.0, f32::min);
+
+    if min_dot <= MIN_CONE_DOT {
+        return no_cone;
+    }
+
+    // Moves the apex back along the axis until it's behind the plane of every triangle
+    let max_t = triangles
+        .iter()
+        .map(|(point, normal)| (center - *point).dot(*normal) / axis.dot(*normal))
+        .fold(0.0, f32::max);
+    let apex = center - axis * max_t;
+
+    // Sine of the angle between the axis and the widest normal
+    let cutoff = (1.0 - min_dot * min_dot).sqrt();
+
+    (apex, axis.extend(cutoff))
+}
@@ -1,3 +1,10 @@
+pub mod baked_ao;
+pub mod generate_lods;
 pub mod generate_tangents;
+pub mod gltf_watcher;
 pub mod materials;
+pub mod meshlets;
 pub mod mesh_baker;
+pub mod optimize_mesh;
+pub mod simplify;
+pub mod texture_atlas;
@@ -0,0 +1,303 @@
+// Index and vertex reordering for the post-transform vertex cache, overdraw and vertex fetch,
+// in the spirit of meshoptimizer. None of these change the geometry, only the order in which
+// it's stored.
+
+use glam::Vec3;
+
+use crate::model::Vertex;
+
+/// Size of the LRU cache simulated by the vertex cache optimizer. Modern GPUs don't have a
+/// classic post-transform cache, but batch locality still benefits from the same ordering.
+const OPTIMIZER_CACHE_SIZE: usize = 32;
+
+/// Size of the FIFO cache used to measure the results, which matches meshoptimizer's analyzer
+const ANALYZER_CACHE_SIZE: usize = 16;
+
+// Scoring constants from Tom Forsyth's "Linear-Speed Vertex Cache Optimisation"
+const CACHE_DECAY_POWER: f32 = 1.5;
+const LAST_TRIANGLE_SCORE: f32 = 0.75;
+const VALENCE_BOOST_SCALE: f32 = 2.0;
+const VALENCE_BOOST_POWER: f32 = 0.5;
+
+fn vertex_score(cache_position: Option<usize>, remaining_triangles: usize) -> f32 {
+    if remaining_triangles == 0 {
+        return -1.0;
+    }
+
+    let cache_score = match cache_position {
+        // The vertices of the last triangle get a fixed score, so that the next triangle
+        // doesn't depend on the order they were emitted in
+        Some(position) if position < 3 => LAST_TRIANGLE_SCORE,
+        Some(position) => {
+            let scale = 1.0 / (OPTIMIZER_CACHE_SIZE - 3) as f32;
+            (1.0 - (position - 3) as f32 * scale).powf(CACHE_DECAY_POWER)
+        }
+        None => 0.0,
+    };
+
+    // Vertices with few remaining triangles are prioritized, to avoid leaving lone triangles
+    let valence_boost =
+        VALENCE_BOOST_SCALE * (remaining_triangles as f32).powf(-VALENCE_BOOST_POWER);
+
+    cache_score + valence_boost
+}
+
+/// Reorders the triangles so that consecutive triangles share as many vertices as possible
+pub fn optimize_vertex_cache(indices: &[u32], vertex_count: usize) -> Vec<u32> {
+    let triangle_count = indices.len() / 3;
+
+    let mut vertex_triangles = vec![Vec::new(); vertex_count];
+    for (triangle, vertices) in indices.chunks_exact(3).enumerate() {
+        for &vertex in vertices {
+            vertex_triangles[vertex as usize].push(triangle as u32);
+        }
+    }
+
+    let mut cache_positions: Vec<Option<usize>> = vec![None; vertex_count];
+    let mut vertex_scores = vertex_triangles
+        .iter()
+        .map(|triangles| vertex_score(None, triangles.len()))
+        .collect::<Vec<_>>();
+
+    let triangle_score = |vertex_scores: &[f32], triangle: usize| {
+        indices[triangle * 3..triangle * 3 + 3]
+            .iter()
+            .map(|&vertex| vertex_scores[vertex as usize])
+            .sum::<f32>()
+    };
+
+    let mut emitted = vec![false; triangle_count];
+    let mut cache: Vec<u32> = Vec::with_capacity(OPTIMIZER_CACHE_SIZE + 3);
+    let mut output = Vec::with_capacity(indices.len());
+    let mut next_unemitted = 0;
+
+    let mut best = (0..triangle_count).max_by(|a, b| {
+        triangle_score(&vertex_scores, *a).total_cmp(&triangle_score(&vertex_scores, *b))
+    });
+
+    while let Some(triangle) = best {
+        emitted[triangle] = true;
+        let vertices = &indices[triangle * 3..triangle * 3 + 3];
+        output.extend_from_slice(vertices);
+
+        for &vertex in vertices {
+            vertex_triangles[vertex as usize].retain(|&other| other as usize != triangle);
+        }
+
+        // The vertices of the triangle move to the front of the cache
+        let mut new_cache = vertices.to_vec();
+        new_cache.extend(cache.iter().filter(|&vertex| !vertices.contains(vertex)));
+        let evicted = new_cache.split_off(new_cache.len().min(OPTIMIZER_CACHE_SIZE));
+        cache = new_cache;
+
+        for &vertex in &evicted {
+            cache_positions[vertex as usize] = None;
+        }
+
+        for (position, &vertex) in cache.iter().enumerate() {
+            cache_positions[vertex as usize] = Some(position);
+        }
+
+        for &vertex in cache.iter().chain(&evicted) {
+            let vertex = vertex as usize;
+            vertex_scores[vertex] =
+                vertex_score(cache_positions[vertex], vertex_triangles[vertex].len());
+        }
+
+        // Only the triangles of the vertices whose scores changed can become the best one
+        best = None;
+        let mut best_score = f32::MIN;
+        for &vertex in cache.iter().chain(&evicted) {
+            for &candidate in &vertex_triangles[vertex as usize] {
+                let score = triangle_score(&vertex_scores, candidate as usize);
+                if score > best_score {
+                    best = Some(candidate as usize);
+                    best_score = score;
+                }
+            }
+        }
+
+        // The cache ran dry, so continue from any remaining triangle
+        if best.is_none() {
+            while next_unemitted < triangle_count && emitted[next_unemitted] {
+                next_unemitted += 1;
+            }
+
+            best = (next_unemitted < triangle_count).then_some(next_unemitted);
+        }
+    }
+
+    output
+}
+
+/// Sorts clusters of triangles so that the ones facing away from the center of the mesh are
+/// drawn first, and are more likely to occlude the rest. Clusters start where the vertex cache
+/// would be empty anyway, so the vertex cache efficiency is mostly preserved. The indices should
+/// already be optimized for the vertex cache.
+pub fn optimize_overdraw(vertices: &[Vertex], indices: &[u32]) -> Vec<u32> {
+    let mut cache = FifoCache::new(vertices.len());
+    let mut cluster_starts = vec![0];
+
+    for (triangle, triangle_indices) in indices.chunks_exact(3).enumerate() {
+        let misses = triangle_indices
+            .iter()
+            .filter(|&&vertex| cache.access(vertex))
+            .count();
+
+        if misses == 3 && triangle > 0 {
+            cluster_starts.push(triangle);
+        }
+    }
+
+    cluster_starts.push(indices.len() / 3);
+
+    let triangle_positions =
+        |triangle: usize| [0, 1, 2].map(|i| vertices[indices[triangle * 3 + i] as usize].position);
+
+    let triangle_count = indices.len() / 3;
+    let mesh_center = (0..triangle_count)
+        .map(|triangle| triangle_positions(triangle).iter().sum::<Vec3>() / 3.0)
+        .sum::<Vec3>()
+        / triangle_count.max(1) as f32;
+
+    let mut clusters = cluster_starts
+        .windows(2)
+        .map(|range| {
+            let mut center = Vec3::ZERO;
+            let mut normal = Vec3::ZERO;
+            let mut area = 0.0;
+
+            for triangle in range[0]..range[1] {
+                let [a, b, c] = triangle_positions(triangle);
+                let triangle_normal = (b - a).cross(c - a);
+                let triangle_area = triangle_normal.length();
+
+                center += (a + b + c) / 3.0 * triangle_area;
+                normal += triangle_normal;
+                area += triangle_area;
+            }
+
+            let center = if area > 0.0 { center / area } else { center };
+            let sort_key = (center - mesh_center).dot(normal.normalize_or_zero());
+
+            (sort_key, range[0]..range[1])
+        })
+        .collect::<Vec<_>>();
+
+    clusters.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+
+    clusters
+        .into_iter()
+        .flat_map(|(_, triangles)| triangles.start * 3..triangles.end * 3)
+        .map(|index| indices[index])
+        .collect()
+}
+
+/// Reorders the vertices by their first use, and updates the indices to match. Vertices that
+/// aren't used by `indices` are moved to the end. Returns the new index of each old vertex.
+pub fn optimize_vertex_fetch(vertices: &mut Vec<Vertex>, indices: &mut [u32]) -> Vec<u32> {
+    const UNASSIGNED: u32 = u32::MAX;
+
+    let mut remap = vec![UNASSIGNED; vertices.len()];
+    let mut next_vertex = 0;
+
+    for &index in indices.iter() {
+        if remap[index as usize] == UNASSIGNED {
+            remap[index as usize] = next_vertex;
+            next_vertex += 1;
+        }
+    }
+
+    for new_index in remap.iter_mut().filter(|index| **index == UNASSIGNED) {
+        *new_index = next_vertex;
+        next_vertex += 1;
+    }
+
+    let mut reordered = vertices.clone();
+    for (old_index, &new_index) in remap.iter().enumerate() {
+        reordered[new_index as usize] = vertices[old_index];
+    }
+    *vertices = reordered;
+
+    remap_indices(indices, &remap);
+
+    remap
+}
+
+pub fn remap_indices(indices: &mut [u32], remap: &[u32]) {
+    for index in indices {
+        *index = remap[*index as usize];
+    }
+}
+
+/// Simulated FIFO cache, for measuring how well the indices are ordered
+struct FifoCache {
+    timestamps: Vec<Option<usize>>,
+    time: usize,
+}
+
+impl FifoCache {
+    fn new(vertex_count: usize) -> Self {
+        Self {
+            timestamps: vec![None; vertex_count],
+            time: 0,
+        }
+    }
+
+    /// Returns true if the vertex wasn't in the cache
+    fn access(&mut self, vertex: u32) -> bool {
+        let timestamp = &mut self.timestamps[vertex as usize];
+
+        match timestamp {
+            Some(inserted) if self.time - *inserted < ANALYZER_CACHE_SIZE => false,
+            _ => {
+                *timestamp = Some(self.time);
+                self.time += 1;
+                true
+            }
+        }
+    }
+}
+
+/// Vertex cache efficiency of all the baked meshes, before and after optimization
+#[derive(Debug, Default)]
+pub struct OptimizationStats {
+    triangles: usize,
+    vertices: usize,
+    misses_before: usize,
+    misses_after: usize,
+}
+
+impl OptimizationStats {
+    pub fn record(&mut self, vertex_count: usize, before: &[u32], after: &[u32]) {
+        self.triangles += after.len() / 3;
+        self.vertices += vertex_count;
+        self.misses_before += count_cache_misses(before, vertex_count);
+        self.misses_after += count_cache_misses(after, vertex_count);
+    }
+
+    /// ACMR is the average number of vertices transformed per triangle (0.5 - 3.0), ATVR is the
+    /// same per vertex (1.0 - 6.0). Lower is better for both.
+    pub fn log(&self) {
+        if self.triangles == 0 {
+            return;
+        }
+
+        let triangles = self.triangles as f32;
+        let vertices = self.vertices.max(1) as f32;
+
+        log::info!(
+            "Optimized {} triangles: ACMR {:.3} -> {:.3}, ATVR {:.3} -> {:.3}",
+            self.triangles,
+            self.misses_before as f32 / triangles,
+            self.misses_after as f32 / triangles,
+            self.misses_before as f32 / vertices,
+            self.misses_after as f32 / vertices
+        );
+    }
+}
+
+fn count_cache_misses(indices: &[u32], vertex_count: usize) -> usize {
+    let mut cache = FifoCache::new(vertex_count);
+    indices.iter().filter(|&&index| cache.access(index)).count()
+}
@@ -0,0 +1,299 @@
+// Quadric error metric simplification, in the spirit of meshoptimizer's `simplify`. Edges are
+// collapsed onto one of their existing vertices, so the result is a new index buffer for the same
+// vertices. Vertices on texture seams and open borders are never moved, which keeps the UVs and
+// silhouettes intact at the cost of limiting how far some meshes can be simplified.
+
+use std::collections::HashMap;
+
+use glam::Vec3;
+
+use crate::model::Vertex;
+
+/// Symmetric 4x4 matrix, which measures the squared distance of a point to a set of planes
+#[derive(Debug, Default, Clone, Copy)]
+struct Quadric {
+    a2: f32,
+    b2: f32,
+    c2: f32,
+    d2: f32,
+    ab: f32,
+    ac: f32,
+    ad: f32,
+    bc: f32,
+    bd: f32,
+    cd: f32,
+}
+
+impl Quadric {
+    fn from_plane(normal: Vec3, d: f32, weight: f32) -> Self {
+        let Vec3 { x: a, y: b, z: c } = normal;
+
+        Self {
+            a2: a * a * weight,
+            b2: b * b * weight,
+            c2: c * c * weight,
+            d2: d * d * weight,
+            ab: a * b * weight,
+            ac: a * c * weight,
+            ad: a * d * weight,
+            bc: b * c * weight,
+            bd: b * d * weight,
+            cd: c * d * weight,
+        }
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        self.a2 += other.a2;
+        self.b2 += other.b2;
+        self.c2 += other.c2;
+        self.d2 += other.d2;
+        self.ab += other.ab;
+        self.ac += other.ac;
+        self.ad += other.ad;
+        self.bc += other.bc;
+        self.bd += other.bd;
+        self.cd += other.cd;
+    }
+
+    fn error(&self, p: Vec3) -> f32 {
+        let Vec3 { x, y, z } = p;
+
+        let error = self.a2 * x * x
+            + self.b2 * y * y
+            + self.c2 * z * z
+            + self.d2
+            + 2.0
+                * (self.ab * x * y
+                    + self.ac * x * z
+                    + self.ad * x
+                    + self.bc * y * z
+                    + self.bd * y
+                    + self.cd * z);
+
+        error.abs()
+    }
+}
+
+/// Moving `from` onto `to` removes the triangles which share the edge
+struct Collapse {
+    from: u32,
+    to: u32,
+    error: f32,
+}
+
+/// Returns indices for the same vertices with at most `target_index_count` indices, or as close
+/// to it as possible without moving locked vertices or flipping triangles
+pub fn simplify(vertices: &[Vertex], indices: &[u32], target_index_count: usize) -> Vec<u32> {
+    let mut indices = indices.to_vec();
+
+    // Vertices which only differ by their attributes share a position, and are welded for the
+    // purposes of topology and error
+    let position_ids = weld_positions(vertices);
+    let locked = find_locked_vertices(&position_ids, &indices);
+
+    let mut quadrics = vec![Quadric::default(); vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| vertices[triangle[i] as usize].position);
+        let normal = (b - a).cross(c - a);
+        let area = normal.length();
+
+        if area <= f32::EPSILON {
+            continue;
+        }
+
+        let normal = normal / area;
+        let quadric = Quadric::from_plane(normal, -normal.dot(a), area);
+
+        for &index in triangle {
+            quadrics[position_ids[index as usize] as usize].add(&quadric);
+        }
+    }
+
+    let mut remap = (0..vertices.len() as u32).collect::<Vec<_>>();
+
+    while indices.len() > target_index_count {
+        let vertex_triangles = build_vertex_triangles(vertices.len(), &indices);
+
+        let mut collapses = Vec::new();
+        for triangle in indices.chunks_exact(3) {
+            for (from, to) in [
+                (triangle[0], triangle[1]),
+                (triangle[1], triangle[2]),
+                (triangle[2], triangle[0]),
+                (triangle[1], triangle[0]),
+                (triangle[2], triangle[1]),
+                (triangle[0], triangle[2]),
+            ] {
+                if locked[from as usize] {
+                    continue;
+                }
+
+                let mut quadric = quadrics[position_ids[from as usize] as usize];
+                quadric.add(&quadrics[position_ids[to as usize] as usize]);
+
+                collapses.push(Collapse {
+                    from,
+                    to,
+                    error: quadric.error(vertices[to as usize].position),
+                });
+            }
+        }
+
+        collapses.sort_by(|a, b| a.error.total_cmp(&b.error));
+
+        // Each pass collapses independent edges, so that the adjacency stays valid
+        let mut touched = vec![false; vertices.len()];
+        let mut removed_triangles = 0;
+        let triangles_to_remove = (indices.len() - target_index_count).div_ceil(3);
+
+        for collapse in collapses {
+            if removed_triangles >= triangles_to_remove {
+                break;
+            }
+
+            let (from, to) = (collapse.from as usize, collapse.to as usize);
+            if touched[from] || touched[to] {
+                continue;
+            }
+
+            let triangles = &vertex_triangles[from];
+            if flips_triangles(vertices, &indices, triangles, collapse.from, collapse.to) {
+                continue;
+            }
+
+            for &triangle in triangles {
+                let triangle = &indices[triangle as usize * 3..triangle as usize * 3 + 3];
+                if triangle.contains(&collapse.to) {
+                    removed_triangles += 1;
+                }
+
+                for &index in triangle {
+                    touched[index as usize] = true;
+                }
+            }
+
+            let from_quadric = quadrics[position_ids[from] as usize];
+            quadrics[position_ids[to] as usize].add(&from_quadric);
+            remap[from] = collapse.to;
+        }
+
+        if removed_triangles == 0 {
+            break;
+        }
+
+        indices = indices
+            .chunks_exact(3)
+            .map(|triangle| {
+                [
+                    remap[triangle[0] as usize],
+                    remap[triangle[1] as usize],
+                    remap[triangle[2] as usize],
+                ]
+            })
+            .filter(|[a, b, c]| a != b && b != c && a != c)
+            .flatten()
+            .collect();
+    }
+
+    indices
+}
+
+/// Maps each vertex to the first vertex with the same position
+fn weld_positions(vertices: &[Vertex]) -> Vec<u32> {
+    let mut first_by_position = HashMap::new();
+
+    vertices
+        .iter()
+        .enumerate()
+        .map(|(i, vertex)| {
+            let key = vertex.position.to_array().map(f32::to_bits);
+            *first_by_position.entry(key).or_insert(i as u32)
+        })
+        .collect()
+}
+
+/// Vertices on texture seams (sharing their position with another vertex) and on open borders
+/// (edges with only one triangle) can't be moved without tearing the mesh
+fn find_locked_vertices(position_ids: &[u32], indices: &[u32]) -> Vec<bool> {
+    let mut locked = vec![false; position_ids.len()];
+
+    for (i, &position_id) in position_ids.iter().enumerate() {
+        if position_id as usize != i {
+            locked[i] = true;
+            locked[position_id as usize] = true;
+        }
+    }
+
+    let mut edge_triangles: HashMap<(u32, u32), u32> = HashMap::new();
+    for triangle in indices.chunks_exact(3) {
+        for (a, b) in [
+            (triangle[0], triangle[1]),
+            (triangle[1], triangle[2]),
+            (triangle[2], triangle[0]),
+        ] {
+            let (a, b) = (position_ids[a as usize], position_ids[b as usize]);
+            *edge_triangles.entry((a.min(b), a.max(b))).or_default() += 1;
+        }
+    }
+
+    let mut border_positions = vec![false; position_ids.len()];
+    for ((a, b), count) in edge_triangles {
+        if count == 1 {
+            border_positions[a as usize] = true;
+            border_positions[b as usize] = true;
+        }
+    }
+
+    for (i, &position_id) in position_ids.iter().enumerate() {
+        if border_positions[position_id as usize] {
+            locked[i] = true;
+        }
+    }
+
+    locked
+}
+
+fn build_vertex_triangles(vertex_count: usize, indices: &[u32]) -> Vec<Vec<u32>> {
+    let mut vertex_triangles = vec![Vec::new(); vertex_count];
+
+    for (triangle, vertices) in indices.chunks_exact(3).enumerate() {
+        for &vertex in vertices {
+            vertex_triangles[vertex as usize].push(triangle as u32);
+        }
+    }
+
+    vertex_triangles
+}
+
+/// True if moving `from` onto `to` would turn any of the remaining triangles around
+fn flips_triangles(
+    vertices: &[Vertex],
+    indices: &[u32],
+    triangles: &[u32],
+    from: u32,
+    to: u32,
+) -> bool {
+    let target = vertices[to as usize].position;
+
+    triangles.iter().any(|&triangle| {
+        let start = triangle as usize * 3;
+        let triangle = [indices[start], indices[start + 1], indices[start + 2]];
+        if triangle.contains(&to) {
+            return false;
+        }
+
+        let before = triangle.map(|index| vertices[index as usize].position);
+        let after = triangle.map(|index| {
+            if index == from {
+                target
+            } else {
+                vertices[index as usize].position
+            }
+        });
+
+        let normal_before = (before[1] - before[0]).cross(before[2] - before[0]);
+        let normal_after = (after[1] - after[0]).cross(after[2] - after[0]);
+
+        normal_before.dot(normal_after) <= 0.0
+    })
+}
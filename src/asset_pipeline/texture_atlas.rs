@@ -0,0 +1,297 @@
+// Packs the textures of materials that only have small textures into shared atlas pages, so
+// that they take a few slots of the texture binding array instead of one per texture. All
+// textures of a material go to the same rectangle of the page's base color, normal, AO /
+// roughness / metallic and emissive textures, so a single UV rectangle per material is enough.
+
+use glam::Vec4;
+
+use crate::asset_pipeline::materials::{
+    ColorSpace, MaterialTexture, PbrMaterialData, TextureSampler,
+};
+
+/// Materials whose textures are at most this big on both axes are packed
+const MAX_ATLASED_SIZE: u32 = 256;
+/// Width of the pages. The height is cropped to what is used.
+const ATLAS_WIDTH: u32 = 2048;
+const MAX_ATLAS_HEIGHT: u32 = 2048;
+/// Texels wrapped around each texture, so that bilinear filtering and the first few mips don't
+/// bleed in the neighbors
+const GUTTER: u32 = 8;
+
+/// Same as the default textures of `RenderMaterialManager`, for materials that lack a texture
+/// that others on the same page have. In the order of `material_textures`.
+const DEFAULT_TEXELS: [[u8; 4]; 4] = [
+    [255, 0, 255, 255],
+    [127, 127, 255, 255],
+    [0, 255, 0, 255],
+    [255, 255, 255, 255],
+];
+
+/// Color spaces of the page textures, in the order of `material_textures`
+const PAGE_COLOR_SPACES: [ColorSpace; 4] = [
+    ColorSpace::Srgb,
+    ColorSpace::Linear,
+    ColorSpace::Linear,
+    ColorSpace::Srgb,
+];
+
+/// Textures shared by the materials packed onto the same page. Types that none of the materials
+/// have are left empty.
+pub struct AtlasPage {
+    pub base_color: Option<MaterialTexture>,
+    pub normal: Option<MaterialTexture>,
+    pub ao_roughness_metallic: Option<MaterialTexture>,
+    pub emissive: Option<MaterialTexture>,
+}
+
+/// Where the textures of an atlased material are
+#[derive(Debug, Clone, Copy)]
+pub struct AtlasPlacement {
+    pub page: usize,
+    /// XY: offset, ZW: size, in the UV space of the page
+    pub rect: Vec4,
+}
+
+fn material_textures(material: &mut PbrMaterialData) -> [&mut Option<MaterialTexture>; 4] {
+    [
+        &mut material.base_color,
+        &mut material.normal,
+        &mut material.ao_roughness_metallic,
+        &mut material.emissive,
+    ]
+}
+
+/// Size of the textures of a material, or None if it can't be atlased
+fn atlased_size(material: &mut PbrMaterialData) -> Option<(u32, u32)> {
+    let dynamic = &material.dynamic_textures;
+    if !material.terrain_layers.is_empty()
+        || dynamic.base_color.is_some()
+        || dynamic.emissive.is_some()
+        || material.atlas.is_some()
+    {
+        return None;
+    }
+
+    let mut size = None;
+
+    for (kind, texture) in material_textures(material).into_iter().enumerate() {
+        let Some(texture) = texture else {
+            continue;
+        };
+
+        // Compressed textures would lose their compression. The page wraps textures like the
+        // default sampler and has a fixed color space per texture type, so textures that differ
+        // can't be atlased. Suspect textures are kept separate for the color space audit.
+        if texture.compressed.is_some()
+            || texture.sampler != TextureSampler::default()
+            || texture.color_space != PAGE_COLOR_SPACES[kind]
+            || texture.color_space_issue().is_some()
+        {
+            return None;
+        }
+
+        let texture_size = (texture.rgba.width, texture.rgba.height);
+        if texture_size.0 == 0
+            || texture_size.1 == 0
+            || texture_size.0 > MAX_ATLASED_SIZE
+            || texture_size.1 > MAX_ATLASED_SIZE
+            || size.is_some_and(|size| size != texture_size)
+        {
+            return None;
+        }
+
+        size = Some(texture_size);
+    }
+
+    size
+}
+
+/// Places cells left to right on shelves, which are as tall as their first cell. Cells are added
+/// from the tallest to the shortest, so little space is wasted.
+struct ShelfPacker {
+    x: u32,
+    y: u32,
+    shelf_height: u32,
+}
+
+impl ShelfPacker {
+    fn new() -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            shelf_height: 0,
+        }
+    }
+
+    fn place(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        if self.x + width > ATLAS_WIDTH {
+            self.x = 0;
+            self.y += self.shelf_height;
+            self.shelf_height = 0;
+        }
+
+        if self.y + height > MAX_ATLAS_HEIGHT {
+            return None;
+        }
+
+        let position = (self.x, self.y);
+        self.x += width;
+        self.shelf_height = self.shelf_height.max(height);
+        Some(position)
+    }
+
+    fn used_height(&self) -> u32 {
+        self.y + self.shelf_height
+    }
+}
+
+/// Material of a page, and the top left corner of its cell including the gutter
+struct PagedMaterial<'a> {
+    material: &'a mut PbrMaterialData,
+    size: (u32, u32),
+    position: (u32, u32),
+}
+
+/// Moves the textures of small materials to atlas pages and sets their `atlas` placements. The
+/// placements refer to the returned pages, starting from `first_page`. Pages that would only
+/// hold one material aren't created.
+pub fn pack_atlases<'a>(
+    materials: impl IntoIterator<Item = &'a mut PbrMaterialData>,
+    first_page: usize,
+) -> Vec<AtlasPage> {
+    let mut candidates = materials
+        .into_iter()
+        .filter_map(|material| Some((atlased_size(material)?, material)))
+        .collect::<Vec<_>>();
+    candidates.sort_by_key(|((width, height), _)| std::cmp::Reverse((*height, *width)));
+
+    let mut pages: Vec<Vec<PagedMaterial>> = Vec::new();
+    let mut packer = ShelfPacker::new();
+    let mut page_heights = Vec::new();
+
+    for (size, material) in candidates {
+        let cell = (size.0 + GUTTER * 2, size.1 + GUTTER * 2);
+
+        let position = match packer.place(cell.0, cell.1) {
+            Some(position) if !pages.is_empty() => position,
+            _ => {
+                if !pages.is_empty() {
+                    page_heights.push(packer.used_height());
+                }
+                pages.push(Vec::new());
+                packer = ShelfPacker::new();
+                packer
+                    .place(cell.0, cell.1)
+                    .expect("Atlased textures fit on an empty page")
+            }
+        };
+
+        pages.last_mut().unwrap().push(PagedMaterial {
+            material,
+            size,
+            position,
+        });
+    }
+    page_heights.push(packer.used_height());
+
+    let mut atlas_pages = Vec::new();
+
+    for (materials, height) in pages.into_iter().zip(page_heights) {
+        if materials.len() < 2 {
+            continue;
+        }
+
+        atlas_pages.push(build_page(
+            materials,
+            height,
+            first_page + atlas_pages.len(),
+        ));
+    }
+
+    let packed = atlas_pages.len();
+    if packed > 0 {
+        log::info!("Packed small material textures into {} atlas pages", packed);
+    }
+
+    atlas_pages
+}
+
+fn build_page(mut materials: Vec<PagedMaterial>, height: u32, page_index: usize) -> AtlasPage {
+    let mut page_textures: [Option<MaterialTexture>; 4] = Default::default();
+
+    for (kind, page_texture) in page_textures.iter_mut().enumerate() {
+        let used = materials
+            .iter_mut()
+            .any(|paged| material_textures(paged.material)[kind].is_some());
+        if !used {
+            continue;
+        }
+
+        let mut pixels = vec![0; (ATLAS_WIDTH * height * 4) as usize];
+
+        for paged in &mut materials {
+            let texture = material_textures(paged.material)[kind].take();
+            let (width, texture_height) = paged.size;
+
+            for y in 0..texture_height + GUTTER * 2 {
+                for x in 0..width + GUTTER * 2 {
+                    let texel = match &texture {
+                        Some(texture) => {
+                            // The gutter continues the texture as if it was repeated
+                            let source_x = (x as i32 - GUTTER as i32).rem_euclid(width as i32);
+                            let source_y =
+                                (y as i32 - GUTTER as i32).rem_euclid(texture_height as i32);
+                            let offset = ((source_y as u32 * width + source_x as u32) * 4) as usize;
+                            &texture.rgba.pixels[offset..offset + 4]
+                        }
+                        None => &DEFAULT_TEXELS[kind][..],
+                    };
+
+                    let offset = (((paged.position.1 + y) * ATLAS_WIDTH + paged.position.0 + x) * 4)
+                        as usize;
+                    pixels[offset..offset + 4].copy_from_slice(texel);
+                }
+            }
+        }
+
+        *page_texture = Some(MaterialTexture {
+            rgba: gltf::image::Data {
+                pixels,
+                format: gltf::image::Format::R8G8B8A8,
+                width: ATLAS_WIDTH,
+                height,
+            },
+            compressed: None,
+            sampler: TextureSampler::default(),
+            color_space: PAGE_COLOR_SPACES[kind],
+        });
+    }
+
+    let page_size = Vec4::new(
+        ATLAS_WIDTH as f32,
+        height as f32,
+        ATLAS_WIDTH as f32,
+        height as f32,
+    );
+
+    for paged in materials {
+        paged.material.atlas = Some(AtlasPlacement {
+            page: page_index,
+            rect: Vec4::new(
+                (paged.position.0 + GUTTER) as f32,
+                (paged.position.1 + GUTTER) as f32,
+                paged.size.0 as f32,
+                paged.size.1 as f32,
+            ) / page_size,
+        });
+    }
+
+    let [base_color, normal, ao_roughness_metallic, emissive] = page_textures;
+
+    AtlasPage {
+        base_color,
+        normal,
+        ao_roughness_metallic,
+        emissive,
+    }
+}
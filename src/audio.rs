@@ -0,0 +1,104 @@
+use std::{fs::File, io::BufReader, path::Path, time::Duration};
+
+use anyhow::Context;
+use rodio::{Decoder, OutputStream, Sink};
+
+/// Plays the soundtrack. When music is playing, its playback position is the demo clock.
+pub struct AudioPlayer {
+    // The stream must be kept alive for the sink to produce sound
+    _stream: OutputStream,
+    sink: Sink,
+}
+
+impl AudioPlayer {
+    /// Loads an audio file (OGG, MP3, WAV or FLAC). Playback starts paused.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+
+        let (stream, stream_handle) =
+            OutputStream::try_default().context("Failed to open audio output stream")?;
+        let sink = Sink::try_new(&stream_handle).context("Failed to create audio sink")?;
+
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open audio file {}", path.display()))?;
+        let source = Decoder::new(BufReader::new(file))
+            .with_context(|| format!("Failed to decode audio file {}", path.display()))?;
+
+        sink.pause();
+        sink.append(source);
+
+        Ok(Self {
+            _stream: stream,
+            sink,
+        })
+    }
+
+    pub fn play(&self) {
+        self.sink.play();
+    }
+
+    pub fn pause(&self) {
+        self.sink.pause();
+    }
+
+    #[allow(dead_code)]
+    pub fn is_paused(&self) -> bool {
+        self.sink.is_paused()
+    }
+
+    /// Playback position in seconds
+    pub fn position(&self) -> f32 {
+        self.sink.get_pos().as_secs_f32()
+    }
+
+    pub fn seek(&self, time: f32) -> anyhow::Result<()> {
+        self.sink
+            .try_seek(Duration::from_secs_f32(time.max(0.0)))
+            .map_err(|e| anyhow::anyhow!("Failed to seek audio: {}", e))
+    }
+}
+
+/// Converts between seconds and musical time
+#[derive(Debug, Clone, Copy)]
+pub struct BeatClock {
+    pub bpm: f32,
+    /// Time of the first beat in seconds
+    pub offset: f32,
+    pub beats_per_bar: u32,
+}
+
+impl BeatClock {
+    pub fn new(bpm: f32, offset: f32) -> Self {
+        Self {
+            bpm,
+            offset,
+            beats_per_bar: 4,
+        }
+    }
+
+    pub fn seconds_per_beat(&self) -> f32 {
+        60.0 / self.bpm
+    }
+
+    /// Fractional beat number at the given time
+    pub fn beat(&self, time: f32) -> f32 {
+        ((time - self.offset) / self.seconds_per_beat()).max(0.0)
+    }
+
+    /// Position within the current beat in [0, 1)
+    #[allow(dead_code)]
+    pub fn beat_phase(&self, time: f32) -> f32 {
+        self.beat(time).fract()
+    }
+
+    #[allow(dead_code)]
+    pub fn bar(&self, time: f32) -> f32 {
+        self.beat(time) / self.beats_per_bar as f32
+    }
+
+    /// Converts a beat number back to seconds, handy for placing keyframes on beats
+    #[allow(dead_code)]
+    pub fn beat_to_time(&self, beat: f32) -> f32 {
+        self.offset + beat * self.seconds_per_beat()
+    }
+}
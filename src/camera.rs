@@ -1,16 +1,141 @@
 use glam::{Mat4, Vec2, Vec3};
 
+/// Close to what the old hardcoded value of 45 (which was passed as radians) ended up producing
+pub const DEFAULT_FOV_Y_RADIANS: f32 = 60.0 * std::f32::consts::PI / 180.0;
+pub const DEFAULT_NEAR: f32 = 0.1;
+pub const DEFAULT_FAR: f32 = 100.0;
+pub const DEFAULT_FOCUS_DISTANCE: f32 = 5.0;
+
 #[derive(Debug, Clone)]
 pub struct Camera {
     pub eye: Vec3,
     pub target: Vec3,
     pub up: Vec3,
+    /// Vertical field of view
+    pub fov_y_radians: f32,
+    pub near: f32,
+    pub far: f32,
+    /// Distance from the eye to the plane that is in focus
+    pub focus_distance: f32,
+    /// Size of the lens opening for depth of field, as the blur radius of infinitely distant
+    /// points relative to the screen height. Zero keeps everything in focus.
+    pub aperture: f32,
 }
 
 impl Camera {
+    pub fn new(eye: Vec3, target: Vec3) -> Self {
+        Self {
+            eye,
+            target,
+            up: Vec3::Y,
+            fov_y_radians: DEFAULT_FOV_Y_RADIANS,
+            near: DEFAULT_NEAR,
+            far: DEFAULT_FAR,
+            focus_distance: DEFAULT_FOCUS_DISTANCE,
+            aperture: 0.0,
+        }
+    }
+
+    pub fn get_view_matrix(&self) -> Mat4 {
+        Mat4::look_at_lh(self.eye, self.target, self.up)
+    }
+
     pub fn get_vp_matrix(&self, resolution: Vec2) -> Mat4 {
-        let view = Mat4::look_at_lh(self.eye, self.target, self.up);
-        let projection = Mat4::perspective_lh(45.0, resolution.x / resolution.y, 0.1, 100.0);
+        let view = self.get_view_matrix();
+        let projection = Mat4::perspective_lh(
+            self.fov_y_radians,
+            resolution.x / resolution.y,
+            self.near,
+            self.far,
+        );
         projection * view
     }
+
+    pub fn draw_ui(&mut self, ui: &imgui::Ui) {
+        ui.window("Camera")
+            .size([260.0, 150.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                let mut fov_y_degrees = self.fov_y_radians.to_degrees();
+                if ui.slider("FOV (degrees)", 5.0, 150.0, &mut fov_y_degrees) {
+                    self.fov_y_radians = fov_y_degrees.to_radians();
+                }
+
+                ui.slider("Near", 0.001, 10.0, &mut self.near);
+                ui.slider("Far", 1.0, 10_000.0, &mut self.far);
+                self.far = self.far.max(self.near + 0.001);
+
+                ui.slider("Focus distance", 0.1, 100.0, &mut self.focus_distance);
+                ui.slider("Aperture", 0.0, 0.05, &mut self.aperture);
+            });
+    }
+}
+
+/// Index into `Cameras`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CameraId(usize);
+
+/// Named cameras of the demo. Only the active camera is rendered; the timeline cuts between
+/// them, unless a camera has been picked manually from the UI.
+#[derive(Debug, Clone)]
+pub struct Cameras {
+    cameras: Vec<(String, Camera)>,
+    active: CameraId,
+    /// Set when the active camera is picked from the UI, which disables timeline cuts
+    manual_override: bool,
+}
+
+impl Cameras {
+    pub fn new(name: impl Into<String>, camera: Camera) -> Self {
+        Self {
+            cameras: vec![(name.into(), camera)],
+            active: CameraId(0),
+            manual_override: false,
+        }
+    }
+
+    pub fn add(&mut self, name: impl Into<String>, camera: Camera) -> CameraId {
+        self.cameras.push((name.into(), camera));
+        CameraId(self.cameras.len() - 1)
+    }
+
+    pub fn find(&self, name: &str) -> Option<CameraId> {
+        self.cameras
+            .iter()
+            .position(|(camera_name, _)| camera_name == name)
+            .map(CameraId)
+    }
+
+    pub fn get_mut(&mut self, id: CameraId) -> Option<&mut Camera> {
+        self.cameras.get_mut(id.0).map(|(_, camera)| camera)
+    }
+
+    pub fn active(&self) -> &Camera {
+        &self.cameras[self.active.0].1
+    }
+
+    pub fn active_mut(&mut self) -> &mut Camera {
+        &mut self.cameras[self.active.0].1
+    }
+
+    /// Switches the active camera, unless one has been picked manually
+    pub fn cut_to(&mut self, id: CameraId) {
+        if !self.manual_override && id.0 < self.cameras.len() {
+            self.active = id;
+        }
+    }
+
+    pub fn draw_ui(&mut self, ui: &imgui::Ui) {
+        ui.window("Cameras")
+            .size([220.0, 150.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                ui.checkbox("Manual camera selection", &mut self.manual_override);
+
+                let _disabled = ui.begin_disabled(!self.manual_override);
+                for (index, (name, _)) in self.cameras.iter().enumerate() {
+                    ui.radio_button(name, &mut self.active, CameraId(index));
+                }
+            });
+
+        self.active_mut().draw_ui(ui);
+    }
 }
@@ -1,134 +1,637 @@
-use std::time::Instant;
+use std::{collections::HashMap, path::PathBuf};
 
 use anyhow::Context;
 use glam::{Quat, Vec3};
 
 use crate::{
-    camera::Camera,
+    asset_loader::LoadingProgress,
+    asset_pipeline::{
+        baked_ao::BakedAo,
+        gltf_watcher::GltfWatcher,
+        mesh_baker::{bake_models, BakeOptions, BakedMeshes},
+    },
+    audio::{AudioPlayer, BeatClock},
+    camera::{CameraId, Cameras},
+    demo_clock::DemoClock,
+    demo_part::{DemoPart, DemoPartId, DemoParts, PartPasses},
+    demo_script::{DemoScript, DEMO_SCRIPT_PATH},
+    events::{EventBus, EventsUi, Flash, TimelineEvent},
+    fly_camera::FlyCameraController,
+    frame_pacing::{FixedSteps, FixedTimestep},
+    gizmo::TransformGizmo,
     material_manager::MaterialManager,
-    rendering::instancing::InstanceType,
-    scene_graph::{object3d::ObjectId, scene::Scene},
+    math::spline::Spline,
+    orbit_camera::OrbitCameraController,
+    path_follower::{PathFollower, PathTarget, Paths},
+    rendering::{
+        global_uniform::{ShaderParams, SHADER_PARAM_COUNT},
+        post_process::{
+            color_grading::ColorGrading, overrides::PostProcessOverrides, PostProcessParams,
+        },
+    },
+    scene_graph::{
+        object3d::ObjectId,
+        scene::Scene,
+        scene_file::{SceneFile, SpawnedObjects},
+    },
+    timeline::{Channel, Easing, Keyframe, Timeline, Track},
 };
 
+const DEMO_DURATION: f32 = 120.0;
+const SOUNDTRACK_PATH: &str = "assets/music/soundtrack.ogg";
+const SOUNDTRACK_BPM: f32 = 120.0;
+
+const CANS_PART: &str = "Cans";
+const FINALE_PART: &str = "Finale";
+
+/// How a part of the demo is loaded
+struct PartDescription {
+    name: &'static str,
+    scene_file: &'static str,
+    passes: PartPasses,
+    /// Creates the timeline of the part from the spawned scene
+    create_timeline: fn(&SpawnedObjects, &Cameras) -> anyhow::Result<Timeline>,
+    /// Creates the paths of the part, for motion that's easier to author as a curve
+    create_paths: fn(&SpawnedObjects, &Cameras) -> anyhow::Result<Paths>,
+}
+
+/// Meshes are baked in this order. The global timeline decides when each part is shown.
+const PARTS: [PartDescription; 2] = [
+    PartDescription {
+        name: CANS_PART,
+        scene_file: "assets/scenes/demo.ron",
+        passes: PartPasses {
+            ssr: true,
+            volumetric_fog: true,
+            depth_of_field: true,
+            sdf_scene: None,
+        },
+        create_timeline: create_cans_timeline,
+        create_paths: create_cans_paths,
+    },
+    PartDescription {
+        name: FINALE_PART,
+        scene_file: "assets/scenes/finale.ron",
+        // The low sun makes screen space reflections streak over the whole grid
+        passes: PartPasses {
+            ssr: false,
+            volumetric_fog: true,
+            depth_of_field: true,
+            sdf_scene: Some("SDF pillars"),
+        },
+        create_timeline: create_finale_timeline,
+        create_paths: create_finale_paths,
+    },
+];
+
+/// Demo options chosen at launch
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DemoOptions {
+    /// Seconds into the demo to start from
+    pub start_time: f32,
+    pub disable_audio: bool,
+}
+
 pub struct DemoState {
-    pub camera: Camera,
-    pub start_time: Instant,
-    pub scene: Scene,
-    can: ObjectId,
-    extra_cans: Vec<ObjectId>,
-    last_cans_randomization: Instant,
+    pub fly_camera: FlyCameraController,
+    pub orbit_camera: OrbitCameraController,
+    pub gizmo: TransformGizmo,
+    /// Owns the music, since it drives the demo time
+    pub clock: DemoClock,
+    /// Splits frame times into fixed simulation steps
+    pub fixed_timestep: FixedTimestep,
+    /// Fixed steps of the current frame, for the simulations that run on the GPU
+    pub steps: FixedSteps,
+    pub parts: DemoParts,
+    /// Cuts between the parts and animates everything that isn't part of a scene
+    pub timeline: Timeline,
+    pub shader_params: ShaderParams,
+    pub post_process: PostProcessParams,
+    pub beat_clock: BeatClock,
+    pub gltf_watcher: Option<GltfWatcher>,
+    pub script: DemoScript,
+    pub events: EventBus,
+    pub events_ui: EventsUi,
+    pub post_process_overrides: PostProcessOverrides,
+    /// Demo time of the previous `update_events`
+    last_marker_time: Option<f32>,
+    /// Seek generation of the clock at the previous `update_events`
+    last_seek_generation: u64,
 }
 
-impl DemoState {
-    pub fn new(material_manager: &mut MaterialManager) -> anyhow::Result<Self> {
-        let camera = Camera {
-            eye: Vec3::new(1.0, 2.0, 1.0),
-            target: Vec3::new(0.0, 1.0, 0.0),
-            up: Vec3::Y,
-        };
+/// Everything that can be loaded off the main thread before the demo starts
+pub struct DemoAssets {
+    pub material_manager: MaterialManager,
+    pub baked_meshes: BakedMeshes,
+    parts: DemoParts,
+    timeline: Timeline,
+    /// glTF files of all parts by name, for hot reloading
+    model_paths: HashMap<String, PathBuf>,
+}
 
-        let mut scene = Scene::new();
+impl DemoAssets {
+    /// Number of `LoadingProgress::step` calls made by `load`
+    pub const LOADING_STEPS: usize = PARTS.len() * 3 + 3;
 
-        let (document, buffers, mut images) = gltf::import("assets/tolkki2/tolkki2.gltf")?;
-        let can_scene = document.scenes().next().context("No scenes in gltf")?;
+    pub fn load(progress: &LoadingProgress, bake_options: BakeOptions) -> anyhow::Result<Self> {
+        let mut material_manager = MaterialManager::new();
+        let mut parts = Vec::with_capacity(PARTS.len());
+        let mut model_paths = HashMap::new();
 
-        material_manager.load_all_materials_from_gltf("can", &document, &mut images);
+        for description in &PARTS {
+            let name = description.name;
 
-        for x in -25..25 {
-            for z in -25..25 {
-                let translation = Vec3::new(x as f32 * 0.5, 0.0, z as f32 * 0.5);
-                // Look towards 0.0, 0.0, 0.0
-                let rotation =
-                    Quat::from_axis_angle(Vec3::Y, (x as f32 * 0.1).atan2(z as f32 * 0.1));
-                let scale = 0.5;
+            let scene_file = progress.step(format!("Loading scene file: {}", name), || {
+                SceneFile::load(description.scene_file)
+            })?;
 
-                let can = scene
-                    .spawn_gltf_scene(
-                        material_manager,
-                        "can",
-                        &buffers,
-                        &can_scene,
-                        InstanceType::Static,
-                    )
-                    .expect("Expected scene to contain a root node");
+            let models = progress.step(format!("Importing models: {}", name), || {
+                scene_file.import_models(&mut material_manager)
+            })?;
 
-                scene.set_object_transform(can, translation, rotation, scale);
-            }
+            // The meshes of all parts are baked into the same buffers, in the order of the parts
+            let first_primitive_index = parts
+                .last()
+                .map_or(0, |part: &DemoPart| part.scene.next_primitive_index());
+            let mut scene = Scene::with_first_primitive_index(first_primitive_index);
+
+            let spawned = progress.step(format!("Spawning scene: {}", name), || {
+                scene_file.spawn(&mut scene, &material_manager, &models)
+            })?;
+            let cameras = scene_file.create_cameras()?;
+            let timeline = (description.create_timeline)(&spawned, &cameras)
+                .with_context(|| format!("Failed to create the timeline of part '{}'", name))?;
+            let paths = (description.create_paths)(&spawned, &cameras)
+                .with_context(|| format!("Failed to create the paths of part '{}'", name))?;
+
+            model_paths.extend(scene_file.models);
+            parts.push(DemoPart {
+                name: name.to_string(),
+                scene,
+                cameras,
+                timeline,
+                paths,
+                passes: description.passes,
+                baked_ao: BakedAo::default(),
+            });
         }
 
-        let can = scene
-            .spawn_gltf_scene(
-                material_manager,
-                "can",
-                &buffers,
-                &can_scene,
-                InstanceType::Dynamic,
-            )
-            .expect("Expected scene to contain a root node");
-
-        let extra_cans = (0..1000)
-            .map(|_| {
-                let can = scene
-                    .spawn_gltf_scene(
-                        material_manager,
-                        "can",
-                        &buffers,
-                        &can_scene,
-                        InstanceType::Dynamic,
-                    )
-                    .expect("Expected scene to contain a root node");
-
-                scene.set_object_scale(can, 0.1);
-
-                can
-            })
-            .collect();
+        let mut parts = DemoParts::new(parts);
+
+        progress.step("Packing texture atlases", || {
+            material_manager.pack_atlases()
+        });
+
+        let baked_meshes = progress.step("Baking meshes", || {
+            bake_models(&parts.models(), &bake_options)
+        });
+
+        // Baked AO follows the vertex order of the baked meshes
+        progress.step("Loading baked ambient occlusion", || {
+            for part in parts.iter_mut() {
+                part.baked_ao = BakedAo::load(&part.name, &part.scene, &baked_meshes);
+            }
+        });
+
+        let timeline = create_timeline(&parts)?;
 
         Ok(Self {
-            camera,
-            start_time: Instant::now(),
-            scene,
-            can,
-            extra_cans,
-            last_cans_randomization: Instant::now(),
+            material_manager,
+            baked_meshes,
+            parts,
+            timeline,
+            model_paths,
         })
     }
+}
+
+impl DemoState {
+    /// Takes the parts out of the loaded assets. Audio is loaded here on the main thread,
+    /// because the output stream can't be sent between threads.
+    pub fn new(assets: DemoAssets, options: DemoOptions) -> (Self, MaterialManager, BakedMeshes) {
+        // The demo can run without music, in which case the wall clock is used instead
+        let audio = if options.disable_audio {
+            None
+        } else {
+            match AudioPlayer::load(SOUNDTRACK_PATH) {
+                Ok(audio) => Some(audio),
+                Err(e) => {
+                    log::warn!("Failed to load soundtrack, running without audio: {:?}", e);
+                    None
+                }
+            }
+        };
+
+        // Hot reloading is a development convenience, the demo works fine without it
+        let gltf_watcher = match GltfWatcher::new(&assets.model_paths) {
+            Ok(watcher) => Some(watcher),
+            Err(e) => {
+                log::warn!(
+                    "Failed to watch glTF files, hot reload is disabled: {:?}",
+                    e
+                );
+                None
+            }
+        };
+
+        let clock = DemoClock::real_time(options.start_time, audio);
+
+        let script = DemoScript::load(DEMO_SCRIPT_PATH, true);
+
+        Self::with_clock(assets, clock, script, gltf_watcher)
+    }
+
+    /// For rendering offline. Time only advances with `step_clock`, and there's no music or hot
+    /// reloading.
+    pub fn new_offline(
+        assets: DemoAssets,
+        options: DemoOptions,
+    ) -> (Self, MaterialManager, BakedMeshes) {
+        let clock = DemoClock::stepped(options.start_time);
+
+        let script = DemoScript::load(DEMO_SCRIPT_PATH, false);
+
+        Self::with_clock(assets, clock, script, None)
+    }
+
+    fn with_clock(
+        assets: DemoAssets,
+        clock: DemoClock,
+        script: DemoScript,
+        gltf_watcher: Option<GltfWatcher>,
+    ) -> (Self, MaterialManager, BakedMeshes) {
+        let DemoAssets {
+            material_manager,
+            baked_meshes,
+            parts,
+            timeline,
+            model_paths: _,
+        } = assets;
+
+        let state = Self {
+            fly_camera: FlyCameraController::default(),
+            orbit_camera: OrbitCameraController::default(),
+            gizmo: TransformGizmo::default(),
+            clock,
+            fixed_timestep: FixedTimestep::default(),
+            steps: FixedSteps::default(),
+            parts,
+            timeline,
+            shader_params: [0.0; SHADER_PARAM_COUNT],
+            post_process: PostProcessParams::default(),
+            beat_clock: BeatClock::new(SOUNDTRACK_BPM, 0.0),
+            gltf_watcher,
+            script,
+            events: EventBus::default(),
+            events_ui: EventsUi::default(),
+            post_process_overrides: PostProcessOverrides::default(),
+            last_marker_time: None,
+            last_seek_generation: 0,
+        };
+
+        (state, material_manager, baked_meshes)
+    }
+
+    /// Current demo time in seconds
+    pub fn time(&self) -> f32 {
+        self.clock.time()
+    }
+
+    /// Advances a stepped clock. Does nothing when running in real time.
+    pub fn step_clock(&mut self, delta_time: f32) {
+        self.clock.step(delta_time);
+    }
+
+    /// Sends the events of the markers passed since the previous frame, and delivers every event
+    /// sent since then
+    pub fn update_events(&mut self, time: f32) {
+        let previous = self.last_marker_time.replace(time);
+
+        // Every marker passed since the previous frame fires, however long it took, but the ones
+        // skipped over by a seek don't
+        let seek_generation = self.clock.seek_generation();
+        let seeked =
+            std::mem::replace(&mut self.last_seek_generation, seek_generation) != seek_generation;
+        if seeked {
+            self.parts.clear_marker_switch();
+        }
+
+        if let Some(previous) = previous.filter(|_| !seeked) {
+            self.timeline.fire_markers(previous, time, &mut self.events);
+            self.parts
+                .active()
+                .timeline
+                .fire_markers(previous, time, &mut self.events);
+        }
+
+        self.events.begin_frame();
+    }
 
+    /// Runs the demo script on the active part
     pub fn update(&mut self) {
-        let time = self.start_time.elapsed().as_secs_f32();
+        let time = self.time();
+        let beat = self.beat_clock.beat(time);
+        let part = self.parts.active_mut();
+        self.script
+            .update(&mut part.scene, time, beat, &mut self.shader_params);
+    }
+}
 
-        let rotation = Quat::from_axis_angle(Vec3::Y, time * 0.5);
+fn create_timeline(parts: &DemoParts) -> anyhow::Result<Timeline> {
+    let find_part = |name: &str| -> anyhow::Result<DemoPartId> {
+        parts
+            .find(name)
+            .with_context(|| format!("Demo has no part named '{}'", name))
+    };
+    let cans_part = find_part(CANS_PART)?;
+    let finale_part = find_part(FINALE_PART)?;
 
-        let translation = Vec3::Y * (time * 2.0).sin() * 0.05;
+    let mut timeline = Timeline::new();
 
-        self.scene
-            .set_object_transform(self.can, translation, rotation, 1.0);
+    timeline.add_channel(Channel::PartCut(
+        Track::new()
+            .keyframe(0.0, cans_part, Easing::Linear)
+            .keyframe(80.0, finale_part, Easing::Linear),
+    ));
 
-        // Rotate camera around the origin
-        let camera_rotation = Quat::from_axis_angle(Vec3::Y, time * 0.1);
-        self.camera.eye = camera_rotation * Vec3::new(1.0, 2.0, 1.0);
+    // One look per camera section, cut together with the cameras after fading in from black
+    let neutral = ColorGrading {
+        contrast: 1.05,
+        ..ColorGrading::default()
+    };
+    let warm = ColorGrading {
+        saturation: 1.1,
+        lift: Vec3::new(0.02, 0.01, 0.0),
+        gain: Vec3::new(1.05, 1.0, 0.92),
+        lut_strength: 1.0,
+        ..neutral
+    };
+    let cold = ColorGrading {
+        exposure: -0.3,
+        contrast: 1.15,
+        saturation: 0.7,
+        lift: Vec3::new(0.0, 0.01, 0.03),
+        gain: Vec3::new(0.92, 0.98, 1.05),
+        ..neutral
+    };
+    // The finale is warm too, but fades out instead of cutting
+    let dusk = ColorGrading {
+        contrast: 1.1,
+        lut_strength: 0.6,
+        ..warm
+    };
+    timeline.add_channel(Channel::ColorGrading(
+        Track::new()
+            .keyframe(
+                0.0,
+                ColorGrading {
+                    exposure: -10.0,
+                    ..neutral
+                },
+                Easing::OutQuad,
+            )
+            .keyframe(3.0, neutral, Easing::Step)
+            .keyframe(20.0, warm, Easing::Step)
+            .keyframe(40.0, cold, Easing::Step)
+            .keyframe(60.0, neutral, Easing::Step)
+            .keyframe(80.0, dusk, Easing::InQuad)
+            .keyframe(
+                DEMO_DURATION,
+                ColorGrading {
+                    exposure: -10.0,
+                    ..dusk
+                },
+                Easing::Linear,
+            ),
+    ));
 
-        randomize_cans(self, Instant::now());
+    // Tighter vignette for the close-up, and a burst of aberration on every cut
+    timeline.add_channel(Channel::Vignette(
+        Track::new()
+            .keyframe(20.0, 0.3, Easing::InOutQuad)
+            .keyframe(24.0, 0.6, Easing::Step)
+            .keyframe(40.0, 0.3, Easing::Linear),
+    ));
+    let mut aberration = Track::new();
+    for cut in [20.0, 40.0, 60.0, 80.0] {
+        aberration.add_keyframe(Keyframe::new(cut, 0.012, Easing::OutCubic));
+        aberration.add_keyframe(Keyframe::new(cut + 0.5, 0.002, Easing::Linear));
     }
+    timeline.add_channel(Channel::ChromaticAberration(aberration));
+
+    // Flash into the finale
+    timeline.add_marker(
+        80.0,
+        TimelineEvent::Flash(Flash {
+            color: Vec3::new(1.0, 0.9, 0.8),
+            duration: 1.5,
+        }),
+    );
+
+    Ok(timeline)
 }
 
-fn randomize_cans(state: &mut DemoState, now: Instant) {
-    if now
-        .duration_since(state.last_cans_randomization)
-        .as_secs_f32()
-        > 1.0
-    {
-        state.last_cans_randomization = now;
-
-        // Randomize the position of the cans
-        for can in state.extra_cans.iter_mut() {
-            let translation = Vec3::new(
-                rand::random::<f32>() * 10.0 - 5.0,
-                1.5,
-                rand::random::<f32>() * 10.0 - 5.0,
-            );
-
-            state.scene.set_object_translation(*can, translation);
-        }
+fn find_object(spawned: &SpawnedObjects, name: &str) -> anyhow::Result<ObjectId> {
+    spawned
+        .get(name)
+        .and_then(|ids| ids.first())
+        .copied()
+        .with_context(|| format!("Scene file has no object named '{}'", name))
+}
+
+fn create_cans_timeline(spawned: &SpawnedObjects, cameras: &Cameras) -> anyhow::Result<Timeline> {
+    let can = find_object(spawned, "Can")?;
+    let find_camera = |name: &str| -> anyhow::Result<CameraId> {
+        cameras
+            .find(name)
+            .with_context(|| format!("Scene file has no camera named '{}'", name))
+    };
+    let orbit_camera = find_camera("Orbit")?;
+    let close_up_camera = find_camera("Close-up")?;
+    let overview_camera = find_camera("Overview")?;
+
+    let mut timeline = Timeline::new();
+
+    // Sample the continuous motion once per second; slerp and lerp fill in the rest
+    let seconds = (0..=DEMO_DURATION as u32).map(|second| second as f32);
+
+    let can_rotation = seconds
+        .clone()
+        .map(|time| {
+            Keyframe::new(
+                time,
+                Quat::from_axis_angle(Vec3::Y, time * 0.5),
+                Easing::Linear,
+            )
+        })
+        .collect();
+    timeline.add_channel(Channel::ObjectRotation(
+        can,
+        Track::from_keyframes(can_rotation),
+    ));
+
+    // Bob the can up and down
+    let mut can_translation = Track::new();
+    let bob_period = std::f32::consts::PI;
+    let mut time = 0.0;
+    while time <= DEMO_DURATION {
+        can_translation.add_keyframe(Keyframe::new(time, Vec3::ZERO, Easing::InOutQuad));
+        can_translation.add_keyframe(Keyframe::new(
+            time + bob_period * 0.25,
+            Vec3::Y * 0.05,
+            Easing::InOutQuad,
+        ));
+        can_translation.add_keyframe(Keyframe::new(
+            time + bob_period * 0.75,
+            Vec3::Y * -0.05,
+            Easing::InOutQuad,
+        ));
+        time += bob_period;
     }
+    timeline.add_channel(Channel::ObjectTranslation(can, can_translation));
+
+    // Rotate camera around the origin
+    let camera_eye = seconds
+        .map(|time| {
+            let rotation = Quat::from_axis_angle(Vec3::Y, time * 0.1);
+            Keyframe::new(time, rotation * Vec3::new(1.0, 2.0, 1.0), Easing::Linear)
+        })
+        .collect();
+    timeline.add_channel(Channel::CameraEye(
+        orbit_camera,
+        Track::from_keyframes(camera_eye),
+    ));
+
+    // Slow zoom during the close-up
+    timeline.add_channel(Channel::CameraFov(
+        close_up_camera,
+        Track::new()
+            .keyframe(20.0, 30f32.to_radians(), Easing::InOutQuad)
+            .keyframe(40.0, 15f32.to_radians(), Easing::InOutQuad),
+    ));
+
+    // Rack focus from the can to the background and back
+    timeline.add_channel(Channel::CameraFocusDistance(
+        close_up_camera,
+        Track::new()
+            .keyframe(26.0, 0.85, Easing::InOutQuad)
+            .keyframe(29.0, 8.0, Easing::InOutQuad)
+            .keyframe(34.0, 8.0, Easing::InOutQuad)
+            .keyframe(37.0, 0.85, Easing::InOutQuad),
+    ));
+
+    // The haze rolls in for the overview shot and clears before the loop
+    timeline.add_channel(Channel::FogDensity(
+        Track::new()
+            .keyframe(38.0, 0.03, Easing::InOutQuad)
+            .keyframe(44.0, 0.15, Easing::InOutQuad)
+            .keyframe(54.0, 0.15, Easing::InOutQuad)
+            .keyframe(60.0, 0.03, Easing::InOutQuad),
+    ));
+    timeline.add_channel(Channel::FogBaseHeight(
+        Track::new()
+            .keyframe(38.0, 0.0, Easing::InOutQuad)
+            .keyframe(50.0, 1.5, Easing::InOutQuad)
+            .keyframe(60.0, 0.0, Easing::InOutQuad),
+    ));
+
+    timeline.add_channel(Channel::CameraCut(
+        Track::new()
+            .keyframe(0.0, orbit_camera, Easing::Linear)
+            .keyframe(20.0, close_up_camera, Easing::Linear)
+            .keyframe(40.0, overview_camera, Easing::Linear)
+            .keyframe(60.0, orbit_camera, Easing::Linear),
+    ));
+
+    Ok(timeline)
+}
+
+fn create_finale_timeline(
+    spawned: &SpawnedObjects,
+    _cameras: &Cameras,
+) -> anyhow::Result<Timeline> {
+    let can = find_object(spawned, "Can")?;
+
+    let mut timeline = Timeline::new();
+    let seconds = (80..=DEMO_DURATION as u32).map(|second| second as f32);
+
+    let can_rotation = seconds
+        .map(|time| {
+            Keyframe::new(
+                time,
+                Quat::from_axis_angle(Vec3::Y, time * 0.3),
+                Easing::Linear,
+            )
+        })
+        .collect();
+    timeline.add_channel(Channel::ObjectRotation(
+        can,
+        Track::from_keyframes(can_rotation),
+    ));
+
+    timeline.add_channel(Channel::FogDensity(
+        Track::new()
+            .keyframe(80.0, 0.05, Easing::InOutQuad)
+            .keyframe(DEMO_DURATION, 0.2, Easing::Linear),
+    ));
+
+    Ok(timeline)
+}
+
+fn create_cans_paths(spawned: &SpawnedObjects, _cameras: &Cameras) -> anyhow::Result<Paths> {
+    let flying_can = find_object(spawned, "Flying can")?;
+
+    let mut paths = Paths::default();
+
+    // One loop around the can, nose first
+    paths.add(
+        PathFollower::new(
+            "Flying can",
+            PathTarget::Object(flying_can),
+            Spline::bezier(vec![
+                Vec3::new(-2.5, 0.8, 0.0),
+                Vec3::new(-2.5, 1.2, 2.5),
+                Vec3::new(2.5, 1.2, 2.5),
+                Vec3::new(2.5, 0.8, 0.0),
+                Vec3::new(2.5, 0.4, -2.5),
+                Vec3::new(-2.5, 0.4, -2.5),
+                Vec3::new(-2.5, 0.8, 0.0),
+            ]),
+            10.0,
+            0.0,
+        )
+        .speed(0.5)
+        .easing(Easing::InOutSine)
+        .oriented(),
+    );
+
+    Ok(paths)
+}
+
+fn create_finale_paths(_spawned: &SpawnedObjects, cameras: &Cameras) -> anyhow::Result<Paths> {
+    let camera = cameras
+        .find("Finale")
+        .context("Scene file has no camera named 'Finale'")?;
+
+    let mut paths = Paths::default();
+
+    // Slowly pull away from the can while circling it
+    paths.add(
+        PathFollower::new(
+            "Finale camera",
+            PathTarget::CameraEye(camera),
+            Spline::catmull_rom(vec![
+                Vec3::new(-0.980, 1.5, 1.135),
+                Vec3::new(-0.527, 2.0, 2.444),
+                Vec3::new(0.993, 2.5, 3.356),
+                Vec3::new(3.189, 3.0, 3.175),
+                Vec3::new(5.281, 3.5, 1.537),
+            ]),
+            80.0,
+            DEMO_DURATION - 80.0,
+        )
+        .easing(Easing::InOutSine),
+    );
+
+    Ok(paths)
 }
@@ -0,0 +1,228 @@
+use std::time::Instant;
+
+use winit::keyboard::KeyCode;
+
+use crate::{audio::AudioPlayer, input::InputState};
+
+const PAUSE_KEY: KeyCode = KeyCode::Space;
+const SEEK_BACK_KEY: KeyCode = KeyCode::ArrowLeft;
+const SEEK_FORWARD_KEY: KeyCode = KeyCode::ArrowRight;
+const SLOWER_KEY: KeyCode = KeyCode::BracketLeft;
+const FASTER_KEY: KeyCode = KeyCode::BracketRight;
+/// Seconds skipped by the seek keys, or a tenth of this with shift held
+const SEEK_STEP: f32 = 5.0;
+const MIN_TIME_SCALE: f32 = 0.125;
+const MAX_TIME_SCALE: f32 = 4.0;
+
+enum ClockSource {
+    /// Wall clock time since `anchor`, scaled by the time scale and added to `anchor_time`.
+    /// Re-anchored whenever the clock is paused, seeked or its speed changes.
+    RealTime { anchor: Instant, anchor_time: f32 },
+    /// Advanced manually by `DemoClock::step`, for rendering frames offline
+    Stepped { time: f32 },
+}
+
+/// Where the demo time comes from. Music playback is the authoritative clock when it's playing
+/// at normal speed. Otherwise the music is paused and the wall clock takes over, and the music
+/// is seeked back in sync when normal playback resumes.
+pub struct DemoClock {
+    source: ClockSource,
+    audio: Option<AudioPlayer>,
+    paused: bool,
+    time_scale: f32,
+    /// Incremented on every seek, so that code tracking the time can tell jumps from long frames
+    seek_generation: u64,
+}
+
+impl DemoClock {
+    /// Starts playing from `start_time`, along with the music if there is any
+    pub fn real_time(start_time: f32, audio: Option<AudioPlayer>) -> Self {
+        let clock = Self {
+            source: ClockSource::RealTime {
+                anchor: Instant::now(),
+                anchor_time: start_time,
+            },
+            audio,
+            paused: false,
+            time_scale: 1.0,
+            seek_generation: 0,
+        };
+
+        clock.sync_audio(start_time);
+        clock
+    }
+
+    pub fn stepped(start_time: f32) -> Self {
+        Self {
+            source: ClockSource::Stepped { time: start_time },
+            audio: None,
+            paused: false,
+            time_scale: 1.0,
+            seek_generation: 0,
+        }
+    }
+
+    /// Current demo time in seconds
+    pub fn time(&self) -> f32 {
+        match (&self.source, &self.audio) {
+            (ClockSource::Stepped { time }, _) => *time,
+            (ClockSource::RealTime { .. }, Some(audio)) if self.follows_audio() => audio.position(),
+            (
+                ClockSource::RealTime {
+                    anchor,
+                    anchor_time,
+                },
+                _,
+            ) => {
+                if self.paused {
+                    *anchor_time
+                } else {
+                    anchor_time + anchor.elapsed().as_secs_f32() * self.time_scale
+                }
+            }
+        }
+    }
+
+    /// How fast the demo time moves relative to real time. Zero when paused.
+    pub fn rate(&self) -> f32 {
+        if self.paused {
+            0.0
+        } else {
+            self.time_scale
+        }
+    }
+
+    /// Advances a stepped clock. Does nothing when running in real time.
+    pub fn step(&mut self, delta_time: f32) {
+        if let ClockSource::Stepped { time } = &mut self.source {
+            *time += delta_time * self.rate();
+        }
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.update(|clock| clock.paused = paused);
+    }
+
+    pub fn set_time_scale(&mut self, time_scale: f32) {
+        let time_scale = time_scale.clamp(MIN_TIME_SCALE, MAX_TIME_SCALE);
+        self.update(|clock| clock.time_scale = time_scale);
+    }
+
+    pub fn seek(&mut self, time: f32) {
+        self.seek_generation += 1;
+        self.set_time(time);
+    }
+
+    pub fn seek_generation(&self) -> u64 {
+        self.seek_generation
+    }
+
+    /// Moves the clock to `time` without counting it as a seek
+    fn set_time(&mut self, time: f32) {
+        let time = time.max(0.0);
+        match &mut self.source {
+            ClockSource::Stepped { time: current } => *current = time,
+            ClockSource::RealTime {
+                anchor,
+                anchor_time,
+            } => {
+                *anchor = Instant::now();
+                *anchor_time = time;
+                self.sync_audio(time);
+            }
+        }
+    }
+
+    /// Space pauses, the arrow keys seek and the bracket keys change the speed
+    pub fn handle_input(&mut self, input: &InputState, duration: f32) {
+        if input.was_key_pressed(PAUSE_KEY) {
+            self.set_paused(!self.paused);
+        }
+
+        let seek_step = if input.is_key_down(KeyCode::ShiftLeft) {
+            SEEK_STEP / 10.0
+        } else {
+            SEEK_STEP
+        };
+        if input.was_key_pressed(SEEK_BACK_KEY) {
+            self.seek(self.time() - seek_step);
+        }
+        if input.was_key_pressed(SEEK_FORWARD_KEY) {
+            self.seek((self.time() + seek_step).min(duration));
+        }
+
+        if input.was_key_pressed(SLOWER_KEY) {
+            self.set_time_scale(self.time_scale / 2.0);
+        }
+        if input.was_key_pressed(FASTER_KEY) {
+            self.set_time_scale(self.time_scale * 2.0);
+        }
+    }
+
+    /// Transport bar with the play button, a scrubber and the playback speed
+    pub fn draw_ui(&mut self, ui: &imgui::Ui, duration: f32) {
+        ui.window("Playback")
+            .size([400.0, 100.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                let label = if self.paused { "Play" } else { "Pause" };
+                if ui.button(label) {
+                    self.set_paused(!self.paused);
+                }
+
+                ui.same_line();
+                let mut time = self.time();
+                if ui
+                    .slider_config("##time", 0.0, duration.max(time))
+                    .display_format("%.2f s")
+                    .build(&mut time)
+                {
+                    self.seek(time);
+                }
+
+                let mut time_scale = self.time_scale;
+                if ui
+                    .slider_config("Speed", MIN_TIME_SCALE, MAX_TIME_SCALE)
+                    .display_format("%.3fx")
+                    .flags(imgui::SliderFlags::LOGARITHMIC)
+                    .build(&mut time_scale)
+                {
+                    self.set_time_scale(time_scale);
+                }
+                ui.same_line();
+                if ui.button("1x") {
+                    self.set_time_scale(1.0);
+                }
+
+                ui.text_disabled("Space: pause, arrows: seek, [ and ]: speed");
+            });
+    }
+
+    /// The music drives the clock only while it can play along
+    fn follows_audio(&self) -> bool {
+        !self.paused && self.time_scale == 1.0
+    }
+
+    /// Applies a change to the playback state without making the time jump
+    fn update(&mut self, change: impl FnOnce(&mut Self)) {
+        let time = self.time();
+        change(self);
+        self.set_time(time);
+    }
+
+    /// Starts or stops the music and moves it to `time`
+    fn sync_audio(&self, time: f32) {
+        let Some(audio) = &self.audio else {
+            return;
+        };
+
+        if let Err(e) = audio.seek(time) {
+            log::warn!("Failed to seek the soundtrack: {:?}", e);
+        }
+
+        if self.follows_audio() {
+            audio.play();
+        } else {
+            audio.pause();
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use crate::{
+    asset_pipeline::baked_ao::BakedAo,
+    camera::Cameras,
+    events::{CutToPart, DespawnObjects, EventBus, PartPass, SetPass, SpawnPrefab, SwitchPart},
+    model::Model,
+    path_follower::Paths,
+    scene_graph::{scene::Scene, transform::Transform},
+    timeline::Timeline,
+};
+
+/// Index into `DemoParts`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DemoPartId(usize);
+
+/// Passes that a part can turn off, e.g. when they don't suit its look. Passes that are disabled
+/// from the UI stay off regardless.
+#[derive(Debug, Clone, Copy)]
+pub struct PartPasses {
+    pub ssr: bool,
+    pub volumetric_fog: bool,
+    pub depth_of_field: bool,
+    /// Name of a raymarched scene from `SDF_SCENES` drawn with the meshes of the part
+    pub sdf_scene: Option<&'static str>,
+}
+
+impl Default for PartPasses {
+    fn default() -> Self {
+        Self {
+            ssr: true,
+            volumetric_fog: true,
+            depth_of_field: true,
+            sdf_scene: None,
+        }
+    }
+}
+
+impl PartPasses {
+    pub fn set(&mut self, pass: PartPass, enabled: bool) {
+        match pass {
+            PartPass::Ssr => self.ssr = enabled,
+            PartPass::VolumetricFog => self.volumetric_fog = enabled,
+            PartPass::DepthOfField => self.depth_of_field = enabled,
+        }
+    }
+}
+
+/// A section of the demo with its own scene, cameras and animation
+pub struct DemoPart {
+    pub name: String,
+    pub scene: Scene,
+    pub cameras: Cameras,
+    /// Animates the scene and cameras of this part. Sampled with the demo time, so parts keep
+    /// moving while they aren't shown.
+    pub timeline: Timeline,
+    /// Splines that objects and cameras follow, applied after the timeline
+    pub paths: Paths,
+    pub passes: PartPasses,
+    /// Ambient occlusion baked for the static objects with `--bake-ao`, empty if not baked
+    pub baked_ao: BakedAo,
+}
+
+/// All parts of the demo. Every part is loaded before the demo starts and their meshes share the
+/// same buffers, so switching parts doesn't load anything. The global timeline cuts between
+/// parts, unless one has been picked manually from the UI.
+pub struct DemoParts {
+    parts: Vec<DemoPart>,
+    active: DemoPartId,
+    /// Set when the active part is picked from the UI, which disables timeline cuts
+    manual_override: bool,
+    /// Part that the global timeline last cut to
+    timeline_part: Option<DemoPartId>,
+    /// Shown instead of the timeline's part until its next cut, set by markers
+    marker_part: Option<DemoPartId>,
+    /// Set when models have been spawned after the meshes were baked
+    meshes_changed: bool,
+}
+
+impl DemoParts {
+    /// The first part is active when the demo starts
+    pub fn new(parts: Vec<DemoPart>) -> Self {
+        assert!(!parts.is_empty(), "A demo needs at least one part");
+
+        Self {
+            parts,
+            active: DemoPartId(0),
+            manual_override: false,
+            timeline_part: None,
+            marker_part: None,
+            meshes_changed: false,
+        }
+    }
+
+    pub fn find(&self, name: &str) -> Option<DemoPartId> {
+        self.parts
+            .iter()
+            .position(|part| part.name == name)
+            .map(DemoPartId)
+    }
+
+    pub fn active_id(&self) -> DemoPartId {
+        self.active
+    }
+
+    pub fn active(&self) -> &DemoPart {
+        &self.parts[self.active.0]
+    }
+
+    pub fn active_mut(&mut self) -> &mut DemoPart {
+        &mut self.parts[self.active.0]
+    }
+
+    /// In the order they were added, which is also the order of their meshes
+    pub fn iter(&self) -> impl Iterator<Item = &DemoPart> {
+        self.parts.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut DemoPart> {
+        self.parts.iter_mut()
+    }
+
+    /// Models of every part. Their meshes are baked in the order of their primitive indices,
+    /// which differs from this for models spawned while the demo is running.
+    pub fn models(&self) -> Vec<&Model> {
+        self.parts
+            .iter()
+            .flat_map(|part| part.scene.models.iter().map(|(_, model)| &model.model))
+            .collect()
+    }
+
+    /// Global index that the next primitive of any part gets
+    fn next_primitive_index(&self) -> usize {
+        self.parts
+            .iter()
+            .map(|part| part.scene.next_primitive_index())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Spawns into the scene of a part while the demo is running. New primitives are numbered
+    /// after the primitives of every part, and the meshes are baked again on the next frame.
+    pub fn spawn<R>(&mut self, id: DemoPartId, spawn: impl FnOnce(&mut Scene) -> R) -> Option<R> {
+        let next_primitive_index = self.next_primitive_index();
+        let scene = &mut self.parts.get_mut(id.0)?.scene;
+
+        scene.continue_primitive_indices_from(next_primitive_index);
+        let result = spawn(scene);
+        self.meshes_changed |= scene.next_primitive_index() != next_primitive_index;
+
+        Some(result)
+    }
+
+    /// Whether models have been spawned since the last call, so the meshes must be baked again
+    pub fn take_meshes_changed(&mut self) -> bool {
+        std::mem::take(&mut self.meshes_changed)
+    }
+
+    /// Switches to the part of the global timeline, unless one has been picked manually. A part
+    /// switched to by a marker stays until the timeline cuts to a different part.
+    pub fn cut_to(&mut self, id: DemoPartId) {
+        if id.0 >= self.parts.len() {
+            return;
+        }
+
+        if self.timeline_part != Some(id) {
+            self.timeline_part = Some(id);
+            self.marker_part = None;
+        }
+
+        if !self.manual_override {
+            self.active = self.marker_part.unwrap_or(id);
+        }
+    }
+
+    /// Shows a part until the next timeline cut, without disabling the cuts like `select` does
+    pub fn switch_until_next_cut(&mut self, id: DemoPartId) {
+        if id.0 < self.parts.len() {
+            self.marker_part = Some(id);
+            if !self.manual_override {
+                self.active = id;
+            }
+        }
+    }
+
+    /// Gives control back to the timeline after a seek, since the markers that switched parts
+    /// may not have been passed
+    pub fn clear_marker_switch(&mut self) {
+        self.marker_part = None;
+    }
+
+    /// Shows a part regardless of the timeline, as if it had been picked from the UI
+    pub fn select(&mut self, id: DemoPartId) {
+        if id.0 < self.parts.len() {
+            self.active = id;
+            self.manual_override = true;
+        }
+    }
+
+    pub fn handle_events(&mut self, events: &EventBus) {
+        for SwitchPart(id) in events.read::<SwitchPart>() {
+            self.select(*id);
+        }
+
+        for CutToPart(id) in events.read::<CutToPart>() {
+            self.switch_until_next_cut(*id);
+        }
+
+        for SetPass { pass, enabled } in events.read::<SetPass>() {
+            self.active_mut().passes.set(*pass, *enabled);
+        }
+
+        for SpawnPrefab {
+            prefab,
+            translation,
+        } in events.read::<SpawnPrefab>()
+        {
+            let spawned = self.spawn(self.active, |scene| {
+                let prefab_id = scene.find_prefab(prefab)?;
+                Some(scene.instantiate(prefab_id, Transform::from_translation(*translation)))
+            });
+
+            if spawned.flatten().is_none() {
+                log::warn!(
+                    "Can't spawn '{}', the active part has no such prefab",
+                    prefab
+                );
+            }
+        }
+
+        for DespawnObjects { name } in events.read::<DespawnObjects>() {
+            let scene = &mut self.active_mut().scene;
+            let object_ids = scene.objects_with_name(name);
+            if object_ids.is_empty() {
+                log::warn!(
+                    "Can't despawn '{}', the active part has no such object",
+                    name
+                );
+            }
+
+            for object_id in object_ids {
+                scene.despawn_object(object_id);
+            }
+        }
+    }
+
+    pub fn draw_ui(&mut self, ui: &imgui::Ui) {
+        ui.window("Demo parts")
+            .size([220.0, 120.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                ui.checkbox("Manual part selection", &mut self.manual_override);
+
+                let _disabled = ui.begin_disabled(!self.manual_override);
+                for (index, part) in self.parts.iter().enumerate() {
+                    ui.radio_button(&part.name, &mut self.active, DemoPartId(index));
+                }
+            });
+    }
+}
@@ -0,0 +1,376 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::mpsc::{channel, Receiver},
+    time::Duration,
+};
+
+use anyhow::Context;
+use glam::{EulerRot, Quat, Vec2, Vec3};
+use notify_debouncer_mini::{
+    new_debouncer_opt, notify::*, DebounceEventResult, DebouncedEventKind, Debouncer,
+};
+use rhai::{Array, CallFnOptions, Dynamic, Engine, EvalAltResult, Scope, AST, FLOAT, INT};
+
+use crate::{
+    math::{
+        easing::Easing,
+        noise::{self, Fbm},
+    },
+    rendering::global_uniform::ShaderParams,
+    scene_graph::{object3d::ObjectId, scene::Scene},
+};
+
+pub const DEMO_SCRIPT_PATH: &str = "assets/scripts/demo.rhai";
+
+/// Targets starting with this refer to a tag instead of an object name
+const TAG_PREFIX: char = '#';
+
+/// Object handle given to scripts by `Scene.find`
+#[derive(Debug, Clone, Copy)]
+struct ScriptObject(ObjectId);
+
+/// Changes requested by a script, applied once it returns
+enum SceneCommand {
+    Translation(ObjectId, Vec3),
+    Rotation(ObjectId, Quat),
+    Scale(ObjectId, Vec3),
+    Enabled(ObjectId, bool),
+    ShaderParam(usize, f32),
+}
+
+/// Objects of a scene by name and tag, copied from the scene's index every time the script runs
+struct ObjectIndex {
+    by_name: HashMap<String, Vec<ObjectId>>,
+    by_tag: HashMap<String, Vec<ObjectId>>,
+}
+
+impl ObjectIndex {
+    fn new(scene: &Scene) -> Self {
+        Self {
+            by_name: scene
+                .object_names()
+                .map(|name| (name.to_string(), scene.objects_with_name(name)))
+                .collect(),
+            by_tag: scene
+                .object_tags()
+                .map(|tag| (tag.to_string(), scene.objects_with_tag(tag)))
+                .collect(),
+        }
+    }
+}
+
+/// The `Scene` type seen by scripts. Scripts can't borrow the real scene, so lookups use a
+/// snapshot of the names and changes are queued.
+#[derive(Clone)]
+struct ScriptScene {
+    index: Rc<ObjectIndex>,
+    commands: Rc<RefCell<Vec<SceneCommand>>>,
+}
+
+impl ScriptScene {
+    /// Objects with the name, or with the tag if the target starts with `#`
+    fn find(&mut self, target: &str) -> Array {
+        let ids = match target.strip_prefix(TAG_PREFIX) {
+            Some(tag) => self.index.by_tag.get(tag),
+            None => self.index.by_name.get(target),
+        };
+
+        ids.into_iter()
+            .flatten()
+            .map(|id| Dynamic::from(ScriptObject(*id)))
+            .collect()
+    }
+
+    fn push(&mut self, command: SceneCommand) {
+        self.commands.borrow_mut().push(command);
+    }
+
+    fn set_translation(&mut self, object: ScriptObject, x: FLOAT, y: FLOAT, z: FLOAT) {
+        let translation = Vec3::new(x as f32, y as f32, z as f32);
+        self.push(SceneCommand::Translation(object.0, translation));
+    }
+
+    /// Euler angles in degrees, in the same order as in scene files
+    fn set_rotation(&mut self, object: ScriptObject, x: FLOAT, y: FLOAT, z: FLOAT) {
+        let rotation = Quat::from_euler(
+            EulerRot::YXZ,
+            (y as f32).to_radians(),
+            (x as f32).to_radians(),
+            (z as f32).to_radians(),
+        );
+        self.push(SceneCommand::Rotation(object.0, rotation));
+    }
+
+    fn set_scale(&mut self, object: ScriptObject, scale: FLOAT) {
+        self.push(SceneCommand::Scale(object.0, Vec3::splat(scale as f32)));
+    }
+
+    fn set_enabled(&mut self, object: ScriptObject, enabled: bool) {
+        self.push(SceneCommand::Enabled(object.0, enabled));
+    }
+
+    fn set_param(&mut self, index: INT, value: FLOAT) {
+        if let Ok(index) = usize::try_from(index) {
+            self.push(SceneCommand::ShaderParam(index, value as f32));
+        }
+    }
+}
+
+/// Per-frame choreography written in Rhai. The script's `update(scene, time, beat)` function is
+/// called every frame after the timeline has been applied, so it can override the timeline.
+/// The script is reloaded when it's saved.
+pub struct DemoScript {
+    engine: Engine,
+    path: PathBuf,
+    ast: Option<AST>,
+    scope: Scope<'static>,
+    /// `this` of the update function. Kept between frames and reloads.
+    state: Dynamic,
+    /// Set when the update function fails, so that the error is logged once instead of every
+    /// frame. Cleared by reloading.
+    failed: bool,
+    watcher: Option<ScriptWatcher>,
+}
+
+impl DemoScript {
+    /// The demo runs without the script if it can't be loaded, so errors are only logged
+    pub fn load(path: impl AsRef<Path>, hot_reload: bool) -> Self {
+        let path = path.as_ref().to_path_buf();
+
+        // Hot reloading is a development convenience, the demo works fine without it
+        let watcher = hot_reload
+            .then(|| match ScriptWatcher::new(&path) {
+                Ok(watcher) => Some(watcher),
+                Err(e) => {
+                    log::warn!("Failed to watch {}: {:?}", path.display(), e);
+                    None
+                }
+            })
+            .flatten();
+
+        let mut script = Self {
+            engine: create_engine(),
+            path,
+            ast: None,
+            scope: Scope::new(),
+            state: Dynamic::from_map(Default::default()),
+            failed: false,
+            watcher,
+        };
+
+        match std::fs::read_to_string(&script.path) {
+            Ok(source) => script.compile(&source),
+            Err(e) => log::warn!(
+                "Failed to read {}, running without a script: {}",
+                script.path.display(),
+                e
+            ),
+        }
+
+        script
+    }
+
+    /// Replaces the script if it compiles, and runs its top level statements
+    fn compile(&mut self, source: &str) {
+        let ast = match self.engine.compile(source) {
+            Ok(ast) => ast,
+            Err(e) => {
+                log::error!("Failed to compile {}: {}", self.path.display(), e);
+                return;
+            }
+        };
+
+        let mut scope = Scope::new();
+        if let Err(e) = self.engine.run_ast_with_scope(&mut scope, &ast) {
+            log::error!("Failed to run {}: {}", self.path.display(), e);
+            return;
+        }
+
+        self.ast = Some(ast);
+        self.scope = scope;
+        self.failed = false;
+    }
+
+    fn poll_reloads(&mut self) {
+        let Some(source) = self
+            .watcher
+            .as_ref()
+            .and_then(|watcher| watcher.poll().last())
+        else {
+            return;
+        };
+
+        log::info!("Reloading {}", self.path.display());
+        self.compile(&source);
+    }
+
+    pub fn update(
+        &mut self,
+        scene: &mut Scene,
+        time: f32,
+        beat: f32,
+        shader_params: &mut ShaderParams,
+    ) {
+        self.poll_reloads();
+
+        let Some(ast) = &self.ast else {
+            return;
+        };
+        if self.failed {
+            return;
+        }
+
+        let index = Rc::new(ObjectIndex::new(scene));
+        let commands = Rc::new(RefCell::new(Vec::new()));
+        let script_scene = ScriptScene {
+            index,
+            commands: commands.clone(),
+        };
+
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .bind_this_ptr(&mut self.state);
+        let result = self.engine.call_fn_with_options::<Dynamic>(
+            options,
+            &mut self.scope,
+            ast,
+            "update",
+            (script_scene, time as FLOAT, beat as FLOAT),
+        );
+
+        if let Err(e) = result {
+            log::error!("Error in {}: {}", self.path.display(), e);
+            self.failed = true;
+            return;
+        }
+
+        for command in commands.take() {
+            match command {
+                SceneCommand::Translation(id, translation) => {
+                    scene.set_object_translation(id, translation)
+                }
+                SceneCommand::Rotation(id, rotation) => scene.set_object_rotation(id, rotation),
+                SceneCommand::Scale(id, scale) => scene.set_object_scale(id, scale),
+                SceneCommand::Enabled(id, enabled) => {
+                    if let Some(object) = scene.get_object_mut(id) {
+                        object.enabled = enabled;
+                    }
+                }
+                SceneCommand::ShaderParam(index, value) => {
+                    if let Some(param) = shader_params.get_mut(index) {
+                        *param = value;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn create_engine() -> Engine {
+    let mut engine = Engine::new();
+
+    engine
+        .register_type_with_name::<ScriptObject>("Object")
+        .register_type_with_name::<ScriptScene>("Scene")
+        .register_fn("find", ScriptScene::find)
+        .register_fn("set_translation", ScriptScene::set_translation)
+        .register_fn("set_rotation", ScriptScene::set_rotation)
+        .register_fn("set_scale", ScriptScene::set_scale)
+        .register_fn("set_enabled", ScriptScene::set_enabled)
+        .register_fn("set_param", ScriptScene::set_param)
+        .register_fn("random", rand::random::<FLOAT>)
+        .register_fn("ease", ease)
+        .register_fn("perlin", |x: FLOAT| noise::perlin1(x as f32) as FLOAT)
+        .register_fn("perlin", |x: FLOAT, y: FLOAT| {
+            noise::perlin2(Vec2::new(x as f32, y as f32)) as FLOAT
+        })
+        .register_fn("perlin", |x: FLOAT, y: FLOAT, z: FLOAT| {
+            noise::perlin3(Vec3::new(x as f32, y as f32, z as f32)) as FLOAT
+        })
+        .register_fn("simplex", |x: FLOAT, y: FLOAT| {
+            noise::simplex2(Vec2::new(x as f32, y as f32)) as FLOAT
+        })
+        .register_fn("simplex", |x: FLOAT, y: FLOAT, z: FLOAT| {
+            noise::simplex3(Vec3::new(x as f32, y as f32, z as f32)) as FLOAT
+        })
+        .register_fn("fbm", |x: FLOAT| {
+            Fbm::default().sample(x as f32, noise::perlin1) as FLOAT
+        })
+        .register_fn("fbm", |x: FLOAT, y: FLOAT| {
+            Fbm::default().sample(Vec2::new(x as f32, y as f32), noise::simplex2) as FLOAT
+        })
+        .register_fn("fbm", |x: FLOAT, y: FLOAT, z: FLOAT| {
+            Fbm::default().sample(Vec3::new(x as f32, y as f32, z as f32), noise::simplex3) as FLOAT
+        });
+
+    engine.on_print(|text| log::info!("Script: {}", text));
+    engine.on_debug(|text, _, position| log::debug!("Script at {}: {}", position, text));
+
+    engine
+}
+
+/// `ease("InOutQuad", t)` shapes `t` from 0 to 1 with one of the `Easing` curves
+fn ease(name: &str, t: FLOAT) -> Result<FLOAT, Box<EvalAltResult>> {
+    let easing = Easing::from_name(name).ok_or_else(|| format!("Unknown easing: {}", name))?;
+    Ok(easing.apply(t as f32) as FLOAT)
+}
+
+/// Watches the script file, and reads it again when it changes
+struct ScriptWatcher {
+    receiver: Receiver<String>,
+    _debouncer: Debouncer<RecommendedWatcher>,
+}
+
+impl ScriptWatcher {
+    fn new(path: &Path) -> anyhow::Result<Self> {
+        let path = path
+            .canonicalize()
+            .with_context(|| format!("Failed to find script: {}", path.display()))?;
+        let folder = path
+            .parent()
+            .context("Script has no parent folder")?
+            .to_path_buf();
+
+        let (sender, receiver) = channel();
+
+        let mut debouncer = new_debouncer_opt(
+            notify_debouncer_mini::Config::default().with_timeout(Duration::from_millis(100)),
+            move |res: DebounceEventResult| match res {
+                Ok(events) => {
+                    let changed = events
+                        .iter()
+                        .any(|event| event.kind == DebouncedEventKind::Any && event.path == path);
+                    if !changed {
+                        return;
+                    }
+
+                    match std::fs::read_to_string(&path) {
+                        Ok(source) => {
+                            let _ = sender.send(source);
+                        }
+                        Err(e) => log::error!("Failed to read {}: {}", path.display(), e),
+                    }
+                }
+                Err(e) => log::error!("Error debouncing script changes: {}", e),
+            },
+        )
+        .context("Failed to create script watcher")?;
+
+        debouncer
+            .watcher()
+            .watch(&folder, RecursiveMode::NonRecursive)
+            .context("Failed to watch script folder")?;
+
+        Ok(Self {
+            receiver,
+            _debouncer: debouncer,
+        })
+    }
+
+    fn poll(&self) -> impl Iterator<Item = String> + '_ {
+        self.receiver.try_iter()
+    }
+}
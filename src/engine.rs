@@ -1,16 +1,140 @@
-use crate::{demo::DemoState, material_manager::MaterialManager, rendering::renderer::Renderer};
+use crate::{
+    asset_pipeline::mesh_baker::bake_models, demo::DemoState, input::InputState,
+    material_manager::MaterialManager, rendering::renderer::Renderer,
+};
 
 pub fn update(
     state: &mut DemoState,
-    _renderer: &mut Renderer,
+    renderer: &mut Renderer,
     material_manager: &mut MaterialManager,
+    input: &InputState,
+    delta_time: f32,
     ui: &imgui::Ui,
 ) -> anyhow::Result<()> {
-    state.scene.early_update();
+    update_changed_models(state, renderer, material_manager);
+
+    let duration = state.timeline.duration();
+    state.clock.handle_input(input, duration);
+
+    let time = state.time();
+    // Animations follow the demo clock, so they stop when it's paused
+    let steps = state
+        .fixed_timestep
+        .advance(delta_time * state.clock.rate());
+    state.steps = steps;
+
+    state.update_events(time);
+    // Before the part cut, which a part switch overrides
+    state.parts.handle_events(&state.events);
+
+    if let Some(part_id) = state.timeline.part_at(time) {
+        state.parts.cut_to(part_id);
+    }
+
+    let part = state.parts.active_mut();
+    part.scene.early_update();
+    state.timeline.apply(
+        time,
+        &mut part.scene,
+        &mut part.cameras,
+        &mut state.shader_params,
+        &mut state.post_process,
+    );
+    part.timeline.apply(
+        time,
+        &mut part.scene,
+        &mut part.cameras,
+        &mut state.shader_params,
+        &mut state.post_process,
+    );
+    part.paths.apply(time, &mut part.scene, &mut part.cameras);
+    state
+        .post_process_overrides
+        .handle_events(&state.events, time);
+    state
+        .post_process_overrides
+        .apply(time, &mut state.post_process);
+    // Applied after the timeline so that it can override the scripted camera. Driven by input,
+    // so it's updated every frame instead of in fixed steps.
+    state
+        .fly_camera
+        .update(input, part.cameras.active_mut(), delta_time);
+    // The orbit camera takes precedence over the fly camera
+    if state.orbit_camera.is_enabled() && state.fly_camera.is_enabled() {
+        state.fly_camera.set_enabled(false, part.cameras.active());
+    }
+    state.orbit_camera.update(input, part.cameras.active_mut());
     state.update();
-    state.scene.late_update(ui);
 
+    let part = state.parts.active_mut();
+    part.scene.update_animations(steps);
+    part.scene.late_update(ui);
+    // After the transform update, so that the gizmo is drawn where the object is this frame
+    state
+        .gizmo
+        .update(input, &mut part.scene, part.cameras.active(), ui);
+
+    state.clock.draw_ui(ui, duration);
+    state.timeline.draw_ui(ui, time);
+    state.parts.draw_ui(ui);
+    state.events_ui.draw_ui(ui, &mut state.events, &state.parts);
+    let part = state.parts.active_mut();
+    state.fly_camera.draw_ui(ui);
+    state.orbit_camera.draw_ui(ui, part.cameras.active());
+    part.cameras.draw_ui(ui);
+    state.gizmo.draw_ui(ui, &part.scene);
+    part.scene.draw_animation_ui(ui);
+    part.scene.draw_particle_emitter_ui(ui);
+    part.scene.draw_text_ui(ui);
+    part.scene.draw_decal_ui(ui);
+    part.scene.draw_reflection_probe_ui(ui);
+    part.scene.draw_fog_ui(ui);
+    part.paths.draw_ui(ui);
+    part.paths.draw(&mut renderer.debug_draw);
     material_manager.draw_ui(ui);
 
     Ok(())
 }
+
+/// Bakes the meshes again if models have been hot reloaded or spawned
+fn update_changed_models(
+    state: &mut DemoState,
+    renderer: &mut Renderer,
+    material_manager: &MaterialManager,
+) {
+    let mut changed = state.parts.take_meshes_changed();
+
+    let reloaded_files = state.gltf_watcher.iter().flat_map(|watcher| watcher.poll());
+
+    for reloaded in reloaded_files {
+        // Any number of parts can use the same file
+        for part in state.parts.iter_mut() {
+            if !part.scene.has_gltf_model(&reloaded.file_name) {
+                continue;
+            }
+
+            match part.scene.reload_gltf_models(
+                material_manager,
+                &reloaded.file_name,
+                &reloaded.document,
+                &reloaded.buffers,
+            ) {
+                Ok(()) => {
+                    log::info!("Model reloaded in {}: {}", part.name, reloaded.file_name);
+                    changed = true;
+                }
+                Err(e) => log::error!(
+                    "Failed to reload {} in {}: {:?}",
+                    reloaded.file_name,
+                    part.name,
+                    e
+                ),
+            }
+        }
+    }
+
+    if changed {
+        let baked_meshes = bake_models(&state.parts.models(), &renderer.bake_options());
+        renderer.update_meshes(&baked_meshes);
+    }
+}
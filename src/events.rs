@@ -0,0 +1,273 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    fmt::Debug,
+};
+
+use glam::Vec3;
+
+use crate::{
+    demo_part::{DemoPartId, DemoParts},
+    rendering::post_process::color_grading::ColorGrading,
+};
+
+/// Cuts to a part and keeps showing it, like picking it from the UI
+#[derive(Debug, Clone, Copy)]
+pub struct SwitchPart(pub DemoPartId);
+
+/// Cuts to a part until the next cut of the global timeline
+#[derive(Debug, Clone, Copy)]
+pub struct CutToPart(pub DemoPartId);
+
+/// Passes that `PartPasses` can turn off
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartPass {
+    Ssr,
+    VolumetricFog,
+    DepthOfField,
+}
+
+impl PartPass {
+    pub const ALL: [PartPass; 3] = [
+        PartPass::Ssr,
+        PartPass::VolumetricFog,
+        PartPass::DepthOfField,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PartPass::Ssr => "SSR",
+            PartPass::VolumetricFog => "Volumetric fog",
+            PartPass::DepthOfField => "Depth of field",
+        }
+    }
+}
+
+/// Turns a pass of the active part on or off
+#[derive(Debug, Clone, Copy)]
+pub struct SetPass {
+    pub pass: PartPass,
+    pub enabled: bool,
+}
+
+/// Fades the screen from a color back to normal
+#[derive(Debug, Clone, Copy)]
+pub struct Flash {
+    pub color: Vec3,
+    /// Seconds of demo time
+    pub duration: f32,
+}
+
+/// Instantiates a prefab of the active part at a position
+#[derive(Debug, Clone)]
+pub struct SpawnPrefab {
+    /// Name of the root object of the prefab
+    pub prefab: String,
+    pub translation: Vec3,
+}
+
+/// Removes the objects of the active part that have this name, along with their children
+#[derive(Debug, Clone)]
+pub struct DespawnObjects {
+    pub name: String,
+}
+
+/// Replaces the color grading of the timeline, or gives control back to it with None
+#[derive(Debug, Clone, Copy)]
+pub struct SetColorGrading(pub Option<ColorGrading>);
+
+/// Events that timeline markers can send
+#[derive(Debug, Clone)]
+pub enum TimelineEvent {
+    CutToPart(CutToPart),
+    SetPass(SetPass),
+    Flash(Flash),
+    SpawnPrefab(SpawnPrefab),
+    DespawnObjects(DespawnObjects),
+    SetColorGrading(SetColorGrading),
+}
+
+impl TimelineEvent {
+    pub fn send(&self, bus: &mut EventBus) {
+        match self.clone() {
+            TimelineEvent::CutToPart(event) => bus.send(event),
+            TimelineEvent::SetPass(event) => bus.send(event),
+            TimelineEvent::Flash(event) => bus.send(event),
+            TimelineEvent::SpawnPrefab(event) => bus.send(event),
+            TimelineEvent::DespawnObjects(event) => bus.send(event),
+            TimelineEvent::SetColorGrading(event) => bus.send(event),
+        }
+    }
+}
+
+trait AnyQueue {
+    fn begin_frame(&mut self);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+struct Queue<E> {
+    /// Sent during the current frame, delivered on the next one
+    pending: Vec<E>,
+    delivered: Vec<E>,
+}
+
+impl<E: 'static> AnyQueue for Queue<E> {
+    fn begin_frame(&mut self) {
+        self.delivered = std::mem::take(&mut self.pending);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Typed events between systems. Events are delivered on the frame after they're sent, so every
+/// system that reads a type sees all of its events regardless of the order the systems run in.
+#[derive(Default)]
+pub struct EventBus {
+    queues: HashMap<TypeId, Box<dyn AnyQueue>>,
+}
+
+impl EventBus {
+    pub fn send<E: Debug + 'static>(&mut self, event: E) {
+        log::debug!("Sending event: {:?}", event);
+
+        self.queues
+            .entry(TypeId::of::<E>())
+            .or_insert_with(|| {
+                Box::new(Queue::<E> {
+                    pending: Vec::new(),
+                    delivered: Vec::new(),
+                })
+            })
+            .as_any_mut()
+            .downcast_mut::<Queue<E>>()
+            .expect("Event queue has the wrong type")
+            .pending
+            .push(event);
+    }
+
+    /// Events of the type delivered this frame
+    pub fn read<E: 'static>(&self) -> &[E] {
+        self.queues
+            .get(&TypeId::of::<E>())
+            .and_then(|queue| queue.as_any().downcast_ref::<Queue<E>>())
+            .map_or(&[], |queue| &queue.delivered)
+    }
+
+    /// Delivers the events sent since the previous call
+    pub fn begin_frame(&mut self) {
+        for queue in self.queues.values_mut() {
+            queue.begin_frame();
+        }
+    }
+}
+
+/// Fires events by hand, for testing what the timeline markers do
+pub struct EventsUi {
+    part_index: usize,
+    pass: PartPass,
+    pass_enabled: bool,
+    flash_color: [f32; 3],
+    flash_duration: f32,
+    prefab_index: usize,
+    spawn_translation: [f32; 3],
+    despawn_name: String,
+}
+
+impl Default for EventsUi {
+    fn default() -> Self {
+        Self {
+            part_index: 0,
+            pass: PartPass::Ssr,
+            pass_enabled: false,
+            flash_color: [1.0; 3],
+            flash_duration: 0.5,
+            prefab_index: 0,
+            spawn_translation: [0.0; 3],
+            despawn_name: String::new(),
+        }
+    }
+}
+
+impl EventsUi {
+    pub fn draw_ui(&mut self, ui: &imgui::Ui, bus: &mut EventBus, parts: &DemoParts) {
+        ui.window("Events")
+            .size([300.0, 320.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                let part_names = parts
+                    .iter()
+                    .map(|part| part.name.as_str())
+                    .collect::<Vec<_>>();
+                ui.combo_simple_string("Part", &mut self.part_index, &part_names);
+                if ui.button("Switch part") {
+                    if let Some(id) = parts.find(part_names[self.part_index]) {
+                        bus.send(SwitchPart(id));
+                    }
+                }
+
+                ui.separator();
+                for pass in PartPass::ALL {
+                    ui.radio_button(pass.name(), &mut self.pass, pass);
+                }
+                ui.checkbox("Enabled", &mut self.pass_enabled);
+                if ui.button("Set pass") {
+                    bus.send(SetPass {
+                        pass: self.pass,
+                        enabled: self.pass_enabled,
+                    });
+                }
+
+                ui.separator();
+                ui.color_edit3("Flash color", &mut self.flash_color);
+                ui.slider("Flash duration", 0.05, 5.0, &mut self.flash_duration);
+                if ui.button("Flash") {
+                    bus.send(Flash {
+                        color: self.flash_color.into(),
+                        duration: self.flash_duration,
+                    });
+                }
+
+                ui.separator();
+                let prefab_names = parts.active().scene.prefab_names();
+                if prefab_names.is_empty() {
+                    ui.text_disabled("The active part has no prefabs");
+                } else {
+                    ui.combo_simple_string("Prefab", &mut self.prefab_index, &prefab_names);
+                    ui.input_float3("Position", &mut self.spawn_translation)
+                        .build();
+                    if let Some(prefab) = prefab_names.get(self.prefab_index) {
+                        if ui.button("Spawn") {
+                            bus.send(SpawnPrefab {
+                                prefab: prefab.to_string(),
+                                translation: self.spawn_translation.into(),
+                            });
+                        }
+                    }
+                }
+
+                ui.input_text("Object name", &mut self.despawn_name).build();
+                if ui.button("Despawn") {
+                    bus.send(DespawnObjects {
+                        name: self.despawn_name.clone(),
+                    });
+                }
+
+                ui.separator();
+                for (name, grading) in ColorGrading::presets() {
+                    if ui.button(name) {
+                        bus.send(SetColorGrading(Some(grading)));
+                    }
+                    ui.same_line();
+                }
+                if ui.button("Timeline") {
+                    bus.send(SetColorGrading(None));
+                }
+            });
+    }
+}
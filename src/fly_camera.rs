@@ -0,0 +1,127 @@
+use glam::Vec3;
+use winit::{event::MouseButton, keyboard::KeyCode};
+
+use crate::{camera::Camera, input::InputState};
+
+const TOGGLE_KEY: KeyCode = KeyCode::F1;
+const MAX_PITCH: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+
+/// Free-flying debug camera. While enabled it overrides whatever the timeline does to the
+/// camera. WASD moves, Q/E moves down/up, holding the right mouse button looks around,
+/// Shift/Ctrl speed up/slow down and the scroll wheel adjusts the base speed.
+#[derive(Debug)]
+pub struct FlyCameraController {
+    enabled: bool,
+    position: Vec3,
+    yaw: f32,
+    pitch: f32,
+    speed: f32,
+    sensitivity: f32,
+}
+
+impl Default for FlyCameraController {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            position: Vec3::ZERO,
+            yaw: 0.0,
+            pitch: 0.0,
+            speed: 3.0,
+            sensitivity: 0.003,
+        }
+    }
+}
+
+impl FlyCameraController {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool, camera: &Camera) {
+        if enabled && !self.enabled {
+            // Continue from wherever the camera currently is
+            let forward = (camera.target - camera.eye).normalize_or(Vec3::Z);
+            self.position = camera.eye;
+            self.yaw = forward.x.atan2(forward.z);
+            self.pitch = forward.y.asin().clamp(-MAX_PITCH, MAX_PITCH);
+        }
+
+        self.enabled = enabled;
+    }
+
+    fn forward(&self) -> Vec3 {
+        Vec3::new(
+            self.yaw.sin() * self.pitch.cos(),
+            self.pitch.sin(),
+            self.yaw.cos() * self.pitch.cos(),
+        )
+    }
+
+    pub fn update(&mut self, input: &InputState, camera: &mut Camera, delta_time: f32) {
+        if input.was_key_pressed(TOGGLE_KEY) {
+            self.set_enabled(!self.enabled, camera);
+        }
+
+        if !self.enabled {
+            return;
+        }
+
+        if input.is_mouse_button_down(MouseButton::Right) {
+            let delta = input.mouse_delta() * self.sensitivity;
+            self.yaw += delta.x;
+            self.pitch = (self.pitch - delta.y).clamp(-MAX_PITCH, MAX_PITCH);
+        }
+
+        self.speed = (self.speed * 1.1f32.powf(input.scroll_delta())).clamp(0.1, 100.0);
+
+        let forward = self.forward();
+        // Left-handed: +X is to the right when looking down +Z
+        let right = Vec3::Y.cross(forward).normalize();
+
+        let mut direction = Vec3::ZERO;
+        let bindings = [
+            (KeyCode::KeyW, forward),
+            (KeyCode::KeyS, -forward),
+            (KeyCode::KeyD, right),
+            (KeyCode::KeyA, -right),
+            (KeyCode::KeyE, Vec3::Y),
+            (KeyCode::KeyQ, -Vec3::Y),
+        ];
+        for (key, axis) in bindings {
+            if input.is_key_down(key) {
+                direction += axis;
+            }
+        }
+
+        let mut speed = self.speed;
+        if input.is_key_down(KeyCode::ShiftLeft) {
+            speed *= 4.0;
+        }
+        if input.is_key_down(KeyCode::ControlLeft) {
+            speed *= 0.25;
+        }
+
+        self.position += direction.normalize_or_zero() * speed * delta_time;
+
+        camera.eye = self.position;
+        camera.target = self.position + forward;
+        camera.up = Vec3::Y;
+    }
+
+    pub fn draw_ui(&mut self, ui: &imgui::Ui) {
+        if !self.enabled {
+            return;
+        }
+
+        ui.window("Fly camera")
+            .size([220.0, 100.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                ui.text(format!(
+                    "Position: {:.2} {:.2} {:.2}",
+                    self.position.x, self.position.y, self.position.z
+                ));
+                ui.slider("Speed", 0.1, 100.0, &mut self.speed);
+                ui.text("F1 to return to the timeline");
+            });
+    }
+}
@@ -0,0 +1,152 @@
+use std::time::{Duration, Instant};
+
+/// Scene simulation runs at this rate regardless of the frame rate, so that it behaves the same
+/// at 60 FPS, 120 FPS or uncapped
+pub const FIXED_TIMESTEP: f32 = 1.0 / 120.0;
+
+/// After a long hitch, the simulation skips ahead instead of trying to catch up
+pub const MAX_STEPS_PER_FRAME: u32 = 8;
+
+/// Accumulated time within this of a full step counts as one, so that frame times which are
+/// multiples of the step don't alternate between too few and too many steps due to rounding
+const STEP_EPSILON: f64 = 1e-6;
+
+/// What the simulation has to do during one frame
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FixedSteps {
+    /// Number of `FIXED_TIMESTEP` steps to simulate
+    pub count: u32,
+    /// How far the frame is between the previous step and the last one, from 0 to 1. Rendered
+    /// state is interpolated by this, so it lags behind by at most one step.
+    pub alpha: f32,
+}
+
+/// Turns variable frame times into a whole number of fixed simulation steps
+#[derive(Debug, Default)]
+pub struct FixedTimestep {
+    /// Time that hasn't been simulated yet, in seconds
+    accumulator: f64,
+}
+
+impl FixedTimestep {
+    pub fn advance(&mut self, delta_time: f32) -> FixedSteps {
+        let step = FIXED_TIMESTEP as f64;
+        self.accumulator += delta_time.max(0.0) as f64;
+
+        let mut count = 0;
+        while self.accumulator + STEP_EPSILON >= step {
+            self.accumulator = (self.accumulator - step).max(0.0);
+            count += 1;
+        }
+
+        if count > MAX_STEPS_PER_FRAME {
+            log::warn!(
+                "Simulation fell behind by {} steps, skipping ahead",
+                count - MAX_STEPS_PER_FRAME
+            );
+            count = MAX_STEPS_PER_FRAME;
+        }
+
+        FixedSteps {
+            count,
+            alpha: (self.accumulator / step) as f32,
+        }
+    }
+}
+
+/// Sleeps between frames to stay under a maximum frame rate. VSync limits the frame rate
+/// already, so this is mostly useful with the immediate present mode.
+#[derive(Debug, Default)]
+pub struct FrameLimiter {
+    next_frame: Option<Instant>,
+}
+
+impl FrameLimiter {
+    /// Waits until the next frame may start. Does nothing if `max_fps` is not set.
+    pub fn wait(&mut self, max_fps: Option<u32>) {
+        let Some(max_fps) = max_fps.filter(|fps| *fps > 0) else {
+            self.next_frame = None;
+            return;
+        };
+
+        let frame_duration = Duration::from_secs_f64(1.0 / max_fps as f64);
+        let now = Instant::now();
+
+        // Frames are scheduled from the previous deadline so that the rate doesn't drift, unless
+        // the frame was late, in which case the limiter doesn't try to make up for it
+        let frame_start = match self.next_frame {
+            Some(next_frame) if next_frame > now => {
+                std::thread::sleep(next_frame - now);
+                next_frame
+            }
+            _ => now,
+        };
+
+        self.next_frame = Some(frame_start + frame_duration);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multiples_of_the_step_take_the_same_number_of_steps_every_frame() {
+        let mut timestep = FixedTimestep::default();
+        for _ in 0..1000 {
+            let steps = timestep.advance(FIXED_TIMESTEP * 2.0);
+            assert_eq!(steps.count, 2);
+            assert!(steps.alpha < 1e-3, "alpha {}", steps.alpha);
+        }
+    }
+
+    #[test]
+    fn partial_steps_carry_over() {
+        let mut timestep = FixedTimestep::default();
+        let steps = timestep.advance(FIXED_TIMESTEP * 1.5);
+        assert_eq!(steps.count, 1);
+        assert!((steps.alpha - 0.5).abs() < 1e-3, "alpha {}", steps.alpha);
+
+        let steps = timestep.advance(FIXED_TIMESTEP * 0.5);
+        assert_eq!(steps.count, 1);
+        assert!(steps.alpha < 1e-3, "alpha {}", steps.alpha);
+    }
+
+    #[test]
+    fn long_frames_are_capped_and_skipped_steps_are_dropped() {
+        let mut timestep = FixedTimestep::default();
+        let steps = timestep.advance(FIXED_TIMESTEP * 100.25);
+        assert_eq!(steps.count, MAX_STEPS_PER_FRAME);
+        // Only the partial step is kept, not the skipped ones
+        assert!((steps.alpha - 0.25).abs() < 1e-3, "alpha {}", steps.alpha);
+
+        let steps = timestep.advance(0.0);
+        assert_eq!(steps.count, 0);
+        assert!((steps.alpha - 0.25).abs() < 1e-3, "alpha {}", steps.alpha);
+    }
+
+    #[test]
+    fn alpha_stays_below_one() {
+        let mut timestep = FixedTimestep::default();
+        for frame in 0..10_000 {
+            // Frame times around the step, including ones just short of a multiple of it
+            let delta_time = FIXED_TIMESTEP * (0.1 + (frame % 37) as f32 * 0.1) - 1e-7;
+            let steps = timestep.advance(delta_time);
+            assert!(
+                (0.0..1.0).contains(&steps.alpha),
+                "alpha {} on frame {}",
+                steps.alpha,
+                frame
+            );
+        }
+    }
+
+    #[test]
+    fn negative_frame_times_dont_rewind() {
+        let mut timestep = FixedTimestep::default();
+        timestep.advance(FIXED_TIMESTEP * 0.5);
+        let steps = timestep.advance(-1.0);
+        assert_eq!(steps.count, 0);
+        assert!((steps.alpha - 0.5).abs() < 1e-3, "alpha {}", steps.alpha);
+    }
+}
@@ -0,0 +1,344 @@
+use glam::{Mat4, Quat, Vec2, Vec3};
+use winit::{event::MouseButton, keyboard::KeyCode};
+
+use crate::{
+    camera::Camera,
+    input::InputState,
+    math::ray::Ray,
+    scene_graph::{
+        object3d::ObjectId,
+        raycast::{raycast, screen_ray, RaycastPrecision},
+        scene::Scene,
+    },
+};
+
+/// Size of the gizmo relative to its distance from the camera, so that it stays roughly the same
+/// size on screen
+const GIZMO_SCALE: f32 = 0.15;
+/// How close to a handle the cursor has to be, relative to the size of the gizmo
+const HANDLE_PICK_RADIUS: f32 = 0.08;
+const ROTATION_CIRCLE_SEGMENTS: usize = 48;
+
+const AXES: [Vec3; 3] = [Vec3::X, Vec3::Y, Vec3::Z];
+const AXIS_COLORS: [[f32; 4]; 3] = [
+    [0.9, 0.2, 0.2, 1.0],
+    [0.2, 0.9, 0.2, 1.0],
+    [0.2, 0.4, 1.0, 1.0],
+];
+const ACTIVE_COLOR: [f32; 4] = [1.0, 0.9, 0.2, 1.0];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GizmoMode {
+    Translate,
+    Rotate,
+    /// The handles are world axes, so they all scale every axis of the object by the same
+    /// factor
+    Scale,
+}
+
+#[derive(Debug)]
+struct Drag {
+    axis: usize,
+    /// Position along the axis for translation and scaling, angle around it for rotation
+    start_parameter: f32,
+    start_origin: Vec3,
+    start_rotation: Quat,
+    start_scale: Vec3,
+}
+
+/// Viewport gizmos for moving, rotating and scaling objects with the mouse. Left click selects
+/// the object under the cursor and dragging a handle transforms it along a world axis.
+#[derive(Debug)]
+pub struct TransformGizmo {
+    mode: GizmoMode,
+    selected: Option<ObjectId>,
+    drag: Option<Drag>,
+}
+
+impl Default for TransformGizmo {
+    fn default() -> Self {
+        Self {
+            mode: GizmoMode::Translate,
+            selected: None,
+            drag: None,
+        }
+    }
+}
+
+impl TransformGizmo {
+    pub fn update(
+        &mut self,
+        input: &InputState,
+        scene: &mut Scene,
+        camera: &Camera,
+        ui: &imgui::Ui,
+    ) {
+        if input.was_key_pressed(KeyCode::Digit1) {
+            self.mode = GizmoMode::Translate;
+        } else if input.was_key_pressed(KeyCode::Digit2) {
+            self.mode = GizmoMode::Rotate;
+        } else if input.was_key_pressed(KeyCode::Digit3) {
+            self.mode = GizmoMode::Scale;
+        } else if input.was_key_pressed(KeyCode::Escape) {
+            self.selected = None;
+        }
+
+        if !input.is_mouse_button_down(MouseButton::Left) {
+            self.drag = None;
+        }
+
+        let screen_size = Vec2::from(ui.io().display_size);
+        let view_proj = camera.get_vp_matrix(screen_size);
+        let ray = screen_ray(camera, Vec2::from(ui.io().mouse_pos), screen_size);
+
+        // The object might have been removed since it was selected
+        let selected = self.selected.filter(|id| scene.get_object(*id).is_some());
+        self.selected = selected;
+
+        let Some(selected) = selected else {
+            if input.was_mouse_button_pressed(MouseButton::Left) {
+                self.selected = pick_object(scene, &ray);
+            }
+            return;
+        };
+
+        let world_matrix = *scene
+            .get_object(selected)
+            .unwrap()
+            .transform
+            .get_world_matrix();
+        let origin = world_matrix.w_axis.truncate();
+        let size = camera.eye.distance(origin) * GIZMO_SCALE;
+
+        let hovered_axis = match &self.drag {
+            Some(drag) => Some(drag.axis),
+            None => self.hovered_axis(&ray, origin, size),
+        };
+
+        if let Some(drag) = &self.drag {
+            self.apply_drag(drag, scene, selected, &ray, size);
+        } else if input.was_mouse_button_pressed(MouseButton::Left) {
+            match hovered_axis {
+                Some(axis) => self.start_drag(scene, selected, axis, &ray, origin),
+                None => self.selected = pick_object(scene, &ray),
+            }
+        }
+
+        self.draw(ui, view_proj, screen_size, origin, size, hovered_axis);
+    }
+
+    pub fn draw_ui(&mut self, ui: &imgui::Ui, scene: &Scene) {
+        ui.window("Gizmo")
+            .size([220.0, 130.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                let selected = self.selected.and_then(|id| scene.get_object(id));
+                match selected {
+                    Some(object) => ui.text(format!("Selected: {}", object.name())),
+                    None => ui.text("Click an object to select it"),
+                }
+
+                ui.radio_button("Translate (1)", &mut self.mode, GizmoMode::Translate);
+                ui.radio_button("Rotate (2)", &mut self.mode, GizmoMode::Rotate);
+                ui.radio_button("Scale (3)", &mut self.mode, GizmoMode::Scale);
+
+                if ui.button("Deselect (Esc)") {
+                    self.selected = None;
+                }
+            });
+    }
+
+    fn hovered_axis(&self, ray: &Ray, origin: Vec3, size: f32) -> Option<usize> {
+        let pick_radius = size * HANDLE_PICK_RADIUS;
+
+        let distances = AXES.iter().enumerate().filter_map(|(i, axis)| {
+            let distance = match self.mode {
+                GizmoMode::Translate | GizmoMode::Scale => {
+                    let (t, distance) = ray.closest_point_on_line(origin, *axis)?;
+                    if !(0.0..=size).contains(&t) {
+                        return None;
+                    }
+                    distance
+                }
+                GizmoMode::Rotate => {
+                    let t = ray.intersect_plane(origin, *axis)?;
+                    (ray.at(t).distance(origin) - size).abs()
+                }
+            };
+
+            (distance < pick_radius).then_some((i, distance))
+        });
+
+        distances
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(i, _)| i)
+    }
+
+    /// Position along the axis or angle around it, depending on the mode
+    fn drag_parameter(&self, ray: &Ray, origin: Vec3, axis: usize) -> Option<f32> {
+        match self.mode {
+            GizmoMode::Translate | GizmoMode::Scale => ray
+                .closest_point_on_line(origin, AXES[axis])
+                .map(|(t, _)| t),
+            GizmoMode::Rotate => {
+                let t = ray.intersect_plane(origin, AXES[axis])?;
+                let offset = ray.at(t) - origin;
+                // Measured from the next axis towards the one after it, which matches the
+                // direction of a positive rotation around the axis
+                let u = AXES[(axis + 1) % 3];
+                let w = AXES[(axis + 2) % 3];
+                Some(offset.dot(w).atan2(offset.dot(u)))
+            }
+        }
+    }
+
+    fn start_drag(
+        &mut self,
+        scene: &Scene,
+        selected: ObjectId,
+        axis: usize,
+        ray: &Ray,
+        origin: Vec3,
+    ) {
+        let Some(start_parameter) = self.drag_parameter(ray, origin, axis) else {
+            return;
+        };
+
+        let transform = &scene.get_object(selected).unwrap().transform;
+
+        self.drag = Some(Drag {
+            axis,
+            start_parameter,
+            start_origin: origin,
+            start_rotation: transform.rotation(),
+            start_scale: transform.scale(),
+        });
+    }
+
+    fn apply_drag(&self, drag: &Drag, scene: &mut Scene, selected: ObjectId, ray: &Ray, size: f32) {
+        let Some(parameter) = self.drag_parameter(ray, drag.start_origin, drag.axis) else {
+            return;
+        };
+
+        let object = scene.get_object(selected).unwrap();
+        let transform = &object.transform;
+        let mut translation = transform.translation();
+        let mut rotation = transform.rotation();
+        let mut scale = transform.scale();
+
+        // Handles are in world space, but the transform is relative to the parent
+        let parent_matrix = object
+            .parent_id
+            .and_then(|id| scene.get_object(id))
+            .map(|parent| *parent.transform.get_world_matrix())
+            .unwrap_or(Mat4::IDENTITY);
+        let (_, parent_rotation, _) = parent_matrix.to_scale_rotation_translation();
+
+        let axis = AXES[drag.axis];
+
+        match self.mode {
+            GizmoMode::Translate => {
+                let world_origin = drag.start_origin + axis * (parameter - drag.start_parameter);
+                translation = parent_matrix.inverse().transform_point3(world_origin);
+            }
+            GizmoMode::Rotate => {
+                let delta = Quat::from_axis_angle(axis, parameter - drag.start_parameter);
+                rotation =
+                    (parent_rotation.inverse() * delta * parent_rotation * drag.start_rotation)
+                        .normalize();
+            }
+            GizmoMode::Scale => {
+                // Dragging the handle by its own length doubles the scale
+                let factor = 1.0 + (parameter - drag.start_parameter) / size;
+                scale = (drag.start_scale * factor).max(Vec3::splat(0.001));
+            }
+        }
+
+        scene.set_object_transform(selected, translation, rotation, scale);
+    }
+
+    fn draw(
+        &self,
+        ui: &imgui::Ui,
+        view_proj: Mat4,
+        screen_size: Vec2,
+        origin: Vec3,
+        size: f32,
+        hovered_axis: Option<usize>,
+    ) {
+        let to_screen = |point: Vec3| -> Option<[f32; 2]> {
+            let clip = view_proj * point.extend(1.0);
+            if clip.w <= 0.0 {
+                return None;
+            }
+
+            let ndc = clip.truncate() / clip.w;
+            Some([
+                (ndc.x + 1.0) * 0.5 * screen_size.x,
+                (1.0 - ndc.y) * 0.5 * screen_size.y,
+            ])
+        };
+
+        let draw_list = ui.get_background_draw_list();
+
+        for (i, axis) in AXES.iter().enumerate() {
+            let color = if hovered_axis == Some(i) {
+                ACTIVE_COLOR
+            } else {
+                AXIS_COLORS[i]
+            };
+
+            match self.mode {
+                GizmoMode::Translate | GizmoMode::Scale => {
+                    let (Some(start), Some(end)) =
+                        (to_screen(origin), to_screen(origin + *axis * size))
+                    else {
+                        continue;
+                    };
+
+                    draw_list.add_line(start, end, color).thickness(3.0).build();
+
+                    if self.mode == GizmoMode::Translate {
+                        draw_list.add_circle(end, 6.0, color).filled(true).build();
+                    } else {
+                        draw_list
+                            .add_rect(
+                                [end[0] - 5.0, end[1] - 5.0],
+                                [end[0] + 5.0, end[1] + 5.0],
+                                color,
+                            )
+                            .filled(true)
+                            .build();
+                    }
+                }
+                GizmoMode::Rotate => {
+                    let u = AXES[(i + 1) % 3];
+                    let w = AXES[(i + 2) % 3];
+
+                    let points = (0..=ROTATION_CIRCLE_SEGMENTS)
+                        .map(|segment| {
+                            let angle = segment as f32 / ROTATION_CIRCLE_SEGMENTS as f32
+                                * std::f32::consts::TAU;
+                            origin + (u * angle.cos() + w * angle.sin()) * size
+                        })
+                        .map(to_screen)
+                        .collect::<Option<Vec<_>>>();
+
+                    if let Some(points) = points {
+                        draw_list.add_polyline(points, color).thickness(2.0).build();
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Returns the root of the hierarchy of the closest object under the cursor, so that clicking
+/// on any part of a glTF scene selects all of it
+fn pick_object(scene: &Scene, ray: &Ray) -> Option<ObjectId> {
+    let mut id = raycast(scene, ray, RaycastPrecision::Triangles)?.object_id;
+    while let Some(parent_id) = scene.get_object(id).and_then(|object| object.parent_id) {
+        id = parent_id;
+    }
+
+    Some(id)
+}
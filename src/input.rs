@@ -0,0 +1,109 @@
+use std::collections::HashSet;
+
+use glam::Vec2;
+use winit::{
+    event::{DeviceEvent, ElementState, MouseButton, MouseScrollDelta, WindowEvent},
+    keyboard::{KeyCode, PhysicalKey},
+};
+
+/// Keyboard and mouse state collected from winit events. Per-frame state (presses, mouse
+/// motion, scrolling) is accumulated between frames and cleared by `end_frame`.
+#[derive(Debug, Default)]
+pub struct InputState {
+    keys_down: HashSet<KeyCode>,
+    keys_pressed: HashSet<KeyCode>,
+    mouse_buttons_down: HashSet<MouseButton>,
+    mouse_buttons_pressed: HashSet<MouseButton>,
+    mouse_delta: Vec2,
+    scroll_delta: f32,
+}
+
+impl InputState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn handle_window_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::KeyboardInput { event, .. } => {
+                let PhysicalKey::Code(code) = event.physical_key else {
+                    return;
+                };
+
+                match event.state {
+                    ElementState::Pressed => {
+                        if !event.repeat {
+                            self.keys_pressed.insert(code);
+                        }
+                        self.keys_down.insert(code);
+                    }
+                    ElementState::Released => {
+                        self.keys_down.remove(&code);
+                    }
+                }
+            }
+            WindowEvent::MouseInput { state, button, .. } => match state {
+                ElementState::Pressed => {
+                    self.mouse_buttons_pressed.insert(*button);
+                    self.mouse_buttons_down.insert(*button);
+                }
+                ElementState::Released => {
+                    self.mouse_buttons_down.remove(button);
+                }
+            },
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.scroll_delta += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    // Roughly one line per 20 pixels
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 20.0,
+                };
+            }
+            WindowEvent::Focused(false) => {
+                // Releases are not delivered to unfocused windows
+                self.keys_down.clear();
+                self.mouse_buttons_down.clear();
+            }
+            _ => (),
+        }
+    }
+
+    /// Raw mouse motion is used for looking around, because it isn't limited by the window edges
+    pub fn handle_device_event(&mut self, event: &DeviceEvent) {
+        if let DeviceEvent::MouseMotion { delta: (x, y) } = event {
+            self.mouse_delta += Vec2::new(*x as f32, *y as f32);
+        }
+    }
+
+    pub fn end_frame(&mut self) {
+        self.keys_pressed.clear();
+        self.mouse_buttons_pressed.clear();
+        self.mouse_delta = Vec2::ZERO;
+        self.scroll_delta = 0.0;
+    }
+
+    pub fn is_key_down(&self, key: KeyCode) -> bool {
+        self.keys_down.contains(&key)
+    }
+
+    /// True only on the frame the key was pressed
+    pub fn was_key_pressed(&self, key: KeyCode) -> bool {
+        self.keys_pressed.contains(&key)
+    }
+
+    pub fn is_mouse_button_down(&self, button: MouseButton) -> bool {
+        self.mouse_buttons_down.contains(&button)
+    }
+
+    /// True only on the frame the button was pressed
+    pub fn was_mouse_button_pressed(&self, button: MouseButton) -> bool {
+        self.mouse_buttons_pressed.contains(&button)
+    }
+
+    pub fn mouse_delta(&self) -> Vec2 {
+        self.mouse_delta
+    }
+
+    pub fn scroll_delta(&self) -> f32 {
+        self.scroll_delta
+    }
+}
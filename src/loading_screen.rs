@@ -0,0 +1,146 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use winit::{dpi::PhysicalSize, window::Window};
+
+use crate::{
+    asset_loader::LoadingProgress,
+    rendering::{
+        adapter::request_adapter,
+        imgui_renderer::{create_imgui_renderer, ImguiRendererState},
+    },
+};
+
+/// Minimal renderer that only draws a progress bar with imgui. It owns its own device and
+/// surface, because the real renderer can't be created until the assets have been loaded.
+pub struct LoadingScreen {
+    surface: wgpu::Surface<'static>,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    surface_config: wgpu::SurfaceConfiguration,
+    imgui: ImguiRendererState,
+}
+
+impl LoadingScreen {
+    pub async fn new(
+        window: Arc<Window>,
+        imgui_context: &mut imgui::Context,
+        adapter_name: Option<&str>,
+    ) -> anyhow::Result<Self> {
+        let size = window.inner_size();
+
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+        let surface = instance
+            .create_surface(window)
+            .context("Failed to create loading screen surface")?;
+
+        // The same adapter that the renderer will use
+        let adapter = request_adapter(&instance, Some(&surface), adapter_name)
+            .await
+            .context("Failed to find an adapter for the loading screen")?;
+
+        let (device, queue) = adapter
+            .request_device(&wgpu::DeviceDescriptor {
+                label: Some("Loading screen device"),
+                ..Default::default()
+            })
+            .await
+            .context("Failed to create loading screen device")?;
+
+        let surface_config = surface
+            .get_default_config(&adapter, size.width.max(1), size.height.max(1))
+            .context("Surface is not supported by the adapter")?;
+        surface.configure(&device, &surface_config);
+
+        let imgui = create_imgui_renderer(&device, &queue, surface_config.format, imgui_context);
+
+        Ok(Self {
+            surface,
+            device,
+            queue,
+            surface_config,
+            imgui,
+        })
+    }
+
+    pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
+        if new_size.width > 0 && new_size.height > 0 {
+            self.surface_config.width = new_size.width;
+            self.surface_config.height = new_size.height;
+            self.surface.configure(&self.device, &self.surface_config);
+        }
+    }
+
+    pub fn draw_ui(ui: &imgui::Ui, progress: &LoadingProgress) {
+        let display_size = ui.io().display_size;
+        let size = [display_size[0] * 0.5, 60.0];
+
+        ui.window("Loading")
+            .position(
+                [display_size[0] * 0.5, display_size[1] * 0.5],
+                imgui::Condition::Always,
+            )
+            .position_pivot([0.5, 0.5])
+            .size(size, imgui::Condition::Always)
+            .no_decoration()
+            .no_inputs()
+            .build(|| {
+                ui.text(progress.current_step());
+                imgui::ProgressBar::new(progress.fraction())
+                    .size([-1.0, 0.0])
+                    .build(ui);
+            });
+    }
+
+    /// Renders the imgui frame on a black background
+    pub fn render(&mut self, imgui_context: &mut imgui::Context) {
+        let output = match self.surface.get_current_texture() {
+            Ok(output) => output,
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                self.surface.configure(&self.device, &self.surface_config);
+                return;
+            }
+            Err(e) => {
+                log::warn!("Failed to get loading screen surface texture: {:?}", e);
+                return;
+            }
+        };
+
+        let view = output
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Loading screen encoder"),
+            });
+
+        // Clear, then draw imgui on top
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Loading screen clear pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        self.imgui.render(
+            &view,
+            imgui_context,
+            &self.device,
+            &self.queue,
+            &mut encoder,
+        );
+
+        self.queue.submit([encoder.finish()]);
+        output.present();
+    }
+}
@@ -1,20 +1,62 @@
 use anyhow::Result;
+use clap::Parser;
 
+mod ao_bake;
+mod asset_loader;
 mod asset_pipeline;
+mod audio;
 mod camera;
 mod demo;
+mod demo_clock;
+mod demo_part;
+mod demo_script;
 mod engine;
+mod events;
+mod fly_camera;
+mod frame_pacing;
+mod gizmo;
+mod input;
+mod loading_screen;
 mod material_manager;
 mod math;
 mod model;
+mod offline;
+mod options;
+mod orbit_camera;
+mod path_follower;
+mod procgen;
 mod rendering;
 mod scene_graph;
+mod settings;
+mod text;
+mod timeline;
 mod window;
 
 fn main() -> Result<()> {
     pretty_env_logger::init();
 
-    pollster::block_on(window::run())?;
+    let options = options::Options::parse();
+    let settings = settings::Settings::load_or_default(settings::SETTINGS_PATH);
+    let renderer_options = options.renderer(&settings);
+
+    if options.bake_ao() {
+        pollster::block_on(ao_bake::run(renderer_options))?;
+        return Ok(());
+    }
+
+    match options.offline() {
+        Some(render_settings) => pollster::block_on(offline::run(
+            render_settings,
+            options.demo(),
+            renderer_options,
+        ))?,
+        None => pollster::block_on(window::run(
+            options.display(),
+            options.demo(),
+            renderer_options,
+            settings,
+        ))?,
+    }
 
     Ok(())
 }
@@ -1,8 +1,15 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, path::Path};
 
+use glam::Vec3;
 use id_arena::{Arena, Id};
 
-use crate::asset_pipeline::materials::PbrMaterialData;
+use crate::asset_pipeline::{
+    materials::{
+        ColorSpace, CompressedTextureData, DynamicMaterialTextures, MaterialOverrideFile,
+        MaterialOverrides, MaterialParameters, MaterialTexture, PbrMaterialData, TextureSampler,
+    },
+    texture_atlas::{self, AtlasPage},
+};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct GltfMaterialKey {
@@ -13,6 +20,7 @@ pub struct GltfMaterialKey {
 pub struct MaterialManager {
     materials: Arena<PbrMaterialData>,
     materials_by_gltf: HashMap<GltfMaterialKey, Id<PbrMaterialData>>,
+    atlas_pages: Vec<AtlasPage>,
 }
 
 pub type MaterialId = Id<PbrMaterialData>;
@@ -22,6 +30,7 @@ impl MaterialManager {
         Self {
             materials: Arena::new(),
             materials_by_gltf: HashMap::new(),
+            atlas_pages: Vec::new(),
         }
     }
 
@@ -42,11 +51,16 @@ impl MaterialManager {
         self.materials_by_gltf.get(&key).cloned()
     }
 
+    /// Precompressed KTX2 versions of textures are picked up from `base_path` when they exist
+    /// next to the original image, e.g. `base_color.ktx2` for `base_color.png`.
+    /// Material parameters from `overrides` take precedence over the ones in glTF extras.
     pub fn load_all_materials_from_gltf(
         &mut self,
         file_name: &str,
+        base_path: &Path,
         document: &gltf::Document,
         images: &mut [gltf::image::Data],
+        overrides: &MaterialOverrideFile,
     ) {
         for material in document.materials() {
             let material_name = material.name().unwrap_or("Unnamed material");
@@ -62,9 +76,11 @@ impl MaterialManager {
 
             let base_color = material.pbr_metallic_roughness().base_color_texture();
             let normal = material.normal_texture();
+            let normal_strength = normal.as_ref().map_or(1.0, |normal| normal.scale());
             // The GLTF spec defines separate occlusion and metallic roughness textures,
             // but Substance packs all three into a single occlusionRoughnessMetallic texture.
             let ao_roughness_metallic = material.occlusion_texture();
+            let emissive = material.emissive_texture();
 
             // Remove textures when found and replace with default using swap
             let default_texture = gltf::image::Data {
@@ -84,7 +100,12 @@ impl MaterialManager {
                         .expect("GLTF texture index out of bounds: baseColor"),
                 );
                 texture = convert_image_data_to_rgba(texture);
-                texture
+                load_material_texture(
+                    base_path,
+                    &texture_info.texture(),
+                    texture,
+                    ColorSpace::Srgb,
+                )
             });
 
             let normal = normal.map(|texture_info| {
@@ -97,7 +118,12 @@ impl MaterialManager {
                         .expect("GLTF texture index out of bounds: normal"),
                 );
                 texture = convert_image_data_to_rgba(texture);
-                texture
+                load_material_texture(
+                    base_path,
+                    &texture_info.texture(),
+                    texture,
+                    ColorSpace::Linear,
+                )
             });
 
             let ao_roughness_metallic = ao_roughness_metallic.map(|texture_info| {
@@ -110,25 +136,106 @@ impl MaterialManager {
                         .expect("GLTF texture index out of bounds: occlusionRoughnessMetallic"),
                 );
                 texture = convert_image_data_to_rgba(texture);
-                texture
+                load_material_texture(
+                    base_path,
+                    &texture_info.texture(),
+                    texture,
+                    ColorSpace::Linear,
+                )
             });
 
-            let material_data = PbrMaterialData {
+            let emissive = emissive.map(|texture_info| {
+                let texture_index = texture_info.texture().index();
+                let mut texture = default_texture.clone();
+                std::mem::swap(
+                    &mut texture,
+                    images
+                        .get_mut(texture_index)
+                        .expect("GLTF texture index out of bounds: emissive"),
+                );
+                texture = convert_image_data_to_rgba(texture);
+                load_material_texture(
+                    base_path,
+                    &texture_info.texture(),
+                    texture,
+                    ColorSpace::Srgb,
+                )
+            });
+
+            let emissive_factor = Vec3::from(material.emissive_factor())
+                * material.emissive_strength().unwrap_or(1.0);
+
+            let mut parameters = MaterialParameters {
+                normal_strength,
+                ..Default::default()
+            };
+
+            let mut dynamic_textures = DynamicMaterialTextures::default();
+            let mut color_spaces = Vec::new();
+
+            match MaterialOverrides::from_gltf_extras(material.extras()) {
+                Ok(extras) => {
+                    extras.apply(&mut parameters);
+                    extras.apply_dynamic_textures(&mut dynamic_textures);
+                    color_spaces.push(extras.color_spaces);
+                }
+                Err(e) => log::warn!("Material {}: {:?}", material_name, e),
+            }
+
+            if let Some(material_overrides) = overrides.get(material_name) {
+                material_overrides.apply(&mut parameters);
+                material_overrides.apply_dynamic_textures(&mut dynamic_textures);
+                color_spaces.push(material_overrides.color_spaces);
+            }
+
+            let mut material_data = PbrMaterialData {
                 name: material_name.to_string(),
                 base_color,
                 normal,
                 ao_roughness_metallic,
+                emissive,
+                emissive_factor,
+                alpha_mode: material.alpha_mode().into(),
+                // The glTF spec defines 0.5 as the default cutoff
+                alpha_cutoff: material.alpha_cutoff().unwrap_or(0.5),
+                parameters,
+                dynamic_textures,
+                terrain_layers: Vec::new(),
+                atlas: None,
             };
 
+            // The override file is applied last, so it wins over the extras
+            for color_spaces in color_spaces {
+                color_spaces.apply(&mut material_data);
+            }
+
             let id = self.add_material(material_data);
             self.materials_by_gltf.insert(key, id);
         }
     }
 
+    pub fn get_material(&self, id: MaterialId) -> Option<&PbrMaterialData> {
+        self.materials.get(id)
+    }
+
     pub fn materials(&self) -> impl Iterator<Item = &PbrMaterialData> {
         self.materials.iter().map(|(_, material)| material)
     }
 
+    /// Moves the textures of materials with only small textures to shared atlas pages. Should be
+    /// called once all materials have been added.
+    pub fn pack_atlases(&mut self) {
+        let pages = texture_atlas::pack_atlases(
+            self.materials.iter_mut().map(|(_, material)| material),
+            self.atlas_pages.len(),
+        );
+        self.atlas_pages.extend(pages);
+    }
+
+    pub fn atlas_pages(&self) -> &[AtlasPage] {
+        &self.atlas_pages
+    }
+
     pub fn draw_ui(&self, ui: &imgui::Ui) {
         ui.window("Material manager").build(|| {
             ui.text("Materials:");
@@ -141,6 +248,42 @@ impl MaterialManager {
     }
 }
 
+fn load_material_texture(
+    base_path: &Path,
+    texture: &gltf::Texture,
+    rgba: gltf::image::Data,
+    color_space: ColorSpace,
+) -> MaterialTexture {
+    let compressed = match texture.source().source() {
+        gltf::image::Source::Uri { uri, .. } => {
+            let ktx2_path = base_path.join(uri).with_extension("ktx2");
+
+            if ktx2_path.exists() {
+                CompressedTextureData::load_ktx2(&ktx2_path)
+                    .inspect_err(|e| {
+                        log::warn!(
+                            "Failed to load compressed texture {}: {:?}",
+                            ktx2_path.display(),
+                            e
+                        )
+                    })
+                    .ok()
+            } else {
+                None
+            }
+        }
+        // Embedded images have no file to put a compressed version next to
+        gltf::image::Source::View { .. } => None,
+    };
+
+    MaterialTexture {
+        rgba,
+        compressed,
+        sampler: TextureSampler::from_gltf(&texture.sampler()),
+        color_space,
+    }
+}
+
 fn convert_image_data_to_rgba(data: gltf::image::Data) -> gltf::image::Data {
     if data.format == gltf::image::Format::R8G8B8A8 {
         return data;
@@ -1,4 +1,4 @@
-use glam::Vec3;
+use glam::{BVec3, Mat4, Vec3};
 
 #[derive(Debug, Clone, Copy)]
 pub struct AABB {
@@ -12,4 +12,166 @@ impl AABB {
         let max = point1.max(point2);
         AABB { min, max }
     }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    /// In the same order as in frustum_culling.wgsl: x changes fastest, then y, then z
+    pub fn corners(&self) -> [Vec3; 8] {
+        std::array::from_fn(|index| {
+            let is_max = BVec3::new(index & 1 != 0, index & 2 != 0, index & 4 != 0);
+            Vec3::select(is_max, self.max, self.min)
+        })
+    }
+
+    pub fn union(&self, other: &AABB) -> AABB {
+        AABB {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    pub fn intersects(&self, other: &AABB) -> bool {
+        self.min.cmple(other.max).all() && other.min.cmple(self.max).all()
+    }
+
+    /// Smallest box containing this box transformed by `matrix`. Projects the half extents on
+    /// the axes of the matrix instead of transforming all eight corners.
+    pub fn transformed(&self, matrix: Mat4) -> AABB {
+        let center = matrix.transform_point3(self.center());
+        let half_extents = (self.max - self.min) * 0.5;
+        let extents = matrix.x_axis.truncate().abs() * half_extents.x
+            + matrix.y_axis.truncate().abs() * half_extents.y
+            + matrix.z_axis.truncate().abs() * half_extents.z;
+
+        AABB {
+            min: center - extents,
+            max: center + extents,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BoundingSphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    /// Centered on the bounding box of the points, which is close to the smallest sphere for
+    /// most meshes and much cheaper to find
+    pub fn from_points(bounding_box: &AABB, points: impl IntoIterator<Item = Vec3>) -> Self {
+        let center = (bounding_box.min + bounding_box.max) * 0.5;
+        let radius = points
+            .into_iter()
+            .map(|point| point.distance_squared(center))
+            .fold(0.0, f32::max)
+            .sqrt();
+
+        BoundingSphere { center, radius }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Mat4, Quat, Vec3};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::AABB;
+
+    const EPSILON: f32 = 1e-3;
+
+    fn random_vec3(rng: &mut StdRng, range: std::ops::Range<f32>) -> Vec3 {
+        Vec3::new(
+            rng.gen_range(range.clone()),
+            rng.gen_range(range.clone()),
+            rng.gen_range(range),
+        )
+    }
+
+    fn random_aabb(rng: &mut StdRng) -> AABB {
+        AABB::new(random_vec3(rng, -5.0..5.0), random_vec3(rng, -5.0..5.0))
+    }
+
+    /// Translation, rotation and scale, including negative scale which mirrors the box
+    fn random_transform(rng: &mut StdRng) -> Mat4 {
+        let axis = random_vec3(rng, -1.0..1.0).normalize_or(Vec3::Y);
+        let rotation = Quat::from_axis_angle(axis, rng.gen_range(-3.2..3.2));
+        let mut scale = random_vec3(rng, 0.1..4.0);
+        if rng.gen_bool(0.25) {
+            scale.x = -scale.x;
+        }
+
+        Mat4::from_scale_rotation_translation(scale, rotation, random_vec3(rng, -20.0..20.0))
+    }
+
+    fn contains(aabb: &AABB, point: Vec3) -> bool {
+        point.cmpge(aabb.min - EPSILON).all() && point.cmple(aabb.max + EPSILON).all()
+    }
+
+    #[test]
+    fn corners_span_the_box() {
+        let aabb = AABB::new(Vec3::new(-1.0, -2.0, -3.0), Vec3::new(1.0, 2.0, 3.0));
+        let corners = aabb.corners();
+
+        assert_eq!(corners[0], aabb.min);
+        assert_eq!(corners[7], aabb.max);
+        assert_eq!(corners[1], Vec3::new(1.0, -2.0, -3.0));
+        assert_eq!(corners[2], Vec3::new(-1.0, 2.0, -3.0));
+        assert_eq!(corners[4], Vec3::new(-1.0, -2.0, 3.0));
+    }
+
+    #[test]
+    fn transformed_contains_transformed_corners() {
+        let mut rng = StdRng::seed_from_u64(1);
+
+        for _ in 0..1000 {
+            let aabb = random_aabb(&mut rng);
+            let transform = random_transform(&mut rng);
+            let transformed = aabb.transformed(transform);
+
+            for corner in aabb.corners() {
+                let corner = transform.transform_point3(corner);
+                assert!(
+                    contains(&transformed, corner),
+                    "{:?} {:?}",
+                    transformed,
+                    corner
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn transformed_is_tight() {
+        let mut rng = StdRng::seed_from_u64(2);
+
+        for _ in 0..1000 {
+            let aabb = random_aabb(&mut rng);
+            let transform = random_transform(&mut rng);
+            let transformed = aabb.transformed(transform);
+
+            // Every face of the result touches a transformed corner
+            let corners = aabb
+                .corners()
+                .map(|corner| transform.transform_point3(corner));
+            let min = corners.into_iter().fold(Vec3::INFINITY, Vec3::min);
+            let max = corners.into_iter().fold(Vec3::NEG_INFINITY, Vec3::max);
+            assert!(transformed.min.abs_diff_eq(min, EPSILON));
+            assert!(transformed.max.abs_diff_eq(max, EPSILON));
+        }
+    }
+
+    #[test]
+    fn transformed_by_identity_is_unchanged() {
+        let mut rng = StdRng::seed_from_u64(3);
+
+        for _ in 0..100 {
+            let aabb = random_aabb(&mut rng);
+            let transformed = aabb.transformed(Mat4::IDENTITY);
+            assert!(transformed.min.abs_diff_eq(aabb.min, EPSILON));
+            assert!(transformed.max.abs_diff_eq(aabb.max, EPSILON));
+        }
+    }
 }
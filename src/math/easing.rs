@@ -0,0 +1,221 @@
+use std::f32::consts::PI;
+
+/// Shapes a normalized time from 0 to 1, e.g. when interpolating from a keyframe to the next one.
+/// The Out curves are the In curves played backwards, and the InOut curves play the In curve for
+/// the first half and the Out curve for the second. `shared::easing` has the same curves in WGSL.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Easing {
+    #[default]
+    Linear,
+    /// Holds the value of the keyframe until the next keyframe is reached
+    Step,
+    InSine,
+    OutSine,
+    InOutSine,
+    InQuad,
+    OutQuad,
+    InOutQuad,
+    InCubic,
+    OutCubic,
+    InOutCubic,
+    InQuart,
+    OutQuart,
+    InOutQuart,
+    InQuint,
+    OutQuint,
+    InOutQuint,
+    InExpo,
+    OutExpo,
+    InOutExpo,
+    InCirc,
+    OutCirc,
+    InOutCirc,
+    /// Pulls back a little before moving
+    InBack,
+    /// Overshoots a little before settling
+    OutBack,
+    InOutBack,
+    InElastic,
+    OutElastic,
+    InOutElastic,
+    InBounce,
+    OutBounce,
+    InOutBounce,
+    SmoothStep,
+}
+
+impl Easing {
+    pub const ALL: [Easing; 33] = [
+        Easing::Linear,
+        Easing::Step,
+        Easing::InSine,
+        Easing::OutSine,
+        Easing::InOutSine,
+        Easing::InQuad,
+        Easing::OutQuad,
+        Easing::InOutQuad,
+        Easing::InCubic,
+        Easing::OutCubic,
+        Easing::InOutCubic,
+        Easing::InQuart,
+        Easing::OutQuart,
+        Easing::InOutQuart,
+        Easing::InQuint,
+        Easing::OutQuint,
+        Easing::InOutQuint,
+        Easing::InExpo,
+        Easing::OutExpo,
+        Easing::InOutExpo,
+        Easing::InCirc,
+        Easing::OutCirc,
+        Easing::InOutCirc,
+        Easing::InBack,
+        Easing::OutBack,
+        Easing::InOutBack,
+        Easing::InElastic,
+        Easing::OutElastic,
+        Easing::InOutElastic,
+        Easing::InBounce,
+        Easing::OutBounce,
+        Easing::InOutBounce,
+        Easing::SmoothStep,
+    ];
+
+    /// Name of the variant, e.g. "InOutQuad"
+    pub fn name(self) -> String {
+        format!("{:?}", self)
+    }
+
+    pub fn from_name(name: &str) -> Option<Easing> {
+        Self::ALL.into_iter().find(|easing| easing.name() == name)
+    }
+
+    /// Maps a normalized time value in [0, 1] to an eased interpolation factor. Back and elastic
+    /// curves go outside of [0, 1] on the way.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+
+        match self {
+            Easing::Linear => t,
+            Easing::Step => {
+                if t < 1.0 {
+                    0.0
+                } else {
+                    1.0
+                }
+            }
+            Easing::InSine => in_sine(t),
+            Easing::OutSine => ease_out(in_sine, t),
+            Easing::InOutSine => ease_in_out(in_sine, t),
+            Easing::InQuad => in_quad(t),
+            Easing::OutQuad => ease_out(in_quad, t),
+            Easing::InOutQuad => ease_in_out(in_quad, t),
+            Easing::InCubic => in_cubic(t),
+            Easing::OutCubic => ease_out(in_cubic, t),
+            Easing::InOutCubic => ease_in_out(in_cubic, t),
+            Easing::InQuart => in_quart(t),
+            Easing::OutQuart => ease_out(in_quart, t),
+            Easing::InOutQuart => ease_in_out(in_quart, t),
+            Easing::InQuint => in_quint(t),
+            Easing::OutQuint => ease_out(in_quint, t),
+            Easing::InOutQuint => ease_in_out(in_quint, t),
+            Easing::InExpo => in_expo(t),
+            Easing::OutExpo => ease_out(in_expo, t),
+            Easing::InOutExpo => ease_in_out(in_expo, t),
+            Easing::InCirc => in_circ(t),
+            Easing::OutCirc => ease_out(in_circ, t),
+            Easing::InOutCirc => ease_in_out(in_circ, t),
+            Easing::InBack => in_back(t),
+            Easing::OutBack => ease_out(in_back, t),
+            Easing::InOutBack => ease_in_out(in_back, t),
+            Easing::InElastic => in_elastic(t),
+            Easing::OutElastic => ease_out(in_elastic, t),
+            Easing::InOutElastic => ease_in_out(in_elastic, t),
+            Easing::InBounce => in_bounce(t),
+            Easing::OutBounce => ease_out(in_bounce, t),
+            Easing::InOutBounce => ease_in_out(in_bounce, t),
+            Easing::SmoothStep => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+fn ease_out(ease_in: fn(f32) -> f32, t: f32) -> f32 {
+    1.0 - ease_in(1.0 - t)
+}
+
+fn ease_in_out(ease_in: fn(f32) -> f32, t: f32) -> f32 {
+    if t < 0.5 {
+        ease_in(2.0 * t) / 2.0
+    } else {
+        1.0 - ease_in(2.0 - 2.0 * t) / 2.0
+    }
+}
+
+fn in_sine(t: f32) -> f32 {
+    1.0 - (t * PI / 2.0).cos()
+}
+
+fn in_quad(t: f32) -> f32 {
+    t * t
+}
+
+fn in_cubic(t: f32) -> f32 {
+    t * t * t
+}
+
+fn in_quart(t: f32) -> f32 {
+    t.powi(4)
+}
+
+fn in_quint(t: f32) -> f32 {
+    t.powi(5)
+}
+
+fn in_expo(t: f32) -> f32 {
+    if t <= 0.0 {
+        0.0
+    } else {
+        2f32.powf(10.0 * t - 10.0)
+    }
+}
+
+fn in_circ(t: f32) -> f32 {
+    1.0 - (1.0 - t * t).max(0.0).sqrt()
+}
+
+fn in_back(t: f32) -> f32 {
+    // Undershoots by 10%
+    const OVERSHOOT: f32 = 1.70158;
+    (OVERSHOOT + 1.0) * t * t * t - OVERSHOOT * t * t
+}
+
+fn in_elastic(t: f32) -> f32 {
+    if t <= 0.0 || t >= 1.0 {
+        t
+    } else {
+        -(2f32.powf(10.0 * t - 10.0)) * ((t * 10.0 - 10.75) * (2.0 * PI / 3.0)).sin()
+    }
+}
+
+fn in_bounce(t: f32) -> f32 {
+    1.0 - out_bounce(1.0 - t)
+}
+
+/// Bounces of decreasing height, each a parabola
+fn out_bounce(t: f32) -> f32 {
+    const N: f32 = 7.5625;
+    const D: f32 = 2.75;
+
+    if t < 1.0 / D {
+        N * t * t
+    } else if t < 2.0 / D {
+        let t = t - 1.5 / D;
+        N * t * t + 0.75
+    } else if t < 2.5 / D {
+        let t = t - 2.25 / D;
+        N * t * t + 0.9375
+    } else {
+        let t = t - 2.625 / D;
+        N * t * t + 0.984375
+    }
+}
@@ -1,7 +1,10 @@
 use bytemuck::{Pod, Zeroable};
 use glam::{vec4, Mat4, Vec3, Vec4Swizzles};
 
-use crate::math::plane::Plane;
+use crate::math::{
+    bounds::{BoundingSphere, AABB},
+    plane::Plane,
+};
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone, Pod, Zeroable)]
@@ -40,6 +43,8 @@ impl Frustum {
         })
     }
 
+    /// The planes face outwards for left-handed view projections, which all of the cameras use.
+    /// The near plane is at NDC depth -1 instead of 0, which is conservative for wgpu.
     pub fn from_view_projection(view_projection: Mat4) -> Frustum {
         let corners = Self::corners(view_projection);
         let [left_bottom_near, right_bottom_near, left_top_near, right_top_near, left_bottom_far, right_bottom_far, left_top_far, _right_top_far] =
@@ -62,4 +67,278 @@ impl Frustum {
 
         Frustum { planes }
     }
+
+    /// CPU reference for `is_inside_frustum_transformed` in frustum_culling.wgsl. A plane only
+    /// rejects the box if all of its corners are outside it, so boxes near the edges of the
+    /// frustum can pass without intersecting it.
+    pub fn intersects_aabb(&self, aabb: &AABB, transform: Mat4) -> bool {
+        let corners = aabb
+            .corners()
+            .map(|corner| transform.transform_point3(corner));
+
+        self.planes.iter().all(|plane| {
+            corners
+                .iter()
+                .any(|corner| plane.signed_distance(*corner) <= 0.0)
+        })
+    }
+
+    /// CPU reference for `is_sphere_inside_frustum_transformed` in frustum_culling.wgsl. The
+    /// radius is scaled by the largest axis scale of the transform.
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere, transform: Mat4) -> bool {
+        let center = transform.transform_point3(sphere.center);
+        let scale = transform
+            .x_axis
+            .xyz()
+            .length_squared()
+            .max(transform.y_axis.xyz().length_squared())
+            .max(transform.z_axis.xyz().length_squared())
+            .sqrt();
+        let radius = sphere.radius * scale;
+
+        self.planes
+            .iter()
+            .all(|plane| plane.signed_distance(center) <= radius)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{vec4, Mat4, Quat, Vec3, Vec4Swizzles};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::Frustum;
+    use crate::math::bounds::{BoundingSphere, AABB};
+
+    const LEFT: usize = 0;
+    const RIGHT: usize = 1;
+    const BOTTOM: usize = 2;
+    const TOP: usize = 3;
+    const NEAR: usize = 4;
+    const FAR: usize = 5;
+
+    /// Relative to the size of the frustums, which are up to 100 units deep
+    const EPSILON: f32 = 1e-2;
+
+    struct TestView {
+        view_projection: Mat4,
+        eye: Vec3,
+        forward: Vec3,
+        far: f32,
+        orthographic: bool,
+    }
+
+    fn random_vec3(rng: &mut StdRng, range: std::ops::Range<f32>) -> Vec3 {
+        Vec3::new(
+            rng.gen_range(range.clone()),
+            rng.gen_range(range.clone()),
+            rng.gen_range(range),
+        )
+    }
+
+    fn random_view(rng: &mut StdRng, orthographic: bool) -> TestView {
+        let eye = random_vec3(rng, -50.0..50.0);
+        let forward = random_vec3(rng, -1.0..1.0).normalize_or(Vec3::Z);
+        // Looking straight up or down has no defined up vector
+        let forward = if forward.y.abs() > 0.95 {
+            Vec3::X
+        } else {
+            forward
+        };
+        let view = Mat4::look_at_lh(eye, eye + forward, Vec3::Y);
+
+        let near = rng.gen_range(0.05..1.0);
+        let far = rng.gen_range(10.0..100.0);
+        let projection = if orthographic {
+            let half_width = rng.gen_range(1.0..30.0);
+            let half_height = rng.gen_range(1.0..30.0);
+            Mat4::orthographic_lh(
+                -half_width,
+                half_width,
+                -half_height,
+                half_height,
+                near,
+                far,
+            )
+        } else {
+            Mat4::perspective_lh(rng.gen_range(0.3..2.5), rng.gen_range(0.5..2.5), near, far)
+        };
+
+        TestView {
+            view_projection: projection * view,
+            eye,
+            forward,
+            far,
+            orthographic,
+        }
+    }
+
+    /// World space position of a point in wgpu's NDC, where depth goes from 0 to 1
+    fn unproject(view_projection: Mat4, ndc: Vec3) -> Vec3 {
+        let world = view_projection.inverse() * ndc.extend(1.0);
+        world.xyz() / world.w
+    }
+
+    /// Perspective and orthographic views, like the camera and shadow views
+    fn random_views(rng: &mut StdRng) -> Vec<TestView> {
+        (0..200)
+            .map(|index| random_view(rng, index % 2 == 1))
+            .collect()
+    }
+
+    #[test]
+    fn points_inside_are_inside_every_plane() {
+        let mut rng = StdRng::seed_from_u64(1);
+
+        for view in random_views(&mut rng) {
+            let frustum = Frustum::from_view_projection(view.view_projection);
+
+            for _ in 0..50 {
+                let ndc = vec4(
+                    rng.gen_range(-0.99..0.99),
+                    rng.gen_range(-0.99..0.99),
+                    rng.gen_range(0.01..0.99),
+                    1.0,
+                );
+                let point = unproject(view.view_projection, ndc.xyz());
+
+                for (index, plane) in frustum.planes.iter().enumerate() {
+                    assert!(
+                        plane.signed_distance(point) <= EPSILON,
+                        "{:?} is outside plane {}",
+                        point,
+                        index
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn points_beyond_each_side_are_outside_its_plane() {
+        let mut rng = StdRng::seed_from_u64(2);
+
+        for view in random_views(&mut rng) {
+            let frustum = Frustum::from_view_projection(view.view_projection);
+
+            for _ in 0..20 {
+                let inside = rng.gen_range(-0.9..0.9);
+                let outside = rng.gen_range(1.1..3.0);
+                let depth = rng.gen_range(0.1..0.9);
+
+                let sides = [
+                    (LEFT, Vec3::new(-outside, inside, depth)),
+                    (RIGHT, Vec3::new(outside, inside, depth)),
+                    (BOTTOM, Vec3::new(inside, -outside, depth)),
+                    (TOP, Vec3::new(inside, outside, depth)),
+                ];
+                for (plane, ndc) in sides {
+                    let point = unproject(view.view_projection, ndc);
+                    assert!(
+                        frustum.planes[plane].signed_distance(point) > 0.0,
+                        "{:?} is inside plane {}",
+                        point,
+                        plane
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn points_behind_the_eye_and_beyond_far_are_outside() {
+        let mut rng = StdRng::seed_from_u64(3);
+
+        for view in random_views(&mut rng) {
+            let frustum = Frustum::from_view_projection(view.view_projection);
+
+            // The conservative near plane of orthographic views is a whole frustum behind the eye
+            let distance = if view.orthographic {
+                view.far * rng.gen_range(1.1..3.0)
+            } else {
+                rng.gen_range(0.1..10.0)
+            };
+            let behind = view.eye - view.forward * distance;
+            assert!(frustum.planes[NEAR].signed_distance(behind) > 0.0);
+
+            let beyond = view.eye + view.forward * view.far * rng.gen_range(1.1..3.0);
+            assert!(frustum.planes[FAR].signed_distance(beyond) > 0.0);
+        }
+    }
+
+    #[test]
+    fn boxes_with_a_corner_inside_are_visible() {
+        let mut rng = StdRng::seed_from_u64(4);
+
+        for view in random_views(&mut rng) {
+            let frustum = Frustum::from_view_projection(view.view_projection);
+
+            for _ in 0..20 {
+                let ndc = Vec3::new(
+                    rng.gen_range(-0.9..0.9),
+                    rng.gen_range(-0.9..0.9),
+                    rng.gen_range(0.1..0.9),
+                );
+                let corner = unproject(view.view_projection, ndc);
+                let transform = Mat4::from_scale_rotation_translation(
+                    random_vec3(&mut rng, 0.2..3.0),
+                    Quat::from_rotation_y(rng.gen_range(-3.2..3.2)),
+                    random_vec3(&mut rng, -5.0..5.0),
+                );
+                // The minimum corner of the box lands on the point
+                let min = transform.inverse().transform_point3(corner);
+                let aabb = AABB::new(min, min + random_vec3(&mut rng, 0.0..5.0));
+
+                assert!(frustum.intersects_aabb(&aabb, transform));
+            }
+        }
+    }
+
+    #[test]
+    fn boxes_outside_a_plane_are_culled() {
+        let mut rng = StdRng::seed_from_u64(5);
+
+        for view in random_views(&mut rng) {
+            let frustum = Frustum::from_view_projection(view.view_projection);
+            let plane = frustum.planes[rng.gen_range(0..6)];
+
+            // A box around a point far enough outside the plane that its corners are too
+            let center = unproject(view.view_projection, Vec3::new(0.0, 0.0, 0.5));
+            let offset = center - plane.normal * plane.signed_distance(center);
+            let size = rng.gen_range(0.1..2.0);
+            let center = offset + plane.normal * (size * 2.0 + rng.gen_range(0.1..10.0));
+            let aabb = AABB::new(center - size, center + size);
+
+            assert!(!frustum.intersects_aabb(&aabb, Mat4::IDENTITY));
+            let sphere = BoundingSphere {
+                center,
+                radius: size,
+            };
+            assert!(!frustum.intersects_sphere(&sphere, Mat4::IDENTITY));
+        }
+    }
+
+    #[test]
+    fn enclosing_spheres_are_at_least_as_conservative_as_boxes() {
+        let mut rng = StdRng::seed_from_u64(6);
+
+        for view in random_views(&mut rng) {
+            let frustum = Frustum::from_view_projection(view.view_projection);
+
+            for _ in 0..20 {
+                let center = view.eye + random_vec3(&mut rng, -60.0..60.0);
+                let aabb = AABB::new(center, center + random_vec3(&mut rng, -5.0..5.0));
+                let sphere = BoundingSphere {
+                    center: aabb.center(),
+                    radius: (aabb.max - aabb.min).length() * 0.5,
+                };
+                let scale = random_vec3(&mut rng, 0.2..3.0);
+                let transform = Mat4::from_scale(scale);
+
+                if frustum.intersects_aabb(&aabb, transform) {
+                    assert!(frustum.intersects_sphere(&sphere, transform));
+                }
+            }
+        }
+    }
 }
@@ -1,3 +1,7 @@
 pub mod bounds;
+pub mod easing;
 pub mod frustum;
+pub mod noise;
 pub mod plane;
+pub mod ray;
+pub mod spline;
@@ -0,0 +1,235 @@
+// Gradient noise for procedural generation and animation. `shared::noise` implements the same
+// functions in WGSL with the same hash, so the CPU and GPU see the same values up to floating
+// point precision. All noise is roughly in -1 to 1, and the Perlin noise is zero at integer
+// coordinates.
+
+use std::ops::Mul;
+
+use glam::{IVec2, IVec3, Vec2, Vec3};
+
+/// PCG hash, see "Hash Functions for GPU Rendering" by Jarzynski & Olano
+pub fn hash(value: u32) -> u32 {
+    let state = value.wrapping_mul(747796405).wrapping_add(2891336453);
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    (word >> 22) ^ word
+}
+
+fn hash2(cell: IVec2) -> u32 {
+    hash(cell.x as u32 ^ hash(cell.y as u32))
+}
+
+fn hash3(cell: IVec3) -> u32 {
+    hash(cell.x as u32 ^ hash(cell.y as u32 ^ hash(cell.z as u32)))
+}
+
+/// Gradient from -1 to 1
+fn gradient1(hash: u32, offset: f32) -> f32 {
+    ((hash & 15) as f32 / 7.5 - 1.0) * offset
+}
+
+/// One of eight directions along the axes and diagonals
+fn gradient2(hash: u32, offset: Vec2) -> f32 {
+    let gradient = match hash & 7 {
+        0 => Vec2::new(1.0, 1.0),
+        1 => Vec2::new(-1.0, 1.0),
+        2 => Vec2::new(1.0, -1.0),
+        3 => Vec2::new(-1.0, -1.0),
+        4 => Vec2::new(1.0, 0.0),
+        5 => Vec2::new(-1.0, 0.0),
+        6 => Vec2::new(0.0, 1.0),
+        _ => Vec2::new(0.0, -1.0),
+    };
+    gradient.dot(offset)
+}
+
+/// One of the twelve edge directions of a cube, as in improved Perlin noise
+fn gradient3(hash: u32, offset: Vec3) -> f32 {
+    let h = hash & 15;
+    let u = if h < 8 { offset.x } else { offset.y };
+    let v = match h {
+        0..=3 => offset.y,
+        12 | 14 => offset.x,
+        _ => offset.z,
+    };
+    let u = if h & 1 == 0 { u } else { -u };
+    let v = if h & 2 == 0 { v } else { -v };
+    u + v
+}
+
+/// Quintic curve with zero first and second derivatives at the ends
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+pub fn perlin1(x: f32) -> f32 {
+    let cell = x.floor();
+    let offset = x - cell;
+    let cell = cell as i32;
+
+    let n0 = gradient1(hash(cell as u32), offset);
+    let n1 = gradient1(hash(cell.wrapping_add(1) as u32), offset - 1.0);
+    // The largest value is reached halfway between cells with opposite gradients
+    lerp(n0, n1, fade(offset)) * 2.0
+}
+
+pub fn perlin2(point: Vec2) -> f32 {
+    let cell = point.floor();
+    let offset = point - cell;
+    let cell = cell.as_ivec2();
+    let corner = |x: i32, y: i32| {
+        let corner = IVec2::new(x, y);
+        gradient2(hash2(cell + corner), offset - corner.as_vec2())
+    };
+
+    let fade = Vec2::new(fade(offset.x), fade(offset.y));
+    let bottom = lerp(corner(0, 0), corner(1, 0), fade.x);
+    let top = lerp(corner(0, 1), corner(1, 1), fade.x);
+    lerp(bottom, top, fade.y)
+}
+
+pub fn perlin3(point: Vec3) -> f32 {
+    let cell = point.floor();
+    let offset = point - cell;
+    let cell = cell.as_ivec3();
+    let corner = |x: i32, y: i32, z: i32| {
+        let corner = IVec3::new(x, y, z);
+        gradient3(hash3(cell + corner), offset - corner.as_vec3())
+    };
+
+    let fade = Vec3::new(fade(offset.x), fade(offset.y), fade(offset.z));
+    let near = lerp(
+        lerp(corner(0, 0, 0), corner(1, 0, 0), fade.x),
+        lerp(corner(0, 1, 0), corner(1, 1, 0), fade.x),
+        fade.y,
+    );
+    let far = lerp(
+        lerp(corner(0, 0, 1), corner(1, 0, 1), fade.x),
+        lerp(corner(0, 1, 1), corner(1, 1, 1), fade.x),
+        fade.y,
+    );
+    lerp(near, far, fade.z)
+}
+
+/// Contribution of a simplex corner, which fades out at `radius_squared`
+fn simplex_falloff(radius_squared: f32, offset_squared: f32) -> f32 {
+    let t = (radius_squared - offset_squared).max(0.0);
+    t * t * t * t
+}
+
+/// Simplex noise from "Simplex noise demystified" by Stefan Gustavson. Cheaper than Perlin noise
+/// and without its axis-aligned artifacts.
+pub fn simplex2(point: Vec2) -> f32 {
+    let skew = 0.5 * (3f32.sqrt() - 1.0);
+    let unskew = (3.0 - 3f32.sqrt()) / 6.0;
+
+    let cell = (point + Vec2::splat(point.element_sum() * skew)).floor();
+    let offset0 = point - (cell - Vec2::splat(cell.element_sum() * unskew));
+    let cell = cell.as_ivec2();
+
+    // Which of the two triangles of the skewed cell the point is in
+    let middle = if offset0.x > offset0.y {
+        IVec2::new(1, 0)
+    } else {
+        IVec2::new(0, 1)
+    };
+    let offset1 = offset0 - middle.as_vec2() + Vec2::splat(unskew);
+    let offset2 = offset0 - Vec2::ONE + Vec2::splat(2.0 * unskew);
+
+    let n0 = simplex_falloff(0.5, offset0.length_squared()) * gradient2(hash2(cell), offset0);
+    let n1 =
+        simplex_falloff(0.5, offset1.length_squared()) * gradient2(hash2(cell + middle), offset1);
+    let n2 = simplex_falloff(0.5, offset2.length_squared()) * gradient2(hash2(cell + 1), offset2);
+
+    70.0 * (n0 + n1 + n2)
+}
+
+pub fn simplex3(point: Vec3) -> f32 {
+    let skew = 1.0 / 3.0;
+    let unskew = 1.0 / 6.0;
+
+    let cell = (point + Vec3::splat(point.element_sum() * skew)).floor();
+    let offset0 = point - (cell - Vec3::splat(cell.element_sum() * unskew));
+    let cell = cell.as_ivec3();
+
+    // The skewed cell is split into six tetrahedra by the order of the offsets
+    let (corner1, corner2) = if offset0.x >= offset0.y {
+        if offset0.y >= offset0.z {
+            (IVec3::X, IVec3::new(1, 1, 0))
+        } else if offset0.x >= offset0.z {
+            (IVec3::X, IVec3::new(1, 0, 1))
+        } else {
+            (IVec3::Z, IVec3::new(1, 0, 1))
+        }
+    } else if offset0.y < offset0.z {
+        (IVec3::Z, IVec3::new(0, 1, 1))
+    } else if offset0.x < offset0.z {
+        (IVec3::Y, IVec3::new(0, 1, 1))
+    } else {
+        (IVec3::Y, IVec3::new(1, 1, 0))
+    };
+
+    let offset1 = offset0 - corner1.as_vec3() + Vec3::splat(unskew);
+    let offset2 = offset0 - corner2.as_vec3() + Vec3::splat(2.0 * unskew);
+    let offset3 = offset0 - Vec3::ONE + Vec3::splat(3.0 * unskew);
+
+    let contribution = |corner: IVec3, offset: Vec3| {
+        simplex_falloff(0.6, offset.length_squared()) * gradient3(hash3(cell + corner), offset)
+    };
+
+    32.0 * (contribution(IVec3::ZERO, offset0)
+        + contribution(corner1, offset1)
+        + contribution(corner2, offset2)
+        + contribution(IVec3::ONE, offset3))
+}
+
+/// Fractal Brownian motion: octaves of noise at increasing frequencies and decreasing
+/// amplitudes, for detail at every scale
+#[derive(Debug, Clone, Copy)]
+pub struct Fbm {
+    pub octaves: u32,
+    /// Frequency multiplier between octaves
+    pub lacunarity: f32,
+    /// Amplitude multiplier between octaves
+    pub gain: f32,
+}
+
+impl Default for Fbm {
+    fn default() -> Self {
+        Self {
+            octaves: 5,
+            lacunarity: 2.0,
+            gain: 0.5,
+        }
+    }
+}
+
+impl Fbm {
+    /// Sums the octaves of any of the noise functions. Normalized by the total amplitude, so it
+    /// stays in the range of the noise.
+    pub fn sample<P>(&self, point: P, noise: impl Fn(P) -> f32) -> f32
+    where
+        P: Copy + Mul<f32, Output = P>,
+    {
+        let mut sum = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+        let mut total_amplitude = 0.0;
+
+        for _ in 0..self.octaves {
+            sum += noise(point * frequency) * amplitude;
+            total_amplitude += amplitude;
+            amplitude *= self.gain;
+            frequency *= self.lacunarity;
+        }
+
+        if total_amplitude > 0.0 {
+            sum / total_amplitude
+        } else {
+            0.0
+        }
+    }
+}
@@ -23,4 +23,73 @@ impl Plane {
             distance: -self.distance,
         }
     }
+
+    /// Positive on the side the normal points to
+    pub fn signed_distance(&self, point: Vec3) -> f32 {
+        self.normal.dot(point) + self.distance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::Plane;
+
+    const EPSILON: f32 = 1e-4;
+
+    fn random_point(rng: &mut StdRng) -> Vec3 {
+        Vec3::new(
+            rng.gen_range(-10.0..10.0),
+            rng.gen_range(-10.0..10.0),
+            rng.gen_range(-10.0..10.0),
+        )
+    }
+
+    #[test]
+    fn normal_follows_counterclockwise_winding() {
+        let plane = Plane::from_points(Vec3::ZERO, Vec3::X, Vec3::Y);
+        assert!(plane.normal.abs_diff_eq(Vec3::Z, EPSILON));
+        assert!(plane.signed_distance(Vec3::Z) > 0.0);
+
+        let plane = Plane::from_points(Vec3::ZERO, Vec3::Y, Vec3::X);
+        assert!(plane.normal.abs_diff_eq(-Vec3::Z, EPSILON));
+    }
+
+    #[test]
+    fn defining_points_are_on_the_plane() {
+        let mut rng = StdRng::seed_from_u64(1);
+
+        for _ in 0..1000 {
+            let [a, b, c] = [(); 3].map(|_| random_point(&mut rng));
+            // Nearly collinear points don't define a plane
+            if (b - a).cross(c - a).length() < 0.1 {
+                continue;
+            }
+
+            let plane = Plane::from_points(a, b, c);
+            assert!((plane.normal.length() - 1.0).abs() < EPSILON);
+            for point in [a, b, c] {
+                assert!(plane.signed_distance(point).abs() < 1e-3, "{:?}", plane);
+            }
+        }
+    }
+
+    #[test]
+    fn flip_negates_distances() {
+        let mut rng = StdRng::seed_from_u64(2);
+
+        for _ in 0..1000 {
+            let [a, b, c, point] = [(); 4].map(|_| random_point(&mut rng));
+            if (b - a).cross(c - a).length() < 0.1 {
+                continue;
+            }
+
+            let plane = Plane::from_points(a, b, c);
+            let flipped = plane.flip();
+            assert!((plane.signed_distance(point) + flipped.signed_distance(point)).abs() < 1e-3);
+            assert!(flipped.flip().normal.abs_diff_eq(plane.normal, EPSILON));
+        }
+    }
 }
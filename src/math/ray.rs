@@ -0,0 +1,124 @@
+use glam::{Mat4, Vec2, Vec3};
+
+use crate::math::bounds::AABB;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Ray {
+    pub origin: Vec3,
+    /// Not necessarily normalized, e.g. after transforming the ray to an object's local space
+    pub direction: Vec3,
+}
+
+impl Ray {
+    /// Ray from the camera through a point on the screen, with Y pointing down
+    pub fn from_screen(screen_position: Vec2, screen_size: Vec2, inverse_view_proj: Mat4) -> Ray {
+        let ndc = Vec2::new(
+            screen_position.x / screen_size.x * 2.0 - 1.0,
+            1.0 - screen_position.y / screen_size.y * 2.0,
+        );
+
+        // Depth goes from 0 (near) to 1 (far)
+        let near = inverse_view_proj.project_point3(ndc.extend(0.0));
+        let far = inverse_view_proj.project_point3(ndc.extend(1.0));
+
+        Ray {
+            origin: near,
+            direction: (far - near).normalize(),
+        }
+    }
+
+    pub fn at(&self, t: f32) -> Vec3 {
+        self.origin + self.direction * t
+    }
+
+    /// Distances along the transformed ray stay comparable with the original ray
+    pub fn transform(&self, matrix: Mat4) -> Ray {
+        Ray {
+            origin: matrix.transform_point3(self.origin),
+            direction: matrix.transform_vector3(self.direction),
+        }
+    }
+
+    /// Distance to the first intersection, or 0 if the origin is inside the box
+    pub fn intersect_aabb(&self, aabb: &AABB) -> Option<f32> {
+        let inverse_direction = self.direction.recip();
+        let t1 = (aabb.min - self.origin) * inverse_direction;
+        let t2 = (aabb.max - self.origin) * inverse_direction;
+
+        let t_near = t1.min(t2).max_element();
+        let t_far = t1.max(t2).min_element();
+
+        if t_near > t_far || t_far < 0.0 {
+            return None;
+        }
+
+        Some(t_near.max(0.0))
+    }
+
+    pub fn intersect_plane(&self, point: Vec3, normal: Vec3) -> Option<f32> {
+        let denominator = normal.dot(self.direction);
+        if denominator.abs() < 1e-6 {
+            return None;
+        }
+
+        let t = normal.dot(point - self.origin) / denominator;
+        (t >= 0.0).then_some(t)
+    }
+
+    /// Möller–Trumbore intersection. Both sides of the triangle are hit.
+    pub fn intersect_triangle(&self, a: Vec3, b: Vec3, c: Vec3) -> Option<f32> {
+        let edge1 = b - a;
+        let edge2 = c - a;
+        let p = self.direction.cross(edge2);
+        let determinant = edge1.dot(p);
+        // The ray is parallel to the triangle
+        if determinant.abs() < 1e-8 {
+            return None;
+        }
+
+        let inverse_determinant = determinant.recip();
+        let s = self.origin - a;
+        let u = s.dot(p) * inverse_determinant;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let q = s.cross(edge1);
+        let v = self.direction.dot(q) * inverse_determinant;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let t = edge2.dot(q) * inverse_determinant;
+        (t >= 0.0).then_some(t)
+    }
+
+    /// Finds the point on an infinite line closest to the ray. Returns the parameter of the point
+    /// along the line and the distance between the line and the ray.
+    pub fn closest_point_on_line(
+        &self,
+        line_origin: Vec3,
+        line_direction: Vec3,
+    ) -> Option<(f32, f32)> {
+        let w = self.origin - line_origin;
+        let a = self.direction.dot(self.direction);
+        let b = self.direction.dot(line_direction);
+        let c = line_direction.dot(line_direction);
+        let d = self.direction.dot(w);
+        let e = line_direction.dot(w);
+
+        let denominator = a * c - b * b;
+        // Parallel lines
+        if denominator.abs() < 1e-6 {
+            return None;
+        }
+
+        let ray_t = ((b * e - c * d) / denominator).max(0.0);
+        let line_t = (a * e - b * d) / denominator;
+        let distance = self
+            .at(ray_t)
+            .distance(line_origin + line_direction * line_t);
+
+        Some((line_t, distance))
+    }
+}
@@ -0,0 +1,307 @@
+use glam::Vec3;
+
+/// Arc length is measured with this many straight lines per segment
+const ARC_LENGTH_SAMPLES_PER_SEGMENT: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplineKind {
+    /// Passes through every point. The ends are extended by mirroring the neighbouring points.
+    CatmullRom,
+    /// Cubic Bézier segments that share their end points. The points go end, control, control,
+    /// end, control, control, end and so on, so there are 3n + 1 of them.
+    Bezier,
+}
+
+/// Curve through control points, parametrized from 0 at the first point to 1 at the last
+#[derive(Debug, Clone)]
+pub struct Spline {
+    pub kind: SplineKind,
+    pub points: Vec<Vec3>,
+}
+
+impl Spline {
+    pub fn catmull_rom(points: Vec<Vec3>) -> Self {
+        Self {
+            kind: SplineKind::CatmullRom,
+            points,
+        }
+    }
+
+    pub fn bezier(points: Vec<Vec3>) -> Self {
+        Self {
+            kind: SplineKind::Bezier,
+            points,
+        }
+    }
+
+    pub fn segment_count(&self) -> usize {
+        match self.kind {
+            SplineKind::CatmullRom => self.points.len().saturating_sub(1),
+            SplineKind::Bezier => self.points.len().saturating_sub(1) / 3,
+        }
+    }
+
+    /// Whether the curve passes through the point, as opposed to it being a Bézier handle
+    pub fn is_anchor(&self, index: usize) -> bool {
+        match self.kind {
+            SplineKind::CatmullRom => true,
+            SplineKind::Bezier => index.is_multiple_of(3),
+        }
+    }
+
+    /// Point on the curve, where `t` goes from 0 to 1 over all segments
+    pub fn sample(&self, t: f32) -> Vec3 {
+        match self.locate(t) {
+            Some((segment, t)) => cubic_bezier(self.segment(segment), t),
+            None => self.points.first().copied().unwrap_or(Vec3::ZERO),
+        }
+    }
+
+    /// Direction of the curve at `t`, not normalized. Zero if there's no curve.
+    pub fn tangent(&self, t: f32) -> Vec3 {
+        match self.locate(t) {
+            Some((segment, t)) => cubic_bezier_derivative(self.segment(segment), t),
+            None => Vec3::ZERO,
+        }
+    }
+
+    /// Segment index and the parameter within it
+    fn locate(&self, t: f32) -> Option<(usize, f32)> {
+        let segment_count = self.segment_count();
+        if segment_count == 0 {
+            return None;
+        }
+
+        let scaled = t.clamp(0.0, 1.0) * segment_count as f32;
+        let segment = (scaled as usize).min(segment_count - 1);
+        Some((segment, scaled - segment as f32))
+    }
+
+    /// Bézier control points of a segment. Catmull-Rom segments are converted, since they're
+    /// the same curves with the handles placed from the neighbouring points.
+    fn segment(&self, index: usize) -> [Vec3; 4] {
+        let points = &self.points;
+
+        match self.kind {
+            SplineKind::Bezier => {
+                let start = index * 3;
+                [
+                    points[start],
+                    points[start + 1],
+                    points[start + 2],
+                    points[start + 3],
+                ]
+            }
+            SplineKind::CatmullRom => {
+                let p1 = points[index];
+                let p2 = points[index + 1];
+                let p0 = if index > 0 {
+                    points[index - 1]
+                } else {
+                    2.0 * p1 - p2
+                };
+                let p3 = points.get(index + 2).copied().unwrap_or(2.0 * p2 - p1);
+
+                [p1, p1 + (p2 - p0) / 6.0, p2 - (p3 - p1) / 6.0, p2]
+            }
+        }
+    }
+}
+
+fn cubic_bezier([p0, p1, p2, p3]: [Vec3; 4], t: f32) -> Vec3 {
+    let s = 1.0 - t;
+    p0 * (s * s * s) + p1 * (3.0 * s * s * t) + p2 * (3.0 * s * t * t) + p3 * (t * t * t)
+}
+
+fn cubic_bezier_derivative([p0, p1, p2, p3]: [Vec3; 4], t: f32) -> Vec3 {
+    let s = 1.0 - t;
+    (p1 - p0) * (3.0 * s * s) + (p2 - p1) * (6.0 * s * t) + (p3 - p2) * (3.0 * t * t)
+}
+
+/// Distances along a spline at evenly spaced parameters. The spline parameter doesn't move at a
+/// constant speed, since the segments have different lengths and the handles bunch up the
+/// points, so this maps distances back to parameters. Must be rebuilt when the points change.
+#[derive(Debug, Clone)]
+pub struct ArcLengths {
+    /// Distance from the start at each sample, starting from 0
+    distances: Vec<f32>,
+}
+
+impl ArcLengths {
+    pub fn new(spline: &Spline) -> Self {
+        let sample_count = spline.segment_count() * ARC_LENGTH_SAMPLES_PER_SEGMENT;
+        let mut distances = Vec::with_capacity(sample_count + 1);
+        distances.push(0.0);
+
+        let mut previous = spline.sample(0.0);
+        let mut distance = 0.0;
+        for sample in 1..=sample_count {
+            let point = spline.sample(sample as f32 / sample_count as f32);
+            distance += point.distance(previous);
+            distances.push(distance);
+            previous = point;
+        }
+
+        Self { distances }
+    }
+
+    pub fn total(&self) -> f32 {
+        self.distances.last().copied().unwrap_or(0.0)
+    }
+
+    /// Spline parameter at a distance from the start
+    pub fn parameter_at(&self, distance: f32) -> f32 {
+        let last = self.distances.len() - 1;
+        if last == 0 || self.total() <= 0.0 {
+            return 0.0;
+        }
+
+        let distance = distance.clamp(0.0, self.total());
+        let next = self
+            .distances
+            .partition_point(|sample| *sample < distance)
+            .clamp(1, last);
+        let (start, end) = (self.distances[next - 1], self.distances[next]);
+        let fraction = if end > start {
+            (distance - start) / (end - start)
+        } else {
+            0.0
+        };
+
+        (next - 1) as f32 / last as f32 + fraction / last as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::{ArcLengths, Spline};
+
+    const EPSILON: f32 = 1e-4;
+
+    /// Unevenly spaced, so that the parameter doesn't move at a constant speed
+    fn uneven_catmull_rom() -> Spline {
+        Spline::catmull_rom(vec![
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.5, 1.0, 0.0),
+            Vec3::new(4.0, 1.0, 2.0),
+            Vec3::new(5.0, 0.0, 6.0),
+            Vec3::new(9.0, 2.0, 6.5),
+        ])
+    }
+
+    #[test]
+    fn catmull_rom_passes_through_every_point() {
+        let spline = uneven_catmull_rom();
+        let segment_count = spline.segment_count() as f32;
+
+        for (index, point) in spline.points.iter().enumerate() {
+            let sampled = spline.sample(index as f32 / segment_count);
+            assert!(
+                sampled.abs_diff_eq(*point, EPSILON),
+                "{} {}",
+                index,
+                sampled
+            );
+        }
+    }
+
+    #[test]
+    fn bezier_passes_through_anchors_only() {
+        let spline = Spline::bezier(vec![
+            Vec3::ZERO,
+            Vec3::new(0.0, 2.0, 0.0),
+            Vec3::new(2.0, 2.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(2.0, -2.0, 0.0),
+            Vec3::new(4.0, -2.0, 0.0),
+            Vec3::new(4.0, 0.0, 0.0),
+        ]);
+        assert_eq!(spline.segment_count(), 2);
+
+        assert!(spline.sample(0.0).abs_diff_eq(Vec3::ZERO, EPSILON));
+        assert!(spline.sample(0.5).abs_diff_eq(Vec3::X * 2.0, EPSILON));
+        assert!(spline.sample(1.0).abs_diff_eq(Vec3::X * 4.0, EPSILON));
+        // The middle of the first segment is pulled towards the handles, 3/4 of the way up
+        assert!(spline
+            .sample(0.25)
+            .abs_diff_eq(Vec3::new(1.0, 1.5, 0.0), EPSILON));
+
+        assert!(spline.is_anchor(3));
+        assert!(!spline.is_anchor(4));
+    }
+
+    #[test]
+    fn tangents_point_at_the_handles_at_the_ends() {
+        let spline = Spline::bezier(vec![
+            Vec3::ZERO,
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+        ]);
+
+        assert!(spline
+            .tangent(0.0)
+            .abs_diff_eq(Vec3::new(0.0, 3.0, 0.0), EPSILON));
+        assert!(spline
+            .tangent(1.0)
+            .abs_diff_eq(Vec3::new(0.0, -3.0, 0.0), EPSILON));
+    }
+
+    #[test]
+    fn parameters_outside_the_curve_are_clamped() {
+        let spline = uneven_catmull_rom();
+
+        assert_eq!(spline.sample(-1.0), spline.sample(0.0));
+        assert_eq!(spline.sample(2.0), spline.sample(1.0));
+    }
+
+    #[test]
+    fn empty_splines_stay_at_the_origin() {
+        let spline = Spline::catmull_rom(Vec::new());
+        let lengths = ArcLengths::new(&spline);
+
+        assert_eq!(spline.sample(0.5), Vec3::ZERO);
+        assert_eq!(spline.tangent(0.5), Vec3::ZERO);
+        assert_eq!(lengths.total(), 0.0);
+        assert_eq!(lengths.parameter_at(1.0), 0.0);
+    }
+
+    #[test]
+    fn straight_lines_have_their_exact_length() {
+        let spline = Spline::catmull_rom(vec![Vec3::ZERO, Vec3::X, Vec3::X * 5.0, Vec3::X * 6.0]);
+        let lengths = ArcLengths::new(&spline);
+
+        assert!((lengths.total() - 6.0).abs() < EPSILON);
+        for distance in [0.0, 0.5, 1.0, 3.0, 5.5, 6.0] {
+            let point = spline.sample(lengths.parameter_at(distance));
+            assert!((point.x - distance).abs() < 1e-3, "{} {}", distance, point);
+        }
+    }
+
+    #[test]
+    fn distances_map_to_constant_speed() {
+        let spline = uneven_catmull_rom();
+        let lengths = ArcLengths::new(&spline);
+
+        let step_count = 200;
+        let step = lengths.total() / step_count as f32;
+        let mut previous = spline.sample(lengths.parameter_at(0.0));
+        for index in 1..=step_count {
+            let point = spline.sample(lengths.parameter_at(index as f32 * step));
+            // Chords are slightly shorter than the arcs they cut across
+            let moved = point.distance(previous);
+            assert!((moved - step).abs() < step * 0.02, "{} {}", index, moved);
+            previous = point;
+        }
+    }
+
+    #[test]
+    fn distances_outside_the_curve_are_clamped() {
+        let lengths = ArcLengths::new(&uneven_catmull_rom());
+
+        assert_eq!(lengths.parameter_at(-1.0), 0.0);
+        assert!((lengths.parameter_at(lengths.total() + 1.0) - 1.0).abs() < EPSILON);
+    }
+}
@@ -5,6 +5,10 @@ use gltf::buffer;
 use itertools::izip;
 
 use crate::{
+    asset_pipeline::{
+        generate_lods::{LodExtras, DEFAULT_LOD_DISTANCE},
+        materials::AlphaMode,
+    },
     material_manager::{MaterialId, MaterialManager},
     math::bounds::AABB,
 };
@@ -16,6 +20,67 @@ pub struct Vertex {
     pub normal: Vec3,
     pub tex_coords: Vec2,
     pub tangent: Vec3,
+    /// Indices into the skin's joints. Unused if the mesh isn't skinned.
+    pub joints: [u16; 4],
+    /// Zero for meshes that aren't skinned. Not a Vec4, which would add padding to the struct.
+    pub weights: [f32; 4],
+}
+
+/// Compact version of `Vertex`, for baking with `VertexFormat::Quantized`. Positions stay full
+/// floats, so that skinning and large meshes don't lose precision.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct QuantizedVertex {
+    pub position: Vec3,
+    /// Octahedral encoded, see `octahedral_encode`
+    pub normal: [i16; 2],
+    pub tangent: [i16; 2],
+    /// Half floats
+    pub tex_coords: [u16; 2],
+    pub joints: [u16; 4],
+    /// Normalized to 0..255
+    pub weights: [u8; 4],
+}
+
+impl QuantizedVertex {
+    pub fn new(vertex: &Vertex) -> Self {
+        let snorm = |value: Vec2| {
+            value
+                .to_array()
+                .map(|component| (component.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16)
+        };
+
+        Self {
+            position: vertex.position,
+            normal: snorm(octahedral_encode(vertex.normal)),
+            tangent: snorm(octahedral_encode(vertex.tangent)),
+            tex_coords: vertex
+                .tex_coords
+                .to_array()
+                .map(|component| half::f16::from_f32(component).to_bits()),
+            joints: vertex.joints,
+            weights: vertex
+                .weights
+                .map(|weight| (weight.clamp(0.0, 1.0) * 255.0).round() as u8),
+        }
+    }
+}
+
+/// Maps a direction onto an octahedron unfolded into the -1..1 square, which spreads the
+/// precision evenly over the sphere. Decoded by `octahedral_decode` in shared/vertex.wgsl.
+fn octahedral_encode(direction: Vec3) -> Vec2 {
+    let direction = direction / (direction.x.abs() + direction.y.abs() + direction.z.abs());
+
+    if direction.z >= 0.0 {
+        direction.truncate()
+    } else {
+        // The lower half is folded over the diagonals
+        let sign = Vec2::new(
+            if direction.x >= 0.0 { 1.0 } else { -1.0 },
+            if direction.y >= 0.0 { 1.0 } else { -1.0 },
+        );
+        (Vec2::ONE - Vec2::new(direction.y, direction.x).abs()) * sign
+    }
 }
 
 pub struct ModelPrimitive {
@@ -24,6 +89,19 @@ pub struct ModelPrimitive {
     pub indices: Vec<u32>,
     pub bounding_box: AABB,
     pub material_id: MaterialId,
+    /// Copied from the material, because blended primitives are drawn in a separate pass
+    pub alpha_mode: AlphaMode,
+    /// Simplified versions of the primitive, ordered from the most to the least detailed
+    pub lods: Vec<PrimitiveLod>,
+}
+
+/// Simplified geometry for a primitive. LODs are drawn with the material of the primitive.
+pub struct PrimitiveLod {
+    /// None if the LOD indexes the vertices of the primitive, which is the case for generated LODs
+    pub vertices: Option<Vec<Vertex>>,
+    pub indices: Vec<u32>,
+    /// Distance from the camera beyond which this LOD replaces the previous one
+    pub distance: f32,
 }
 
 impl ModelPrimitive {
@@ -63,6 +141,9 @@ impl Model {
             primitives: Vec::new(),
         };
 
+        let lod_extras = LodExtras::from_gltf_extras(mesh.extras())
+            .with_context(|| format!("Failed to read LOD settings of model '{}'", model.name))?;
+
         for primitive in mesh.primitives() {
             if primitive.mode() != gltf::mesh::Mode::Triangles {
                 return Err(anyhow::anyhow!(
@@ -93,15 +174,27 @@ impl Model {
                 .expect("Failed to read tex coords")
                 .into_f32();
 
-            let vertices = izip!(position_reader, normal_reader, tex_coords_reader,)
+            let mut vertices = izip!(position_reader, normal_reader, tex_coords_reader,)
                 .map(|(pos, normal, tex_coords)| Vertex {
                     position: Vec3::from(pos),
                     normal: Vec3::from(normal),
                     tex_coords: Vec2::from(tex_coords),
                     tangent: Vec3::ZERO,
+                    joints: [0; 4],
+                    weights: [0.0; 4],
                 })
                 .collect::<Vec<Vertex>>();
 
+            // Only the first set of joints is supported, which limits skinning to 4 influences
+            if let (Some(joints), Some(weights)) = (reader.read_joints(0), reader.read_weights(0)) {
+                for (vertex, joints, weights) in
+                    izip!(&mut vertices, joints.into_u16(), weights.into_f32())
+                {
+                    vertex.joints = joints;
+                    vertex.weights = weights;
+                }
+            }
+
             let index_reader = reader.read_indices().expect("Failed to read indices");
             let indices = index_reader.into_u32().collect::<Vec<u32>>();
 
@@ -113,6 +206,10 @@ impl Model {
                 AABB::new(min, max)
             };
 
+            let alpha_mode = material_manager
+                .get_material(material_id)
+                .map_or(AlphaMode::Opaque, |material| material.alpha_mode);
+
             let global_index = *primitive_index;
 
             let mut primitive = ModelPrimitive {
@@ -121,6 +218,8 @@ impl Model {
                 bounding_box,
                 global_index,
                 material_id,
+                alpha_mode,
+                lods: Vec::new(),
             };
 
             primitive.generate_tangents().with_context(|| {
@@ -130,6 +229,14 @@ impl Model {
                 )
             })?;
 
+            let lod_ratios = lod_extras.lod_ratios();
+            if !lod_ratios.is_empty() {
+                primitive.generate_lods(
+                    &lod_ratios,
+                    lod_extras.lod_distance.unwrap_or(DEFAULT_LOD_DISTANCE),
+                );
+            }
+
             model.primitives.push(primitive);
             *primitive_index += 1;
         }
@@ -140,4 +247,36 @@ impl Model {
 
         Ok(model)
     }
+
+    /// Replaces the LODs of each primitive with the matching primitives of `lods`, which were
+    /// authored as separate meshes in the glTF file. Authored LODs take precedence over
+    /// generated ones.
+    pub fn set_authored_lods(&mut self, lods: Vec<(Model, f32)>) -> anyhow::Result<()> {
+        for (lod, _) in &lods {
+            anyhow::ensure!(
+                lod.primitives.len() == self.primitives.len(),
+                "LOD '{}' has {} primitives, but '{}' has {}",
+                lod.name,
+                lod.primitives.len(),
+                self.name,
+                self.primitives.len()
+            );
+        }
+
+        for primitive in &mut self.primitives {
+            primitive.lods.clear();
+        }
+
+        for (lod, distance) in lods {
+            for (primitive, lod_primitive) in self.primitives.iter_mut().zip(lod.primitives) {
+                primitive.lods.push(PrimitiveLod {
+                    vertices: Some(lod_primitive.vertices),
+                    indices: lod_primitive.indices,
+                    distance,
+                });
+            }
+        }
+
+        Ok(())
+    }
 }
@@ -0,0 +1,190 @@
+use std::{
+    io::Write,
+    path::PathBuf,
+    process::{Child, ChildStdin, Command, Stdio},
+    time::Duration,
+};
+
+use anyhow::Context;
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    asset_loader::AssetLoader,
+    demo::{DemoAssets, DemoOptions, DemoState},
+    engine,
+    input::InputState,
+    rendering::{config::RendererOptions, renderer::Renderer},
+};
+
+/// Where the rendered frames are written
+pub enum FrameOutput {
+    /// Numbered PNG files in a directory
+    Images(PathBuf),
+    /// A video file, encoded by piping raw frames to ffmpeg
+    Video(PathBuf),
+}
+
+/// Renders the demo without a window at a fixed frame rate, instead of in real time
+pub struct OfflineRenderSettings {
+    pub output: FrameOutput,
+    pub size: PhysicalSize<u32>,
+    pub fps: u32,
+    /// Seconds. Defaults to the length of the timeline.
+    pub duration: Option<f32>,
+}
+
+enum FrameSink {
+    Images(PathBuf),
+    Ffmpeg {
+        process: Child,
+        /// Closed when the sink is finished or dropped
+        stdin: Option<ChildStdin>,
+    },
+}
+
+impl FrameSink {
+    fn new(settings: &OfflineRenderSettings) -> anyhow::Result<Self> {
+        match &settings.output {
+            FrameOutput::Images(directory) => {
+                std::fs::create_dir_all(directory).with_context(|| {
+                    format!("Failed to create output directory {:?}", directory)
+                })?;
+                Ok(Self::Images(directory.clone()))
+            }
+            FrameOutput::Video(path) => {
+                let mut process = Command::new("ffmpeg")
+                    .args(["-y", "-f", "rawvideo", "-pix_fmt", "rgba", "-s"])
+                    .arg(format!("{}x{}", settings.size.width, settings.size.height))
+                    .arg("-r")
+                    .arg(settings.fps.to_string())
+                    .args(["-i", "-", "-c:v", "libx264", "-pix_fmt", "yuv420p"])
+                    .arg(path)
+                    .stdin(Stdio::piped())
+                    .spawn()
+                    .context("Failed to start ffmpeg, is it installed?")?;
+                let stdin = process.stdin.take();
+
+                Ok(Self::Ffmpeg { process, stdin })
+            }
+        }
+    }
+
+    fn write(&mut self, index: usize, frame: &image::RgbaImage) -> anyhow::Result<()> {
+        match self {
+            Self::Images(directory) => {
+                let path = directory.join(format!("frame_{:05}.png", index));
+                frame
+                    .save(&path)
+                    .with_context(|| format!("Failed to write frame {:?}", path))
+            }
+            Self::Ffmpeg { stdin, .. } => stdin
+                .as_mut()
+                .context("ffmpeg has already finished")?
+                .write_all(frame.as_raw())
+                .context("Failed to write frame to ffmpeg"),
+        }
+    }
+
+    /// Waits for ffmpeg to finish encoding
+    fn finish(mut self) -> anyhow::Result<()> {
+        if let Self::Ffmpeg { process, stdin } = &mut self {
+            // Closing stdin tells ffmpeg that there are no more frames
+            drop(stdin.take());
+            let status = process.wait().context("Failed to wait for ffmpeg")?;
+            anyhow::ensure!(status.success(), "ffmpeg failed: {}", status);
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for FrameSink {
+    /// Reaps ffmpeg when rendering fails before `finish`, so it doesn't outlive the demo.
+    /// Waiting again after `finish` just returns the same status.
+    fn drop(&mut self) {
+        if let Self::Ffmpeg { process, stdin } = self {
+            drop(stdin.take());
+            if let Err(e) = process.wait() {
+                log::error!("Failed to wait for ffmpeg: {:?}", e);
+            }
+        }
+    }
+}
+
+pub async fn run(
+    settings: OfflineRenderSettings,
+    demo_options: DemoOptions,
+    renderer_options: RendererOptions,
+) -> anyhow::Result<()> {
+    let bake_options = renderer_options.bake_options();
+    let assets = AssetLoader::spawn(DemoAssets::LOADING_STEPS, move |progress| {
+        DemoAssets::load(progress, bake_options)
+    })?
+    .wait()?;
+    let (mut state, mut material_manager, baked_meshes) =
+        DemoState::new_offline(assets, demo_options);
+
+    let mut imgui_context = imgui::Context::create();
+    imgui_context.set_ini_filename(None);
+    imgui_context.io_mut().display_size = [settings.size.width as f32, settings.size.height as f32];
+
+    let mut renderer = Renderer::new_headless(
+        settings.size,
+        &state,
+        &material_manager,
+        &baked_meshes,
+        &mut imgui_context,
+        &renderer_options,
+    )
+    .await?;
+    renderer.wait_for_shaders()?;
+
+    let duration = settings
+        .duration
+        .unwrap_or_else(|| (state.timeline.duration() - state.time()).max(0.0));
+    let delta_time = 1.0 / settings.fps as f32;
+    let frame_count = (duration * settings.fps as f32).ceil() as usize;
+    // Nothing is ever pressed, but the engine wants an input state
+    let input = InputState::new();
+
+    let mut sink = FrameSink::new(&settings)?;
+
+    for index in 0..frame_count {
+        imgui_context
+            .io_mut()
+            .update_delta_time(Duration::from_secs_f32(delta_time));
+        let ui = imgui_context.new_frame();
+
+        engine::update(
+            &mut state,
+            &mut renderer,
+            &mut material_manager,
+            &input,
+            delta_time,
+            ui,
+        )?;
+
+        let result = renderer
+            .render(&mut state, ui)
+            .context("Failed to render frame")?;
+        renderer.finish_frame(result, &mut imgui_context);
+
+        let frame = renderer.read_output()?;
+        // There's nobody to look at an error overlay, and the frame would be broken anyway
+        if let Some(error) = renderer.poll_device_errors().into_iter().next() {
+            anyhow::bail!("GPU error while rendering frame {}: {:?}", index, error);
+        }
+        sink.write(index, &frame)?;
+
+        state.step_clock(delta_time);
+
+        if index % settings.fps as usize == 0 {
+            log::info!("Rendered frame {}/{}", index + 1, frame_count);
+        }
+    }
+
+    sink.finish()?;
+    log::info!("Rendered {} frames", frame_count);
+
+    Ok(())
+}
@@ -0,0 +1,161 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::Parser;
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    demo::DemoOptions,
+    offline::{FrameOutput, OfflineRenderSettings},
+    rendering::config::{QualityPreset, QualitySettings, RendererOptions},
+    settings::Settings,
+    window::{DisplayMode, DisplaySettings},
+};
+
+const DEFAULT_OFFLINE_SIZE: PhysicalSize<u32> = PhysicalSize::new(1920, 1080);
+
+/// Command line options
+#[derive(Debug, Parser)]
+#[command(about = "Runs the demo")]
+pub struct Options {
+    /// Window size or fullscreen video mode, e.g. 1920x1080. Also the size of offline renders.
+    #[arg(long, value_parser = parse_size)]
+    size: Option<PhysicalSize<u32>>,
+
+    /// Exclusive fullscreen
+    #[arg(long, conflicts_with = "borderless")]
+    fullscreen: bool,
+
+    /// Borderless fullscreen window
+    #[arg(long)]
+    borderless: bool,
+
+    /// Index of the monitor to use
+    #[arg(long)]
+    monitor: Option<usize>,
+
+    /// Limit the frame rate when running in a window
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    max_fps: Option<u32>,
+
+    /// Where to start the demo, in seconds or minutes:seconds, e.g. 1:30
+    #[arg(long, value_parser = parse_time, default_value = "0")]
+    start: f32,
+
+    /// Run without music
+    #[arg(long)]
+    no_audio: bool,
+
+    /// Use the low quality preset instead of the settings file
+    #[arg(long)]
+    low_quality: bool,
+
+    /// Use the first GPU whose name contains this, ignoring case
+    #[arg(long)]
+    adapter: Option<String>,
+
+    /// Render numbered PNG frames to this directory instead of opening a window
+    #[arg(long, conflicts_with = "render_video")]
+    render_frames: Option<PathBuf>,
+
+    /// Render a video with ffmpeg instead of opening a window
+    #[arg(long)]
+    render_video: Option<PathBuf>,
+
+    /// Frame rate of offline renders
+    #[arg(long, default_value_t = 60, value_parser = clap::value_parser!(u32).range(1..))]
+    fps: u32,
+
+    /// Length of offline renders in seconds. Defaults to the rest of the demo.
+    #[arg(long)]
+    duration: Option<f32>,
+
+    /// Bake ambient occlusion for the static objects of every part into assets/baked, then exit
+    #[arg(long, conflicts_with_all = ["render_frames", "render_video"])]
+    bake_ao: bool,
+}
+
+impl Options {
+    /// When set, the demo is rendered to files without opening a window
+    pub fn offline(&self) -> Option<OfflineRenderSettings> {
+        let output = match (&self.render_frames, &self.render_video) {
+            (Some(directory), _) => FrameOutput::Images(directory.clone()),
+            (None, Some(path)) => FrameOutput::Video(path.clone()),
+            (None, None) => return None,
+        };
+
+        Some(OfflineRenderSettings {
+            output,
+            size: self.size.unwrap_or(DEFAULT_OFFLINE_SIZE),
+            fps: self.fps,
+            duration: self.duration,
+        })
+    }
+
+    /// When set, ambient occlusion is baked instead of running the demo
+    pub fn bake_ao(&self) -> bool {
+        self.bake_ao
+    }
+
+    pub fn display(&self) -> DisplaySettings {
+        let mode = if self.fullscreen {
+            DisplayMode::Exclusive
+        } else if self.borderless {
+            DisplayMode::Borderless
+        } else {
+            DisplayMode::Windowed
+        };
+
+        DisplaySettings {
+            mode,
+            monitor: self.monitor,
+            size: self.size,
+            max_fps: self.max_fps,
+        }
+    }
+
+    pub fn demo(&self) -> DemoOptions {
+        DemoOptions {
+            start_time: self.start,
+            disable_audio: self.no_audio,
+        }
+    }
+
+    pub fn renderer(&self, settings: &Settings) -> RendererOptions {
+        RendererOptions {
+            adapter_name: self.adapter.clone(),
+            quality: if self.low_quality {
+                QualitySettings::preset(QualityPreset::Low)
+            } else {
+                settings.quality.clone()
+            },
+            transparency: settings.transparency,
+        }
+    }
+}
+
+fn parse_size(size: &str) -> anyhow::Result<PhysicalSize<u32>> {
+    let (width, height) = size
+        .split_once('x')
+        .with_context(|| format!("Invalid size '{}', expected WxH", size))?;
+    let width = width.parse().context("Invalid width")?;
+    let height = height.parse().context("Invalid height")?;
+    anyhow::ensure!(width > 0 && height > 0, "Size must be positive");
+
+    Ok(PhysicalSize::new(width, height))
+}
+
+/// Seconds, or minutes and seconds separated by a colon
+fn parse_time(time: &str) -> anyhow::Result<f32> {
+    let seconds = match time.split_once(':') {
+        Some((minutes, seconds)) => {
+            let minutes: u32 = minutes.parse().context("Invalid minutes")?;
+            let seconds: f32 = seconds.parse().context("Invalid seconds")?;
+            minutes as f32 * 60.0 + seconds
+        }
+        None => time.parse().context("Invalid time")?,
+    };
+    anyhow::ensure!(seconds >= 0.0, "Time can't be negative");
+
+    Ok(seconds)
+}
@@ -0,0 +1,115 @@
+use glam::Vec3;
+use winit::{event::MouseButton, keyboard::KeyCode};
+
+use crate::{camera::Camera, input::InputState};
+
+const MAX_PITCH: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+
+/// Inspection camera that orbits around a focus point. Middle mouse drag orbits, Shift + middle
+/// mouse drag pans and the scroll wheel zooms. Toggled from its debug window, and overrides both
+/// the timeline and the fly camera while enabled.
+#[derive(Debug)]
+pub struct OrbitCameraController {
+    enabled: bool,
+    focus: Vec3,
+    distance: f32,
+    yaw: f32,
+    pitch: f32,
+    sensitivity: f32,
+}
+
+impl Default for OrbitCameraController {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            focus: Vec3::ZERO,
+            distance: 5.0,
+            yaw: 0.0,
+            pitch: 0.0,
+            sensitivity: 0.005,
+        }
+    }
+}
+
+impl OrbitCameraController {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool, camera: &Camera) {
+        if enabled && !self.enabled {
+            // Orbit around whatever the camera is currently looking at
+            let offset = camera.target - camera.eye;
+            let forward = offset.normalize_or(Vec3::Z);
+            self.focus = camera.target;
+            self.distance = offset.length().max(0.1);
+            self.yaw = forward.x.atan2(forward.z);
+            self.pitch = forward.y.asin().clamp(-MAX_PITCH, MAX_PITCH);
+        }
+
+        self.enabled = enabled;
+    }
+
+    fn forward(&self) -> Vec3 {
+        Vec3::new(
+            self.yaw.sin() * self.pitch.cos(),
+            self.pitch.sin(),
+            self.yaw.cos() * self.pitch.cos(),
+        )
+    }
+
+    pub fn update(&mut self, input: &InputState, camera: &mut Camera) {
+        if !self.enabled {
+            return;
+        }
+
+        let forward = self.forward();
+        // Left-handed: +X is to the right when looking down +Z
+        let right = Vec3::Y.cross(forward).normalize();
+        let up = forward.cross(right);
+
+        if input.is_mouse_button_down(MouseButton::Middle) {
+            let delta = input.mouse_delta();
+
+            if input.is_key_down(KeyCode::ShiftLeft) {
+                // Pan speed scales with distance, so the focus point follows the cursor
+                let pan_speed = self.distance * 0.0015;
+                self.focus += (-right * delta.x + up * delta.y) * pan_speed;
+            } else {
+                self.yaw += delta.x * self.sensitivity;
+                self.pitch = (self.pitch - delta.y * self.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+            }
+        }
+
+        self.distance = (self.distance * 0.9f32.powf(input.scroll_delta())).clamp(0.1, 500.0);
+
+        let forward = self.forward();
+        camera.eye = self.focus - forward * self.distance;
+        camera.target = self.focus;
+        camera.up = Vec3::Y;
+    }
+
+    pub fn draw_ui(&mut self, ui: &imgui::Ui, camera: &Camera) {
+        ui.window("Orbit camera")
+            .size([240.0, 120.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                let mut enabled = self.enabled;
+                if ui.checkbox("Enabled", &mut enabled) {
+                    self.set_enabled(enabled, camera);
+                }
+
+                if !self.enabled {
+                    return;
+                }
+
+                ui.text(format!(
+                    "Focus: {:.2} {:.2} {:.2}",
+                    self.focus.x, self.focus.y, self.focus.z
+                ));
+                ui.slider("Distance", 0.1, 500.0, &mut self.distance);
+                if ui.button("Reset focus") {
+                    self.focus = Vec3::ZERO;
+                }
+            });
+    }
+}
@@ -0,0 +1,306 @@
+use glam::{Mat3, Quat, Vec3, Vec4};
+
+use crate::{
+    camera::{CameraId, Cameras},
+    math::spline::{ArcLengths, Spline, SplineKind},
+    rendering::debug_draw::DebugDraw,
+    scene_graph::{object3d::ObjectId, scene::Scene},
+    timeline::Easing,
+};
+
+/// Lines drawn for each segment of a path
+const DRAW_SEGMENT_STEPS: usize = 16;
+const POINT_RADIUS: f32 = 0.05;
+const PATH_COLOR: Vec4 = Vec4::new(1.0, 0.4, 0.8, 1.0);
+const HANDLE_COLOR: Vec4 = Vec4::new(0.6, 0.6, 0.6, 1.0);
+const SELECTED_COLOR: Vec4 = Vec4::new(1.0, 1.0, 0.0, 1.0);
+
+/// What a path moves
+#[derive(Debug, Clone, Copy)]
+pub enum PathTarget {
+    Object(ObjectId),
+    CameraEye(CameraId),
+    CameraTarget(CameraId),
+}
+
+/// Moves an object or a camera along a spline at a constant speed, shaped by an easing curve.
+/// Like timeline tracks, it's sampled with the demo time and holds the ends outside its time
+/// range.
+pub struct PathFollower {
+    pub name: String,
+    pub target: PathTarget,
+    spline: Spline,
+    lengths: ArcLengths,
+    pub start_time: f32,
+    pub duration: f32,
+    pub easing: Easing,
+    /// Turns objects so that their +Z axis points along the path
+    pub orient: bool,
+}
+
+impl PathFollower {
+    pub fn new(
+        name: impl Into<String>,
+        target: PathTarget,
+        spline: Spline,
+        start_time: f32,
+        duration: f32,
+    ) -> Self {
+        let lengths = ArcLengths::new(&spline);
+
+        Self {
+            name: name.into(),
+            target,
+            spline,
+            lengths,
+            start_time,
+            duration,
+            easing: Easing::Linear,
+            orient: false,
+        }
+    }
+
+    /// Builder-style setter for `easing`
+    pub fn easing(mut self, easing: Easing) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    /// Builder-style setter for `orient`
+    pub fn oriented(mut self) -> Self {
+        self.orient = true;
+        self
+    }
+
+    /// Sets the duration from a speed in units per second
+    pub fn speed(mut self, speed: f32) -> Self {
+        self.duration = self.lengths.total() / speed.max(f32::EPSILON);
+        self
+    }
+
+    pub fn spline(&self) -> &Spline {
+        &self.spline
+    }
+
+    /// Changes the points, e.g. from the UI
+    pub fn edit_spline(&mut self, edit: impl FnOnce(&mut Spline)) {
+        edit(&mut self.spline);
+        self.lengths = ArcLengths::new(&self.spline);
+    }
+
+    /// Spline parameter at the given time
+    fn parameter_at(&self, time: f32) -> f32 {
+        let progress = if self.duration > 0.0 {
+            (time - self.start_time) / self.duration
+        } else if time < self.start_time {
+            0.0
+        } else {
+            1.0
+        };
+
+        let distance = self.easing.apply(progress) * self.lengths.total();
+        self.lengths.parameter_at(distance)
+    }
+
+    pub fn apply(&self, time: f32, scene: &mut Scene, cameras: &mut Cameras) {
+        if self.spline.points.is_empty() {
+            return;
+        }
+
+        let t = self.parameter_at(time);
+        let position = self.spline.sample(t);
+
+        match self.target {
+            PathTarget::Object(object_id) => {
+                scene.set_object_translation(object_id, position);
+
+                if let Some(rotation) = self.orient.then(|| facing(self.spline.tangent(t))) {
+                    scene.set_object_rotation(object_id, rotation);
+                }
+            }
+            PathTarget::CameraEye(camera_id) => {
+                if let Some(camera) = cameras.get_mut(camera_id) {
+                    camera.eye = position;
+                }
+            }
+            PathTarget::CameraTarget(camera_id) => {
+                if let Some(camera) = cameras.get_mut(camera_id) {
+                    camera.target = position;
+                }
+            }
+        }
+    }
+}
+
+/// Rotation that turns +Z to the direction while keeping +Y up
+fn facing(direction: Vec3) -> Quat {
+    let forward = direction.normalize_or(Vec3::Z);
+    let right = Vec3::Y.cross(forward).normalize_or(Vec3::X);
+    let up = forward.cross(right);
+    Quat::from_mat3(&Mat3::from_cols(right, up, forward))
+}
+
+/// Paths of a demo part. Applied after the timeline, so they override its channels for the same
+/// targets. The points can be edited from the UI and copied from the log back into the code.
+#[derive(Default)]
+pub struct Paths {
+    followers: Vec<PathFollower>,
+    show: bool,
+    selected: usize,
+    selected_point: Option<usize>,
+}
+
+impl Paths {
+    pub fn add(&mut self, follower: PathFollower) {
+        self.followers.push(follower);
+    }
+
+    pub fn apply(&self, time: f32, scene: &mut Scene, cameras: &mut Cameras) {
+        for follower in &self.followers {
+            follower.apply(time, scene, cameras);
+        }
+    }
+
+    /// Draws the paths with their control points, if enabled from the UI
+    pub fn draw(&self, debug_draw: &mut DebugDraw) {
+        if !self.show {
+            return;
+        }
+
+        for (index, follower) in self.followers.iter().enumerate() {
+            let spline = follower.spline();
+            let selected = index == self.selected;
+
+            let steps = spline.segment_count() * DRAW_SEGMENT_STEPS;
+            for step in 0..steps {
+                debug_draw.line(
+                    spline.sample(step as f32 / steps as f32),
+                    spline.sample((step + 1) as f32 / steps as f32),
+                    PATH_COLOR,
+                );
+            }
+
+            for (point_index, point) in spline.points.iter().enumerate() {
+                let color = if selected && self.selected_point == Some(point_index) {
+                    SELECTED_COLOR
+                } else if spline.is_anchor(point_index) {
+                    PATH_COLOR
+                } else {
+                    HANDLE_COLOR
+                };
+                debug_draw.sphere(*point, POINT_RADIUS, color);
+            }
+
+            // Bézier handles are connected to the anchor they belong to
+            if spline.kind == SplineKind::Bezier {
+                for (point_index, point) in spline.points.iter().enumerate() {
+                    let anchor = match point_index % 3 {
+                        1 => point_index - 1,
+                        2 => point_index + 1,
+                        _ => continue,
+                    };
+                    if let Some(anchor) = spline.points.get(anchor) {
+                        debug_draw.line(*anchor, *point, HANDLE_COLOR);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn draw_ui(&mut self, ui: &imgui::Ui) {
+        if self.followers.is_empty() {
+            return;
+        }
+
+        ui.window("Paths")
+            .size([320.0, 300.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                ui.checkbox("Show paths", &mut self.show);
+
+                let names = self
+                    .followers
+                    .iter()
+                    .map(|follower| follower.name.as_str())
+                    .collect::<Vec<_>>();
+                if ui.combo_simple_string("Path", &mut self.selected, &names) {
+                    self.selected_point = None;
+                }
+
+                let Some(follower) = self.followers.get_mut(self.selected) else {
+                    return;
+                };
+
+                ui.text(format!("Target: {:?}", follower.target));
+                ui.text(format!("Length: {:.2}", follower.lengths.total()));
+                ui.input_float("Start time", &mut follower.start_time)
+                    .build();
+                ui.input_float("Duration", &mut follower.duration).build();
+                ui.checkbox("Orient along path", &mut follower.orient);
+
+                let easing_names = Easing::ALL.map(Easing::name);
+                let mut easing_index = Easing::ALL
+                    .iter()
+                    .position(|easing| *easing == follower.easing)
+                    .unwrap_or(0);
+                if ui.combo_simple_string("Easing", &mut easing_index, &easing_names) {
+                    follower.easing = Easing::ALL[easing_index];
+                }
+
+                ui.separator();
+                let mut points = follower.spline().points.clone();
+                let mut changed = false;
+                for (index, point) in points.iter_mut().enumerate() {
+                    let label = if follower.spline().is_anchor(index) {
+                        format!("Point {}", index)
+                    } else {
+                        format!("Handle {}", index)
+                    };
+
+                    let mut array = point.to_array();
+                    if ui.input_float3(&label, &mut array).build() {
+                        *point = array.into();
+                        changed = true;
+                    }
+                    if ui.is_item_active() || ui.is_item_hovered() {
+                        self.selected_point = Some(index);
+                    }
+                }
+
+                // Bézier splines grow by a whole segment at a time
+                let points_per_segment = match follower.spline().kind {
+                    SplineKind::CatmullRom => 1,
+                    SplineKind::Bezier => 3,
+                };
+                if ui.button("Add point") {
+                    let last = points.last().copied().unwrap_or(Vec3::ZERO);
+                    let direction = match points.len() {
+                        0 | 1 => Vec3::X,
+                        len => last - points[len - 2],
+                    };
+                    for step in 1..=points_per_segment {
+                        points.push(last + direction * step as f32);
+                    }
+                    changed = true;
+                }
+                ui.same_line();
+                if ui.button("Remove point") && points.len() > points_per_segment {
+                    points.truncate(points.len() - points_per_segment);
+                    changed = true;
+                }
+                ui.same_line();
+                if ui.button("Log points") {
+                    let points = points
+                        .iter()
+                        .map(|point| {
+                            format!("Vec3::new({:.3}, {:.3}, {:.3})", point.x, point.y, point.z)
+                        })
+                        .collect::<Vec<_>>();
+                    log::info!("{}: vec![{}]", follower.name, points.join(", "));
+                }
+
+                if changed {
+                    follower.edit_spline(|spline| spline.points = points);
+                }
+            });
+    }
+}
@@ -0,0 +1,171 @@
+use glam::{Mat4, Vec2, Vec3};
+
+use crate::{
+    asset_pipeline::materials::AlphaMode,
+    material_manager::MaterialId,
+    math::bounds::AABB,
+    model::{ModelPrimitive, Vertex},
+};
+
+/// Geometry of one primitive, built from code. Triangles are counter-clockwise when seen from
+/// the front, like in glTF.
+#[derive(Debug, Clone, Default)]
+pub struct MeshBuilder {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the index of the vertex
+    pub fn add_vertex(&mut self, position: Vec3, normal: Vec3, tex_coords: Vec2) -> u32 {
+        let index = self.vertices.len() as u32;
+        self.vertices.push(Vertex {
+            position,
+            normal,
+            tex_coords,
+            // Generated when the primitive is built
+            tangent: Vec3::ZERO,
+            joints: [0; 4],
+            weights: [0.0; 4],
+        });
+        index
+    }
+
+    pub fn add_triangle(&mut self, a: u32, b: u32, c: u32) {
+        self.indices.extend([a, b, c]);
+    }
+
+    /// Corners in counter-clockwise order
+    pub fn add_quad(&mut self, a: u32, b: u32, c: u32, d: u32) {
+        self.add_triangle(a, b, c);
+        self.add_triangle(a, c, d);
+    }
+
+    /// Samples `surface` on a grid of `columns` x `rows` quads. The surface returns the position
+    /// and the normal at UV coordinates from 0 to 1. The front faces the side that the cross
+    /// product of the U and V directions points to. Seams get their own vertices, so that the UVs
+    /// don't wrap around.
+    pub fn add_surface(&mut self, columns: u32, rows: u32, surface: impl Fn(Vec2) -> (Vec3, Vec3)) {
+        let columns = columns.max(1);
+        let rows = rows.max(1);
+        let first = self.vertices.len() as u32;
+
+        for row in 0..=rows {
+            for column in 0..=columns {
+                let uv = Vec2::new(column as f32 / columns as f32, row as f32 / rows as f32);
+                let (position, normal) = surface(uv);
+                self.add_vertex(position, normal.normalize_or_zero(), uv);
+            }
+        }
+
+        let index = |column: u32, row: u32| first + row * (columns + 1) + column;
+        for row in 0..rows {
+            for column in 0..columns {
+                self.add_quad(
+                    index(column, row),
+                    index(column + 1, row),
+                    index(column + 1, row + 1),
+                    index(column, row + 1),
+                );
+            }
+        }
+    }
+
+    /// Appends another mesh, e.g. to combine several shapes into one primitive
+    pub fn append(&mut self, other: &MeshBuilder, transform: Mat4) {
+        let offset = self.vertices.len() as u32;
+        let normal_matrix = transform.inverse().transpose();
+
+        self.vertices.extend(other.vertices.iter().map(|vertex| {
+            Vertex {
+                position: transform.transform_point3(vertex.position),
+                normal: normal_matrix
+                    .transform_vector3(vertex.normal)
+                    .normalize_or_zero(),
+                ..*vertex
+            }
+        }));
+        self.indices
+            .extend(other.indices.iter().map(|index| index + offset));
+    }
+
+    pub fn transformed(mut self, transform: Mat4) -> Self {
+        let normal_matrix = transform.inverse().transpose();
+
+        for vertex in &mut self.vertices {
+            vertex.position = transform.transform_point3(vertex.position);
+            vertex.normal = normal_matrix
+                .transform_vector3(vertex.normal)
+                .normalize_or_zero();
+        }
+
+        self
+    }
+
+    /// Turns the mesh inside out, e.g. to view a cylinder from the inside as a tunnel
+    #[allow(dead_code)]
+    pub fn flipped(mut self) -> Self {
+        for triangle in self.indices.chunks_exact_mut(3) {
+            triangle.swap(1, 2);
+        }
+
+        for vertex in &mut self.vertices {
+            vertex.normal = -vertex.normal;
+        }
+
+        self
+    }
+
+    /// Replaces the normals with the area weighted averages of the triangles around each
+    /// vertex. Vertices on seams aren't shared, so they keep a hard edge.
+    #[allow(dead_code)]
+    pub fn compute_normals(&mut self) {
+        let mut normals = vec![Vec3::ZERO; self.vertices.len()];
+
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [triangle[0], triangle[1], triangle[2]]
+                .map(|index| self.vertices[index as usize].position);
+            let normal = (b - a).cross(c - a);
+
+            for &index in triangle {
+                normals[index as usize] += normal;
+            }
+        }
+
+        for (vertex, normal) in self.vertices.iter_mut().zip(normals) {
+            vertex.normal = normal.normalize_or_zero();
+        }
+    }
+
+    /// Builds the primitive, with tangents generated from the normals and UVs
+    pub fn build(
+        self,
+        global_index: usize,
+        material_id: MaterialId,
+        alpha_mode: AlphaMode,
+    ) -> anyhow::Result<ModelPrimitive> {
+        anyhow::ensure!(!self.indices.is_empty(), "Generated mesh has no triangles");
+
+        let (min, max) = self.vertices.iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), vertex| (min.min(vertex.position), max.max(vertex.position)),
+        );
+
+        let mut primitive = ModelPrimitive {
+            global_index,
+            vertices: self.vertices,
+            indices: self.indices,
+            bounding_box: AABB::new(min, max),
+            material_id,
+            alpha_mode,
+            lods: Vec::new(),
+        };
+        primitive.generate_tangents()?;
+
+        Ok(primitive)
+    }
+}
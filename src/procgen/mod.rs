@@ -0,0 +1,3 @@
+pub mod mesh_builder;
+pub mod shapes;
+pub mod terrain;
@@ -0,0 +1,109 @@
+use std::f32::consts::{PI, TAU};
+
+use glam::{Mat4, Quat, Vec2, Vec3};
+
+use crate::procgen::mesh_builder::MeshBuilder;
+
+/// Grid on the XZ plane facing up, centered on the origin
+#[allow(dead_code)]
+pub fn plane(size: Vec2, subdivisions: u32) -> MeshBuilder {
+    let mut mesh = MeshBuilder::new();
+    mesh.add_surface(subdivisions, subdivisions, |uv| {
+        let position = Vec3::new((uv.x - 0.5) * size.x, 0.0, (0.5 - uv.y) * size.y);
+        (position, Vec3::Y)
+    });
+    mesh
+}
+
+/// Box centered on the origin. Each face has its own vertices and UVs.
+#[allow(dead_code)]
+pub fn cuboid(size: Vec3) -> MeshBuilder {
+    let face = plane(Vec2::ONE, 1).transformed(Mat4::from_translation(Vec3::Y * 0.5));
+    let mut mesh = MeshBuilder::new();
+
+    for normal in [Vec3::X, -Vec3::X, Vec3::Y, -Vec3::Y, Vec3::Z, -Vec3::Z] {
+        let rotation = Quat::from_rotation_arc(Vec3::Y, normal);
+        mesh.append(&face, Mat4::from_scale(size) * Mat4::from_quat(rotation));
+    }
+
+    mesh
+}
+
+/// UV sphere centered on the origin. `segments` go around the Y axis and `rings` from the top
+/// to the bottom.
+#[allow(dead_code)]
+pub fn sphere(radius: f32, segments: u32, rings: u32) -> MeshBuilder {
+    let mut mesh = MeshBuilder::new();
+    mesh.add_surface(segments, rings, |uv| {
+        let (sin_azimuth, cos_azimuth) = (uv.x * TAU).sin_cos();
+        let (sin_polar, cos_polar) = (uv.y * PI).sin_cos();
+        let normal = Vec3::new(sin_polar * cos_azimuth, cos_polar, sin_polar * sin_azimuth);
+        (normal * radius, normal)
+    });
+    mesh
+}
+
+/// Torus around the Y axis. `major_radius` is the distance from the origin to the center of the
+/// tube, and `minor_radius` the radius of the tube.
+#[allow(dead_code)]
+pub fn torus(major_radius: f32, minor_radius: f32, segments: u32, sides: u32) -> MeshBuilder {
+    let mut mesh = MeshBuilder::new();
+    mesh.add_surface(segments, sides, |uv| {
+        let (sin_azimuth, cos_azimuth) = (uv.x * TAU).sin_cos();
+        // Goes around the tube in the direction that keeps the front outside
+        let (sin_tube, cos_tube) = ((1.0 - uv.y) * TAU).sin_cos();
+        let normal = Vec3::new(cos_tube * cos_azimuth, sin_tube, cos_tube * sin_azimuth);
+        let center = Vec3::new(cos_azimuth, 0.0, sin_azimuth) * major_radius;
+        (center + normal * minor_radius, normal)
+    });
+    mesh
+}
+
+/// Capped cylinder along the Y axis, centered on the origin. Use `cylinder_side` for a tube
+/// without caps.
+#[allow(dead_code)]
+pub fn cylinder(radius: f32, height: f32, segments: u32) -> MeshBuilder {
+    let mut mesh = cylinder_side(radius, height, segments);
+    add_cap(&mut mesh, radius, height * 0.5, segments, true);
+    add_cap(&mut mesh, radius, -height * 0.5, segments, false);
+    mesh
+}
+
+/// Side of a cylinder along the Y axis. Flip it to get a tunnel.
+#[allow(dead_code)]
+pub fn cylinder_side(radius: f32, height: f32, segments: u32) -> MeshBuilder {
+    let mut mesh = MeshBuilder::new();
+    mesh.add_surface(segments, 1, |uv| {
+        let (sin, cos) = (uv.x * TAU).sin_cos();
+        let normal = Vec3::new(cos, 0.0, sin);
+        let position = normal * radius + Vec3::Y * (0.5 - uv.y) * height;
+        (position, normal)
+    });
+    mesh
+}
+
+/// Disc of triangles around a center vertex, with planar UVs
+fn add_cap(mesh: &mut MeshBuilder, radius: f32, y: f32, segments: u32, facing_up: bool) {
+    let segments = segments.max(3);
+    let normal = if facing_up { Vec3::Y } else { -Vec3::Y };
+    let center = mesh.add_vertex(Vec3::Y * y, normal, Vec2::splat(0.5));
+
+    let rim: Vec<u32> = (0..=segments)
+        .map(|segment| {
+            let (sin, cos) = (segment as f32 / segments as f32 * TAU).sin_cos();
+            mesh.add_vertex(
+                Vec3::new(cos * radius, y, sin * radius),
+                normal,
+                Vec2::new(0.5 + cos * 0.5, 0.5 + sin * 0.5),
+            )
+        })
+        .collect();
+
+    for edge in rim.windows(2) {
+        if facing_up {
+            mesh.add_triangle(center, edge[1], edge[0]);
+        } else {
+            mesh.add_triangle(center, edge[0], edge[1]);
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use glam::{Vec2, Vec3};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    asset_pipeline::materials::{
+        AlphaMode, ColorSpace, DynamicMaterialTextures, MaterialParameters, MaterialTexture,
+        PbrMaterialData, TerrainLayer,
+    },
+    material_manager::{MaterialId, MaterialManager},
+    procgen::mesh_builder::MeshBuilder,
+};
+
+/// Grayscale height image, sampled with bilinear filtering. Values are from 0 to 1.
+pub struct Heightmap {
+    width: u32,
+    height: u32,
+    samples: Vec<f32>,
+}
+
+impl Heightmap {
+    /// 16-bit images are loaded at full precision, to avoid terracing on gentle slopes
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let image = image::open(path)
+            .with_context(|| format!("Failed to load heightmap {}", path.display()))?
+            .into_luma16();
+
+        anyhow::ensure!(
+            image.width() >= 2 && image.height() >= 2,
+            "Heightmap {} is too small",
+            path.display()
+        );
+
+        Ok(Self {
+            width: image.width(),
+            height: image.height(),
+            samples: image
+                .pixels()
+                .map(|pixel| pixel.0[0] as f32 / u16::MAX as f32)
+                .collect(),
+        })
+    }
+
+    /// UV (0, 0) is the top left corner of the image. Clamped at the edges.
+    pub fn sample(&self, uv: Vec2) -> f32 {
+        let max = Vec2::new((self.width - 1) as f32, (self.height - 1) as f32);
+        let position = (uv * max).clamp(Vec2::ZERO, max);
+        let x0 = (position.x.floor() as u32).min(self.width - 2);
+        let y0 = (position.y.floor() as u32).min(self.height - 2);
+        let fraction = position - Vec2::new(x0 as f32, y0 as f32);
+
+        let at = |x: u32, y: u32| self.samples[(y * self.width + x) as usize];
+        let top = at(x0, y0) + (at(x0 + 1, y0) - at(x0, y0)) * fraction.x;
+        let bottom = at(x0, y0 + 1) + (at(x0 + 1, y0 + 1) - at(x0, y0 + 1)) * fraction.x;
+        top + (bottom - top) * fraction.y
+    }
+}
+
+/// Serialized form of a `Terrain`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TerrainDescription {
+    pub heightmap: PathBuf,
+    /// Extent on the X and Z axes, centered on the origin. The top of the heightmap is towards -Z.
+    pub size: Vec2,
+    /// Height of white in the heightmap, black is at zero
+    pub height: f32,
+    /// Number of chunks along each axis. Each chunk is culled on its own.
+    #[serde(default = "default_chunks")]
+    pub chunks: u32,
+    /// Number of quads along each axis of a chunk
+    #[serde(default = "default_chunk_resolution")]
+    pub chunk_resolution: u32,
+    /// The first layer is also used where none of the layers apply
+    pub layers: Vec<TerrainLayerDescription>,
+}
+
+fn default_chunks() -> u32 {
+    8
+}
+
+fn default_chunk_resolution() -> u32 {
+    32
+}
+
+/// Serialized form of a `TerrainLayer`, with paths to image files
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TerrainLayerDescription {
+    pub base_color: Option<PathBuf>,
+    pub normal: Option<PathBuf>,
+    pub ao_roughness_metallic: Option<PathBuf>,
+    pub height_range: Vec2,
+    pub slope_range: Vec2,
+    pub height_blend: f32,
+    pub slope_blend: f32,
+    pub tile_size: f32,
+}
+
+impl Default for TerrainLayerDescription {
+    fn default() -> Self {
+        Self {
+            base_color: None,
+            normal: None,
+            ao_roughness_metallic: None,
+            height_range: Vec2::new(f32::MIN, f32::MAX),
+            slope_range: Vec2::new(0.0, 1.0),
+            height_blend: 1.0,
+            slope_blend: 0.1,
+            tile_size: 4.0,
+        }
+    }
+}
+
+impl TerrainLayerDescription {
+    fn load(&self) -> anyhow::Result<TerrainLayer> {
+        let load_texture = |path: &Option<PathBuf>, color_space| {
+            path.as_deref()
+                .map(|path| MaterialTexture::load(path, color_space))
+                .transpose()
+        };
+
+        Ok(TerrainLayer {
+            base_color: load_texture(&self.base_color, ColorSpace::Srgb)?,
+            normal: load_texture(&self.normal, ColorSpace::Linear)?,
+            ao_roughness_metallic: load_texture(&self.ao_roughness_metallic, ColorSpace::Linear)?,
+            height_range: self.height_range,
+            slope_range: self.slope_range,
+            height_blend: self.height_blend,
+            slope_blend: self.slope_blend,
+            tile_size: self.tile_size,
+        })
+    }
+}
+
+/// Heightmap terrain, split into a grid of chunks that become separate models
+pub struct Terrain {
+    pub heightmap: Heightmap,
+    pub size: Vec2,
+    pub height: f32,
+    pub chunks: u32,
+    pub chunk_resolution: u32,
+    pub material_id: MaterialId,
+}
+
+impl Terrain {
+    /// Loads the heightmap and the layer textures, and adds the terrain material
+    pub fn load(
+        name: &str,
+        description: &TerrainDescription,
+        material_manager: &mut MaterialManager,
+    ) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            !description.layers.is_empty(),
+            "Terrain '{}' has no layers",
+            name
+        );
+
+        let heightmap = Heightmap::load(&description.heightmap)?;
+        let terrain_layers = description
+            .layers
+            .par_iter()
+            .map(|layer| layer.load())
+            .collect::<anyhow::Result<Vec<_>>>()
+            .with_context(|| format!("Failed to load the layers of terrain '{}'", name))?;
+
+        let material_id = material_manager.add_material(PbrMaterialData {
+            name: name.to_string(),
+            base_color: None,
+            normal: None,
+            ao_roughness_metallic: None,
+            emissive: None,
+            emissive_factor: Vec3::ZERO,
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+            parameters: MaterialParameters::default(),
+            dynamic_textures: DynamicMaterialTextures::default(),
+            terrain_layers,
+            atlas: None,
+        });
+
+        Ok(Self {
+            heightmap,
+            size: description.size,
+            height: description.height,
+            chunks: description.chunks.max(1),
+            chunk_resolution: description.chunk_resolution.max(1),
+            material_id,
+        })
+    }
+
+    /// Height at a point on the XZ plane, in the terrain's own space
+    pub fn height_at(&self, xz: Vec2) -> f32 {
+        self.heightmap.sample(xz / self.size + 0.5) * self.height
+    }
+
+    /// Central differences over one heightmap pixel
+    pub fn normal_at(&self, xz: Vec2) -> Vec3 {
+        let step = self.size
+            / Vec2::new(
+                (self.heightmap.width - 1) as f32,
+                (self.heightmap.height - 1) as f32,
+            );
+        let dx = (self.height_at(xz + Vec2::new(step.x, 0.0))
+            - self.height_at(xz - Vec2::new(step.x, 0.0)))
+            / (2.0 * step.x);
+        let dz = (self.height_at(xz + Vec2::new(0.0, step.y))
+            - self.height_at(xz - Vec2::new(0.0, step.y)))
+            / (2.0 * step.y);
+
+        Vec3::new(-dx, 1.0, -dz).normalize()
+    }
+
+    /// Mesh of one chunk, in the terrain's space. Neighboring chunks sample the same heights
+    /// on their shared edge, so there are no cracks between them.
+    pub fn chunk_mesh(&self, column: u32, row: u32) -> MeshBuilder {
+        let chunk_size = self.size / self.chunks as f32;
+        let min = -self.size * 0.5 + Vec2::new(column as f32, row as f32) * chunk_size;
+
+        let mut mesh = MeshBuilder::new();
+        // V runs towards -Z like in `shapes::plane`, so the front faces up
+        mesh.add_surface(self.chunk_resolution, self.chunk_resolution, |uv| {
+            let xz = Vec2::new(
+                min.x + uv.x * chunk_size.x,
+                min.y + (1.0 - uv.y) * chunk_size.y,
+            );
+            let position = Vec3::new(xz.x, self.height_at(xz), xz.y);
+            (position, self.normal_at(xz))
+        });
+        mesh
+    }
+}
@@ -0,0 +1,208 @@
+use anyhow::Context;
+
+use crate::rendering::render_material_manager::MAX_MATERIAL_SAMPLERS;
+
+/// Material textures are bound as a single binding array of at least this size when the adapter
+/// allows. Demos with more textures ask for a bigger array.
+const PREFERRED_TEXTURE_COUNT: u32 = 128;
+/// Enough for the default textures and a handful of materials. Textures beyond the limit are
+/// replaced with the defaults.
+const MIN_TEXTURE_COUNT: u32 = 32;
+/// Samplers that passes bind next to the material samplers, like the shadow and environment
+/// samplers of the transparent pass
+const MAX_PASS_SAMPLERS: u32 = 2;
+
+/// Features that the renderer can't work without. Optional features are enabled in
+/// `Renderer::create` when the adapter supports them.
+pub fn required_features() -> wgpu::Features {
+    wgpu::Features::MULTI_DRAW_INDIRECT
+        | wgpu::Features::INDIRECT_FIRST_INSTANCE
+        | wgpu::Features::TEXTURE_BINDING_ARRAY
+        | wgpu::Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING
+}
+
+/// Picks the first adapter whose name contains `name`, ignoring case. When there's no name or no
+/// match, picks the adapter with the best score, which prefers discrete GPUs.
+pub async fn request_adapter(
+    instance: &wgpu::Instance,
+    surface: Option<&wgpu::Surface<'_>>,
+    name: Option<&str>,
+) -> anyhow::Result<wgpu::Adapter> {
+    let adapters = instance
+        .enumerate_adapters(wgpu::Backends::all())
+        .into_iter()
+        .filter(|adapter| surface.is_none_or(|surface| adapter.is_surface_supported(surface)))
+        .collect::<Vec<_>>();
+
+    for adapter in &adapters {
+        log::debug!(
+            "Found adapter {} (score {:?})",
+            describe_adapter(&adapter.get_info()),
+            adapter_score(adapter)
+        );
+    }
+
+    if let Some(name) = name {
+        let lowercase_name = name.to_lowercase();
+        let matching = adapters.iter().position(|adapter| {
+            adapter
+                .get_info()
+                .name
+                .to_lowercase()
+                .contains(&lowercase_name)
+        });
+
+        match matching {
+            Some(index) => return Ok(adapters.into_iter().nth(index).unwrap()),
+            None => {
+                let available = adapters
+                    .iter()
+                    .map(|adapter| adapter.get_info().name)
+                    .collect::<Vec<_>>();
+                log::warn!(
+                    "No adapter matching '{}', picking one automatically. Available adapters: {:?}",
+                    name,
+                    available
+                );
+            }
+        }
+    }
+
+    let mut best: Option<(u32, wgpu::Adapter)> = None;
+    for adapter in adapters {
+        let Some(score) = adapter_score(&adapter) else {
+            continue;
+        };
+
+        // Ties go to the first adapter, which is what the backend lists as the preferred one
+        if best
+            .as_ref()
+            .is_none_or(|(best_score, _)| score > *best_score)
+        {
+            best = Some((score, adapter));
+        }
+    }
+
+    if let Some((_, adapter)) = best {
+        return Ok(adapter);
+    }
+
+    // Enumeration isn't supported everywhere, and none of the adapters may support everything
+    // we need. Let wgpu pick one, and report what's missing when the device is created.
+    instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            compatible_surface: surface,
+            force_fallback_adapter: false,
+        })
+        .await
+        .context("Failed to find a GPU adapter")
+}
+
+/// Higher is better. None if the adapter doesn't support the required features.
+fn adapter_score(adapter: &wgpu::Adapter) -> Option<u32> {
+    if !adapter.features().contains(required_features()) {
+        return None;
+    }
+
+    let type_score = match adapter.get_info().device_type {
+        wgpu::DeviceType::DiscreteGpu => 3,
+        wgpu::DeviceType::IntegratedGpu => 2,
+        wgpu::DeviceType::VirtualGpu => 1,
+        wgpu::DeviceType::Cpu | wgpu::DeviceType::Other => 0,
+    };
+
+    // Adapters that can bind every texture win over ones of the same type that can't
+    let texture_count_score = (adapter.limits().max_binding_array_elements_per_shader_stage
+        >= PREFERRED_TEXTURE_COUNT) as u32;
+
+    Some(type_score * 2 + texture_count_score)
+}
+
+fn describe_adapter(info: &wgpu::AdapterInfo) -> String {
+    format!(
+        "{} ({:?}, {:?}, driver: {} {})",
+        info.name, info.device_type, info.backend, info.driver, info.driver_info
+    )
+}
+
+pub fn log_adapter_info(adapter: &wgpu::Adapter) {
+    let limits = adapter.limits();
+
+    log::info!("Using adapter {}", describe_adapter(&adapter.get_info()));
+    log::info!(
+        "Max texture size: {}, max binding array elements: {}, max bind groups: {}, max storage buffer size: {}",
+        limits.max_texture_dimension_2d,
+        limits.max_binding_array_elements_per_shader_stage,
+        limits.max_bind_groups,
+        limits.max_storage_buffer_binding_size
+    );
+    log::debug!("Adapter features: {:?}", adapter.features());
+    log::debug!("Adapter limits: {:#?}", limits);
+}
+
+/// The limits the device is created with. Asks for room for `required_texture_count` material
+/// textures, or fewer when the adapter can't bind that many, and fails if anything else is
+/// missing.
+pub fn device_limits(
+    adapter: &wgpu::Adapter,
+    required_texture_count: u32,
+) -> anyhow::Result<wgpu::Limits> {
+    let supported = adapter.limits();
+
+    let missing_features = required_features().difference(adapter.features());
+    anyhow::ensure!(
+        missing_features.is_empty(),
+        "The GPU doesn't support the required features: {:?}",
+        missing_features
+    );
+
+    let max_texture_count = supported
+        .max_binding_array_elements_per_shader_stage
+        .min(required_texture_count.max(PREFERRED_TEXTURE_COUNT));
+    anyhow::ensure!(
+        max_texture_count >= MIN_TEXTURE_COUNT,
+        "The GPU can only bind {} textures in an array, at least {} are needed",
+        max_texture_count,
+        MIN_TEXTURE_COUNT
+    );
+
+    if max_texture_count < required_texture_count {
+        log::warn!(
+            "The GPU can only bind {} of the {} material textures, some materials will use default textures",
+            max_texture_count,
+            required_texture_count
+        );
+    }
+
+    // Samplers in the material binding array count towards the per-stage sampler limit too
+    let sampler_count = MAX_MATERIAL_SAMPLERS + MAX_PASS_SAMPLERS;
+    anyhow::ensure!(
+        supported.max_samplers_per_shader_stage >= sampler_count,
+        "The GPU can only bind {} samplers per shader stage, {} are needed for the {} material samplers",
+        supported.max_samplers_per_shader_stage,
+        sampler_count,
+        MAX_MATERIAL_SAMPLERS
+    );
+
+    let limits = wgpu::Limits {
+        max_binding_array_elements_per_shader_stage: max_texture_count,
+        max_binding_array_sampler_elements_per_shader_stage: MAX_MATERIAL_SAMPLERS,
+        max_samplers_per_shader_stage: sampler_count,
+        // The transparent pass binds everything the geometry and lighting passes do
+        max_bind_groups: 8,
+        ..Default::default()
+    };
+
+    let mut missing_limits = Vec::new();
+    limits.check_limits_with_fail_fn(&supported, false, |name, requested, allowed| {
+        missing_limits.push(format!("{} (needs {}, has {})", name, requested, allowed));
+    });
+    anyhow::ensure!(
+        missing_limits.is_empty(),
+        "The GPU doesn't support the required limits: {}",
+        missing_limits.join(", ")
+    );
+
+    Ok(limits)
+}
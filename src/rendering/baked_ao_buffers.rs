@@ -0,0 +1,188 @@
+use bytemuck::{Pod, Zeroable};
+
+use crate::{
+    asset_pipeline::baked_ao::BakedAo,
+    demo_part::DemoPartId,
+    rendering::{
+        instancing::DrawableManager, mesh_buffers::MeshBuffers,
+        util::bind_group_builder::BindGroupBuilder,
+    },
+    scene_graph::object3d::ObjectId,
+};
+
+/// Where the baked AO of a drawable's vertices is. Vertices outside the range, e.g. those of
+/// authored LODs or skinned meshes, aren't occluded. This should match BakedAoRange in
+/// deferred/geometry.wgsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Pod, Zeroable)]
+struct BakedAoRange {
+    /// Vertex offset of the baked mesh in the vertex megabuffer
+    vertex_offset: u32,
+    vertex_count: u32,
+    /// Index of the value of the first vertex
+    first_value: u32,
+}
+
+impl BakedAoRange {
+    const NONE: BakedAoRange = BakedAoRange {
+        vertex_offset: 0,
+        vertex_count: 0,
+        first_value: 0,
+    };
+}
+
+/// Baked ambient occlusion of the active part for the geometry pass. The values are uploaded
+/// when the part changes, and the range of each drawable slot whenever the slots change.
+pub struct BakedAoBuffers {
+    device: wgpu::Device,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    values: wgpu::Buffer,
+    ranges_buffer: wgpu::Buffer,
+    /// Contents of `ranges_buffer`, by drawable slot
+    ranges: Vec<BakedAoRange>,
+    /// The baked meshes of each object, and their ranges
+    mesh_ranges: Vec<(ObjectId, u32, BakedAoRange)>,
+    uploaded_part: Option<DemoPartId>,
+}
+
+impl BakedAoBuffers {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let values = create_values_buffer(device, 0);
+        let ranges_buffer = create_ranges_buffer(device, 0);
+        let (bind_group_layout, bind_group) =
+            Self::bind_group_builder(&values, &ranges_buffer).build(device);
+
+        Self {
+            device: device.clone(),
+            bind_group_layout,
+            bind_group,
+            values,
+            ranges_buffer,
+            ranges: Vec::new(),
+            mesh_ranges: Vec::new(),
+            uploaded_part: None,
+        }
+    }
+
+    fn bind_group_builder<'a>(
+        values: &'a wgpu::Buffer,
+        ranges: &'a wgpu::Buffer,
+    ) -> BindGroupBuilder<'a> {
+        BindGroupBuilder::new("Baked AO", wgpu::ShaderStages::VERTEX)
+            .storage_r(0, "Baked AO range buffer", ranges.as_entire_binding())
+            .storage_r(1, "Baked AO value buffer", values.as_entire_binding())
+    }
+
+    fn rebuild_bind_group(&mut self) {
+        self.bind_group = Self::bind_group_builder(&self.values, &self.ranges_buffer)
+            .build_with_layout(&self.device, &self.bind_group_layout);
+    }
+
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    /// Uploads the values again on the next update, e.g. after the meshes have moved in the
+    /// vertex megabuffer
+    pub fn invalidate(&mut self) {
+        self.uploaded_part = None;
+    }
+
+    pub fn update(
+        &mut self,
+        queue: &wgpu::Queue,
+        part: DemoPartId,
+        baked_ao: &BakedAo,
+        mesh_buffers: &MeshBuffers,
+        drawable_manager: &DrawableManager,
+    ) {
+        if self.uploaded_part != Some(part) {
+            self.upload_values(queue, baked_ao, mesh_buffers);
+            self.uploaded_part = Some(part);
+        }
+
+        let drawables = drawable_manager.drawables();
+        let mut ranges = vec![BakedAoRange::NONE; drawables.len()];
+        for (object, mesh_index, range) in &self.mesh_ranges {
+            for slot in drawable_manager.object_slots(*object) {
+                if drawables[slot].primitive_index == *mesh_index {
+                    ranges[slot] = *range;
+                }
+            }
+        }
+
+        if ranges == self.ranges {
+            return;
+        }
+
+        let range_size = std::mem::size_of::<BakedAoRange>() as u64;
+        if ranges.len() as u64 * range_size > self.ranges_buffer.size() {
+            self.ranges_buffer = create_ranges_buffer(&self.device, ranges.len() as u64);
+            self.rebuild_bind_group();
+        }
+
+        if !ranges.is_empty() {
+            queue.write_buffer(&self.ranges_buffer, 0, bytemuck::cast_slice(&ranges));
+        }
+        self.ranges = ranges;
+    }
+
+    fn upload_values(
+        &mut self,
+        queue: &wgpu::Queue,
+        baked_ao: &BakedAo,
+        mesh_buffers: &MeshBuffers,
+    ) {
+        let mut values = Vec::new();
+        self.mesh_ranges.clear();
+
+        for mesh in &baked_ao.meshes {
+            let Some(mesh_info) = mesh_buffers.mesh_info(mesh.mesh_index) else {
+                continue;
+            };
+
+            let range = BakedAoRange {
+                vertex_offset: mesh_info.vertex_offset,
+                vertex_count: mesh.values.len() as u32,
+                first_value: values.len() as u32,
+            };
+            values.extend_from_slice(&mesh.values);
+            self.mesh_ranges.push((mesh.object, mesh.mesh_index, range));
+        }
+
+        if values.len() as u64 * std::mem::size_of::<f32>() as u64 > self.values.size() {
+            self.values = create_values_buffer(&self.device, values.len() as u64);
+            self.rebuild_bind_group();
+        }
+
+        if !values.is_empty() {
+            queue.write_buffer(&self.values, 0, bytemuck::cast_slice(&values));
+        }
+        // Every slot needs its range updated
+        self.ranges.clear();
+    }
+}
+
+fn create_values_buffer(device: &wgpu::Device, count: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Baked AO value buffer"),
+        // Empty storage buffers can't be bound
+        size: count.max(1) * std::mem::size_of::<f32>() as u64,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_ranges_buffer(device: &wgpu::Device, count: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Baked AO range buffer"),
+        size: count.max(1) * std::mem::size_of::<BakedAoRange>() as u64,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
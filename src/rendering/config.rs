@@ -1,12 +1,271 @@
+use serde::{Deserialize, Serialize};
+
+use crate::asset_pipeline::mesh_baker::{BakeOptions, VertexFormat};
+
+/// Operator used to map HDR scene color to the displayable range
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Tonemapper {
+    /// Narkowicz's fit of the ACES filmic curve
+    #[default]
+    Aces,
+    Reinhard,
+    /// Clamp to [0, 1]
+    None,
+}
+
+impl Tonemapper {
+    /// Must match the constants in post/tonemap.wgsl
+    pub fn shader_index(self) -> u32 {
+        match self {
+            Tonemapper::Aces => 0,
+            Tonemapper::Reinhard => 1,
+            Tonemapper::None => 2,
+        }
+    }
+}
+
+/// How alpha blended drawables are composited over the opaque scene
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransparencyMode {
+    /// Sorted back to front by the center of their bounds, which is exact for non-overlapping
+    /// drawables
+    #[default]
+    Sorted,
+    /// Weighted blended order-independent transparency. Approximate, but doesn't pop when
+    /// drawables overlap or change order, which suits particles and layered glass.
+    WeightedBlended,
+}
+
+impl TransparencyMode {
+    pub const ALL: [TransparencyMode; 2] =
+        [TransparencyMode::Sorted, TransparencyMode::WeightedBlended];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TransparencyMode::Sorted => "Sorted",
+            TransparencyMode::WeightedBlended => "Weighted blended",
+        }
+    }
+}
+
+/// Bounds that drawables are tested against the view frustum with
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CullingBounds {
+    /// Exact for boxy meshes, but transforms all eight corners
+    Aabb,
+    /// Cheapest, and doesn't grow when the drawable rotates. Keeps more drawables near the
+    /// edges of the view.
+    Sphere,
+    /// Rejects most drawables with the sphere, and tests the ones it keeps with the bounding box
+    #[default]
+    SphereThenAabb,
+}
+
+impl CullingBounds {
+    pub const ALL: [CullingBounds; 3] = [
+        CullingBounds::Aabb,
+        CullingBounds::Sphere,
+        CullingBounds::SphereThenAabb,
+    ];
+
+    /// Must match the constants in frustum_culling.wgsl
+    pub fn shader_index(self) -> u32 {
+        match self {
+            CullingBounds::Aabb => 0,
+            CullingBounds::Sphere => 1,
+            CullingBounds::SphereThenAabb => 2,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CullingBounds::Aabb => "AABB",
+            CullingBounds::Sphere => "Sphere",
+            CullingBounds::SphereThenAabb => "Sphere, then AABB",
+        }
+    }
+}
+
+/// Starting point for `QualitySettings`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QualityPreset {
+    /// For slow GPUs. Renders at half resolution, and starts with SSAO, SSR, volumetric fog and
+    /// depth of field disabled.
+    Low,
+    Medium,
+    #[default]
+    High,
+}
+
+impl QualityPreset {
+    pub const ALL: [QualityPreset; 3] = [
+        QualityPreset::Low,
+        QualityPreset::Medium,
+        QualityPreset::High,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            QualityPreset::Low => "Low",
+            QualityPreset::Medium => "Medium",
+            QualityPreset::High => "High",
+        }
+    }
+}
+
+/// Costly rendering features, which are read from the settings file at startup. Most of them
+/// can also be adjusted from the UI of the pass they belong to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QualitySettings {
+    /// Width and height of each shadow cascade
+    pub shadow_map_size: u32,
+    /// Samples per pixel, at most 32
+    pub ssao_samples: u32,
+    pub bloom_iterations: u32,
+    /// Resolution of the scene relative to the output, from 0.5 to 2.0
+    pub resolution_scale: f32,
+    pub occlusion_culling: bool,
+    /// Multiplies the distances where lower LODs are switched to
+    pub lod_distance_scale: f32,
+    /// Initial state of SSAO, SSR, volumetric fog and depth of field
+    pub expensive_passes: bool,
+    /// Maximum anisotropic filtering of material textures, from 1 (off) to 16
+    pub anisotropy: u16,
+    /// Bakes meshes with `VertexFormat::Quantized`, which halves the size of the vertex buffer
+    pub quantized_vertices: bool,
+    /// Draws the G-buffer from meshlets culled on the GPU, instead of whole primitives
+    pub meshlets: bool,
+    /// VRAM for streamed material textures. Their lowest mips are loaded regardless.
+    pub texture_budget_mb: u32,
+}
+
+impl QualitySettings {
+    pub fn preset(preset: QualityPreset) -> Self {
+        match preset {
+            QualityPreset::Low => Self {
+                shadow_map_size: 512,
+                ssao_samples: 4,
+                bloom_iterations: 1,
+                resolution_scale: 0.5,
+                occlusion_culling: true,
+                lod_distance_scale: 0.5,
+                expensive_passes: false,
+                anisotropy: 4,
+                quantized_vertices: true,
+                meshlets: false,
+                texture_budget_mb: 256,
+            },
+            QualityPreset::Medium => Self {
+                shadow_map_size: 1024,
+                ssao_samples: 8,
+                bloom_iterations: 2,
+                resolution_scale: 0.75,
+                occlusion_culling: true,
+                lod_distance_scale: 0.75,
+                expensive_passes: true,
+                anisotropy: 8,
+                quantized_vertices: false,
+                meshlets: false,
+                texture_budget_mb: 512,
+            },
+            QualityPreset::High => Self {
+                shadow_map_size: 2048,
+                ssao_samples: 16,
+                bloom_iterations: 2,
+                resolution_scale: 1.0,
+                occlusion_culling: true,
+                lod_distance_scale: 1.0,
+                expensive_passes: true,
+                anisotropy: 16,
+                quantized_vertices: false,
+                meshlets: false,
+                texture_budget_mb: 1024,
+            },
+        }
+    }
+}
+
+impl Default for QualitySettings {
+    fn default() -> Self {
+        Self::preset(QualityPreset::default())
+    }
+}
+
+/// Renderer options chosen at launch
+#[derive(Debug, Clone, Default)]
+pub struct RendererOptions {
+    /// Picks the first GPU whose name contains this, ignoring case
+    pub adapter_name: Option<String>,
+    pub quality: QualitySettings,
+    pub transparency: TransparencyMode,
+}
+
+impl RendererOptions {
+    /// Options for baking the meshes the renderer is created with
+    pub fn bake_options(&self) -> BakeOptions {
+        let vertex_format = if self.quality.quantized_vertices {
+            VertexFormat::Quantized
+        } else {
+            VertexFormat::Full
+        };
+
+        BakeOptions {
+            vertex_format,
+            meshlets: self.quality.meshlets,
+            ..Default::default()
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct RenderConfig {
     pub use_multi_draw_indirect_count: bool,
+    /// BCn compressed textures are used when supported, otherwise RGBA8 is used instead
+    pub use_bc_texture_compression: bool,
+    /// GPU pass timings need timestamp queries written directly into command encoders
+    pub use_gpu_timestamps: bool,
+    pub tonemapper: Tonemapper,
+    pub exposure: f32,
+    pub transparency: TransparencyMode,
+    /// Initial bounds used by frustum culling, can be changed from the UI
+    pub culling_bounds: CullingBounds,
+    /// Fifo waits for VSync, Mailbox replaces the queued frame without waiting, Immediate may
+    /// tear. Falls back to Fifo, which is always supported. Can be changed from the UI.
+    pub present_mode: wgpu::PresentMode,
+    pub quality: QualitySettings,
+    /// Size of the material texture binding array, lower on adapters that can't bind 128
+    pub max_texture_count: u32,
+}
+
+impl RenderConfig {
+    pub fn with_quality(quality: QualitySettings) -> Self {
+        Self {
+            quality,
+            ..Default::default()
+        }
+    }
+
+    /// Initial state of the passes that lower quality settings can disable
+    pub fn expensive_passes_enabled(&self) -> bool {
+        self.quality.expensive_passes
+    }
 }
 
 impl Default for RenderConfig {
     fn default() -> Self {
         Self {
             use_multi_draw_indirect_count: false,
+            use_bc_texture_compression: false,
+            use_gpu_timestamps: false,
+            tonemapper: Tonemapper::default(),
+            exposure: 1.0,
+            transparency: TransparencyMode::default(),
+            culling_bounds: CullingBounds::default(),
+            present_mode: wgpu::PresentMode::Fifo,
+            quality: QualitySettings::default(),
+            max_texture_count: 128,
         }
     }
 }
@@ -0,0 +1,268 @@
+use bytemuck::{Pod, Zeroable};
+use glam::{vec3, Mat4, Vec3, Vec4};
+
+use crate::{
+    math::bounds::AABB,
+    rendering::passes::shadow_pass::CASCADE_COLORS,
+    scene_graph::{light::LightKind, scene::Scene},
+};
+
+const CIRCLE_SEGMENTS: usize = 32;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct DebugVertex {
+    pub position: Vec3,
+    pub color: Vec4,
+}
+
+/// Immediate-mode debug shapes. Everything drawn during a frame is rendered as lines by
+/// `DebugDrawPass` at the end of the frame and then cleared.
+pub struct DebugDraw {
+    depth_tested: Vec<DebugVertex>,
+    overlay: Vec<DebugVertex>,
+    depth_test: bool,
+}
+
+impl DebugDraw {
+    pub fn new() -> Self {
+        Self {
+            depth_tested: Vec::new(),
+            overlay: Vec::new(),
+            depth_test: true,
+        }
+    }
+
+    /// Affects all shapes drawn after this call. Shapes without depth testing are drawn on top of
+    /// everything.
+    pub fn set_depth_test(&mut self, depth_test: bool) {
+        self.depth_test = depth_test;
+    }
+
+    pub fn line(&mut self, a: Vec3, b: Vec3, color: Vec4) {
+        let vertices = if self.depth_test {
+            &mut self.depth_tested
+        } else {
+            &mut self.overlay
+        };
+
+        vertices.push(DebugVertex { position: a, color });
+        vertices.push(DebugVertex { position: b, color });
+    }
+
+    /// Draws the box transformed by `transform`, e.g. an object's world matrix
+    pub fn aabb(&mut self, aabb: &AABB, transform: Mat4, color: Vec4) {
+        let corners = [
+            vec3(aabb.min.x, aabb.min.y, aabb.min.z),
+            vec3(aabb.max.x, aabb.min.y, aabb.min.z),
+            vec3(aabb.min.x, aabb.max.y, aabb.min.z),
+            vec3(aabb.max.x, aabb.max.y, aabb.min.z),
+            vec3(aabb.min.x, aabb.min.y, aabb.max.z),
+            vec3(aabb.max.x, aabb.min.y, aabb.max.z),
+            vec3(aabb.min.x, aabb.max.y, aabb.max.z),
+            vec3(aabb.max.x, aabb.max.y, aabb.max.z),
+        ]
+        .map(|corner| transform.transform_point3(corner));
+
+        self.box_edges(&corners, color);
+    }
+
+    /// Three great circles around the center
+    pub fn sphere(&mut self, center: Vec3, radius: f32, color: Vec4) {
+        self.circle(center, Vec3::X, Vec3::Y, radius, color);
+        self.circle(center, Vec3::Y, Vec3::Z, radius, color);
+        self.circle(center, Vec3::Z, Vec3::X, radius, color);
+    }
+
+    /// Circle spanned by two perpendicular unit vectors
+    pub fn circle(&mut self, center: Vec3, u: Vec3, v: Vec3, radius: f32, color: Vec4) {
+        let point = |segment: usize| {
+            let angle = segment as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+            center + (u * angle.cos() + v * angle.sin()) * radius
+        };
+
+        for segment in 0..CIRCLE_SEGMENTS {
+            self.line(point(segment), point(segment + 1), color);
+        }
+    }
+
+    /// Cone opening from `apex` along `direction`, e.g. for spot lights
+    pub fn cone(&mut self, apex: Vec3, direction: Vec3, length: f32, angle: f32, color: Vec4) {
+        let direction = direction.normalize();
+        let (u, v) = direction.any_orthonormal_pair();
+        let center = apex + direction * length * angle.cos();
+        let radius = length * angle.sin();
+
+        self.circle(center, u, v, radius, color);
+        for side in [u, -u, v, -v] {
+            self.line(apex, center + side * radius, color);
+        }
+    }
+
+    /// Draws the frustum of a view-projection matrix
+    pub fn frustum(&mut self, view_proj: Mat4, color: Vec4) {
+        let inverse = view_proj.inverse();
+
+        // Depth goes from 0 (near) to 1 (far)
+        let corners = [
+            vec3(-1.0, -1.0, 0.0),
+            vec3(1.0, -1.0, 0.0),
+            vec3(-1.0, 1.0, 0.0),
+            vec3(1.0, 1.0, 0.0),
+            vec3(-1.0, -1.0, 1.0),
+            vec3(1.0, -1.0, 1.0),
+            vec3(-1.0, 1.0, 1.0),
+            vec3(1.0, 1.0, 1.0),
+        ]
+        .map(|corner| inverse.project_point3(corner));
+
+        self.box_edges(&corners, color);
+    }
+
+    /// Corners are indexed by their bits: X is bit 0, Y bit 1 and Z bit 2
+    fn box_edges(&mut self, corners: &[Vec3; 8], color: Vec4) {
+        for i in 0..8 {
+            for bit in [1, 2, 4] {
+                if i & bit == 0 {
+                    self.line(corners[i], corners[i | bit], color);
+                }
+            }
+        }
+    }
+
+    pub fn depth_tested_vertices(&self) -> &[DebugVertex] {
+        &self.depth_tested
+    }
+
+    pub fn overlay_vertices(&self) -> &[DebugVertex] {
+        &self.overlay
+    }
+
+    pub fn clear(&mut self) {
+        self.depth_tested.clear();
+        self.overlay.clear();
+        self.depth_test = true;
+    }
+}
+
+/// Built-in visualizations for debugging culling and lighting, toggled from the UI
+#[derive(Default)]
+pub struct DebugVisualizations {
+    object_aabbs: bool,
+    world_bounds: bool,
+    light_volumes: bool,
+    shadow_cascades: bool,
+    reflection_probes: bool,
+    /// Camera frustum captured when the checkbox was ticked, so that it can be inspected from
+    /// another viewpoint
+    frozen_camera_frustum: Option<Mat4>,
+    draw_on_top: bool,
+}
+
+impl DebugVisualizations {
+    pub fn draw_ui(&mut self, ui: &imgui::Ui, view_proj: Mat4) {
+        ui.window("Debug draw")
+            .size([220.0, 160.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                ui.checkbox("Object AABBs", &mut self.object_aabbs);
+                ui.checkbox("World bounds", &mut self.world_bounds);
+                ui.checkbox("Light volumes", &mut self.light_volumes);
+                ui.checkbox("Shadow cascades", &mut self.shadow_cascades);
+                ui.checkbox("Reflection probes", &mut self.reflection_probes);
+
+                let mut freeze = self.frozen_camera_frustum.is_some();
+                if ui.checkbox("Freeze camera frustum", &mut freeze) {
+                    self.frozen_camera_frustum = freeze.then_some(view_proj);
+                }
+
+                ui.checkbox("Draw on top", &mut self.draw_on_top);
+            });
+    }
+
+    /// `shadow_cascades` are the light space projections of the shadow cascades
+    pub fn draw(&self, debug_draw: &mut DebugDraw, scene: &Scene, shadow_cascades: &[Mat4]) {
+        debug_draw.set_depth_test(!self.draw_on_top);
+
+        if self.object_aabbs {
+            let color = Vec4::new(0.0, 1.0, 0.0, 1.0);
+            for (_, object) in scene.objects.iter() {
+                let Some(model) = object.model_id.and_then(|id| scene.models.get(id)) else {
+                    continue;
+                };
+
+                if !object.enabled {
+                    continue;
+                }
+
+                let world_matrix = *object.transform.get_world_matrix();
+                for primitive in &model.model.primitives {
+                    debug_draw.aabb(&primitive.bounding_box, world_matrix, color);
+                }
+            }
+        }
+
+        if self.world_bounds {
+            let color = Vec4::new(1.0, 0.5, 0.0, 1.0);
+            for (_, object) in scene.objects.iter() {
+                let Some(bounds) = object.world_bounds.filter(|_| object.enabled) else {
+                    continue;
+                };
+
+                debug_draw.aabb(&bounds, Mat4::IDENTITY, color);
+            }
+        }
+
+        if self.light_volumes {
+            for (_, object) in scene.objects.iter() {
+                let Some(light) = object.light.as_ref().filter(|_| object.enabled) else {
+                    continue;
+                };
+
+                let world_matrix = *object.transform.get_world_matrix();
+                let position = world_matrix.w_axis.truncate();
+                let direction = world_matrix.transform_vector3(Vec3::Z).normalize();
+                let color = light.color.extend(1.0);
+
+                match light.kind {
+                    LightKind::Directional => {
+                        debug_draw.line(position, position + direction * 2.0, color)
+                    }
+                    LightKind::Point { range } => debug_draw.sphere(position, range, color),
+                    LightKind::Spot {
+                        range, outer_angle, ..
+                    } => debug_draw.cone(position, direction, range, outer_angle, color),
+                }
+            }
+        }
+
+        if self.reflection_probes {
+            let color = Vec4::new(0.3, 0.8, 1.0, 1.0);
+            for (_, object) in scene.objects.iter() {
+                let Some(probe) = object.reflection_probe.as_ref().filter(|_| object.enabled)
+                else {
+                    continue;
+                };
+
+                let (box_matrix, extents) = probe.world_box(*object.transform.get_world_matrix());
+                let bounds = AABB {
+                    min: -extents,
+                    max: extents,
+                };
+                debug_draw.aabb(&bounds, box_matrix, color);
+                debug_draw.sphere(box_matrix.w_axis.truncate(), 0.1, color);
+            }
+        }
+
+        if self.shadow_cascades {
+            for (view_proj, color) in shadow_cascades.iter().zip(CASCADE_COLORS) {
+                debug_draw.frustum(*view_proj, color);
+            }
+        }
+
+        if let Some(view_proj) = self.frozen_camera_frustum {
+            debug_draw.frustum(view_proj, Vec4::new(0.0, 1.0, 1.0, 1.0));
+        }
+
+        debug_draw.set_depth_test(true);
+    }
+}
@@ -0,0 +1,243 @@
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec4};
+use wgpu::{PipelineCompilationOptions, RenderPassDescriptor};
+
+use crate::{
+    rendering::{
+        deferred::gbuffer::GBuffer,
+        passes::render_pass_context::{RenderPassContext, RenderPassCreationContext},
+        render_graph::ColorTarget,
+        shader_loader::{RenderPipelineId, ShaderDefinition},
+        util::bind_group_builder::BindGroupBuilder,
+    },
+    scene_graph::scene::Scene,
+};
+
+const SHADER_DEF: ShaderDefinition = ShaderDefinition {
+    name: "Decal shader",
+    path: "deferred/decal.wgsl",
+    defines: &[],
+};
+
+const MAX_DECALS: usize = 1024;
+
+/// This should match Decal in deferred/decal.wgsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct GpuDecal {
+    model_matrix: Mat4,
+    inverse_model_matrix: Mat4,
+    color: Vec4,
+    material_id: u32,
+    normal_opacity: f32,
+    edge_fade: f32,
+    angle_fade: f32,
+}
+
+pub struct DecalPassTextureViews {
+    pub color_roughness: ColorTarget,
+    pub normal_metallic: ColorTarget,
+}
+
+/// Projects the decal components of the scene onto the G-buffer after the geometry. Each decal
+/// is drawn as the inside faces of its box, and finds the surface to project onto from the depth
+/// buffer, so it works with the camera inside the box too.
+pub struct DecalPass {
+    device: wgpu::Device,
+    enabled: bool,
+    pipeline_id: RenderPipelineId,
+    camera_bind_group: wgpu::BindGroup,
+    decal_buffer: wgpu::Buffer,
+    inputs_bind_group_layout: wgpu::BindGroupLayout,
+    inputs_bind_group: wgpu::BindGroup,
+    decal_count: u32,
+}
+
+impl DecalPass {
+    pub fn new(context: &mut RenderPassCreationContext, g_buffer: &GBuffer) -> Self {
+        let device = &context.shared.device;
+
+        let decal_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Decal buffer"),
+            size: (MAX_DECALS * std::mem::size_of::<GpuDecal>()) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let (camera_bind_group_layout, camera_bind_group) =
+            BindGroupBuilder::new("Decal camera", wgpu::ShaderStages::VERTEX_FRAGMENT)
+                .uniform(
+                    0,
+                    "Camera uniform buffer",
+                    context.camera_uniform_buffer.as_entire_binding(),
+                )
+                .build(device);
+
+        let (inputs_bind_group_layout, inputs_bind_group) =
+            Self::inputs_bind_group_builder(g_buffer, &decal_buffer).build(device);
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Decal pass pipeline layout"),
+                bind_group_layouts: &[
+                    &camera_bind_group_layout,
+                    &inputs_bind_group_layout,
+                    context.material_manager.bind_group_layout(),
+                ],
+                push_constant_ranges: &[],
+            });
+
+        let pipeline_id = context.cache_builder.add_shader(
+            SHADER_DEF,
+            Box::new(move |device, shader_module, cache| {
+                // The alpha channels hold the roughness and metallic of the surface
+                let blended = |format: wgpu::TextureFormat| wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::COLOR,
+                };
+
+                let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("Decal pipeline"),
+                    layout: Some(&render_pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader_module,
+                        entry_point: Some("vs_main"),
+                        buffers: &[],
+                        compilation_options: PipelineCompilationOptions::default(),
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader_module,
+                        entry_point: Some("fs_main"),
+                        targets: &[
+                            Some(blended(GBuffer::COLOR_ROUGHNESS_FORMAT)),
+                            Some(blended(GBuffer::NORMAL_METALLIC_FORMAT)),
+                        ],
+                        compilation_options: PipelineCompilationOptions::default(),
+                    }),
+                    // Back faces are visible from both inside and outside the box, and each
+                    // pixel is only covered once
+                    primitive: wgpu::PrimitiveState {
+                        cull_mode: Some(wgpu::Face::Front),
+                        ..Default::default()
+                    },
+                    depth_stencil: None,
+                    multisample: wgpu::MultisampleState::default(),
+                    multiview: None,
+                    cache,
+                });
+
+                Ok(pipeline)
+            }),
+        );
+
+        Self {
+            device: device.clone(),
+            enabled: true,
+            pipeline_id,
+            camera_bind_group,
+            decal_buffer,
+            inputs_bind_group_layout,
+            inputs_bind_group,
+            decal_count: 0,
+        }
+    }
+
+    fn inputs_bind_group_builder<'a>(
+        g_buffer: &'a GBuffer,
+        decal_buffer: &'a wgpu::Buffer,
+    ) -> BindGroupBuilder<'a> {
+        BindGroupBuilder::new("Decal inputs", wgpu::ShaderStages::VERTEX_FRAGMENT)
+            .storage_r(0, "Decal buffer", decal_buffer.as_entire_binding())
+            .texture_2d(
+                1,
+                "GBuffer depth",
+                wgpu::TextureSampleType::Depth,
+                wgpu::BindingResource::TextureView(g_buffer.depth.view()),
+            )
+    }
+
+    /// Must be called when the GBuffer attachments have been recreated
+    pub fn resize(&mut self, g_buffer: &GBuffer) {
+        self.inputs_bind_group = Self::inputs_bind_group_builder(g_buffer, &self.decal_buffer)
+            .build_with_layout(&self.device, &self.inputs_bind_group_layout);
+    }
+
+    /// Collects the enabled decals, sorted so that higher orders are drawn last
+    pub fn update_from_scene(&mut self, scene: &Scene, queue: &wgpu::Queue) {
+        let mut decals: Vec<(i32, GpuDecal)> = scene
+            .objects
+            .iter()
+            .filter(|(_, object)| object.enabled)
+            .filter_map(|(_, object)| {
+                let decal = object.decal.as_ref()?;
+                let material_id = decal.material_id?;
+
+                if decal.opacity <= 0.0 {
+                    return None;
+                }
+
+                let model_matrix = *object.transform.get_world_matrix();
+
+                Some((
+                    decal.order,
+                    GpuDecal {
+                        model_matrix,
+                        inverse_model_matrix: model_matrix.inverse(),
+                        color: decal.color.extend(decal.opacity),
+                        material_id: material_id.index() as u32,
+                        normal_opacity: decal.normal_opacity,
+                        edge_fade: decal.edge_fade,
+                        angle_fade: decal.angle_fade,
+                    },
+                ))
+            })
+            .collect();
+
+        // Stable, so decals with the same order keep the order of the scene
+        decals.sort_by_key(|(order, _)| *order);
+        // The highest orders draw on top, so the lowest ones are dropped when over the limit
+        let excess = decals.len().saturating_sub(MAX_DECALS);
+        decals.drain(..excess);
+
+        let decals: Vec<GpuDecal> = decals.into_iter().map(|(_, decal)| decal).collect();
+        self.decal_count = decals.len() as u32;
+
+        if !decals.is_empty() {
+            queue.write_buffer(&self.decal_buffer, 0, bytemuck::cast_slice(&decals));
+        }
+    }
+
+    pub fn should_render(&self) -> bool {
+        self.enabled && self.decal_count > 0
+    }
+
+    pub fn draw_ui(&mut self, ui: &imgui::Ui) {
+        ui.window("Decal pass")
+            .size([200.0, 80.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                ui.checkbox("Enabled##decals", &mut self.enabled);
+                ui.text(format!("Decals: {}", self.decal_count));
+            });
+    }
+
+    pub fn render(&self, texture_views: &DecalPassTextureViews, context: &mut RenderPassContext) {
+        let mut render_pass = context.encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Decal pass"),
+            color_attachments: &[
+                texture_views.color_roughness.attachment(),
+                texture_views.normal_metallic.attachment(),
+            ],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        render_pass.set_pipeline(context.pipeline_cache.get(self.pipeline_id));
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.inputs_bind_group, &[]);
+        render_pass.set_bind_group(2, context.material_manager.bind_group(), &[]);
+        // 36 vertices for the 12 triangles of the box
+        render_pass.draw(0..36, 0..self.decal_count);
+    }
+}
@@ -12,6 +12,10 @@ pub struct GBuffer {
     pub color_roughness: Texture,
     /// In 16-bit float. RGB for normal, A for metallic.
     pub normal_metallic: Texture,
+    /// In 16-bit float. RGB for emitted radiance, A for baked ambient occlusion.
+    pub emission: Texture,
+    /// In 16-bit float. Screen UV of this frame minus the screen UV of the previous frame.
+    pub velocity: Texture,
     // 32-bit float depth texture
     pub depth: DepthTexture,
 }
@@ -19,16 +23,22 @@ pub struct GBuffer {
 impl GBuffer {
     pub const COLOR_ROUGHNESS_FORMAT: TextureFormat = TextureFormat::Rgba8UnormSrgb;
     pub const NORMAL_METALLIC_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+    pub const EMISSION_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+    pub const VELOCITY_FORMAT: TextureFormat = TextureFormat::Rg16Float;
 
     pub fn new(device: &wgpu::Device, size: Resolution) -> Self {
         let color_roughness = Self::create_color_roughness_texture(device, size);
         let normal_metallic = Self::create_normal_metallic_texture(device, size);
+        let emission = Self::create_emission_texture(device, size);
+        let velocity = Self::create_velocity_texture(device, size);
         let depth = Self::create_depth_texture(device, size);
 
         Self {
             device: device.clone(),
             color_roughness: color_roughness,
             normal_metallic,
+            emission,
+            velocity,
             depth,
         }
     }
@@ -71,6 +81,15 @@ impl GBuffer {
             size,
         )
     }
+
+    fn create_emission_texture(device: &wgpu::Device, size: Resolution) -> Texture {
+        Self::create_attachment(device, "GBuffer emission", Self::EMISSION_FORMAT, size)
+    }
+
+    fn create_velocity_texture(device: &wgpu::Device, size: Resolution) -> Texture {
+        Self::create_attachment(device, "GBuffer velocity", Self::VELOCITY_FORMAT, size)
+    }
+
     pub fn create_depth_texture(device: &wgpu::Device, size: Resolution) -> DepthTexture {
         DepthTexture::new(device, size, "GBuffer depth texture")
     }
@@ -78,6 +97,8 @@ impl GBuffer {
     pub fn resize(&mut self, size: Resolution) {
         self.color_roughness.resize(&self.device, size);
         self.normal_metallic.resize(&self.device, size);
+        self.emission.resize(&self.device, size);
+        self.velocity.resize(&self.device, size);
         self.depth.resize(&self.device, size);
     }
 }
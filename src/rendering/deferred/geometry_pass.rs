@@ -1,42 +1,89 @@
 use std::sync::Arc;
 
 use wgpu::{
-    LoadOp, PipelineCompilationOptions, RenderPassColorAttachment,
-    RenderPassDepthStencilAttachment, RenderPassDescriptor, RenderPipelineDescriptor, StoreOp,
-    TextureView, VertexState,
+    PipelineCompilationOptions, RenderPassDescriptor, RenderPipelineDescriptor, VertexState,
 };
 
-use crate::rendering::{
-    config::RenderConfig,
-    deferred::gbuffer::GBuffer,
-    instancing::{self, DrawableBuffers},
-    mesh_buffers::MeshBuffers,
-    passes::render_pass_context::{RenderPassContext, RenderPassCreationContext},
-    render_model::RENDER_MODEL_VBL,
-    shader_loader::{RenderPipelineId, ShaderDefinition},
-    texture::DepthTexture,
-    util::bind_group_builder::BindGroupBuilder,
+use crate::{
+    asset_pipeline::{baked_ao::BakedAo, mesh_baker::VertexFormat},
+    demo_part::DemoPartId,
+    rendering::{
+        baked_ao_buffers::BakedAoBuffers,
+        config::RenderConfig,
+        deferred::gbuffer::GBuffer,
+        instancing::{DrawableBuffers, DrawableManager, MeshletDrawBuffers},
+        mesh_buffers::MeshBuffers,
+        passes::render_pass_context::{RenderPassContext, RenderPassCreationContext},
+        render_graph::{ColorTarget, DepthTarget},
+        render_model::{render_model_vbl, MODEL_PRIMITIVE_STATE, QUANTIZED_VERTEX_DEFINES},
+        shader_loader::{RenderPipelineId, ShaderDefValue, ShaderDefinition},
+        texture::DepthTexture,
+        util::bind_group_builder::BindGroupBuilder,
+    },
 };
 
 pub struct GeometryPass {
+    device: wgpu::Device,
     config: &'static RenderConfig,
     pipeline_id: RenderPipelineId,
     camera_bind_group: wgpu::BindGroup,
     mesh_buffers: Arc<MeshBuffers>,
     drawable_buffers: Arc<DrawableBuffers>,
+    baked_ao: BakedAoBuffers,
+    /// Set if the meshes were baked with meshlets, which are then drawn instead of primitives
+    meshlet_bindings: Option<MeshletBindings>,
+}
+
+/// Buffers the vertex shader pulls meshlet vertices from
+struct MeshletBindings {
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// One for each set of culling outputs. Recreated when the mesh buffers are reallocated.
+    bind_groups: Vec<wgpu::BindGroup>,
+}
+
+type MeshletSources = (wgpu::Buffer, wgpu::Buffer, wgpu::Buffer, wgpu::Buffer);
+
+fn meshlet_sources(mesh_buffers: &MeshBuffers) -> MeshletSources {
+    (
+        mesh_buffers.meshes(),
+        mesh_buffers.meshlets(),
+        mesh_buffers.indices(),
+        mesh_buffers.vertices(),
+    )
 }
 
 pub struct GeometryPassTextureViews {
-    pub color_roughness: TextureView,
-    pub normal_metallic: TextureView,
-    pub depth: TextureView,
+    pub color_roughness: ColorTarget,
+    pub normal_metallic: ColorTarget,
+    pub emission: ColorTarget,
+    pub velocity: ColorTarget,
+    pub depth: DepthTarget,
 }
 
 const SHADER_DEF: ShaderDefinition = ShaderDefinition {
     name: "Geometry pass shader",
     path: "deferred/geometry.wgsl",
+    defines: &[],
 };
 
+const QUANTIZED_SHADER_DEF: ShaderDefinition = SHADER_DEF.variant(
+    "Geometry pass shader (quantized vertices)",
+    QUANTIZED_VERTEX_DEFINES,
+);
+
+const MESHLET_SHADER_DEF: ShaderDefinition = SHADER_DEF.variant(
+    "Geometry pass shader (meshlets)",
+    &[("MESHLETS", ShaderDefValue::Bool(true))],
+);
+
+const QUANTIZED_MESHLET_SHADER_DEF: ShaderDefinition = SHADER_DEF.variant(
+    "Geometry pass shader (meshlets, quantized vertices)",
+    &[
+        ("MESHLETS", ShaderDefValue::Bool(true)),
+        ("QUANTIZED_VERTICES", ShaderDefValue::Bool(true)),
+    ],
+);
+
 impl GeometryPass {
     pub fn new(context: &mut RenderPassCreationContext) -> Self {
         let device = &context.shared.device;
@@ -53,31 +100,79 @@ impl GeometryPass {
                 )
                 .build(device);
 
+        let baked_ao = BakedAoBuffers::new(device);
+
+        let mesh_buffers = &context.shared.mesh_buffers;
+        let drawable_buffers = &context.shared.drawable_buffers;
+        let meshlet_bindings =
+            drawable_buffers
+                .culled()
+                .meshlet_draws
+                .as_ref()
+                .map(|draw_buffers| {
+                    let sources = meshlet_sources(mesh_buffers);
+                    let bind_group_layout =
+                        Self::meshlet_bind_group_builder(&sources, draw_buffers)
+                            .build_layout(device);
+                    let bind_groups = Self::meshlet_bind_groups(
+                        device,
+                        &bind_group_layout,
+                        &sources,
+                        drawable_buffers,
+                    );
+
+                    MeshletBindings {
+                        bind_group_layout,
+                        bind_groups,
+                    }
+                });
+
+        // The culling outputs of every frame have identical layouts
+        let mut bind_group_layouts = vec![
+            &camera_bind_group_layout,
+            drawable_buffers
+                .culled()
+                .visible_drawables
+                .bind_group_layout(),
+            context.material_manager.bind_group_layout(),
+            baked_ao.bind_group_layout(),
+        ];
+        if let Some(meshlet_bindings) = &meshlet_bindings {
+            bind_group_layouts.push(&meshlet_bindings.bind_group_layout);
+        }
+
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Render pipeline layout"),
-                bind_group_layouts: &[
-                    &camera_bind_group_layout,
-                    context
-                        .shared
-                        .drawable_buffers
-                        .visible_drawables
-                        .bind_group_layout(),
-                    context.material_manager.bind_group_layout(),
-                ],
+                bind_group_layouts: &bind_group_layouts,
                 push_constant_ranges: &[],
             });
 
+        let vertex_format = mesh_buffers.vertex_format();
+        let meshlets = meshlet_bindings.is_some();
+        let shader_def = match (vertex_format, meshlets) {
+            (VertexFormat::Full, false) => SHADER_DEF,
+            (VertexFormat::Quantized, false) => QUANTIZED_SHADER_DEF,
+            (VertexFormat::Full, true) => MESHLET_SHADER_DEF,
+            (VertexFormat::Quantized, true) => QUANTIZED_MESHLET_SHADER_DEF,
+        };
+        // Meshlet vertices are read from storage buffers
+        let vertex_buffers = if meshlets {
+            vec![]
+        } else {
+            vec![render_model_vbl(vertex_format)]
+        };
+
         let pipeline_id = cache_builder.add_shader(
-            SHADER_DEF,
-            Box::new(move |device, shader_module| {
+            shader_def,
+            Box::new(move |device, shader_module, cache| {
                 let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
                     label: Some("Geometry pass render pipeline"),
                     layout: Some(&render_pipeline_layout),
                     vertex: VertexState {
                         module: &shader_module,
                         entry_point: Some("vs_main"),
-                        buffers: &[RENDER_MODEL_VBL],
+                        buffers: &vertex_buffers,
                         compilation_options: PipelineCompilationOptions::default(),
                     },
                     fragment: Some(wgpu::FragmentState {
@@ -94,18 +189,20 @@ impl GeometryPass {
                                 blend: Some(wgpu::BlendState::REPLACE),
                                 write_mask: wgpu::ColorWrites::ALL,
                             }),
+                            Some(wgpu::ColorTargetState {
+                                format: GBuffer::EMISSION_FORMAT,
+                                blend: Some(wgpu::BlendState::REPLACE),
+                                write_mask: wgpu::ColorWrites::ALL,
+                            }),
+                            Some(wgpu::ColorTargetState {
+                                format: GBuffer::VELOCITY_FORMAT,
+                                blend: Some(wgpu::BlendState::REPLACE),
+                                write_mask: wgpu::ColorWrites::ALL,
+                            }),
                         ],
                         compilation_options: PipelineCompilationOptions::default(),
                     }),
-                    primitive: wgpu::PrimitiveState {
-                        topology: wgpu::PrimitiveTopology::TriangleList,
-                        strip_index_format: None,
-                        front_face: wgpu::FrontFace::Cw,
-                        cull_mode: Some(wgpu::Face::Back),
-                        polygon_mode: wgpu::PolygonMode::Fill,
-                        unclipped_depth: false,
-                        conservative: false,
-                    },
+                    primitive: MODEL_PRIMITIVE_STATE,
                     depth_stencil: Some(wgpu::DepthStencilState {
                         format: DepthTexture::DEPTH_FORMAT,
                         depth_write_enabled: true,
@@ -115,7 +212,7 @@ impl GeometryPass {
                     }),
                     multisample: wgpu::MultisampleState::default(),
                     multiview: None,
-                    cache: None,
+                    cache,
                 });
 
                 Ok(pipeline)
@@ -123,14 +220,85 @@ impl GeometryPass {
         );
 
         GeometryPass {
+            device: device.clone(),
             config,
             pipeline_id,
             camera_bind_group,
             mesh_buffers: context.shared.mesh_buffers.clone(),
             drawable_buffers: context.shared.drawable_buffers.clone(),
+            baked_ao,
+            meshlet_bindings,
         }
     }
 
+    /// The mesh buffers are fetched for each bind group, so they're passed in as a tuple of
+    /// mesh infos, meshlets, indices and vertices
+    fn meshlet_bind_group_builder<'a>(
+        (meshes, meshlets, indices, vertices): &'a MeshletSources,
+        draw_buffers: &'a MeshletDrawBuffers,
+    ) -> BindGroupBuilder<'a> {
+        BindGroupBuilder::new("Geometry pass meshlets", wgpu::ShaderStages::VERTEX)
+            .storage_r(0, "Mesh info buffer", meshes.as_entire_binding())
+            .storage_r(1, "Meshlet buffer", meshlets.as_entire_binding())
+            .storage_r(
+                2,
+                "Visible meshlet buffer",
+                draw_buffers.visible_meshlets.as_entire_binding(),
+            )
+            .storage_r(3, "Index megabuffer", indices.as_entire_binding())
+            .storage_r(4, "Vertex megabuffer", vertices.as_entire_binding())
+    }
+
+    fn meshlet_bind_groups(
+        device: &wgpu::Device,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        sources: &MeshletSources,
+        drawable_buffers: &DrawableBuffers,
+    ) -> Vec<wgpu::BindGroup> {
+        drawable_buffers
+            .culled_frames
+            .iter()
+            .filter_map(|culled| culled.meshlet_draws.as_ref())
+            .map(|draw_buffers| {
+                Self::meshlet_bind_group_builder(sources, draw_buffers)
+                    .build_with_layout(device, bind_group_layout)
+            })
+            .collect()
+    }
+
+    /// Must be called when the mesh buffers have been reallocated
+    pub fn resize_meshes(&mut self) {
+        let Some(meshlet_bindings) = &mut self.meshlet_bindings else {
+            return;
+        };
+
+        let sources = meshlet_sources(&self.mesh_buffers);
+        meshlet_bindings.bind_groups = Self::meshlet_bind_groups(
+            &self.device,
+            &meshlet_bindings.bind_group_layout,
+            &sources,
+            &self.drawable_buffers,
+        );
+    }
+
+    /// Uploads the baked AO again on the next update, e.g. after the meshes have been re-baked
+    pub fn invalidate_baked_ao(&mut self) {
+        self.baked_ao.invalidate();
+    }
+
+    /// Uploads the baked AO of the active part, and points the drawable slots of its static
+    /// objects to it
+    pub fn update_baked_ao(
+        &mut self,
+        queue: &wgpu::Queue,
+        part: DemoPartId,
+        baked_ao: &BakedAo,
+        drawable_manager: &DrawableManager,
+    ) {
+        self.baked_ao
+            .update(queue, part, baked_ao, &self.mesh_buffers, drawable_manager);
+    }
+
     pub fn render_indirect(
         &self,
         texture_views: &GeometryPassTextureViews,
@@ -139,31 +307,12 @@ impl GeometryPass {
         let mut render_pass = context.encoder.begin_render_pass(&RenderPassDescriptor {
             label: Some("Geometry pass (Indirect)"),
             color_attachments: &[
-                Some(RenderPassColorAttachment {
-                    view: &texture_views.color_roughness,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: LoadOp::Clear(wgpu::Color::BLACK),
-                        store: StoreOp::Store,
-                    },
-                }),
-                Some(RenderPassColorAttachment {
-                    view: &texture_views.normal_metallic,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: LoadOp::Clear(wgpu::Color::BLACK),
-                        store: StoreOp::Store,
-                    },
-                }),
+                texture_views.color_roughness.attachment(),
+                texture_views.normal_metallic.attachment(),
+                texture_views.emission.attachment(),
+                texture_views.velocity.attachment(),
             ],
-            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
-                view: &texture_views.depth,
-                depth_ops: Some(wgpu::Operations {
-                    load: LoadOp::Clear(1.0),
-                    store: StoreOp::Store,
-                }),
-                stencil_ops: None,
-            }),
+            depth_stencil_attachment: texture_views.depth.attachment(),
             occlusion_query_set: None,
             timestamp_writes: None,
         });
@@ -171,12 +320,27 @@ impl GeometryPass {
         let pipeline = context.pipeline_cache.get(self.pipeline_id);
         render_pass.set_pipeline(pipeline);
         render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
-        render_pass.set_bind_group(1, self.drawable_buffers.visible_drawables.bind_group(), &[]);
+        let culled = self.drawable_buffers.culled();
+        render_pass.set_bind_group(1, &culled.visible_drawables.bind_group(), &[]);
         render_pass.set_bind_group(2, context.material_manager.bind_group(), &[]);
+        render_pass.set_bind_group(3, self.baked_ao.bind_group(), &[]);
+
+        if let (Some(meshlet_bindings), Some(draw_buffers)) =
+            (&self.meshlet_bindings, culled.meshlet_draws.as_ref())
+        {
+            let frame = self.drawable_buffers.current_frame();
+            render_pass.set_bind_group(4, &meshlet_bindings.bind_groups[frame], &[]);
+            render_pass.set_index_buffer(draw_buffers.indices.slice(..), wgpu::IndexFormat::Uint32);
+            render_pass.draw_indexed_indirect(
+                &draw_buffers.draw_args,
+                MeshletDrawBuffers::DRAW_ARGS_OFFSET,
+            );
+            return;
+        }
 
-        render_pass.set_vertex_buffer(0, self.mesh_buffers.vertices.slice(..));
+        render_pass.set_vertex_buffer(0, self.mesh_buffers.vertices().slice(..));
         render_pass.set_index_buffer(
-            self.mesh_buffers.indices.slice(..),
+            self.mesh_buffers.indices().slice(..),
             wgpu::IndexFormat::Uint32,
         );
 
@@ -186,13 +350,13 @@ impl GeometryPass {
                 0,
                 context.draw_commands_count_buffer,
                 0,
-                instancing::MAX_MESHES as u32,
+                self.mesh_buffers.mesh_count(),
             );
         } else {
             render_pass.multi_draw_indexed_indirect(
                 context.draw_commands_buffer,
                 0,
-                instancing::MAX_MESHES as u32,
+                self.mesh_buffers.mesh_count(),
             );
         }
     }
@@ -1 +1,231 @@
+use std::sync::Arc;
 
+use wgpu::{MultisampleState, PipelineCompilationOptions, RenderPassDescriptor};
+
+use crate::rendering::{
+    deferred::{gbuffer::GBuffer, ssao_pass::SsaoPass},
+    environment_map::EnvironmentMap,
+    light_buffer::LightBuffer,
+    passes::render_pass_context::{RenderPassContext, RenderPassCreationContext},
+    passes::shadow_pass::ShadowPass,
+    post_process::targets::HDR_FORMAT,
+    reflection_probes::ReflectionProbes,
+    render_graph::ColorTarget,
+    shader_loader::{RenderPipelineId, ShaderDefinition},
+    util::bind_group_builder::BindGroupBuilder,
+};
+
+pub struct LightingPass {
+    device: wgpu::Device,
+    light_buffer: Arc<LightBuffer>,
+    pipeline_id: RenderPipelineId,
+    camera_bind_group: wgpu::BindGroup,
+    g_buffer_bind_group_layout: wgpu::BindGroupLayout,
+    g_buffer_bind_group: wgpu::BindGroup,
+    ambient_occlusion_sampler: wgpu::Sampler,
+    shadow_bind_group: wgpu::BindGroup,
+    environment_bind_group: wgpu::BindGroup,
+    reflection_probe_bind_group: wgpu::BindGroup,
+}
+
+pub struct LightingPassTextureViews {
+    pub output: ColorTarget,
+}
+
+const SHADER_DEF: ShaderDefinition = ShaderDefinition {
+    name: "Lighting pass shader",
+    path: "deferred/lighting.wgsl",
+    defines: &[],
+};
+
+impl LightingPass {
+    pub fn new(
+        context: &mut RenderPassCreationContext,
+        g_buffer: &GBuffer,
+        ssao_pass: &SsaoPass,
+        shadow_pass: &ShadowPass,
+        environment_map: &EnvironmentMap,
+        reflection_probes: &ReflectionProbes,
+    ) -> Self {
+        let device = &context.shared.device;
+        let common = context.shared.common.clone();
+
+        let (camera_bind_group_layout, camera_bind_group) =
+            BindGroupBuilder::new("Lighting camera", wgpu::ShaderStages::FRAGMENT)
+                .uniform(
+                    0,
+                    "Camera uniform buffer",
+                    common.camera_uniform_buffer.as_entire_binding(),
+                )
+                .build(device);
+
+        // The occlusion is at half resolution, so it's upsampled with bilinear filtering
+        let ambient_occlusion_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Lighting ambient occlusion sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let (g_buffer_bind_group_layout, g_buffer_bind_group) = Self::g_buffer_bind_group_builder(
+            g_buffer,
+            ssao_pass.output_view(),
+            &ambient_occlusion_sampler,
+        )
+        .build(device);
+
+        let light_buffer = context.shared.light_buffer.clone();
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Lighting pass pipeline layout"),
+                bind_group_layouts: &[
+                    &camera_bind_group_layout,
+                    &g_buffer_bind_group_layout,
+                    light_buffer.bind_group_layout(),
+                    shadow_pass.sampling_bind_group_layout(),
+                    environment_map.sampling_bind_group_layout(),
+                    reflection_probes.sampling_bind_group_layout(),
+                ],
+                push_constant_ranges: &[],
+            });
+
+        let pipeline_id = context.cache_builder.add_shader(
+            SHADER_DEF,
+            Box::new(move |device, shader_module, cache| {
+                let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("Lighting pass render pipeline"),
+                    layout: Some(&render_pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader_module,
+                        entry_point: Some("vs_main"),
+                        buffers: &[],
+                        compilation_options: PipelineCompilationOptions::default(),
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader_module,
+                        entry_point: Some("fs_main"),
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format: HDR_FORMAT,
+                            blend: Some(wgpu::BlendState::REPLACE),
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                        compilation_options: PipelineCompilationOptions::default(),
+                    }),
+                    primitive: wgpu::PrimitiveState {
+                        topology: wgpu::PrimitiveTopology::TriangleList,
+                        strip_index_format: None,
+                        front_face: wgpu::FrontFace::Ccw,
+                        cull_mode: None,
+                        polygon_mode: wgpu::PolygonMode::Fill,
+                        unclipped_depth: false,
+                        conservative: false,
+                    },
+                    depth_stencil: None,
+                    multisample: MultisampleState::default(),
+                    multiview: None,
+                    cache,
+                });
+
+                Ok(pipeline)
+            }),
+        );
+
+        Self {
+            device: device.clone(),
+            light_buffer,
+            pipeline_id,
+            camera_bind_group,
+            g_buffer_bind_group_layout,
+            g_buffer_bind_group,
+            ambient_occlusion_sampler,
+            shadow_bind_group: shadow_pass.sampling_bind_group().clone(),
+            environment_bind_group: environment_map.sampling_bind_group().clone(),
+            reflection_probe_bind_group: reflection_probes.sampling_bind_group().clone(),
+        }
+    }
+
+    fn g_buffer_bind_group_builder<'a>(
+        g_buffer: &'a GBuffer,
+        ambient_occlusion: &'a wgpu::TextureView,
+        ambient_occlusion_sampler: &'a wgpu::Sampler,
+    ) -> BindGroupBuilder<'a> {
+        // All attachments are read with textureLoad, so they don't need to be filterable
+        let unfilterable = wgpu::TextureSampleType::Float { filterable: false };
+
+        BindGroupBuilder::new("GBuffer", wgpu::ShaderStages::FRAGMENT)
+            .texture_2d(
+                0,
+                "GBuffer color and roughness",
+                unfilterable,
+                wgpu::BindingResource::TextureView(&g_buffer.color_roughness.view),
+            )
+            .texture_2d(
+                1,
+                "GBuffer normal and metallic",
+                unfilterable,
+                wgpu::BindingResource::TextureView(&g_buffer.normal_metallic.view),
+            )
+            .texture_2d(
+                2,
+                "GBuffer depth",
+                wgpu::TextureSampleType::Depth,
+                wgpu::BindingResource::TextureView(g_buffer.depth.view()),
+            )
+            .texture_2d(
+                3,
+                "GBuffer emission",
+                unfilterable,
+                wgpu::BindingResource::TextureView(&g_buffer.emission.view),
+            )
+            .texture_2d(
+                4,
+                "Ambient occlusion",
+                wgpu::TextureSampleType::Float { filterable: true },
+                wgpu::BindingResource::TextureView(ambient_occlusion),
+            )
+            .sampler(
+                5,
+                "Ambient occlusion sampler",
+                wgpu::SamplerBindingType::Filtering,
+                wgpu::BindingResource::Sampler(ambient_occlusion_sampler),
+            )
+    }
+
+    /// Must be called when the GBuffer attachments or the SSAO targets have been recreated
+    pub fn resize(&mut self, g_buffer: &GBuffer, ssao_pass: &SsaoPass) {
+        self.g_buffer_bind_group = Self::g_buffer_bind_group_builder(
+            g_buffer,
+            ssao_pass.output_view(),
+            &self.ambient_occlusion_sampler,
+        )
+        .build_with_layout(&self.device, &self.g_buffer_bind_group_layout);
+    }
+
+    pub fn render(
+        &self,
+        texture_views: &LightingPassTextureViews,
+        context: &mut RenderPassContext,
+    ) {
+        let mut render_pass = context.encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Lighting pass"),
+            color_attachments: &[texture_views.output.attachment()],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        let pipeline = context.pipeline_cache.get(self.pipeline_id);
+
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.g_buffer_bind_group, &[]);
+        render_pass.set_bind_group(2, self.light_buffer.bind_group(), &[]);
+        render_pass.set_bind_group(3, &self.shadow_bind_group, &[]);
+        render_pass.set_bind_group(4, &self.environment_bind_group, &[]);
+        render_pass.set_bind_group(5, &self.reflection_probe_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
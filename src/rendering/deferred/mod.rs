@@ -1,3 +1,8 @@
+pub(crate) mod decal_pass;
 pub(crate) mod gbuffer;
 pub(crate) mod geometry_pass;
 pub(crate) mod lighting_pass;
+pub(crate) mod sdf_pass;
+pub(crate) mod ssao_pass;
+pub(crate) mod ssr_pass;
+pub(crate) mod volumetric_fog_pass;
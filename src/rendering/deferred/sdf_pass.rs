@@ -0,0 +1,170 @@
+use wgpu::{PipelineCompilationOptions, RenderPassDescriptor};
+
+use crate::rendering::{
+    deferred::gbuffer::GBuffer,
+    passes::render_pass_context::RenderPassCreationContext,
+    render_graph::{ColorTarget, DepthTarget},
+    shader_loader::{PipelineCache, RenderPipelineId, ShaderDefinition},
+    texture::DepthTexture,
+    util::bind_group_builder::BindGroupBuilder,
+};
+
+/// Raymarched scenes that parts can pick by name. Each shader marches its own distance function
+/// and writes the hits with `shared::raymarch::surface_output`.
+pub const SDF_SCENES: [ShaderDefinition; 1] = [ShaderDefinition {
+    name: "SDF pillars",
+    path: "sdf/pillars.wgsl",
+    defines: &[],
+}];
+
+pub struct SdfPassTextureViews {
+    pub color_roughness: ColorTarget,
+    pub normal_metallic: ColorTarget,
+    pub emission: ColorTarget,
+    pub velocity: ColorTarget,
+    pub depth: DepthTarget,
+}
+
+/// Raymarches a signed distance field scene into the G-buffer after the rasterized geometry.
+/// Hits write their depth and are depth tested, so both kinds of geometry are lit together and
+/// occlude each other. SDF scenes don't cast shadows.
+pub struct SdfPass {
+    pipelines: Vec<(&'static str, RenderPipelineId)>,
+    camera_bind_group: wgpu::BindGroup,
+    global_uniform_bind_group: wgpu::BindGroup,
+    enabled: bool,
+}
+
+impl SdfPass {
+    pub fn new(context: &mut RenderPassCreationContext) -> Self {
+        let device = &context.shared.device;
+        let common = &context.shared.common;
+
+        let (camera_bind_group_layout, camera_bind_group) =
+            BindGroupBuilder::new("SDF camera", wgpu::ShaderStages::FRAGMENT)
+                .uniform(
+                    0,
+                    "Camera uniform buffer",
+                    context.camera_uniform_buffer.as_entire_binding(),
+                )
+                .build(device);
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("SDF pass pipeline layout"),
+                bind_group_layouts: &[
+                    &camera_bind_group_layout,
+                    &common.global_uniform.bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            });
+
+        let pipelines = SDF_SCENES
+            .into_iter()
+            .map(|shader_def| {
+                let name = shader_def.name;
+                let render_pipeline_layout = render_pipeline_layout.clone();
+
+                let pipeline_id = context.cache_builder.add_shader(
+                    shader_def,
+                    Box::new(move |device, shader_module, cache| {
+                        let pipeline =
+                            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                                label: Some(name),
+                                layout: Some(&render_pipeline_layout),
+                                vertex: wgpu::VertexState {
+                                    module: &shader_module,
+                                    entry_point: Some("vs_main"),
+                                    buffers: &[],
+                                    compilation_options: PipelineCompilationOptions::default(),
+                                },
+                                fragment: Some(wgpu::FragmentState {
+                                    module: &shader_module,
+                                    entry_point: Some("fs_main"),
+                                    targets: &[
+                                        Some(GBuffer::COLOR_ROUGHNESS_FORMAT.into()),
+                                        Some(GBuffer::NORMAL_METALLIC_FORMAT.into()),
+                                        Some(GBuffer::EMISSION_FORMAT.into()),
+                                        Some(GBuffer::VELOCITY_FORMAT.into()),
+                                    ],
+                                    compilation_options: PipelineCompilationOptions::default(),
+                                }),
+                                primitive: wgpu::PrimitiveState::default(),
+                                depth_stencil: Some(wgpu::DepthStencilState {
+                                    format: DepthTexture::DEPTH_FORMAT,
+                                    depth_write_enabled: true,
+                                    depth_compare: wgpu::CompareFunction::Less,
+                                    stencil: wgpu::StencilState::default(),
+                                    bias: wgpu::DepthBiasState::default(),
+                                }),
+                                multisample: wgpu::MultisampleState::default(),
+                                multiview: None,
+                                cache,
+                            });
+
+                        Ok(pipeline)
+                    }),
+                );
+
+                (name, pipeline_id)
+            })
+            .collect();
+
+        Self {
+            pipelines,
+            camera_bind_group,
+            global_uniform_bind_group: common.global_uniform.bind_group.clone(),
+            enabled: true,
+        }
+    }
+
+    /// Whether the scene exists and the pass hasn't been turned off from the UI
+    pub fn should_render(&self, scene_name: &str) -> bool {
+        self.enabled && self.pipeline_id(scene_name).is_some()
+    }
+
+    fn pipeline_id(&self, scene_name: &str) -> Option<RenderPipelineId> {
+        self.pipelines
+            .iter()
+            .find(|(name, _)| *name == scene_name)
+            .map(|(_, id)| *id)
+    }
+
+    pub fn draw_ui(&mut self, ui: &imgui::Ui) {
+        ui.window("SDF")
+            .size([200.0, 60.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                ui.checkbox("Enabled##sdf", &mut self.enabled);
+            });
+    }
+
+    pub fn render(
+        &self,
+        scene_name: &str,
+        texture_views: &SdfPassTextureViews,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline_cache: &PipelineCache,
+    ) {
+        let Some(pipeline_id) = self.pipeline_id(scene_name) else {
+            return;
+        };
+
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("SDF pass"),
+            color_attachments: &[
+                texture_views.color_roughness.attachment(),
+                texture_views.normal_metallic.attachment(),
+                texture_views.emission.attachment(),
+                texture_views.velocity.attachment(),
+            ],
+            depth_stencil_attachment: texture_views.depth.attachment(),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        render_pass.set_pipeline(pipeline_cache.get(pipeline_id));
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.global_uniform_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
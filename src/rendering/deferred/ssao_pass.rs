@@ -0,0 +1,380 @@
+use bytemuck::{Pod, Zeroable};
+use glam::{Vec3, Vec4};
+use rand::Rng;
+use wgpu::{TexelCopyBufferLayout, TexelCopyTextureInfo, TextureFormat};
+
+use crate::rendering::{
+    common::{PhysicalSizeExt, Resolution},
+    deferred::gbuffer::GBuffer,
+    passes::render_pass_context::{RenderPassContext, RenderPassCreationContext},
+    post_process::{create_fullscreen_pipeline, draw_fullscreen},
+    shader_loader::{RenderPipelineId, ShaderDefinition},
+    texture::Texture,
+    util::bind_group_builder::BindGroupBuilder,
+};
+
+const SHADER_DEF: ShaderDefinition = ShaderDefinition {
+    name: "SSAO shader",
+    path: "deferred/ssao.wgsl",
+    defines: &[],
+};
+
+const BLUR_SHADER_DEF: ShaderDefinition = ShaderDefinition {
+    name: "SSAO blur shader",
+    path: "deferred/ssao_blur.wgsl",
+    defines: &[],
+};
+
+const KERNEL_SIZE: usize = 32;
+/// The noise texture is tiled over the screen, and the blur is sized to cancel out its pattern
+const NOISE_SIZE: u32 = 4;
+
+/// This should match SsaoSettings in deferred/ssao.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct SsaoSettings {
+    /// Sample offsets in a tangent space hemisphere, XYZ used
+    kernel: [Vec4; KERNEL_SIZE],
+    radius: f32,
+    bias: f32,
+    intensity: f32,
+    sample_count: u32,
+}
+
+/// Hemisphere sampled ambient occlusion from the G-buffer depth and normals. Computed at half
+/// resolution and blurred, the result is multiplied into the ambient light of the lighting pass.
+pub struct SsaoPass {
+    device: wgpu::Device,
+    enabled: bool,
+    radius: f32,
+    bias: f32,
+    intensity: f32,
+    sample_count: u32,
+    kernel: [Vec4; KERNEL_SIZE],
+
+    /// In 8-bit unorm, 1 is unoccluded. The raw result of the SSAO shader.
+    occlusion: Texture,
+    /// In 8-bit unorm, 1 is unoccluded. Read by the lighting pass.
+    blurred: Texture,
+    noise: Texture,
+
+    settings_buffer: wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
+    inputs_bind_group_layout: wgpu::BindGroupLayout,
+    inputs_bind_group: wgpu::BindGroup,
+    blur_bind_group_layout: wgpu::BindGroupLayout,
+    blur_bind_group: wgpu::BindGroup,
+
+    pipeline_id: RenderPipelineId,
+    blur_pipeline_id: RenderPipelineId,
+}
+
+impl SsaoPass {
+    pub const FORMAT: TextureFormat = TextureFormat::R8Unorm;
+
+    pub fn new(
+        context: &mut RenderPassCreationContext,
+        queue: &wgpu::Queue,
+        g_buffer: &GBuffer,
+        size: Resolution,
+    ) -> Self {
+        let device = &context.shared.device;
+        let common = context.shared.common.clone();
+
+        let occlusion = Self::create_target(device, "SSAO occlusion", size);
+        let blurred = Self::create_target(device, "SSAO blurred occlusion", size);
+        let noise = Self::create_noise_texture(device, queue);
+
+        let settings_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("SSAO settings buffer"),
+            size: std::mem::size_of::<SsaoSettings>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let (camera_bind_group_layout, camera_bind_group) =
+            BindGroupBuilder::new("SSAO camera", wgpu::ShaderStages::FRAGMENT)
+                .uniform(
+                    0,
+                    "Camera uniform buffer",
+                    common.camera_uniform_buffer.as_entire_binding(),
+                )
+                .build(device);
+
+        let (inputs_bind_group_layout, inputs_bind_group) =
+            Self::inputs_bind_group_builder(g_buffer, &noise, &settings_buffer).build(device);
+
+        let (blur_bind_group_layout, blur_bind_group) =
+            Self::blur_bind_group_builder(&occlusion).build(device);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("SSAO pipeline layout"),
+            bind_group_layouts: &[&camera_bind_group_layout, &inputs_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline_id = context.cache_builder.add_shader(
+            SHADER_DEF,
+            Box::new(move |device, shader_module, cache| {
+                Ok(create_fullscreen_pipeline(
+                    device,
+                    "SSAO pipeline",
+                    &pipeline_layout,
+                    &shader_module,
+                    "fs_main",
+                    Self::FORMAT,
+                    cache,
+                ))
+            }),
+        );
+
+        let blur_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("SSAO blur pipeline layout"),
+            bind_group_layouts: &[&blur_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let blur_pipeline_id = context.cache_builder.add_shader(
+            BLUR_SHADER_DEF,
+            Box::new(move |device, shader_module, cache| {
+                Ok(create_fullscreen_pipeline(
+                    device,
+                    "SSAO blur pipeline",
+                    &blur_pipeline_layout,
+                    &shader_module,
+                    "fs_main",
+                    Self::FORMAT,
+                    cache,
+                ))
+            }),
+        );
+
+        Self {
+            device: device.clone(),
+            enabled: context.shared.config.expensive_passes_enabled(),
+            radius: 0.5,
+            bias: 0.025,
+            intensity: 1.0,
+            sample_count: context
+                .shared
+                .config
+                .quality
+                .ssao_samples
+                .clamp(1, KERNEL_SIZE as u32),
+            kernel: Self::generate_kernel(),
+
+            occlusion,
+            blurred,
+            noise,
+
+            settings_buffer,
+            camera_bind_group,
+            inputs_bind_group_layout,
+            inputs_bind_group,
+            blur_bind_group_layout,
+            blur_bind_group,
+
+            pipeline_id,
+            blur_pipeline_id,
+        }
+    }
+
+    /// Samples are distributed in a hemisphere around +Z and packed closer to the origin, where
+    /// occluders matter the most
+    fn generate_kernel() -> [Vec4; KERNEL_SIZE] {
+        let mut rng = rand::thread_rng();
+
+        std::array::from_fn(|i| {
+            let direction = Vec3::new(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(0.0..1.0),
+            )
+            .normalize_or(Vec3::Z);
+
+            let t = i as f32 / KERNEL_SIZE as f32;
+            let scale = 0.1 + 0.9 * t * t;
+
+            (direction * rng.gen_range(0.0..1.0) * scale).extend(0.0)
+        })
+    }
+
+    /// Random rotations around the surface normal, XY used
+    fn create_noise_texture(device: &wgpu::Device, queue: &wgpu::Queue) -> Texture {
+        let mut rng = rand::thread_rng();
+        let data: Vec<Vec4> = (0..NOISE_SIZE * NOISE_SIZE)
+            .map(|_| Vec4::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0, 0.0))
+            .collect();
+
+        let descriptor = wgpu::TextureDescriptor {
+            label: Some("SSAO noise"),
+            size: Resolution::new(NOISE_SIZE, NOISE_SIZE).to_extent3d(),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: TextureFormat::Rgba32Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        };
+
+        let texture = device.create_texture(&descriptor);
+
+        queue.write_texture(
+            TexelCopyTextureInfo {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(&data),
+            TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(NOISE_SIZE * std::mem::size_of::<Vec4>() as u32),
+                rows_per_image: None,
+            },
+            descriptor.size,
+        );
+
+        Texture::from_wgpu_texture(device, descriptor, texture, None)
+    }
+
+    fn half_resolution(size: Resolution) -> Resolution {
+        Resolution::new((size.width / 2).max(1), (size.height / 2).max(1))
+    }
+
+    fn create_target(device: &wgpu::Device, label: &'static str, size: Resolution) -> Texture {
+        let descriptor = wgpu::TextureDescriptor {
+            label: Some(label),
+            size: Self::half_resolution(size).to_extent3d(),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        };
+
+        let texture = device.create_texture(&descriptor);
+        Texture::from_wgpu_texture(device, descriptor, texture, None)
+    }
+
+    fn inputs_bind_group_builder<'a>(
+        g_buffer: &'a GBuffer,
+        noise: &'a Texture,
+        settings_buffer: &'a wgpu::Buffer,
+    ) -> BindGroupBuilder<'a> {
+        // Everything is read with textureLoad, so they don't need to be filterable
+        let unfilterable = wgpu::TextureSampleType::Float { filterable: false };
+
+        BindGroupBuilder::new("SSAO inputs", wgpu::ShaderStages::FRAGMENT)
+            .texture_2d(
+                0,
+                "GBuffer depth",
+                wgpu::TextureSampleType::Depth,
+                wgpu::BindingResource::TextureView(g_buffer.depth.view()),
+            )
+            .texture_2d(
+                1,
+                "GBuffer normal and metallic",
+                unfilterable,
+                wgpu::BindingResource::TextureView(&g_buffer.normal_metallic.view),
+            )
+            .texture_2d(
+                2,
+                "SSAO noise",
+                unfilterable,
+                wgpu::BindingResource::TextureView(&noise.view),
+            )
+            .uniform(3, "SSAO settings", settings_buffer.as_entire_binding())
+    }
+
+    fn blur_bind_group_builder(occlusion: &Texture) -> BindGroupBuilder<'_> {
+        BindGroupBuilder::new("SSAO blur", wgpu::ShaderStages::FRAGMENT).texture_2d(
+            0,
+            "SSAO occlusion",
+            wgpu::TextureSampleType::Float { filterable: false },
+            wgpu::BindingResource::TextureView(&occlusion.view),
+        )
+    }
+
+    /// Blurred occlusion, sampled by the lighting pass
+    pub fn output_view(&self) -> &wgpu::TextureView {
+        &self.blurred.view
+    }
+
+    /// Must be called when the GBuffer attachments have been recreated. The lighting pass must be
+    /// resized afterwards, since the output is recreated too.
+    pub fn resize(&mut self, g_buffer: &GBuffer, size: Resolution) {
+        let half_size = Self::half_resolution(size);
+        self.occlusion.resize(&self.device, half_size);
+        self.blurred.resize(&self.device, half_size);
+
+        self.inputs_bind_group =
+            Self::inputs_bind_group_builder(g_buffer, &self.noise, &self.settings_buffer)
+                .build_with_layout(&self.device, &self.inputs_bind_group_layout);
+        self.blur_bind_group = Self::blur_bind_group_builder(&self.occlusion)
+            .build_with_layout(&self.device, &self.blur_bind_group_layout);
+    }
+
+    pub fn draw_ui(&mut self, ui: &imgui::Ui) {
+        ui.window("SSAO")
+            .size([300.0, 150.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                ui.checkbox("Enabled##ssao", &mut self.enabled);
+                ui.slider("Radius", 0.05, 2.0, &mut self.radius);
+                ui.slider("Bias", 0.0, 0.1, &mut self.bias);
+                ui.slider("Intensity", 0.0, 4.0, &mut self.intensity);
+                ui.slider("Samples", 1, KERNEL_SIZE as u32, &mut self.sample_count);
+            });
+    }
+
+    pub fn render(&self, queue: &wgpu::Queue, context: &mut RenderPassContext) {
+        if !self.enabled {
+            // Fully unoccluded, so the lighting pass doesn't need to know about the toggle
+            context
+                .encoder
+                .begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("SSAO clear"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: &self.blurred.view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                    occlusion_query_set: None,
+                    timestamp_writes: None,
+                });
+            return;
+        }
+
+        let settings = SsaoSettings {
+            kernel: self.kernel,
+            radius: self.radius,
+            bias: self.bias,
+            intensity: self.intensity,
+            sample_count: self.sample_count,
+        };
+        queue.write_buffer(&self.settings_buffer, 0, bytemuck::cast_slice(&[settings]));
+
+        let pipeline_cache = context.pipeline_cache;
+
+        draw_fullscreen(
+            context.encoder,
+            "SSAO",
+            &self.occlusion.view,
+            pipeline_cache.get(self.pipeline_id),
+            &[&self.camera_bind_group, &self.inputs_bind_group],
+        );
+
+        draw_fullscreen(
+            context.encoder,
+            "SSAO blur",
+            &self.blurred.view,
+            pipeline_cache.get(self.blur_pipeline_id),
+            &[&self.blur_bind_group],
+        );
+    }
+}
@@ -0,0 +1,363 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::{MultisampleState, PipelineCompilationOptions, RenderPassDescriptor};
+
+use crate::rendering::{
+    common::Resolution,
+    deferred::gbuffer::GBuffer,
+    environment_map::EnvironmentMap,
+    passes::render_pass_context::{RenderPassContext, RenderPassCreationContext},
+    post_process::{
+        create_fullscreen_pipeline, draw_fullscreen,
+        targets::{RenderTarget, HDR_FORMAT},
+    },
+    render_graph::{
+        ClearValue, ColorTarget, RenderGraph, TextureHandle, TransientTextureDescriptor,
+    },
+    shader_loader::{RenderPipelineId, ShaderDefinition},
+    util::bind_group_builder::BindGroupBuilder,
+};
+
+const SHADER_DEF: ShaderDefinition = ShaderDefinition {
+    name: "SSR shader",
+    path: "deferred/ssr.wgsl",
+    defines: &[],
+};
+
+const COMPOSITE_SHADER_DEF: ShaderDefinition = ShaderDefinition {
+    name: "SSR composite shader",
+    path: "deferred/ssr_composite.wgsl",
+    defines: &[],
+};
+
+/// This should match SsrSettings in deferred/ssr.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct SsrSettings {
+    max_distance: f32,
+    max_steps: u32,
+    thickness: f32,
+    max_roughness: f32,
+    intensity: f32,
+    _padding: [f32; 3],
+}
+
+pub struct SsrTraceTextureViews {
+    pub reflections: wgpu::TextureView,
+}
+
+pub struct SsrCompositeTextureViews {
+    pub reflections: wgpu::TextureView,
+    pub output: ColorTarget,
+}
+
+/// Screen-space reflections for glossy surfaces. Rays are marched against the G-buffer depth and
+/// the hits are read from the lit scene. Misses keep the environment map reflection that the
+/// lighting pass already applied.
+///
+/// The reflections are traced to a transient texture of the render graph, in 16-bit float. It
+/// holds the difference between the traced and the environment map reflection, weighted by the
+/// specular response, so it can be negative.
+pub struct SsrPass {
+    device: wgpu::Device,
+    enabled: bool,
+    max_distance: f32,
+    max_steps: u32,
+    thickness: f32,
+    max_roughness: f32,
+    intensity: f32,
+
+    settings_buffer: wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
+    inputs_bind_group_layout: wgpu::BindGroupLayout,
+    inputs_bind_group: wgpu::BindGroup,
+    environment_bind_group: wgpu::BindGroup,
+    composite_bind_group_layout: wgpu::BindGroupLayout,
+
+    pipeline_id: RenderPipelineId,
+    composite_pipeline_id: RenderPipelineId,
+}
+
+impl SsrPass {
+    pub fn new(
+        context: &mut RenderPassCreationContext,
+        g_buffer: &GBuffer,
+        scene_color: &RenderTarget,
+        environment_map: &EnvironmentMap,
+    ) -> Self {
+        let device = &context.shared.device;
+        let common = context.shared.common.clone();
+
+        let settings_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("SSR settings buffer"),
+            size: std::mem::size_of::<SsrSettings>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let (camera_bind_group_layout, camera_bind_group) =
+            BindGroupBuilder::new("SSR camera", wgpu::ShaderStages::FRAGMENT)
+                .uniform(
+                    0,
+                    "Camera uniform buffer",
+                    common.camera_uniform_buffer.as_entire_binding(),
+                )
+                .build(device);
+
+        let (inputs_bind_group_layout, inputs_bind_group) =
+            Self::inputs_bind_group_builder(g_buffer, scene_color, &settings_buffer).build(device);
+
+        // The reflections are transient, so the bind group is created when rendering
+        let composite_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("SSR composite bind group layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                }],
+            });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("SSR pipeline layout"),
+            bind_group_layouts: &[
+                &camera_bind_group_layout,
+                &inputs_bind_group_layout,
+                environment_map.sampling_bind_group_layout(),
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline_id = context.cache_builder.add_shader(
+            SHADER_DEF,
+            Box::new(move |device, shader_module, cache| {
+                Ok(create_fullscreen_pipeline(
+                    device,
+                    "SSR pipeline",
+                    &pipeline_layout,
+                    &shader_module,
+                    "fs_main",
+                    HDR_FORMAT,
+                    cache,
+                ))
+            }),
+        );
+
+        let composite_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("SSR composite pipeline layout"),
+                bind_group_layouts: &[&composite_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let composite_pipeline_id = context.cache_builder.add_shader(
+            COMPOSITE_SHADER_DEF,
+            Box::new(move |device, shader_module, cache| {
+                let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("SSR composite pipeline"),
+                    layout: Some(&composite_pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader_module,
+                        entry_point: Some("vs_main"),
+                        buffers: &[],
+                        compilation_options: PipelineCompilationOptions::default(),
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader_module,
+                        entry_point: Some("fs_main"),
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format: HDR_FORMAT,
+                            // Reflections are added on top of the lit scene
+                            blend: Some(wgpu::BlendState {
+                                color: wgpu::BlendComponent {
+                                    src_factor: wgpu::BlendFactor::One,
+                                    dst_factor: wgpu::BlendFactor::One,
+                                    operation: wgpu::BlendOperation::Add,
+                                },
+                                alpha: wgpu::BlendComponent {
+                                    src_factor: wgpu::BlendFactor::Zero,
+                                    dst_factor: wgpu::BlendFactor::One,
+                                    operation: wgpu::BlendOperation::Add,
+                                },
+                            }),
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                        compilation_options: PipelineCompilationOptions::default(),
+                    }),
+                    primitive: wgpu::PrimitiveState {
+                        topology: wgpu::PrimitiveTopology::TriangleList,
+                        strip_index_format: None,
+                        front_face: wgpu::FrontFace::Ccw,
+                        cull_mode: None,
+                        polygon_mode: wgpu::PolygonMode::Fill,
+                        unclipped_depth: false,
+                        conservative: false,
+                    },
+                    depth_stencil: None,
+                    multisample: MultisampleState::default(),
+                    multiview: None,
+                    cache,
+                });
+
+                Ok(pipeline)
+            }),
+        );
+
+        Self {
+            device: device.clone(),
+            enabled: context.shared.config.expensive_passes_enabled(),
+            max_distance: 20.0,
+            max_steps: 64,
+            thickness: 0.2,
+            max_roughness: 0.5,
+            intensity: 1.0,
+
+            settings_buffer,
+            camera_bind_group,
+            inputs_bind_group_layout,
+            inputs_bind_group,
+            environment_bind_group: environment_map.sampling_bind_group().clone(),
+            composite_bind_group_layout,
+
+            pipeline_id,
+            composite_pipeline_id,
+        }
+    }
+
+    /// Reflections are only needed until they have been composited, so they can share memory
+    /// with other full resolution HDR targets
+    pub fn create_reflections_texture(graph: &mut RenderGraph, size: Resolution) -> TextureHandle {
+        graph.create_texture(
+            TransientTextureDescriptor::render_target("SSR reflections", size, HDR_FORMAT),
+            ClearValue::Color(wgpu::Color::BLACK),
+        )
+    }
+
+    fn inputs_bind_group_builder<'a>(
+        g_buffer: &'a GBuffer,
+        scene_color: &'a RenderTarget,
+        settings_buffer: &'a wgpu::Buffer,
+    ) -> BindGroupBuilder<'a> {
+        // Everything is read with textureLoad, so they don't need to be filterable
+        let unfilterable = wgpu::TextureSampleType::Float { filterable: false };
+
+        BindGroupBuilder::new("SSR inputs", wgpu::ShaderStages::FRAGMENT)
+            .texture_2d(
+                0,
+                "GBuffer color and roughness",
+                unfilterable,
+                wgpu::BindingResource::TextureView(&g_buffer.color_roughness.view),
+            )
+            .texture_2d(
+                1,
+                "GBuffer normal and metallic",
+                unfilterable,
+                wgpu::BindingResource::TextureView(&g_buffer.normal_metallic.view),
+            )
+            .texture_2d(
+                2,
+                "GBuffer depth",
+                wgpu::TextureSampleType::Depth,
+                wgpu::BindingResource::TextureView(g_buffer.depth.view()),
+            )
+            .texture_2d(
+                3,
+                "Lit scene color",
+                unfilterable,
+                wgpu::BindingResource::TextureView(scene_color.view()),
+            )
+            .uniform(4, "SSR settings", settings_buffer.as_entire_binding())
+    }
+
+    fn composite_bind_group_builder(reflections: &wgpu::TextureView) -> BindGroupBuilder<'_> {
+        BindGroupBuilder::new("SSR composite", wgpu::ShaderStages::FRAGMENT).texture_2d(
+            0,
+            "SSR reflections",
+            wgpu::TextureSampleType::Float { filterable: false },
+            wgpu::BindingResource::TextureView(reflections),
+        )
+    }
+
+    /// Must be called when the GBuffer attachments and the scene color target have been recreated
+    pub fn resize(&mut self, g_buffer: &GBuffer, scene_color: &RenderTarget) {
+        self.inputs_bind_group =
+            Self::inputs_bind_group_builder(g_buffer, scene_color, &self.settings_buffer)
+                .build_with_layout(&self.device, &self.inputs_bind_group_layout);
+    }
+
+    pub fn draw_ui(&mut self, ui: &imgui::Ui) {
+        ui.window("SSR")
+            .size([300.0, 170.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                ui.checkbox("Enabled##ssr", &mut self.enabled);
+                ui.slider("Max distance", 1.0, 100.0, &mut self.max_distance);
+                ui.slider("Max steps", 8, 256, &mut self.max_steps);
+                ui.slider("Thickness", 0.01, 1.0, &mut self.thickness);
+                ui.slider("Max roughness", 0.0, 1.0, &mut self.max_roughness);
+                ui.slider("Intensity##ssr", 0.0, 1.0, &mut self.intensity);
+            });
+    }
+
+    pub fn trace(
+        &self,
+        queue: &wgpu::Queue,
+        texture_views: &SsrTraceTextureViews,
+        context: &mut RenderPassContext,
+    ) {
+        if !self.enabled {
+            return;
+        }
+
+        let settings = SsrSettings {
+            max_distance: self.max_distance,
+            max_steps: self.max_steps,
+            thickness: self.thickness,
+            max_roughness: self.max_roughness,
+            intensity: self.intensity,
+            _padding: [0.0; 3],
+        };
+        queue.write_buffer(&self.settings_buffer, 0, bytemuck::cast_slice(&[settings]));
+
+        // The lit scene is read here, so the result can't be written to it directly
+        draw_fullscreen(
+            context.encoder,
+            "SSR trace",
+            &texture_views.reflections,
+            context.pipeline_cache.get(self.pipeline_id),
+            &[
+                &self.camera_bind_group,
+                &self.inputs_bind_group,
+                &self.environment_bind_group,
+            ],
+        );
+    }
+
+    pub fn composite(
+        &self,
+        texture_views: &SsrCompositeTextureViews,
+        context: &mut RenderPassContext,
+    ) {
+        if !self.enabled {
+            return;
+        }
+
+        let composite_bind_group = Self::composite_bind_group_builder(&texture_views.reflections)
+            .build_with_layout(&self.device, &self.composite_bind_group_layout);
+
+        let mut render_pass = context.encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("SSR composite"),
+            color_attachments: &[texture_views.output.attachment()],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        render_pass.set_pipeline(context.pipeline_cache.get(self.composite_pipeline_id));
+        render_pass.set_bind_group(0, &composite_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,337 @@
+use bytemuck::{Pod, Zeroable};
+use glam::{Vec3, Vec4};
+use wgpu::{MultisampleState, PipelineCompilationOptions, RenderPassDescriptor};
+
+use crate::{
+    rendering::{
+        common::Resolution,
+        deferred::gbuffer::GBuffer,
+        passes::{
+            render_pass_context::{RenderPassContext, RenderPassCreationContext},
+            shadow_pass::ShadowPass,
+        },
+        post_process::{
+            create_fullscreen_pipeline, draw_fullscreen,
+            targets::{RenderTarget, TargetSampling, HDR_FORMAT},
+        },
+        render_graph::ColorTarget,
+        shader_loader::{RenderPipelineId, ShaderDefinition},
+        util::bind_group_builder::BindGroupBuilder,
+    },
+    scene_graph::fog::Fog,
+};
+
+const SHADER_DEF: ShaderDefinition = ShaderDefinition {
+    name: "Volumetric fog shader",
+    path: "deferred/volumetric_fog.wgsl",
+    defines: &[],
+};
+
+const COMPOSITE_SHADER_DEF: ShaderDefinition = ShaderDefinition {
+    name: "Volumetric fog composite shader",
+    path: "deferred/volumetric_fog_composite.wgsl",
+    defines: &[],
+};
+
+/// This should match VolumetricFogSettings in deferred/volumetric_fog.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct VolumetricFogSettings {
+    /// Direction the light is shining towards, W unused
+    light_direction: Vec4,
+    /// Color times intensity of the light, W = ambient
+    light_radiance: Vec4,
+    /// W = anisotropy
+    albedo: Vec4,
+    density: f32,
+    height_falloff: f32,
+    base_height: f32,
+    max_distance: f32,
+    step_count: u32,
+    /// Varies the per-pixel offset of the ray march, so that TAA can average the banding away
+    frame_index: u32,
+    _padding: [u32; 2],
+}
+
+pub struct VolumetricFogPassTextureViews {
+    pub output: ColorTarget,
+}
+
+/// Height fog lit by the primary directional light. The view rays are marched through the fog at
+/// half resolution, accumulating in-scattered light with shadow map visibility. The result is
+/// upsampled and blended over the lit scene by the remaining transmittance.
+pub struct VolumetricFogPass {
+    device: wgpu::Device,
+    enabled: bool,
+    step_count: u32,
+    max_distance: f32,
+    frame_index: u32,
+
+    sampling: TargetSampling,
+    /// Half resolution. RGB = in-scattered light, A = transmittance.
+    scattering: RenderTarget,
+
+    settings_buffer: wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
+    inputs_bind_group_layout: wgpu::BindGroupLayout,
+    inputs_bind_group: wgpu::BindGroup,
+    shadow_bind_group: wgpu::BindGroup,
+
+    pipeline_id: RenderPipelineId,
+    composite_pipeline_id: RenderPipelineId,
+}
+
+impl VolumetricFogPass {
+    pub fn new(
+        context: &mut RenderPassCreationContext,
+        g_buffer: &GBuffer,
+        shadow_pass: &ShadowPass,
+        size: Resolution,
+    ) -> Self {
+        let device = &context.shared.device;
+        let common = context.shared.common.clone();
+
+        let sampling = TargetSampling::new(device);
+        let scattering = Self::create_scattering_target(device, size, &sampling);
+
+        let settings_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Volumetric fog settings buffer"),
+            size: std::mem::size_of::<VolumetricFogSettings>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let (camera_bind_group_layout, camera_bind_group) =
+            BindGroupBuilder::new("Volumetric fog camera", wgpu::ShaderStages::FRAGMENT)
+                .uniform(
+                    0,
+                    "Camera uniform buffer",
+                    common.camera_uniform_buffer.as_entire_binding(),
+                )
+                .build(device);
+
+        let (inputs_bind_group_layout, inputs_bind_group) =
+            Self::inputs_bind_group_builder(g_buffer, &settings_buffer).build(device);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Volumetric fog pipeline layout"),
+            bind_group_layouts: &[
+                &camera_bind_group_layout,
+                &inputs_bind_group_layout,
+                shadow_pass.sampling_bind_group_layout(),
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline_id = context.cache_builder.add_shader(
+            SHADER_DEF,
+            Box::new(move |device, shader_module, cache| {
+                Ok(create_fullscreen_pipeline(
+                    device,
+                    "Volumetric fog pipeline",
+                    &pipeline_layout,
+                    &shader_module,
+                    "fs_main",
+                    HDR_FORMAT,
+                    cache,
+                ))
+            }),
+        );
+
+        let composite_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Volumetric fog composite pipeline layout"),
+                bind_group_layouts: &[&sampling.bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let composite_pipeline_id = context.cache_builder.add_shader(
+            COMPOSITE_SHADER_DEF,
+            Box::new(move |device, shader_module, cache| {
+                let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("Volumetric fog composite pipeline"),
+                    layout: Some(&composite_pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader_module,
+                        entry_point: Some("vs_main"),
+                        buffers: &[],
+                        compilation_options: PipelineCompilationOptions::default(),
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader_module,
+                        entry_point: Some("fs_main"),
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format: HDR_FORMAT,
+                            // The scene is attenuated by the transmittance, and the in-scattered
+                            // light is added on top
+                            blend: Some(wgpu::BlendState {
+                                color: wgpu::BlendComponent {
+                                    src_factor: wgpu::BlendFactor::One,
+                                    dst_factor: wgpu::BlendFactor::SrcAlpha,
+                                    operation: wgpu::BlendOperation::Add,
+                                },
+                                alpha: wgpu::BlendComponent {
+                                    src_factor: wgpu::BlendFactor::Zero,
+                                    dst_factor: wgpu::BlendFactor::One,
+                                    operation: wgpu::BlendOperation::Add,
+                                },
+                            }),
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                        compilation_options: PipelineCompilationOptions::default(),
+                    }),
+                    primitive: wgpu::PrimitiveState {
+                        topology: wgpu::PrimitiveTopology::TriangleList,
+                        strip_index_format: None,
+                        front_face: wgpu::FrontFace::Ccw,
+                        cull_mode: None,
+                        polygon_mode: wgpu::PolygonMode::Fill,
+                        unclipped_depth: false,
+                        conservative: false,
+                    },
+                    depth_stencil: None,
+                    multisample: MultisampleState::default(),
+                    multiview: None,
+                    cache,
+                });
+
+                Ok(pipeline)
+            }),
+        );
+
+        Self {
+            device: device.clone(),
+            enabled: context.shared.config.expensive_passes_enabled(),
+            step_count: 32,
+            max_distance: 50.0,
+            frame_index: 0,
+
+            sampling,
+            scattering,
+
+            settings_buffer,
+            camera_bind_group,
+            inputs_bind_group_layout,
+            inputs_bind_group,
+            shadow_bind_group: shadow_pass.sampling_bind_group().clone(),
+
+            pipeline_id,
+            composite_pipeline_id,
+        }
+    }
+
+    fn half_resolution(size: Resolution) -> Resolution {
+        Resolution::new((size.width / 2).max(1), (size.height / 2).max(1))
+    }
+
+    fn create_scattering_target(
+        device: &wgpu::Device,
+        size: Resolution,
+        sampling: &TargetSampling,
+    ) -> RenderTarget {
+        RenderTarget::new(
+            device,
+            "Volumetric fog scattering",
+            Self::half_resolution(size),
+            sampling,
+        )
+    }
+
+    fn inputs_bind_group_builder<'a>(
+        g_buffer: &'a GBuffer,
+        settings_buffer: &'a wgpu::Buffer,
+    ) -> BindGroupBuilder<'a> {
+        BindGroupBuilder::new("Volumetric fog inputs", wgpu::ShaderStages::FRAGMENT)
+            .texture_2d(
+                0,
+                "GBuffer depth",
+                wgpu::TextureSampleType::Depth,
+                wgpu::BindingResource::TextureView(g_buffer.depth.view()),
+            )
+            .uniform(
+                1,
+                "Volumetric fog settings",
+                settings_buffer.as_entire_binding(),
+            )
+    }
+
+    /// Must be called when the GBuffer attachments have been recreated
+    pub fn resize(&mut self, g_buffer: &GBuffer, size: Resolution) {
+        self.scattering = Self::create_scattering_target(&self.device, size, &self.sampling);
+
+        self.inputs_bind_group = Self::inputs_bind_group_builder(g_buffer, &self.settings_buffer)
+            .build_with_layout(&self.device, &self.inputs_bind_group_layout);
+    }
+
+    pub fn draw_ui(&mut self, ui: &imgui::Ui) {
+        ui.window("Volumetric fog")
+            .size([300.0, 100.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                ui.checkbox("Enabled##volumetric_fog", &mut self.enabled);
+                ui.slider("Steps", 4, 128, &mut self.step_count);
+                ui.slider(
+                    "Max distance##volumetric_fog",
+                    5.0,
+                    200.0,
+                    &mut self.max_distance,
+                );
+            });
+    }
+
+    /// The light is the primary directional light of the scene, which is also the one that casts
+    /// shadows
+    pub fn render(
+        &mut self,
+        queue: &wgpu::Queue,
+        fog: &Fog,
+        light_direction: Vec3,
+        light_radiance: Vec3,
+        texture_views: &VolumetricFogPassTextureViews,
+        context: &mut RenderPassContext,
+    ) {
+        if !self.enabled || !fog.is_enabled() {
+            return;
+        }
+
+        let settings = VolumetricFogSettings {
+            light_direction: light_direction.normalize_or(Vec3::NEG_Y).extend(0.0),
+            light_radiance: light_radiance.extend(fog.ambient),
+            albedo: fog.albedo.extend(fog.anisotropy),
+            density: fog.density,
+            height_falloff: fog.height_falloff,
+            base_height: fog.base_height,
+            max_distance: self.max_distance,
+            step_count: self.step_count,
+            frame_index: self.frame_index,
+            _padding: [0; 2],
+        };
+        queue.write_buffer(&self.settings_buffer, 0, bytemuck::cast_slice(&[settings]));
+        self.frame_index = self.frame_index.wrapping_add(1);
+
+        let pipeline_cache = context.pipeline_cache;
+
+        draw_fullscreen(
+            context.encoder,
+            "Volumetric fog march",
+            self.scattering.view(),
+            pipeline_cache.get(self.pipeline_id),
+            &[
+                &self.camera_bind_group,
+                &self.inputs_bind_group,
+                &self.shadow_bind_group,
+            ],
+        );
+
+        let mut render_pass = context.encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Volumetric fog composite"),
+            color_attachments: &[texture_views.output.attachment()],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        render_pass.set_pipeline(pipeline_cache.get(self.composite_pipeline_id));
+        render_pass.set_bind_group(0, &self.scattering.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
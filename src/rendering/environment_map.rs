@@ -0,0 +1,557 @@
+use std::path::Path;
+
+use anyhow::Context;
+use wgpu::{util::DeviceExt, TexelCopyBufferLayout, TexelCopyTextureInfo};
+
+use crate::rendering::{
+    passes::render_pass_context::RenderPassCreationContext,
+    shader_loader::{ComputePipelineId, PipelineCache, PipelineFactory, ShaderDefinition},
+    util::bind_group_builder::BindGroupBuilder,
+};
+
+const EQUIRECT_TO_CUBEMAP_SHADER: ShaderDefinition = ShaderDefinition {
+    name: "Equirectangular to cubemap compute shader",
+    path: "environment/equirect_to_cubemap.wgsl",
+    defines: &[],
+};
+
+pub const IRRADIANCE_SHADER: ShaderDefinition = ShaderDefinition {
+    name: "Irradiance map compute shader",
+    path: "environment/irradiance.wgsl",
+    defines: &[],
+};
+
+pub const PREFILTER_SHADER: ShaderDefinition = ShaderDefinition {
+    name: "Prefiltered environment map compute shader",
+    path: "environment/prefilter.wgsl",
+    defines: &[],
+};
+
+const BRDF_LUT_SHADER: ShaderDefinition = ShaderDefinition {
+    name: "BRDF LUT compute shader",
+    path: "environment/brdf_lut.wgsl",
+    defines: &[],
+};
+
+pub const ENVIRONMENT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+const EQUIRECT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Float;
+const CUBEMAP_SIZE: u32 = 512;
+const IRRADIANCE_SIZE: u32 = 32;
+const PREFILTERED_SIZE: u32 = 128;
+/// Roughness goes from 0 at mip 0 to 1 at the last mip
+const PREFILTERED_MIP_COUNT: u32 = 5;
+const BRDF_LUT_SIZE: u32 = 256;
+
+/// Must match the workgroup size in the environment shaders
+pub const WORKGROUP_SIZE: u32 = 8;
+
+/// Used when the environment map can't be loaded, so that sampling it still works
+const FALLBACK_COLOR: [f32; 4] = [0.03, 0.03, 0.03, 1.0];
+
+/// This should match PrefilterSettings in environment/prefilter.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PrefilterSettings {
+    roughness: f32,
+    _padding: [f32; 3],
+}
+
+/// HDR environment loaded from an equirectangular image and converted to a cubemap, which is
+/// used for the skybox. The maps for image-based lighting (diffuse irradiance, specular
+/// prefiltered by roughness and the BRDF lookup table) are generated from it at load time.
+pub struct EnvironmentMap {
+    loaded: bool,
+    conversion_pipeline_id: ComputePipelineId,
+    conversion_bind_group: wgpu::BindGroup,
+    cubemap: wgpu::Texture,
+
+    irradiance_pipeline_id: ComputePipelineId,
+    irradiance_bind_group: wgpu::BindGroup,
+    prefilter_pipeline_id: ComputePipelineId,
+    /// One per mip
+    prefilter_bind_groups: Vec<wgpu::BindGroup>,
+    brdf_lut_pipeline_id: ComputePipelineId,
+    brdf_lut_bind_group: wgpu::BindGroup,
+
+    sampling_bind_group_layout: wgpu::BindGroupLayout,
+    sampling_bind_group: wgpu::BindGroup,
+}
+
+impl EnvironmentMap {
+    pub fn new(
+        context: &mut RenderPassCreationContext,
+        queue: &wgpu::Queue,
+        path: impl AsRef<Path>,
+    ) -> Self {
+        let device = &context.shared.device;
+        let path = path.as_ref();
+
+        let (equirect, loaded) = match load_equirect(device, queue, path) {
+            Ok(texture) => (texture, true),
+            Err(e) => {
+                log::warn!(
+                    "Failed to load environment map {}, using a constant color: {:?}",
+                    path.display(),
+                    e
+                );
+                (create_fallback_equirect(device, queue), false)
+            }
+        };
+
+        let equirect_view = equirect.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let cubemap = create_cubemap(device, "Environment cubemap", CUBEMAP_SIZE, 1);
+        let cubemap_storage_view = cube_storage_view(&cubemap, 0, 0);
+        let cubemap_view = cube_view(&cubemap);
+
+        let (conversion_bind_group_layout, conversion_bind_group) =
+            BindGroupBuilder::new("Equirectangular to cubemap", wgpu::ShaderStages::COMPUTE)
+                .texture_2d(
+                    0,
+                    "Equirectangular environment map",
+                    wgpu::TextureSampleType::Float { filterable: false },
+                    wgpu::BindingResource::TextureView(&equirect_view),
+                )
+                .storage_texture_2d_array(
+                    1,
+                    "Environment cubemap",
+                    ENVIRONMENT_FORMAT,
+                    wgpu::BindingResource::TextureView(&cubemap_storage_view),
+                )
+                .build(device);
+
+        let conversion_pipeline_id = context.cache_builder.add_shader(
+            EQUIRECT_TO_CUBEMAP_SHADER,
+            create_pipeline(
+                "Equirectangular to cubemap compute pipeline",
+                conversion_bind_group_layout,
+            ),
+        );
+
+        let sampler = create_sampler(device);
+
+        let irradiance_map = create_cubemap(device, "Irradiance map", IRRADIANCE_SIZE, 1);
+        let irradiance_storage_view = cube_storage_view(&irradiance_map, 0, 0);
+
+        let (irradiance_bind_group_layout, irradiance_bind_group) =
+            irradiance_bind_group_builder(&cubemap_view, &sampler, &irradiance_storage_view)
+                .build(device);
+
+        let irradiance_pipeline_id = context.cache_builder.add_shader(
+            IRRADIANCE_SHADER,
+            create_pipeline(
+                "Irradiance map compute pipeline",
+                irradiance_bind_group_layout,
+            ),
+        );
+
+        let prefiltered_map = create_cubemap(
+            device,
+            "Prefiltered environment map",
+            PREFILTERED_SIZE,
+            PREFILTERED_MIP_COUNT,
+        );
+
+        let prefilter_outputs = (0..PREFILTERED_MIP_COUNT)
+            .map(|mip| {
+                (
+                    create_prefilter_settings_buffer(device, mip, PREFILTERED_MIP_COUNT),
+                    cube_storage_view(&prefiltered_map, 0, mip),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let prefilter_bind_group_layout = prefilter_bind_group_builder(
+            &cubemap_view,
+            &sampler,
+            &prefilter_outputs[0].0,
+            &prefilter_outputs[0].1,
+        )
+        .build_layout(device);
+
+        let prefilter_bind_groups = prefilter_outputs
+            .iter()
+            .map(|(settings_buffer, output_view)| {
+                prefilter_bind_group_builder(&cubemap_view, &sampler, settings_buffer, output_view)
+                    .build_with_layout(device, &prefilter_bind_group_layout)
+            })
+            .collect();
+
+        let prefilter_pipeline_id = context.cache_builder.add_shader(
+            PREFILTER_SHADER,
+            create_pipeline(
+                "Prefiltered environment map compute pipeline",
+                prefilter_bind_group_layout,
+            ),
+        );
+
+        let brdf_lut = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("BRDF LUT"),
+            size: wgpu::Extent3d {
+                width: BRDF_LUT_SIZE,
+                height: BRDF_LUT_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: ENVIRONMENT_FORMAT,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let brdf_lut_view = brdf_lut.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let (brdf_lut_bind_group_layout, brdf_lut_bind_group) =
+            BindGroupBuilder::new("BRDF LUT generation", wgpu::ShaderStages::COMPUTE)
+                .storage_texture_2d(
+                    0,
+                    "BRDF LUT",
+                    ENVIRONMENT_FORMAT,
+                    wgpu::BindingResource::TextureView(&brdf_lut_view),
+                )
+                .build(device);
+
+        let brdf_lut_pipeline_id = context.cache_builder.add_shader(
+            BRDF_LUT_SHADER,
+            create_pipeline("BRDF LUT compute pipeline", brdf_lut_bind_group_layout),
+        );
+
+        // Used by passes that sample the environment
+        let (sampling_bind_group_layout, sampling_bind_group) =
+            BindGroupBuilder::new("Environment sampling", wgpu::ShaderStages::FRAGMENT)
+                .texture_cube(
+                    0,
+                    "Environment cubemap",
+                    wgpu::TextureSampleType::Float { filterable: true },
+                    wgpu::BindingResource::TextureView(&cubemap_view),
+                )
+                .sampler(
+                    1,
+                    "Environment sampler",
+                    wgpu::SamplerBindingType::Filtering,
+                    wgpu::BindingResource::Sampler(&sampler),
+                )
+                .texture_cube(
+                    2,
+                    "Irradiance map",
+                    wgpu::TextureSampleType::Float { filterable: true },
+                    wgpu::BindingResource::TextureView(&cube_view(&irradiance_map)),
+                )
+                .texture_cube(
+                    3,
+                    "Prefiltered environment map",
+                    wgpu::TextureSampleType::Float { filterable: true },
+                    wgpu::BindingResource::TextureView(&cube_view(&prefiltered_map)),
+                )
+                .texture_2d(
+                    4,
+                    "BRDF LUT",
+                    wgpu::TextureSampleType::Float { filterable: true },
+                    wgpu::BindingResource::TextureView(&brdf_lut_view),
+                )
+                .build(device);
+
+        Self {
+            loaded,
+            conversion_pipeline_id,
+            conversion_bind_group,
+            cubemap,
+
+            irradiance_pipeline_id,
+            irradiance_bind_group,
+            prefilter_pipeline_id,
+            prefilter_bind_groups,
+            brdf_lut_pipeline_id,
+            brdf_lut_bind_group,
+
+            sampling_bind_group_layout,
+            sampling_bind_group,
+        }
+    }
+
+    /// False if the image couldn't be loaded and the fallback color is used instead
+    pub fn is_loaded(&self) -> bool {
+        self.loaded
+    }
+
+    /// Fills the cubemap from the equirectangular image and generates the lighting maps from
+    /// it. This only needs to run once.
+    pub fn generate(&self, encoder: &mut wgpu::CommandEncoder, pipeline_cache: &PipelineCache) {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Environment map generation compute pass"),
+            timestamp_writes: None,
+        });
+
+        let dispatch_cube = |compute_pass: &mut wgpu::ComputePass, size: u32| {
+            let workgroups = size.div_ceil(WORKGROUP_SIZE);
+            compute_pass.dispatch_workgroups(workgroups, workgroups, 6);
+        };
+
+        compute_pass.set_pipeline(pipeline_cache.get(self.conversion_pipeline_id));
+        compute_pass.set_bind_group(0, &self.conversion_bind_group, &[]);
+        dispatch_cube(&mut compute_pass, self.cubemap.width());
+
+        compute_pass.set_pipeline(pipeline_cache.get(self.irradiance_pipeline_id));
+        compute_pass.set_bind_group(0, &self.irradiance_bind_group, &[]);
+        dispatch_cube(&mut compute_pass, IRRADIANCE_SIZE);
+
+        compute_pass.set_pipeline(pipeline_cache.get(self.prefilter_pipeline_id));
+        for (mip, bind_group) in self.prefilter_bind_groups.iter().enumerate() {
+            compute_pass.set_bind_group(0, bind_group, &[]);
+            dispatch_cube(&mut compute_pass, (PREFILTERED_SIZE >> mip).max(1));
+        }
+
+        compute_pass.set_pipeline(pipeline_cache.get(self.brdf_lut_pipeline_id));
+        compute_pass.set_bind_group(0, &self.brdf_lut_bind_group, &[]);
+        let workgroups = BRDF_LUT_SIZE.div_ceil(WORKGROUP_SIZE);
+        compute_pass.dispatch_workgroups(workgroups, workgroups, 1);
+    }
+
+    pub fn sampling_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.sampling_bind_group_layout
+    }
+
+    pub fn sampling_bind_group(&self) -> &wgpu::BindGroup {
+        &self.sampling_bind_group
+    }
+}
+
+pub fn create_sampler(device: &wgpu::Device) -> wgpu::Sampler {
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("Environment sampler"),
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    })
+}
+
+/// Roughness goes from 0 at mip 0 to 1 at the last mip
+pub fn create_prefilter_settings_buffer(
+    device: &wgpu::Device,
+    mip: u32,
+    mip_count: u32,
+) -> wgpu::Buffer {
+    let settings = PrefilterSettings {
+        roughness: mip as f32 / (mip_count - 1).max(1) as f32,
+        _padding: [0.0; 3],
+    };
+
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Prefilter settings buffer"),
+        contents: bytemuck::cast_slice(&[settings]),
+        usage: wgpu::BufferUsages::UNIFORM,
+    })
+}
+
+pub fn irradiance_bind_group_builder<'a>(
+    cubemap_view: &'a wgpu::TextureView,
+    sampler: &'a wgpu::Sampler,
+    output_view: &'a wgpu::TextureView,
+) -> BindGroupBuilder<'a> {
+    BindGroupBuilder::new("Irradiance map generation", wgpu::ShaderStages::COMPUTE)
+        .texture_cube(
+            0,
+            "Environment cubemap",
+            wgpu::TextureSampleType::Float { filterable: true },
+            wgpu::BindingResource::TextureView(cubemap_view),
+        )
+        .sampler(
+            1,
+            "Environment sampler",
+            wgpu::SamplerBindingType::Filtering,
+            wgpu::BindingResource::Sampler(sampler),
+        )
+        .storage_texture_2d_array(
+            2,
+            "Irradiance map",
+            ENVIRONMENT_FORMAT,
+            wgpu::BindingResource::TextureView(output_view),
+        )
+}
+
+pub fn prefilter_bind_group_builder<'a>(
+    cubemap_view: &'a wgpu::TextureView,
+    sampler: &'a wgpu::Sampler,
+    settings_buffer: &'a wgpu::Buffer,
+    output_view: &'a wgpu::TextureView,
+) -> BindGroupBuilder<'a> {
+    BindGroupBuilder::new("Environment prefiltering", wgpu::ShaderStages::COMPUTE)
+        .texture_cube(
+            0,
+            "Environment cubemap",
+            wgpu::TextureSampleType::Float { filterable: true },
+            wgpu::BindingResource::TextureView(cubemap_view),
+        )
+        .sampler(
+            1,
+            "Environment sampler",
+            wgpu::SamplerBindingType::Filtering,
+            wgpu::BindingResource::Sampler(sampler),
+        )
+        .uniform(
+            2,
+            "Prefilter settings buffer",
+            settings_buffer.as_entire_binding(),
+        )
+        .storage_texture_2d_array(
+            3,
+            "Prefiltered environment map mip",
+            ENVIRONMENT_FORMAT,
+            wgpu::BindingResource::TextureView(output_view),
+        )
+}
+
+pub fn create_pipeline(
+    label: &'static str,
+    bind_group_layout: wgpu::BindGroupLayout,
+) -> PipelineFactory<wgpu::ComputePipeline> {
+    Box::new(move |device, shader_module, cache| {
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(label),
+            layout: Some(
+                &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some(label),
+                    bind_group_layouts: &[&bind_group_layout],
+                    push_constant_ranges: &[],
+                }),
+            ),
+            module: &shader_module,
+            entry_point: Some("main"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache,
+        });
+
+        Ok(compute_pipeline)
+    })
+}
+
+fn create_cubemap(
+    device: &wgpu::Device,
+    label: &'static str,
+    size: u32,
+    mip_level_count: u32,
+) -> wgpu::Texture {
+    create_cubemap_array(device, label, size, mip_level_count, 1)
+}
+
+pub fn create_cubemap_array(
+    device: &wgpu::Device,
+    label: &'static str,
+    size: u32,
+    mip_level_count: u32,
+    cube_count: u32,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 6 * cube_count,
+        },
+        mip_level_count,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: ENVIRONMENT_FORMAT,
+        usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    })
+}
+
+/// Compute shaders write all faces of one mip as a 2D array. `cube` picks the cubemap of a
+/// cubemap array.
+pub fn cube_storage_view(texture: &wgpu::Texture, cube: u32, mip: u32) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        label: Some("Cubemap storage view"),
+        dimension: Some(wgpu::TextureViewDimension::D2Array),
+        base_mip_level: mip,
+        mip_level_count: Some(1),
+        base_array_layer: cube * 6,
+        array_layer_count: Some(6),
+        ..Default::default()
+    })
+}
+
+pub fn cube_view(texture: &wgpu::Texture) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        label: Some("Cubemap view"),
+        dimension: Some(wgpu::TextureViewDimension::Cube),
+        ..Default::default()
+    })
+}
+
+pub fn cube_array_view(texture: &wgpu::Texture) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        label: Some("Cubemap array view"),
+        dimension: Some(wgpu::TextureViewDimension::CubeArray),
+        ..Default::default()
+    })
+}
+
+fn load_equirect(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    path: &Path,
+) -> anyhow::Result<wgpu::Texture> {
+    let image = image::open(path)
+        .context("Failed to open image")?
+        .into_rgba32f();
+
+    Ok(create_equirect(
+        device,
+        queue,
+        image.width(),
+        image.height(),
+        bytemuck::cast_slice(image.as_raw()),
+    ))
+}
+
+fn create_fallback_equirect(device: &wgpu::Device, queue: &wgpu::Queue) -> wgpu::Texture {
+    create_equirect(device, queue, 1, 1, bytemuck::cast_slice(&FALLBACK_COLOR))
+}
+
+fn create_equirect(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    width: u32,
+    height: u32,
+    data: &[u8],
+) -> wgpu::Texture {
+    let size = wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Equirectangular environment map"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: EQUIRECT_FORMAT,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+
+    queue.write_texture(
+        TexelCopyTextureInfo {
+            texture: &texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        data,
+        TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(width * 16),
+            rows_per_image: None,
+        },
+        size,
+    );
+
+    texture
+}
@@ -4,20 +4,28 @@ use winit::dpi::PhysicalSize;
 
 use crate::rendering::util::bind_group_builder::BindGroupBuilder;
 
+pub const SHADER_PARAM_COUNT: usize = 4;
+
+/// Free-form parameters for shaders, usually animated by the timeline
+pub type ShaderParams = [f32; SHADER_PARAM_COUNT];
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Pod, Zeroable)]
 pub struct GlobalUniformState {
     pub resolution: [f32; 2],
     pub now: f32,
-    _padding: f32,
+    /// Fractional beat number of the soundtrack
+    pub beat: f32,
+    pub params: ShaderParams,
 }
 
 impl GlobalUniformState {
-    pub fn new(resolution: PhysicalSize<u32>, now: f32) -> Self {
+    pub fn new(resolution: PhysicalSize<u32>, now: f32, beat: f32, params: ShaderParams) -> Self {
         Self {
             resolution: [resolution.width as f32, resolution.height as f32],
             now,
-            _padding: 0.0,
+            beat,
+            params,
         }
     }
 }
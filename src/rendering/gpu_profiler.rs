@@ -0,0 +1,259 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+/// Maximum number of scopes per frame
+const MAX_SCOPES: usize = 32;
+const QUERY_COUNT: u32 = MAX_SCOPES as u32 * 2;
+/// Results are read back a few frames late, so a few buffers are needed to avoid stalling
+const READBACK_BUFFER_COUNT: usize = 3;
+/// Number of frames in the rolling average
+const HISTORY_LENGTH: usize = 60;
+
+struct ReadbackBuffer {
+    buffer: wgpu::Buffer,
+    scopes: Vec<&'static str>,
+    /// Set when the buffer has been submitted and is waiting to be mapped
+    in_flight: bool,
+    mapped: Arc<AtomicBool>,
+}
+
+struct ScopeTimings {
+    name: &'static str,
+    milliseconds: VecDeque<f32>,
+}
+
+impl ScopeTimings {
+    fn average(&self) -> f32 {
+        self.milliseconds.iter().sum::<f32>() / self.milliseconds.len().max(1) as f32
+    }
+}
+
+struct Timestamps {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    readback_buffers: Vec<ReadbackBuffer>,
+    /// Readback buffer used for the frame being recorded, if any is free
+    current_buffer: Option<usize>,
+    current_scopes: Vec<&'static str>,
+    open_scope: bool,
+}
+
+/// Measures GPU time of passes with timestamp queries written between them. Does nothing if the
+/// adapter doesn't support timestamps inside encoders.
+pub struct GpuProfiler {
+    timestamps: Option<Timestamps>,
+    /// Nanoseconds per timestamp tick
+    timestamp_period: f32,
+    timings: Vec<ScopeTimings>,
+}
+
+impl GpuProfiler {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, enabled: bool) -> Self {
+        let timestamps = enabled.then(|| {
+            let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("GPU profiler query set"),
+                ty: wgpu::QueryType::Timestamp,
+                count: QUERY_COUNT,
+            });
+
+            let buffer_size = QUERY_COUNT as u64 * std::mem::size_of::<u64>() as u64;
+
+            let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("GPU profiler resolve buffer"),
+                size: buffer_size,
+                usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            });
+
+            let readback_buffers = (0..READBACK_BUFFER_COUNT)
+                .map(|_| ReadbackBuffer {
+                    buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                        label: Some("GPU profiler readback buffer"),
+                        size: buffer_size,
+                        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                        mapped_at_creation: false,
+                    }),
+                    scopes: Vec::new(),
+                    in_flight: false,
+                    mapped: Arc::new(AtomicBool::new(false)),
+                })
+                .collect();
+
+            Timestamps {
+                query_set,
+                resolve_buffer,
+                readback_buffers,
+                current_buffer: None,
+                current_scopes: Vec::new(),
+                open_scope: false,
+            }
+        });
+
+        Self {
+            timestamps,
+            timestamp_period: queue.get_timestamp_period(),
+            timings: Vec::new(),
+        }
+    }
+
+    /// Reads back finished frames and picks a buffer for the next one. The device must have been
+    /// polled for the results to arrive.
+    pub fn begin_frame(&mut self) {
+        let Some(timestamps) = &mut self.timestamps else {
+            return;
+        };
+
+        for readback in &mut timestamps.readback_buffers {
+            if !readback.in_flight || !readback.mapped.load(Ordering::Acquire) {
+                continue;
+            }
+
+            {
+                let data = readback.buffer.slice(..).get_mapped_range();
+                let ticks: &[u64] = bytemuck::cast_slice(&data);
+
+                for (i, name) in readback.scopes.iter().enumerate() {
+                    let elapsed = ticks[i * 2 + 1].wrapping_sub(ticks[i * 2]);
+                    let milliseconds = elapsed as f32 * self.timestamp_period / 1_000_000.0;
+                    record_timing(&mut self.timings, name, milliseconds);
+                }
+            }
+
+            readback.buffer.unmap();
+            readback.mapped.store(false, Ordering::Release);
+            readback.in_flight = false;
+        }
+
+        timestamps.current_buffer = timestamps
+            .readback_buffers
+            .iter()
+            .position(|readback| !readback.in_flight);
+        timestamps.current_scopes.clear();
+    }
+
+    pub fn begin_scope(&mut self, encoder: &mut wgpu::CommandEncoder, name: &'static str) {
+        let Some(timestamps) = &mut self.timestamps else {
+            return;
+        };
+
+        if timestamps.current_buffer.is_none() || timestamps.current_scopes.len() >= MAX_SCOPES {
+            return;
+        }
+
+        let index = timestamps.current_scopes.len() as u32 * 2;
+        encoder.write_timestamp(&timestamps.query_set, index);
+        timestamps.current_scopes.push(name);
+        timestamps.open_scope = true;
+    }
+
+    pub fn end_scope(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let Some(timestamps) = &mut self.timestamps else {
+            return;
+        };
+
+        if !timestamps.open_scope {
+            return;
+        }
+
+        let index = timestamps.current_scopes.len() as u32 * 2 - 1;
+        encoder.write_timestamp(&timestamps.query_set, index);
+        timestamps.open_scope = false;
+    }
+
+    /// Copies the timestamps of the frame to a readback buffer. Call before finishing the encoder.
+    pub fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let Some(timestamps) = &mut self.timestamps else {
+            return;
+        };
+
+        let Some(current) = timestamps.current_buffer else {
+            return;
+        };
+
+        if timestamps.current_scopes.is_empty() {
+            timestamps.current_buffer = None;
+            return;
+        }
+
+        let query_count = timestamps.current_scopes.len() as u32 * 2;
+        let size = query_count as u64 * std::mem::size_of::<u64>() as u64;
+        encoder.resolve_query_set(
+            &timestamps.query_set,
+            0..query_count,
+            &timestamps.resolve_buffer,
+            0,
+        );
+
+        let readback = &mut timestamps.readback_buffers[current];
+        encoder.copy_buffer_to_buffer(&timestamps.resolve_buffer, 0, &readback.buffer, 0, size);
+        readback.scopes = std::mem::take(&mut timestamps.current_scopes);
+        readback.in_flight = true;
+    }
+
+    /// Starts mapping the readback buffer. Call after the frame has been submitted.
+    pub fn end_frame(&mut self) {
+        let Some(timestamps) = &mut self.timestamps else {
+            return;
+        };
+
+        let Some(current) = timestamps.current_buffer.take() else {
+            return;
+        };
+
+        let readback = &timestamps.readback_buffers[current];
+        let mapped = readback.mapped.clone();
+        readback
+            .buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                if result.is_ok() {
+                    mapped.store(true, Ordering::Release);
+                }
+            });
+    }
+
+    pub fn draw_ui(&self, ui: &imgui::Ui) {
+        ui.window("GPU profiler")
+            .size([260.0, 260.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                if self.timestamps.is_none() {
+                    ui.text("Timestamp queries are not supported");
+                    return;
+                }
+
+                let mut total = 0.0;
+                for timing in &self.timings {
+                    let average = timing.average();
+                    total += average;
+                    ui.text(format!("{:<20} {:>6.3} ms", timing.name, average));
+                }
+
+                ui.separator();
+                ui.text(format!("{:<20} {:>6.3} ms", "Total", total));
+            });
+    }
+}
+
+fn record_timing(timings: &mut Vec<ScopeTimings>, name: &'static str, milliseconds: f32) {
+    let index = match timings.iter().position(|timing| timing.name == name) {
+        Some(index) => index,
+        None => {
+            timings.push(ScopeTimings {
+                name,
+                milliseconds: VecDeque::with_capacity(HISTORY_LENGTH),
+            });
+            timings.len() - 1
+        }
+    };
+
+    let history = &mut timings[index].milliseconds;
+    if history.len() == HISTORY_LENGTH {
+        history.pop_front();
+    }
+    history.push_back(milliseconds);
+}
@@ -0,0 +1,277 @@
+use std::collections::VecDeque;
+
+use glam::{Vec3, Vec4Swizzles};
+
+use crate::{
+    asset_pipeline::mesh_baker::MeshInfo,
+    math::{
+        bounds::{BoundingSphere, AABB},
+        frustum::Frustum,
+    },
+    rendering::{
+        config::CullingBounds, instancing::drawable::Drawable,
+        util::buffer_readback::BufferReadback,
+    },
+    scene_graph::layers::Layers,
+};
+
+/// Results arrive a few frames late, this many frames can be in flight at once
+const READBACK_BUFFER_COUNT: usize = 3;
+/// Mismatches beyond this are only counted
+const MAX_LISTED_MISMATCHES: usize = 32;
+
+/// Everything the culling shader read during one dispatch. Kept until its results have been
+/// read back, since the drawables and meshes change in the meantime.
+pub struct CullingInputs {
+    pub frustum: Frustum,
+    pub lod_origin: Vec3,
+    pub lod_distance_scale: f32,
+    pub culling_bounds: CullingBounds,
+    pub layers: Layers,
+    /// The GPU culls occluded drawables, which the CPU can't know about
+    pub occlusion_culling: bool,
+    pub drawables: Vec<Drawable>,
+    pub meshes: Vec<MeshInfo>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Mismatch {
+    slot: usize,
+    /// Mesh of the selected LOD, or `MeshInfo::NO_LOD` if culled
+    gpu: u32,
+    cpu: u32,
+}
+
+#[derive(Debug, Default)]
+struct ValidationReport {
+    checked: usize,
+    visible: usize,
+    /// Visible on the CPU but culled on the GPU with occlusion culling enabled
+    occluded: usize,
+    mismatch_count: usize,
+    mismatches: Vec<Mismatch>,
+}
+
+/// Runs the CPU reference of frustum_culling.wgsl against the visibility the GPU wrote, to catch
+/// differences between the two. Only checks the camera view.
+pub struct CullingValidation {
+    readback: BufferReadback,
+    /// Inputs of the dispatch recorded this frame, until its readback has started
+    recorded: Option<CullingInputs>,
+    /// Inputs by readback frame
+    in_flight: VecDeque<(u64, CullingInputs)>,
+    validated_frame: Option<u64>,
+    report: Option<ValidationReport>,
+}
+
+impl CullingValidation {
+    /// Must be recreated when the drawable buffers grow past `drawable_capacity`
+    pub fn new(device: &wgpu::Device, drawable_capacity: u64) -> Self {
+        Self {
+            readback: BufferReadback::new(
+                device,
+                "Culling validation readback buffer",
+                drawable_capacity * std::mem::size_of::<u32>() as u64,
+                READBACK_BUFFER_COUNT,
+            ),
+            recorded: None,
+            in_flight: VecDeque::new(),
+            validated_frame: None,
+            report: None,
+        }
+    }
+
+    /// Copies the visibility written by a culling dispatch with the given inputs
+    pub fn record(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        visibility_buffer: &wgpu::Buffer,
+        inputs: CullingInputs,
+    ) {
+        let size = (inputs.drawables.len() * std::mem::size_of::<u32>()) as u64;
+        if size == 0 {
+            return;
+        }
+
+        self.readback.copy(encoder, visibility_buffer, 0, size);
+        self.recorded = Some(inputs);
+    }
+
+    /// Must be called after the commands from `record` have been submitted
+    pub fn after_submit(&mut self) {
+        let recorded = self.recorded.take();
+        if let (Some(frame), Some(inputs)) = (self.readback.after_submit(), recorded) {
+            self.in_flight.push_back((frame, inputs));
+            if self.in_flight.len() > READBACK_BUFFER_COUNT {
+                self.in_flight.pop_front();
+            }
+        }
+    }
+
+    /// Validates the latest readback, if it hasn't been already
+    pub fn update(&mut self) {
+        let Some((frame, data)) = self.readback.latest_with_frame() else {
+            return;
+        };
+        if self.validated_frame == Some(frame) {
+            return;
+        }
+        self.validated_frame = Some(frame);
+
+        // Readbacks can complete out of order, in which case the older ones are skipped
+        while let Some((inputs_frame, inputs)) = self.in_flight.pop_front() {
+            if inputs_frame != frame {
+                continue;
+            }
+
+            let gpu_visibility: Vec<u32> = bytemuck::pod_collect_to_vec(data);
+            let report = validate(&inputs, &gpu_visibility);
+
+            let previous_count = self
+                .report
+                .as_ref()
+                .map_or(0, |report| report.mismatch_count);
+            if report.mismatch_count > 0 && report.mismatch_count != previous_count {
+                log::warn!(
+                    "GPU culling differs from the CPU reference for {} drawables, first: {:?}",
+                    report.mismatch_count,
+                    report.mismatches.first()
+                );
+            }
+
+            self.report = Some(report);
+            break;
+        }
+    }
+
+    pub fn draw_ui(&self, ui: &imgui::Ui) {
+        let Some(report) = &self.report else {
+            ui.text("Culling validation: waiting for readback");
+            return;
+        };
+
+        ui.text(format!(
+            "Culling validation: {} / {} visible on the CPU",
+            report.visible, report.checked
+        ));
+        if report.occluded > 0 {
+            ui.text(format!("Occluded on the GPU: {}", report.occluded));
+        }
+        if report.mismatch_count == 0 {
+            ui.text("No mismatches");
+            return;
+        }
+
+        ui.text_colored(
+            [1.0, 0.3, 0.3, 1.0],
+            format!("Mismatches: {}", report.mismatch_count),
+        );
+        if let Some(_node) = ui.tree_node("Mismatched slots") {
+            for mismatch in &report.mismatches {
+                ui.text(format!(
+                    "Slot {}: GPU {}, CPU {}",
+                    mismatch.slot,
+                    format_mesh(mismatch.gpu),
+                    format_mesh(mismatch.cpu)
+                ));
+            }
+        }
+    }
+}
+
+fn format_mesh(mesh: u32) -> String {
+    if mesh == MeshInfo::NO_LOD {
+        "culled".to_string()
+    } else {
+        format!("mesh {}", mesh)
+    }
+}
+
+fn validate(inputs: &CullingInputs, gpu_visibility: &[u32]) -> ValidationReport {
+    let mut report = ValidationReport::default();
+
+    for (slot, (drawable, &gpu)) in inputs.drawables.iter().zip(gpu_visibility).enumerate() {
+        let cpu = cpu_visibility(inputs, drawable);
+        report.checked += 1;
+        if cpu != MeshInfo::NO_LOD {
+            report.visible += 1;
+        }
+
+        if gpu == cpu {
+            continue;
+        }
+
+        if inputs.occlusion_culling && gpu == MeshInfo::NO_LOD {
+            report.occluded += 1;
+            continue;
+        }
+
+        report.mismatch_count += 1;
+        if report.mismatches.len() < MAX_LISTED_MISMATCHES {
+            report.mismatches.push(Mismatch { slot, gpu, cpu });
+        }
+    }
+
+    report
+}
+
+/// CPU reference for frustum_culling.wgsl: the mesh of the selected LOD if the drawable is
+/// visible, `MeshInfo::NO_LOD` if it's culled
+fn cpu_visibility(inputs: &CullingInputs, drawable: &Drawable) -> u32 {
+    let mesh_index = drawable.primitive_index;
+    if mesh_index == Drawable::NO_PRIMITIVE || drawable.layers & inputs.layers.bits() == 0 {
+        return MeshInfo::NO_LOD;
+    }
+
+    let Some(mesh) = inputs.meshes.get(mesh_index as usize) else {
+        return MeshInfo::NO_LOD;
+    };
+
+    let aabb = AABB {
+        min: mesh.aabb_min,
+        max: mesh.aabb_max.xyz(),
+    };
+    let sphere = BoundingSphere {
+        center: mesh.bounding_sphere.xyz(),
+        radius: mesh.bounding_sphere.w,
+    };
+
+    let frustum = &inputs.frustum;
+    let transform = drawable.model_matrix;
+    let visible = match inputs.culling_bounds {
+        CullingBounds::Aabb => frustum.intersects_aabb(&aabb, transform),
+        CullingBounds::Sphere => frustum.intersects_sphere(&sphere, transform),
+        CullingBounds::SphereThenAabb => {
+            frustum.intersects_sphere(&sphere, transform)
+                && frustum.intersects_aabb(&aabb, transform)
+        }
+    };
+    if !visible {
+        return MeshInfo::NO_LOD;
+    }
+
+    let distance = transform
+        .transform_point3(aabb.center())
+        .distance(inputs.lod_origin);
+    select_lod(
+        &inputs.meshes,
+        mesh_index,
+        distance,
+        inputs.lod_distance_scale,
+    )
+}
+
+/// Follows the LOD chain of the mesh until the distance is within the range of a LOD
+fn select_lod(meshes: &[MeshInfo], mesh_index: u32, distance: f32, distance_scale: f32) -> u32 {
+    let mut selected = mesh_index;
+
+    while let Some(mesh) = meshes.get(selected as usize) {
+        if mesh.next_lod == MeshInfo::NO_LOD || distance <= mesh.lod_distance * distance_scale {
+            break;
+        }
+
+        selected = mesh.next_lod;
+    }
+
+    selected
+}
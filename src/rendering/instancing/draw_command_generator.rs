@@ -1,144 +1,174 @@
 use bytemuck::{Pod, Zeroable};
-use glam::Vec4;
+use glam::{Mat4, Vec3, Vec4};
 use wgpu::wgt::DrawIndexedIndirectArgs;
 
 use crate::{
     math::frustum::Frustum,
     rendering::{
-        instancing::{self},
-        passes::render_pass_context::ComputePassCreationContext,
-        shader_loader::{ComputePipelineCache, ComputePipelineId, ShaderDefinition},
-        util::bind_group_builder::BindGroupBuilder,
+        config::CullingBounds,
+        instancing::{
+            drawable_storage_buffer::DrawableBuffer,
+            meshlet_culling::{MeshletCulling, MeshletCullingInputs, MeshletDrawBuffers},
+        },
+        mesh_buffers::MeshBuffers,
+        passes::{hi_z_pass::HiZPass, render_pass_context::RenderPassCreationContext},
+        shader_loader::{ComputePipelineId, PipelineCache, PipelineCacheBuilder, ShaderDefinition},
+        util::{bind_group_builder::BindGroupBuilder, buffer_readback::BufferReadback},
     },
+    scene_graph::layers::Layers,
 };
 
 const FRUSTUM_CULLING_SHADER: ShaderDefinition = ShaderDefinition {
     name: "Frustum culling compute shader",
     path: "frustum_culling.wgsl",
+    defines: &[],
+};
+
+const OCCLUSION_CULLING_SHADER: ShaderDefinition = ShaderDefinition {
+    name: "Occlusion culling compute shader",
+    path: "occlusion_culling.wgsl",
+    defines: &[],
 };
 
 const GENERATE_DRAWS_SHADER: ShaderDefinition = ShaderDefinition {
     name: "Generate draw commands compute shader",
     path: "generate_draws.wgsl",
+    defines: &[],
 };
 
+/// Stats arrive a few frames late, this many frames can be in flight at once
+const STATS_READBACK_BUFFER_COUNT: usize = 3;
+/// The draw command count and the meshlet counters, which are left zero without meshlet culling
+const STATS_HEADER_SIZE: u64 = 3 * std::mem::size_of::<u32>() as u64;
+
+/// Results of culling for one view, read back from the GPU
+#[derive(Debug, Clone, Default)]
+pub struct DrawStats {
+    pub draw_command_count: u32,
+    /// Zero for views without meshlet culling. Can exceed `MAX_VISIBLE_MESHLETS`.
+    pub visible_meshlets: u32,
+    pub meshlet_triangles: u32,
+    pub visible_drawables: u32,
+    pub visible_drawables_by_mesh: Vec<u32>,
+}
+
 const GATHER_INSTANCE_DATA_SHADER: ShaderDefinition = ShaderDefinition {
     name: "Gather instance data compute shader",
     path: "gather_instance_data.wgsl",
+    defines: &[],
 };
 
 pub struct DrawCommandGenerator {
+    device: wgpu::Device,
+    mesh_info_buffer: wgpu::Buffer,
+    /// Per-mesh buffers are sized by this, and recreated when the mesh buffers are reallocated
+    mesh_count: u32,
+
     culling_pipeline_id: ComputePipelineId,
     frustum_buffer: wgpu::Buffer,
+    culling_bind_group_layout: wgpu::BindGroupLayout,
     culling_bind_group: wgpu::BindGroup,
     drawable_visibility_buffer: wgpu::Buffer,
     visible_drawables_by_mesh_buffer: wgpu::Buffer,
+    occlusion_culling: Option<OcclusionCulling>,
+    meshlet_culling: Option<MeshletCulling>,
 
     generate_draws_pipeline_id: ComputePipelineId,
+    generate_draws_bind_group_layout: wgpu::BindGroupLayout,
     generate_draws_bind_group: wgpu::BindGroup,
     pub draw_commands_buffer: wgpu::Buffer,
     pub draw_commands_count_buffer: wgpu::Buffer,
 
     gather_instance_data_pipeline_id: ComputePipelineId,
+    gather_instance_data_bind_group_layout: wgpu::BindGroupLayout,
     gather_instance_data_bind_group: wgpu::BindGroup,
+    base_offsets_buffer: wgpu::Buffer,
     drawable_local_indices_buffer: wgpu::Buffer,
+
+    /// Draw command count, the meshlet counters and the visible drawable counts by mesh
+    stats_readback: BufferReadback,
 }
 
 impl DrawCommandGenerator {
-    pub fn new(context: &mut ComputePassCreationContext) -> Self {
+    /// Visible drawables for each view (e.g. the main camera or a shadow casting light)
+    /// are gathered to a separate buffer, so each view needs its own generator.
+    /// Views with a Hi-Z pyramid can additionally run occlusion culling, and views with meshlet
+    /// draw buffers cull the meshlets of the visible drawables into them.
+    pub fn new(
+        context: &mut RenderPassCreationContext,
+        visible_drawables: &DrawableBuffer,
+        hi_z: Option<&HiZPass>,
+        meshlet_draws: Option<&MeshletDrawBuffers>,
+    ) -> Self {
         let device = &context.shared.device;
 
-        let visible_drawable_buffer = context.shared.drawable_buffers.visible_drawables.buffer();
-        let drawable_buffer = context.shared.drawable_buffers.all_drawables.buffer();
-        let mesh_info_buffer = &context.shared.mesh_buffers.meshes;
+        let visible_drawable_buffer = visible_drawables.buffer();
+        let all_drawables = &context.shared.drawable_buffers.all_drawables;
+        let drawable_buffer = all_drawables.buffer();
+        let mesh_info_buffer = &context.shared.mesh_buffers.meshes();
+        let mesh_count = context.shared.mesh_buffers.mesh_count();
         let pipeline_builder = &mut context.cache_builder;
 
         let frustum_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Frustum buffer"),
-            size: std::mem::size_of::<GpuFrustum>() as u64,
+            size: std::mem::size_of::<GpuCullingView>() as u64,
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
 
-        let drawable_visibility_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Drawable visibility buffer"),
-            size: (instancing::MAX_DRAWABLES as u32 * std::mem::size_of::<u32>() as u32) as u64,
-            usage: wgpu::BufferUsages::STORAGE
-                | wgpu::BufferUsages::COPY_SRC
-                | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+        let drawable_visibility_buffer =
+            create_drawable_visibility_buffer(device, all_drawables.capacity());
 
-        let visible_drawables_by_mesh_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Visible drawable counts by mesh buffer"),
-            size: (instancing::MAX_MESHES as u32 * std::mem::size_of::<u32>() as u32) as u64,
-            usage: wgpu::BufferUsages::STORAGE
-                | wgpu::BufferUsages::COPY_SRC
-                | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+        let visible_drawables_by_mesh_buffer =
+            create_visible_drawables_by_mesh_buffer(device, mesh_count);
 
-        let (culling_bind_group_layout, culling_bind_group) =
-            BindGroupBuilder::new("Frustum culling", wgpu::ShaderStages::COMPUTE)
-                .uniform(
-                    0,
-                    "Frustum uniform buffer",
-                    frustum_buffer.as_entire_binding(),
-                )
-                .storage_r(1, "Mesh info buffer", mesh_info_buffer.as_entire_binding())
-                .storage_r(2, "Drawable buffer", drawable_buffer.as_entire_binding())
-                .storage_rw(
-                    3,
-                    "Drawable visibility buffer",
-                    drawable_visibility_buffer.as_entire_binding(),
-                )
-                .storage_rw(
-                    4,
-                    "Visible drawables by mesh buffer",
-                    visible_drawables_by_mesh_buffer.as_entire_binding(),
-                )
-                .build(device);
+        let (culling_bind_group_layout, culling_bind_group) = Self::culling_bind_group_builder(
+            &frustum_buffer,
+            mesh_info_buffer,
+            &drawable_buffer,
+            &drawable_visibility_buffer,
+            &visible_drawables_by_mesh_buffer,
+        )
+        .build(device);
 
+        let pipeline_culling_bind_group_layout = culling_bind_group_layout.clone();
         let culling_pipeline_id = pipeline_builder.add_shader(
             FRUSTUM_CULLING_SHADER,
-            Box::new(move |device, shader_module| {
+            Box::new(move |device, shader_module, cache| {
                 let compute_pipeline =
                     device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
                         label: Some("Frustum culling compute pipeline"),
                         layout: Some(&device.create_pipeline_layout(
                             &wgpu::PipelineLayoutDescriptor {
                                 label: Some("Frustum culling pipeline layout"),
-                                bind_group_layouts: &[&culling_bind_group_layout],
+                                bind_group_layouts: &[&pipeline_culling_bind_group_layout],
                                 push_constant_ranges: &[],
                             },
                         )),
                         module: &shader_module,
                         entry_point: Some("main"),
                         compilation_options: wgpu::PipelineCompilationOptions::default(),
-                        cache: None,
+                        cache,
                     });
 
                 Ok(compute_pipeline)
             }),
         );
 
-        let base_offsets_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Base offsets buffer"),
-            size: (instancing::MAX_MESHES as u32 * std::mem::size_of::<u32>() as u32) as u64,
-            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
-            mapped_at_creation: false,
+        let occlusion_culling = hi_z.map(|hi_z| {
+            OcclusionCulling::new(
+                device,
+                pipeline_builder,
+                mesh_info_buffer,
+                &drawable_buffer,
+                &drawable_visibility_buffer,
+                &visible_drawables_by_mesh_buffer,
+                hi_z,
+            )
         });
 
-        let draw_commands_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Draw commands buffer"),
-            size: (instancing::MAX_MESHES as u32
-                * std::mem::size_of::<DrawIndexedIndirectArgs>() as u32) as u64,
-            usage: wgpu::BufferUsages::STORAGE
-                | wgpu::BufferUsages::COPY_SRC
-                | wgpu::BufferUsages::COPY_DST
-                | wgpu::BufferUsages::INDIRECT,
-            mapped_at_creation: false,
-        });
+        let base_offsets_buffer = create_base_offsets_buffer(device, mesh_count);
+        let draw_commands_buffer = create_draw_commands_buffer(device, mesh_count);
 
         let draw_commands_count_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Draw commands count buffer"),
@@ -151,142 +181,360 @@ impl DrawCommandGenerator {
         });
 
         let (generate_draws_bind_group_layout, generate_draws_bind_group) =
-            BindGroupBuilder::new("Generate draws", wgpu::ShaderStages::COMPUTE)
-                .storage_r(0, "Mesh info buffer", mesh_info_buffer.as_entire_binding())
-                .storage_r(
-                    1,
-                    "Visible drawables by mesh buffer",
-                    visible_drawables_by_mesh_buffer.as_entire_binding(),
-                )
-                .storage_rw(
-                    2,
-                    "Base offsets buffer",
-                    base_offsets_buffer.as_entire_binding(),
-                )
-                .storage_rw(
-                    3,
-                    "Draw commands buffer",
-                    draw_commands_buffer.as_entire_binding(),
-                )
-                .storage_rw(
-                    4,
-                    "Draw commands count buffer",
-                    draw_commands_count_buffer.as_entire_binding(),
-                )
-                .build(device);
+            Self::generate_draws_bind_group_builder(
+                mesh_info_buffer,
+                &visible_drawables_by_mesh_buffer,
+                &base_offsets_buffer,
+                &draw_commands_buffer,
+                &draw_commands_count_buffer,
+            )
+            .build(device);
 
+        let pipeline_generate_draws_bind_group_layout = generate_draws_bind_group_layout.clone();
         let generate_draws_pipeline_id = pipeline_builder.add_shader(
             GENERATE_DRAWS_SHADER,
-            Box::new(move |device, shader_module| {
+            Box::new(move |device, shader_module, cache| {
                 let compute_pipeline =
                     device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
                         label: Some("Generate draws compute pipeline"),
                         layout: Some(&device.create_pipeline_layout(
                             &wgpu::PipelineLayoutDescriptor {
                                 label: Some("Generate draws pipeline layout"),
-                                bind_group_layouts: &[&generate_draws_bind_group_layout],
+                                bind_group_layouts: &[&pipeline_generate_draws_bind_group_layout],
                                 push_constant_ranges: &[],
                             },
                         )),
                         module: &shader_module,
                         entry_point: Some("main"),
                         compilation_options: wgpu::PipelineCompilationOptions::default(),
-                        cache: None,
+                        cache,
                     });
 
                 Ok(compute_pipeline)
             }),
         );
 
-        let drawable_local_indices_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Drawable local indices buffer"),
-            size: (instancing::MAX_MESHES as u32 * std::mem::size_of::<u32>() as u32) as u64,
-            usage: wgpu::BufferUsages::STORAGE
-                | wgpu::BufferUsages::COPY_SRC
-                | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+        let drawable_local_indices_buffer =
+            create_drawable_local_indices_buffer(device, mesh_count);
 
         let (gather_instance_data_bind_group_layout, gather_instance_data_bind_group) =
-            BindGroupBuilder::new("Gather instance data", wgpu::ShaderStages::COMPUTE)
-                .storage_r(0, "Drawable buffer", drawable_buffer.as_entire_binding())
-                .storage_r(
-                    1,
-                    "Drawable visibility buffer",
-                    drawable_visibility_buffer.as_entire_binding(),
-                )
-                .storage_r(
-                    2,
-                    "Base offsets buffer",
-                    base_offsets_buffer.as_entire_binding(),
-                )
-                .storage_rw(
-                    3,
-                    "Visible drawable buffer",
-                    visible_drawable_buffer.as_entire_binding(),
-                )
-                .storage_rw(
-                    4,
-                    "Drawable local indices buffer",
-                    drawable_local_indices_buffer.as_entire_binding(),
-                )
-                .build(device);
+            Self::gather_instance_data_bind_group_builder(
+                &drawable_buffer,
+                &drawable_visibility_buffer,
+                &base_offsets_buffer,
+                &visible_drawable_buffer,
+                &drawable_local_indices_buffer,
+            )
+            .build(device);
 
+        let pipeline_gather_instance_data_bind_group_layout =
+            gather_instance_data_bind_group_layout.clone();
         let gather_instance_data_pipeline_id = pipeline_builder.add_shader(
             GATHER_INSTANCE_DATA_SHADER,
-            Box::new(move |device, shader_module| {
+            Box::new(move |device, shader_module, cache| {
                 let compute_pipeline =
                     device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
                         label: Some("Gather instance data compute pipeline"),
                         layout: Some(&device.create_pipeline_layout(
                             &wgpu::PipelineLayoutDescriptor {
                                 label: Some("Gather instance data pipeline layout"),
-                                bind_group_layouts: &[&gather_instance_data_bind_group_layout],
+                                bind_group_layouts: &[
+                                    &pipeline_gather_instance_data_bind_group_layout,
+                                ],
                                 push_constant_ranges: &[],
                             },
                         )),
                         module: &shader_module,
                         entry_point: Some("main"),
                         compilation_options: wgpu::PipelineCompilationOptions::default(),
-                        cache: None,
+                        cache,
                     });
 
                 Ok(compute_pipeline)
             }),
         );
 
+        let meshlet_culling = meshlet_draws.map(|draw_buffers| {
+            MeshletCulling::new(
+                device,
+                pipeline_builder,
+                context.shared.mesh_buffers.clone(),
+                draw_buffers,
+                &MeshletCullingInputs {
+                    view_buffer: &frustum_buffer,
+                    mesh_info_buffer,
+                    visible_drawable_buffer: &visible_drawable_buffer,
+                    visible_drawables_by_mesh_buffer: &visible_drawables_by_mesh_buffer,
+                    base_offsets_buffer: &base_offsets_buffer,
+                },
+            )
+        });
+
+        let stats_readback = create_stats_readback(device, mesh_count);
+
         Self {
+            device: device.clone(),
+            mesh_info_buffer: mesh_info_buffer.clone(),
+            mesh_count,
+
             culling_pipeline_id,
+            culling_bind_group_layout,
             culling_bind_group,
             frustum_buffer,
             drawable_visibility_buffer,
             visible_drawables_by_mesh_buffer,
+            occlusion_culling,
+            meshlet_culling,
 
             generate_draws_pipeline_id,
+            generate_draws_bind_group_layout,
             generate_draws_bind_group,
             draw_commands_buffer,
             draw_commands_count_buffer,
 
             gather_instance_data_pipeline_id,
+            gather_instance_data_bind_group_layout,
             gather_instance_data_bind_group,
+            base_offsets_buffer,
             drawable_local_indices_buffer,
+
+            stats_readback,
         }
     }
 
-    pub fn update_frustum(&self, queue: &wgpu::Queue, frustum: &Frustum) {
-        let gpu_frustum = GpuFrustum::from(frustum);
-        queue.write_buffer(
+    fn culling_bind_group_builder<'a>(
+        frustum_buffer: &'a wgpu::Buffer,
+        mesh_info_buffer: &'a wgpu::Buffer,
+        drawable_buffer: &'a wgpu::Buffer,
+        drawable_visibility_buffer: &'a wgpu::Buffer,
+        visible_drawables_by_mesh_buffer: &'a wgpu::Buffer,
+    ) -> BindGroupBuilder<'a> {
+        BindGroupBuilder::new("Frustum culling", wgpu::ShaderStages::COMPUTE)
+            .uniform(
+                0,
+                "Frustum uniform buffer",
+                frustum_buffer.as_entire_binding(),
+            )
+            .storage_r(1, "Mesh info buffer", mesh_info_buffer.as_entire_binding())
+            .storage_r(2, "Drawable buffer", drawable_buffer.as_entire_binding())
+            .storage_rw(
+                3,
+                "Drawable visibility buffer",
+                drawable_visibility_buffer.as_entire_binding(),
+            )
+            .storage_rw(
+                4,
+                "Visible drawables by mesh buffer",
+                visible_drawables_by_mesh_buffer.as_entire_binding(),
+            )
+    }
+
+    fn generate_draws_bind_group_builder<'a>(
+        mesh_info_buffer: &'a wgpu::Buffer,
+        visible_drawables_by_mesh_buffer: &'a wgpu::Buffer,
+        base_offsets_buffer: &'a wgpu::Buffer,
+        draw_commands_buffer: &'a wgpu::Buffer,
+        draw_commands_count_buffer: &'a wgpu::Buffer,
+    ) -> BindGroupBuilder<'a> {
+        BindGroupBuilder::new("Generate draws", wgpu::ShaderStages::COMPUTE)
+            .storage_r(0, "Mesh info buffer", mesh_info_buffer.as_entire_binding())
+            .storage_r(
+                1,
+                "Visible drawables by mesh buffer",
+                visible_drawables_by_mesh_buffer.as_entire_binding(),
+            )
+            .storage_rw(
+                2,
+                "Base offsets buffer",
+                base_offsets_buffer.as_entire_binding(),
+            )
+            .storage_rw(
+                3,
+                "Draw commands buffer",
+                draw_commands_buffer.as_entire_binding(),
+            )
+            .storage_rw(
+                4,
+                "Draw commands count buffer",
+                draw_commands_count_buffer.as_entire_binding(),
+            )
+    }
+
+    fn gather_instance_data_bind_group_builder<'a>(
+        drawable_buffer: &'a wgpu::Buffer,
+        drawable_visibility_buffer: &'a wgpu::Buffer,
+        base_offsets_buffer: &'a wgpu::Buffer,
+        visible_drawable_buffer: &'a wgpu::Buffer,
+        drawable_local_indices_buffer: &'a wgpu::Buffer,
+    ) -> BindGroupBuilder<'a> {
+        BindGroupBuilder::new("Gather instance data", wgpu::ShaderStages::COMPUTE)
+            .storage_r(0, "Drawable buffer", drawable_buffer.as_entire_binding())
+            .storage_r(
+                1,
+                "Drawable visibility buffer",
+                drawable_visibility_buffer.as_entire_binding(),
+            )
+            .storage_r(
+                2,
+                "Base offsets buffer",
+                base_offsets_buffer.as_entire_binding(),
+            )
+            .storage_rw(
+                3,
+                "Visible drawable buffer",
+                visible_drawable_buffer.as_entire_binding(),
+            )
+            .storage_rw(
+                4,
+                "Drawable local indices buffer",
+                drawable_local_indices_buffer.as_entire_binding(),
+            )
+    }
+
+    /// Must be called when the drawable buffers have been reallocated
+    pub fn resize_drawables(
+        &mut self,
+        all_drawables: &DrawableBuffer,
+        visible_drawables: &DrawableBuffer,
+        meshlet_draws: Option<&MeshletDrawBuffers>,
+    ) {
+        self.drawable_visibility_buffer =
+            create_drawable_visibility_buffer(&self.device, all_drawables.capacity());
+
+        self.rebuild_bind_groups(all_drawables, visible_drawables, meshlet_draws);
+    }
+
+    /// Must be called when the mesh buffers have been reallocated. Recreates the per-mesh
+    /// buffers, so the draw command buffers must be fetched again.
+    pub fn resize_meshes(
+        &mut self,
+        mesh_buffers: &MeshBuffers,
+        all_drawables: &DrawableBuffer,
+        visible_drawables: &DrawableBuffer,
+        meshlet_draws: Option<&MeshletDrawBuffers>,
+    ) {
+        let device = &self.device;
+        self.mesh_info_buffer = mesh_buffers.meshes();
+        self.mesh_count = mesh_buffers.mesh_count();
+
+        self.visible_drawables_by_mesh_buffer =
+            create_visible_drawables_by_mesh_buffer(device, self.mesh_count);
+        self.base_offsets_buffer = create_base_offsets_buffer(device, self.mesh_count);
+        self.draw_commands_buffer = create_draw_commands_buffer(device, self.mesh_count);
+        self.drawable_local_indices_buffer =
+            create_drawable_local_indices_buffer(device, self.mesh_count);
+        // Stats which are still in flight have the old mesh count, so they are dropped
+        self.stats_readback = create_stats_readback(device, self.mesh_count);
+
+        self.generate_draws_bind_group = Self::generate_draws_bind_group_builder(
+            &self.mesh_info_buffer,
+            &self.visible_drawables_by_mesh_buffer,
+            &self.base_offsets_buffer,
+            &self.draw_commands_buffer,
+            &self.draw_commands_count_buffer,
+        )
+        .build_with_layout(&self.device, &self.generate_draws_bind_group_layout);
+
+        self.rebuild_bind_groups(all_drawables, visible_drawables, meshlet_draws);
+    }
+
+    /// Recreates the bind groups which refer to both drawable and per-mesh buffers
+    fn rebuild_bind_groups(
+        &mut self,
+        all_drawables: &DrawableBuffer,
+        visible_drawables: &DrawableBuffer,
+        meshlet_draws: Option<&MeshletDrawBuffers>,
+    ) {
+        let drawable_buffer = all_drawables.buffer();
+        let visible_drawable_buffer = visible_drawables.buffer();
+
+        self.culling_bind_group = Self::culling_bind_group_builder(
             &self.frustum_buffer,
-            0,
-            bytemuck::cast_slice(&[gpu_frustum]),
-        );
+            &self.mesh_info_buffer,
+            &drawable_buffer,
+            &self.drawable_visibility_buffer,
+            &self.visible_drawables_by_mesh_buffer,
+        )
+        .build_with_layout(&self.device, &self.culling_bind_group_layout);
+
+        if let Some(occlusion_culling) = &mut self.occlusion_culling {
+            occlusion_culling.resize_buffers(
+                &self.mesh_info_buffer,
+                &drawable_buffer,
+                &self.drawable_visibility_buffer,
+                &self.visible_drawables_by_mesh_buffer,
+            );
+        }
+
+        self.gather_instance_data_bind_group = Self::gather_instance_data_bind_group_builder(
+            &drawable_buffer,
+            &self.drawable_visibility_buffer,
+            &self.base_offsets_buffer,
+            &visible_drawable_buffer,
+            &self.drawable_local_indices_buffer,
+        )
+        .build_with_layout(&self.device, &self.gather_instance_data_bind_group_layout);
+
+        if let (Some(meshlet_culling), Some(draw_buffers)) =
+            (&mut self.meshlet_culling, meshlet_draws)
+        {
+            meshlet_culling.resize_buffers(
+                draw_buffers,
+                &MeshletCullingInputs {
+                    view_buffer: &self.frustum_buffer,
+                    mesh_info_buffer: &self.mesh_info_buffer,
+                    visible_drawable_buffer: &visible_drawable_buffer,
+                    visible_drawables_by_mesh_buffer: &self.visible_drawables_by_mesh_buffer,
+                    base_offsets_buffer: &self.base_offsets_buffer,
+                },
+            );
+        }
     }
 
-    pub fn dispatch(
+    /// LOD distances are measured from `lod_origin` and multiplied by `lod_distance_scale`.
+    /// Drawables which aren't in any of `layers` are culled.
+    pub fn update_view(
         &self,
+        queue: &wgpu::Queue,
+        frustum: &Frustum,
+        lod_origin: Vec3,
+        lod_distance_scale: f32,
+        culling_bounds: CullingBounds,
+        layers: Layers,
+    ) {
+        let view = GpuCullingView {
+            frustum: GpuFrustum::from(frustum),
+            lod_origin: lod_origin.extend(lod_distance_scale),
+            culling_bounds: culling_bounds.shader_index(),
+            layers: layers.bits(),
+            _padding: [0; 2],
+        };
+        queue.write_buffer(&self.frustum_buffer, 0, bytemuck::cast_slice(&[view]));
+    }
+
+    pub fn update_occlusion_view_proj(&self, queue: &wgpu::Queue, view_proj: Mat4) {
+        if let Some(occlusion_culling) = &self.occlusion_culling {
+            queue.write_buffer(
+                &occlusion_culling.view_proj_buffer,
+                0,
+                bytemuck::cast_slice(&[view_proj]),
+            );
+        }
+    }
+
+    pub fn resize_hi_z(&mut self, hi_z: &HiZPass) {
+        if let Some(occlusion_culling) = &mut self.occlusion_culling {
+            occlusion_culling.resize_hi_z(hi_z);
+        }
+    }
+
+    /// Occlusion culling is skipped if the generator wasn't created with a Hi-Z pyramid
+    pub fn dispatch(
+        &mut self,
         encoder: &mut wgpu::CommandEncoder,
-        pipeline_cache: &ComputePipelineCache,
+        pipeline_cache: &PipelineCache,
         instance_count: u32,
+        occlusion_culling: bool,
     ) {
         const WORKGROUP_SIZE: u32 = 64;
         let drawable_workgroup_count = instance_count.div_ceil(WORKGROUP_SIZE);
@@ -294,7 +542,6 @@ impl DrawCommandGenerator {
         // Reset buffers
         encoder.clear_buffer(&self.draw_commands_buffer, 0, None);
         encoder.clear_buffer(&self.draw_commands_count_buffer, 0, None);
-        encoder.clear_buffer(&self.drawable_visibility_buffer, 0, None);
         encoder.clear_buffer(&self.visible_drawables_by_mesh_buffer, 0, None);
         encoder.clear_buffer(&self.drawable_local_indices_buffer, 0, None);
 
@@ -310,6 +557,23 @@ impl DrawCommandGenerator {
             compute_pass.dispatch_workgroups(drawable_workgroup_count, 1, 1);
         }
 
+        if let Some(occlusion_culling) = self
+            .occlusion_culling
+            .as_ref()
+            .filter(|_| occlusion_culling)
+        {
+            let pipeline = pipeline_cache.get(occlusion_culling.pipeline_id);
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Occlusion culling compute pass"),
+                timestamp_writes: None,
+            });
+
+            compute_pass.set_pipeline(pipeline);
+            compute_pass.set_bind_group(0, &occlusion_culling.bind_group, &[]);
+            compute_pass.set_bind_group(1, &occlusion_culling.hi_z_bind_group, &[]);
+            compute_pass.dispatch_workgroups(drawable_workgroup_count, 1, 1);
+        }
+
         {
             let pipeline = pipeline_cache.get(self.generate_draws_pipeline_id);
             let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
@@ -333,11 +597,266 @@ impl DrawCommandGenerator {
             compute_pass.set_bind_group(0, &self.gather_instance_data_bind_group, &[]);
             compute_pass.dispatch_workgroups(drawable_workgroup_count, 1, 1);
         }
+
+        if let Some(meshlet_culling) = &self.meshlet_culling {
+            meshlet_culling.dispatch(encoder, pipeline_cache, instance_count);
+        }
+
+        let count_size = std::mem::size_of::<u32>() as u64;
+        self.stats_readback
+            .copy(encoder, &self.draw_commands_count_buffer, 0, count_size);
+        if let Some(meshlet_culling) = &self.meshlet_culling {
+            self.stats_readback.copy(
+                encoder,
+                meshlet_culling.draw_args(),
+                count_size,
+                MeshletDrawBuffers::COUNTERS_SIZE,
+            );
+        }
+        self.stats_readback.copy(
+            encoder,
+            &self.visible_drawables_by_mesh_buffer,
+            STATS_HEADER_SIZE,
+            mesh_counts_size(self.mesh_count),
+        );
+    }
+
+    /// Must be called after the commands from `dispatch` have been submitted
+    pub fn after_submit(&mut self) {
+        self.stats_readback.after_submit();
+    }
+
+    /// Latest stats that have been read back, if any
+    pub fn stats(&mut self) -> Option<DrawStats> {
+        let data = self.stats_readback.latest()?;
+        // The data isn't necessarily aligned for u32
+        let values: Vec<u32> = bytemuck::pod_collect_to_vec(data);
+        let visible_drawables_by_mesh = values[3..].to_vec();
+
+        Some(DrawStats {
+            draw_command_count: values[0],
+            visible_meshlets: values[1],
+            meshlet_triangles: values[2] / 3,
+            visible_drawables: visible_drawables_by_mesh.iter().sum(),
+            visible_drawables_by_mesh,
+        })
     }
 
     pub fn draw_commands_count_buffer(&self) -> &wgpu::Buffer {
         &self.draw_commands_count_buffer
     }
+
+    /// Mesh of the selected LOD for each drawable slot, or `MeshInfo::NO_LOD` if it was culled
+    pub fn drawable_visibility_buffer(&self) -> &wgpu::Buffer {
+        &self.drawable_visibility_buffer
+    }
+}
+
+/// Second culling phase, which tests frustum culling survivors against a Hi-Z pyramid
+struct OcclusionCulling {
+    device: wgpu::Device,
+    pipeline_id: ComputePipelineId,
+    view_proj_buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    hi_z_bind_group_layout: wgpu::BindGroupLayout,
+    /// Recreated when the pyramid is resized
+    hi_z_bind_group: wgpu::BindGroup,
+}
+
+impl OcclusionCulling {
+    fn new(
+        device: &wgpu::Device,
+        pipeline_builder: &mut PipelineCacheBuilder,
+        mesh_info_buffer: &wgpu::Buffer,
+        drawable_buffer: &wgpu::Buffer,
+        drawable_visibility_buffer: &wgpu::Buffer,
+        visible_drawables_by_mesh_buffer: &wgpu::Buffer,
+        hi_z: &HiZPass,
+    ) -> Self {
+        let view_proj_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Occlusion culling view projection buffer"),
+            size: std::mem::size_of::<Mat4>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let (bind_group_layout, bind_group) = Self::bind_group_builder(
+            &view_proj_buffer,
+            mesh_info_buffer,
+            drawable_buffer,
+            drawable_visibility_buffer,
+            visible_drawables_by_mesh_buffer,
+        )
+        .build(device);
+
+        let (hi_z_bind_group_layout, hi_z_bind_group) =
+            Self::hi_z_bind_group_builder(hi_z).build(device);
+
+        let pipeline_bind_group_layout = bind_group_layout.clone();
+        let pipeline_hi_z_bind_group_layout = hi_z_bind_group_layout.clone();
+        let pipeline_id = pipeline_builder.add_shader(
+            OCCLUSION_CULLING_SHADER,
+            Box::new(move |device, shader_module, cache| {
+                let compute_pipeline =
+                    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: Some("Occlusion culling compute pipeline"),
+                        layout: Some(&device.create_pipeline_layout(
+                            &wgpu::PipelineLayoutDescriptor {
+                                label: Some("Occlusion culling pipeline layout"),
+                                bind_group_layouts: &[
+                                    &pipeline_bind_group_layout,
+                                    &pipeline_hi_z_bind_group_layout,
+                                ],
+                                push_constant_ranges: &[],
+                            },
+                        )),
+                        module: &shader_module,
+                        entry_point: Some("main"),
+                        compilation_options: wgpu::PipelineCompilationOptions::default(),
+                        cache,
+                    });
+
+                Ok(compute_pipeline)
+            }),
+        );
+
+        Self {
+            device: device.clone(),
+            pipeline_id,
+            view_proj_buffer,
+            bind_group_layout,
+            bind_group,
+            hi_z_bind_group_layout,
+            hi_z_bind_group,
+        }
+    }
+
+    fn bind_group_builder<'a>(
+        view_proj_buffer: &'a wgpu::Buffer,
+        mesh_info_buffer: &'a wgpu::Buffer,
+        drawable_buffer: &'a wgpu::Buffer,
+        drawable_visibility_buffer: &'a wgpu::Buffer,
+        visible_drawables_by_mesh_buffer: &'a wgpu::Buffer,
+    ) -> BindGroupBuilder<'a> {
+        BindGroupBuilder::new("Occlusion culling", wgpu::ShaderStages::COMPUTE)
+            .uniform(
+                0,
+                "Occlusion culling view projection buffer",
+                view_proj_buffer.as_entire_binding(),
+            )
+            .storage_r(1, "Mesh info buffer", mesh_info_buffer.as_entire_binding())
+            .storage_r(2, "Drawable buffer", drawable_buffer.as_entire_binding())
+            .storage_rw(
+                3,
+                "Drawable visibility buffer",
+                drawable_visibility_buffer.as_entire_binding(),
+            )
+            .storage_rw(
+                4,
+                "Visible drawables by mesh buffer",
+                visible_drawables_by_mesh_buffer.as_entire_binding(),
+            )
+    }
+
+    fn resize_buffers(
+        &mut self,
+        mesh_info_buffer: &wgpu::Buffer,
+        drawable_buffer: &wgpu::Buffer,
+        drawable_visibility_buffer: &wgpu::Buffer,
+        visible_drawables_by_mesh_buffer: &wgpu::Buffer,
+    ) {
+        self.bind_group = Self::bind_group_builder(
+            &self.view_proj_buffer,
+            mesh_info_buffer,
+            drawable_buffer,
+            drawable_visibility_buffer,
+            visible_drawables_by_mesh_buffer,
+        )
+        .build_with_layout(&self.device, &self.bind_group_layout);
+    }
+
+    fn hi_z_bind_group_builder(hi_z: &HiZPass) -> BindGroupBuilder {
+        BindGroupBuilder::new("Occlusion culling Hi-Z", wgpu::ShaderStages::COMPUTE).texture_2d(
+            0,
+            "Hi-Z pyramid",
+            wgpu::TextureSampleType::Float { filterable: false },
+            wgpu::BindingResource::TextureView(hi_z.pyramid_view()),
+        )
+    }
+
+    fn resize_hi_z(&mut self, hi_z: &HiZPass) {
+        self.hi_z_bind_group = Self::hi_z_bind_group_builder(hi_z)
+            .build_with_layout(&self.device, &self.hi_z_bind_group_layout);
+    }
+}
+
+fn create_drawable_visibility_buffer(device: &wgpu::Device, capacity: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Drawable visibility buffer"),
+        size: capacity * std::mem::size_of::<u32>() as u64,
+        usage: wgpu::BufferUsages::STORAGE
+            | wgpu::BufferUsages::COPY_SRC
+            | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_visible_drawables_by_mesh_buffer(device: &wgpu::Device, mesh_count: u32) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Visible drawable counts by mesh buffer"),
+        size: mesh_counts_size(mesh_count),
+        usage: wgpu::BufferUsages::STORAGE
+            | wgpu::BufferUsages::COPY_SRC
+            | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_base_offsets_buffer(device: &wgpu::Device, mesh_count: u32) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Base offsets buffer"),
+        size: mesh_counts_size(mesh_count),
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_draw_commands_buffer(device: &wgpu::Device, mesh_count: u32) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Draw commands buffer"),
+        size: mesh_count.max(1) as u64 * std::mem::size_of::<DrawIndexedIndirectArgs>() as u64,
+        usage: wgpu::BufferUsages::STORAGE
+            | wgpu::BufferUsages::COPY_SRC
+            | wgpu::BufferUsages::COPY_DST
+            | wgpu::BufferUsages::INDIRECT,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_drawable_local_indices_buffer(device: &wgpu::Device, mesh_count: u32) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Drawable local indices buffer"),
+        size: mesh_counts_size(mesh_count),
+        usage: wgpu::BufferUsages::STORAGE
+            | wgpu::BufferUsages::COPY_SRC
+            | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_stats_readback(device: &wgpu::Device, mesh_count: u32) -> BufferReadback {
+    BufferReadback::new(
+        device,
+        "Draw stats readback buffer",
+        STATS_HEADER_SIZE + mesh_counts_size(mesh_count),
+        STATS_READBACK_BUFFER_COUNT,
+    )
+}
+
+/// Size of a buffer with a u32 for each mesh
+fn mesh_counts_size(mesh_count: u32) -> u64 {
+    mesh_count.max(1) as u64 * std::mem::size_of::<u32>() as u64
 }
 
 /// GPU representation of frustum planes (must match WGSL struct)
@@ -347,6 +866,20 @@ pub struct GpuFrustum {
     pub planes: [Vec4; 6],
 }
 
+/// Frustum and LOD selection parameters of a view (must match WGSL struct)
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct GpuCullingView {
+    frustum: GpuFrustum,
+    /// W is the multiplier for LOD distances
+    lod_origin: Vec4,
+    /// `CullingBounds::shader_index`
+    culling_bounds: u32,
+    /// Drawables outside these layers are culled
+    layers: u32,
+    _padding: [u32; 2],
+}
+
 impl From<&Frustum> for GpuFrustum {
     fn from(frustum: &Frustum) -> Self {
         let planes = frustum.planes.map(|plane| {
@@ -1,4 +1,6 @@
-use glam::Mat4;
+use glam::{Mat4, Vec3, Vec4};
+
+use crate::scene_graph::draw_params::DrawParams;
 
 /// This should match the same structure defined in WGSL
 #[repr(C)]
@@ -6,24 +8,71 @@ use glam::Mat4;
 pub struct Drawable {
     pub model_matrix: Mat4,
     pub inverse_transpose_model_matrix: Mat4,
+    /// Model matrix of the previous frame, for motion vectors
+    pub previous_model_matrix: Mat4,
     pub primitive_index: u32,
     pub material_id: u32,
-    _padding: [u32; 2],
+    /// Offset of the drawable's first joint matrix in the joint buffer
+    pub joint_offset: u32,
+    /// `Layers` of the object, which culling compares against the layer filter of the view
+    pub layers: u32,
+    /// `DrawParams` of the object
+    pub tint: Vec3,
+    pub emission: f32,
+    pub params: Vec4,
 }
 
 impl Drawable {
+    /// Joint offset of drawables that aren't skinned
+    pub const NO_JOINTS: u32 = u32::MAX;
+    /// Primitive index of unused slots in the drawable buffer, which are skipped by culling
+    pub const NO_PRIMITIVE: u32 = u32::MAX;
+
+    /// Placeholder for unused slots in the drawable buffer
+    pub const EMPTY: Drawable = Drawable {
+        model_matrix: Mat4::ZERO,
+        inverse_transpose_model_matrix: Mat4::ZERO,
+        previous_model_matrix: Mat4::ZERO,
+        primitive_index: Self::NO_PRIMITIVE,
+        material_id: 0,
+        joint_offset: Self::NO_JOINTS,
+        layers: 0,
+        tint: Vec3::ONE,
+        emission: 1.0,
+        params: Vec4::ZERO,
+    };
+
     pub fn new(
         model_matrix: Mat4,
         inverse_transpose_model_matrix: Mat4,
         primitive_index: u32,
         material_id: u32,
+        joint_offset: u32,
+        layers: u32,
+        params: &DrawParams,
     ) -> Self {
         Self {
             model_matrix,
             inverse_transpose_model_matrix,
+            previous_model_matrix: model_matrix,
             primitive_index,
             material_id,
-            _padding: [0; 2],
+            joint_offset,
+            layers,
+            tint: params.tint,
+            emission: params.emission,
+            params: params.params,
+        }
+    }
+
+    pub fn params_differ(&self, other: &Drawable) -> bool {
+        self.tint != other.tint || self.emission != other.emission || self.params != other.params
+    }
+
+    pub fn with_previous_model_matrix(self, previous_model_matrix: Mat4) -> Self {
+        Self {
+            previous_model_matrix,
+            ..self
         }
     }
 }
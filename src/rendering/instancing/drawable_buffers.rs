@@ -1,23 +1,93 @@
-use crate::rendering::instancing::{drawable_storage_buffer::DrawableBuffer, MAX_DRAWABLES};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::rendering::instancing::{
+    drawable_storage_buffer::DrawableBuffer, meshlet_culling::MeshletDrawBuffers,
+    INITIAL_DRAWABLE_CAPACITY, INITIAL_TRANSPARENT_DRAWABLE_CAPACITY,
+};
+
+/// Number of frames whose culling outputs are kept apart. wgpu only exposes a single queue, so
+/// culling can't run on a separate compute queue. With separate buffers, the culling dispatches
+/// of a frame don't write anything the previous frame's draws still read, which lets the driver
+/// overlap them instead of waiting for the draws to finish.
+pub const CULLING_FRAMES: usize = 2;
+
+/// Written by culling and read by the passes that draw the results during the same frame
+pub struct CulledDrawables {
+    pub visible_drawables: DrawableBuffer,
+    /// Drawables visible from the shadow casting light
+    pub shadow_visible_drawables: DrawableBuffer,
+    /// Meshlets visible from the camera, if the meshes were baked with meshlets
+    pub meshlet_draws: Option<MeshletDrawBuffers>,
+}
+
+impl CulledDrawables {
+    fn new(device: &wgpu::Device, initial_capacity: u64, meshlets: bool) -> Self {
+        Self {
+            visible_drawables: DrawableBuffer::new(device, initial_capacity),
+            shadow_visible_drawables: DrawableBuffer::new(device, initial_capacity),
+            meshlet_draws: meshlets.then(|| MeshletDrawBuffers::new(device)),
+        }
+    }
+}
 
-#[derive(Clone)]
 pub struct DrawableBuffers {
     pub all_drawables: DrawableBuffer,
-    pub visible_drawables: DrawableBuffer,
+    /// Culling outputs of consecutive frames, see `CULLING_FRAMES`
+    pub culled_frames: [CulledDrawables; CULLING_FRAMES],
+    /// Index into `culled_frames` used by the current frame
+    current_frame: AtomicUsize,
+    /// Alpha blended drawables, sorted back to front. These are not culled.
+    pub transparent_drawables: DrawableBuffer,
 }
 
 impl DrawableBuffers {
-    pub fn new(device: &wgpu::Device, initial_capacity: u64) -> Self {
+    pub fn new(device: &wgpu::Device, initial_capacity: u64, meshlets: bool) -> Self {
         let all_drawables = DrawableBuffer::new(device, initial_capacity);
-        let visible_drawables = DrawableBuffer::new(device, initial_capacity);
+        let culled_frames =
+            std::array::from_fn(|_| CulledDrawables::new(device, initial_capacity, meshlets));
+        let transparent_drawables =
+            DrawableBuffer::new(device, INITIAL_TRANSPARENT_DRAWABLE_CAPACITY);
 
         Self {
             all_drawables,
-            visible_drawables,
+            culled_frames,
+            current_frame: AtomicUsize::new(0),
+            transparent_drawables,
         }
     }
 
-    pub fn new_default_capacity(device: &wgpu::Device) -> Self {
-        Self::new(device, MAX_DRAWABLES as u64)
+    pub fn new_default_capacity(device: &wgpu::Device, meshlets: bool) -> Self {
+        Self::new(device, INITIAL_DRAWABLE_CAPACITY, meshlets)
+    }
+
+    pub fn current_frame(&self) -> usize {
+        self.current_frame.load(Ordering::Relaxed)
+    }
+
+    /// Culling outputs of the current frame
+    pub fn culled(&self) -> &CulledDrawables {
+        &self.culled_frames[self.current_frame()]
+    }
+
+    /// Switches to the next set of culling outputs. Must be called once per frame, before
+    /// culling. Returns the new frame index.
+    pub fn advance_frame(&self) -> usize {
+        let frame = (self.current_frame() + 1) % CULLING_FRAMES;
+        self.current_frame.store(frame, Ordering::Relaxed);
+        frame
+    }
+
+    /// Grows the culled buffers to fit `count` drawables. Returns true if they were reallocated,
+    /// in which case bind groups referring to them must be recreated.
+    pub fn ensure_capacity(&self, device: &wgpu::Device, count: u64) -> bool {
+        // Any of the views can see every drawable, so they grow together
+        let mut grown = self.all_drawables.ensure_capacity(device, count);
+        for culled in &self.culled_frames {
+            grown |= culled.visible_drawables.ensure_capacity(device, count);
+            grown |= culled
+                .shadow_visible_drawables
+                .ensure_capacity(device, count);
+        }
+        grown
     }
 }
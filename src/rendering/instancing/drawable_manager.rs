@@ -1,103 +1,805 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
+
+use glam::{Mat4, Vec3};
+use rayon::prelude::*;
 
 use crate::{
+    asset_pipeline::{materials::AlphaMode, mesh_baker::MeshInfo},
     math::frustum::Frustum,
+    model::ModelPrimitive,
     rendering::{
+        config::CullingBounds,
         instancing::{
-            draw_command_generator::DrawCommandGenerator, drawable::Drawable, DrawableBuffers,
+            culling_validation::{CullingInputs, CullingValidation},
+            draw_command_generator::{DrawCommandGenerator, DrawStats},
+            drawable::Drawable,
+            drawable_buffers::CULLING_FRAMES,
+            drawable_slots::{DrawableSlot, DrawableSlots},
+            gpu_transforms::GpuTransforms,
+            DrawableBuffers,
+        },
+        joint_buffer::{JointBuffer, MAX_JOINTS},
+        mesh_buffers::{MeshBuffers, MAX_SKINNED_MESHES, MAX_SKINNED_VERTICES},
+        passes::{
+            hi_z_pass::HiZPass, render_pass_context::RenderPassCreationContext,
+            skinning_pass::SkinningJob,
         },
-        passes::render_pass_context::ComputePassCreationContext,
-        shader_loader::ComputePipelineCache,
+        shader_loader::PipelineCache,
+    },
+    scene_graph::{
+        layers::Layers,
+        object3d::{Object3D, ObjectId},
+        scene::Scene,
+        scene_model::SceneModelId,
+        skin::Skin,
     },
-    scene_graph::scene::Scene,
 };
 
+/// What was written to the drawable buffer during the last frame
+#[derive(Debug, Default, Clone, Copy)]
+struct UploadStats {
+    drawables: usize,
+    writes: usize,
+}
+
+/// Slots of the opaque primitives of one object
+struct ObjectDrawables {
+    model_id: SceneModelId,
+    slots: Vec<DrawableSlot>,
+    /// Objects which weren't seen during a frame have been removed or disabled
+    last_seen_frame: u64,
+}
+
+/// Drawables of one object, built in parallel with the other objects. They are not skinned yet.
+struct GatheredObject<'a> {
+    id: ObjectId,
+    model_id: SceneModelId,
+    /// The skin and the world matrix of the object
+    skin: Option<(&'a Skin, Mat4)>,
+    changed: bool,
+    drawables: Vec<(&'a ModelPrimitive, Drawable)>,
+}
+
+impl<'a> GatheredObject<'a> {
+    /// Returns None for objects that aren't drawn. Materials from `material_count` onwards
+    /// haven't been loaded by the renderer, and are replaced with the fallback material.
+    fn new(
+        scene: &'a Scene,
+        id: ObjectId,
+        object: &'a Object3D,
+        material_count: u32,
+    ) -> Option<Self> {
+        if !object.enabled {
+            return None;
+        }
+
+        let model_id = object.model_id?;
+        let model = scene.models.get(model_id)?;
+
+        let matrix = *object.transform.get_world_matrix();
+        let inverse_transpose_matrix = *object.transform.get_inverse_transpose_world_matrix();
+
+        let drawables = model
+            .model
+            .primitives
+            .iter()
+            .map(|primitive| {
+                let drawable = Drawable::new(
+                    matrix,
+                    inverse_transpose_matrix,
+                    primitive.global_index as u32,
+                    (primitive.material_id.index() as u32).min(material_count),
+                    Drawable::NO_JOINTS,
+                    object.layers.bits(),
+                    &object.draw_params,
+                );
+
+                (primitive, drawable)
+            })
+            .collect();
+
+        Some(Self {
+            id,
+            model_id,
+            skin: object
+                .skin_id
+                .and_then(|id| scene.skins.get(id))
+                .map(|skin| (skin, matrix)),
+            changed: object.transform.has_changed(),
+            drawables,
+        })
+    }
+}
+
+/// Keeps the drawable buffer in sync with the scene. Each (object, primitive) pair has a
+/// persistent slot in the buffer, and only the slots of changed objects are uploaded.
 pub struct DrawableManager {
+    device: wgpu::Device,
     drawable_buffers: Arc<DrawableBuffers>,
-    drawables: Vec<Drawable>,
-    draw_command_generator: DrawCommandGenerator,
+    slots: DrawableSlots,
+    object_drawables: HashMap<ObjectId, ObjectDrawables>,
+    frame: u64,
+    /// Set when the drawables can't be trusted, e.g. after models have been reloaded
+    force_update: bool,
+    upload_stats: UploadStats,
+    /// Blended drawables bypass GPU culling, and don't cast shadows
+    transparent_drawables: Vec<Drawable>,
+    joint_buffer: Arc<JointBuffer>,
+    joint_matrices: Vec<Mat4>,
+    mesh_buffers: Arc<MeshBuffers>,
+    /// Each skinned primitive gets its own mesh, which points to its skinned vertices
+    skinned_meshes: Vec<MeshInfo>,
+    skinning_jobs: Vec<SkinningJob>,
+    skinned_vertex_count: u32,
+    /// One generator per set of culling outputs, indexed by the current culling frame
+    draw_command_generators: [DrawCommandGenerator; CULLING_FRAMES],
+    shadow_draw_command_generators: [DrawCommandGenerator; CULLING_FRAMES],
+    gpu_transforms: GpuTransforms,
+    /// Resolves world matrices on the GPU, so that moving objects don't need their drawables
+    /// uploaded. The CPU still computes them for everything else.
+    gpu_transforms_enabled: bool,
+    occlusion_culling_enabled: bool,
+    /// Multiplier for the distances at which LODs are switched
+    lod_distance_scale: f32,
+    culling_bounds: CullingBounds,
+    /// Layer filters of the camera and shadow views
+    camera_layers: Layers,
+    shadow_layers: Layers,
+    /// Compares the camera's GPU culling against the CPU reference, if enabled from the UI
+    culling_validation: Option<CullingValidation>,
 }
 
 impl DrawableManager {
-    pub fn new(context: &mut ComputePassCreationContext) -> Self {
-        let draw_command_generator = DrawCommandGenerator::new(context);
+    pub fn new(context: &mut RenderPassCreationContext, hi_z: &HiZPass) -> Self {
+        let drawable_buffers = context.shared.drawable_buffers.clone();
+        let draw_command_generators = std::array::from_fn(|frame| {
+            let culled = &drawable_buffers.culled_frames[frame];
+            DrawCommandGenerator::new(
+                context,
+                &culled.visible_drawables,
+                Some(hi_z),
+                culled.meshlet_draws.as_ref(),
+            )
+        });
+        // The pyramid is built from the camera's depth buffer, so it's useless for shadows.
+        // Shadows are drawn from whole primitives.
+        let shadow_draw_command_generators = std::array::from_fn(|frame| {
+            let culled = &drawable_buffers.culled_frames[frame];
+            DrawCommandGenerator::new(context, &culled.shadow_visible_drawables, None, None)
+        });
+        let gpu_transforms = GpuTransforms::new(context, &drawable_buffers.all_drawables);
 
         Self {
-            drawable_buffers: context.shared.drawable_buffers.clone(),
-            draw_command_generator,
-            drawables: Vec::new(),
+            device: context.shared.device.clone(),
+            drawable_buffers,
+            draw_command_generators,
+            shadow_draw_command_generators,
+            gpu_transforms,
+            gpu_transforms_enabled: false,
+            slots: DrawableSlots::default(),
+            object_drawables: HashMap::new(),
+            frame: 0,
+            force_update: false,
+            upload_stats: UploadStats::default(),
+            transparent_drawables: Vec::new(),
+            joint_buffer: context.shared.joint_buffer.clone(),
+            joint_matrices: Vec::new(),
+            mesh_buffers: context.shared.mesh_buffers.clone(),
+            skinned_meshes: Vec::new(),
+            skinning_jobs: Vec::new(),
+            skinned_vertex_count: 0,
+            occlusion_culling_enabled: context.shared.config.quality.occlusion_culling,
+            lod_distance_scale: context.shared.config.quality.lod_distance_scale,
+            culling_bounds: context.shared.config.culling_bounds,
+            camera_layers: Layers::CAMERA.union(Layers::UI_3D),
+            shadow_layers: Layers::SHADOW_CASTER,
+            culling_validation: None,
+        }
+    }
+
+    pub fn resize_hi_z(&mut self, hi_z: &HiZPass) {
+        for generator in &mut self.draw_command_generators {
+            generator.resize_hi_z(hi_z);
+        }
+    }
+
+    /// Must be called when the mesh buffers have been reallocated
+    pub fn resize_meshes(&mut self) {
+        let buffers = &self.drawable_buffers;
+        for (culled, (generator, shadow_generator)) in buffers.culled_frames.iter().zip(
+            self.draw_command_generators
+                .iter_mut()
+                .zip(&mut self.shadow_draw_command_generators),
+        ) {
+            generator.resize_meshes(
+                &self.mesh_buffers,
+                &buffers.all_drawables,
+                &culled.visible_drawables,
+                culled.meshlet_draws.as_ref(),
+            );
+            shadow_generator.resize_meshes(
+                &self.mesh_buffers,
+                &buffers.all_drawables,
+                &culled.shadow_visible_drawables,
+                None,
+            );
         }
     }
 
-    pub fn update_from_scene(&mut self, scene: &Scene, queue: &wgpu::Queue, imgui_ui: &imgui::Ui) {
-        self.gather_drawables_from_scene(scene, imgui_ui);
+    pub fn occlusion_culling_enabled(&self) -> bool {
+        self.occlusion_culling_enabled
+    }
+
+    /// Updates all drawables on the next frame, even if their transforms haven't changed
+    pub fn invalidate(&mut self) {
+        self.force_update = true;
+    }
 
+    /// Culling runs over every slot, the free ones are skipped on the GPU
+    fn drawable_count(&self) -> u32 {
+        self.slots.slot_count() as u32
+    }
+
+    pub fn update_from_scene(
+        &mut self,
+        scene: &Scene,
+        camera_position: Vec3,
+        material_count: u32,
+        queue: &wgpu::Queue,
+        imgui_ui: &imgui::Ui,
+    ) {
+        self.sync_with_scene(scene, camera_position, material_count);
+
+        // The previous contents are lost when the buffers are reallocated
+        if self.ensure_capacity() {
+            self.slots.mark_all_dirty();
+        }
+
+        self.upload_drawables(queue);
+        if self.gpu_transforms_enabled {
+            let all_drawables = &self.drawable_buffers.all_drawables;
+            self.gpu_transforms
+                .update_objects(queue, scene, all_drawables);
+            self.gpu_transforms
+                .update_drawable_objects(queue, self.drawable_objects());
+        }
         self.drawable_buffers
-            .all_drawables
-            .write_drawables_at_offset(queue, &self.drawables, 0);
+            .transparent_drawables
+            .write_drawables_at_offset(queue, &self.transparent_drawables, 0);
+        self.joint_buffer.write(queue, &self.joint_matrices);
+        self.mesh_buffers
+            .write_skinned_meshes(queue, &self.skinned_meshes);
+
+        self.draw_ui(imgui_ui);
     }
 
-    fn gather_drawables_from_scene(&mut self, scene: &Scene, imgui_ui: &imgui::Ui) {
-        self.drawables.clear();
+    /// Allocates slots for new objects, updates the ones of changed objects and frees the ones
+    /// of objects that have been removed or disabled
+    fn sync_with_scene(&mut self, scene: &Scene, camera_position: Vec3, material_count: u32) {
+        self.frame += 1;
+        self.joint_matrices.clear();
+        self.skinned_meshes.clear();
+        self.skinning_jobs.clear();
+        self.skinned_vertex_count = 0;
 
-        for (_, object) in scene.objects.iter() {
-            if !object.enabled {
-                continue;
+        // Removed objects are no longer in the scene, so their slots are only freed here
+        for id in scene.removed_objects() {
+            if let Some(removed) = self.object_drawables.remove(id) {
+                removed
+                    .slots
+                    .into_iter()
+                    .for_each(|slot| self.slots.remove(slot));
             }
+        }
 
-            let Some(model_id) = object.model_id else {
-                continue;
-            };
+        // Distance from the camera to the center of the bounds, for sorting
+        let mut transparent_drawables = Vec::new();
 
-            let Some(model) = scene.models.get(model_id) else {
-                continue;
-            };
+        // Building the drawables is independent for each object, but skinning and slot
+        // allocation have to happen in order
+        let gathered_objects = scene
+            .objects
+            .par_iter()
+            .filter_map(|(id, object)| GatheredObject::new(scene, id, object, material_count))
+            .collect::<Vec<_>>();
 
-            let matrix = object.transform.get_world_matrix().clone();
-            let inverse_transpose_matrix = object
-                .transform
-                .get_inverse_transpose_world_matrix()
-                .clone();
+        for gathered in gathered_objects {
+            let GatheredObject {
+                id,
+                model_id,
+                skin,
+                changed,
+                mut drawables,
+            } = gathered;
 
-            for primitive in &model.model.primitives {
-                self.drawables.push(Drawable::new(
-                    matrix,
-                    inverse_transpose_matrix,
-                    primitive.global_index as u32,
-                    primitive.material_id.index() as u32,
-                ));
+            // Skinned meshes that don't fit into the joint buffer are drawn in their bind pose
+            if let Some((skin, matrix)) = skin {
+                if self.joint_matrices.len() + skin.joints.len() <= MAX_JOINTS {
+                    let joint_offset = self.joint_matrices.len() as u32;
+                    skin.append_joint_matrices(scene, &matrix, &mut self.joint_matrices);
+
+                    for (primitive, drawable) in &mut drawables {
+                        drawable.joint_offset = joint_offset;
+                        if let Some(mesh_index) = self.add_skinned_mesh(primitive, joint_offset) {
+                            drawable.primitive_index = mesh_index;
+                        }
+                    }
+                }
+            }
+
+            let mut opaque_drawables = Vec::with_capacity(drawables.len());
+
+            for (primitive, drawable) in drawables {
+                // Transparent drawables are sorted and uploaded every frame. They are only drawn
+                // by the camera, so they are filtered here instead of during culling.
+                if primitive.alpha_mode == AlphaMode::Blend {
+                    if drawable.layers & self.camera_layers.bits() == 0 {
+                        continue;
+                    }
+
+                    let bounds = &primitive.bounding_box;
+                    let center = drawable
+                        .model_matrix
+                        .transform_point3((bounds.min + bounds.max) * 0.5);
+                    transparent_drawables
+                        .push((center.distance_squared(camera_position), drawable));
+                } else {
+                    opaque_drawables.push(drawable);
+                }
+            }
+
+            let existing = self.object_drawables.get_mut(&id).filter(|existing| {
+                existing.model_id == model_id && existing.slots.len() == opaque_drawables.len()
+            });
+
+            match existing {
+                Some(existing) => {
+                    existing.last_seen_frame = self.frame;
+                    // GPU transforms are written to the drawable buffer on the GPU
+                    let gpu_transforms = self.gpu_transforms_enabled;
+                    let changed = self.force_update || (changed && !gpu_transforms);
+
+                    for (slot, drawable) in existing.slots.iter().zip(opaque_drawables) {
+                        let previous = *self.slots.get(*slot);
+                        // Motion vectors need one more upload after the object stops moving
+                        let moved_last_frame = !gpu_transforms
+                            && previous.previous_model_matrix != previous.model_matrix;
+
+                        // Joint offsets and skinned meshes shift when skinned objects before this
+                        // one change
+                        if changed
+                            || moved_last_frame
+                            || previous.joint_offset != drawable.joint_offset
+                            || previous.primitive_index != drawable.primitive_index
+                            || previous.layers != drawable.layers
+                            || previous.params_differ(&drawable)
+                        {
+                            let drawable =
+                                drawable.with_previous_model_matrix(previous.model_matrix);
+                            self.slots.update(*slot, drawable);
+                        }
+                    }
+                }
+                None => {
+                    // New object, or its model has changed
+                    if opaque_drawables
+                        .iter()
+                        .any(|drawable| drawable.material_id == material_count)
+                    {
+                        log::warn!(
+                            "Object {} uses a material that was added after the renderer loaded the materials",
+                            scene.get_object(id).map_or("?", |object| object.name())
+                        );
+                    }
+
+                    if let Some(previous) = self.object_drawables.remove(&id) {
+                        previous
+                            .slots
+                            .into_iter()
+                            .for_each(|slot| self.slots.remove(slot));
+                    }
+
+                    let slots = opaque_drawables
+                        .into_iter()
+                        .map(|drawable| self.slots.add(drawable))
+                        .collect();
+
+                    self.object_drawables.insert(
+                        id,
+                        ObjectDrawables {
+                            model_id,
+                            slots,
+                            last_seen_frame: self.frame,
+                        },
+                    );
+                }
+            }
+        }
+
+        let frame = self.frame;
+        let slots = &mut self.slots;
+        self.object_drawables.retain(|_, object_drawables| {
+            let seen = object_drawables.last_seen_frame == frame;
+            if !seen {
+                object_drawables
+                    .slots
+                    .iter()
+                    .for_each(|slot| slots.remove(*slot));
+            }
+            seen
+        });
+
+        transparent_drawables.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        self.transparent_drawables = transparent_drawables
+            .into_iter()
+            .map(|(_, drawable)| drawable)
+            .collect();
+
+        self.force_update = false;
+    }
+
+    /// Allocates a skinned mesh and vertices for a primitive, and returns the index of the mesh.
+    /// Returns None if they're full, in which case the primitive is drawn in its bind pose.
+    fn add_skinned_mesh(&mut self, primitive: &ModelPrimitive, joint_offset: u32) -> Option<u32> {
+        let vertex_count = primitive.vertices.len() as u32;
+        if self.skinned_meshes.len() >= MAX_SKINNED_MESHES as usize
+            || self.skinned_vertex_count + vertex_count > MAX_SKINNED_VERTICES
+        {
+            return None;
+        }
+
+        let source = self.mesh_buffers.mesh_info(primitive.global_index as u32)?;
+        let destination_vertex_offset =
+            self.mesh_buffers.skinned_vertex_offset() + self.skinned_vertex_count;
+
+        self.skinning_jobs.push(SkinningJob {
+            source_vertex_offset: source.vertex_offset,
+            destination_vertex_offset,
+            vertex_count,
+            joint_offset,
+        });
+
+        let mesh_index = self.mesh_buffers.first_skinned_mesh() + self.skinned_meshes.len() as u32;
+        self.skinned_meshes
+            .push(source.skinned(destination_vertex_offset));
+        self.skinned_vertex_count += vertex_count;
+
+        Some(mesh_index)
+    }
+
+    /// Arena index of the object of each drawable slot
+    fn drawable_objects(&self) -> Vec<u32> {
+        let mut drawable_objects = vec![GpuTransforms::NO_OBJECT; self.slots.slot_count()];
+
+        for (id, object_drawables) in &self.object_drawables {
+            for slot in &object_drawables.slots {
+                drawable_objects[slot.index()] = id.index() as u32;
             }
+        }
+
+        drawable_objects
+    }
+
+    fn upload_drawables(&mut self, queue: &wgpu::Queue) {
+        let buffer = &self.drawable_buffers.all_drawables;
+        let mut stats = UploadStats::default();
+
+        for range in self.slots.take_dirty_ranges() {
+            buffer.write_drawables_at_offset(
+                queue,
+                &self.slots.drawables()[range.clone()],
+                range.start as u32,
+            );
+            stats.drawables += range.len();
+            stats.writes += 1;
+        }
+
+        self.upload_stats = stats;
+    }
 
-            /*self.drawables.iter_mut().for_each(|drawable| {
-                drawable.calculate_inverse_transpose_model_matrix();
-            });*/
+    fn draw_ui(&mut self, imgui_ui: &imgui::Ui) {
+        let camera_stats = self.draw_command_generator().stats();
+        let shadow_stats = self.shadow_draw_command_generator().stats();
+        let total = self.drawable_count();
+        let free = self.slots.free_count() as u32;
+        let upload_stats = self.upload_stats;
+        if let Some(validation) = &mut self.culling_validation {
+            validation.update();
         }
 
         imgui_ui
             .window("Instance Manager")
             .size([300.0, 200.0], imgui::Condition::FirstUseEver)
             .build(|| {
-                imgui_ui.text(format!("Total drawables: {}", self.drawables.len()));
+                imgui_ui.text(format!(
+                    "Drawable slots: {} ({} free)",
+                    format_thousands(total),
+                    format_thousands(free)
+                ));
+                imgui_ui.text(format!(
+                    "Transparent drawables: {}",
+                    format_thousands(self.transparent_drawables.len() as u32)
+                ));
+                imgui_ui.text(format!(
+                    "Skinned meshes: {} / {} ({} vertices)",
+                    self.skinned_meshes.len(),
+                    MAX_SKINNED_MESHES,
+                    format_thousands(self.skinned_vertex_count)
+                ));
+                imgui_ui.text(format!(
+                    "Uploaded: {} drawables in {} writes",
+                    format_thousands(upload_stats.drawables as u32),
+                    upload_stats.writes
+                ));
+                imgui_ui.checkbox("Occlusion culling", &mut self.occlusion_culling_enabled);
+                if imgui_ui.checkbox("GPU transforms", &mut self.gpu_transforms_enabled) {
+                    // Whichever side takes over has missed the changes made in the meantime
+                    self.force_update = true;
+                    self.gpu_transforms.invalidate();
+                }
+                imgui_ui.slider("LOD distance scale", 0.0, 4.0, &mut self.lod_distance_scale);
+                for bounds in CullingBounds::ALL {
+                    imgui_ui.radio_button(bounds.name(), &mut self.culling_bounds, bounds);
+                }
+                self.camera_layers.draw_ui(imgui_ui, "Camera layers");
+                self.shadow_layers.draw_ui(imgui_ui, "Shadow layers");
+
+                let mut validate_culling = self.culling_validation.is_some();
+                if imgui_ui.checkbox("Validate culling", &mut validate_culling) {
+                    self.culling_validation = validate_culling.then(|| {
+                        let capacity = self.drawable_buffers.all_drawables.capacity();
+                        CullingValidation::new(&self.device, capacity)
+                    });
+                }
+
+                draw_stats_ui(imgui_ui, "Camera", camera_stats.as_ref(), total - free);
+                draw_stats_ui(imgui_ui, "Shadows", shadow_stats.as_ref(), total - free);
+
+                if let Some(validation) = &self.culling_validation {
+                    imgui_ui.separator();
+                    if self.gpu_transforms_enabled {
+                        // The CPU copies of the drawables miss the transforms resolved on the GPU
+                        imgui_ui.text_disabled("Culling validation needs GPU transforms disabled");
+                    } else {
+                        validation.draw_ui(imgui_ui);
+                    }
+                }
             });
     }
 
+    /// Grows the drawable buffers if the scene no longer fits into them. Returns true if they
+    /// were reallocated.
+    fn ensure_capacity(&mut self) -> bool {
+        let buffers = &self.drawable_buffers;
+
+        let grown = buffers.ensure_capacity(&self.device, self.drawable_count() as u64);
+        if grown {
+            for (culled, (generator, shadow_generator)) in buffers.culled_frames.iter().zip(
+                self.draw_command_generators
+                    .iter_mut()
+                    .zip(&mut self.shadow_draw_command_generators),
+            ) {
+                generator.resize_drawables(
+                    &buffers.all_drawables,
+                    &culled.visible_drawables,
+                    culled.meshlet_draws.as_ref(),
+                );
+                shadow_generator.resize_drawables(
+                    &buffers.all_drawables,
+                    &culled.shadow_visible_drawables,
+                    None,
+                );
+            }
+            self.gpu_transforms.resize_drawables(&buffers.all_drawables);
+
+            if let Some(validation) = &mut self.culling_validation {
+                *validation =
+                    CullingValidation::new(&self.device, buffers.all_drawables.capacity());
+            }
+        }
+
+        buffers
+            .transparent_drawables
+            .ensure_capacity(&self.device, self.transparent_drawables.len() as u64);
+
+        grown
+    }
+
+    /// Writes the world matrices resolved on the GPU to the drawable buffer, if enabled. Must be
+    /// dispatched before culling.
+    pub fn dispatch_transforms(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline_cache: &PipelineCache,
+    ) {
+        if self.gpu_transforms_enabled {
+            self.gpu_transforms.dispatch(encoder, pipeline_cache);
+        }
+    }
+
     pub fn cull_and_generate_commands(
         &mut self,
         queue: &wgpu::Queue,
         encoder: &mut wgpu::CommandEncoder,
-        pipeline_cache: &ComputePipelineCache,
+        pipeline_cache: &PipelineCache,
         frustum: &Frustum,
+        camera_position: Vec3,
+        hi_z_view_proj: Option<Mat4>,
     ) {
-        self.draw_command_generator.update_frustum(queue, frustum);
-        self.draw_command_generator
-            .dispatch(encoder, pipeline_cache, self.drawables.len() as u32);
+        let frame = self.drawable_buffers.advance_frame();
+        let drawable_count = self.drawable_count();
+        let generator = &mut self.draw_command_generators[frame];
+
+        generator.update_view(
+            queue,
+            frustum,
+            camera_position,
+            self.lod_distance_scale,
+            self.culling_bounds,
+            self.camera_layers,
+        );
+
+        // The Hi-Z pyramid is built from the previous frame, so drawables are tested against
+        // the view projection it was rendered with
+        let hi_z_view_proj = hi_z_view_proj.filter(|_| self.occlusion_culling_enabled);
+        if let Some(view_proj) = hi_z_view_proj {
+            generator.update_occlusion_view_proj(queue, view_proj);
+        }
+
+        generator.dispatch(
+            encoder,
+            pipeline_cache,
+            drawable_count,
+            hi_z_view_proj.is_some(),
+        );
+
+        if let Some(validation) = self
+            .culling_validation
+            .as_mut()
+            .filter(|_| !self.gpu_transforms_enabled)
+        {
+            let inputs = CullingInputs {
+                frustum: *frustum,
+                lod_origin: camera_position,
+                lod_distance_scale: self.lod_distance_scale,
+                culling_bounds: self.culling_bounds,
+                layers: self.camera_layers,
+                occlusion_culling: hi_z_view_proj.is_some(),
+                drawables: self.slots.drawables().to_vec(),
+                meshes: self.mesh_buffers.mesh_infos(),
+            };
+            validation.record(encoder, generator.drawable_visibility_buffer(), inputs);
+        }
     }
 
+    pub fn cull_and_generate_shadow_commands(
+        &mut self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline_cache: &PipelineCache,
+        light_frustum: &Frustum,
+        camera_position: Vec3,
+    ) {
+        let frame = self.drawable_buffers.current_frame();
+        let drawable_count = self.drawable_count();
+        let generator = &mut self.shadow_draw_command_generators[frame];
+
+        // LODs are selected by the distance to the camera, so that shadows match the geometry
+        generator.update_view(
+            queue,
+            light_frustum,
+            camera_position,
+            self.lod_distance_scale,
+            self.culling_bounds,
+            self.shadow_layers,
+        );
+        generator.dispatch(encoder, pipeline_cache, drawable_count, false);
+    }
+
+    /// Starts reading back draw stats. Must be called after the frame has been submitted.
+    pub fn after_submit(&mut self) {
+        let frame = self.drawable_buffers.current_frame();
+        self.draw_command_generators[frame].after_submit();
+        self.shadow_draw_command_generators[frame].after_submit();
+        if let Some(validation) = &mut self.culling_validation {
+            validation.after_submit();
+        }
+    }
+
+    /// Skinning jobs of the current frame, which must be dispatched before culling
+    pub fn skinning_jobs(&self) -> &[SkinningJob] {
+        &self.skinning_jobs
+    }
+
+    /// Sorted back to front, in the same order as in the transparent drawable buffer
+    pub fn transparent_drawables(&self) -> &[Drawable] {
+        &self.transparent_drawables
+    }
+
+    /// Opaque drawables by slot, in the same order as in the drawable buffer. Free slots have
+    /// `Drawable::NO_PRIMITIVE`.
+    pub fn drawables(&self) -> &[Drawable] {
+        self.slots.drawables()
+    }
+
+    /// Slot indices of the opaque drawables of an object, empty if it has none
+    pub fn object_slots(&self, id: ObjectId) -> impl Iterator<Item = usize> + '_ {
+        self.object_drawables
+            .get(&id)
+            .into_iter()
+            .flat_map(|object_drawables| object_drawables.slots.iter().map(|slot| slot.index()))
+    }
+
+    fn draw_command_generator(&self) -> &DrawCommandGenerator {
+        &self.draw_command_generators[self.drawable_buffers.current_frame()]
+    }
+
+    fn shadow_draw_command_generator(&self) -> &DrawCommandGenerator {
+        &self.shadow_draw_command_generators[self.drawable_buffers.current_frame()]
+    }
+
+    /// Draw commands generated for the current frame
     pub fn draw_commands_buffer(&self) -> &wgpu::Buffer {
-        &self.draw_command_generator.draw_commands_buffer
+        &self.draw_command_generator().draw_commands_buffer
     }
 
     pub fn draw_commands_count_buffer(&self) -> &wgpu::Buffer {
-        self.draw_command_generator.draw_commands_count_buffer()
+        self.draw_command_generator().draw_commands_count_buffer()
     }
+
+    pub fn shadow_draw_commands_buffer(&self) -> &wgpu::Buffer {
+        &self.shadow_draw_command_generator().draw_commands_buffer
+    }
+
+    pub fn shadow_draw_commands_count_buffer(&self) -> &wgpu::Buffer {
+        self.shadow_draw_command_generator()
+            .draw_commands_count_buffer()
+    }
+}
+
+fn draw_stats_ui(ui: &imgui::Ui, view_name: &str, stats: Option<&DrawStats>, total: u32) {
+    ui.separator();
+
+    let Some(stats) = stats else {
+        ui.text(format!("{}: waiting for readback", view_name));
+        return;
+    };
+
+    ui.text(format!(
+        "{}: {} / {} drawables visible",
+        view_name,
+        format_thousands(stats.visible_drawables),
+        format_thousands(total)
+    ));
+    ui.text(format!("Draw commands: {}", stats.draw_command_count));
+    if stats.visible_meshlets > 0 {
+        ui.text(format!(
+            "Meshlets: {} visible, {} triangles",
+            format_thousands(stats.visible_meshlets),
+            format_thousands(stats.meshlet_triangles)
+        ));
+    }
+
+    if let Some(_node) = ui.tree_node(format!("Visible by mesh##{}", view_name)) {
+        for (mesh, count) in stats.visible_drawables_by_mesh.iter().enumerate() {
+            if *count > 0 {
+                ui.text(format!("Mesh {}: {}", mesh, format_thousands(*count)));
+            }
+        }
+    }
+}
+
+/// Formats e.g. 12420 as "12,420"
+fn format_thousands(value: u32) -> String {
+    let digits = value.to_string();
+    let mut formatted = String::with_capacity(digits.len() + digits.len() / 3);
+
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+
+    formatted
 }
@@ -0,0 +1,93 @@
+use std::ops::Range;
+
+use crate::rendering::instancing::drawable::Drawable;
+
+/// Index of a drawable in the drawable buffer. It stays the same for as long as the drawable
+/// exists, so it can be used to refer to the drawable from the GPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DrawableSlot(u32);
+
+impl DrawableSlot {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// CPU copy of the drawable buffer. Removed drawables leave holes which are reused by later
+/// additions, and modified slots are tracked so that only they need to be uploaded.
+#[derive(Default)]
+pub struct DrawableSlots {
+    drawables: Vec<Drawable>,
+    free_slots: Vec<DrawableSlot>,
+    dirty_slots: Vec<DrawableSlot>,
+}
+
+impl DrawableSlots {
+    pub fn add(&mut self, drawable: Drawable) -> DrawableSlot {
+        let slot = match self.free_slots.pop() {
+            Some(slot) => {
+                self.drawables[slot.0 as usize] = drawable;
+                slot
+            }
+            None => {
+                self.drawables.push(drawable);
+                DrawableSlot(self.drawables.len() as u32 - 1)
+            }
+        };
+
+        self.dirty_slots.push(slot);
+        slot
+    }
+
+    pub fn remove(&mut self, slot: DrawableSlot) {
+        self.drawables[slot.0 as usize] = Drawable::EMPTY;
+        self.free_slots.push(slot);
+        self.dirty_slots.push(slot);
+    }
+
+    pub fn update(&mut self, slot: DrawableSlot, drawable: Drawable) {
+        self.drawables[slot.0 as usize] = drawable;
+        self.dirty_slots.push(slot);
+    }
+
+    pub fn get(&self, slot: DrawableSlot) -> &Drawable {
+        &self.drawables[slot.0 as usize]
+    }
+
+    /// Marks every slot as modified, e.g. when the buffer has been reallocated
+    pub fn mark_all_dirty(&mut self) {
+        self.dirty_slots.clear();
+        self.dirty_slots
+            .extend((0..self.drawables.len() as u32).map(DrawableSlot));
+    }
+
+    /// Returns the modified slots as sorted, contiguous ranges and clears them
+    pub fn take_dirty_ranges(&mut self) -> Vec<Range<usize>> {
+        self.dirty_slots.sort_unstable_by_key(|slot| slot.0);
+        self.dirty_slots.dedup();
+
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+        for slot in self.dirty_slots.drain(..) {
+            let index = slot.0 as usize;
+            match ranges.last_mut() {
+                Some(range) if range.end == index => range.end += 1,
+                _ => ranges.push(index..index + 1),
+            }
+        }
+
+        ranges
+    }
+
+    pub fn drawables(&self) -> &[Drawable] {
+        &self.drawables
+    }
+
+    /// Number of slots, including the free ones in between
+    pub fn slot_count(&self) -> usize {
+        self.drawables.len()
+    }
+
+    pub fn free_count(&self) -> usize {
+        self.free_slots.len()
+    }
+}
@@ -1,30 +1,36 @@
+use std::sync::RwLock;
+
 use wgpu::{BufferUsages, ShaderStages};
 
 use crate::rendering::{
     instancing::drawable::Drawable, util::bind_group_builder::BindGroupBuilder,
 };
 
-#[derive(Clone)]
+/// Storage buffer of drawables, which grows when more drawables are written to it. The buffer
+/// and its bind group are replaced when it grows, so they must be fetched again every frame.
 pub struct DrawableBuffer {
-    buffer: wgpu::Buffer,
     bind_group_layout: wgpu::BindGroupLayout,
+    inner: RwLock<DrawableBufferInner>,
+}
+
+struct DrawableBufferInner {
+    buffer: wgpu::Buffer,
     bind_group: wgpu::BindGroup,
+    capacity: u64,
 }
 
 impl DrawableBuffer {
     pub fn new(device: &wgpu::Device, initial_capacity: u64) -> Self {
         let buffer = Self::create_buffer(device, initial_capacity);
-        let (bind_group_layout, bind_group) = BindGroupBuilder::new(
-            "Drawable storage",
-            ShaderStages::VERTEX_FRAGMENT | ShaderStages::COMPUTE,
-        )
-        .storage_r(0, "Drawable storage buffer", buffer.as_entire_binding())
-        .build(device);
+        let (bind_group_layout, bind_group) = Self::bind_group_builder(&buffer).build(device);
 
         Self {
-            buffer,
             bind_group_layout,
-            bind_group,
+            inner: RwLock::new(DrawableBufferInner {
+                buffer,
+                bind_group,
+                capacity: initial_capacity,
+            }),
         }
     }
 
@@ -37,6 +43,39 @@ impl DrawableBuffer {
         })
     }
 
+    fn bind_group_builder(buffer: &wgpu::Buffer) -> BindGroupBuilder<'_> {
+        BindGroupBuilder::new(
+            "Drawable storage",
+            ShaderStages::VERTEX_FRAGMENT | ShaderStages::COMPUTE,
+        )
+        .storage_r(0, "Drawable storage buffer", buffer.as_entire_binding())
+    }
+
+    /// Reallocates the buffer if `count` drawables don't fit, at least doubling its capacity.
+    /// The previous contents are not preserved. Returns true if the buffer was reallocated.
+    pub fn ensure_capacity(&self, device: &wgpu::Device, count: u64) -> bool {
+        let mut inner = self.inner.write().unwrap();
+
+        if count <= inner.capacity {
+            return false;
+        }
+
+        let capacity = count.max(inner.capacity * 2);
+        log::info!(
+            "Growing drawable buffer from {} to {} drawables",
+            inner.capacity,
+            capacity
+        );
+
+        let buffer = Self::create_buffer(device, capacity);
+        inner.bind_group =
+            Self::bind_group_builder(&buffer).build_with_layout(device, &self.bind_group_layout);
+        inner.buffer = buffer;
+        inner.capacity = capacity;
+
+        true
+    }
+
     pub fn write_drawables_at_offset(
         &self,
         queue: &wgpu::Queue,
@@ -48,18 +87,23 @@ impl DrawableBuffer {
         }
 
         let offset = (start_index as u64) * std::mem::size_of::<Drawable>() as u64;
-        queue.write_buffer(&self.buffer, offset, bytemuck::cast_slice(instances));
+        let inner = self.inner.read().unwrap();
+        queue.write_buffer(&inner.buffer, offset, bytemuck::cast_slice(instances));
+    }
+
+    pub fn buffer(&self) -> wgpu::Buffer {
+        self.inner.read().unwrap().buffer.clone()
     }
 
-    pub fn buffer(&self) -> &wgpu::Buffer {
-        &self.buffer
+    pub fn capacity(&self) -> u64 {
+        self.inner.read().unwrap().capacity
     }
 
     pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
         &self.bind_group_layout
     }
 
-    pub fn bind_group(&self) -> &wgpu::BindGroup {
-        &self.bind_group
+    pub fn bind_group(&self) -> wgpu::BindGroup {
+        self.inner.read().unwrap().bind_group.clone()
     }
 }
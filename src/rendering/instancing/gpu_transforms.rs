@@ -0,0 +1,408 @@
+use bytemuck::{Pod, Zeroable};
+use glam::Mat4;
+
+use crate::{
+    rendering::{
+        instancing::drawable_storage_buffer::DrawableBuffer,
+        passes::render_pass_context::RenderPassCreationContext,
+        shader_loader::{ComputePipelineId, PipelineCache, ShaderDefinition},
+        util::bind_group_builder::BindGroupBuilder,
+    },
+    scene_graph::{object3d::Object3D, scene::Scene},
+};
+
+const RESOLVE_SHADER: ShaderDefinition = ShaderDefinition {
+    name: "Resolve transforms compute shader",
+    path: "transforms/resolve.wgsl",
+    defines: &[],
+};
+
+const APPLY_SHADER: ShaderDefinition = ShaderDefinition {
+    name: "Apply transforms compute shader",
+    path: "transforms/apply.wgsl",
+    defines: &[],
+};
+
+/// Must match the workgroup size in transforms/resolve.wgsl and transforms/apply.wgsl
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Object transform buffers grow beyond this when needed
+const INITIAL_NODE_CAPACITY: u64 = 1024;
+
+/// Local matrix and parent of an object. This should match TransformNode in
+/// shared/transform.wgsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct TransformNode {
+    matrix: Mat4,
+    parent: u32,
+    _padding: [u32; 3],
+}
+
+impl TransformNode {
+    /// This should match NO_PARENT in shared/transform.wgsl
+    const NO_PARENT: u32 = u32::MAX;
+
+    fn new(object: &Object3D) -> Self {
+        Self {
+            matrix: *object.transform.get_local_matrix(),
+            parent: object
+                .parent_id
+                .map_or(Self::NO_PARENT, |parent_id| parent_id.index() as u32),
+            _padding: [0; 3],
+        }
+    }
+}
+
+/// Resolves the world matrices of all objects on the GPU, and writes them to the drawable
+/// buffer. Only the local transforms of changed objects are uploaded, so moving the root of a
+/// large hierarchy doesn't upload its whole subtree. The scene still resolves the world
+/// matrices on the CPU for bounds, lights and cameras. Nodes are indexed like the object arena.
+pub struct GpuTransforms {
+    device: wgpu::Device,
+    resolve_pipeline_id: ComputePipelineId,
+    apply_pipeline_id: ComputePipelineId,
+    resolve_bind_group_layout: wgpu::BindGroupLayout,
+    apply_bind_group_layout: wgpu::BindGroupLayout,
+
+    node_capacity: u64,
+    local_nodes: wgpu::Buffer,
+    /// Ping-ponged between resolve passes
+    resolved_nodes: [wgpu::Buffer; 2],
+    /// Local to the first resolved buffer, then between the resolved buffers
+    resolve_bind_groups: [wgpu::BindGroup; 3],
+    /// Hierarchy that the local nodes were last fully uploaded for
+    uploaded_hierarchy_version: Option<u64>,
+    resolve_pass_count: u32,
+
+    /// Arena index of the object of each drawable slot
+    drawable_objects: Vec<u32>,
+    drawable_objects_buffer: wgpu::Buffer,
+    /// Reads the first or second resolved buffer, whichever was written last
+    apply_bind_groups: [wgpu::BindGroup; 2],
+}
+
+impl GpuTransforms {
+    /// Drawable slots which don't belong to an object. This should match NO_OBJECT in
+    /// transforms/apply.wgsl.
+    pub const NO_OBJECT: u32 = u32::MAX;
+
+    pub fn new(context: &mut RenderPassCreationContext, all_drawables: &DrawableBuffer) -> Self {
+        let device = &context.shared.device;
+
+        let local_nodes = create_node_buffer(device, "Local transform node buffer", 0);
+        let resolved_nodes = [
+            create_node_buffer(device, "Resolved transform node buffer A", 0),
+            create_node_buffer(device, "Resolved transform node buffer B", 0),
+        ];
+
+        let (resolve_bind_group_layout, resolve_bind_group) =
+            Self::resolve_bind_group_builder(&local_nodes, &resolved_nodes[0]).build(device);
+        let resolve_bind_groups = [
+            resolve_bind_group,
+            Self::resolve_bind_group_builder(&resolved_nodes[0], &resolved_nodes[1])
+                .build_with_layout(device, &resolve_bind_group_layout),
+            Self::resolve_bind_group_builder(&resolved_nodes[1], &resolved_nodes[0])
+                .build_with_layout(device, &resolve_bind_group_layout),
+        ];
+
+        let drawable_objects_buffer =
+            create_drawable_objects_buffer(device, all_drawables.capacity());
+        let drawable_buffer = all_drawables.buffer();
+
+        let (apply_bind_group_layout, apply_bind_group) = Self::apply_bind_group_builder(
+            &resolved_nodes[0],
+            &drawable_objects_buffer,
+            &drawable_buffer,
+        )
+        .build(device);
+        let apply_bind_groups = [
+            apply_bind_group,
+            Self::apply_bind_group_builder(
+                &resolved_nodes[1],
+                &drawable_objects_buffer,
+                &drawable_buffer,
+            )
+            .build_with_layout(device, &apply_bind_group_layout),
+        ];
+
+        let pipeline_resolve_bind_group_layout = resolve_bind_group_layout.clone();
+        let resolve_pipeline_id = context.cache_builder.add_shader(
+            RESOLVE_SHADER,
+            Box::new(move |device, shader_module, cache| {
+                let compute_pipeline =
+                    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: Some("Resolve transforms compute pipeline"),
+                        layout: Some(&device.create_pipeline_layout(
+                            &wgpu::PipelineLayoutDescriptor {
+                                label: Some("Resolve transforms pipeline layout"),
+                                bind_group_layouts: &[&pipeline_resolve_bind_group_layout],
+                                push_constant_ranges: &[],
+                            },
+                        )),
+                        module: &shader_module,
+                        entry_point: Some("main"),
+                        compilation_options: wgpu::PipelineCompilationOptions::default(),
+                        cache,
+                    });
+
+                Ok(compute_pipeline)
+            }),
+        );
+
+        let pipeline_apply_bind_group_layout = apply_bind_group_layout.clone();
+        let apply_pipeline_id = context.cache_builder.add_shader(
+            APPLY_SHADER,
+            Box::new(move |device, shader_module, cache| {
+                let compute_pipeline =
+                    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: Some("Apply transforms compute pipeline"),
+                        layout: Some(&device.create_pipeline_layout(
+                            &wgpu::PipelineLayoutDescriptor {
+                                label: Some("Apply transforms pipeline layout"),
+                                bind_group_layouts: &[&pipeline_apply_bind_group_layout],
+                                push_constant_ranges: &[],
+                            },
+                        )),
+                        module: &shader_module,
+                        entry_point: Some("main"),
+                        compilation_options: wgpu::PipelineCompilationOptions::default(),
+                        cache,
+                    });
+
+                Ok(compute_pipeline)
+            }),
+        );
+
+        Self {
+            device: device.clone(),
+            resolve_pipeline_id,
+            apply_pipeline_id,
+            resolve_bind_group_layout,
+            apply_bind_group_layout,
+
+            node_capacity: 0,
+            local_nodes,
+            resolved_nodes,
+            resolve_bind_groups,
+            uploaded_hierarchy_version: None,
+            resolve_pass_count: 1,
+
+            drawable_objects: Vec::new(),
+            drawable_objects_buffer,
+            apply_bind_groups,
+        }
+    }
+
+    fn resolve_bind_group_builder<'a>(
+        input_nodes: &'a wgpu::Buffer,
+        output_nodes: &'a wgpu::Buffer,
+    ) -> BindGroupBuilder<'a> {
+        BindGroupBuilder::new("Resolve transforms", wgpu::ShaderStages::COMPUTE)
+            .storage_r(0, "Input transform nodes", input_nodes.as_entire_binding())
+            .storage_rw(
+                1,
+                "Output transform nodes",
+                output_nodes.as_entire_binding(),
+            )
+    }
+
+    fn apply_bind_group_builder<'a>(
+        nodes: &'a wgpu::Buffer,
+        drawable_objects_buffer: &'a wgpu::Buffer,
+        drawable_buffer: &'a wgpu::Buffer,
+    ) -> BindGroupBuilder<'a> {
+        BindGroupBuilder::new("Apply transforms", wgpu::ShaderStages::COMPUTE)
+            .storage_r(0, "Resolved transform nodes", nodes.as_entire_binding())
+            .storage_r(
+                1,
+                "Drawable object buffer",
+                drawable_objects_buffer.as_entire_binding(),
+            )
+            .storage_rw(2, "Drawable buffer", drawable_buffer.as_entire_binding())
+    }
+
+    fn rebuild_apply_bind_groups(&mut self, all_drawables: &DrawableBuffer) {
+        let drawable_buffer = all_drawables.buffer();
+
+        self.apply_bind_groups = self.resolved_nodes.clone().map(|nodes| {
+            Self::apply_bind_group_builder(&nodes, &self.drawable_objects_buffer, &drawable_buffer)
+                .build_with_layout(&self.device, &self.apply_bind_group_layout)
+        });
+    }
+
+    /// Must be called when the drawable buffers have been reallocated
+    pub fn resize_drawables(&mut self, all_drawables: &DrawableBuffer) {
+        self.drawable_objects_buffer =
+            create_drawable_objects_buffer(&self.device, all_drawables.capacity());
+        // The new buffer is empty
+        self.drawable_objects.clear();
+
+        self.rebuild_apply_bind_groups(all_drawables);
+    }
+
+    /// Grows the node buffers to fit `count` objects. Returns true if they were reallocated.
+    fn ensure_node_capacity(&mut self, count: u64, all_drawables: &DrawableBuffer) -> bool {
+        if count <= self.node_capacity {
+            return false;
+        }
+
+        let capacity = count.max(INITIAL_NODE_CAPACITY).next_power_of_two();
+        let device = &self.device;
+
+        self.local_nodes = create_node_buffer(device, "Local transform node buffer", capacity);
+        self.resolved_nodes = [
+            create_node_buffer(device, "Resolved transform node buffer A", capacity),
+            create_node_buffer(device, "Resolved transform node buffer B", capacity),
+        ];
+        self.node_capacity = capacity;
+
+        let [a, b] = &self.resolved_nodes;
+        self.resolve_bind_groups =
+            [(&self.local_nodes, a), (a, b), (b, a)].map(|(input, output)| {
+                Self::resolve_bind_group_builder(input, output)
+                    .build_with_layout(device, &self.resolve_bind_group_layout)
+            });
+
+        self.rebuild_apply_bind_groups(all_drawables);
+        true
+    }
+
+    /// Uploads everything on the next update, e.g. after updates have been skipped
+    pub fn invalidate(&mut self) {
+        self.uploaded_hierarchy_version = None;
+    }
+
+    /// Uploads the local transforms of changed objects, or all of them if the hierarchy has
+    /// changed
+    pub fn update_objects(
+        &mut self,
+        queue: &wgpu::Queue,
+        scene: &Scene,
+        all_drawables: &DrawableBuffer,
+    ) {
+        let grown = self.ensure_node_capacity(scene.objects.slot_count() as u64, all_drawables);
+        let full_upload =
+            grown || self.uploaded_hierarchy_version != Some(scene.hierarchy_version());
+
+        let node_size = std::mem::size_of::<TransformNode>() as u64;
+        let mut nodes = Vec::new();
+        let mut first_index = 0;
+
+        // Changed objects are uploaded in contiguous runs, which end at free slots too
+        for (id, object) in scene.objects.iter() {
+            let changed = full_upload || object.transform.has_local_changed();
+            let contiguous = id.index() == first_index + nodes.len();
+            if !nodes.is_empty() && (!changed || !contiguous) {
+                queue.write_buffer(
+                    &self.local_nodes,
+                    first_index as u64 * node_size,
+                    bytemuck::cast_slice(&nodes),
+                );
+                nodes.clear();
+            }
+
+            if !changed {
+                continue;
+            }
+
+            if nodes.is_empty() {
+                first_index = id.index();
+            }
+            nodes.push(TransformNode::new(object));
+        }
+
+        if !nodes.is_empty() {
+            queue.write_buffer(
+                &self.local_nodes,
+                first_index as u64 * node_size,
+                bytemuck::cast_slice(&nodes),
+            );
+        }
+
+        self.uploaded_hierarchy_version = Some(scene.hierarchy_version());
+        // Every pass doubles the number of resolved levels, and the first one also copies the
+        // local nodes
+        self.resolve_pass_count = scene
+            .hierarchy_depth()
+            .max(1)
+            .next_power_of_two()
+            .ilog2()
+            .max(1);
+    }
+
+    /// Uploads which object each drawable slot belongs to, if it has changed
+    pub fn update_drawable_objects(&mut self, queue: &wgpu::Queue, drawable_objects: Vec<u32>) {
+        if drawable_objects == self.drawable_objects {
+            return;
+        }
+
+        // Slots past the end may still hold objects from before they were freed
+        let mut contents = drawable_objects.clone();
+        let capacity = self.drawable_objects_buffer.size() as usize / std::mem::size_of::<u32>();
+        contents.resize(capacity.max(contents.len()), Self::NO_OBJECT);
+        queue.write_buffer(
+            &self.drawable_objects_buffer,
+            0,
+            bytemuck::cast_slice(&contents[..capacity]),
+        );
+
+        self.drawable_objects = drawable_objects;
+    }
+
+    /// Resolves the world matrices and writes them to the drawable buffer. Must be dispatched
+    /// once per frame before culling.
+    pub fn dispatch(&self, encoder: &mut wgpu::CommandEncoder, pipeline_cache: &PipelineCache) {
+        if self.node_capacity == 0 {
+            return;
+        }
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("GPU transforms compute pass"),
+            timestamp_writes: None,
+        });
+
+        compute_pass.set_pipeline(pipeline_cache.get(self.resolve_pipeline_id));
+        for pass in 0..self.resolve_pass_count {
+            let bind_group = match pass {
+                0 => &self.resolve_bind_groups[0],
+                pass if pass % 2 == 1 => &self.resolve_bind_groups[1],
+                _ => &self.resolve_bind_groups[2],
+            };
+
+            compute_pass.set_bind_group(0, bind_group, &[]);
+            compute_pass.dispatch_workgroups(
+                (self.node_capacity as u32).div_ceil(WORKGROUP_SIZE),
+                1,
+                1,
+            );
+        }
+
+        // Odd pass counts end in the first resolved buffer
+        let apply_bind_group = &self.apply_bind_groups[(self.resolve_pass_count as usize + 1) % 2];
+        let slot_count = self.drawable_objects.len() as u32;
+
+        compute_pass.set_pipeline(pipeline_cache.get(self.apply_pipeline_id));
+        compute_pass.set_bind_group(0, apply_bind_group, &[]);
+        compute_pass.dispatch_workgroups(slot_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+}
+
+fn create_node_buffer(device: &wgpu::Device, label: &str, capacity: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        // Empty storage buffers can't be bound
+        size: capacity.max(1) * std::mem::size_of::<TransformNode>() as u64,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_drawable_objects_buffer(device: &wgpu::Device, capacity: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Drawable object buffer"),
+        size: capacity.max(1) * std::mem::size_of::<u32>() as u64,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
@@ -0,0 +1,246 @@
+use std::sync::Arc;
+
+use crate::{
+    asset_pipeline::meshlets::MESHLET_CORNER_BITS,
+    rendering::{
+        mesh_buffers::MeshBuffers,
+        shader_loader::{ComputePipelineId, PipelineCache, PipelineCacheBuilder, ShaderDefinition},
+        util::bind_group_builder::BindGroupBuilder,
+    },
+};
+
+const MESHLET_CULLING_SHADER: ShaderDefinition = ShaderDefinition {
+    name: "Meshlet culling compute shader",
+    path: "meshlet_culling.wgsl",
+    defines: &[],
+};
+
+/// Meshlets that can be drawn in one frame. The rest are dropped.
+pub const MAX_VISIBLE_MESHLETS: u32 = 1 << 18;
+/// Size of the meshlet index buffer, about 1.4M triangles
+pub const MAX_MESHLET_INDICES: u32 = 1 << 22;
+
+const _: () = assert!(MAX_VISIBLE_MESHLETS <= 1 << (32 - MESHLET_CORNER_BITS));
+
+/// Dispatches are split into rows of this many workgroups
+const MAX_WORKGROUPS_PER_DIMENSION: u32 = 65535;
+
+/// The visible meshlet counter followed by the indirect draw of the meshlet index buffer. This
+/// should match the same structure defined in WGSL.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct MeshletDrawArgs {
+    visible_meshlet_count: u32,
+    index_count: u32,
+    instance_count: u32,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+}
+
+/// Output of meshlet culling, which the geometry pass draws with a single indirect draw. The
+/// buffers have a fixed size, so they're never reallocated.
+pub struct MeshletDrawBuffers {
+    /// Drawable and meshlet of each visible meshlet
+    pub visible_meshlets: wgpu::Buffer,
+    /// Each entry has the visible meshlet in the high bits and the corner within the meshlet in
+    /// the low `MESHLET_CORNER_BITS`. The vertex shader resolves the actual vertex.
+    pub indices: wgpu::Buffer,
+    /// `MeshletDrawArgs`
+    pub draw_args: wgpu::Buffer,
+}
+
+impl MeshletDrawBuffers {
+    /// Offset of the indirect draw in `draw_args`
+    pub const DRAW_ARGS_OFFSET: u64 = std::mem::offset_of!(MeshletDrawArgs, index_count) as u64;
+    /// Size of the counters at the start of `draw_args`, which are read back for stats
+    pub const COUNTERS_SIZE: u64 = 2 * std::mem::size_of::<u32>() as u64;
+
+    pub fn new(device: &wgpu::Device) -> Self {
+        let visible_meshlets = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Visible meshlet buffer"),
+            size: MAX_VISIBLE_MESHLETS as u64 * 2 * std::mem::size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let indices = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Meshlet index buffer"),
+            size: MAX_MESHLET_INDICES as u64 * std::mem::size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::INDEX,
+            mapped_at_creation: false,
+        });
+
+        let draw_args = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Meshlet draw args buffer"),
+            size: std::mem::size_of::<MeshletDrawArgs>() as u64,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::INDIRECT
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            visible_meshlets,
+            indices,
+            draw_args,
+        }
+    }
+}
+
+/// Third culling phase, which tests the meshlets of the visible drawables against the frustum
+/// and their normal cones, and writes the survivors into the meshlet index buffer
+pub struct MeshletCulling {
+    device: wgpu::Device,
+    pipeline_id: ComputePipelineId,
+    mesh_buffers: Arc<MeshBuffers>,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    draw_args: wgpu::Buffer,
+}
+
+/// Buffers of the draw command generator which meshlet culling reads
+pub struct MeshletCullingInputs<'a> {
+    pub view_buffer: &'a wgpu::Buffer,
+    pub mesh_info_buffer: &'a wgpu::Buffer,
+    pub visible_drawable_buffer: &'a wgpu::Buffer,
+    pub visible_drawables_by_mesh_buffer: &'a wgpu::Buffer,
+    pub base_offsets_buffer: &'a wgpu::Buffer,
+}
+
+impl MeshletCulling {
+    pub fn new(
+        device: &wgpu::Device,
+        pipeline_builder: &mut PipelineCacheBuilder,
+        mesh_buffers: Arc<MeshBuffers>,
+        draw_buffers: &MeshletDrawBuffers,
+        inputs: &MeshletCullingInputs,
+    ) -> Self {
+        let meshlet_buffer = mesh_buffers.meshlets();
+        let (bind_group_layout, bind_group) =
+            Self::bind_group_builder(inputs, &meshlet_buffer, draw_buffers).build(device);
+
+        let pipeline_bind_group_layout = bind_group_layout.clone();
+        let pipeline_id = pipeline_builder.add_shader(
+            MESHLET_CULLING_SHADER,
+            Box::new(move |device, shader_module, cache| {
+                let compute_pipeline =
+                    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: Some("Meshlet culling compute pipeline"),
+                        layout: Some(&device.create_pipeline_layout(
+                            &wgpu::PipelineLayoutDescriptor {
+                                label: Some("Meshlet culling pipeline layout"),
+                                bind_group_layouts: &[&pipeline_bind_group_layout],
+                                push_constant_ranges: &[],
+                            },
+                        )),
+                        module: &shader_module,
+                        entry_point: Some("main"),
+                        compilation_options: wgpu::PipelineCompilationOptions::default(),
+                        cache,
+                    });
+
+                Ok(compute_pipeline)
+            }),
+        );
+
+        Self {
+            device: device.clone(),
+            pipeline_id,
+            mesh_buffers,
+            bind_group_layout,
+            bind_group,
+            draw_args: draw_buffers.draw_args.clone(),
+        }
+    }
+
+    fn bind_group_builder<'a>(
+        inputs: &MeshletCullingInputs<'a>,
+        meshlet_buffer: &'a wgpu::Buffer,
+        draw_buffers: &'a MeshletDrawBuffers,
+    ) -> BindGroupBuilder<'a> {
+        BindGroupBuilder::new("Meshlet culling", wgpu::ShaderStages::COMPUTE)
+            .uniform(
+                0,
+                "Frustum uniform buffer",
+                inputs.view_buffer.as_entire_binding(),
+            )
+            .storage_r(
+                1,
+                "Mesh info buffer",
+                inputs.mesh_info_buffer.as_entire_binding(),
+            )
+            .storage_r(2, "Meshlet buffer", meshlet_buffer.as_entire_binding())
+            .storage_r(
+                3,
+                "Visible drawable buffer",
+                inputs.visible_drawable_buffer.as_entire_binding(),
+            )
+            .storage_r(
+                4,
+                "Visible drawables by mesh buffer",
+                inputs.visible_drawables_by_mesh_buffer.as_entire_binding(),
+            )
+            .storage_r(
+                5,
+                "Base offsets buffer",
+                inputs.base_offsets_buffer.as_entire_binding(),
+            )
+            .storage_rw(
+                6,
+                "Visible meshlet buffer",
+                draw_buffers.visible_meshlets.as_entire_binding(),
+            )
+            .storage_rw(
+                7,
+                "Meshlet index buffer",
+                draw_buffers.indices.as_entire_binding(),
+            )
+            .storage_rw(
+                8,
+                "Meshlet draw args buffer",
+                draw_buffers.draw_args.as_entire_binding(),
+            )
+    }
+
+    /// Must be called when any of the inputs or the mesh buffers have been reallocated
+    pub fn resize_buffers(
+        &mut self,
+        draw_buffers: &MeshletDrawBuffers,
+        inputs: &MeshletCullingInputs,
+    ) {
+        let meshlet_buffer = self.mesh_buffers.meshlets();
+        self.bind_group = Self::bind_group_builder(inputs, &meshlet_buffer, draw_buffers)
+            .build_with_layout(&self.device, &self.bind_group_layout);
+    }
+
+    /// Runs one workgroup for each drawable slot, since the number of visible drawables is only
+    /// known on the GPU. The workgroups past the visible drawables exit right away.
+    pub fn dispatch(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline_cache: &PipelineCache,
+        instance_count: u32,
+    ) {
+        encoder.clear_buffer(&self.draw_args, 0, None);
+
+        let pipeline = pipeline_cache.get(self.pipeline_id);
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Meshlet culling compute pass"),
+            timestamp_writes: None,
+        });
+
+        compute_pass.set_pipeline(pipeline);
+        compute_pass.set_bind_group(0, &self.bind_group, &[]);
+        compute_pass.dispatch_workgroups(
+            instance_count.min(MAX_WORKGROUPS_PER_DIMENSION),
+            instance_count.div_ceil(MAX_WORKGROUPS_PER_DIMENSION),
+            1,
+        );
+    }
+
+    pub fn draw_args(&self) -> &wgpu::Buffer {
+        &self.draw_args
+    }
+}
@@ -1,17 +1,27 @@
+mod culling_validation;
 mod draw_command_generator;
 mod drawable;
 mod drawable_buffers;
 mod drawable_manager;
+mod drawable_slots;
 mod drawable_storage_buffer;
+mod gpu_transforms;
+mod meshlet_culling;
 
+use serde::{Deserialize, Serialize};
+
+pub use drawable::Drawable;
 pub use drawable_buffers::DrawableBuffers;
 pub use drawable_manager::DrawableManager;
+pub use meshlet_culling::MeshletDrawBuffers;
 
-pub const MAX_MESHES: usize = 128;
-pub const MAX_DRAWABLES: usize = 32_000;
+/// Drawable buffers grow beyond this when needed
+pub const INITIAL_DRAWABLE_CAPACITY: u64 = 32_000;
+/// Blended drawables are sorted and drawn one by one, so there shouldn't be many of them
+pub const INITIAL_TRANSPARENT_DRAWABLE_CAPACITY: u64 = 1024;
 
 /// Defines whether an instance is static (rarely changes) or dynamic (frequently updated)
-#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum InstanceType {
     /// Dynamic instances that change frequently (e.g., moving objects, animated elements)
     #[default]
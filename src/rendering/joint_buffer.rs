@@ -0,0 +1,56 @@
+use glam::Mat4;
+
+use crate::rendering::util::bind_group_builder::BindGroupBuilder;
+
+pub const MAX_JOINTS: usize = 8192;
+
+/// Joint matrices of all skinned drawables, indexed by `Drawable::joint_offset`. Only read by
+/// the skinning pass.
+pub struct JointBuffer {
+    buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+}
+
+impl JointBuffer {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Joint storage buffer"),
+            size: (std::mem::size_of::<Mat4>() * MAX_JOINTS) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let (bind_group_layout, bind_group) =
+            BindGroupBuilder::new("Joint storage", wgpu::ShaderStages::COMPUTE)
+                .storage_r(0, "Joint storage buffer", buffer.as_entire_binding())
+                .build(device);
+
+        Self {
+            buffer,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    pub fn write(&self, queue: &wgpu::Queue, joint_matrices: &[Mat4]) {
+        if joint_matrices.is_empty() {
+            return;
+        }
+
+        let count = joint_matrices.len().min(MAX_JOINTS);
+        queue.write_buffer(
+            &self.buffer,
+            0,
+            bytemuck::cast_slice(&joint_matrices[..count]),
+        );
+    }
+
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+}
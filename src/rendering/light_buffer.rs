@@ -0,0 +1,246 @@
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec3, Vec4};
+
+use crate::{
+    camera::Camera,
+    math::frustum::Frustum,
+    rendering::util::bind_group_builder::BindGroupBuilder,
+    scene_graph::{
+        light::{Light, LightKind},
+        scene::Scene,
+    },
+};
+
+pub const MAX_LIGHTS: usize = 256;
+
+/// Number of clusters along the X and Y axes of the screen and the depth. These should match
+/// CLUSTER_GRID_* in shared/light.wgsl.
+const CLUSTER_GRID: [u32; 3] = [16, 9, 24];
+pub const CLUSTER_COUNT: u32 = CLUSTER_GRID[0] * CLUSTER_GRID[1] * CLUSTER_GRID[2];
+/// Each cluster has a count followed by this many light indices. This should match
+/// MAX_LIGHTS_PER_CLUSTER in shared/light.wgsl.
+const MAX_LIGHTS_PER_CLUSTER: usize = 63;
+
+const LIGHT_TYPE_DIRECTIONAL: f32 = 0.0;
+const LIGHT_TYPE_POINT: f32 = 1.0;
+const LIGHT_TYPE_SPOT: f32 = 2.0;
+
+/// This should match Light in shared/light.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+pub struct GpuLight {
+    /// W: light type
+    pub position_type: Vec4,
+    /// Direction the light is shining towards. W: range
+    pub direction_range: Vec4,
+    /// W: intensity
+    pub color_intensity: Vec4,
+    /// X: cosine of the inner cone angle, Y: cosine of the outer cone angle,
+    /// Z: 1 if the light casts shadows
+    pub spot: Vec4,
+}
+
+impl GpuLight {
+    pub fn new(light: &Light, position: Vec3, direction: Vec3, casts_shadows: bool) -> Self {
+        let (light_type, range, spot) = match light.kind {
+            LightKind::Directional => (LIGHT_TYPE_DIRECTIONAL, 0.0, Vec4::ZERO),
+            LightKind::Point { range } => (LIGHT_TYPE_POINT, range, Vec4::ZERO),
+            LightKind::Spot {
+                range,
+                inner_angle,
+                outer_angle,
+            } => (
+                LIGHT_TYPE_SPOT,
+                range,
+                Vec4::new(inner_angle.cos(), outer_angle.cos(), 0.0, 0.0),
+            ),
+        };
+
+        Self {
+            position_type: position.extend(light_type),
+            direction_range: direction.extend(range),
+            color_intensity: light.color.extend(light.intensity),
+            spot: Vec4::new(spot.x, spot.y, if casts_shadows { 1.0 } else { 0.0 }, 0.0),
+        }
+    }
+}
+
+/// Header of the light storage buffer, followed by an array of GpuLights
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct GpuLightHeader {
+    count: u32,
+    _padding: [u32; 3],
+}
+
+/// This should match LightClusters in shared/light.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct GpuLightClusters {
+    view: Mat4,
+    /// Lights outside the view frustum are skipped before they are assigned to clusters
+    frustum: Frustum,
+    /// X = tangent of half the horizontal FOV, Y = same for vertical, Z = near, W = far
+    projection: Vec4,
+}
+
+/// Lights of the scene, and the lists of lights that affect each cluster of the view frustum.
+/// The lists are built on the GPU by `LightCullingPass`.
+pub struct LightBuffer {
+    buffer: wgpu::Buffer,
+    clusters_buffer: wgpu::Buffer,
+    /// Count of the lights inside the view frustum, followed by their indices
+    visible_lights_buffer: wgpu::Buffer,
+    cluster_lights_buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+}
+
+impl LightBuffer {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Light storage buffer"),
+            size: (std::mem::size_of::<GpuLightHeader>()
+                + std::mem::size_of::<GpuLight>() * MAX_LIGHTS) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let clusters_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Light cluster uniform buffer"),
+            size: std::mem::size_of::<GpuLightClusters>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let visible_lights_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Visible light buffer"),
+            size: (std::mem::size_of::<u32>() * (1 + MAX_LIGHTS)) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let cluster_lights_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cluster light buffer"),
+            size: (std::mem::size_of::<u32>()
+                * (1 + MAX_LIGHTS_PER_CLUSTER)
+                * CLUSTER_COUNT as usize) as u64,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let (bind_group_layout, bind_group) =
+            BindGroupBuilder::new("Light storage", wgpu::ShaderStages::FRAGMENT)
+                .storage_r(0, "Light storage buffer", buffer.as_entire_binding())
+                .uniform(
+                    1,
+                    "Light cluster uniform buffer",
+                    clusters_buffer.as_entire_binding(),
+                )
+                .storage_r(
+                    2,
+                    "Cluster light buffer",
+                    cluster_lights_buffer.as_entire_binding(),
+                )
+                .build(device);
+
+        Self {
+            buffer,
+            clusters_buffer,
+            visible_lights_buffer,
+            cluster_lights_buffer,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    /// Gathers all enabled lights from the scene and uploads them. Returns the light count.
+    pub fn update_from_scene(&self, scene: &Scene, queue: &wgpu::Queue) -> usize {
+        let shadow_caster = scene.primary_directional_light();
+
+        let lights = scene
+            .objects
+            .iter()
+            .filter(|(_, object)| object.enabled)
+            .filter_map(|(id, object)| {
+                let light = object.light.as_ref()?;
+                let world_matrix = *object.transform.get_world_matrix();
+                let position = world_matrix.w_axis.truncate();
+                let direction = world_matrix.transform_vector3(Vec3::Z).normalize_or_zero();
+
+                Some(GpuLight::new(
+                    light,
+                    position,
+                    direction,
+                    shadow_caster == Some(id),
+                ))
+            })
+            .take(MAX_LIGHTS)
+            .collect::<Vec<_>>();
+
+        let header = GpuLightHeader {
+            count: lights.len() as u32,
+            _padding: [0; 3],
+        };
+
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[header]));
+
+        if !lights.is_empty() {
+            queue.write_buffer(
+                &self.buffer,
+                std::mem::size_of::<GpuLightHeader>() as u64,
+                bytemuck::cast_slice(&lights),
+            );
+        }
+
+        lights.len()
+    }
+
+    /// Uploads the view the clusters are built for. `frustum` should be the view frustum of the
+    /// same camera.
+    pub fn update_clusters(
+        &self,
+        camera: &Camera,
+        frustum: &Frustum,
+        aspect_ratio: f32,
+        queue: &wgpu::Queue,
+    ) {
+        let tan_half_fov_y = (camera.fov_y_radians * 0.5).tan();
+        let clusters = GpuLightClusters {
+            view: camera.get_view_matrix(),
+            frustum: *frustum,
+            projection: Vec4::new(
+                tan_half_fov_y * aspect_ratio,
+                tan_half_fov_y,
+                camera.near,
+                camera.far,
+            ),
+        };
+
+        queue.write_buffer(&self.clusters_buffer, 0, bytemuck::cast_slice(&[clusters]));
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    pub fn clusters_buffer(&self) -> &wgpu::Buffer {
+        &self.clusters_buffer
+    }
+
+    pub fn visible_lights_buffer(&self) -> &wgpu::Buffer {
+        &self.visible_lights_buffer
+    }
+
+    pub fn cluster_lights_buffer(&self) -> &wgpu::Buffer {
+        &self.cluster_lights_buffer
+    }
+
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+}
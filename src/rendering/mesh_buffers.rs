@@ -1,37 +1,316 @@
-use wgpu::util::DeviceExt;
+use std::sync::RwLock;
 
-use crate::asset_pipeline::mesh_baker::BakedMeshes;
+use crate::asset_pipeline::{
+    mesh_baker::{BakedMeshes, MeshInfo, VertexFormat},
+    meshlets::MeshletInfo,
+};
 
+/// Hot reloaded and newly spawned models are written into the existing buffers when they fit, so
+/// leave some room for them to grow. Buffers that are reallocated grow by at least this much too.
+const CAPACITY_MULTIPLIER: usize = 2;
+
+/// Mesh entries reserved for skinned drawables, each of which has its own skinned copy of the
+/// vertices of its mesh
+pub const MAX_SKINNED_MESHES: u32 = 256;
+/// Size of the vertex region the skinning pass writes to each frame
+pub const MAX_SKINNED_VERTICES: u32 = 262_144;
+
+/// The vertex buffer has the baked vertices first, followed by free space and the skinned
+/// vertices. The mesh buffer has the baked meshes first, followed by empty entries and the
+/// skinned meshes.
+///
+/// The buffers are replaced when baked meshes no longer fit into them, so they must be fetched
+/// again every frame, and bind groups referring to them must be recreated.
 pub struct MeshBuffers {
-    pub vertices: wgpu::Buffer,
-    pub indices: wgpu::Buffer,
-    pub meshes: wgpu::Buffer,
+    /// Pipelines are created for the format of the first bake, so it can't change afterwards
+    vertex_format: VertexFormat,
+    /// Same for the meshlet rendering path, which needs every mesh to have meshlets
+    has_meshlets: bool,
+    inner: RwLock<MeshBuffersInner>,
+}
+
+struct MeshBuffersInner {
+    vertices: wgpu::Buffer,
+    indices: wgpu::Buffer,
+    meshes: wgpu::Buffer,
+    /// Meshlets of the baked meshes. Skinned meshes share the meshlets of their source mesh.
+    meshlets: wgpu::Buffer,
+    /// CPU copy of the mesh buffer, for passes which issue draws without GPU generated commands
+    mesh_infos: Vec<MeshInfo>,
+    /// Index of the first skinned mesh. Baked meshes can use the entries before it.
+    baked_mesh_capacity: u32,
+    /// Index of the first skinned vertex. Baked vertices can use the space before it.
+    skinned_vertex_offset: u32,
 }
 
 impl MeshBuffers {
     pub fn new(device: &wgpu::Device, baked_primitives: &BakedMeshes) -> Self {
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Vertex megabuffer"),
-            contents: bytemuck::cast_slice(&baked_primitives.buffers.vertices),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
-
-        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Index megabuffer"),
-            contents: bytemuck::cast_slice(&baked_primitives.buffers.indices),
-            usage: wgpu::BufferUsages::INDEX,
-        });
-
-        let mesh_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Mesh megabuffer"),
-            contents: bytemuck::cast_slice(&baked_primitives.meshes),
-            usage: wgpu::BufferUsages::STORAGE,
-        });
+        let inner = MeshBuffersInner::new(
+            device,
+            baked_primitives,
+            baked_primitives.buffers.vertex_count() * CAPACITY_MULTIPLIER,
+            baked_primitives.buffers.indices.len() * CAPACITY_MULTIPLIER,
+            baked_primitives.meshes.len() * CAPACITY_MULTIPLIER,
+            baked_primitives.meshlets.len() * CAPACITY_MULTIPLIER,
+        );
+
+        Self {
+            vertex_format: baked_primitives.buffers.vertex_format,
+            has_meshlets: baked_primitives.has_meshlets(),
+            inner: RwLock::new(inner),
+        }
+    }
+
+    /// Replaces the baked meshes, e.g. after models have been hot reloaded or spawned. Grows the
+    /// buffers if the meshes don't fit, and returns true if they were reallocated. The skinned
+    /// meshes must be written again after that.
+    pub fn update(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        baked_primitives: &BakedMeshes,
+    ) -> bool {
+        if baked_primitives.buffers.vertex_format != self.vertex_format {
+            log::error!(
+                "Meshes were baked with {:?} vertices instead of {:?}, ignoring them",
+                baked_primitives.buffers.vertex_format,
+                self.vertex_format
+            );
+            return false;
+        }
+
+        if self.has_meshlets
+            && !baked_primitives.has_meshlets()
+            && !baked_primitives.meshes.is_empty()
+        {
+            log::error!("Meshes were baked without meshlets, ignoring them");
+            return false;
+        }
+
+        let mut inner = self.inner.write().unwrap();
+
+        let vertex_count = baked_primitives.buffers.vertex_count();
+        let index_count = baked_primitives.buffers.indices.len();
+        let mesh_count = baked_primitives.meshes.len();
+        let meshlet_count = baked_primitives.meshlets.len();
+        let index_capacity = inner.indices.size() as usize / std::mem::size_of::<u32>();
+        let meshlet_capacity = inner.meshlets.size() as usize / std::mem::size_of::<MeshletInfo>();
+
+        if vertex_count > inner.skinned_vertex_offset as usize
+            || index_count > index_capacity
+            || mesh_count > inner.baked_mesh_capacity as usize
+            || meshlet_count > meshlet_capacity
+        {
+            let grow = |count: usize, capacity: usize| {
+                if count > capacity {
+                    count.max(capacity * CAPACITY_MULTIPLIER)
+                } else {
+                    capacity
+                }
+            };
+
+            let vertex_capacity = grow(vertex_count, inner.skinned_vertex_offset as usize);
+            let index_capacity = grow(index_count, index_capacity);
+            let mesh_capacity = grow(mesh_count, inner.baked_mesh_capacity as usize);
+            let meshlet_capacity = grow(meshlet_count, meshlet_capacity);
+
+            log::info!(
+                "Growing mesh buffers to {} vertices, {} indices and {} meshes",
+                vertex_capacity,
+                index_capacity,
+                mesh_capacity
+            );
+
+            *inner = MeshBuffersInner::new(
+                device,
+                baked_primitives,
+                vertex_capacity,
+                index_capacity,
+                mesh_capacity,
+                meshlet_capacity,
+            );
+
+            return true;
+        }
+
+        queue.write_buffer(&inner.vertices, 0, &baked_primitives.buffers.vertices);
+        queue.write_buffer(
+            &inner.indices,
+            0,
+            bytemuck::cast_slice(&baked_primitives.buffers.indices),
+        );
+        queue.write_buffer(
+            &inner.meshlets,
+            0,
+            bytemuck::cast_slice(&baked_primitives.meshlets),
+        );
+
+        // Entries past a smaller bake must not keep their old geometry
+        let inner = &mut *inner;
+        let baked_mesh_capacity = inner.baked_mesh_capacity as usize;
+        let mesh_infos = &mut inner.mesh_infos[..baked_mesh_capacity];
+        mesh_infos[..mesh_count].copy_from_slice(&baked_primitives.meshes);
+        mesh_infos[mesh_count..].fill(MeshInfo::EMPTY);
+        queue.write_buffer(&inner.meshes, 0, bytemuck::cast_slice(mesh_infos));
+
+        false
+    }
+
+    /// Replaces the skinned meshes, starting from the first one
+    pub fn write_skinned_meshes(&self, queue: &wgpu::Queue, meshes: &[MeshInfo]) {
+        if meshes.is_empty() {
+            return;
+        }
+
+        let mut inner = self.inner.write().unwrap();
+        let first = inner.baked_mesh_capacity as usize;
+        let offset = (first * std::mem::size_of::<MeshInfo>()) as u64;
+        queue.write_buffer(&inner.meshes, offset, bytemuck::cast_slice(meshes));
+
+        inner.mesh_infos[first..first + meshes.len()].copy_from_slice(meshes);
+    }
+
+    pub fn vertex_format(&self) -> VertexFormat {
+        self.vertex_format
+    }
+
+    /// Whether the meshes were baked with meshlets
+    pub fn has_meshlets(&self) -> bool {
+        self.has_meshlets
+    }
+
+    pub fn vertices(&self) -> wgpu::Buffer {
+        self.inner.read().unwrap().vertices.clone()
+    }
+
+    pub fn indices(&self) -> wgpu::Buffer {
+        self.inner.read().unwrap().indices.clone()
+    }
+
+    pub fn meshes(&self) -> wgpu::Buffer {
+        self.inner.read().unwrap().meshes.clone()
+    }
+
+    pub fn meshlets(&self) -> wgpu::Buffer {
+        self.inner.read().unwrap().meshlets.clone()
+    }
+
+    /// Index of the first skinned mesh in the mesh buffer
+    pub fn first_skinned_mesh(&self) -> u32 {
+        self.inner.read().unwrap().baked_mesh_capacity
+    }
+
+    /// Index of the first skinned vertex in the vertex buffer
+    pub fn skinned_vertex_offset(&self) -> u32 {
+        self.inner.read().unwrap().skinned_vertex_offset
+    }
+
+    /// Number of entries in the mesh buffer, including the empty ones
+    pub fn mesh_count(&self) -> u32 {
+        self.inner.read().unwrap().mesh_infos.len() as u32
+    }
+
+    /// Copy of every mesh entry, including the skinned ones
+    pub fn mesh_infos(&self) -> Vec<MeshInfo> {
+        self.inner.read().unwrap().mesh_infos.clone()
+    }
+
+    pub fn mesh_info(&self, index: u32) -> Option<MeshInfo> {
+        self.inner
+            .read()
+            .unwrap()
+            .mesh_infos
+            .get(index as usize)
+            .copied()
+    }
+}
+
+impl MeshBuffersInner {
+    /// Capacities are for the baked meshes, the skinned ones get their fixed amount of space
+    /// after them
+    fn new(
+        device: &wgpu::Device,
+        baked_primitives: &BakedMeshes,
+        vertex_capacity: usize,
+        index_capacity: usize,
+        mesh_capacity: usize,
+        meshlet_capacity: usize,
+    ) -> Self {
+        let vertex_size = baked_primitives.buffers.vertex_format.stride();
+        let skinned_vertex_offset = vertex_capacity as u32;
+
+        // Skinning is done in a compute shader, which writes into the vertex buffer
+        let vertex_buffer = create_buffer_with_capacity(
+            device,
+            "Vertex megabuffer",
+            &baked_primitives.buffers.vertices,
+            (skinned_vertex_offset + MAX_SKINNED_VERTICES) as usize * vertex_size,
+            wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
+        );
+
+        let index_buffer = create_buffer_with_capacity(
+            device,
+            "Index megabuffer",
+            bytemuck::cast_slice(&baked_primitives.buffers.indices),
+            index_capacity * std::mem::size_of::<u32>(),
+            // Meshlet draws read the indices in the vertex shader
+            wgpu::BufferUsages::INDEX | wgpu::BufferUsages::STORAGE,
+        );
+
+        let baked_mesh_capacity = mesh_capacity as u32;
+        let mut mesh_infos = baked_primitives.meshes.clone();
+        mesh_infos.resize(
+            (baked_mesh_capacity + MAX_SKINNED_MESHES) as usize,
+            MeshInfo::EMPTY,
+        );
+
+        let mesh_buffer = create_buffer_with_capacity(
+            device,
+            "Mesh megabuffer",
+            bytemuck::cast_slice(&mesh_infos),
+            mesh_infos.len() * std::mem::size_of::<MeshInfo>(),
+            wgpu::BufferUsages::STORAGE,
+        );
+
+        let meshlet_buffer = create_buffer_with_capacity(
+            device,
+            "Meshlet megabuffer",
+            bytemuck::cast_slice(&baked_primitives.meshlets),
+            meshlet_capacity * std::mem::size_of::<MeshletInfo>(),
+            wgpu::BufferUsages::STORAGE,
+        );
 
         Self {
             vertices: vertex_buffer,
             indices: index_buffer,
             meshes: mesh_buffer,
+            meshlets: meshlet_buffer,
+            mesh_infos,
+            baked_mesh_capacity,
+            skinned_vertex_offset,
         }
     }
 }
+
+/// Creates a buffer of `capacity` bytes, which starts with `contents`
+fn create_buffer_with_capacity(
+    device: &wgpu::Device,
+    label: &str,
+    contents: &[u8],
+    capacity: usize,
+    usage: wgpu::BufferUsages,
+) -> wgpu::Buffer {
+    let size = capacity.max(wgpu::COPY_BUFFER_ALIGNMENT as usize);
+
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size: size as u64,
+        usage: usage | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: true,
+    });
+
+    buffer.slice(..).get_mapped_range_mut()[..contents.len()].copy_from_slice(contents);
+    buffer.unmap();
+
+    buffer
+}
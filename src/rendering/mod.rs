@@ -1,16 +1,31 @@
+pub mod adapter;
+pub mod baked_ao_buffers;
 pub mod common;
 pub mod config;
+pub mod debug_draw;
 pub mod deferred;
+pub mod environment_map;
 pub mod global_uniform;
-mod imgui_renderer;
+pub mod gpu_profiler;
+pub mod imgui_renderer;
 pub mod instancing;
+pub mod joint_buffer;
+pub mod light_buffer;
 pub mod mesh_buffers;
+pub mod oit;
+pub mod particle_system;
 pub mod passes;
+pub mod pipeline_disk_cache;
+pub mod post_process;
+pub mod reflection_probes;
 pub mod render_camera;
 pub mod render_common;
+pub mod render_graph;
 pub mod render_material_manager;
 pub mod render_model;
+pub mod render_target_manager;
 pub mod renderer;
 pub mod shader_loader;
 pub mod texture;
+pub mod texture_streaming;
 mod util;
@@ -0,0 +1,161 @@
+use wgpu::{MultisampleState, PipelineCompilationOptions, RenderPassDescriptor};
+
+use crate::rendering::{
+    passes::render_pass_context::{RenderPassContext, RenderPassCreationContext},
+    post_process::targets::HDR_FORMAT,
+    render_graph::ColorTarget,
+    shader_loader::{RenderPipelineId, ShaderDefinition},
+    util::bind_group_builder::BindGroupBuilder,
+};
+
+/// Resolves the OIT targets and blends the result over the lit scene
+pub struct CompositePass {
+    device: wgpu::Device,
+    pipeline_id: RenderPipelineId,
+    targets_bind_group_layout: wgpu::BindGroupLayout,
+}
+
+pub struct CompositePassTextureViews {
+    pub accumulation: wgpu::TextureView,
+    pub revealage: wgpu::TextureView,
+    pub output: ColorTarget,
+}
+
+const SHADER_DEF: ShaderDefinition = ShaderDefinition {
+    name: "OIT composite pass shader",
+    path: "oit/composite.wgsl",
+    defines: &[],
+};
+
+impl CompositePass {
+    pub fn new(context: &mut RenderPassCreationContext) -> Self {
+        let device = &context.shared.device;
+
+        // The targets are transient, so the bind group is created when rendering
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let targets_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("OIT targets bind group layout"),
+                entries: &[texture_entry(0), texture_entry(1)],
+            });
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("OIT composite pass pipeline layout"),
+                bind_group_layouts: &[&targets_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let pipeline_id = context.cache_builder.add_shader(
+            SHADER_DEF,
+            Box::new(move |device, shader_module, cache| {
+                let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("OIT composite pass render pipeline"),
+                    layout: Some(&render_pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader_module,
+                        entry_point: Some("vs_main"),
+                        buffers: &[],
+                        compilation_options: PipelineCompilationOptions::default(),
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader_module,
+                        entry_point: Some("fs_main"),
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format: HDR_FORMAT,
+                            // The shader outputs the revealage as alpha
+                            blend: Some(wgpu::BlendState {
+                                color: wgpu::BlendComponent {
+                                    src_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                                    dst_factor: wgpu::BlendFactor::SrcAlpha,
+                                    operation: wgpu::BlendOperation::Add,
+                                },
+                                alpha: wgpu::BlendComponent {
+                                    src_factor: wgpu::BlendFactor::Zero,
+                                    dst_factor: wgpu::BlendFactor::One,
+                                    operation: wgpu::BlendOperation::Add,
+                                },
+                            }),
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                        compilation_options: PipelineCompilationOptions::default(),
+                    }),
+                    primitive: wgpu::PrimitiveState {
+                        topology: wgpu::PrimitiveTopology::TriangleList,
+                        strip_index_format: None,
+                        front_face: wgpu::FrontFace::Ccw,
+                        cull_mode: None,
+                        polygon_mode: wgpu::PolygonMode::Fill,
+                        unclipped_depth: false,
+                        conservative: false,
+                    },
+                    depth_stencil: None,
+                    multisample: MultisampleState::default(),
+                    multiview: None,
+                    cache,
+                });
+
+                Ok(pipeline)
+            }),
+        );
+
+        Self {
+            device: device.clone(),
+            pipeline_id,
+            targets_bind_group_layout,
+        }
+    }
+
+    fn targets_bind_group_builder<'a>(
+        texture_views: &'a CompositePassTextureViews,
+    ) -> BindGroupBuilder<'a> {
+        // Both targets are read with textureLoad, so they don't need to be filterable
+        let unfilterable = wgpu::TextureSampleType::Float { filterable: false };
+
+        BindGroupBuilder::new("OIT targets", wgpu::ShaderStages::FRAGMENT)
+            .texture_2d(
+                0,
+                "OIT accumulation",
+                unfilterable,
+                wgpu::BindingResource::TextureView(&texture_views.accumulation),
+            )
+            .texture_2d(
+                1,
+                "OIT revealage",
+                unfilterable,
+                wgpu::BindingResource::TextureView(&texture_views.revealage),
+            )
+    }
+
+    pub fn render(
+        &self,
+        texture_views: &CompositePassTextureViews,
+        context: &mut RenderPassContext,
+    ) {
+        let targets_bind_group = Self::targets_bind_group_builder(texture_views)
+            .build_with_layout(&self.device, &self.targets_bind_group_layout);
+
+        let mut render_pass = context.encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("OIT composite pass"),
+            color_attachments: &[texture_views.output.attachment()],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        let pipeline = context.pipeline_cache.get(self.pipeline_id);
+
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &targets_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,3 @@
+pub(crate) mod accumulation_pass;
+pub(crate) mod composite_pass;
+pub(crate) mod oit_targets;
@@ -0,0 +1,46 @@
+use wgpu::TextureFormat;
+
+use crate::rendering::{
+    common::Resolution,
+    render_graph::{ClearValue, RenderGraph, TextureHandle, TransientTextureDescriptor},
+};
+
+/// Render targets of weighted blended order-independent transparency. They are only needed
+/// during the transparency passes, so they are transient textures of the render graph.
+pub struct OitTargets {
+    /// In 16-bit float. RGB for the weighted sum of premultiplied colors, A for the weighted sum
+    /// of alphas. Cleared to zero.
+    pub accumulation: TextureHandle,
+    /// In 8-bit unorm. Product of (1 - alpha) of all surfaces, i.e. how much of the background is
+    /// visible. Cleared to one.
+    pub revealage: TextureHandle,
+}
+
+impl OitTargets {
+    pub const ACCUMULATION_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+    pub const REVEALAGE_FORMAT: TextureFormat = TextureFormat::R8Unorm;
+
+    pub fn create(graph: &mut RenderGraph, size: Resolution) -> Self {
+        let accumulation = graph.create_texture(
+            TransientTextureDescriptor::render_target(
+                "OIT accumulation",
+                size,
+                Self::ACCUMULATION_FORMAT,
+            ),
+            ClearValue::Color(wgpu::Color::TRANSPARENT),
+        );
+        let revealage = graph.create_texture(
+            TransientTextureDescriptor::render_target(
+                "OIT revealage",
+                size,
+                Self::REVEALAGE_FORMAT,
+            ),
+            ClearValue::Color(wgpu::Color::WHITE),
+        );
+
+        Self {
+            accumulation,
+            revealage,
+        }
+    }
+}
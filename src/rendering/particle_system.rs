@@ -0,0 +1,351 @@
+use std::collections::HashMap;
+
+use bytemuck::{Pod, Zeroable};
+use glam::{Vec3, Vec4};
+
+use crate::{
+    frame_pacing::{FixedSteps, FIXED_TIMESTEP, MAX_STEPS_PER_FRAME},
+    rendering::{
+        passes::render_pass_context::RenderPassCreationContext,
+        shader_loader::{ComputePipelineId, PipelineCache, ShaderDefinition},
+        util::bind_group_builder::BindGroupBuilder,
+    },
+    scene_graph::{object3d::ObjectId, particle_emitter::ParticleEmitter, scene::Scene},
+};
+
+/// Size of the particle ring buffer. When it's full, the oldest particles are replaced.
+pub const MAX_PARTICLES: u32 = 65_536;
+pub const MAX_EMITTERS: usize = 64;
+
+/// Must match the workgroup size in particles/simulate.wgsl
+const WORKGROUP_SIZE: u32 = 64;
+
+const SIMULATE_SHADER: ShaderDefinition = ShaderDefinition {
+    name: "Particle simulation compute shader",
+    path: "particles/simulate.wgsl",
+    defines: &[],
+};
+
+/// This should match Particle in particles/simulate.wgsl and particles/render.wgsl. Only
+/// accessed on the GPU.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct GpuParticle {
+    /// W: age in seconds
+    position_age: Vec4,
+    /// W: lifetime in seconds, the particle is dead once its age exceeds this
+    velocity_lifetime: Vec4,
+    /// W: size
+    gravity_size: Vec4,
+    color_start: Vec4,
+    color_end: Vec4,
+}
+
+/// This should match Emitter in particles/simulate.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct GpuEmitter {
+    position: Vec3,
+    /// Index of the emitter's first new particle among all particles spawned this step
+    spawn_offset: u32,
+    direction: Vec3,
+    spawn_count: u32,
+    gravity: Vec3,
+    speed: f32,
+    color_start: Vec4,
+    color_end: Vec4,
+    lifetime: f32,
+    spread: f32,
+    size: f32,
+    _padding: f32,
+}
+
+impl GpuEmitter {
+    fn new(
+        emitter: &ParticleEmitter,
+        position: Vec3,
+        direction: Vec3,
+        spawn_offset: u32,
+        spawn_count: u32,
+    ) -> Self {
+        Self {
+            position,
+            spawn_offset,
+            direction,
+            spawn_count,
+            gravity: emitter.gravity,
+            speed: emitter.speed,
+            color_start: emitter.color_start,
+            color_end: emitter.color_end,
+            lifetime: emitter.lifetime,
+            spread: emitter.spread,
+            size: emitter.size,
+            _padding: 0.0,
+        }
+    }
+}
+
+/// This should match Simulation in particles/simulate.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct GpuSimulation {
+    delta_time: f32,
+    /// Particles spawned this step replace the ones starting from this index
+    spawn_start: u32,
+    spawn_count: u32,
+    emitter_count: u32,
+    /// Index of the step, for the random directions of new particles
+    seed: u32,
+    _padding: [u32; 3],
+}
+
+const SIMULATION_SIZE: u64 = std::mem::size_of::<GpuSimulation>() as u64;
+const EMITTERS_SIZE: u64 = (std::mem::size_of::<GpuEmitter>() * MAX_EMITTERS) as u64;
+
+/// Simulates the particles of all emitters in the scene with one compute dispatch per fixed
+/// step, so they move the same regardless of the frame rate. New particles are allocated from a
+/// ring buffer on the CPU, so the GPU doesn't need to track free particles.
+pub struct ParticleSystem {
+    particle_buffer: wgpu::Buffer,
+    /// Each step of a frame gets its own slot in the emitter and simulation buffers, picked with
+    /// dynamic offsets
+    emitter_buffer: wgpu::Buffer,
+    emitter_stride: u64,
+    simulation_buffer: wgpu::Buffer,
+    simulation_stride: u64,
+    bind_group: wgpu::BindGroup,
+    pipeline_id: ComputePipelineId,
+
+    /// Fractional particles carried over to the next step, by emitter object
+    spawn_remainders: HashMap<ObjectId, f32>,
+    next_particle: u32,
+    /// Number of steps simulated so far
+    step: u32,
+    /// Steps to dispatch this frame
+    step_count: u32,
+}
+
+impl ParticleSystem {
+    pub fn new(context: &mut RenderPassCreationContext) -> Self {
+        let device = &context.shared.device;
+
+        // Zeroed particles have a lifetime of zero, so they start out dead
+        let particle_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Particle storage buffer"),
+            size: (std::mem::size_of::<GpuParticle>() * MAX_PARTICLES as usize) as u64,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let limits = device.limits();
+        let emitter_stride =
+            EMITTERS_SIZE.next_multiple_of(limits.min_storage_buffer_offset_alignment as u64);
+        let emitter_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Particle emitter buffer"),
+            size: emitter_stride * MAX_STEPS_PER_FRAME as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let simulation_stride =
+            SIMULATION_SIZE.next_multiple_of(limits.min_uniform_buffer_offset_alignment as u64);
+        let simulation_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Particle simulation uniform buffer"),
+            size: simulation_stride * MAX_STEPS_PER_FRAME as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        // The builder doesn't support dynamic offsets, so the layout is created by hand
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Particle simulation bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: wgpu::BufferSize::new(SIMULATION_SIZE),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: true,
+                        min_binding_size: wgpu::BufferSize::new(EMITTERS_SIZE),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let bind_group = BindGroupBuilder::new("Particle simulation", wgpu::ShaderStages::COMPUTE)
+            .uniform(
+                0,
+                "Particle simulation uniform buffer",
+                wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &simulation_buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(SIMULATION_SIZE),
+                }),
+            )
+            .storage_r(
+                1,
+                "Particle emitter buffer",
+                wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &emitter_buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(EMITTERS_SIZE),
+                }),
+            )
+            .storage_rw(
+                2,
+                "Particle storage buffer",
+                particle_buffer.as_entire_binding(),
+            )
+            .build_with_layout(device, &bind_group_layout);
+
+        let pipeline_id = context.cache_builder.add_shader(
+            SIMULATE_SHADER,
+            Box::new(move |device, shader_module, cache| {
+                let compute_pipeline =
+                    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: Some("Particle simulation compute pipeline"),
+                        layout: Some(&device.create_pipeline_layout(
+                            &wgpu::PipelineLayoutDescriptor {
+                                label: Some("Particle simulation compute pipeline layout"),
+                                bind_group_layouts: &[&bind_group_layout],
+                                push_constant_ranges: &[],
+                            },
+                        )),
+                        module: &shader_module,
+                        entry_point: Some("main"),
+                        compilation_options: wgpu::PipelineCompilationOptions::default(),
+                        cache,
+                    });
+
+                Ok(compute_pipeline)
+            }),
+        );
+
+        Self {
+            particle_buffer,
+            emitter_buffer,
+            emitter_stride,
+            simulation_buffer,
+            simulation_stride,
+            bind_group,
+            pipeline_id,
+            spawn_remainders: HashMap::new(),
+            next_particle: 0,
+            step: 0,
+            step_count: 0,
+        }
+    }
+
+    /// Gathers the enabled emitters from the scene and decides how many particles each of them
+    /// spawns in each step of this frame. The steps follow the demo clock, so pausing the demo
+    /// also pauses the particles.
+    pub fn update_from_scene(&mut self, scene: &Scene, queue: &wgpu::Queue, steps: FixedSteps) {
+        self.step_count = steps.count.min(MAX_STEPS_PER_FRAME);
+
+        for slot in 0..self.step_count {
+            self.update_step(scene, queue, slot);
+        }
+    }
+
+    fn update_step(&mut self, scene: &Scene, queue: &wgpu::Queue, slot: u32) {
+        let mut emitters = Vec::new();
+        let mut spawn_count = 0;
+        let mut remainders = HashMap::with_capacity(self.spawn_remainders.len());
+
+        for (id, object) in scene.objects.iter() {
+            if emitters.len() == MAX_EMITTERS {
+                break;
+            }
+
+            let Some(emitter) = object.particle_emitter.as_ref().filter(|_| object.enabled) else {
+                continue;
+            };
+
+            let particles = self.spawn_remainders.get(&id).copied().unwrap_or(0.0)
+                + emitter.rate * FIXED_TIMESTEP;
+            let count = (particles.floor() as u32).min(MAX_PARTICLES - spawn_count);
+            remainders.insert(id, particles.fract());
+
+            let world_matrix = object.transform.get_world_matrix();
+            emitters.push(GpuEmitter::new(
+                emitter,
+                world_matrix.w_axis.truncate(),
+                world_matrix
+                    .transform_vector3(Vec3::Z)
+                    .normalize_or(Vec3::Z),
+                spawn_count,
+                count,
+            ));
+            spawn_count += count;
+        }
+
+        self.spawn_remainders = remainders;
+
+        let simulation = GpuSimulation {
+            delta_time: FIXED_TIMESTEP,
+            spawn_start: self.next_particle,
+            spawn_count,
+            emitter_count: emitters.len() as u32,
+            seed: self.step,
+            _padding: [0; 3],
+        };
+        self.next_particle = (self.next_particle + spawn_count) % MAX_PARTICLES;
+        self.step = self.step.wrapping_add(1);
+
+        queue.write_buffer(
+            &self.simulation_buffer,
+            slot as u64 * self.simulation_stride,
+            bytemuck::cast_slice(&[simulation]),
+        );
+
+        if !emitters.is_empty() {
+            queue.write_buffer(
+                &self.emitter_buffer,
+                slot as u64 * self.emitter_stride,
+                bytemuck::cast_slice(&emitters),
+            );
+        }
+    }
+
+    pub fn dispatch(&self, encoder: &mut wgpu::CommandEncoder, pipeline_cache: &PipelineCache) {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Particle simulation compute pass"),
+            timestamp_writes: None,
+        });
+
+        compute_pass.set_pipeline(pipeline_cache.get(self.pipeline_id));
+        for slot in 0..self.step_count {
+            let offsets = [
+                (slot as u64 * self.simulation_stride) as u32,
+                (slot as u64 * self.emitter_stride) as u32,
+            ];
+            compute_pass.set_bind_group(0, &self.bind_group, &offsets);
+            compute_pass.dispatch_workgroups(MAX_PARTICLES.div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+    }
+
+    pub fn particle_buffer(&self) -> &wgpu::Buffer {
+        &self.particle_buffer
+    }
+}
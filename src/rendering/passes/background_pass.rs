@@ -2,7 +2,9 @@ use wgpu::{MultisampleState, PipelineCompilationOptions, RenderPassDescriptor};
 
 use crate::rendering::{
     passes::render_pass_context::RenderPassCreationContext,
-    shader_loader::{RenderPipelineCache, RenderPipelineId, ShaderDefinition},
+    post_process::targets::HDR_FORMAT,
+    render_graph::ColorTarget,
+    shader_loader::{PipelineCache, RenderPipelineId, ShaderDefinition},
 };
 
 pub struct BackgroundPass {
@@ -13,10 +15,11 @@ pub struct BackgroundPass {
 const FULLSCREEN_QUAD_SHADER: ShaderDefinition = ShaderDefinition {
     name: "Fullscreen Quad",
     path: "fullscreen_quad.wgsl",
+    defines: &[],
 };
 
 pub struct BackgroundPassTextureViews {
-    pub color: wgpu::TextureView,
+    pub color: ColorTarget,
 }
 
 impl BackgroundPass {
@@ -35,7 +38,7 @@ impl BackgroundPass {
 
         let pipeline_id = context.cache_builder.add_shader(
             FULLSCREEN_QUAD_SHADER,
-            Box::new(move |device, shader_module| {
+            Box::new(move |device, shader_module, cache| {
                 let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
                     label: Some("Background Pass Pipeline"),
                     layout: Some(&quad_render_pipeline_layout),
@@ -49,7 +52,7 @@ impl BackgroundPass {
                         module: &shader_module,
                         entry_point: Some("fs_main"),
                         targets: &[Some(wgpu::ColorTargetState {
-                            format: common.output_surface_config.read().unwrap().format,
+                            format: HDR_FORMAT,
                             blend: Some(wgpu::BlendState::REPLACE),
                             write_mask: wgpu::ColorWrites::ALL,
                         })],
@@ -67,7 +70,7 @@ impl BackgroundPass {
                     depth_stencil: None,
                     multisample: MultisampleState::default(),
                     multiview: None,
-                    cache: None,
+                    cache,
                 });
 
                 Ok(pipeline)
@@ -84,18 +87,11 @@ impl BackgroundPass {
         &self,
         texture_views: &BackgroundPassTextureViews,
         encoder: &mut wgpu::CommandEncoder,
-        pipeline_cache: &RenderPipelineCache,
+        pipeline_cache: &PipelineCache,
     ) {
         let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
             label: Some("Background Pass"),
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &texture_views.color,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-                    store: wgpu::StoreOp::Store,
-                },
-            })],
+            color_attachments: &[texture_views.color.attachment()],
             depth_stencil_attachment: None,
             occlusion_query_set: None,
             timestamp_writes: None,
@@ -0,0 +1,369 @@
+use std::cell::Cell;
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::rendering::{
+    common::Resolution,
+    passes::render_pass_context::RenderPassCreationContext,
+    post_process::targets::HDR_FORMAT,
+    shader_loader::{ComputePipelineId, PipelineCache, PipelineFactory, ShaderDefinition},
+    util::bind_group_builder::BindGroupBuilder,
+};
+
+const SHADER_DEF: ShaderDefinition = ShaderDefinition {
+    name: "Blur compute shader",
+    path: "util/blur.wgsl",
+    defines: &[],
+};
+
+/// Must match the workgroup size in util/blur.wgsl
+const WORKGROUP_SIZE: u32 = 8;
+
+/// Parameter slots shared by all blurs of a frame
+const MAX_DISPATCHES_PER_FRAME: u32 = 64;
+
+/// This should match BlurParams in util/blur.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct BlurParams {
+    direction: [i32; 2],
+    radius: i32,
+    offset: f32,
+}
+
+const PARAMS_SIZE: u64 = std::mem::size_of::<BlurParams>() as u64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlurKind {
+    /// Separable, with a horizontal and a vertical dispatch per iteration
+    Gaussian,
+    /// Four bilinear samples per iteration, moving further out each time. Cheaper than a
+    /// gaussian of the same size, but the input must be sampled with filtering.
+    Kawase,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BlurSettings {
+    pub kind: BlurKind,
+    /// Gaussian: taps on each side of the center. Kawase: scale of the sample offsets, so 1 is
+    /// the classic kernel.
+    pub radius: u32,
+    pub iterations: u32,
+}
+
+/// Textures of a blur. All of them must be in `BlurPass::FORMAT` and have the same size.
+pub struct BlurTextureViews<'a> {
+    /// Only read by the first dispatch, so it can also be the output. It must not be the scratch
+    /// texture.
+    pub input: &'a wgpu::TextureView,
+    /// Holds the intermediate results. Needs `STORAGE_BINDING` and `TEXTURE_BINDING` usages.
+    pub scratch: &'a wgpu::TextureView,
+    /// Needs `STORAGE_BINDING` and `TEXTURE_BINDING` usages
+    pub output: &'a wgpu::TextureView,
+    pub size: Resolution,
+}
+
+/// Compute blur shared by all effects, so that its pipelines are only created once. The bind
+/// groups are created for each call, so the textures can change from frame to frame.
+pub struct BlurPass {
+    device: wgpu::Device,
+    sampler: wgpu::Sampler,
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// Each dispatch of a frame gets its own slot, since a queue write would otherwise be
+    /// visible to every blur of the frame. The slot is picked with a dynamic offset.
+    params_buffer: wgpu::Buffer,
+    params_stride: u64,
+    next_params_slot: Cell<u32>,
+    gaussian_pipeline_id: ComputePipelineId,
+    kawase_pipeline_id: ComputePipelineId,
+}
+
+impl BlurPass {
+    pub const FORMAT: wgpu::TextureFormat = HDR_FORMAT;
+
+    pub fn new(context: &mut RenderPassCreationContext) -> Self {
+        let device = &context.shared.device;
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Blur sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        // The textures are only known when blurring, so the layout is created by hand
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Blur bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: Self::FORMAT,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: wgpu::BufferSize::new(PARAMS_SIZE),
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let gaussian_pipeline_id = context.cache_builder.add_shader(
+            SHADER_DEF,
+            Self::create_pipeline(
+                "Gaussian blur compute pipeline",
+                "gaussian",
+                bind_group_layout.clone(),
+            ),
+        );
+
+        let kawase_pipeline_id = context.cache_builder.add_shader(
+            SHADER_DEF,
+            Self::create_pipeline(
+                "Kawase blur compute pipeline",
+                "kawase",
+                bind_group_layout.clone(),
+            ),
+        );
+
+        let params_stride = PARAMS_SIZE
+            .next_multiple_of(device.limits().min_uniform_buffer_offset_alignment as u64);
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Blur params buffer"),
+            size: params_stride * MAX_DISPATCHES_PER_FRAME as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            device: device.clone(),
+            sampler,
+            bind_group_layout,
+            params_buffer,
+            params_stride,
+            next_params_slot: Cell::new(0),
+            gaussian_pipeline_id,
+            kawase_pipeline_id,
+        }
+    }
+
+    fn create_pipeline(
+        label: &'static str,
+        entry_point: &'static str,
+        bind_group_layout: wgpu::BindGroupLayout,
+    ) -> PipelineFactory<wgpu::ComputePipeline> {
+        Box::new(move |device, shader_module, cache| {
+            let compute_pipeline =
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some(label),
+                    layout: Some(
+                        &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                            label: Some(label),
+                            bind_group_layouts: &[&bind_group_layout],
+                            push_constant_ranges: &[],
+                        }),
+                    ),
+                    module: &shader_module,
+                    entry_point: Some(entry_point),
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                    cache,
+                });
+
+            Ok(compute_pipeline)
+        })
+    }
+
+    /// Must be called before the first blur of a frame, so the parameter slots can be reused
+    pub fn begin_frame(&self) {
+        self.next_params_slot.set(0);
+    }
+
+    fn bind_group(&self, input: &wgpu::TextureView, output: &wgpu::TextureView) -> wgpu::BindGroup {
+        BindGroupBuilder::new("Blur", wgpu::ShaderStages::COMPUTE)
+            .texture_2d(
+                0,
+                "Blur input",
+                wgpu::TextureSampleType::Float { filterable: true },
+                wgpu::BindingResource::TextureView(input),
+            )
+            .sampler(
+                1,
+                "Blur sampler",
+                wgpu::SamplerBindingType::Filtering,
+                wgpu::BindingResource::Sampler(&self.sampler),
+            )
+            .storage_texture_2d(
+                2,
+                "Blur output",
+                Self::FORMAT,
+                wgpu::BindingResource::TextureView(output),
+            )
+            .uniform(
+                3,
+                "Blur params",
+                wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &self.params_buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(PARAMS_SIZE),
+                }),
+            )
+            .build_with_layout(&self.device, &self.bind_group_layout)
+    }
+
+    /// Blurs the input into the output. Does nothing if there are no iterations.
+    pub fn blur(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline_cache: &PipelineCache,
+        settings: BlurSettings,
+        texture_views: &BlurTextureViews,
+    ) {
+        if settings.iterations == 0 {
+            return;
+        }
+
+        let BlurTextureViews {
+            input,
+            scratch,
+            output,
+            size,
+        } = *texture_views;
+        let radius = settings.radius as i32;
+
+        // Pairs of input and output views, one per dispatch
+        let (pipeline_id, dispatches) = match settings.kind {
+            BlurKind::Gaussian => {
+                let mut dispatches = Vec::new();
+                for iteration in 0..settings.iterations {
+                    let source = if iteration == 0 { input } else { output };
+
+                    dispatches.push((
+                        source,
+                        scratch,
+                        BlurParams {
+                            direction: [1, 0],
+                            radius,
+                            offset: 0.0,
+                        },
+                    ));
+                    dispatches.push((
+                        scratch,
+                        output,
+                        BlurParams {
+                            direction: [0, 1],
+                            radius,
+                            offset: 0.0,
+                        },
+                    ));
+                }
+
+                (self.gaussian_pipeline_id, dispatches)
+            }
+            BlurKind::Kawase => {
+                // Ping-pong between the scratch and the output, starting from the scratch so
+                // that the input can be the output
+                let targets = [scratch, output];
+                let kawase_params = |offset| BlurParams {
+                    direction: [0, 0],
+                    radius,
+                    offset,
+                };
+
+                let mut dispatches = (0..settings.iterations)
+                    .map(|iteration| {
+                        let source = if iteration == 0 {
+                            input
+                        } else {
+                            targets[(iteration as usize - 1) % 2]
+                        };
+
+                        (
+                            source,
+                            targets[iteration as usize % 2],
+                            kawase_params((iteration as f32 + 0.5) * settings.radius as f32),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+
+                // After an odd number of iterations the result is in the scratch texture. With
+                // no offset every sample is at the texel center, so this is a plain copy.
+                if settings.iterations % 2 == 1 {
+                    dispatches.push((scratch, output, kawase_params(0.0)));
+                }
+
+                (self.kawase_pipeline_id, dispatches)
+            }
+        };
+
+        let first_slot = self.next_params_slot.get();
+        let dispatch_count = dispatches.len() as u32;
+        if first_slot + dispatch_count > MAX_DISPATCHES_PER_FRAME {
+            log::warn!("Too many blur dispatches this frame, skipping a blur");
+            return;
+        }
+        self.next_params_slot.set(first_slot + dispatch_count);
+
+        let stride = self.params_stride as usize;
+        let mut params_data = vec![0u8; stride * dispatches.len()];
+        for (index, (_, _, params)) in dispatches.iter().enumerate() {
+            params_data[index * stride..][..PARAMS_SIZE as usize]
+                .copy_from_slice(bytemuck::bytes_of(params));
+        }
+        queue.write_buffer(
+            &self.params_buffer,
+            first_slot as u64 * self.params_stride,
+            &params_data,
+        );
+
+        let bind_groups = dispatches
+            .into_iter()
+            .map(|(source, target, _)| self.bind_group(source, target))
+            .collect::<Vec<_>>();
+
+        let workgroups_x = size.width.div_ceil(WORKGROUP_SIZE);
+        let workgroups_y = size.height.div_ceil(WORKGROUP_SIZE);
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Blur compute pass"),
+            timestamp_writes: None,
+        });
+
+        compute_pass.set_pipeline(pipeline_cache.get(pipeline_id));
+        for (slot, bind_group) in (first_slot..).zip(&bind_groups) {
+            let offset = slot as u64 * self.params_stride;
+            compute_pass.set_bind_group(0, bind_group, &[offset as u32]);
+            compute_pass.dispatch_workgroups(workgroups_x, workgroups_y, 1);
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use std::mem::offset_of;
+
+use wgpu::{MultisampleState, PipelineCompilationOptions, RenderPassDescriptor};
+
+use crate::rendering::{
+    debug_draw::{DebugDraw, DebugVertex},
+    passes::render_pass_context::RenderPassCreationContext,
+    post_process::targets::HDR_FORMAT,
+    render_graph::{ColorTarget, DepthTarget},
+    shader_loader::{PipelineCache, RenderPipelineId, ShaderDefinition},
+    texture::DepthTexture,
+    util::bind_group_builder::BindGroupBuilder,
+};
+
+const SHADER_DEF: ShaderDefinition = ShaderDefinition {
+    name: "Debug draw shader",
+    path: "debug_draw.wgsl",
+    defines: &[],
+};
+
+/// Anything past this is dropped
+const MAX_DEBUG_VERTICES: usize = 1 << 19;
+
+const DEBUG_VERTEX_VBL: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
+    array_stride: std::mem::size_of::<DebugVertex>() as wgpu::BufferAddress,
+    step_mode: wgpu::VertexStepMode::Vertex,
+    attributes: &[
+        wgpu::VertexAttribute {
+            offset: offset_of!(DebugVertex, position) as wgpu::BufferAddress,
+            shader_location: 0,
+            format: wgpu::VertexFormat::Float32x3,
+        },
+        wgpu::VertexAttribute {
+            offset: offset_of!(DebugVertex, color) as wgpu::BufferAddress,
+            shader_location: 1,
+            format: wgpu::VertexFormat::Float32x4,
+        },
+    ],
+};
+
+pub struct DebugDrawPassTextureViews {
+    pub color: ColorTarget,
+    pub depth: DepthTarget,
+}
+
+/// Renders the lines accumulated in `DebugDraw` on top of the lit scene
+pub struct DebugDrawPass {
+    depth_tested_pipeline_id: RenderPipelineId,
+    overlay_pipeline_id: RenderPipelineId,
+    camera_bind_group: wgpu::BindGroup,
+    vertex_buffer: wgpu::Buffer,
+}
+
+impl DebugDrawPass {
+    pub fn new(context: &mut RenderPassCreationContext) -> Self {
+        let device = &context.shared.device;
+
+        let (camera_bind_group_layout, camera_bind_group) =
+            BindGroupBuilder::new("Debug draw camera", wgpu::ShaderStages::VERTEX)
+                .uniform(
+                    0,
+                    "Camera uniform buffer",
+                    context.camera_uniform_buffer.as_entire_binding(),
+                )
+                .build(device);
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Debug draw pass pipeline layout"),
+                bind_group_layouts: &[&camera_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let mut add_pipeline = |label: &'static str, depth_compare: wgpu::CompareFunction| {
+            let render_pipeline_layout = render_pipeline_layout.clone();
+
+            context.cache_builder.add_shader(
+                SHADER_DEF,
+                Box::new(move |device, shader_module, cache| {
+                    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                        label: Some(label),
+                        layout: Some(&render_pipeline_layout),
+                        vertex: wgpu::VertexState {
+                            module: &shader_module,
+                            entry_point: Some("vs_main"),
+                            buffers: &[DEBUG_VERTEX_VBL],
+                            compilation_options: PipelineCompilationOptions::default(),
+                        },
+                        fragment: Some(wgpu::FragmentState {
+                            module: &shader_module,
+                            entry_point: Some("fs_main"),
+                            targets: &[Some(wgpu::ColorTargetState {
+                                format: HDR_FORMAT,
+                                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                                write_mask: wgpu::ColorWrites::ALL,
+                            })],
+                            compilation_options: PipelineCompilationOptions::default(),
+                        }),
+                        primitive: wgpu::PrimitiveState {
+                            topology: wgpu::PrimitiveTopology::LineList,
+                            ..Default::default()
+                        },
+                        depth_stencil: Some(wgpu::DepthStencilState {
+                            format: DepthTexture::DEPTH_FORMAT,
+                            depth_write_enabled: false,
+                            depth_compare,
+                            stencil: wgpu::StencilState::default(),
+                            bias: wgpu::DepthBiasState::default(),
+                        }),
+                        multisample: MultisampleState::default(),
+                        multiview: None,
+                        cache,
+                    });
+
+                    Ok(pipeline)
+                }),
+            )
+        };
+
+        let depth_tested_pipeline_id = add_pipeline(
+            "Depth tested debug draw pipeline",
+            wgpu::CompareFunction::Less,
+        );
+        let overlay_pipeline_id =
+            add_pipeline("Overlay debug draw pipeline", wgpu::CompareFunction::Always);
+
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Debug draw vertex buffer"),
+            size: (MAX_DEBUG_VERTICES * std::mem::size_of::<DebugVertex>()) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            depth_tested_pipeline_id,
+            overlay_pipeline_id,
+            camera_bind_group,
+            vertex_buffer,
+        }
+    }
+
+    /// Draws and clears everything in `debug_draw`
+    pub fn render(
+        &self,
+        texture_views: &DebugDrawPassTextureViews,
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue,
+        pipeline_cache: &PipelineCache,
+        debug_draw: &mut DebugDraw,
+    ) {
+        let depth_tested = debug_draw.depth_tested_vertices();
+        let overlay = debug_draw.overlay_vertices();
+
+        // Keep whole lines when truncating
+        let depth_tested_count = depth_tested.len().min(MAX_DEBUG_VERTICES) & !1;
+        let overlay_count = overlay.len().min(MAX_DEBUG_VERTICES - depth_tested_count) & !1;
+
+        if depth_tested_count + overlay_count < depth_tested.len() + overlay.len() {
+            log::warn!("Too many debug vertices, some lines were dropped");
+        }
+
+        if depth_tested_count + overlay_count == 0 {
+            debug_draw.clear();
+            return;
+        }
+
+        let vertex_size = std::mem::size_of::<DebugVertex>() as u64;
+        queue.write_buffer(
+            &self.vertex_buffer,
+            0,
+            bytemuck::cast_slice(&depth_tested[..depth_tested_count]),
+        );
+        queue.write_buffer(
+            &self.vertex_buffer,
+            depth_tested_count as u64 * vertex_size,
+            bytemuck::cast_slice(&overlay[..overlay_count]),
+        );
+
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Debug draw pass"),
+            color_attachments: &[texture_views.color.attachment()],
+            depth_stencil_attachment: texture_views.depth.attachment(),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+
+        let depth_tested_end = depth_tested_count as u32;
+        let overlay_end = depth_tested_end + overlay_count as u32;
+
+        if depth_tested_count > 0 {
+            render_pass.set_pipeline(pipeline_cache.get(self.depth_tested_pipeline_id));
+            render_pass.draw(0..depth_tested_end, 0..1);
+        }
+
+        if overlay_count > 0 {
+            render_pass.set_pipeline(pipeline_cache.get(self.overlay_pipeline_id));
+            render_pass.draw(depth_tested_end..overlay_end, 0..1);
+        }
+
+        drop(render_pass);
+        debug_draw.clear();
+    }
+}
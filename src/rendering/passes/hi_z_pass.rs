@@ -0,0 +1,263 @@
+use crate::rendering::{
+    common::{PhysicalSizeExt, Resolution},
+    passes::render_pass_context::RenderPassCreationContext,
+    shader_loader::{ComputePipelineId, PipelineCache, PipelineFactory, ShaderDefinition},
+    texture::DepthTexture,
+    util::bind_group_builder::BindGroupBuilder,
+};
+
+const COPY_DEPTH_SHADER: ShaderDefinition = ShaderDefinition {
+    name: "Hi-Z copy depth compute shader",
+    path: "hi_z/copy_depth.wgsl",
+    defines: &[],
+};
+
+const DOWNSAMPLE_SHADER: ShaderDefinition = ShaderDefinition {
+    name: "Hi-Z downsample compute shader",
+    path: "hi_z/downsample.wgsl",
+    defines: &[],
+};
+
+pub const HI_Z_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Float;
+
+/// Must match the workgroup size in the hi_z shaders
+const WORKGROUP_SIZE: u32 = 8;
+
+/// Builds a hierarchical depth pyramid from the depth buffer. Mip 0 is a copy of the depth
+/// buffer and each following mip stores the farthest depth of the texels it covers, so a
+/// single texel of a coarse mip conservatively covers a large area of the screen.
+pub struct HiZPass {
+    device: wgpu::Device,
+    copy_pipeline_id: ComputePipelineId,
+    downsample_pipeline_id: ComputePipelineId,
+    copy_bind_group_layout: wgpu::BindGroupLayout,
+    downsample_bind_group_layout: wgpu::BindGroupLayout,
+
+    pyramid: wgpu::Texture,
+    pyramid_view: wgpu::TextureView,
+    copy_bind_group: wgpu::BindGroup,
+    /// One bind group per mip after the first, reading from the previous mip
+    downsample_bind_groups: Vec<wgpu::BindGroup>,
+}
+
+impl HiZPass {
+    pub fn new(
+        context: &mut RenderPassCreationContext,
+        depth: &DepthTexture,
+        size: Resolution,
+    ) -> Self {
+        let device = &context.shared.device;
+
+        // Only the layouts are needed here, so the views don't matter
+        let copy_bind_group_layout =
+            Self::copy_bind_group_builder(depth.view(), depth.view()).build_layout(device);
+        let downsample_bind_group_layout =
+            Self::downsample_bind_group_builder(depth.view(), depth.view()).build_layout(device);
+
+        let copy_pipeline_id = context.cache_builder.add_shader(
+            COPY_DEPTH_SHADER,
+            Self::create_pipeline(
+                "Hi-Z copy depth compute pipeline",
+                copy_bind_group_layout.clone(),
+            ),
+        );
+
+        let downsample_pipeline_id = context.cache_builder.add_shader(
+            DOWNSAMPLE_SHADER,
+            Self::create_pipeline(
+                "Hi-Z downsample compute pipeline",
+                downsample_bind_group_layout.clone(),
+            ),
+        );
+
+        let (pyramid, pyramid_view, copy_bind_group, downsample_bind_groups) = Self::create_pyramid(
+            device,
+            &copy_bind_group_layout,
+            &downsample_bind_group_layout,
+            depth,
+            size,
+        );
+
+        Self {
+            device: device.clone(),
+            copy_pipeline_id,
+            downsample_pipeline_id,
+            copy_bind_group_layout,
+            downsample_bind_group_layout,
+
+            pyramid,
+            pyramid_view,
+            copy_bind_group,
+            downsample_bind_groups,
+        }
+    }
+
+    fn create_pipeline(
+        label: &'static str,
+        bind_group_layout: wgpu::BindGroupLayout,
+    ) -> PipelineFactory<wgpu::ComputePipeline> {
+        Box::new(move |device, shader_module, cache| {
+            let compute_pipeline =
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some(label),
+                    layout: Some(
+                        &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                            label: Some(label),
+                            bind_group_layouts: &[&bind_group_layout],
+                            push_constant_ranges: &[],
+                        }),
+                    ),
+                    module: &shader_module,
+                    entry_point: Some("main"),
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                    cache,
+                });
+
+            Ok(compute_pipeline)
+        })
+    }
+
+    fn copy_bind_group_builder<'a>(
+        depth_view: &'a wgpu::TextureView,
+        output_view: &'a wgpu::TextureView,
+    ) -> BindGroupBuilder<'a> {
+        BindGroupBuilder::new("Hi-Z copy depth", wgpu::ShaderStages::COMPUTE)
+            .texture_2d(
+                0,
+                "Depth texture",
+                wgpu::TextureSampleType::Depth,
+                wgpu::BindingResource::TextureView(depth_view),
+            )
+            .storage_texture_2d(
+                1,
+                "Hi-Z output mip",
+                HI_Z_FORMAT,
+                wgpu::BindingResource::TextureView(output_view),
+            )
+    }
+
+    fn downsample_bind_group_builder<'a>(
+        input_view: &'a wgpu::TextureView,
+        output_view: &'a wgpu::TextureView,
+    ) -> BindGroupBuilder<'a> {
+        BindGroupBuilder::new("Hi-Z downsample", wgpu::ShaderStages::COMPUTE)
+            .texture_2d(
+                0,
+                "Hi-Z input mip",
+                wgpu::TextureSampleType::Float { filterable: false },
+                wgpu::BindingResource::TextureView(input_view),
+            )
+            .storage_texture_2d(
+                1,
+                "Hi-Z output mip",
+                HI_Z_FORMAT,
+                wgpu::BindingResource::TextureView(output_view),
+            )
+    }
+
+    fn create_pyramid(
+        device: &wgpu::Device,
+        copy_bind_group_layout: &wgpu::BindGroupLayout,
+        downsample_bind_group_layout: &wgpu::BindGroupLayout,
+        depth: &DepthTexture,
+        size: Resolution,
+    ) -> (
+        wgpu::Texture,
+        wgpu::TextureView,
+        wgpu::BindGroup,
+        Vec<wgpu::BindGroup>,
+    ) {
+        let mip_level_count = size.width.max(size.height).max(1).ilog2() + 1;
+
+        let pyramid = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Hi-Z pyramid"),
+            size: size.to_extent3d(),
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: HI_Z_FORMAT,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let pyramid_view = pyramid.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mip_views = (0..mip_level_count)
+            .map(|mip| {
+                pyramid.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("Hi-Z pyramid mip"),
+                    base_mip_level: mip,
+                    mip_level_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let copy_bind_group = Self::copy_bind_group_builder(depth.view(), &mip_views[0])
+            .build_with_layout(device, copy_bind_group_layout);
+
+        let downsample_bind_groups = mip_views
+            .windows(2)
+            .map(|views| {
+                Self::downsample_bind_group_builder(&views[0], &views[1])
+                    .build_with_layout(device, downsample_bind_group_layout)
+            })
+            .collect();
+
+        (
+            pyramid,
+            pyramid_view,
+            copy_bind_group,
+            downsample_bind_groups,
+        )
+    }
+
+    pub fn resize(&mut self, depth: &DepthTexture, size: Resolution) {
+        let (pyramid, pyramid_view, copy_bind_group, downsample_bind_groups) = Self::create_pyramid(
+            &self.device,
+            &self.copy_bind_group_layout,
+            &self.downsample_bind_group_layout,
+            depth,
+            size,
+        );
+
+        self.pyramid = pyramid;
+        self.pyramid_view = pyramid_view;
+        self.copy_bind_group = copy_bind_group;
+        self.downsample_bind_groups = downsample_bind_groups;
+    }
+
+    /// View of the whole mip chain, for sampling during culling
+    pub fn pyramid_view(&self) -> &wgpu::TextureView {
+        &self.pyramid_view
+    }
+
+    pub fn dispatch(&self, encoder: &mut wgpu::CommandEncoder, pipeline_cache: &PipelineCache) {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Hi-Z compute pass"),
+            timestamp_writes: None,
+        });
+
+        let size = self.pyramid.size();
+        let workgroups = |mip: u32| {
+            let width = (size.width >> mip).max(1);
+            let height = (size.height >> mip).max(1);
+            (
+                width.div_ceil(WORKGROUP_SIZE),
+                height.div_ceil(WORKGROUP_SIZE),
+            )
+        };
+
+        compute_pass.set_pipeline(pipeline_cache.get(self.copy_pipeline_id));
+        compute_pass.set_bind_group(0, &self.copy_bind_group, &[]);
+        let (x, y) = workgroups(0);
+        compute_pass.dispatch_workgroups(x, y, 1);
+
+        compute_pass.set_pipeline(pipeline_cache.get(self.downsample_pipeline_id));
+        for (i, bind_group) in self.downsample_bind_groups.iter().enumerate() {
+            compute_pass.set_bind_group(0, bind_group, &[]);
+            let (x, y) = workgroups(i as u32 + 1);
+            compute_pass.dispatch_workgroups(x, y, 1);
+        }
+    }
+}
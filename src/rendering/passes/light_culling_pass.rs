@@ -0,0 +1,131 @@
+use std::sync::Arc;
+
+use crate::rendering::{
+    light_buffer::{LightBuffer, CLUSTER_COUNT, MAX_LIGHTS},
+    passes::render_pass_context::RenderPassCreationContext,
+    shader_loader::{ComputePipelineId, PipelineCache, ShaderDefinition},
+    util::bind_group_builder::BindGroupBuilder,
+};
+
+const CULL_LIGHTS_SHADER: ShaderDefinition = ShaderDefinition {
+    name: "Light frustum culling compute shader",
+    path: "light_culling/cull_lights.wgsl",
+    defines: &[],
+};
+
+const ASSIGN_CLUSTERS_SHADER: ShaderDefinition = ShaderDefinition {
+    name: "Light cluster assignment compute shader",
+    path: "light_culling/assign_clusters.wgsl",
+    defines: &[],
+};
+
+/// Must match the workgroup size of both shaders
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Builds the per-cluster light lists of the `LightBuffer` in two steps: the lights are first
+/// culled against the view frustum, and only the visible ones are tested against each cluster.
+pub struct LightCullingPass {
+    light_buffer: Arc<LightBuffer>,
+    cull_pipeline_id: ComputePipelineId,
+    assign_pipeline_id: ComputePipelineId,
+    bind_group: wgpu::BindGroup,
+}
+
+impl LightCullingPass {
+    pub fn new(context: &mut RenderPassCreationContext) -> Self {
+        let device = &context.shared.device;
+        let light_buffer = context.shared.light_buffer.clone();
+
+        let (bind_group_layout, bind_group) =
+            BindGroupBuilder::new("Light culling", wgpu::ShaderStages::COMPUTE)
+                .storage_r(
+                    0,
+                    "Light storage buffer",
+                    light_buffer.buffer().as_entire_binding(),
+                )
+                .uniform(
+                    1,
+                    "Light cluster uniform buffer",
+                    light_buffer.clusters_buffer().as_entire_binding(),
+                )
+                .storage_rw(
+                    2,
+                    "Visible light buffer",
+                    light_buffer.visible_lights_buffer().as_entire_binding(),
+                )
+                .storage_rw(
+                    3,
+                    "Cluster light buffer",
+                    light_buffer.cluster_lights_buffer().as_entire_binding(),
+                )
+                .build(device);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Light culling pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let cull_pipeline_layout = pipeline_layout.clone();
+        let cull_pipeline_id = context.cache_builder.add_shader(
+            CULL_LIGHTS_SHADER,
+            Box::new(move |device, shader_module, cache| {
+                Ok(
+                    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: Some("Light frustum culling pipeline"),
+                        layout: Some(&cull_pipeline_layout),
+                        module: &shader_module,
+                        entry_point: Some("main"),
+                        compilation_options: wgpu::PipelineCompilationOptions::default(),
+                        cache,
+                    }),
+                )
+            }),
+        );
+
+        let assign_pipeline_id = context.cache_builder.add_shader(
+            ASSIGN_CLUSTERS_SHADER,
+            Box::new(move |device, shader_module, cache| {
+                Ok(
+                    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: Some("Light cluster assignment pipeline"),
+                        layout: Some(&pipeline_layout),
+                        module: &shader_module,
+                        entry_point: Some("main"),
+                        compilation_options: wgpu::PipelineCompilationOptions::default(),
+                        cache,
+                    }),
+                )
+            }),
+        );
+
+        Self {
+            light_buffer,
+            cull_pipeline_id,
+            assign_pipeline_id,
+            bind_group,
+        }
+    }
+
+    /// Must run after the lights and the clusters of the `LightBuffer` have been updated
+    pub fn dispatch(&self, encoder: &mut wgpu::CommandEncoder, pipeline_cache: &PipelineCache) {
+        encoder.clear_buffer(
+            self.light_buffer.visible_lights_buffer(),
+            0,
+            Some(std::mem::size_of::<u32>() as u64),
+        );
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Light culling compute pass"),
+            timestamp_writes: None,
+        });
+
+        compute_pass.set_bind_group(0, &self.bind_group, &[]);
+
+        compute_pass.set_pipeline(pipeline_cache.get(self.cull_pipeline_id));
+        compute_pass.dispatch_workgroups((MAX_LIGHTS as u32).div_ceil(WORKGROUP_SIZE), 1, 1);
+
+        compute_pass.set_pipeline(pipeline_cache.get(self.assign_pipeline_id));
+        compute_pass.dispatch_workgroups(CLUSTER_COUNT.div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+}
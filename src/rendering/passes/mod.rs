@@ -1,3 +1,13 @@
 pub mod background_pass;
-pub mod pbr_pass;
+pub mod blur_pass;
+pub mod debug_draw_pass;
+pub mod hi_z_pass;
+pub mod light_culling_pass;
+pub mod particle_pass;
 pub mod render_pass_context;
+pub mod shadow_pass;
+pub mod skinning_pass;
+pub mod skybox_pass;
+pub mod taa_pass;
+pub mod text_pass;
+pub mod transparent_pass;
@@ -0,0 +1,137 @@
+use wgpu::{MultisampleState, PipelineCompilationOptions, RenderPassDescriptor};
+
+use crate::rendering::{
+    particle_system::{ParticleSystem, MAX_PARTICLES},
+    passes::render_pass_context::{RenderPassContext, RenderPassCreationContext},
+    post_process::targets::HDR_FORMAT,
+    render_graph::{ColorTarget, DepthTarget},
+    shader_loader::{RenderPipelineId, ShaderDefinition},
+    texture::DepthTexture,
+    util::bind_group_builder::BindGroupBuilder,
+};
+
+const SHADER_DEF: ShaderDefinition = ShaderDefinition {
+    name: "Particle shader",
+    path: "particles/render.wgsl",
+    defines: &[],
+};
+
+pub struct ParticlePassTextureViews {
+    pub color: ColorTarget,
+    /// Depth of the opaque geometry, which is tested against but not written to
+    pub depth: DepthTarget,
+}
+
+/// Draws every particle as a camera facing billboard, with one instance per particle. Dead
+/// particles are culled in the vertex shader.
+pub struct ParticlePass {
+    pipeline_id: RenderPipelineId,
+    camera_bind_group: wgpu::BindGroup,
+    particle_bind_group: wgpu::BindGroup,
+}
+
+impl ParticlePass {
+    pub fn new(context: &mut RenderPassCreationContext, particle_system: &ParticleSystem) -> Self {
+        let device = &context.shared.device;
+        let common = context.shared.common.clone();
+
+        let (camera_bind_group_layout, camera_bind_group) =
+            BindGroupBuilder::new("Particle camera", wgpu::ShaderStages::VERTEX)
+                .uniform(
+                    0,
+                    "Camera uniform buffer",
+                    common.camera_uniform_buffer.as_entire_binding(),
+                )
+                .build(device);
+
+        let (particle_bind_group_layout, particle_bind_group) =
+            BindGroupBuilder::new("Particles", wgpu::ShaderStages::VERTEX)
+                .storage_r(
+                    0,
+                    "Particle storage buffer",
+                    particle_system.particle_buffer().as_entire_binding(),
+                )
+                .build(device);
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Particle pass pipeline layout"),
+                bind_group_layouts: &[&camera_bind_group_layout, &particle_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let pipeline_id = context.cache_builder.add_shader(
+            SHADER_DEF,
+            Box::new(move |device, shader_module, cache| {
+                let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("Particle pass render pipeline"),
+                    layout: Some(&render_pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader_module,
+                        entry_point: Some("vs_main"),
+                        buffers: &[],
+                        compilation_options: PipelineCompilationOptions::default(),
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader_module,
+                        entry_point: Some("fs_main"),
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format: HDR_FORMAT,
+                            // Particles aren't sorted. With premultiplied alpha, fully
+                            // transparent colors are additive, which hides the order.
+                            blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                        compilation_options: PipelineCompilationOptions::default(),
+                    }),
+                    primitive: wgpu::PrimitiveState {
+                        topology: wgpu::PrimitiveTopology::TriangleList,
+                        strip_index_format: None,
+                        front_face: wgpu::FrontFace::Ccw,
+                        cull_mode: None,
+                        polygon_mode: wgpu::PolygonMode::Fill,
+                        unclipped_depth: false,
+                        conservative: false,
+                    },
+                    depth_stencil: Some(wgpu::DepthStencilState {
+                        format: DepthTexture::DEPTH_FORMAT,
+                        depth_write_enabled: false,
+                        depth_compare: wgpu::CompareFunction::Less,
+                        stencil: wgpu::StencilState::default(),
+                        bias: wgpu::DepthBiasState::default(),
+                    }),
+                    multisample: MultisampleState::default(),
+                    multiview: None,
+                    cache,
+                });
+
+                Ok(pipeline)
+            }),
+        );
+
+        Self {
+            pipeline_id,
+            camera_bind_group,
+            particle_bind_group,
+        }
+    }
+
+    pub fn render(
+        &self,
+        texture_views: &ParticlePassTextureViews,
+        context: &mut RenderPassContext,
+    ) {
+        let mut render_pass = context.encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Particle pass"),
+            color_attachments: &[texture_views.color.attachment()],
+            depth_stencil_attachment: texture_views.depth.attachment(),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        render_pass.set_pipeline(context.pipeline_cache.get(self.pipeline_id));
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.particle_bind_group, &[]);
+        render_pass.draw(0..6, 0..MAX_PARTICLES);
+    }
+}
@@ -3,10 +3,13 @@ use std::sync::Arc;
 use crate::rendering::{
     config::RenderConfig,
     instancing::DrawableBuffers,
+    joint_buffer::JointBuffer,
+    light_buffer::LightBuffer,
     mesh_buffers::MeshBuffers,
+    passes::blur_pass::BlurPass,
     render_common::RenderCommon,
     render_material_manager::RenderMaterialManager,
-    shader_loader::{PipelineCacheBuilder, RenderPipelineCache},
+    shader_loader::{PipelineCache, PipelineCacheBuilder},
 };
 
 pub struct PassCreationContext {
@@ -16,24 +19,23 @@ pub struct PassCreationContext {
 
     pub drawable_buffers: Arc<DrawableBuffers>,
     pub mesh_buffers: Arc<MeshBuffers>,
+    pub light_buffer: Arc<LightBuffer>,
+    pub joint_buffer: Arc<JointBuffer>,
 }
 
+/// Used to create both render and compute passes, whose pipelines go to the same cache
 pub struct RenderPassCreationContext<'a> {
     pub shared: &'a PassCreationContext,
-    pub cache_builder: &'a mut PipelineCacheBuilder<wgpu::RenderPipeline>,
+    pub cache_builder: &'a mut PipelineCacheBuilder,
     pub material_manager: &'a RenderMaterialManager,
     pub camera_uniform_buffer: &'a wgpu::Buffer,
 }
 
-pub struct ComputePassCreationContext<'a> {
-    pub shared: &'a PassCreationContext,
-    pub cache_builder: &'a mut PipelineCacheBuilder<wgpu::ComputePipeline>,
-}
-
 pub struct RenderPassContext<'a> {
     pub encoder: &'a mut wgpu::CommandEncoder,
-    pub pipeline_cache: &'a RenderPipelineCache,
+    pub pipeline_cache: &'a PipelineCache,
     pub draw_commands_buffer: &'a wgpu::Buffer,
     pub draw_commands_count_buffer: &'a wgpu::Buffer,
     pub material_manager: &'a mut RenderMaterialManager,
+    pub blur_pass: &'a BlurPass,
 }
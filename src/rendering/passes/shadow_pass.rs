@@ -0,0 +1,490 @@
+use std::sync::Arc;
+
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec3, Vec4};
+use wgpu::{MultisampleState, PipelineCompilationOptions, RenderPassDescriptor};
+
+use crate::{
+    camera::Camera,
+    rendering::{
+        config::RenderConfig,
+        instancing::DrawableBuffers,
+        mesh_buffers::MeshBuffers,
+        passes::render_pass_context::{RenderPassContext, RenderPassCreationContext},
+        render_model::{render_model_vbl, MODEL_PRIMITIVE_STATE},
+        shader_loader::{RenderPipelineId, ShaderDefinition},
+        texture::DepthTexture,
+        util::bind_group_builder::BindGroupBuilder,
+    },
+};
+
+/// Layers in the shadow map. This should match MAX_SHADOW_CASCADES in shared/shadow.wgsl.
+pub const MAX_SHADOW_CASCADES: usize = 4;
+/// Casters this far behind a cascade, towards the light, still cast shadows into it
+const CASTER_DISTANCE: f32 = 50.0;
+
+/// Tints of the cascades in the debug views. This should match cascade_debug_color in
+/// shared/shadow.wgsl.
+pub const CASCADE_COLORS: [Vec4; MAX_SHADOW_CASCADES] = [
+    Vec4::new(1.0, 0.3, 0.3, 1.0),
+    Vec4::new(0.3, 1.0, 0.3, 1.0),
+    Vec4::new(0.3, 0.5, 1.0, 1.0),
+    Vec4::new(1.0, 1.0, 0.3, 1.0),
+];
+
+const SHADER_DEF: ShaderDefinition = ShaderDefinition {
+    name: "Shadow pass shader",
+    path: "shadow.wgsl",
+    defines: &[],
+};
+
+/// This should match ShadowCascade in shared/shadow.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct ShadowCascadeData {
+    light_view_proj: Mat4,
+    /// World space size of a shadow map texel
+    texel_size: f32,
+    _padding: [f32; 3],
+}
+
+/// This should match ShadowUniform in shared/shadow.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct ShadowUniformData {
+    cascades: [ShadowCascadeData; MAX_SHADOW_CASCADES],
+    cascade_count: u32,
+    blend_fraction: f32,
+    visualize_cascades: u32,
+    _padding: u32,
+}
+
+/// Renders scene depth from the primary directional light's point of view into cascades, each
+/// covering a slice of the camera's view. Nearer slices are smaller, so they get more detail.
+pub struct ShadowPass {
+    config: &'static RenderConfig,
+    pipeline_id: RenderPipelineId,
+    /// All cascades, for sampling
+    shadow_map_view: wgpu::TextureView,
+    /// One per cascade, for rendering
+    cascade_views: Vec<wgpu::TextureView>,
+    shadow_map_size: u32,
+    uniform_buffer: wgpu::Buffer,
+    cascade_buffers: Vec<wgpu::Buffer>,
+    cascade_bind_groups: Vec<wgpu::BindGroup>,
+    sampling_bind_group_layout: wgpu::BindGroupLayout,
+    sampling_bind_group: wgpu::BindGroup,
+    mesh_buffers: Arc<MeshBuffers>,
+    drawable_buffers: Arc<DrawableBuffers>,
+    /// Light space projections of the cascades of the current frame
+    cascade_view_projs: Vec<Mat4>,
+    /// Covers every cascade, for culling the shadow casters
+    culling_view_proj: Mat4,
+    cascade_count: u32,
+    /// Cascades cover the view up to this distance from the camera, or the camera's far plane if
+    /// it's closer
+    max_distance: f32,
+    /// Blends the split distances from uniform (0) to logarithmic (1)
+    split_lambda: f32,
+    blend_fraction: f32,
+    visualize_cascades: bool,
+}
+
+impl ShadowPass {
+    pub fn new(context: &mut RenderPassCreationContext) -> Self {
+        let device = &context.shared.device;
+
+        let shadow_map_size = context.shared.config.quality.shadow_map_size.max(1);
+        let shadow_map = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Shadow map"),
+            size: wgpu::Extent3d {
+                width: shadow_map_size,
+                height: shadow_map_size,
+                depth_or_array_layers: MAX_SHADOW_CASCADES as u32,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DepthTexture::DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let shadow_map_view = shadow_map.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Shadow map view"),
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let cascade_views = (0..MAX_SHADOW_CASCADES as u32)
+            .map(|cascade| {
+                shadow_map.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("Shadow cascade view"),
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: cascade,
+                    array_layer_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Shadow uniform buffer"),
+            size: std::mem::size_of::<ShadowUniformData>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let cascade_buffers = (0..MAX_SHADOW_CASCADES)
+            .map(|_| {
+                device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Shadow cascade uniform buffer"),
+                    size: std::mem::size_of::<ShadowCascadeData>() as u64,
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let comparison_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Shadow comparison sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        let light_bind_group_layout =
+            cascade_bind_group_builder(&cascade_buffers[0]).build_layout(device);
+        let cascade_bind_groups = cascade_buffers
+            .iter()
+            .map(|buffer| {
+                cascade_bind_group_builder(buffer)
+                    .build_with_layout(device, &light_bind_group_layout)
+            })
+            .collect();
+
+        // Used by passes that sample the shadow map
+        let (sampling_bind_group_layout, sampling_bind_group) =
+            BindGroupBuilder::new("Shadow sampling", wgpu::ShaderStages::FRAGMENT)
+                .uniform(
+                    0,
+                    "Shadow uniform buffer",
+                    uniform_buffer.as_entire_binding(),
+                )
+                .texture_2d_array(
+                    1,
+                    "Shadow map",
+                    wgpu::TextureSampleType::Depth,
+                    wgpu::BindingResource::TextureView(&shadow_map_view),
+                )
+                .sampler(
+                    2,
+                    "Shadow comparison sampler",
+                    wgpu::SamplerBindingType::Comparison,
+                    wgpu::BindingResource::Sampler(&comparison_sampler),
+                )
+                .build(device);
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Shadow pass pipeline layout"),
+                bind_group_layouts: &[
+                    &light_bind_group_layout,
+                    context
+                        .shared
+                        .drawable_buffers
+                        .culled()
+                        .shadow_visible_drawables
+                        .bind_group_layout(),
+                ],
+                push_constant_ranges: &[],
+            });
+
+        // The shader only reads the position, which is the same in both formats
+        let vertex_format = context.shared.mesh_buffers.vertex_format();
+        let pipeline_id = context.cache_builder.add_shader(
+            SHADER_DEF,
+            Box::new(move |device, shader_module, cache| {
+                let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("Shadow pass render pipeline"),
+                    layout: Some(&render_pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader_module,
+                        entry_point: Some("vs_main"),
+                        buffers: &[render_model_vbl(vertex_format)],
+                        compilation_options: PipelineCompilationOptions::default(),
+                    },
+                    fragment: None,
+                    primitive: MODEL_PRIMITIVE_STATE,
+                    depth_stencil: Some(wgpu::DepthStencilState {
+                        format: DepthTexture::DEPTH_FORMAT,
+                        depth_write_enabled: true,
+                        depth_compare: wgpu::CompareFunction::Less,
+                        stencil: wgpu::StencilState::default(),
+                        // Reduces shadow acne
+                        bias: wgpu::DepthBiasState {
+                            constant: 2,
+                            slope_scale: 2.0,
+                            clamp: 0.0,
+                        },
+                    }),
+                    multisample: MultisampleState::default(),
+                    multiview: None,
+                    cache,
+                });
+
+                Ok(pipeline)
+            }),
+        );
+
+        Self {
+            config: context.shared.config,
+            pipeline_id,
+            shadow_map_view,
+            cascade_views,
+            shadow_map_size,
+            uniform_buffer,
+            cascade_buffers,
+            cascade_bind_groups,
+            sampling_bind_group_layout,
+            sampling_bind_group,
+            mesh_buffers: context.shared.mesh_buffers.clone(),
+            drawable_buffers: context.shared.drawable_buffers.clone(),
+            cascade_view_projs: Vec::new(),
+            culling_view_proj: Mat4::IDENTITY,
+            cascade_count: MAX_SHADOW_CASCADES as u32,
+            max_distance: 80.0,
+            split_lambda: 0.75,
+            blend_fraction: 0.1,
+            visualize_cascades: false,
+        }
+    }
+
+    pub fn draw_ui(&mut self, ui: &imgui::Ui) {
+        ui.window("Shadows")
+            .size([300.0, 150.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                ui.slider(
+                    "Cascades",
+                    1,
+                    MAX_SHADOW_CASCADES as u32,
+                    &mut self.cascade_count,
+                );
+                ui.slider("Max distance##shadows", 5.0, 500.0, &mut self.max_distance);
+                ui.slider("Split lambda", 0.0, 1.0, &mut self.split_lambda);
+                ui.slider("Blend fraction", 0.0, 0.5, &mut self.blend_fraction);
+                ui.checkbox("Visualize cascades", &mut self.visualize_cascades);
+            });
+    }
+
+    /// View distances where the cascades end, mixing uniform and logarithmic splits. Logarithmic
+    /// splits keep the texel density on screen even, but make the nearest cascade tiny.
+    fn split_distances(&self, near: f32, far: f32) -> Vec<f32> {
+        let count = self.cascade_count as f32;
+
+        (1..=self.cascade_count)
+            .map(|cascade| {
+                let fraction = cascade as f32 / count;
+                let logarithmic = near * (far / near).powf(fraction);
+                let uniform = near + (far - near) * fraction;
+                uniform + (logarithmic - uniform) * self.split_lambda
+            })
+            .collect()
+    }
+
+    /// Fits an orthographic projection looking along the light direction around each cascade's
+    /// slice of the camera frustum, and uploads them
+    pub fn update(
+        &mut self,
+        queue: &wgpu::Queue,
+        camera: &Camera,
+        aspect_ratio: f32,
+        light_direction: Vec3,
+    ) {
+        let direction = light_direction.normalize_or(Vec3::NEG_Y);
+        // Avoid a degenerate view matrix when the light points straight up or down
+        let up = if direction.y.abs() > 0.99 {
+            Vec3::Z
+        } else {
+            Vec3::Y
+        };
+        let light_view = Mat4::look_to_lh(Vec3::ZERO, direction, up);
+        let inverse_camera_view = camera.get_view_matrix().inverse();
+
+        let near = camera.near.max(0.001);
+        let far = camera.far.min(self.max_distance).max(near + 0.001);
+        let tan_half_fov = (camera.fov_y_radians * 0.5).tan();
+
+        let mut cascades = [ShadowCascadeData::zeroed(); MAX_SHADOW_CASCADES];
+        let mut light_min = Vec3::splat(f32::MAX);
+        let mut light_max = Vec3::splat(f32::MIN);
+        self.cascade_view_projs.clear();
+
+        let mut slice_near = near;
+        for (cascade, slice_far) in self.split_distances(near, far).into_iter().enumerate() {
+            let corners = [slice_near, slice_far]
+                .into_iter()
+                .flat_map(|distance| {
+                    let half_height = distance * tan_half_fov;
+                    let half_width = half_height * aspect_ratio;
+                    [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)].map(|(x, y)| {
+                        inverse_camera_view.transform_point3(Vec3::new(
+                            x * half_width,
+                            y * half_height,
+                            distance,
+                        ))
+                    })
+                })
+                .collect::<Vec<_>>();
+            slice_near = slice_far;
+
+            // A bounding sphere doesn't change size when the camera turns, so the texels don't
+            // shimmer
+            let center = corners.iter().sum::<Vec3>() / corners.len() as f32;
+            let radius = corners
+                .iter()
+                .map(|corner| corner.distance(center))
+                .fold(0.0, f32::max);
+            let radius = (radius * 16.0).ceil() / 16.0;
+
+            // Moving the cascade in whole texels keeps the edges from crawling
+            let texel_size = radius * 2.0 / self.shadow_map_size as f32;
+            let light_center = light_view.transform_point3(center);
+            let light_center = Vec3::new(
+                (light_center.x / texel_size).floor() * texel_size,
+                (light_center.y / texel_size).floor() * texel_size,
+                light_center.z,
+            );
+
+            let cascade_min = light_center - Vec3::new(radius, radius, radius + CASTER_DISTANCE);
+            let cascade_max = light_center + Vec3::splat(radius);
+            light_min = light_min.min(cascade_min);
+            light_max = light_max.max(cascade_max);
+
+            let light_view_proj = orthographic(cascade_min, cascade_max) * light_view;
+            self.cascade_view_projs.push(light_view_proj);
+            cascades[cascade] = ShadowCascadeData {
+                light_view_proj,
+                texel_size,
+                _padding: [0.0; 3],
+            };
+            queue.write_buffer(
+                &self.cascade_buffers[cascade],
+                0,
+                bytemuck::bytes_of(&cascades[cascade]),
+            );
+        }
+
+        self.culling_view_proj = orthographic(light_min, light_max) * light_view;
+
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::bytes_of(&ShadowUniformData {
+                cascades,
+                cascade_count: self.cascade_count,
+                blend_fraction: self.blend_fraction,
+                visualize_cascades: self.visualize_cascades as u32,
+                _padding: 0,
+            }),
+        );
+    }
+
+    /// Light space projections of the cascades, from the nearest to the furthest
+    pub fn cascade_view_projs(&self) -> &[Mat4] {
+        &self.cascade_view_projs
+    }
+
+    /// Covers every cascade. The casters are culled once and drawn into every cascade.
+    pub fn culling_view_proj(&self) -> Mat4 {
+        self.culling_view_proj
+    }
+
+    pub fn sampling_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.sampling_bind_group_layout
+    }
+
+    pub fn sampling_bind_group(&self) -> &wgpu::BindGroup {
+        &self.sampling_bind_group
+    }
+
+    pub fn shadow_map_view(&self) -> &wgpu::TextureView {
+        &self.shadow_map_view
+    }
+
+    /// Draws the shadow casters into each cascade with the same indirect draw commands
+    pub fn render_indirect(&self, context: &mut RenderPassContext) {
+        let pipeline = context.pipeline_cache.get(self.pipeline_id);
+
+        for (cascade_view, cascade_bind_group) in self
+            .cascade_views
+            .iter()
+            .zip(&self.cascade_bind_groups)
+            .take(self.cascade_view_projs.len())
+        {
+            let mut render_pass = context.encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("Shadow pass (Indirect)"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: cascade_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, cascade_bind_group, &[]);
+            render_pass.set_bind_group(
+                1,
+                &self
+                    .drawable_buffers
+                    .culled()
+                    .shadow_visible_drawables
+                    .bind_group(),
+                &[],
+            );
+
+            render_pass.set_vertex_buffer(0, self.mesh_buffers.vertices().slice(..));
+            render_pass.set_index_buffer(
+                self.mesh_buffers.indices().slice(..),
+                wgpu::IndexFormat::Uint32,
+            );
+
+            if self.config.use_multi_draw_indirect_count {
+                render_pass.multi_draw_indexed_indirect_count(
+                    context.draw_commands_buffer,
+                    0,
+                    context.draw_commands_count_buffer,
+                    0,
+                    self.mesh_buffers.mesh_count(),
+                );
+            } else {
+                render_pass.multi_draw_indexed_indirect(
+                    context.draw_commands_buffer,
+                    0,
+                    self.mesh_buffers.mesh_count(),
+                );
+            }
+        }
+    }
+}
+
+fn cascade_bind_group_builder(buffer: &wgpu::Buffer) -> BindGroupBuilder<'_> {
+    BindGroupBuilder::new("Shadow cascade", wgpu::ShaderStages::VERTEX).uniform(
+        0,
+        "Shadow cascade uniform buffer",
+        buffer.as_entire_binding(),
+    )
+}
+
+/// Orthographic projection of a light space box
+fn orthographic(min: Vec3, max: Vec3) -> Mat4 {
+    Mat4::orthographic_lh(min.x, max.x, min.y, max.y, min.z, max.z)
+}
@@ -0,0 +1,154 @@
+use std::sync::Arc;
+
+use crate::{
+    asset_pipeline::mesh_baker::VertexFormat,
+    rendering::{
+        joint_buffer::JointBuffer,
+        mesh_buffers::{MeshBuffers, MAX_SKINNED_MESHES},
+        passes::render_pass_context::RenderPassCreationContext,
+        render_model::QUANTIZED_VERTEX_DEFINES,
+        shader_loader::{ComputePipelineId, PipelineCache, ShaderDefinition},
+        util::bind_group_builder::BindGroupBuilder,
+    },
+};
+
+const SKINNING_SHADER: ShaderDefinition = ShaderDefinition {
+    name: "Skinning compute shader",
+    path: "skinning.wgsl",
+    defines: &[],
+};
+
+const QUANTIZED_SKINNING_SHADER: ShaderDefinition = SKINNING_SHADER.variant(
+    "Skinning compute shader (quantized vertices)",
+    QUANTIZED_VERTEX_DEFINES,
+);
+
+/// Must match the workgroup size in skinning.wgsl
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Skins the vertices of one mesh into the skinned region of the vertex buffer. This should
+/// match the same structure defined in WGSL.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SkinningJob {
+    pub source_vertex_offset: u32,
+    pub destination_vertex_offset: u32,
+    pub vertex_count: u32,
+    pub joint_offset: u32,
+}
+
+/// Applies the joint matrices to the baked vertices of skinned drawables, before anything else
+/// reads the vertex buffer. The render passes then draw skinned meshes like any other mesh.
+pub struct SkinningPass {
+    device: wgpu::Device,
+    pipeline_id: ComputePipelineId,
+    jobs_buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    joint_buffer: Arc<JointBuffer>,
+}
+
+impl SkinningPass {
+    pub fn new(context: &mut RenderPassCreationContext) -> Self {
+        let device = &context.shared.device;
+        let mesh_buffers: &MeshBuffers = &context.shared.mesh_buffers;
+        let joint_buffer = context.shared.joint_buffer.clone();
+
+        let jobs_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Skinning job buffer"),
+            size: (std::mem::size_of::<SkinningJob>() * MAX_SKINNED_MESHES as usize) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let (bind_group_layout, bind_group) =
+            Self::bind_group_builder(&jobs_buffer, &mesh_buffers.vertices()).build(device);
+
+        let pipeline_bind_group_layout = bind_group_layout.clone();
+        let joint_bind_group_layout = joint_buffer.bind_group_layout().clone();
+
+        let shader_def = match mesh_buffers.vertex_format() {
+            VertexFormat::Full => SKINNING_SHADER,
+            VertexFormat::Quantized => QUANTIZED_SKINNING_SHADER,
+        };
+
+        let pipeline_id = context.cache_builder.add_shader(
+            shader_def,
+            Box::new(move |device, shader_module, cache| {
+                let compute_pipeline =
+                    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: Some("Skinning compute pipeline"),
+                        layout: Some(&device.create_pipeline_layout(
+                            &wgpu::PipelineLayoutDescriptor {
+                                label: Some("Skinning compute pipeline layout"),
+                                bind_group_layouts: &[
+                                    &pipeline_bind_group_layout,
+                                    &joint_bind_group_layout,
+                                ],
+                                push_constant_ranges: &[],
+                            },
+                        )),
+                        module: &shader_module,
+                        entry_point: Some("main"),
+                        compilation_options: wgpu::PipelineCompilationOptions::default(),
+                        cache,
+                    });
+
+                Ok(compute_pipeline)
+            }),
+        );
+
+        Self {
+            device: device.clone(),
+            pipeline_id,
+            jobs_buffer,
+            bind_group_layout,
+            bind_group,
+            joint_buffer,
+        }
+    }
+
+    fn bind_group_builder<'a>(
+        jobs_buffer: &'a wgpu::Buffer,
+        vertex_buffer: &'a wgpu::Buffer,
+    ) -> BindGroupBuilder<'a> {
+        BindGroupBuilder::new("Skinning", wgpu::ShaderStages::COMPUTE)
+            .storage_r(0, "Skinning job buffer", jobs_buffer.as_entire_binding())
+            .storage_rw(1, "Vertex megabuffer", vertex_buffer.as_entire_binding())
+    }
+
+    /// Must be called when the mesh buffers have been reallocated
+    pub fn resize_meshes(&mut self, mesh_buffers: &MeshBuffers) {
+        self.bind_group = Self::bind_group_builder(&self.jobs_buffer, &mesh_buffers.vertices())
+            .build_with_layout(&self.device, &self.bind_group_layout);
+    }
+
+    pub fn dispatch(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline_cache: &PipelineCache,
+        jobs: &[SkinningJob],
+    ) {
+        let Some(max_vertex_count) = jobs.iter().map(|job| job.vertex_count).max() else {
+            return;
+        };
+
+        let jobs = &jobs[..jobs.len().min(MAX_SKINNED_MESHES as usize)];
+        queue.write_buffer(&self.jobs_buffer, 0, bytemuck::cast_slice(jobs));
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Skinning compute pass"),
+            timestamp_writes: None,
+        });
+
+        compute_pass.set_pipeline(pipeline_cache.get(self.pipeline_id));
+        compute_pass.set_bind_group(0, &self.bind_group, &[]);
+        compute_pass.set_bind_group(1, self.joint_buffer.bind_group(), &[]);
+        compute_pass.dispatch_workgroups(
+            max_vertex_count.div_ceil(WORKGROUP_SIZE),
+            jobs.len() as u32,
+            1,
+        );
+    }
+}
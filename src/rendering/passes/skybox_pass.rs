@@ -0,0 +1,112 @@
+use wgpu::{MultisampleState, PipelineCompilationOptions, RenderPassDescriptor};
+
+use crate::rendering::{
+    environment_map::EnvironmentMap,
+    passes::render_pass_context::RenderPassCreationContext,
+    post_process::targets::HDR_FORMAT,
+    render_graph::ColorTarget,
+    shader_loader::{PipelineCache, RenderPipelineId, ShaderDefinition},
+    util::bind_group_builder::BindGroupBuilder,
+};
+
+const SHADER_DEF: ShaderDefinition = ShaderDefinition {
+    name: "Skybox shader",
+    path: "skybox.wgsl",
+    defines: &[],
+};
+
+pub struct SkyboxPassTextureViews {
+    pub color: ColorTarget,
+}
+
+/// Draws the environment cubemap behind the scene. The lighting pass only writes pixels
+/// covered by geometry, so this runs first, which makes it the pass that clears the target.
+pub struct SkyboxPass {
+    pipeline_id: RenderPipelineId,
+    camera_bind_group: wgpu::BindGroup,
+    environment_bind_group: wgpu::BindGroup,
+}
+
+impl SkyboxPass {
+    pub fn new(context: &mut RenderPassCreationContext, environment_map: &EnvironmentMap) -> Self {
+        let device = &context.shared.device;
+
+        let (camera_bind_group_layout, camera_bind_group) =
+            BindGroupBuilder::new("Skybox camera", wgpu::ShaderStages::FRAGMENT)
+                .uniform(
+                    0,
+                    "Camera uniform buffer",
+                    context.camera_uniform_buffer.as_entire_binding(),
+                )
+                .build(device);
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Skybox pass pipeline layout"),
+                bind_group_layouts: &[
+                    &camera_bind_group_layout,
+                    environment_map.sampling_bind_group_layout(),
+                ],
+                push_constant_ranges: &[],
+            });
+
+        let pipeline_id = context.cache_builder.add_shader(
+            SHADER_DEF,
+            Box::new(move |device, shader_module, cache| {
+                let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("Skybox pass render pipeline"),
+                    layout: Some(&render_pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader_module,
+                        entry_point: Some("vs_main"),
+                        buffers: &[],
+                        compilation_options: PipelineCompilationOptions::default(),
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader_module,
+                        entry_point: Some("fs_main"),
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format: HDR_FORMAT,
+                            blend: Some(wgpu::BlendState::REPLACE),
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                        compilation_options: PipelineCompilationOptions::default(),
+                    }),
+                    primitive: wgpu::PrimitiveState::default(),
+                    depth_stencil: None,
+                    multisample: MultisampleState::default(),
+                    multiview: None,
+                    cache,
+                });
+
+                Ok(pipeline)
+            }),
+        );
+
+        Self {
+            pipeline_id,
+            camera_bind_group,
+            environment_bind_group: environment_map.sampling_bind_group().clone(),
+        }
+    }
+
+    pub fn render(
+        &self,
+        texture_views: &SkyboxPassTextureViews,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline_cache: &PipelineCache,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Skybox pass"),
+            color_attachments: &[texture_views.color.attachment()],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        render_pass.set_pipeline(pipeline_cache.get(self.pipeline_id));
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.environment_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
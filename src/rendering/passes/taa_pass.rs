@@ -0,0 +1,262 @@
+use bytemuck::{Pod, Zeroable};
+
+use crate::rendering::{
+    common::Resolution,
+    deferred::gbuffer::GBuffer,
+    passes::render_pass_context::{RenderPassContext, RenderPassCreationContext},
+    post_process::{
+        create_fullscreen_pipeline, draw_fullscreen,
+        targets::{RenderTarget, TargetSampling, HDR_FORMAT},
+    },
+    shader_loader::{RenderPipelineId, ShaderDefinition},
+    util::bind_group_builder::BindGroupBuilder,
+};
+
+const SHADER_DEF: ShaderDefinition = ShaderDefinition {
+    name: "TAA shader",
+    path: "taa.wgsl",
+    defines: &[],
+};
+
+/// This should match TaaSettings in taa.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct TaaSettings {
+    /// How much of the current frame is blended into the history, or 1 to discard the history
+    current_weight: f32,
+    _padding: [f32; 3],
+}
+
+pub struct TaaPassTextureViews {
+    pub color: wgpu::TextureView,
+}
+
+/// Temporal anti-aliasing. The camera is jittered by a sub-pixel amount every frame, and the
+/// scene color is accumulated into a history buffer reprojected with the G-buffer velocity.
+/// The history is clamped to the neighborhood of the current pixel to reject stale samples.
+pub struct TaaPass {
+    device: wgpu::Device,
+    enabled: bool,
+    current_weight: f32,
+    /// Cleared when the history doesn't match the current frame, e.g. after a resize
+    history_valid: bool,
+
+    sampling: TargetSampling,
+    /// Resolved frames are written alternately into these, the other one is the history
+    history: [RenderTarget; 2],
+    current_history: usize,
+
+    camera_uniform_buffer: wgpu::Buffer,
+    settings_buffer: wgpu::Buffer,
+    inputs_bind_group_layout: wgpu::BindGroupLayout,
+    inputs_bind_group: wgpu::BindGroup,
+
+    resolve_pipeline_id: RenderPipelineId,
+    copy_pipeline_id: RenderPipelineId,
+}
+
+impl TaaPass {
+    pub fn new(
+        context: &mut RenderPassCreationContext,
+        g_buffer: &GBuffer,
+        scene_color: &RenderTarget,
+        size: Resolution,
+    ) -> Self {
+        let device = &context.shared.device;
+        let common = context.shared.common.clone();
+
+        let sampling = TargetSampling::new(device);
+        let history = Self::create_history(device, size, &sampling);
+
+        let settings_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("TAA settings buffer"),
+            size: std::mem::size_of::<TaaSettings>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let (inputs_bind_group_layout, inputs_bind_group) = Self::inputs_bind_group_builder(
+            &common.camera_uniform_buffer,
+            g_buffer,
+            scene_color,
+            &settings_buffer,
+        )
+        .build(device);
+
+        // The history is bound first, so that it has the same group index in both entry points
+        let resolve_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("TAA resolve pipeline layout"),
+            bind_group_layouts: &[&sampling.bind_group_layout, &inputs_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let copy_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("TAA copy pipeline layout"),
+            bind_group_layouts: &[&sampling.bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let mut add_pipeline =
+            |label: &'static str, entry_point: &'static str, layout: wgpu::PipelineLayout| {
+                context.cache_builder.add_shader(
+                    SHADER_DEF,
+                    Box::new(move |device, shader_module, cache| {
+                        Ok(create_fullscreen_pipeline(
+                            device,
+                            label,
+                            &layout,
+                            &shader_module,
+                            entry_point,
+                            HDR_FORMAT,
+                            cache,
+                        ))
+                    }),
+                )
+            };
+
+        let resolve_pipeline_id =
+            add_pipeline("TAA resolve pipeline", "fs_resolve", resolve_layout);
+        let copy_pipeline_id = add_pipeline("TAA copy pipeline", "fs_copy", copy_layout);
+
+        Self {
+            device: device.clone(),
+            enabled: true,
+            current_weight: 0.1,
+            history_valid: false,
+
+            sampling,
+            history,
+            current_history: 0,
+
+            camera_uniform_buffer: common.camera_uniform_buffer.clone(),
+            settings_buffer,
+            inputs_bind_group_layout,
+            inputs_bind_group,
+
+            resolve_pipeline_id,
+            copy_pipeline_id,
+        }
+    }
+
+    fn create_history(
+        device: &wgpu::Device,
+        size: Resolution,
+        sampling: &TargetSampling,
+    ) -> [RenderTarget; 2] {
+        [
+            RenderTarget::new(device, "TAA history A", size, sampling),
+            RenderTarget::new(device, "TAA history B", size, sampling),
+        ]
+    }
+
+    fn inputs_bind_group_builder<'a>(
+        camera_uniform_buffer: &'a wgpu::Buffer,
+        g_buffer: &'a GBuffer,
+        scene_color: &'a RenderTarget,
+        settings_buffer: &'a wgpu::Buffer,
+    ) -> BindGroupBuilder<'a> {
+        // Everything here is read with textureLoad, so they don't need to be filterable
+        let unfilterable = wgpu::TextureSampleType::Float { filterable: false };
+
+        BindGroupBuilder::new("TAA inputs", wgpu::ShaderStages::FRAGMENT)
+            .uniform(
+                0,
+                "Camera uniform buffer",
+                camera_uniform_buffer.as_entire_binding(),
+            )
+            .texture_2d(
+                1,
+                "Scene color",
+                unfilterable,
+                wgpu::BindingResource::TextureView(scene_color.view()),
+            )
+            .texture_2d(
+                2,
+                "GBuffer velocity",
+                unfilterable,
+                wgpu::BindingResource::TextureView(&g_buffer.velocity.view),
+            )
+            .texture_2d(
+                3,
+                "GBuffer depth",
+                wgpu::TextureSampleType::Depth,
+                wgpu::BindingResource::TextureView(g_buffer.depth.view()),
+            )
+            .uniform(4, "TAA settings", settings_buffer.as_entire_binding())
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Must be called when the GBuffer attachments and the scene color target have been recreated
+    pub fn resize(&mut self, g_buffer: &GBuffer, scene_color: &RenderTarget, size: Resolution) {
+        self.history = Self::create_history(&self.device, size, &self.sampling);
+        self.history_valid = false;
+
+        self.inputs_bind_group = Self::inputs_bind_group_builder(
+            &self.camera_uniform_buffer,
+            g_buffer,
+            scene_color,
+            &self.settings_buffer,
+        )
+        .build_with_layout(&self.device, &self.inputs_bind_group_layout);
+    }
+
+    pub fn draw_ui(&mut self, ui: &imgui::Ui) {
+        ui.window("TAA")
+            .size([300.0, 100.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                // The history was accumulated without jitter
+                if ui.checkbox("Enabled##taa", &mut self.enabled) {
+                    self.history_valid = false;
+                }
+                ui.slider("Current frame weight", 0.02, 1.0, &mut self.current_weight);
+            });
+    }
+
+    pub fn render(
+        &mut self,
+        queue: &wgpu::Queue,
+        texture_views: &TaaPassTextureViews,
+        context: &mut RenderPassContext,
+    ) {
+        if !self.enabled {
+            return;
+        }
+
+        let settings = TaaSettings {
+            current_weight: if self.history_valid {
+                self.current_weight
+            } else {
+                1.0
+            },
+            _padding: [0.0; 3],
+        };
+        queue.write_buffer(&self.settings_buffer, 0, bytemuck::cast_slice(&[settings]));
+
+        let history = &self.history[self.current_history];
+        let resolved = &self.history[1 - self.current_history];
+        let pipeline_cache = context.pipeline_cache;
+
+        // The scene color is an input of the resolve, so the result is copied back afterwards
+        draw_fullscreen(
+            context.encoder,
+            "TAA resolve",
+            resolved.view(),
+            pipeline_cache.get(self.resolve_pipeline_id),
+            &[&history.bind_group, &self.inputs_bind_group],
+        );
+
+        draw_fullscreen(
+            context.encoder,
+            "TAA copy",
+            &texture_views.color,
+            pipeline_cache.get(self.copy_pipeline_id),
+            &[&resolved.bind_group],
+        );
+
+        self.current_history = 1 - self.current_history;
+        self.history_valid = true;
+    }
+}
@@ -0,0 +1,382 @@
+use bytemuck::{Pod, Zeroable};
+use glam::{Vec3, Vec4};
+use wgpu::{
+    MultisampleState, PipelineCompilationOptions, RenderPassDescriptor, TexelCopyBufferLayout,
+    TexelCopyTextureInfo,
+};
+
+use crate::{
+    rendering::{
+        passes::render_pass_context::RenderPassCreationContext,
+        post_process::targets::HDR_FORMAT,
+        render_graph::{ColorTarget, DepthTarget},
+        shader_loader::{PipelineCache, RenderPipelineId, ShaderDefValue, ShaderDefinition},
+        texture::DepthTexture,
+        util::bind_group_builder::BindGroupBuilder,
+    },
+    scene_graph::{
+        scene::Scene,
+        text::{Text, TextMode},
+    },
+    text::{font_atlas::FontAtlas, layout::layout_text},
+};
+
+const SHADER_DEF: ShaderDefinition = ShaderDefinition {
+    name: "Text shader",
+    path: "text.wgsl",
+    defines: &[],
+};
+
+const SCREEN_SHADER_DEF: ShaderDefinition = SHADER_DEF.variant(
+    "Screen text shader",
+    &[("SCREEN_SPACE", ShaderDefValue::Bool(true))],
+);
+
+const MAX_GLYPHS: usize = 8192;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct GpuGlyph {
+    origin: Vec3,
+    mode: u32,
+    right: Vec3,
+    _padding0: f32,
+    up: Vec3,
+    _padding1: f32,
+    rect: Vec4,
+    uv_rect: Vec4,
+    color: Vec4,
+}
+
+pub struct TextPassTextureViews {
+    pub color: ColorTarget,
+    /// Depth of the opaque geometry, which world text is tested against but doesn't write to
+    pub depth: DepthTarget,
+}
+
+/// Draws the text components of the scene from a signed distance field atlas. World and
+/// billboard text is drawn with the particles, and screen text on top of the image after
+/// depth of field.
+pub struct TextPass {
+    atlas: Option<FontAtlas>,
+    world_pipeline_id: RenderPipelineId,
+    screen_pipeline_id: RenderPipelineId,
+    camera_bind_group: wgpu::BindGroup,
+    global_uniform_bind_group: wgpu::BindGroup,
+    glyph_bind_group: wgpu::BindGroup,
+    glyph_buffer: wgpu::Buffer,
+    /// World glyphs come first in the buffer, followed by the screen glyphs
+    world_glyph_count: u32,
+    screen_glyph_count: u32,
+}
+
+impl TextPass {
+    pub fn new(
+        context: &mut RenderPassCreationContext,
+        queue: &wgpu::Queue,
+        font_path: &str,
+    ) -> Self {
+        let device = &context.shared.device;
+        let common = &context.shared.common;
+
+        // Text is optional, so a missing font only hides it
+        let atlas = FontAtlas::load(font_path)
+            .inspect_err(|e| log::warn!("Failed to load font, text is disabled: {:?}", e))
+            .ok();
+        let atlas_texture = Self::create_atlas_texture(device, queue, atlas.as_ref());
+        let atlas_view = atlas_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let atlas_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Font atlas sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let glyph_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Glyph buffer"),
+            size: (MAX_GLYPHS * std::mem::size_of::<GpuGlyph>()) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let (camera_bind_group_layout, camera_bind_group) =
+            BindGroupBuilder::new("Text camera", wgpu::ShaderStages::VERTEX)
+                .uniform(
+                    0,
+                    "Camera uniform buffer",
+                    context.camera_uniform_buffer.as_entire_binding(),
+                )
+                .build(device);
+
+        let (glyph_bind_group_layout, glyph_bind_group) =
+            BindGroupBuilder::new("Glyphs", wgpu::ShaderStages::VERTEX_FRAGMENT)
+                .storage_r(0, "Glyph buffer", glyph_buffer.as_entire_binding())
+                .texture_2d(
+                    1,
+                    "Font atlas",
+                    wgpu::TextureSampleType::Float { filterable: true },
+                    wgpu::BindingResource::TextureView(&atlas_view),
+                )
+                .sampler(
+                    2,
+                    "Font atlas sampler",
+                    wgpu::SamplerBindingType::Filtering,
+                    wgpu::BindingResource::Sampler(&atlas_sampler),
+                )
+                .build(device);
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Text pass pipeline layout"),
+                bind_group_layouts: &[
+                    &camera_bind_group_layout,
+                    &common.global_uniform.bind_group_layout,
+                    &glyph_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            });
+
+        // Screen text is drawn over everything, so it doesn't need the depth buffer
+        let world_pipeline_id =
+            Self::add_pipeline(context, SHADER_DEF, render_pipeline_layout.clone(), true);
+        let screen_pipeline_id =
+            Self::add_pipeline(context, SCREEN_SHADER_DEF, render_pipeline_layout, false);
+
+        Self {
+            atlas,
+            world_pipeline_id,
+            screen_pipeline_id,
+            camera_bind_group,
+            global_uniform_bind_group: common.global_uniform.bind_group.clone(),
+            glyph_bind_group,
+            glyph_buffer,
+            world_glyph_count: 0,
+            screen_glyph_count: 0,
+        }
+    }
+
+    fn add_pipeline(
+        context: &mut RenderPassCreationContext,
+        shader_def: ShaderDefinition,
+        render_pipeline_layout: wgpu::PipelineLayout,
+        depth_test: bool,
+    ) -> RenderPipelineId {
+        let name = shader_def.name;
+
+        context.cache_builder.add_shader(
+            shader_def,
+            Box::new(move |device, shader_module, cache| {
+                let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some(name),
+                    layout: Some(&render_pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader_module,
+                        entry_point: Some("vs_main"),
+                        buffers: &[],
+                        compilation_options: PipelineCompilationOptions::default(),
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader_module,
+                        entry_point: Some("fs_main"),
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format: HDR_FORMAT,
+                            blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                        compilation_options: PipelineCompilationOptions::default(),
+                    }),
+                    // World text can be seen from behind
+                    primitive: wgpu::PrimitiveState {
+                        cull_mode: None,
+                        ..Default::default()
+                    },
+                    depth_stencil: depth_test.then(|| wgpu::DepthStencilState {
+                        format: DepthTexture::DEPTH_FORMAT,
+                        depth_write_enabled: false,
+                        depth_compare: wgpu::CompareFunction::Less,
+                        stencil: wgpu::StencilState::default(),
+                        bias: wgpu::DepthBiasState::default(),
+                    }),
+                    multisample: MultisampleState::default(),
+                    multiview: None,
+                    cache,
+                });
+
+                Ok(pipeline)
+            }),
+        )
+    }
+
+    /// A 1x1 texture when there's no font, so that the bind group can still be created
+    fn create_atlas_texture(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        atlas: Option<&FontAtlas>,
+    ) -> wgpu::Texture {
+        let (width, height, pixels) = match atlas {
+            Some(atlas) => (atlas.width, atlas.height, atlas.pixels.as_slice()),
+            None => (1, 1, [0u8].as_slice()),
+        };
+
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Font atlas"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        queue.write_texture(
+            TexelCopyTextureInfo {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            pixels,
+            TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(width),
+                rows_per_image: None,
+            },
+            size,
+        );
+
+        texture
+    }
+
+    /// Lays out the text of every enabled object
+    pub fn update_from_scene(&mut self, scene: &Scene, queue: &wgpu::Queue) {
+        let Some(atlas) = &self.atlas else {
+            return;
+        };
+
+        let mut world_glyphs = Vec::new();
+        let mut screen_glyphs = Vec::new();
+
+        for (_, object) in scene.objects.iter() {
+            let Some(text) = object.text.as_ref().filter(|_| object.enabled) else {
+                continue;
+            };
+
+            if text.opacity <= 0.0 || text.reveal <= 0.0 || text.content.is_empty() {
+                continue;
+            }
+
+            let glyphs = match text.mode {
+                TextMode::Screen => &mut screen_glyphs,
+                TextMode::World | TextMode::Billboard => &mut world_glyphs,
+            };
+            Self::add_glyphs(atlas, object.transform.get_world_matrix(), text, glyphs);
+        }
+
+        world_glyphs.truncate(MAX_GLYPHS);
+        screen_glyphs.truncate(MAX_GLYPHS - world_glyphs.len());
+
+        self.world_glyph_count = world_glyphs.len() as u32;
+        self.screen_glyph_count = screen_glyphs.len() as u32;
+
+        world_glyphs.append(&mut screen_glyphs);
+        if !world_glyphs.is_empty() {
+            queue.write_buffer(&self.glyph_buffer, 0, bytemuck::cast_slice(&world_glyphs));
+        }
+    }
+
+    fn add_glyphs(
+        atlas: &FontAtlas,
+        world_matrix: &glam::Mat4,
+        text: &Text,
+        glyphs: &mut Vec<GpuGlyph>,
+    ) {
+        let quads = layout_text(atlas, &text.content, text.align);
+        let visible = (quads.len() as f32 * text.reveal.min(1.0)).ceil() as usize;
+
+        let (origin, right, up) = match text.mode {
+            // Only the translation is used, the size isn't scaled
+            TextMode::Screen => (world_matrix.w_axis.truncate(), Vec3::X, Vec3::Y),
+            TextMode::World | TextMode::Billboard => (
+                world_matrix.w_axis.truncate(),
+                world_matrix.x_axis.truncate(),
+                world_matrix.y_axis.truncate(),
+            ),
+        };
+        let mode = match text.mode {
+            TextMode::World | TextMode::Screen => 0,
+            TextMode::Billboard => 1,
+        };
+
+        glyphs.extend(quads.iter().take(visible).map(|quad| GpuGlyph {
+            origin,
+            mode,
+            right: right * text.size,
+            _padding0: 0.0,
+            up: up * text.size,
+            _padding1: 0.0,
+            rect: Vec4::new(quad.min.x, quad.min.y, quad.max.x, quad.max.y),
+            uv_rect: Vec4::new(quad.uv_min.x, quad.uv_min.y, quad.uv_max.x, quad.uv_max.y),
+            color: text.color.extend(text.opacity),
+        }));
+    }
+
+    /// World and billboard text, tested against the depth of the scene
+    pub fn render_world(
+        &self,
+        texture_views: &TextPassTextureViews,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline_cache: &PipelineCache,
+    ) {
+        if self.world_glyph_count == 0 {
+            return;
+        }
+
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Text pass"),
+            color_attachments: &[texture_views.color.attachment()],
+            depth_stencil_attachment: texture_views.depth.attachment(),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        render_pass.set_pipeline(pipeline_cache.get(self.world_pipeline_id));
+        self.set_bind_groups(&mut render_pass);
+        render_pass.draw(0..6, 0..self.world_glyph_count);
+    }
+
+    pub fn render_screen(
+        &self,
+        color: &ColorTarget,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline_cache: &PipelineCache,
+    ) {
+        if self.screen_glyph_count == 0 {
+            return;
+        }
+
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Screen text pass"),
+            color_attachments: &[color.attachment()],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        render_pass.set_pipeline(pipeline_cache.get(self.screen_pipeline_id));
+        self.set_bind_groups(&mut render_pass);
+        let first = self.world_glyph_count;
+        render_pass.draw(0..6, first..first + self.screen_glyph_count);
+    }
+
+    fn set_bind_groups(&self, render_pass: &mut wgpu::RenderPass) {
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.global_uniform_bind_group, &[]);
+        render_pass.set_bind_group(2, &self.glyph_bind_group, &[]);
+    }
+}
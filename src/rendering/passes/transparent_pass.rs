@@ -0,0 +1,200 @@
+use std::sync::Arc;
+
+use wgpu::{
+    PipelineCompilationOptions, RenderPassDescriptor, RenderPipelineDescriptor, VertexState,
+};
+
+use crate::{
+    asset_pipeline::mesh_baker::VertexFormat,
+    rendering::{
+        environment_map::EnvironmentMap,
+        instancing::{Drawable, DrawableBuffers},
+        light_buffer::LightBuffer,
+        mesh_buffers::MeshBuffers,
+        passes::{
+            render_pass_context::{RenderPassContext, RenderPassCreationContext},
+            shadow_pass::ShadowPass,
+        },
+        post_process::targets::HDR_FORMAT,
+        render_graph::{ColorTarget, DepthTarget},
+        render_model::{render_model_vbl, MODEL_PRIMITIVE_STATE, QUANTIZED_VERTEX_DEFINES},
+        shader_loader::{RenderPipelineId, ShaderDefinition},
+        texture::DepthTexture,
+        util::bind_group_builder::BindGroupBuilder,
+    },
+};
+
+/// Forward shades alpha blended drawables on top of the lit scene. The drawables are sorted on
+/// the CPU and drawn one at a time, so they don't go through GPU culling.
+pub struct TransparentPass {
+    pipeline_id: RenderPipelineId,
+    camera_bind_group: wgpu::BindGroup,
+    mesh_buffers: Arc<MeshBuffers>,
+    drawable_buffers: Arc<DrawableBuffers>,
+    light_buffer: Arc<LightBuffer>,
+    shadow_bind_group: wgpu::BindGroup,
+    environment_bind_group: wgpu::BindGroup,
+}
+
+pub struct TransparentPassTextureViews {
+    pub color: ColorTarget,
+    /// Depth of the opaque geometry, which is tested against but not written to
+    pub depth: DepthTarget,
+}
+
+const SHADER_DEF: ShaderDefinition = ShaderDefinition {
+    name: "Transparent pass shader",
+    path: "transparent.wgsl",
+    defines: &[],
+};
+
+const QUANTIZED_SHADER_DEF: ShaderDefinition = SHADER_DEF.variant(
+    "Transparent pass shader (quantized vertices)",
+    QUANTIZED_VERTEX_DEFINES,
+);
+
+impl TransparentPass {
+    pub fn new(
+        context: &mut RenderPassCreationContext,
+        shadow_pass: &ShadowPass,
+        environment_map: &EnvironmentMap,
+    ) -> Self {
+        let device = &context.shared.device;
+        let common = context.shared.common.clone();
+
+        let (camera_bind_group_layout, camera_bind_group) =
+            BindGroupBuilder::new("Transparent camera", wgpu::ShaderStages::VERTEX_FRAGMENT)
+                .uniform(
+                    0,
+                    "Camera uniform buffer",
+                    common.camera_uniform_buffer.as_entire_binding(),
+                )
+                .build(device);
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Transparent pass pipeline layout"),
+                bind_group_layouts: &[
+                    &camera_bind_group_layout,
+                    context
+                        .shared
+                        .drawable_buffers
+                        .transparent_drawables
+                        .bind_group_layout(),
+                    context.material_manager.bind_group_layout(),
+                    context.shared.light_buffer.bind_group_layout(),
+                    shadow_pass.sampling_bind_group_layout(),
+                    environment_map.sampling_bind_group_layout(),
+                ],
+                push_constant_ranges: &[],
+            });
+
+        let vertex_format = context.shared.mesh_buffers.vertex_format();
+        let shader_def = match vertex_format {
+            VertexFormat::Full => SHADER_DEF,
+            VertexFormat::Quantized => QUANTIZED_SHADER_DEF,
+        };
+
+        let pipeline_id = context.cache_builder.add_shader(
+            shader_def,
+            Box::new(move |device, shader_module, cache| {
+                let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+                    label: Some("Transparent pass render pipeline"),
+                    layout: Some(&render_pipeline_layout),
+                    vertex: VertexState {
+                        module: &shader_module,
+                        entry_point: Some("vs_main"),
+                        buffers: &[render_model_vbl(vertex_format)],
+                        compilation_options: PipelineCompilationOptions::default(),
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader_module,
+                        entry_point: Some("fs_main"),
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format: HDR_FORMAT,
+                            blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                        compilation_options: PipelineCompilationOptions::default(),
+                    }),
+                    primitive: MODEL_PRIMITIVE_STATE,
+                    depth_stencil: Some(wgpu::DepthStencilState {
+                        format: DepthTexture::DEPTH_FORMAT,
+                        depth_write_enabled: false,
+                        depth_compare: wgpu::CompareFunction::Less,
+                        stencil: wgpu::StencilState::default(),
+                        bias: wgpu::DepthBiasState::default(),
+                    }),
+                    multisample: wgpu::MultisampleState::default(),
+                    multiview: None,
+                    cache,
+                });
+
+                Ok(pipeline)
+            }),
+        );
+
+        Self {
+            pipeline_id,
+            camera_bind_group,
+            mesh_buffers: context.shared.mesh_buffers.clone(),
+            drawable_buffers: context.shared.drawable_buffers.clone(),
+            light_buffer: context.shared.light_buffer.clone(),
+            shadow_bind_group: shadow_pass.sampling_bind_group().clone(),
+            environment_bind_group: environment_map.sampling_bind_group().clone(),
+        }
+    }
+
+    /// The drawables must be in the same order as in the transparent drawable buffer
+    pub fn render(
+        &self,
+        texture_views: &TransparentPassTextureViews,
+        drawables: &[Drawable],
+        context: &mut RenderPassContext,
+    ) {
+        let mut render_pass = context.encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Transparent pass"),
+            color_attachments: &[texture_views.color.attachment()],
+            depth_stencil_attachment: texture_views.depth.attachment(),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        if drawables.is_empty() {
+            return;
+        }
+
+        let pipeline = context.pipeline_cache.get(self.pipeline_id);
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+        render_pass.set_bind_group(
+            1,
+            &self.drawable_buffers.transparent_drawables.bind_group(),
+            &[],
+        );
+        render_pass.set_bind_group(2, context.material_manager.bind_group(), &[]);
+        render_pass.set_bind_group(3, self.light_buffer.bind_group(), &[]);
+        render_pass.set_bind_group(4, &self.shadow_bind_group, &[]);
+        render_pass.set_bind_group(5, &self.environment_bind_group, &[]);
+
+        render_pass.set_vertex_buffer(0, self.mesh_buffers.vertices().slice(..));
+        render_pass.set_index_buffer(
+            self.mesh_buffers.indices().slice(..),
+            wgpu::IndexFormat::Uint32,
+        );
+
+        // One draw per drawable to preserve the back to front order
+        for (i, drawable) in drawables.iter().enumerate() {
+            let Some(mesh) = self.mesh_buffers.mesh_info(drawable.primitive_index) else {
+                continue;
+            };
+
+            let i = i as u32;
+            render_pass.draw_indexed(
+                mesh.first_index..mesh.first_index + mesh.index_count,
+                mesh.vertex_offset as i32,
+                i..i + 1,
+            );
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+const CACHE_FOLDER: &str = "cache/pipelines";
+const SHADER_FOLDER: &str = "assets/shaders";
+
+/// Compiled pipelines, saved to disk so that the next run doesn't have to compile them again.
+/// Only some backends (currently Vulkan) support this.
+pub struct PipelineDiskCache {
+    cache: wgpu::PipelineCache,
+    path: PathBuf,
+    /// Files of the same adapter that were written for other versions of the shaders
+    stale_files: Vec<PathBuf>,
+}
+
+impl PipelineDiskCache {
+    /// None if the device doesn't support pipeline caches
+    pub fn load(device: &wgpu::Device, adapter_info: &wgpu::AdapterInfo) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::PIPELINE_CACHE) {
+            return None;
+        }
+
+        // Includes the vendor, device and driver, which the cached data is only valid for
+        let adapter_key = wgpu::util::pipeline_cache_key(adapter_info)?;
+        let shader_hash = match hash_shaders(Path::new(SHADER_FOLDER)) {
+            Ok(hash) => hash,
+            Err(e) => {
+                log::warn!(
+                    "Failed to hash shaders, not using a pipeline cache: {:?}",
+                    e
+                );
+                return None;
+            }
+        };
+
+        let file_name = format!("{}-{:016x}", adapter_key, shader_hash);
+        let path = Path::new(CACHE_FOLDER).join(&file_name);
+        let stale_files = std::fs::read_dir(CACHE_FOLDER)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|other| {
+                other.file_name().is_some_and(|name| {
+                    let name = name.to_string_lossy();
+                    name.starts_with(&adapter_key) && name != file_name
+                })
+            })
+            .collect();
+
+        let data = std::fs::read(&path).ok();
+        log::info!(
+            "{} pipeline cache {}",
+            if data.is_some() { "Loaded" } else { "Created" },
+            path.display()
+        );
+
+        // SAFETY: The data was written by `save` for the same adapter and driver, since the key
+        // includes them. With `fallback`, wgpu creates an empty cache if it can't use the data.
+        let cache = unsafe {
+            device.create_pipeline_cache(&wgpu::PipelineCacheDescriptor {
+                label: Some("Pipeline cache"),
+                data: data.as_deref(),
+                fallback: true,
+            })
+        };
+
+        Some(Self {
+            cache,
+            path,
+            stale_files,
+        })
+    }
+
+    pub fn cache(&self) -> &wgpu::PipelineCache {
+        &self.cache
+    }
+
+    /// Should be called after the pipelines have been created
+    pub fn save(&mut self) -> anyhow::Result<()> {
+        let Some(data) = self.cache.get_data() else {
+            return Ok(());
+        };
+
+        std::fs::create_dir_all(CACHE_FOLDER).context("Failed to create pipeline cache folder")?;
+
+        // Written to a temporary file first, so that a crash can't leave a partial cache behind
+        let temp_path = self.path.with_extension("tmp");
+        std::fs::write(&temp_path, data).context("Failed to write pipeline cache")?;
+        std::fs::rename(&temp_path, &self.path).context("Failed to replace pipeline cache")?;
+
+        for stale_file in self.stale_files.drain(..) {
+            let _ = std::fs::remove_file(stale_file);
+        }
+
+        Ok(())
+    }
+}
+
+/// Changes whenever any shader file changes, so that caches of old shaders can be removed
+fn hash_shaders(folder: &Path) -> anyhow::Result<u64> {
+    let mut hasher = DefaultHasher::new();
+    hash_folder(folder, &mut hasher)?;
+    Ok(hasher.finish())
+}
+
+fn hash_folder(folder: &Path, hasher: &mut DefaultHasher) -> anyhow::Result<()> {
+    let mut entries = std::fs::read_dir(folder)
+        .with_context(|| format!("Failed to read {}", folder.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    // The order of the directory listing isn't defined
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            hash_folder(&path, hasher)?;
+        } else {
+            path.hash(hasher);
+            std::fs::read(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?
+                .hash(hasher);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,261 @@
+use bytemuck::{Pod, Zeroable};
+use winit::dpi::PhysicalSize;
+
+use crate::rendering::{
+    common::Resolution,
+    passes::{
+        blur_pass::{BlurKind, BlurSettings, BlurTextureViews},
+        render_pass_context::RenderPassCreationContext,
+    },
+    post_process::{
+        create_fullscreen_pipeline, draw_fullscreen,
+        targets::{RenderTarget, TargetSampling, HDR_FORMAT},
+        PostProcessContext, PostProcessPass,
+    },
+    shader_loader::{RenderPipelineId, ShaderDefinition},
+    util::bind_group_builder::BindGroupBuilder,
+};
+
+const SHADER_DEF: ShaderDefinition = ShaderDefinition {
+    name: "Bloom shader",
+    path: "post/bloom.wgsl",
+    defines: &[],
+};
+
+/// This should match BloomSettings in post/bloom.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct BloomSettings {
+    threshold: f32,
+    intensity: f32,
+    _padding: [f32; 2],
+}
+
+/// Bright pass, gaussian or Kawase blur at half resolution and additive composite
+pub struct BloomPass {
+    device: wgpu::Device,
+    enabled: bool,
+    threshold: f32,
+    intensity: f32,
+    blur_kind: BlurKind,
+    blur_iterations: u32,
+
+    sampling: TargetSampling,
+    // Half resolution targets for the blur
+    bloom_targets: [RenderTarget; 2],
+    half_size: Resolution,
+
+    settings_buffer: wgpu::Buffer,
+    settings_bind_group: wgpu::BindGroup,
+
+    bright_pipeline_id: RenderPipelineId,
+    composite_pipeline_id: RenderPipelineId,
+}
+
+impl BloomPass {
+    pub fn new(
+        context: &mut RenderPassCreationContext,
+        target_layout: &wgpu::BindGroupLayout,
+        size: Resolution,
+    ) -> Self {
+        let device = &context.shared.device;
+
+        let settings = BloomSettings {
+            threshold: 1.0,
+            intensity: 0.3,
+            _padding: [0.0; 2],
+        };
+
+        let settings_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Bloom settings buffer"),
+            size: std::mem::size_of::<BloomSettings>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let (settings_bind_group_layout, settings_bind_group) =
+            BindGroupBuilder::new("Bloom settings", wgpu::ShaderStages::FRAGMENT)
+                .uniform(
+                    0,
+                    "Bloom settings buffer",
+                    settings_buffer.as_entire_binding(),
+                )
+                .build(device);
+
+        let sampling = TargetSampling::new(device);
+        let half_size = Self::half_size(size);
+        let bloom_targets = Self::create_bloom_targets(device, half_size, &sampling);
+
+        let single_input_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Bloom pipeline layout"),
+            bind_group_layouts: &[target_layout, &settings_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        // The bloom texture is bound last, so that the settings have the same group index
+        // in every entry point
+        let composite_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Bloom composite pipeline layout"),
+            bind_group_layouts: &[
+                target_layout,
+                &settings_bind_group_layout,
+                &sampling.bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let mut add_pipeline =
+            |label: &'static str, entry_point: &'static str, layout: wgpu::PipelineLayout| {
+                context.cache_builder.add_shader(
+                    SHADER_DEF,
+                    Box::new(move |device, shader_module, cache| {
+                        Ok(create_fullscreen_pipeline(
+                            device,
+                            label,
+                            &layout,
+                            &shader_module,
+                            entry_point,
+                            HDR_FORMAT,
+                            cache,
+                        ))
+                    }),
+                )
+            };
+
+        let bright_pipeline_id = add_pipeline(
+            "Bloom bright pass pipeline",
+            "fs_bright",
+            single_input_layout,
+        );
+        let composite_pipeline_id =
+            add_pipeline("Bloom composite pipeline", "fs_composite", composite_layout);
+
+        Self {
+            device: device.clone(),
+            enabled: true,
+            threshold: settings.threshold,
+            intensity: settings.intensity,
+            blur_kind: BlurKind::Gaussian,
+            blur_iterations: context.shared.config.quality.bloom_iterations,
+
+            sampling,
+            bloom_targets,
+            half_size,
+
+            settings_buffer,
+            settings_bind_group,
+
+            bright_pipeline_id,
+            composite_pipeline_id,
+        }
+    }
+
+    fn half_size(size: Resolution) -> Resolution {
+        PhysicalSize::new((size.width / 2).max(1), (size.height / 2).max(1))
+    }
+
+    /// Written by the shared blur pass, so they need to be storage textures
+    fn create_bloom_targets(
+        device: &wgpu::Device,
+        half_size: Resolution,
+        sampling: &TargetSampling,
+    ) -> [RenderTarget; 2] {
+        [
+            RenderTarget::storage(device, "Bloom target A", half_size, sampling),
+            RenderTarget::storage(device, "Bloom target B", half_size, sampling),
+        ]
+    }
+}
+
+impl PostProcessPass for BloomPass {
+    fn name(&self) -> &'static str {
+        "Bloom"
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn resize(&mut self, size: Resolution) {
+        self.half_size = Self::half_size(size);
+        self.bloom_targets =
+            Self::create_bloom_targets(&self.device, self.half_size, &self.sampling);
+    }
+
+    fn draw_ui(&mut self, ui: &imgui::Ui) {
+        ui.checkbox("Enabled##bloom", &mut self.enabled);
+        ui.slider("Threshold", 0.0, 4.0, &mut self.threshold);
+        ui.slider("Intensity", 0.0, 2.0, &mut self.intensity);
+        let mut kawase = self.blur_kind == BlurKind::Kawase;
+        if ui.checkbox("Kawase blur", &mut kawase) {
+            self.blur_kind = if kawase {
+                BlurKind::Kawase
+            } else {
+                BlurKind::Gaussian
+            };
+        }
+        ui.slider("Blur iterations", 1, 8, &mut self.blur_iterations);
+    }
+
+    fn render(
+        &self,
+        context: &mut PostProcessContext,
+        input: &RenderTarget,
+        output: &wgpu::TextureView,
+    ) {
+        let settings = BloomSettings {
+            threshold: self.threshold,
+            intensity: self.intensity,
+            _padding: [0.0; 2],
+        };
+        context
+            .queue
+            .write_buffer(&self.settings_buffer, 0, bytemuck::cast_slice(&[settings]));
+
+        let [bloom_a, bloom_b] = &self.bloom_targets;
+        let pipeline_cache = context.pipeline_cache;
+
+        draw_fullscreen(
+            context.encoder,
+            "Bloom bright pass",
+            bloom_a.view(),
+            pipeline_cache.get(self.bright_pipeline_id),
+            &[&input.bind_group, &self.settings_bind_group],
+        );
+
+        // The gaussian has 9 taps in each direction
+        let radius = match self.blur_kind {
+            BlurKind::Gaussian => 4,
+            BlurKind::Kawase => 1,
+        };
+
+        context.blur_pass.blur(
+            context.queue,
+            context.encoder,
+            pipeline_cache,
+            BlurSettings {
+                kind: self.blur_kind,
+                radius,
+                iterations: self.blur_iterations,
+            },
+            &BlurTextureViews {
+                input: bloom_a.view(),
+                scratch: bloom_b.view(),
+                output: bloom_a.view(),
+                size: self.half_size,
+            },
+        );
+
+        draw_fullscreen(
+            context.encoder,
+            "Bloom composite",
+            output,
+            pipeline_cache.get(self.composite_pipeline_id),
+            &[
+                &input.bind_group,
+                &self.settings_bind_group,
+                &bloom_a.bind_group,
+            ],
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use crate::rendering::{
+    common::Resolution,
+    post_process::{
+        targets::{PingPongTargets, RenderTarget},
+        tonemap::TonemapPass,
+        PostProcessContext, PostProcessParams, PostProcessPass,
+    },
+};
+
+/// The scene is rendered into the first ping-pong target. Each enabled pass then reads the
+/// previous result and writes to the other target, and the tonemap pass writes the final
+/// result to the swapchain.
+pub struct PostProcessChain {
+    targets: PingPongTargets,
+    passes: Vec<Box<dyn PostProcessPass>>,
+    tonemap_pass: TonemapPass,
+}
+
+impl PostProcessChain {
+    const SCENE_TARGET: usize = 0;
+
+    pub fn new(targets: PingPongTargets, tonemap_pass: TonemapPass) -> Self {
+        Self {
+            targets,
+            passes: Vec::new(),
+            tonemap_pass,
+        }
+    }
+
+    pub fn add_pass(&mut self, pass: Box<dyn PostProcessPass>) {
+        self.passes.push(pass);
+    }
+
+    /// HDR target the scene should be rendered into
+    pub fn scene_target(&self) -> &RenderTarget {
+        self.targets.get(Self::SCENE_TARGET)
+    }
+
+    pub fn resize(&mut self, size: Resolution) {
+        self.targets.resize(size);
+
+        for pass in &mut self.passes {
+            pass.resize(size);
+        }
+    }
+
+    /// The parameters are usually overwritten by the timeline on the next frame
+    pub fn draw_ui(&mut self, ui: &imgui::Ui, params: &mut PostProcessParams) {
+        ui.window("Post processing")
+            .size([300.0, 200.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                for pass in &mut self.passes {
+                    if ui.collapsing_header(pass.name(), imgui::TreeNodeFlags::DEFAULT_OPEN) {
+                        pass.draw_ui(ui);
+                    }
+                }
+
+                params.draw_ui(ui);
+            });
+    }
+
+    pub fn render(&self, context: &mut PostProcessContext, output: &wgpu::TextureView) {
+        let mut source = Self::SCENE_TARGET;
+
+        for pass in self.passes.iter().filter(|pass| pass.is_enabled()) {
+            let destination = 1 - source;
+            pass.render(
+                context,
+                self.targets.get(source),
+                self.targets.get(destination).view(),
+            );
+            source = destination;
+        }
+
+        self.tonemap_pass
+            .render(context, self.targets.get(source), output);
+    }
+}
@@ -0,0 +1,250 @@
+use std::path::Path;
+
+use anyhow::{bail, Context};
+use glam::Vec3;
+
+/// Look of the final image, applied around tonemapping. Animated by the timeline, usually with a
+/// different grade for each section of the demo.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorGrading {
+    /// In stops, on top of the exposure from `RenderConfig`
+    pub exposure: f32,
+    /// Power curve around middle gray, applied before tonemapping
+    pub contrast: f32,
+    /// 0 is grayscale, applied before tonemapping
+    pub saturation: f32,
+    /// Raises the shadows of the tonemapped image
+    pub lift: Vec3,
+    /// Exponent for the midtones of the tonemapped image. Values over 1 brighten.
+    pub gamma: Vec3,
+    /// Multiplies the highlights of the tonemapped image
+    pub gain: Vec3,
+    /// How much of the LUT is blended over the graded image
+    pub lut_strength: f32,
+}
+
+impl Default for ColorGrading {
+    fn default() -> Self {
+        Self {
+            exposure: 0.0,
+            contrast: 1.0,
+            saturation: 1.0,
+            lift: Vec3::ZERO,
+            gamma: Vec3::ONE,
+            gain: Vec3::ONE,
+            lut_strength: 0.0,
+        }
+    }
+}
+
+impl ColorGrading {
+    /// Looks that can be picked without the timeline, for testing
+    pub fn presets() -> [(&'static str, ColorGrading); 3] {
+        [
+            ("Neutral", ColorGrading::default()),
+            (
+                "Grayscale",
+                ColorGrading {
+                    saturation: 0.0,
+                    ..ColorGrading::default()
+                },
+            ),
+            (
+                "High contrast",
+                ColorGrading {
+                    contrast: 1.6,
+                    saturation: 1.2,
+                    ..ColorGrading::default()
+                },
+            ),
+        ]
+    }
+
+    pub fn draw_ui(&mut self, ui: &imgui::Ui) {
+        ui.slider("Exposure (EV)", -4.0, 4.0, &mut self.exposure);
+        ui.slider("Contrast", 0.5, 2.0, &mut self.contrast);
+        ui.slider("Saturation", 0.0, 2.0, &mut self.saturation);
+        ui.slider("LUT strength", 0.0, 1.0, &mut self.lut_strength);
+
+        for (label, value) in [
+            ("Lift", &mut self.lift),
+            ("Gamma", &mut self.gamma),
+            ("Gain", &mut self.gain),
+        ] {
+            let mut array = value.to_array();
+            if ui.input_float3(label, &mut array).build() {
+                *value = array.into();
+            }
+        }
+    }
+}
+
+/// 3D color lookup table, indexed by the sRGB encoded color. Red varies fastest and blue slowest,
+/// which is the layout of both .cube files and 3D textures.
+pub struct ColorLut {
+    pub size: u32,
+    pub texels: Vec<[u8; 4]>,
+}
+
+impl ColorLut {
+    /// Trilinear filtering between the corners reproduces the input exactly
+    pub fn identity() -> Self {
+        let texels = (0..8u8)
+            .map(|index| {
+                let channel = |bit: u8| if index & bit != 0 { 255 } else { 0 };
+                [channel(1), channel(2), channel(4), 255]
+            })
+            .collect();
+
+        Self { size: 2, texels }
+    }
+
+    /// Loads a .cube file, or a PNG strip of N blue slices of NxN placed side by side
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+
+        match extension.as_deref() {
+            Some("cube") => {
+                let contents = std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read LUT: {}", path.display()))?;
+                Self::parse_cube(&contents)
+                    .with_context(|| format!("Failed to parse LUT: {}", path.display()))
+            }
+            Some("png") => {
+                let image = image::open(path)
+                    .with_context(|| format!("Failed to open LUT: {}", path.display()))?
+                    .into_rgba8();
+                Self::from_strip(&image)
+                    .with_context(|| format!("Failed to parse LUT: {}", path.display()))
+            }
+            _ => bail!("Unsupported LUT format: {}", path.display()),
+        }
+    }
+
+    fn parse_cube(contents: &str) -> anyhow::Result<Self> {
+        let mut size = None;
+        let mut texels = Vec::new();
+
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut words = line.split_whitespace();
+            let Some(first) = words.next() else {
+                continue;
+            };
+
+            match first {
+                "LUT_3D_SIZE" => {
+                    let value: u32 = words
+                        .next()
+                        .context("LUT_3D_SIZE without a value")?
+                        .parse()
+                        .context("Invalid LUT_3D_SIZE")?;
+                    if value < 2 {
+                        bail!("LUT_3D_SIZE must be at least 2");
+                    }
+                    size = Some(value);
+                }
+                "LUT_1D_SIZE" => bail!("1D LUTs are not supported"),
+                "DOMAIN_MIN" | "DOMAIN_MAX" => {
+                    // Only the default domain of [0, 1] is supported
+                    let expected = if first == "DOMAIN_MIN" { 0.0 } else { 1.0 };
+                    for word in words {
+                        let value: f32 = word.parse().context("Invalid domain")?;
+                        if value != expected {
+                            bail!("Only the default LUT domain is supported");
+                        }
+                    }
+                }
+                "TITLE" => {}
+                _ => {
+                    let mut texel = [0u8, 0, 0, 255];
+                    for (channel, word) in std::iter::once(first).chain(words).enumerate() {
+                        if channel >= 3 {
+                            bail!("Too many values on line: {}", line);
+                        }
+
+                        let value: f32 = word
+                            .parse()
+                            .with_context(|| format!("Invalid LUT entry: {}", line))?;
+                        texel[channel] = (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+                    }
+                    texels.push(texel);
+                }
+            }
+        }
+
+        let size = size.context("Missing LUT_3D_SIZE")?;
+        let expected = (size * size * size) as usize;
+        if texels.len() != expected {
+            bail!("Expected {} LUT entries, found {}", expected, texels.len());
+        }
+
+        Ok(Self { size, texels })
+    }
+
+    fn from_strip(image: &image::RgbaImage) -> anyhow::Result<Self> {
+        let size = image.height();
+        if size < 2 || image.width() != size * size {
+            bail!(
+                "LUT strips must be N*N pixels wide and N pixels high, got {}x{}",
+                image.width(),
+                image.height()
+            );
+        }
+
+        let mut texels = Vec::with_capacity((size * size * size) as usize);
+        for blue in 0..size {
+            for green in 0..size {
+                for red in 0..size {
+                    texels.push(image.get_pixel(blue * size + red, green).0);
+                }
+            }
+        }
+
+        Ok(Self { size, texels })
+    }
+
+    pub fn create_texture(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> wgpu::Texture {
+        let size = wgpu::Extent3d {
+            width: self.size,
+            height: self.size,
+            depth_or_array_layers: self.size,
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Color grading LUT"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(&self.texels),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(self.size * std::mem::size_of::<[u8; 4]>() as u32),
+                rows_per_image: Some(self.size),
+            },
+            size,
+        );
+
+        texture
+    }
+}
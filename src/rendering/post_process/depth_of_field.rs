@@ -0,0 +1,462 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::{MultisampleState, PipelineCompilationOptions};
+
+use crate::{
+    camera::Camera,
+    rendering::{
+        common::{PhysicalSizeExt, Resolution},
+        deferred::gbuffer::GBuffer,
+        passes::render_pass_context::{RenderPassContext, RenderPassCreationContext},
+        post_process::{
+            create_fullscreen_pipeline, draw_fullscreen,
+            targets::{RenderTarget, HDR_FORMAT},
+        },
+        render_graph::ColorTarget,
+        shader_loader::{RenderPipelineId, ShaderDefinition},
+        texture::Texture,
+        util::bind_group_builder::BindGroupBuilder,
+    },
+};
+
+const PREPARE_SHADER_DEF: ShaderDefinition = ShaderDefinition {
+    name: "Depth of field prepare shader",
+    path: "depth_of_field/prepare.wgsl",
+    defines: &[],
+};
+
+const GATHER_SHADER_DEF: ShaderDefinition = ShaderDefinition {
+    name: "Depth of field gather shader",
+    path: "depth_of_field/gather.wgsl",
+    defines: &[],
+};
+
+const COMPOSITE_SHADER_DEF: ShaderDefinition = ShaderDefinition {
+    name: "Depth of field composite shader",
+    path: "depth_of_field/composite.wgsl",
+    defines: &[],
+};
+
+/// This should match DepthOfFieldSettings in shared/depth_of_field.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct DepthOfFieldSettings {
+    focus_distance: f32,
+    aperture: f32,
+    max_coc: f32,
+    near: f32,
+    far: f32,
+    _padding: [f32; 3],
+}
+
+pub struct DepthOfFieldPassTextureViews {
+    pub color: ColorTarget,
+}
+
+/// Depth of field driven by the focus distance and aperture of the camera. The scene is
+/// downsampled with its circle of confusion, the near and far fields are blurred separately at
+/// half resolution, and the result is composited over the sharp scene.
+///
+/// Runs on the HDR scene color before the post process chain, since it needs the G-buffer depth.
+pub struct DepthOfFieldPass {
+    device: wgpu::Device,
+    enabled: bool,
+    /// Radius of the blur kernel in half resolution pixels
+    max_coc: f32,
+
+    /// In 16-bit float. RGB for the downsampled scene, A for the signed CoC.
+    prepared: Texture,
+    /// In 16-bit float. Blurred background.
+    far_field: Texture,
+    /// In 16-bit float. Blurred foreground, A for its coverage.
+    near_field: Texture,
+    sampler: wgpu::Sampler,
+
+    settings_buffer: wgpu::Buffer,
+    common_bind_group_layout: wgpu::BindGroupLayout,
+    common_bind_group: wgpu::BindGroup,
+    scene_bind_group_layout: wgpu::BindGroupLayout,
+    scene_bind_group: wgpu::BindGroup,
+    prepared_bind_group_layout: wgpu::BindGroupLayout,
+    prepared_bind_group: wgpu::BindGroup,
+    fields_bind_group_layout: wgpu::BindGroupLayout,
+    fields_bind_group: wgpu::BindGroup,
+
+    prepare_pipeline_id: RenderPipelineId,
+    gather_pipeline_id: RenderPipelineId,
+    composite_pipeline_id: RenderPipelineId,
+}
+
+impl DepthOfFieldPass {
+    pub fn new(
+        context: &mut RenderPassCreationContext,
+        g_buffer: &GBuffer,
+        scene_color: &RenderTarget,
+        size: Resolution,
+    ) -> Self {
+        let device = &context.shared.device;
+
+        let prepared = Self::create_target(device, "Depth of field prepared", size);
+        let far_field = Self::create_target(device, "Depth of field far field", size);
+        let near_field = Self::create_target(device, "Depth of field near field", size);
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Depth of field sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let settings_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Depth of field settings buffer"),
+            size: std::mem::size_of::<DepthOfFieldSettings>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let (common_bind_group_layout, common_bind_group) =
+            Self::common_bind_group_builder(&settings_buffer, g_buffer, &sampler).build(device);
+        let (scene_bind_group_layout, scene_bind_group) =
+            Self::scene_bind_group_builder(scene_color).build(device);
+        let (prepared_bind_group_layout, prepared_bind_group) =
+            Self::prepared_bind_group_builder(&prepared).build(device);
+        let (fields_bind_group_layout, fields_bind_group) =
+            Self::fields_bind_group_builder(&far_field, &near_field).build(device);
+
+        let prepare_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Depth of field prepare pipeline layout"),
+            bind_group_layouts: &[&common_bind_group_layout, &scene_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let gather_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Depth of field gather pipeline layout"),
+            bind_group_layouts: &[&common_bind_group_layout, &prepared_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let composite_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Depth of field composite pipeline layout"),
+            bind_group_layouts: &[&common_bind_group_layout, &fields_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let prepare_pipeline_id = context.cache_builder.add_shader(
+            PREPARE_SHADER_DEF,
+            Box::new(move |device, shader_module, cache| {
+                Ok(create_fullscreen_pipeline(
+                    device,
+                    "Depth of field prepare pipeline",
+                    &prepare_layout,
+                    &shader_module,
+                    "fs_main",
+                    HDR_FORMAT,
+                    cache,
+                ))
+            }),
+        );
+
+        let gather_pipeline_id = context.cache_builder.add_shader(
+            GATHER_SHADER_DEF,
+            Box::new(move |device, shader_module, cache| {
+                let target = Some(wgpu::ColorTargetState {
+                    format: HDR_FORMAT,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                });
+
+                let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("Depth of field gather pipeline"),
+                    layout: Some(&gather_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader_module,
+                        entry_point: Some("vs_main"),
+                        buffers: &[],
+                        compilation_options: PipelineCompilationOptions::default(),
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader_module,
+                        entry_point: Some("fs_main"),
+                        // Far and near fields
+                        targets: &[target.clone(), target],
+                        compilation_options: PipelineCompilationOptions::default(),
+                    }),
+                    primitive: wgpu::PrimitiveState {
+                        topology: wgpu::PrimitiveTopology::TriangleList,
+                        strip_index_format: None,
+                        front_face: wgpu::FrontFace::Ccw,
+                        cull_mode: None,
+                        polygon_mode: wgpu::PolygonMode::Fill,
+                        unclipped_depth: false,
+                        conservative: false,
+                    },
+                    depth_stencil: None,
+                    multisample: MultisampleState::default(),
+                    multiview: None,
+                    cache,
+                });
+
+                Ok(pipeline)
+            }),
+        );
+
+        let composite_pipeline_id = context.cache_builder.add_shader(
+            COMPOSITE_SHADER_DEF,
+            Box::new(move |device, shader_module, cache| {
+                let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("Depth of field composite pipeline"),
+                    layout: Some(&composite_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader_module,
+                        entry_point: Some("vs_main"),
+                        buffers: &[],
+                        compilation_options: PipelineCompilationOptions::default(),
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader_module,
+                        entry_point: Some("fs_main"),
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format: HDR_FORMAT,
+                            blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                            write_mask: wgpu::ColorWrites::COLOR,
+                        })],
+                        compilation_options: PipelineCompilationOptions::default(),
+                    }),
+                    primitive: wgpu::PrimitiveState {
+                        topology: wgpu::PrimitiveTopology::TriangleList,
+                        strip_index_format: None,
+                        front_face: wgpu::FrontFace::Ccw,
+                        cull_mode: None,
+                        polygon_mode: wgpu::PolygonMode::Fill,
+                        unclipped_depth: false,
+                        conservative: false,
+                    },
+                    depth_stencil: None,
+                    multisample: MultisampleState::default(),
+                    multiview: None,
+                    cache,
+                });
+
+                Ok(pipeline)
+            }),
+        );
+
+        Self {
+            device: device.clone(),
+            enabled: context.shared.config.expensive_passes_enabled(),
+            max_coc: 12.0,
+
+            prepared,
+            far_field,
+            near_field,
+            sampler,
+
+            settings_buffer,
+            common_bind_group_layout,
+            common_bind_group,
+            scene_bind_group_layout,
+            scene_bind_group,
+            prepared_bind_group_layout,
+            prepared_bind_group,
+            fields_bind_group_layout,
+            fields_bind_group,
+
+            prepare_pipeline_id,
+            gather_pipeline_id,
+            composite_pipeline_id,
+        }
+    }
+
+    fn half_resolution(size: Resolution) -> Resolution {
+        Resolution::new((size.width / 2).max(1), (size.height / 2).max(1))
+    }
+
+    fn create_target(device: &wgpu::Device, label: &'static str, size: Resolution) -> Texture {
+        let descriptor = wgpu::TextureDescriptor {
+            label: Some(label),
+            size: Self::half_resolution(size).to_extent3d(),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: HDR_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        };
+
+        let texture = device.create_texture(&descriptor);
+        Texture::from_wgpu_texture(device, descriptor, texture, None)
+    }
+
+    fn common_bind_group_builder<'a>(
+        settings_buffer: &'a wgpu::Buffer,
+        g_buffer: &'a GBuffer,
+        sampler: &'a wgpu::Sampler,
+    ) -> BindGroupBuilder<'a> {
+        BindGroupBuilder::new("Depth of field common", wgpu::ShaderStages::FRAGMENT)
+            .uniform(
+                0,
+                "Depth of field settings",
+                settings_buffer.as_entire_binding(),
+            )
+            .texture_2d(
+                1,
+                "GBuffer depth",
+                wgpu::TextureSampleType::Depth,
+                wgpu::BindingResource::TextureView(g_buffer.depth.view()),
+            )
+            .sampler(
+                2,
+                "Depth of field sampler",
+                wgpu::SamplerBindingType::Filtering,
+                wgpu::BindingResource::Sampler(sampler),
+            )
+    }
+
+    fn scene_bind_group_builder(scene_color: &RenderTarget) -> BindGroupBuilder<'_> {
+        BindGroupBuilder::new("Depth of field scene", wgpu::ShaderStages::FRAGMENT).texture_2d(
+            0,
+            "Scene color",
+            wgpu::TextureSampleType::Float { filterable: true },
+            wgpu::BindingResource::TextureView(scene_color.view()),
+        )
+    }
+
+    fn prepared_bind_group_builder(prepared: &Texture) -> BindGroupBuilder<'_> {
+        BindGroupBuilder::new("Depth of field prepared", wgpu::ShaderStages::FRAGMENT).texture_2d(
+            0,
+            "Depth of field prepared",
+            wgpu::TextureSampleType::Float { filterable: false },
+            wgpu::BindingResource::TextureView(&prepared.view),
+        )
+    }
+
+    fn fields_bind_group_builder<'a>(
+        far_field: &'a Texture,
+        near_field: &'a Texture,
+    ) -> BindGroupBuilder<'a> {
+        let filterable = wgpu::TextureSampleType::Float { filterable: true };
+
+        BindGroupBuilder::new("Depth of field fields", wgpu::ShaderStages::FRAGMENT)
+            .texture_2d(
+                0,
+                "Depth of field far field",
+                filterable,
+                wgpu::BindingResource::TextureView(&far_field.view),
+            )
+            .texture_2d(
+                1,
+                "Depth of field near field",
+                filterable,
+                wgpu::BindingResource::TextureView(&near_field.view),
+            )
+    }
+
+    /// Must be called when the GBuffer attachments and the scene color target have been recreated
+    pub fn resize(&mut self, g_buffer: &GBuffer, scene_color: &RenderTarget, size: Resolution) {
+        let half_size = Self::half_resolution(size);
+        self.prepared.resize(&self.device, half_size);
+        self.far_field.resize(&self.device, half_size);
+        self.near_field.resize(&self.device, half_size);
+
+        self.common_bind_group =
+            Self::common_bind_group_builder(&self.settings_buffer, g_buffer, &self.sampler)
+                .build_with_layout(&self.device, &self.common_bind_group_layout);
+        self.scene_bind_group = Self::scene_bind_group_builder(scene_color)
+            .build_with_layout(&self.device, &self.scene_bind_group_layout);
+        self.prepared_bind_group = Self::prepared_bind_group_builder(&self.prepared)
+            .build_with_layout(&self.device, &self.prepared_bind_group_layout);
+        self.fields_bind_group = Self::fields_bind_group_builder(&self.far_field, &self.near_field)
+            .build_with_layout(&self.device, &self.fields_bind_group_layout);
+    }
+
+    /// Focus distance and aperture are properties of the camera, so that they can be animated
+    pub fn draw_ui(&mut self, ui: &imgui::Ui) {
+        ui.window("Depth of field")
+            .size([300.0, 80.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                ui.checkbox("Enabled##dof", &mut self.enabled);
+                ui.slider("Max blur radius", 1.0, 32.0, &mut self.max_coc);
+            });
+    }
+
+    pub fn render(
+        &self,
+        queue: &wgpu::Queue,
+        camera: &Camera,
+        texture_views: &DepthOfFieldPassTextureViews,
+        context: &mut RenderPassContext,
+    ) {
+        if !self.enabled || camera.aperture <= 0.0 {
+            return;
+        }
+
+        let settings = DepthOfFieldSettings {
+            focus_distance: camera.focus_distance.max(camera.near),
+            aperture: camera.aperture,
+            max_coc: self.max_coc,
+            near: camera.near,
+            far: camera.far,
+            _padding: [0.0; 3],
+        };
+        queue.write_buffer(&self.settings_buffer, 0, bytemuck::cast_slice(&[settings]));
+
+        let pipeline_cache = context.pipeline_cache;
+
+        draw_fullscreen(
+            context.encoder,
+            "Depth of field prepare",
+            &self.prepared.view,
+            pipeline_cache.get(self.prepare_pipeline_id),
+            &[&self.common_bind_group, &self.scene_bind_group],
+        );
+
+        {
+            let clear = wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                store: wgpu::StoreOp::Store,
+            };
+
+            let mut render_pass = context
+                .encoder
+                .begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("Depth of field gather"),
+                    color_attachments: &[
+                        Some(wgpu::RenderPassColorAttachment {
+                            view: &self.far_field.view,
+                            resolve_target: None,
+                            ops: clear,
+                        }),
+                        Some(wgpu::RenderPassColorAttachment {
+                            view: &self.near_field.view,
+                            resolve_target: None,
+                            ops: clear,
+                        }),
+                    ],
+                    depth_stencil_attachment: None,
+                    occlusion_query_set: None,
+                    timestamp_writes: None,
+                });
+
+            render_pass.set_pipeline(pipeline_cache.get(self.gather_pipeline_id));
+            render_pass.set_bind_group(0, &self.common_bind_group, &[]);
+            render_pass.set_bind_group(1, &self.prepared_bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+
+        let mut render_pass = context
+            .encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Depth of field composite"),
+                color_attachments: &[texture_views.color.attachment()],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+
+        render_pass.set_pipeline(pipeline_cache.get(self.composite_pipeline_id));
+        render_pass.set_bind_group(0, &self.common_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.fields_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,179 @@
+use bytemuck::{Pod, Zeroable};
+
+use crate::rendering::{
+    passes::render_pass_context::RenderPassCreationContext,
+    post_process::{
+        create_fullscreen_pipeline, draw_fullscreen,
+        targets::{RenderTarget, HDR_FORMAT},
+        PostProcessContext, PostProcessPass,
+    },
+    shader_loader::{RenderPipelineId, ShaderDefinition},
+    util::bind_group_builder::BindGroupBuilder,
+};
+
+const SHADER_DEF: ShaderDefinition = ShaderDefinition {
+    name: "Lens artifacts shader",
+    path: "post/lens_artifacts.wgsl",
+    defines: &[],
+};
+
+/// Imperfections of a physical camera. Animated by the timeline.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LensArtifacts {
+    /// Strength of the animated noise, relative to the scene color
+    pub film_grain: f32,
+    /// How much the corners are darkened
+    pub vignette: f32,
+    /// Separation of the color channels in the corners, as a fraction of the screen
+    pub chromatic_aberration: f32,
+}
+
+impl Default for LensArtifacts {
+    fn default() -> Self {
+        Self {
+            film_grain: 0.04,
+            vignette: 0.3,
+            chromatic_aberration: 0.002,
+        }
+    }
+}
+
+impl LensArtifacts {
+    pub fn draw_ui(&mut self, ui: &imgui::Ui) {
+        ui.slider("Film grain", 0.0, 0.5, &mut self.film_grain);
+        ui.slider("Vignette", 0.0, 1.0, &mut self.vignette);
+        ui.slider(
+            "Chromatic aberration",
+            0.0,
+            0.02,
+            &mut self.chromatic_aberration,
+        );
+    }
+}
+
+/// This should match LensArtifactsSettings in post/lens_artifacts.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct LensArtifactsSettings {
+    film_grain: f32,
+    vignette: f32,
+    chromatic_aberration: f32,
+    _padding: f32,
+}
+
+/// Chromatic aberration, vignette and film grain in a single pass. The grain is animated with the
+/// time from the global uniform.
+pub struct LensArtifactsPass {
+    enabled: bool,
+
+    settings_buffer: wgpu::Buffer,
+    settings_bind_group: wgpu::BindGroup,
+    global_uniform_bind_group: wgpu::BindGroup,
+
+    pipeline_id: RenderPipelineId,
+}
+
+impl LensArtifactsPass {
+    pub fn new(
+        context: &mut RenderPassCreationContext,
+        target_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let device = &context.shared.device;
+        let common = context.shared.common.clone();
+
+        let settings_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Lens artifacts settings buffer"),
+            size: std::mem::size_of::<LensArtifactsSettings>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let (settings_bind_group_layout, settings_bind_group) =
+            BindGroupBuilder::new("Lens artifacts settings", wgpu::ShaderStages::FRAGMENT)
+                .uniform(
+                    0,
+                    "Lens artifacts settings buffer",
+                    settings_buffer.as_entire_binding(),
+                )
+                .build(device);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Lens artifacts pipeline layout"),
+            bind_group_layouts: &[
+                target_layout,
+                &settings_bind_group_layout,
+                &common.global_uniform.bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline_id = context.cache_builder.add_shader(
+            SHADER_DEF,
+            Box::new(move |device, shader_module, cache| {
+                Ok(create_fullscreen_pipeline(
+                    device,
+                    "Lens artifacts pipeline",
+                    &pipeline_layout,
+                    &shader_module,
+                    "fs_main",
+                    HDR_FORMAT,
+                    cache,
+                ))
+            }),
+        );
+
+        Self {
+            enabled: true,
+
+            settings_buffer,
+            settings_bind_group,
+            global_uniform_bind_group: common.global_uniform.bind_group.clone(),
+
+            pipeline_id,
+        }
+    }
+}
+
+impl PostProcessPass for LensArtifactsPass {
+    fn name(&self) -> &'static str {
+        "Lens artifacts"
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn draw_ui(&mut self, ui: &imgui::Ui) {
+        ui.checkbox("Enabled##lens_artifacts", &mut self.enabled);
+    }
+
+    fn render(
+        &self,
+        context: &mut PostProcessContext,
+        input: &RenderTarget,
+        output: &wgpu::TextureView,
+    ) {
+        let lens_artifacts = &context.params.lens_artifacts;
+        let settings = LensArtifactsSettings {
+            film_grain: lens_artifacts.film_grain,
+            vignette: lens_artifacts.vignette,
+            chromatic_aberration: lens_artifacts.chromatic_aberration,
+            _padding: 0.0,
+        };
+        context
+            .queue
+            .write_buffer(&self.settings_buffer, 0, bytemuck::cast_slice(&[settings]));
+
+        draw_fullscreen(
+            context.encoder,
+            "Lens artifacts",
+            output,
+            context.pipeline_cache.get(self.pipeline_id),
+            &[
+                &input.bind_group,
+                &self.settings_bind_group,
+                &self.global_uniform_bind_group,
+            ],
+        );
+    }
+}
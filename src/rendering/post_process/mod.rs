@@ -0,0 +1,145 @@
+pub mod bloom;
+pub mod chain;
+pub mod color_grading;
+pub mod depth_of_field;
+pub mod lens_artifacts;
+pub mod overrides;
+pub mod targets;
+pub mod tonemap;
+pub mod upscale;
+
+pub use chain::PostProcessChain;
+
+use crate::rendering::{
+    common::Resolution,
+    passes::blur_pass::BlurPass,
+    post_process::{
+        color_grading::ColorGrading, lens_artifacts::LensArtifacts, targets::RenderTarget,
+    },
+    shader_loader::PipelineCache,
+};
+
+/// Parameters of the chain that change from frame to frame. Owned by the demo, so that the
+/// timeline can animate them.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PostProcessParams {
+    pub color_grading: ColorGrading,
+    pub lens_artifacts: LensArtifacts,
+}
+
+impl PostProcessParams {
+    pub fn draw_ui(&mut self, ui: &imgui::Ui) {
+        if ui.collapsing_header("Color grading", imgui::TreeNodeFlags::empty()) {
+            self.color_grading.draw_ui(ui);
+        }
+
+        if ui.collapsing_header("Lens artifact intensities", imgui::TreeNodeFlags::empty()) {
+            self.lens_artifacts.draw_ui(ui);
+        }
+    }
+}
+
+pub struct PostProcessContext<'a> {
+    pub encoder: &'a mut wgpu::CommandEncoder,
+    pub pipeline_cache: &'a PipelineCache,
+    pub queue: &'a wgpu::Queue,
+    pub params: &'a PostProcessParams,
+    pub blur_pass: &'a BlurPass,
+}
+
+/// A fullscreen effect that reads the previous result of the chain and writes a new one
+pub trait PostProcessPass {
+    fn name(&self) -> &'static str;
+
+    fn is_enabled(&self) -> bool {
+        true
+    }
+
+    fn resize(&mut self, _size: Resolution) {}
+
+    fn draw_ui(&mut self, _ui: &imgui::Ui) {}
+
+    fn render(
+        &self,
+        context: &mut PostProcessContext,
+        input: &RenderTarget,
+        output: &wgpu::TextureView,
+    );
+}
+
+/// Creates a pipeline that draws a single fullscreen triangle, as used by most post process passes
+pub fn create_fullscreen_pipeline(
+    device: &wgpu::Device,
+    label: &str,
+    layout: &wgpu::PipelineLayout,
+    shader_module: &wgpu::ShaderModule,
+    fragment_entry_point: &str,
+    format: wgpu::TextureFormat,
+    cache: Option<&wgpu::PipelineCache>,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader_module,
+            entry_point: Some("vs_main"),
+            buffers: &[],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader_module,
+            entry_point: Some(fragment_entry_point),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache,
+    })
+}
+
+/// Begins a render pass that overwrites the whole output and draws a fullscreen triangle
+pub fn draw_fullscreen(
+    encoder: &mut wgpu::CommandEncoder,
+    label: &str,
+    output: &wgpu::TextureView,
+    pipeline: &wgpu::RenderPipeline,
+    bind_groups: &[&wgpu::BindGroup],
+) {
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some(label),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: output,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        occlusion_query_set: None,
+        timestamp_writes: None,
+    });
+
+    render_pass.set_pipeline(pipeline);
+
+    for (index, bind_group) in bind_groups.iter().enumerate() {
+        render_pass.set_bind_group(index as u32, *bind_group, &[]);
+    }
+
+    render_pass.draw(0..3, 0..1);
+}
@@ -0,0 +1,60 @@
+use glam::Vec3;
+
+use crate::{
+    events::{EventBus, Flash, SetColorGrading},
+    rendering::post_process::{color_grading::ColorGrading, PostProcessParams},
+};
+
+struct ActiveFlash {
+    color: Vec3,
+    /// Demo time when the flash was fired
+    start_time: f32,
+    duration: f32,
+}
+
+/// Post-processing changes made by events. They're applied on top of whatever the timeline sets,
+/// so they must be reapplied every frame after it.
+#[derive(Default)]
+pub struct PostProcessOverrides {
+    flash: Option<ActiveFlash>,
+    color_grading: Option<ColorGrading>,
+}
+
+impl PostProcessOverrides {
+    pub fn handle_events(&mut self, events: &EventBus, time: f32) {
+        if let Some(flash) = events.read::<Flash>().last() {
+            self.flash = Some(ActiveFlash {
+                color: flash.color,
+                start_time: time,
+                duration: flash.duration,
+            });
+        }
+
+        if let Some(SetColorGrading(grading)) = events.read::<SetColorGrading>().last() {
+            self.color_grading = *grading;
+        }
+    }
+
+    pub fn apply(&mut self, time: f32, params: &mut PostProcessParams) {
+        if let Some(grading) = self.color_grading {
+            params.color_grading = grading;
+        }
+
+        let Some(flash) = &self.flash else {
+            return;
+        };
+
+        // Seeking out of the flash ends it
+        let progress = (time - flash.start_time) / flash.duration.max(f32::EPSILON);
+        if !(0.0..1.0).contains(&progress) {
+            self.flash = None;
+            return;
+        }
+
+        // Lift pulls the darkest parts of the image to its color, so a full lift of white
+        // covers the whole screen
+        let strength = (1.0 - progress).powi(2);
+        let grading = &mut params.color_grading;
+        grading.lift = grading.lift.lerp(flash.color, strength);
+    }
+}
@@ -0,0 +1,196 @@
+use crate::rendering::{
+    common::{PhysicalSizeExt, Resolution},
+    texture::Texture,
+    util::bind_group_builder::BindGroupBuilder,
+};
+
+/// Format used for the scene color and all intermediate post process targets
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// Color texture that can be rendered to, and sampled through its bind group
+pub struct RenderTarget {
+    pub texture: Texture,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl RenderTarget {
+    pub fn new(
+        device: &wgpu::Device,
+        label: &'static str,
+        size: Resolution,
+        sampling: &TargetSampling,
+    ) -> Self {
+        Self::with_format(device, label, size, HDR_FORMAT, sampling)
+    }
+
+    pub fn with_format(
+        device: &wgpu::Device,
+        label: &'static str,
+        size: Resolution,
+        format: wgpu::TextureFormat,
+        sampling: &TargetSampling,
+    ) -> Self {
+        Self::with_usage(
+            device,
+            label,
+            size,
+            format,
+            wgpu::TextureUsages::empty(),
+            sampling,
+        )
+    }
+
+    /// Can also be written by compute passes, e.g. the blur pass
+    pub fn storage(
+        device: &wgpu::Device,
+        label: &'static str,
+        size: Resolution,
+        sampling: &TargetSampling,
+    ) -> Self {
+        Self::with_usage(
+            device,
+            label,
+            size,
+            HDR_FORMAT,
+            wgpu::TextureUsages::STORAGE_BINDING,
+            sampling,
+        )
+    }
+
+    /// Render targets can always be rendered to and sampled, `usage` adds to those
+    fn with_usage(
+        device: &wgpu::Device,
+        label: &'static str,
+        size: Resolution,
+        format: wgpu::TextureFormat,
+        usage: wgpu::TextureUsages,
+        sampling: &TargetSampling,
+    ) -> Self {
+        let descriptor = wgpu::TextureDescriptor {
+            label: Some(label),
+            size: size.to_extent3d(),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | usage,
+            view_formats: &[],
+        };
+
+        let texture = device.create_texture(&descriptor);
+        let texture = Texture::from_wgpu_texture(device, descriptor, texture, None);
+
+        let bind_group = BindGroupBuilder::new(label, wgpu::ShaderStages::FRAGMENT)
+            .texture_2d(
+                0,
+                "Render target texture",
+                wgpu::TextureSampleType::Float { filterable: true },
+                wgpu::BindingResource::TextureView(&texture.view),
+            )
+            .sampler(
+                1,
+                "Render target sampler",
+                wgpu::SamplerBindingType::Filtering,
+                wgpu::BindingResource::Sampler(&sampling.sampler),
+            )
+            .build_with_layout(device, &sampling.bind_group_layout);
+
+        Self {
+            texture,
+            bind_group,
+        }
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.texture.view
+    }
+}
+
+/// Bind group layout and sampler shared by all render targets
+pub struct TargetSampling {
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub sampler: wgpu::Sampler,
+}
+
+impl TargetSampling {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Render target bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Render target sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Self {
+            bind_group_layout,
+            sampler,
+        }
+    }
+}
+
+/// Two full resolution HDR targets. Each post process pass reads one and writes the other.
+pub struct PingPongTargets {
+    device: wgpu::Device,
+    pub sampling: TargetSampling,
+    targets: [RenderTarget; 2],
+}
+
+impl PingPongTargets {
+    pub fn new(device: &wgpu::Device, size: Resolution) -> Self {
+        let sampling = TargetSampling::new(device);
+        let targets = Self::create_targets(device, size, &sampling);
+
+        Self {
+            device: device.clone(),
+            sampling,
+            targets,
+        }
+    }
+
+    fn create_targets(
+        device: &wgpu::Device,
+        size: Resolution,
+        sampling: &TargetSampling,
+    ) -> [RenderTarget; 2] {
+        [
+            RenderTarget::new(device, "Post process target A", size, sampling),
+            RenderTarget::new(device, "Post process target B", size, sampling),
+        ]
+    }
+
+    pub fn resize(&mut self, size: Resolution) {
+        self.targets = Self::create_targets(&self.device, size, &self.sampling);
+    }
+
+    pub fn get(&self, index: usize) -> &RenderTarget {
+        &self.targets[index]
+    }
+}
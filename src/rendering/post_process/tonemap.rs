@@ -0,0 +1,157 @@
+use bytemuck::{Pod, Zeroable};
+use glam::Vec4;
+
+use crate::rendering::{
+    passes::render_pass_context::RenderPassCreationContext,
+    post_process::{
+        color_grading::ColorLut, create_fullscreen_pipeline, draw_fullscreen,
+        targets::RenderTarget, PostProcessContext,
+    },
+    shader_loader::{RenderPipelineId, ShaderDefinition},
+    util::bind_group_builder::BindGroupBuilder,
+};
+
+const SHADER_DEF: ShaderDefinition = ShaderDefinition {
+    name: "Tonemap shader",
+    path: "post/tonemap.wgsl",
+    defines: &[],
+};
+
+/// This should match TonemapSettings in post/tonemap.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct TonemapSettings {
+    exposure: f32,
+    tonemapper: u32,
+    contrast: f32,
+    saturation: f32,
+    /// W unused
+    lift: Vec4,
+    /// W unused
+    gamma: Vec4,
+    /// W = LUT strength
+    gain_lut_strength: Vec4,
+}
+
+/// Maps the final HDR result to the swapchain, using the tonemapper from `RenderConfig`.
+/// Color grading is applied here too: contrast and saturation on the HDR color, then lift, gamma,
+/// gain and the LUT on the tonemapped color.
+pub struct TonemapPass {
+    pipeline_id: RenderPipelineId,
+    exposure: f32,
+    tonemapper: u32,
+    settings_buffer: wgpu::Buffer,
+    settings_bind_group: wgpu::BindGroup,
+}
+
+impl TonemapPass {
+    pub fn new(
+        context: &mut RenderPassCreationContext,
+        queue: &wgpu::Queue,
+        target_layout: &wgpu::BindGroupLayout,
+        lut: &ColorLut,
+    ) -> Self {
+        let device = &context.shared.device;
+        let common = context.shared.common.clone();
+        let config = context.shared.config;
+
+        let settings_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Tonemap settings buffer"),
+            size: std::mem::size_of::<TonemapSettings>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let lut_texture = lut.create_texture(device, queue);
+        let lut_view = lut_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        // Clamped, so that the edges of the cube aren't blended with the opposite side
+        let lut_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Color grading LUT sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let (settings_bind_group_layout, settings_bind_group) =
+            BindGroupBuilder::new("Tonemap settings", wgpu::ShaderStages::FRAGMENT)
+                .uniform(
+                    0,
+                    "Tonemap settings buffer",
+                    settings_buffer.as_entire_binding(),
+                )
+                .texture_3d(
+                    1,
+                    "Color grading LUT",
+                    wgpu::TextureSampleType::Float { filterable: true },
+                    wgpu::BindingResource::TextureView(&lut_view),
+                )
+                .sampler(
+                    2,
+                    "Color grading LUT sampler",
+                    wgpu::SamplerBindingType::Filtering,
+                    wgpu::BindingResource::Sampler(&lut_sampler),
+                )
+                .build(device);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Tonemap pipeline layout"),
+            bind_group_layouts: &[target_layout, &settings_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline_id = context.cache_builder.add_shader(
+            SHADER_DEF,
+            Box::new(move |device, shader_module, cache| {
+                Ok(create_fullscreen_pipeline(
+                    device,
+                    "Tonemap pipeline",
+                    &pipeline_layout,
+                    &shader_module,
+                    "fs_main",
+                    common.output_surface_config.read().unwrap().format,
+                    cache,
+                ))
+            }),
+        );
+
+        Self {
+            pipeline_id,
+            exposure: config.exposure,
+            tonemapper: config.tonemapper.shader_index(),
+            settings_buffer,
+            settings_bind_group,
+        }
+    }
+
+    pub fn render(
+        &self,
+        context: &mut PostProcessContext,
+        input: &RenderTarget,
+        output: &wgpu::TextureView,
+    ) {
+        let color_grading = &context.params.color_grading;
+        let settings = TonemapSettings {
+            exposure: self.exposure * color_grading.exposure.exp2(),
+            tonemapper: self.tonemapper,
+            contrast: color_grading.contrast,
+            saturation: color_grading.saturation,
+            lift: color_grading.lift.extend(0.0),
+            gamma: color_grading.gamma.extend(0.0),
+            gain_lut_strength: color_grading.gain.extend(color_grading.lut_strength),
+        };
+        context
+            .queue
+            .write_buffer(&self.settings_buffer, 0, bytemuck::cast_slice(&[settings]));
+
+        draw_fullscreen(
+            context.encoder,
+            "Tonemap",
+            output,
+            context.pipeline_cache.get(self.pipeline_id),
+            &[&input.bind_group, &self.settings_bind_group],
+        );
+    }
+}
@@ -0,0 +1,219 @@
+use bytemuck::{Pod, Zeroable};
+
+use crate::rendering::{
+    common::Resolution,
+    passes::render_pass_context::RenderPassCreationContext,
+    post_process::{
+        create_fullscreen_pipeline, draw_fullscreen,
+        targets::{RenderTarget, TargetSampling},
+        PostProcessContext,
+    },
+    shader_loader::{RenderPipelineId, ShaderDefValue, ShaderDefinition},
+    util::bind_group_builder::BindGroupBuilder,
+};
+
+const SHADER_DEF: ShaderDefinition = ShaderDefinition {
+    name: "Upscale shader",
+    path: "post/upscale.wgsl",
+    defines: &[],
+};
+
+const SHARPENED_SHADER_DEF: ShaderDefinition = SHADER_DEF.variant(
+    "Sharpened upscale shader",
+    &[("SHARPENED", ShaderDefValue::Bool(true))],
+);
+
+const MIN_SCALE: f32 = 0.5;
+const MAX_SCALE: f32 = 2.0;
+
+/// Resolution the scene is rendered at for the given output size
+pub fn scale_resolution(output_size: Resolution, scale: f32) -> Resolution {
+    let scale = scale.clamp(MIN_SCALE, MAX_SCALE);
+    let scale_dimension = |value: u32| ((value as f32 * scale).round() as u32).max(1);
+    Resolution::new(
+        scale_dimension(output_size.width),
+        scale_dimension(output_size.height),
+    )
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum UpscaleFilter {
+    Bilinear,
+    /// Catmull-Rom followed by contrast adaptive sharpening, similar to FSR 1
+    #[default]
+    Sharpened,
+}
+
+/// This should match UpscaleSettings in post/upscale.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct UpscaleSettings {
+    sharpness: f32,
+    _padding: [f32; 3],
+}
+
+/// The scene can be rendered at a different resolution than the output, which is then scaled
+/// to the output after tonemapping and before the UI is drawn. When the scales match, the
+/// tonemap pass writes to the output directly and this pass is skipped.
+pub struct UpscalePass {
+    device: wgpu::Device,
+    format: wgpu::TextureFormat,
+    sampling: TargetSampling,
+    /// Tonemapped scene at the internal resolution
+    input: Option<RenderTarget>,
+
+    scale: f32,
+    filter: UpscaleFilter,
+    sharpness: f32,
+
+    settings_buffer: wgpu::Buffer,
+    settings_bind_group: wgpu::BindGroup,
+    bilinear_pipeline_id: RenderPipelineId,
+    sharpened_pipeline_id: RenderPipelineId,
+}
+
+impl UpscalePass {
+    pub fn new(
+        context: &mut RenderPassCreationContext,
+        output_size: Resolution,
+        scale: f32,
+    ) -> Self {
+        let device = &context.shared.device;
+        let format = context
+            .shared
+            .common
+            .output_surface_config
+            .read()
+            .unwrap()
+            .format;
+        let sampling = TargetSampling::new(device);
+
+        let settings_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Upscale settings buffer"),
+            size: std::mem::size_of::<UpscaleSettings>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let (settings_bind_group_layout, settings_bind_group) =
+            BindGroupBuilder::new("Upscale settings", wgpu::ShaderStages::FRAGMENT)
+                .uniform(
+                    0,
+                    "Upscale settings buffer",
+                    settings_buffer.as_entire_binding(),
+                )
+                .build(device);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Upscale pipeline layout"),
+            bind_group_layouts: &[&sampling.bind_group_layout, &settings_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        // The filters are variants of the same shader
+        let mut add_pipeline = |shader_def: ShaderDefinition, label: &'static str| {
+            let pipeline_layout = pipeline_layout.clone();
+            context.cache_builder.add_shader(
+                shader_def,
+                Box::new(move |device, shader_module, cache| {
+                    Ok(create_fullscreen_pipeline(
+                        device,
+                        label,
+                        &pipeline_layout,
+                        &shader_module,
+                        "fs_main",
+                        format,
+                        cache,
+                    ))
+                }),
+            )
+        };
+        let bilinear_pipeline_id = add_pipeline(SHADER_DEF, "Bilinear upscale pipeline");
+        let sharpened_pipeline_id =
+            add_pipeline(SHARPENED_SHADER_DEF, "Sharpened upscale pipeline");
+
+        let mut pass = Self {
+            device: device.clone(),
+            format,
+            sampling,
+            input: None,
+
+            scale: scale.clamp(MIN_SCALE, MAX_SCALE),
+            filter: UpscaleFilter::default(),
+            sharpness: 0.5,
+
+            settings_buffer,
+            settings_bind_group,
+            bilinear_pipeline_id,
+            sharpened_pipeline_id,
+        };
+        pass.resize(output_size);
+        pass
+    }
+
+    pub fn internal_size(&self, output_size: Resolution) -> Resolution {
+        scale_resolution(output_size, self.scale)
+    }
+
+    pub fn resize(&mut self, output_size: Resolution) {
+        let internal_size = self.internal_size(output_size);
+        self.input = (internal_size != output_size).then(|| {
+            RenderTarget::with_format(
+                &self.device,
+                "Upscale input",
+                internal_size,
+                self.format,
+                &self.sampling,
+            )
+        });
+    }
+
+    /// Where the tonemap pass should write to, or None if it can write to the output directly
+    pub fn input(&self) -> Option<&RenderTarget> {
+        self.input.as_ref()
+    }
+
+    /// Returns true if the scale has changed, in which case everything rendered at the internal
+    /// resolution has to be resized
+    pub fn draw_ui(&mut self, ui: &imgui::Ui) -> bool {
+        let mut scale_changed = false;
+
+        ui.window("Resolution scaling")
+            .size([300.0, 120.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                scale_changed = ui.slider("Scale", MIN_SCALE, MAX_SCALE, &mut self.scale);
+                ui.radio_button("Bilinear", &mut self.filter, UpscaleFilter::Bilinear);
+                ui.radio_button("Sharpened", &mut self.filter, UpscaleFilter::Sharpened);
+
+                let _disabled = ui.begin_disabled(self.filter != UpscaleFilter::Sharpened);
+                ui.slider("Sharpness", 0.0, 1.0, &mut self.sharpness);
+            });
+
+        scale_changed
+    }
+
+    pub fn render(&self, context: &mut PostProcessContext, output: &wgpu::TextureView) {
+        let Some(input) = &self.input else {
+            return;
+        };
+
+        let settings = UpscaleSettings {
+            sharpness: self.sharpness,
+            _padding: [0.0; 3],
+        };
+        context
+            .queue
+            .write_buffer(&self.settings_buffer, 0, bytemuck::cast_slice(&[settings]));
+
+        draw_fullscreen(
+            context.encoder,
+            "Upscale",
+            output,
+            context.pipeline_cache.get(match self.filter {
+                UpscaleFilter::Bilinear => self.bilinear_pipeline_id,
+                UpscaleFilter::Sharpened => self.sharpened_pipeline_id,
+            }),
+            &[&input.bind_group, &self.settings_bind_group],
+        );
+    }
+}
@@ -0,0 +1,694 @@
+use std::{collections::HashMap, f32::consts::FRAC_PI_2, sync::Arc};
+
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec2, Vec3, Vec4};
+use wgpu::{PipelineCompilationOptions, RenderPassDescriptor, RenderPipelineDescriptor};
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    asset_pipeline::mesh_baker::VertexFormat,
+    camera::Camera,
+    rendering::{
+        environment_map::{
+            self, EnvironmentMap, ENVIRONMENT_FORMAT, IRRADIANCE_SHADER, PREFILTER_SHADER,
+            WORKGROUP_SIZE,
+        },
+        instancing::{Drawable, DrawableBuffers},
+        light_buffer::LightBuffer,
+        mesh_buffers::MeshBuffers,
+        passes::{render_pass_context::RenderPassCreationContext, shadow_pass::ShadowPass},
+        render_camera::CameraUniformData,
+        render_material_manager::RenderMaterialManager,
+        render_model::{render_model_vbl, MODEL_PRIMITIVE_STATE, QUANTIZED_VERTEX_DEFINES},
+        shader_loader::{ComputePipelineId, PipelineCache, RenderPipelineId, ShaderDefinition},
+        texture::DepthTexture,
+        util::bind_group_builder::BindGroupBuilder,
+    },
+    scene_graph::{
+        layers::{Layer, Layers},
+        object3d::ObjectId,
+        scene::Scene,
+    },
+};
+
+pub const MAX_REFLECTION_PROBES: usize = 16;
+
+const CAPTURE_SIZE: u32 = 128;
+const CAPTURE_NEAR: f32 = 0.05;
+const IRRADIANCE_SIZE: u32 = 16;
+/// Roughness goes from 0 at mip 0 to 1 at the last mip
+const PREFILTERED_MIP_COUNT: u32 = 5;
+
+/// Forward shades the surroundings with the lighting of the transparent pass
+const CAPTURE_SHADER_DEF: ShaderDefinition = ShaderDefinition {
+    name: "Reflection probe capture shader",
+    path: "transparent.wgsl",
+    defines: &[],
+};
+
+const QUANTIZED_CAPTURE_SHADER_DEF: ShaderDefinition = CAPTURE_SHADER_DEF.variant(
+    "Reflection probe capture shader (quantized vertices)",
+    QUANTIZED_VERTEX_DEFINES,
+);
+
+const SKY_SHADER_DEF: ShaderDefinition = ShaderDefinition {
+    name: "Reflection probe sky shader",
+    path: "skybox.wgsl",
+    defines: &[],
+};
+
+/// Direction and up vector of each cube face, in the order of shared/cubemap.wgsl
+const CUBE_FACES: [(Vec3, Vec3); 6] = [
+    (Vec3::X, Vec3::Y),
+    (Vec3::NEG_X, Vec3::Y),
+    (Vec3::Y, Vec3::NEG_Z),
+    (Vec3::NEG_Y, Vec3::Z),
+    (Vec3::Z, Vec3::Y),
+    (Vec3::NEG_Z, Vec3::Y),
+];
+
+/// This should match ReflectionProbe in shared/reflection_probe.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct GpuReflectionProbe {
+    world_to_box: Mat4,
+    box_to_world: Mat4,
+    /// W: blend distance
+    extents_blend: Vec4,
+    cube: u32,
+    intensity: f32,
+    _padding: [u32; 2],
+}
+
+/// Header of the probe storage buffer, followed by an array of GpuReflectionProbes
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct GpuReflectionProbeHeader {
+    count: u32,
+    _padding: [u32; 3],
+}
+
+struct ProbeState {
+    /// Index of the probe's cubemap in the cubemap arrays
+    cube: u32,
+    /// Generation, far plane and world matrix of the last capture. None to capture the probe again.
+    captured: Option<(u32, f32, Mat4)>,
+    /// The cubemap has been captured at least once, and can be sampled
+    ready: bool,
+}
+
+/// Captures the reflection probes of the scene into cubemap arrays, which the lighting pass
+/// samples instead of the environment map inside the probes' boxes. A probe is captured when
+/// it's added, moved or its generation changes, one probe per frame. The captures are forward
+/// shaded from every drawable and filtered like the environment map.
+pub struct ReflectionProbes {
+    probes: HashMap<ObjectId, ProbeState>,
+    /// Cubemap that is being captured this frame
+    pending_capture: Option<u32>,
+
+    mesh_buffers: Arc<MeshBuffers>,
+    drawable_buffers: Arc<DrawableBuffers>,
+    light_buffer: Arc<LightBuffer>,
+    shadow_bind_group: wgpu::BindGroup,
+    environment_bind_group: wgpu::BindGroup,
+
+    capture_pipeline_id: RenderPipelineId,
+    sky_pipeline_id: RenderPipelineId,
+    /// One per cube face
+    face_camera_buffers: Vec<wgpu::Buffer>,
+    face_camera_bind_groups: Vec<wgpu::BindGroup>,
+    face_views: Vec<wgpu::TextureView>,
+    depth: DepthTexture,
+
+    irradiance_pipeline_id: ComputePipelineId,
+    /// One per cubemap
+    irradiance_bind_groups: Vec<wgpu::BindGroup>,
+    prefilter_pipeline_id: ComputePipelineId,
+    /// One per mip of each cubemap
+    prefilter_bind_groups: Vec<Vec<wgpu::BindGroup>>,
+
+    probe_buffer: wgpu::Buffer,
+    sampling_bind_group_layout: wgpu::BindGroupLayout,
+    sampling_bind_group: wgpu::BindGroup,
+}
+
+impl ReflectionProbes {
+    pub fn new(
+        context: &mut RenderPassCreationContext,
+        shadow_pass: &ShadowPass,
+        environment_map: &EnvironmentMap,
+    ) -> Self {
+        let device = &context.shared.device;
+        let cube_count = MAX_REFLECTION_PROBES as u32;
+
+        let capture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Reflection probe capture"),
+            size: wgpu::Extent3d {
+                width: CAPTURE_SIZE,
+                height: CAPTURE_SIZE,
+                depth_or_array_layers: 6,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: ENVIRONMENT_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let capture_view = environment_map::cube_view(&capture);
+        let face_views = (0..6)
+            .map(|face| {
+                capture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("Reflection probe capture face"),
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: face,
+                    array_layer_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect::<Vec<_>>();
+        let depth = DepthTexture::new(
+            device,
+            PhysicalSize::new(CAPTURE_SIZE, CAPTURE_SIZE),
+            "Reflection probe capture depth",
+        );
+
+        let face_camera_buffers = (0..6)
+            .map(|_| {
+                device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Reflection probe camera uniform buffer"),
+                    size: std::mem::size_of::<CameraUniformData>() as u64,
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                })
+            })
+            .collect::<Vec<_>>();
+        let camera_bind_group_layout =
+            camera_bind_group_builder(&face_camera_buffers[0]).build_layout(device);
+        let face_camera_bind_groups = face_camera_buffers
+            .iter()
+            .map(|buffer| {
+                camera_bind_group_builder(buffer)
+                    .build_with_layout(device, &camera_bind_group_layout)
+            })
+            .collect();
+
+        let capture_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Reflection probe capture pipeline layout"),
+                bind_group_layouts: &[
+                    &camera_bind_group_layout,
+                    context
+                        .shared
+                        .drawable_buffers
+                        .all_drawables
+                        .bind_group_layout(),
+                    context.material_manager.bind_group_layout(),
+                    context.shared.light_buffer.bind_group_layout(),
+                    shadow_pass.sampling_bind_group_layout(),
+                    environment_map.sampling_bind_group_layout(),
+                ],
+                push_constant_ranges: &[],
+            });
+
+        let vertex_format = context.shared.mesh_buffers.vertex_format();
+        let capture_shader_def = match vertex_format {
+            VertexFormat::Full => CAPTURE_SHADER_DEF,
+            VertexFormat::Quantized => QUANTIZED_CAPTURE_SHADER_DEF,
+        };
+
+        let capture_pipeline_id = context.cache_builder.add_shader(
+            capture_shader_def,
+            Box::new(move |device, shader_module, cache| {
+                let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+                    label: Some("Reflection probe capture render pipeline"),
+                    layout: Some(&capture_pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader_module,
+                        entry_point: Some("vs_main"),
+                        buffers: &[render_model_vbl(vertex_format)],
+                        compilation_options: PipelineCompilationOptions::default(),
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader_module,
+                        entry_point: Some("fs_capture"),
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format: ENVIRONMENT_FORMAT,
+                            blend: Some(wgpu::BlendState::REPLACE),
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                        compilation_options: PipelineCompilationOptions::default(),
+                    }),
+                    primitive: MODEL_PRIMITIVE_STATE,
+                    depth_stencil: Some(wgpu::DepthStencilState {
+                        format: DepthTexture::DEPTH_FORMAT,
+                        depth_write_enabled: true,
+                        depth_compare: wgpu::CompareFunction::Less,
+                        stencil: wgpu::StencilState::default(),
+                        bias: wgpu::DepthBiasState::default(),
+                    }),
+                    multisample: wgpu::MultisampleState::default(),
+                    multiview: None,
+                    cache,
+                });
+
+                Ok(pipeline)
+            }),
+        );
+
+        let sky_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Reflection probe sky pipeline layout"),
+            bind_group_layouts: &[
+                &camera_bind_group_layout,
+                environment_map.sampling_bind_group_layout(),
+            ],
+            push_constant_ranges: &[],
+        });
+
+        // Drawn first, behind everything that the capture pipeline draws
+        let sky_pipeline_id = context.cache_builder.add_shader(
+            SKY_SHADER_DEF,
+            Box::new(move |device, shader_module, cache| {
+                let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+                    label: Some("Reflection probe sky render pipeline"),
+                    layout: Some(&sky_pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader_module,
+                        entry_point: Some("vs_main"),
+                        buffers: &[],
+                        compilation_options: PipelineCompilationOptions::default(),
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader_module,
+                        entry_point: Some("fs_main"),
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format: ENVIRONMENT_FORMAT,
+                            blend: Some(wgpu::BlendState::REPLACE),
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                        compilation_options: PipelineCompilationOptions::default(),
+                    }),
+                    primitive: wgpu::PrimitiveState::default(),
+                    depth_stencil: Some(wgpu::DepthStencilState {
+                        format: DepthTexture::DEPTH_FORMAT,
+                        depth_write_enabled: false,
+                        depth_compare: wgpu::CompareFunction::Always,
+                        stencil: wgpu::StencilState::default(),
+                        bias: wgpu::DepthBiasState::default(),
+                    }),
+                    multisample: wgpu::MultisampleState::default(),
+                    multiview: None,
+                    cache,
+                });
+
+                Ok(pipeline)
+            }),
+        );
+
+        let sampler = environment_map::create_sampler(device);
+
+        let irradiance_maps = environment_map::create_cubemap_array(
+            device,
+            "Reflection probe irradiance maps",
+            IRRADIANCE_SIZE,
+            1,
+            cube_count,
+        );
+        let irradiance_outputs = (0..cube_count)
+            .map(|cube| environment_map::cube_storage_view(&irradiance_maps, cube, 0))
+            .collect::<Vec<_>>();
+
+        let irradiance_bind_group_layout = environment_map::irradiance_bind_group_builder(
+            &capture_view,
+            &sampler,
+            &irradiance_outputs[0],
+        )
+        .build_layout(device);
+        let irradiance_bind_groups = irradiance_outputs
+            .iter()
+            .map(|output_view| {
+                environment_map::irradiance_bind_group_builder(&capture_view, &sampler, output_view)
+                    .build_with_layout(device, &irradiance_bind_group_layout)
+            })
+            .collect();
+
+        let irradiance_pipeline_id = context.cache_builder.add_shader(
+            IRRADIANCE_SHADER,
+            environment_map::create_pipeline(
+                "Reflection probe irradiance compute pipeline",
+                irradiance_bind_group_layout,
+            ),
+        );
+
+        let prefiltered_maps = environment_map::create_cubemap_array(
+            device,
+            "Reflection probe prefiltered maps",
+            CAPTURE_SIZE,
+            PREFILTERED_MIP_COUNT,
+            cube_count,
+        );
+        let prefilter_settings_buffers = (0..PREFILTERED_MIP_COUNT)
+            .map(|mip| {
+                environment_map::create_prefilter_settings_buffer(
+                    device,
+                    mip,
+                    PREFILTERED_MIP_COUNT,
+                )
+            })
+            .collect::<Vec<_>>();
+        let prefilter_outputs = (0..cube_count)
+            .map(|cube| {
+                (0..PREFILTERED_MIP_COUNT)
+                    .map(|mip| environment_map::cube_storage_view(&prefiltered_maps, cube, mip))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let prefilter_bind_group_layout = environment_map::prefilter_bind_group_builder(
+            &capture_view,
+            &sampler,
+            &prefilter_settings_buffers[0],
+            &prefilter_outputs[0][0],
+        )
+        .build_layout(device);
+        let prefilter_bind_groups = prefilter_outputs
+            .iter()
+            .map(|mip_views| {
+                prefilter_settings_buffers
+                    .iter()
+                    .zip(mip_views)
+                    .map(|(settings_buffer, output_view)| {
+                        environment_map::prefilter_bind_group_builder(
+                            &capture_view,
+                            &sampler,
+                            settings_buffer,
+                            output_view,
+                        )
+                        .build_with_layout(device, &prefilter_bind_group_layout)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let prefilter_pipeline_id = context.cache_builder.add_shader(
+            PREFILTER_SHADER,
+            environment_map::create_pipeline(
+                "Reflection probe prefilter compute pipeline",
+                prefilter_bind_group_layout,
+            ),
+        );
+
+        let probe_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Reflection probe storage buffer"),
+            size: (std::mem::size_of::<GpuReflectionProbeHeader>()
+                + std::mem::size_of::<GpuReflectionProbe>() * MAX_REFLECTION_PROBES)
+                as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        // Sampled with the sampler of the environment map
+        let (sampling_bind_group_layout, sampling_bind_group) =
+            BindGroupBuilder::new("Reflection probe sampling", wgpu::ShaderStages::FRAGMENT)
+                .storage_r(
+                    0,
+                    "Reflection probe storage buffer",
+                    probe_buffer.as_entire_binding(),
+                )
+                .texture_cube_array(
+                    1,
+                    "Reflection probe irradiance maps",
+                    wgpu::TextureSampleType::Float { filterable: true },
+                    wgpu::BindingResource::TextureView(&environment_map::cube_array_view(
+                        &irradiance_maps,
+                    )),
+                )
+                .texture_cube_array(
+                    2,
+                    "Reflection probe prefiltered maps",
+                    wgpu::TextureSampleType::Float { filterable: true },
+                    wgpu::BindingResource::TextureView(&environment_map::cube_array_view(
+                        &prefiltered_maps,
+                    )),
+                )
+                .build(device);
+
+        Self {
+            probes: HashMap::new(),
+            pending_capture: None,
+
+            mesh_buffers: context.shared.mesh_buffers.clone(),
+            drawable_buffers: context.shared.drawable_buffers.clone(),
+            light_buffer: context.shared.light_buffer.clone(),
+            shadow_bind_group: shadow_pass.sampling_bind_group().clone(),
+            environment_bind_group: environment_map.sampling_bind_group().clone(),
+
+            capture_pipeline_id,
+            sky_pipeline_id,
+            face_camera_buffers,
+            face_camera_bind_groups,
+            face_views,
+            depth,
+
+            irradiance_pipeline_id,
+            irradiance_bind_groups,
+            prefilter_pipeline_id,
+            prefilter_bind_groups,
+
+            probe_buffer,
+            sampling_bind_group_layout,
+            sampling_bind_group,
+        }
+    }
+
+    /// Assigns cubemaps to the probes of the scene, picks the probe to capture this frame and
+    /// uploads the probes that have been captured
+    pub fn update_from_scene(&mut self, scene: &Scene, queue: &wgpu::Queue) {
+        let scene_probes = scene
+            .objects
+            .iter()
+            .filter(|(_, object)| object.enabled)
+            .filter_map(|(id, object)| {
+                let probe = object.reflection_probe.as_ref()?;
+                Some((id, probe, *object.transform.get_world_matrix()))
+            })
+            .take(MAX_REFLECTION_PROBES)
+            .collect::<Vec<_>>();
+
+        // Cubemaps of removed probes are reused by new ones
+        self.probes
+            .retain(|id, _| scene_probes.iter().any(|(probe_id, _, _)| probe_id == id));
+        for (id, _, _) in &scene_probes {
+            if !self.probes.contains_key(id) {
+                let cube = (0..MAX_REFLECTION_PROBES as u32)
+                    .find(|cube| self.probes.values().all(|state| state.cube != *cube))
+                    .expect("There's a free cubemap for every probe");
+                self.probes.insert(
+                    *id,
+                    ProbeState {
+                        cube,
+                        captured: None,
+                        ready: false,
+                    },
+                );
+            }
+        }
+
+        self.pending_capture = None;
+        for (id, probe, world_matrix) in &scene_probes {
+            let state = self.probes.get_mut(id).unwrap();
+            let current = (probe.capture_generation, probe.far, *world_matrix);
+            if state.captured == Some(current) {
+                continue;
+            }
+
+            let position = world_matrix.w_axis.truncate();
+            for ((direction, up), buffer) in CUBE_FACES.iter().zip(&self.face_camera_buffers) {
+                let camera = Camera {
+                    up: *up,
+                    fov_y_radians: FRAC_PI_2,
+                    near: CAPTURE_NEAR,
+                    far: probe.far.max(CAPTURE_NEAR * 2.0),
+                    ..Camera::new(position, position + *direction)
+                };
+                let view_proj = camera.get_vp_matrix(Vec2::splat(CAPTURE_SIZE as f32));
+                let uniform = CameraUniformData::new(&camera, view_proj, view_proj, Vec4::ZERO);
+                queue.write_buffer(buffer, 0, bytemuck::cast_slice(&[uniform]));
+            }
+
+            state.captured = Some(current);
+            state.ready = true;
+            self.pending_capture = Some(state.cube);
+            break;
+        }
+
+        let mut probes = scene_probes
+            .iter()
+            .filter(|(id, _, _)| self.probes[id].ready)
+            .map(|(id, probe, world_matrix)| {
+                let (box_to_world, extents) = probe.world_box(*world_matrix);
+                GpuReflectionProbe {
+                    world_to_box: box_to_world.inverse(),
+                    box_to_world,
+                    extents_blend: extents.extend(probe.blend_distance),
+                    cube: self.probes[id].cube,
+                    intensity: probe.intensity,
+                    _padding: [0; 2],
+                }
+            })
+            .collect::<Vec<_>>();
+        // Smaller boxes first, so that a probe inside another one overrides it
+        probes.sort_by(|a, b| {
+            let volume =
+                |probe: &GpuReflectionProbe| probe.extents_blend.truncate().element_product();
+            volume(a).total_cmp(&volume(b))
+        });
+
+        let header = GpuReflectionProbeHeader {
+            count: probes.len() as u32,
+            _padding: [0; 3],
+        };
+
+        queue.write_buffer(&self.probe_buffer, 0, bytemuck::cast_slice(&[header]));
+
+        if !probes.is_empty() {
+            queue.write_buffer(
+                &self.probe_buffer,
+                std::mem::size_of::<GpuReflectionProbeHeader>() as u64,
+                bytemuck::cast_slice(&probes),
+            );
+        }
+    }
+
+    pub fn has_pending_capture(&self) -> bool {
+        self.pending_capture.is_some()
+    }
+
+    /// Captures the probe picked by `update_from_scene` and filters the capture into its
+    /// cubemaps. `drawables` are the opaque drawables by slot.
+    pub fn capture(
+        &self,
+        drawables: &[Drawable],
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline_cache: &PipelineCache,
+        material_manager: &mut RenderMaterialManager,
+    ) {
+        let Some(cube) = self.pending_capture else {
+            return;
+        };
+
+        let vertices = self.mesh_buffers.vertices();
+        let indices = self.mesh_buffers.indices();
+        let drawable_bind_group = self.drawable_buffers.all_drawables.bind_group();
+        let material_bind_group = material_manager.bind_group();
+
+        for (face_view, camera_bind_group) in
+            self.face_views.iter().zip(&self.face_camera_bind_groups)
+        {
+            let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("Reflection probe capture pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: face_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: self.depth.view(),
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Discard,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+
+            render_pass.set_pipeline(pipeline_cache.get(self.sky_pipeline_id));
+            render_pass.set_bind_group(0, camera_bind_group, &[]);
+            render_pass.set_bind_group(1, &self.environment_bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+
+            render_pass.set_pipeline(pipeline_cache.get(self.capture_pipeline_id));
+            render_pass.set_bind_group(1, &drawable_bind_group, &[]);
+            render_pass.set_bind_group(2, material_bind_group, &[]);
+            render_pass.set_bind_group(3, self.light_buffer.bind_group(), &[]);
+            render_pass.set_bind_group(4, &self.shadow_bind_group, &[]);
+            render_pass.set_bind_group(5, &self.environment_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, vertices.slice(..));
+            render_pass.set_index_buffer(indices.slice(..), wgpu::IndexFormat::Uint32);
+
+            // Captures are rare, so the drawables are drawn one at a time without culling
+            let capture_layers = Layers::from_layer(Layer::Reflection).union(Layers::CAMERA);
+            for (slot, drawable) in drawables.iter().enumerate() {
+                if drawable.primitive_index == Drawable::NO_PRIMITIVE
+                    || drawable.layers & capture_layers.bits() == 0
+                {
+                    continue;
+                }
+
+                let Some(mesh) = self.mesh_buffers.mesh_info(drawable.primitive_index) else {
+                    continue;
+                };
+
+                let slot = slot as u32;
+                render_pass.draw_indexed(
+                    mesh.first_index..mesh.first_index + mesh.index_count,
+                    mesh.vertex_offset as i32,
+                    slot..slot + 1,
+                );
+            }
+        }
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Reflection probe filtering compute pass"),
+            timestamp_writes: None,
+        });
+
+        let dispatch_cube = |compute_pass: &mut wgpu::ComputePass, size: u32| {
+            let workgroups = size.div_ceil(WORKGROUP_SIZE);
+            compute_pass.dispatch_workgroups(workgroups, workgroups, 6);
+        };
+
+        compute_pass.set_pipeline(pipeline_cache.get(self.irradiance_pipeline_id));
+        compute_pass.set_bind_group(0, &self.irradiance_bind_groups[cube as usize], &[]);
+        dispatch_cube(&mut compute_pass, IRRADIANCE_SIZE);
+
+        compute_pass.set_pipeline(pipeline_cache.get(self.prefilter_pipeline_id));
+        for (mip, bind_group) in self.prefilter_bind_groups[cube as usize].iter().enumerate() {
+            compute_pass.set_bind_group(0, bind_group, &[]);
+            dispatch_cube(&mut compute_pass, (CAPTURE_SIZE >> mip).max(1));
+        }
+    }
+
+    /// Captures every probe again over the next frames, e.g. after the meshes have changed. The
+    /// previous captures are used until then.
+    pub fn invalidate(&mut self) {
+        for state in self.probes.values_mut() {
+            state.captured = None;
+        }
+    }
+
+    pub fn sampling_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.sampling_bind_group_layout
+    }
+
+    pub fn sampling_bind_group(&self) -> &wgpu::BindGroup {
+        &self.sampling_bind_group
+    }
+}
+
+fn camera_bind_group_builder(buffer: &wgpu::Buffer) -> BindGroupBuilder<'_> {
+    BindGroupBuilder::new(
+        "Reflection probe camera",
+        wgpu::ShaderStages::VERTEX_FRAGMENT,
+    )
+    .uniform(
+        0,
+        "Reflection probe camera uniform buffer",
+        buffer.as_entire_binding(),
+    )
+}
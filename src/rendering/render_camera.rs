@@ -1,16 +1,75 @@
 use std::cell::{Cell, Ref, RefCell};
 
-use glam::{Mat4, Vec2};
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec2, Vec3, Vec4};
 use wgpu::util::DeviceExt;
 
 use crate::{camera::Camera, rendering::common::Resolution};
 
+/// This should match CameraUniform in shared/camera.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+pub struct CameraUniformData {
+    /// Includes the sub-pixel jitter of temporal anti-aliasing
+    pub view_proj: Mat4,
+    pub inverse_view_proj: Mat4,
+    // W is unused
+    pub position: Vec4,
+    /// X = vertical FOV in radians, Y = near plane, Z = far plane, W is unused
+    pub projection: Vec4,
+    /// Without jitter, for motion vectors
+    pub unjittered_view_proj: Mat4,
+    /// Unjittered view projection of the previous frame
+    pub previous_view_proj: Mat4,
+    /// XY = jitter of this frame in NDC, ZW = jitter of the previous frame
+    pub jitter: Vec4,
+}
+
+impl CameraUniformData {
+    pub fn new(camera: &Camera, view_proj: Mat4, previous_view_proj: Mat4, jitter: Vec4) -> Self {
+        // Offsets the clip space XY by jitter * W, i.e. the NDC by jitter
+        let jittered_view_proj =
+            Mat4::from_translation(Vec3::new(jitter.x, jitter.y, 0.0)) * view_proj;
+
+        Self {
+            view_proj: jittered_view_proj,
+            inverse_view_proj: jittered_view_proj.inverse(),
+            position: camera.eye.extend(1.0),
+            projection: Vec4::new(camera.fov_y_radians, camera.near, camera.far, 0.0),
+            unjittered_view_proj: view_proj,
+            previous_view_proj,
+            jitter,
+        }
+    }
+}
+
+/// Number of sub-pixel positions the jitter cycles through
+const JITTER_SEQUENCE_LENGTH: u32 = 8;
+
+/// Element of the Halton low-discrepancy sequence in the given base, in [0, 1)
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut result = 0.0;
+    let mut fraction = 1.0;
+
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+
+    result
+}
+
 pub struct RenderCamera {
     camera: Camera,
     resolution: Resolution,
     view_proj: RefCell<Mat4>,
     is_dirty: Cell<bool>,
-    should_update_uniform: Cell<bool>,
+    /// Unjittered view projection written to the uniform buffer during the previous frame
+    previous_view_proj: Option<Mat4>,
+    previous_jitter: Vec2,
+    jitter_enabled: bool,
+    frame_index: u32,
     pub uniform_buffer: wgpu::Buffer,
 }
 
@@ -21,7 +80,12 @@ impl RenderCamera {
 
         let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Camera uniform buffer"),
-            contents: bytemuck::cast_slice(&[matrix]),
+            contents: bytemuck::cast_slice(&[CameraUniformData::new(
+                &camera,
+                matrix,
+                matrix,
+                Vec4::ZERO,
+            )]),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
@@ -30,23 +94,47 @@ impl RenderCamera {
             resolution,
             view_proj: RefCell::new(matrix),
             is_dirty: Cell::new(true),
-            should_update_uniform: Cell::new(true),
+            previous_view_proj: None,
+            previous_jitter: Vec2::ZERO,
+            jitter_enabled: false,
+            frame_index: 0,
             uniform_buffer,
         }
     }
 
     fn invalidate(&self) {
         self.is_dirty.set(true);
-        self.should_update_uniform.set(true);
     }
 
     pub fn update_resolution(&mut self, resolution: Resolution) {
         if self.resolution != resolution {
             self.resolution = resolution;
             self.invalidate();
+            // The previous frame was rendered at a different resolution
+            self.previous_view_proj = None;
         }
     }
 
+    /// Offsets the projection by a different sub-pixel amount every frame, for temporal
+    /// anti-aliasing
+    pub fn set_jitter_enabled(&mut self, enabled: bool) {
+        self.jitter_enabled = enabled;
+    }
+
+    /// Sub-pixel offset of this frame in NDC
+    fn jitter(&self) -> Vec2 {
+        if !self.jitter_enabled {
+            return Vec2::ZERO;
+        }
+
+        // Starts from 1, since the first element of the sequence is always 0
+        let index = self.frame_index % JITTER_SEQUENCE_LENGTH + 1;
+        let offset_pixels = Vec2::new(halton(index, 2), halton(index, 3)) - 0.5;
+        let resolution = Vec2::new(self.resolution.width as f32, self.resolution.height as f32);
+
+        offset_pixels * 2.0 / resolution
+    }
+
     pub fn update_camera(&mut self, camera: &Camera) {
         self.camera = camera.clone();
         self.invalidate();
@@ -69,15 +157,29 @@ impl RenderCamera {
         self.view_proj.borrow()
     }
 
-    pub fn update_uniform_buffer(&self, queue: &wgpu::Queue) {
-        if !self.should_update_uniform.get() {
-            return;
-        }
-
+    /// Must be called exactly once per frame, since the previous view projection and the jitter
+    /// advance with every call
+    pub fn update_uniform_buffer(&mut self, queue: &wgpu::Queue) {
         let view_proj = *self.get_view_proj();
-
-        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[view_proj]));
-
-        self.should_update_uniform.set(false);
+        let previous_view_proj = self.previous_view_proj.unwrap_or(view_proj);
+        let jitter = self.jitter();
+
+        let data = CameraUniformData::new(
+            &self.camera,
+            view_proj,
+            previous_view_proj,
+            Vec4::new(
+                jitter.x,
+                jitter.y,
+                self.previous_jitter.x,
+                self.previous_jitter.y,
+            ),
+        );
+
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[data]));
+
+        self.previous_view_proj = Some(view_proj);
+        self.previous_jitter = jitter;
+        self.frame_index = self.frame_index.wrapping_add(1);
     }
 }
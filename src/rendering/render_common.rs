@@ -3,23 +3,72 @@ use std::sync::RwLock;
 use wgpu::{PresentMode, SurfaceConfiguration};
 use winit::dpi::PhysicalSize;
 
-use crate::rendering::global_uniform::{GlobalUniform, GlobalUniformState};
+use crate::rendering::global_uniform::{GlobalUniform, GlobalUniformState, SHADER_PARAM_COUNT};
 
 pub struct RenderCommon {
     pub output_surface_config: RwLock<SurfaceConfiguration>,
+    /// Empty when rendering offline
+    pub supported_present_modes: Vec<PresentMode>,
     pub camera_uniform_buffer: wgpu::Buffer,
     pub global_uniform: GlobalUniform,
 }
 
 impl RenderCommon {
+    /// Format of the output when rendering offline. sRGB, so that it can be written to image
+    /// files as is.
+    pub const OFFSCREEN_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    /// Without a surface, the configuration describes the offscreen output texture instead
     pub fn new(
         device: &wgpu::Device,
         adapter: &wgpu::Adapter,
-        surface: &wgpu::Surface,
+        surface: Option<&wgpu::Surface>,
         size: PhysicalSize<u32>,
+        present_mode: PresentMode,
         camera_uniform_buffer: wgpu::Buffer,
     ) -> Self {
-        let surface_caps = surface.get_capabilities(&adapter);
+        let supported_present_modes = surface
+            .map(|surface| surface.get_capabilities(adapter).present_modes)
+            .unwrap_or_default();
+
+        let output_surface_config = match surface {
+            Some(surface) => {
+                let config = Self::surface_config(surface, adapter, size, present_mode);
+                surface.configure(device, &config);
+                config
+            }
+            None => wgpu::SurfaceConfiguration {
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+                format: Self::OFFSCREEN_FORMAT,
+                width: size.width,
+                height: size.height,
+                present_mode: PresentMode::Fifo,
+                alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+                view_formats: vec![],
+                desired_maximum_frame_latency: 2,
+            },
+        };
+
+        let global_uniform = GlobalUniform::new(
+            device,
+            GlobalUniformState::new(size, 0.0, 0.0, [0.0; SHADER_PARAM_COUNT]),
+        );
+
+        Self {
+            output_surface_config: RwLock::new(output_surface_config),
+            supported_present_modes,
+            camera_uniform_buffer,
+            global_uniform,
+        }
+    }
+
+    fn surface_config(
+        surface: &wgpu::Surface,
+        adapter: &wgpu::Adapter,
+        size: PhysicalSize<u32>,
+        present_mode: PresentMode,
+    ) -> SurfaceConfiguration {
+        let surface_caps = surface.get_capabilities(adapter);
         let surface_format = surface_caps
             .formats
             .iter()
@@ -27,20 +76,17 @@ impl RenderCommon {
             .copied()
             .unwrap_or(surface_caps.formats[0]);
 
-        let present_mode_preference = [
-            PresentMode::Mailbox,
-            PresentMode::Fifo,
-            PresentMode::Immediate,
-        ];
-
-        let present_mode = surface_caps
-            .present_modes
-            .iter()
-            .find(|&&mode| present_mode_preference.contains(&mode))
-            .copied()
-            .unwrap_or(surface_caps.present_modes[0]);
+        let present_mode = if surface_caps.present_modes.contains(&present_mode) {
+            present_mode
+        } else {
+            log::warn!(
+                "Present mode {:?} is not supported, using Fifo instead",
+                present_mode
+            );
+            PresentMode::Fifo
+        };
 
-        let output_surface_config = wgpu::SurfaceConfiguration {
+        wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             format: surface_format,
             width: size.width,
@@ -49,16 +95,6 @@ impl RenderCommon {
             alpha_mode: surface_caps.alpha_modes[0],
             view_formats: vec![],
             desired_maximum_frame_latency: 2,
-        };
-
-        surface.configure(device, &output_surface_config);
-
-        let global_uniform = GlobalUniform::new(device, GlobalUniformState::new(size, 0.0));
-
-        Self {
-            output_surface_config: RwLock::new(output_surface_config),
-            camera_uniform_buffer,
-            global_uniform,
         }
     }
 }
@@ -0,0 +1,478 @@
+use std::collections::HashMap;
+
+use crate::rendering::{
+    common::{PhysicalSizeExt, Resolution},
+    gpu_profiler::GpuProfiler,
+    passes::render_pass_context::RenderPassContext,
+};
+
+/// Refers to a texture of a `RenderGraph`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextureHandle(usize);
+
+/// What a texture is cleared to by the first pass that writes to it during a frame
+#[derive(Debug, Clone, Copy)]
+pub enum ClearValue {
+    Color(wgpu::Color),
+    Depth(f32),
+}
+
+/// A texture that only lives for a single frame. The graph takes it from the pool when the
+/// first pass that isn't culled uses it, and returns it after the last one. Textures with the same
+/// size, format and usage share memory if their lifetimes don't overlap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TransientTextureDescriptor {
+    pub label: &'static str,
+    pub size: Resolution,
+    pub format: wgpu::TextureFormat,
+    pub usage: wgpu::TextureUsages,
+}
+
+impl TransientTextureDescriptor {
+    /// A render target that is sampled by later passes
+    pub fn render_target(
+        label: &'static str,
+        size: Resolution,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        Self {
+            label,
+            size,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        }
+    }
+
+    fn pool_key(&self) -> PoolKey {
+        PoolKey {
+            size: self.size,
+            format: self.format,
+            usage: self.usage,
+        }
+    }
+}
+
+/// Transient textures are interchangeable if these match. The label is only for debugging.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct PoolKey {
+    size: Resolution,
+    format: wgpu::TextureFormat,
+    usage: wgpu::TextureUsages,
+}
+
+/// A color attachment with the load and store ops decided by the graph
+#[derive(Clone)]
+pub struct ColorTarget {
+    pub view: wgpu::TextureView,
+    pub ops: wgpu::Operations<wgpu::Color>,
+}
+
+impl ColorTarget {
+    pub fn attachment(&self) -> Option<wgpu::RenderPassColorAttachment<'_>> {
+        Some(wgpu::RenderPassColorAttachment {
+            view: &self.view,
+            resolve_target: None,
+            ops: self.ops,
+        })
+    }
+}
+
+/// A depth attachment with the load and store ops decided by the graph
+#[derive(Clone)]
+pub struct DepthTarget {
+    pub view: wgpu::TextureView,
+    pub ops: wgpu::Operations<f32>,
+}
+
+impl DepthTarget {
+    pub fn attachment(&self) -> Option<wgpu::RenderPassDepthStencilAttachment<'_>> {
+        Some(wgpu::RenderPassDepthStencilAttachment {
+            view: &self.view,
+            depth_ops: Some(self.ops),
+            stencil_ops: None,
+        })
+    }
+}
+
+enum GraphTextureSource {
+    /// Owned by a pass or the renderer, and kept between frames
+    Imported(wgpu::TextureView),
+    Transient(TransientTextureDescriptor),
+}
+
+struct GraphTexture {
+    name: &'static str,
+    source: GraphTextureSource,
+    /// None if the texture keeps its contents from outside the graph
+    clear: Option<ClearValue>,
+}
+
+type PassExecutor<'a> = Box<dyn FnOnce(&mut RenderPassContext, &PassResources) + 'a>;
+
+struct GraphPass<'a> {
+    name: &'static str,
+    reads: Vec<TextureHandle>,
+    writes: Vec<TextureHandle>,
+    execute: PassExecutor<'a>,
+}
+
+/// The passes of a single frame, and the textures they read and write. Passes run in the order
+/// they are added, which decides which write each read sees. From the declared inputs and
+/// outputs the graph
+///
+/// - culls passes whose results are never used,
+/// - allocates the transient textures of the remaining passes, aliasing the ones whose lifetimes
+///   don't overlap,
+/// - clears each texture on its first write and discards the contents of transient textures
+///   after their last use.
+///
+/// Compute work that only writes buffers, like culling, runs before the graph.
+#[derive(Default)]
+pub struct RenderGraph<'a> {
+    textures: Vec<GraphTexture>,
+    passes: Vec<GraphPass<'a>>,
+}
+
+impl<'a> RenderGraph<'a> {
+    /// A texture that lives outside the graph. Passes that write to it are never culled.
+    pub fn import_texture(
+        &mut self,
+        name: &'static str,
+        view: &wgpu::TextureView,
+        clear: Option<ClearValue>,
+    ) -> TextureHandle {
+        self.add_texture(GraphTexture {
+            name,
+            source: GraphTextureSource::Imported(view.clone()),
+            clear,
+        })
+    }
+
+    pub fn create_texture(
+        &mut self,
+        descriptor: TransientTextureDescriptor,
+        clear: ClearValue,
+    ) -> TextureHandle {
+        self.add_texture(GraphTexture {
+            name: descriptor.label,
+            source: GraphTextureSource::Transient(descriptor),
+            clear: Some(clear),
+        })
+    }
+
+    fn add_texture(&mut self, texture: GraphTexture) -> TextureHandle {
+        self.textures.push(texture);
+        TextureHandle(self.textures.len() - 1)
+    }
+
+    /// The name is also used for the GPU profiler scope of the pass
+    pub fn add_pass(&mut self, name: &'static str) -> PassBuilder<'_, 'a> {
+        PassBuilder {
+            graph: self,
+            pass_name: name,
+            reads: Vec::new(),
+            writes: Vec::new(),
+        }
+    }
+
+    pub fn execute(
+        self,
+        context: &mut RenderPassContext,
+        transient_textures: &mut TransientTextures,
+        profiler: &mut GpuProfiler,
+    ) {
+        let kept = self.find_kept_passes();
+
+        // Indices of the first and the last kept pass that use each texture
+        let mut first_use = vec![None; self.textures.len()];
+        let mut last_use = vec![None; self.textures.len()];
+        for (index, pass) in self.passes.iter().enumerate() {
+            if kept[index] {
+                for texture in pass.reads.iter().chain(&pass.writes) {
+                    first_use[texture.0].get_or_insert(index);
+                    last_use[texture.0] = Some(index);
+                }
+            }
+        }
+
+        // Transient textures are acquired right before their first use, so that they can reuse
+        // the memory of textures that are no longer needed
+        let mut views = self
+            .textures
+            .iter()
+            .map(|texture| match &texture.source {
+                GraphTextureSource::Imported(view) => Some(view.clone()),
+                GraphTextureSource::Transient(_) => None,
+            })
+            .collect::<Vec<_>>();
+        let mut acquired = self
+            .textures
+            .iter()
+            .map(|_| None)
+            .collect::<Vec<Option<PooledTexture>>>();
+
+        let mut written = vec![false; self.textures.len()];
+
+        for (index, pass) in self.passes.into_iter().enumerate() {
+            if !kept[index] {
+                continue;
+            }
+
+            for texture in pass.reads.iter().chain(&pass.writes) {
+                if let GraphTextureSource::Transient(descriptor) = &self.textures[texture.0].source
+                {
+                    if first_use[texture.0] == Some(index) && acquired[texture.0].is_none() {
+                        let (pooled, view) = transient_textures.acquire(descriptor);
+                        acquired[texture.0] = Some(pooled);
+                        views[texture.0] = Some(view);
+                    }
+                }
+            }
+
+            for texture in &pass.reads {
+                assert!(
+                    written[texture.0]
+                        || matches!(
+                            self.textures[texture.0].source,
+                            GraphTextureSource::Imported(_)
+                        ),
+                    "Pass {} reads {} before any pass has written to it",
+                    pass.name,
+                    self.textures[texture.0].name
+                );
+            }
+
+            let mut ops = HashMap::new();
+            for texture in &pass.writes {
+                let graph_texture = &self.textures[texture.0];
+
+                let clear = graph_texture.clear.filter(|_| !written[texture.0]);
+                let store = match graph_texture.source {
+                    GraphTextureSource::Imported(_) => wgpu::StoreOp::Store,
+                    GraphTextureSource::Transient(_) if last_use[texture.0] > Some(index) => {
+                        wgpu::StoreOp::Store
+                    }
+                    GraphTextureSource::Transient(_) => wgpu::StoreOp::Discard,
+                };
+
+                ops.insert(*texture, (clear, store));
+                written[texture.0] = true;
+            }
+
+            let resources = PassResources { views: &views, ops };
+
+            profiler.begin_scope(context.encoder, pass.name);
+            (pass.execute)(context, &resources);
+            profiler.end_scope(context.encoder);
+
+            // Later passes can alias the textures whose last use this was
+            for texture in pass.reads.iter().chain(&pass.writes) {
+                if last_use[texture.0] == Some(index) {
+                    if let Some(pooled) = acquired[texture.0].take() {
+                        transient_textures.release(pooled);
+                    }
+                }
+            }
+        }
+
+        transient_textures.end_frame();
+    }
+
+    /// A pass is kept if it writes to an imported texture, writes to a texture that a kept pass
+    /// uses later, or doesn't declare any outputs at all.
+    fn find_kept_passes(&self) -> Vec<bool> {
+        let mut used = self
+            .textures
+            .iter()
+            .map(|texture| matches!(texture.source, GraphTextureSource::Imported(_)))
+            .collect::<Vec<_>>();
+        let mut kept = vec![false; self.passes.len()];
+
+        for (index, pass) in self.passes.iter().enumerate().rev() {
+            kept[index] =
+                pass.writes.is_empty() || pass.writes.iter().any(|texture| used[texture.0]);
+
+            if kept[index] {
+                // Writes after the first one load the previous contents, so they count as reads
+                for texture in pass.reads.iter().chain(&pass.writes) {
+                    used[texture.0] = true;
+                }
+            } else {
+                log::trace!("Culled render graph pass {}", pass.name);
+            }
+        }
+
+        kept
+    }
+}
+
+pub struct PassBuilder<'g, 'a> {
+    graph: &'g mut RenderGraph<'a>,
+    pass_name: &'static str,
+    reads: Vec<TextureHandle>,
+    writes: Vec<TextureHandle>,
+}
+
+impl<'a> PassBuilder<'_, 'a> {
+    /// Sampled in a shader, or used as a depth attachment that isn't written to
+    pub fn read(mut self, texture: TextureHandle) -> Self {
+        self.reads.push(texture);
+        self
+    }
+
+    /// Rendered to. Passes that render to their own targets can declare them too, so that the
+    /// passes which read them are ordered and culled correctly.
+    pub fn write(mut self, texture: TextureHandle) -> Self {
+        self.writes.push(texture);
+        self
+    }
+
+    pub fn execute(self, execute: impl FnOnce(&mut RenderPassContext, &PassResources) + 'a) {
+        self.graph.passes.push(GraphPass {
+            name: self.pass_name,
+            reads: self.reads,
+            writes: self.writes,
+            execute: Box::new(execute),
+        });
+    }
+}
+
+/// The textures of the graph, as seen by the pass that is being executed
+pub struct PassResources<'r> {
+    views: &'r [Option<wgpu::TextureView>],
+    /// Clear value on the first write, and the store op, for each texture the pass writes
+    ops: HashMap<TextureHandle, (Option<ClearValue>, wgpu::StoreOp)>,
+}
+
+impl PassResources<'_> {
+    pub fn view(&self, texture: TextureHandle) -> &wgpu::TextureView {
+        self.views[texture.0]
+            .as_ref()
+            .expect("Texture isn't used by any pass")
+    }
+
+    /// Textures that the pass only reads are loaded and stored as they are
+    fn ops(&self, texture: TextureHandle) -> (Option<ClearValue>, wgpu::StoreOp) {
+        self.ops
+            .get(&texture)
+            .copied()
+            .unwrap_or((None, wgpu::StoreOp::Store))
+    }
+
+    pub fn color_target(&self, texture: TextureHandle) -> ColorTarget {
+        let (clear, store) = self.ops(texture);
+        let load = match clear {
+            Some(ClearValue::Color(color)) => wgpu::LoadOp::Clear(color),
+            Some(ClearValue::Depth(_)) => panic!("Depth clear value used for a color target"),
+            None => wgpu::LoadOp::Load,
+        };
+
+        ColorTarget {
+            view: self.view(texture).clone(),
+            ops: wgpu::Operations { load, store },
+        }
+    }
+
+    pub fn depth_target(&self, texture: TextureHandle) -> DepthTarget {
+        let (clear, store) = self.ops(texture);
+        let load = match clear {
+            Some(ClearValue::Depth(depth)) => wgpu::LoadOp::Clear(depth),
+            Some(ClearValue::Color(_)) => panic!("Color clear value used for a depth target"),
+            None => wgpu::LoadOp::Load,
+        };
+
+        DepthTarget {
+            view: self.view(texture).clone(),
+            ops: wgpu::Operations { load, store },
+        }
+    }
+}
+
+/// A texture of the pool, while it is used by a transient texture of the graph
+struct PooledTexture {
+    key: PoolKey,
+    texture: wgpu::Texture,
+}
+
+/// Textures of the render graph that only live for a frame. A texture that is released can be
+/// acquired again later in the same frame. The textures are kept for the next frame, and dropped
+/// if it doesn't use them, e.g. after a resize.
+pub struct TransientTextures {
+    device: wgpu::Device,
+    /// Left over from the previous frame
+    previous_frame: HashMap<PoolKey, Vec<wgpu::Texture>>,
+    /// Released during this frame
+    released: HashMap<PoolKey, Vec<wgpu::Texture>>,
+    created_count: usize,
+}
+
+impl TransientTextures {
+    pub fn new(device: &wgpu::Device) -> Self {
+        Self {
+            device: device.clone(),
+            previous_frame: HashMap::new(),
+            released: HashMap::new(),
+            created_count: 0,
+        }
+    }
+
+    fn acquire(
+        &mut self,
+        descriptor: &TransientTextureDescriptor,
+    ) -> (PooledTexture, wgpu::TextureView) {
+        let key = descriptor.pool_key();
+
+        let texture = self
+            .released
+            .get_mut(&key)
+            .and_then(Vec::pop)
+            .or_else(|| self.previous_frame.get_mut(&key).and_then(Vec::pop))
+            .unwrap_or_else(|| {
+                self.created_count += 1;
+                log::debug!(
+                    "Creating transient texture #{} for {} ({}x{} {:?})",
+                    self.created_count,
+                    descriptor.label,
+                    key.size.width,
+                    key.size.height,
+                    key.format
+                );
+
+                // The texture can be shared by many graph textures, so it is labeled by its key
+                let label = format!(
+                    "Transient {}x{} {:?}",
+                    key.size.width, key.size.height, key.format
+                );
+
+                self.device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some(&label),
+                    size: key.size.to_extent3d(),
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: key.format,
+                    usage: key.usage,
+                    view_formats: &[],
+                })
+            });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some(descriptor.label),
+            ..Default::default()
+        });
+
+        (PooledTexture { key, texture }, view)
+    }
+
+    fn release(&mut self, pooled: PooledTexture) {
+        self.released
+            .entry(pooled.key)
+            .or_default()
+            .push(pooled.texture);
+    }
+
+    fn end_frame(&mut self) {
+        // Every acquired texture has been released by now
+        self.previous_frame = std::mem::take(&mut self.released);
+    }
+}
@@ -1,9 +1,31 @@
-use std::num::NonZeroU32;
+use std::{
+    collections::{HashMap, HashSet},
+    num::NonZeroU32,
+};
 
 use bytemuck::{Pod, Zeroable};
+use glam::Vec4;
 use wgpu::{util::DeviceExt, TexelCopyBufferLayout, TexelCopyTextureInfo, TextureDescriptor};
 
-use crate::{asset_pipeline::materials::PbrMaterialData, material_manager::MaterialManager};
+use crate::{
+    asset_pipeline::materials::{
+        AlphaMode, ColorSpace, CompressedTextureData, MaterialTexture, PbrMaterialData,
+        TerrainLayer, TextureFilter, TextureSampler, TextureWrap,
+    },
+    camera::Camera,
+    material_manager::MaterialManager,
+    rendering::{
+        config::RenderConfig,
+        post_process::targets::HDR_FORMAT,
+        texture_streaming::{self, StreamingSource, TextureStreamer},
+        util::mipmap_generator::{self, MipmapGenerator},
+    },
+    scene_graph::scene::Scene,
+};
+
+/// Size of the material sampler binding array. Materials share samplers by their settings, so
+/// only a few are usually needed.
+pub const MAX_MATERIAL_SAMPLERS: u32 = 16;
 
 pub struct TextureEntry {
     #[allow(dead_code)]
@@ -19,68 +41,184 @@ pub struct PbrMaterialInfo {
     pub base_color: u32,
     pub normal: u32,
     pub ao_roughness_metallic: u32,
-    _padding: u32,
+    pub emissive: u32,
+    /// W is unused
+    pub emissive_factor: Vec4,
+    /// One of the ALPHA_MODE constants in shared/material_info.wgsl
+    pub alpha_mode: u32,
+    pub alpha_cutoff: f32,
+    pub normal_strength: f32,
+    /// 1 if the green channel of the normal map is flipped
+    pub flip_normal_green: u32,
+    /// XY: UV scale, ZW: UV offset
+    pub uv_transform: Vec4,
+    /// Range in the terrain layer buffer, zero for regular materials
+    pub terrain_layer_start: u32,
+    pub terrain_layer_count: u32,
+    /// Sampler indices of the base color, normal, AO / roughness / metallic and emissive
+    /// textures, 8 bits each from the lowest
+    pub samplers: u32,
+    /// 1 while the color space audit highlights the material
+    pub highlight: u32,
+    /// XY: offset, ZW: size of the material's rectangle in its atlas page. (0, 0, 1, 1) for
+    /// materials with textures of their own.
+    pub atlas_rect: Vec4,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct TerrainLayerInfo {
+    pub base_color: u32,
+    pub normal: u32,
+    pub ao_roughness_metallic: u32,
+    pub tile_size: f32,
+    /// XY: height range, ZW: slope range
+    pub ranges: Vec4,
+    /// X: height blend, Y: slope blend, ZW are unused
+    pub blend: Vec4,
 }
 
 pub struct RenderMaterialManager {
     device: wgpu::Device,
     queue: wgpu::Queue,
+    config: &'static RenderConfig,
 
     textures: Vec<TextureEntry>,
     materials: Vec<PbrMaterialInfo>,
+    terrain_layers: Vec<TerrainLayerInfo>,
+    /// Texture indices of render targets, by name
+    render_targets: HashMap<String, usize>,
+    /// Textures left out because they didn't fit into the binding array. Materials use the
+    /// default textures instead.
+    evicted_textures: HashSet<TextureSlot>,
+    /// Base color, normal, AO / roughness / metallic and emissive texture indices of each atlas
+    /// page
+    atlas_pages: Vec<[usize; 4]>,
+    /// Textures that might be sampled in the wrong color space, as text for the UI
+    color_space_suspects: Vec<String>,
+    /// Indices of the materials that have such textures
+    suspect_materials: HashSet<usize>,
+    /// Whether the suspect materials are highlighted
+    color_space_audit: bool,
 
     material_info_buffer: Option<wgpu::Buffer>,
-    sampler: wgpu::Sampler,
+    terrain_layer_buffer: Option<wgpu::Buffer>,
+    /// The default sampler is first, and the rest are created as materials need them
+    samplers: Vec<wgpu::Sampler>,
+    sampler_indices: HashMap<TextureSampler, usize>,
+    mipmap_generator: MipmapGenerator,
+    /// Replaces the entries of large material textures as their mips are streamed in and out
+    streamer: TextureStreamer,
 
     bind_group_layout: wgpu::BindGroupLayout,
     // Created lazily
     bind_group: Option<wgpu::BindGroup>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum TextureType {
     BaseColor,
     Normal,
     AoRoughnessMetallic,
+    Emissive,
+    /// Rendered every frame, see `RenderTargetManager`
+    RenderTarget,
 }
 
-impl RenderMaterialManager {
-    // This could be handled dynamically, but we don't need that for a demo
-    pub const MAX_TEXTURE_COUNT: u32 = 128;
+/// Where a material refers to a texture
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct TextureSlot {
+    material_index: usize,
+    terrain_layer: Option<usize>,
+    ty: TextureType,
+}
+
+impl TextureSlot {
+    /// All textures of a material, in the order they are loaded
+    fn all(material_index: usize, material: &PbrMaterialData) -> Vec<TextureSlot> {
+        let slot = |terrain_layer, ty| TextureSlot {
+            material_index,
+            terrain_layer,
+            ty,
+        };
 
+        let mut slots = Vec::new();
+        let textures = [
+            (material.base_color.is_some(), TextureType::BaseColor),
+            (material.normal.is_some(), TextureType::Normal),
+            (
+                material.ao_roughness_metallic.is_some(),
+                TextureType::AoRoughnessMetallic,
+            ),
+            (material.emissive.is_some(), TextureType::Emissive),
+        ];
+        slots.extend(
+            textures
+                .into_iter()
+                .filter(|(present, _)| *present)
+                .map(|(_, ty)| slot(None, ty)),
+        );
+
+        for (index, layer) in material.terrain_layers.iter().enumerate() {
+            let textures = [
+                (layer.base_color.is_some(), TextureType::BaseColor),
+                (layer.normal.is_some(), TextureType::Normal),
+                (
+                    layer.ao_roughness_metallic.is_some(),
+                    TextureType::AoRoughnessMetallic,
+                ),
+            ];
+            slots.extend(
+                textures
+                    .into_iter()
+                    .filter(|(present, _)| *present)
+                    .map(|(_, ty)| slot(Some(index), ty)),
+            );
+        }
+
+        slots
+    }
+
+    /// Lower is kept first when the textures don't fit. A missing base color is the most
+    /// noticeable.
+    fn priority(&self) -> u32 {
+        match self.ty {
+            TextureType::BaseColor | TextureType::RenderTarget => 0,
+            TextureType::Normal => 1,
+            TextureType::AoRoughnessMetallic => 2,
+            TextureType::Emissive => 3,
+        }
+    }
+}
+
+impl RenderMaterialManager {
     const DEFAULT_TEXTURE_BASE_COLOR: usize = 0;
     const DEFAULT_TEXTURE_NORMAL: usize = 1;
     const DEFAULT_TEXTURE_AO_ROUGHNESS_METALLIC: usize = 2;
+    const DEFAULT_TEXTURE_EMISSIVE: usize = 3;
 
-    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+    const DEFAULT_SAMPLER: u32 = 0;
+
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, config: &'static RenderConfig) -> Self {
         let default_base_color =
             Self::create_default_texture(device, queue, TextureType::BaseColor);
         let default_normal = Self::create_default_texture(device, queue, TextureType::Normal);
         let default_ao_roughness_metallic =
             Self::create_default_texture(device, queue, TextureType::AoRoughnessMetallic);
+        let default_emissive = Self::create_default_texture(device, queue, TextureType::Emissive);
 
         let textures = vec![
             default_base_color,
             default_normal,
             default_ao_roughness_metallic,
+            default_emissive,
         ];
 
         let materials = Vec::new();
 
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            label: Some("Default sampler"),
-            address_mode_u: wgpu::AddressMode::Repeat,
-            address_mode_v: wgpu::AddressMode::Repeat,
-            address_mode_w: wgpu::AddressMode::Repeat,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Linear,
-            mipmap_filter: wgpu::FilterMode::Linear,
-            lod_min_clamp: 0.0,
-            lod_max_clamp: 32.0,
-            compare: None,
-            anisotropy_clamp: 1,
-            border_color: None,
-        });
+        let default_sampler = TextureSampler::default();
+        let samplers = vec![Self::create_sampler(device, config, &default_sampler)];
+        let sampler_indices = HashMap::from([(default_sampler, Self::DEFAULT_SAMPLER as usize)]);
 
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("Texture manager bind group layout"),
@@ -105,13 +243,24 @@ impl RenderMaterialManager {
                         view_dimension: wgpu::TextureViewDimension::D2,
                         multisampled: false,
                     },
-                    count: Some(NonZeroU32::new(Self::MAX_TEXTURE_COUNT).unwrap()),
+                    count: Some(NonZeroU32::new(config.max_texture_count).unwrap()),
                 },
-                // Sampler
+                // Samplers
                 wgpu::BindGroupLayoutEntry {
                     binding: 2,
                     visibility: wgpu::ShaderStages::FRAGMENT,
                     ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: Some(NonZeroU32::new(MAX_MATERIAL_SAMPLERS).unwrap()),
+                },
+                // Terrain layer buffer
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
                     count: None,
                 },
             ],
@@ -120,54 +269,386 @@ impl RenderMaterialManager {
         Self {
             device: device.clone(),
             queue: queue.clone(),
+            config,
 
             textures,
             materials,
+            terrain_layers: Vec::new(),
+            render_targets: HashMap::new(),
+            evicted_textures: HashSet::new(),
+            atlas_pages: Vec::new(),
+            color_space_suspects: Vec::new(),
+            suspect_materials: HashSet::new(),
+            color_space_audit: false,
 
             material_info_buffer: None,
-            sampler,
+            terrain_layer_buffer: None,
+            samplers,
+            sampler_indices,
+            mipmap_generator: MipmapGenerator::new(device),
+            streamer: TextureStreamer::new(device, queue, config.quality.texture_budget_mb),
 
             bind_group_layout,
             bind_group: None,
         }
     }
 
+    /// Makes a texture that is rendered at runtime available to materials that refer to it by
+    /// name. Must be called before the materials are loaded.
+    pub fn register_render_target(
+        &mut self,
+        name: &str,
+        texture: &wgpu::Texture,
+        view: &wgpu::TextureView,
+    ) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            !self.is_full(),
+            "Too many textures, can't register render target {}",
+            name
+        );
+
+        let texture_index = self.textures.len();
+        self.textures.push(TextureEntry {
+            ty: TextureType::RenderTarget,
+            texture: texture.clone(),
+            view: view.clone(),
+        });
+        self.render_targets.insert(name.to_string(), texture_index);
+        self.bind_group = None;
+
+        Ok(())
+    }
+
     pub fn load_material(&mut self, pbr_material: &PbrMaterialData) -> usize {
-        let base_color = if let Some(data) = &pbr_material.base_color {
-            self.create_texture(&pbr_material.name, TextureType::BaseColor, data)
+        let material_index = self.materials.len();
+        let slot = |ty| TextureSlot {
+            material_index,
+            terrain_layer: None,
+            ty,
+        };
+
+        let dynamic_textures = &pbr_material.dynamic_textures;
+        let dynamic_base_color =
+            self.find_render_target(&pbr_material.name, dynamic_textures.base_color.as_deref());
+        let dynamic_emissive =
+            self.find_render_target(&pbr_material.name, dynamic_textures.emissive.as_deref());
+
+        let mut base_color = if let Some(index) = dynamic_base_color {
+            index
+        } else if let Some(data) = &pbr_material.base_color {
+            self.load_texture(&pbr_material.name, slot(TextureType::BaseColor), data)
         } else {
             Self::DEFAULT_TEXTURE_BASE_COLOR
         };
 
-        let normal = if let Some(data) = &pbr_material.normal {
-            self.create_texture(&pbr_material.name, TextureType::Normal, data)
+        let mut normal = if let Some(data) = &pbr_material.normal {
+            self.load_texture(&pbr_material.name, slot(TextureType::Normal), data)
         } else {
             Self::DEFAULT_TEXTURE_NORMAL
         };
 
-        let ao_roughness_metallic = if let Some(data) = &pbr_material.ao_roughness_metallic {
-            self.create_texture(&pbr_material.name, TextureType::AoRoughnessMetallic, data)
+        let mut ao_roughness_metallic = if let Some(data) = &pbr_material.ao_roughness_metallic {
+            self.load_texture(
+                &pbr_material.name,
+                slot(TextureType::AoRoughnessMetallic),
+                data,
+            )
         } else {
             Self::DEFAULT_TEXTURE_AO_ROUGHNESS_METALLIC
         };
 
+        let mut emissive = if let Some(index) = dynamic_emissive {
+            index
+        } else if let Some(data) = &pbr_material.emissive {
+            self.load_texture(&pbr_material.name, slot(TextureType::Emissive), data)
+        } else {
+            Self::DEFAULT_TEXTURE_EMISSIVE
+        };
+
+        // Atlased materials no longer have textures of their own, so they get the default sampler
+        let samplers = [
+            (base_color, &pbr_material.base_color),
+            (normal, &pbr_material.normal),
+            (ao_roughness_metallic, &pbr_material.ao_roughness_metallic),
+            (emissive, &pbr_material.emissive),
+        ]
+        .into_iter()
+        .enumerate()
+        .fold(0, |samplers, (slot, (texture_index, texture))| {
+            samplers | (self.texture_sampler(texture_index, texture.as_ref()) << (slot * 8))
+        });
+
+        if let Some(atlas) = &pbr_material.atlas {
+            [base_color, normal, ao_roughness_metallic, emissive] = self.atlas_pages[atlas.page];
+        }
+
+        let terrain_layer_start = self.terrain_layers.len() as u32;
+        for (index, layer) in pbr_material.terrain_layers.iter().enumerate() {
+            self.load_terrain_layer(&pbr_material.name, material_index, index, layer);
+        }
+
         let material_info = PbrMaterialInfo {
             base_color: base_color as u32,
             normal: normal as u32,
             ao_roughness_metallic: ao_roughness_metallic as u32,
-            _padding: 0,
+            emissive: emissive as u32,
+            emissive_factor: pbr_material.emissive_factor.extend(0.0),
+            alpha_mode: match pbr_material.alpha_mode {
+                AlphaMode::Opaque => 0,
+                AlphaMode::Mask => 1,
+                AlphaMode::Blend => 2,
+            },
+            alpha_cutoff: pbr_material.alpha_cutoff,
+            normal_strength: pbr_material.parameters.normal_strength,
+            flip_normal_green: pbr_material.parameters.flip_normal_green as u32,
+            uv_transform: pbr_material
+                .parameters
+                .uv_scale
+                .extend(pbr_material.parameters.uv_offset.x)
+                .extend(pbr_material.parameters.uv_offset.y),
+            terrain_layer_start,
+            terrain_layer_count: pbr_material.terrain_layers.len() as u32,
+            samplers,
+            highlight: 0,
+            atlas_rect: pbr_material
+                .atlas
+                .map_or(Vec4::new(0.0, 0.0, 1.0, 1.0), |atlas| atlas.rect),
         };
 
-        let material_index = self.materials.len();
         self.materials.push(material_info);
         material_index
     }
 
-    pub fn load_all_materials(&mut self, material_manager: &MaterialManager) {
+    fn load_terrain_layer(
+        &mut self,
+        material_name: &str,
+        material_index: usize,
+        layer_index: usize,
+        layer: &TerrainLayer,
+    ) {
+        let slot = |ty| TextureSlot {
+            material_index,
+            terrain_layer: Some(layer_index),
+            ty,
+        };
+
+        let base_color = match &layer.base_color {
+            Some(data) => self.load_texture(material_name, slot(TextureType::BaseColor), data),
+            None => Self::DEFAULT_TEXTURE_BASE_COLOR,
+        };
+
+        let normal = match &layer.normal {
+            Some(data) => self.load_texture(material_name, slot(TextureType::Normal), data),
+            None => Self::DEFAULT_TEXTURE_NORMAL,
+        };
+
+        let ao_roughness_metallic = match &layer.ao_roughness_metallic {
+            Some(data) => {
+                self.load_texture(material_name, slot(TextureType::AoRoughnessMetallic), data)
+            }
+            None => Self::DEFAULT_TEXTURE_AO_ROUGHNESS_METALLIC,
+        };
+
+        self.terrain_layers.push(TerrainLayerInfo {
+            base_color: base_color as u32,
+            normal: normal as u32,
+            ao_roughness_metallic: ao_roughness_metallic as u32,
+            tile_size: layer.tile_size.max(0.001),
+            ranges: Vec4::new(
+                layer.height_range.x,
+                layer.height_range.y,
+                layer.slope_range.x,
+                layer.slope_range.y,
+            ),
+            blend: Vec4::new(layer.height_blend, layer.slope_blend, 0.0, 0.0),
+        });
+    }
+
+    /// Sampler of a loaded material texture. Default textures are a single texel, so any
+    /// sampler works for them.
+    fn texture_sampler(&mut self, texture_index: usize, texture: Option<&MaterialTexture>) -> u32 {
+        match texture {
+            Some(texture) if texture_index > Self::DEFAULT_TEXTURE_EMISSIVE => {
+                self.sampler_index(&texture.sampler)
+            }
+            _ => Self::DEFAULT_SAMPLER,
+        }
+    }
+
+    /// Finds or creates a sampler with the given settings. Falls back to the default sampler
+    /// when the binding array is full.
+    fn sampler_index(&mut self, sampler: &TextureSampler) -> u32 {
+        if let Some(index) = self.sampler_indices.get(sampler) {
+            return *index as u32;
+        }
+
+        if self.samplers.len() >= MAX_MATERIAL_SAMPLERS as usize {
+            log::warn!(
+                "Too many different texture samplers, using the default sampler instead of {:?}",
+                sampler
+            );
+            return Self::DEFAULT_SAMPLER;
+        }
+
+        let index = self.samplers.len();
+        self.samplers
+            .push(Self::create_sampler(&self.device, self.config, sampler));
+        self.sampler_indices.insert(*sampler, index);
+        self.bind_group = None;
+        index as u32
+    }
+
+    fn create_sampler(
+        device: &wgpu::Device,
+        config: &RenderConfig,
+        sampler: &TextureSampler,
+    ) -> wgpu::Sampler {
+        let address_mode = |wrap| match wrap {
+            TextureWrap::Repeat => wgpu::AddressMode::Repeat,
+            TextureWrap::MirroredRepeat => wgpu::AddressMode::MirrorRepeat,
+            TextureWrap::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+        };
+        let filter_mode = |filter| match filter {
+            TextureFilter::Nearest => wgpu::FilterMode::Nearest,
+            TextureFilter::Linear => wgpu::FilterMode::Linear,
+        };
+
+        // wgpu only allows anisotropic filtering when every filter is linear
+        let linear = [
+            sampler.mag_filter,
+            sampler.min_filter,
+            sampler.mipmap_filter,
+        ]
+        .into_iter()
+        .all(|filter| filter == TextureFilter::Linear);
+        let anisotropy_clamp = if linear {
+            config.quality.anisotropy.clamp(1, 16)
+        } else {
+            1
+        };
+
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Material sampler"),
+            address_mode_u: address_mode(sampler.wrap_u),
+            address_mode_v: address_mode(sampler.wrap_v),
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter: filter_mode(sampler.mag_filter),
+            min_filter: filter_mode(sampler.min_filter),
+            mipmap_filter: filter_mode(sampler.mipmap_filter),
+            lod_min_clamp: 0.0,
+            lod_max_clamp: 32.0,
+            compare: None,
+            anisotropy_clamp,
+            border_color: None,
+        })
+    }
+
+    /// Unknown names fall back to the texture from the material, so that a typo in an override
+    /// file doesn't stop the demo from loading
+    fn find_render_target(&self, material_name: &str, name: Option<&str>) -> Option<usize> {
+        let name = name?;
+        let index = self.render_targets.get(name).copied();
+
+        if index.is_none() {
+            log::warn!(
+                "Material {} refers to an unknown render target: {}",
+                material_name,
+                name
+            );
+        }
+
+        index
+    }
+
+    /// Size of the texture binding array that fits every material texture, the default
+    /// textures and the render targets
+    pub fn required_texture_count(
+        material_manager: &MaterialManager,
+        render_target_count: usize,
+    ) -> u32 {
+        let material_textures: usize = material_manager
+            .materials()
+            .enumerate()
+            .map(|(index, material)| TextureSlot::all(index, material).len())
+            .sum();
+
+        let atlas_textures: usize = material_manager
+            .atlas_pages()
+            .iter()
+            .map(|page| {
+                [
+                    &page.base_color,
+                    &page.normal,
+                    &page.ao_roughness_metallic,
+                    &page.emissive,
+                ]
+                .into_iter()
+                .flatten()
+                .count()
+            })
+            .sum();
+
+        (Self::DEFAULT_TEXTURE_EMISSIVE
+            + 1
+            + render_target_count
+            + material_textures
+            + atlas_textures) as u32
+    }
+
+    /// Loads the materials in the order of their ids. When the textures don't fit into the
+    /// binding array, the ones of the materials that the `scenes` draw the least are left out.
+    pub fn load_all_materials<'a>(
+        &mut self,
+        material_manager: &MaterialManager,
+        scenes: impl IntoIterator<Item = &'a Scene>,
+    ) {
+        // Pages are shared by many materials, so they are never left out
+        for (index, page) in material_manager.atlas_pages().iter().enumerate() {
+            let name = format!("Atlas page {}", index);
+            let mut load = |ty, texture: &Option<MaterialTexture>| match texture {
+                Some(data) => self.create_texture(&name, ty, data, false),
+                None => Self::default_texture(ty),
+            };
+
+            let textures = [
+                load(TextureType::BaseColor, &page.base_color),
+                load(TextureType::Normal, &page.normal),
+                load(
+                    TextureType::AoRoughnessMetallic,
+                    &page.ao_roughness_metallic,
+                ),
+                load(TextureType::Emissive, &page.emissive),
+            ];
+            self.atlas_pages.push(textures);
+        }
+
+        self.evicted_textures = self.plan_evictions(material_manager, scenes);
+
         for pbr_material in material_manager.materials() {
             self.load_material(pbr_material);
         }
 
+        // Drawables whose material was added after this point use the fallback material, which
+        // has the magenta default base color
+        let fallback_material = PbrMaterialInfo {
+            base_color: Self::DEFAULT_TEXTURE_BASE_COLOR as u32,
+            normal: Self::DEFAULT_TEXTURE_NORMAL as u32,
+            ao_roughness_metallic: Self::DEFAULT_TEXTURE_AO_ROUGHNESS_METALLIC as u32,
+            emissive: Self::DEFAULT_TEXTURE_EMISSIVE as u32,
+            emissive_factor: Vec4::ZERO,
+            alpha_mode: 0,
+            alpha_cutoff: 0.5,
+            normal_strength: 1.0,
+            flip_normal_green: 0,
+            uv_transform: Vec4::new(1.0, 1.0, 0.0, 0.0),
+            terrain_layer_start: 0,
+            terrain_layer_count: 0,
+            samplers: Self::DEFAULT_SAMPLER,
+            highlight: 0,
+            atlas_rect: Vec4::new(0.0, 0.0, 1.0, 1.0),
+        };
+        self.materials.push(fallback_material);
+
         let material_info_buffer =
             self.device
                 .create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -176,7 +657,158 @@ impl RenderMaterialManager {
                     usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
                 });
 
+        // Storage buffers can't be empty
+        let terrain_layers = if self.terrain_layers.is_empty() {
+            vec![TerrainLayerInfo::zeroed()]
+        } else {
+            self.terrain_layers.clone()
+        };
+
+        let terrain_layer_buffer =
+            self.device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Terrain layer buffer"),
+                    contents: bytemuck::cast_slice(&terrain_layers),
+                    usage: wgpu::BufferUsages::STORAGE,
+                });
+
         self.material_info_buffer = Some(material_info_buffer);
+        self.terrain_layer_buffer = Some(terrain_layer_buffer);
+    }
+
+    /// Picks the textures to leave out, starting from the least drawn materials
+    fn plan_evictions<'a>(
+        &self,
+        material_manager: &MaterialManager,
+        scenes: impl IntoIterator<Item = &'a Scene>,
+    ) -> HashSet<TextureSlot> {
+        let mut slots = material_manager
+            .materials()
+            .enumerate()
+            .flat_map(|(index, material)| TextureSlot::all(index, material))
+            .collect::<Vec<_>>();
+
+        let free = (self.config.max_texture_count as usize).saturating_sub(self.textures.len());
+        if slots.len() <= free {
+            return HashSet::new();
+        }
+
+        let mut usage = HashMap::<usize, usize>::new();
+        for scene in scenes {
+            for (_, object) in scene.objects.iter() {
+                if let Some(model) = object.model_id.and_then(|id| scene.models.get(id)) {
+                    for primitive in &model.model.primitives {
+                        *usage.entry(primitive.material_id.index()).or_default() += 1;
+                    }
+                }
+
+                if let Some(material_id) = object.decal.as_ref().and_then(|decal| decal.material_id)
+                {
+                    *usage.entry(material_id.index()).or_default() += 1;
+                }
+            }
+        }
+
+        // Stable, so ties keep the order of the materials
+        slots.sort_by_key(|slot| {
+            let uses = usage.get(&slot.material_index).copied().unwrap_or(0);
+            (std::cmp::Reverse(uses), slot.priority())
+        });
+
+        log::warn!(
+            "{} material textures don't fit into the {} available slots, the least used {} use default textures",
+            slots.len(),
+            free,
+            slots.len() - free
+        );
+
+        slots.split_off(free).into_iter().collect()
+    }
+
+    /// Number of materials loaded from the `MaterialManager`. The fallback material is at this
+    /// index.
+    pub fn material_count(&self) -> u32 {
+        (self.materials.len() as u32).saturating_sub(1)
+    }
+
+    /// Requests the mips that each material's textures need at their size on the screen, and
+    /// applies whatever the streamer has finished
+    pub fn update_streaming(&mut self, scene: &Scene, camera: &Camera, screen_height: f32) {
+        let coverage = texture_streaming::material_screen_coverage(scene, camera, screen_height);
+
+        for (material_index, pixels) in coverage {
+            let Some(material) = self.materials.get(material_index) else {
+                continue;
+            };
+
+            // Tiled textures repeat across the surface, so they need more texels
+            let uv_scale = material
+                .uv_transform
+                .x
+                .abs()
+                .max(material.uv_transform.y.abs());
+            let texels = pixels * uv_scale;
+            for texture_index in [
+                material.base_color,
+                material.normal,
+                material.ao_roughness_metallic,
+                material.emissive,
+            ] {
+                self.streamer.request(texture_index as usize, texels);
+            }
+
+            // Terrain layers tile many times across the terrain, so they're always wanted at
+            // full resolution
+            let start = material.terrain_layer_start as usize;
+            let end = start + material.terrain_layer_count as usize;
+            for layer in &self.terrain_layers[start..end] {
+                for texture_index in [layer.base_color, layer.normal, layer.ao_roughness_metallic] {
+                    self.streamer.request(texture_index as usize, f32::INFINITY);
+                }
+            }
+        }
+
+        if self
+            .streamer
+            .update(&mut self.mipmap_generator, &mut self.textures)
+        {
+            self.bind_group = None;
+        }
+    }
+
+    pub fn draw_ui(&mut self, ui: &imgui::Ui) {
+        ui.window("Texture streaming")
+            .size([360.0, 200.0], imgui::Condition::FirstUseEver)
+            .build(|| self.streamer.draw_ui(ui));
+
+        ui.window("Color space audit")
+            .size([360.0, 200.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                if ui.checkbox("Highlight suspect materials", &mut self.color_space_audit) {
+                    self.update_highlights();
+                }
+
+                ui.separator();
+
+                if self.color_space_suspects.is_empty() {
+                    ui.text("No suspect textures");
+                }
+
+                for suspect in &self.color_space_suspects {
+                    ui.text_wrapped(suspect);
+                }
+            });
+    }
+
+    fn update_highlights(&mut self) {
+        for index in &self.suspect_materials {
+            self.materials[*index].highlight = self.color_space_audit as u32;
+        }
+
+        if let Some(buffer) = &self.material_info_buffer {
+            self.queue
+                .write_buffer(buffer, 0, bytemuck::cast_slice(&self.materials));
+        }
     }
 
     pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
@@ -189,7 +821,7 @@ impl RenderMaterialManager {
             return self.bind_group.as_ref().unwrap();
         }
 
-        let mut texture_views = Vec::with_capacity(Self::MAX_TEXTURE_COUNT as usize);
+        let mut texture_views = Vec::with_capacity(self.config.max_texture_count as usize);
 
         for texture in &self.textures {
             texture_views.push(&texture.view);
@@ -197,10 +829,16 @@ impl RenderMaterialManager {
 
         // Fill the rest with copies of the default texture view
         let default_texture_view = self.textures[0].view.clone();
-        while texture_views.len() < Self::MAX_TEXTURE_COUNT as usize {
+        while texture_views.len() < self.config.max_texture_count as usize {
             texture_views.push(&default_texture_view);
         }
 
+        // Same for the samplers
+        let mut samplers = self.samplers.iter().collect::<Vec<_>>();
+        while samplers.len() < MAX_MATERIAL_SAMPLERS as usize {
+            samplers.push(&self.samplers[Self::DEFAULT_SAMPLER as usize]);
+        }
+
         let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("Texture manager bind group"),
             layout: &self.bind_group_layout,
@@ -219,7 +857,15 @@ impl RenderMaterialManager {
                 },
                 wgpu::BindGroupEntry {
                     binding: 2,
-                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    resource: wgpu::BindingResource::SamplerArray(&samplers),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self
+                        .terrain_layer_buffer
+                        .as_ref()
+                        .unwrap()
+                        .as_entire_binding(),
                 },
             ],
         });
@@ -228,35 +874,90 @@ impl RenderMaterialManager {
         self.bind_group.as_ref().unwrap()
     }
 
+    fn load_texture(
+        &mut self,
+        name: &str,
+        slot: TextureSlot,
+        material_texture: &MaterialTexture,
+    ) -> usize {
+        if let Some(issue) = material_texture.color_space_issue() {
+            let suspect = format!(
+                "{} ({:?}, {:?}): {}",
+                name, slot.ty, material_texture.color_space, issue
+            );
+            log::warn!("Texture might be in the wrong color space: {}", suspect);
+            self.color_space_suspects.push(suspect);
+            self.suspect_materials.insert(slot.material_index);
+        }
+
+        if self.evicted_textures.contains(&slot) {
+            return Self::default_texture(slot.ty);
+        }
+
+        self.create_texture(name, slot.ty, material_texture, true)
+    }
+
+    /// Streamed textures start from their lowest mips, see `TextureStreamer`
     fn create_texture(
         &mut self,
         name: &str,
         texture_type: TextureType,
-        texture_data: &gltf::image::Data,
+        material_texture: &MaterialTexture,
+        streamed: bool,
     ) -> usize {
+        if self.is_full() {
+            log::warn!(
+                "Too many textures, using a default {:?} texture for {}",
+                texture_type,
+                name
+            );
+            return Self::default_texture(texture_type);
+        }
+
         let label = format!("{name}({:?})", texture_type);
-        get_texture_format_from_type(texture_type);
-
-        let texture = self.device.create_texture_with_data(
-            &self.queue,
-            &TextureDescriptor {
-                label: Some(&label),
-                size: wgpu::Extent3d {
-                    width: texture_data.width,
-                    height: texture_data.height,
-                    depth_or_array_layers: 1,
-                },
-                // TODO: Generate mipmaps
-                mip_level_count: 1,
-                sample_count: 1,
-                dimension: wgpu::TextureDimension::D2,
-                format: get_texture_format_from_type(texture_type),
-                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-                view_formats: &[],
-            },
-            wgpu::wgt::TextureDataOrder::default(),
-            &texture_data.pixels,
-        );
+
+        let compressed = material_texture
+            .compressed
+            .as_ref()
+            .filter(|_| self.config.use_bc_texture_compression)
+            .and_then(|data| {
+                let format = get_compressed_texture_format(
+                    texture_type,
+                    material_texture.color_space,
+                    data,
+                )?;
+                Some((data, format))
+            });
+
+        let texture_index = self.textures.len();
+        let rgba = &material_texture.rgba;
+        let color_space = material_texture.color_space;
+
+        if streamed && rgba.width.max(rgba.height) > texture_streaming::BASE_SIZE {
+            let (format, source) = match compressed {
+                Some((data, format)) => (format, StreamingSource::Compressed(data.clone())),
+                None => (
+                    get_rgba_texture_format(color_space),
+                    StreamingSource::Rgba(rgba.clone()),
+                ),
+            };
+
+            let texture_entry = self.streamer.add(
+                &mut self.mipmap_generator,
+                &label,
+                texture_type,
+                texture_index,
+                format,
+                source,
+            );
+            self.textures.push(texture_entry);
+            return texture_index;
+        }
+
+        let texture = match compressed {
+            Some((data, format)) => self.create_compressed_texture(&label, format, data),
+            None => self.create_rgba_texture(&label, color_space, rgba),
+        };
 
         // TODO: Default view is probably not what we want
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
@@ -267,11 +968,135 @@ impl RenderMaterialManager {
             view,
         };
 
-        let texture_index = self.textures.len();
         self.textures.push(texture_entry);
         texture_index
     }
 
+    /// The adapter limits how many textures can be bound at once
+    fn is_full(&self) -> bool {
+        self.textures.len() >= self.config.max_texture_count as usize
+    }
+
+    fn default_texture(texture_type: TextureType) -> usize {
+        match texture_type {
+            TextureType::BaseColor | TextureType::RenderTarget => Self::DEFAULT_TEXTURE_BASE_COLOR,
+            TextureType::Normal => Self::DEFAULT_TEXTURE_NORMAL,
+            TextureType::AoRoughnessMetallic => Self::DEFAULT_TEXTURE_AO_ROUGHNESS_METALLIC,
+            TextureType::Emissive => Self::DEFAULT_TEXTURE_EMISSIVE,
+        }
+    }
+
+    fn create_rgba_texture(
+        &mut self,
+        label: &str,
+        color_space: ColorSpace,
+        texture_data: &gltf::image::Data,
+    ) -> wgpu::Texture {
+        let size = wgpu::Extent3d {
+            width: texture_data.width,
+            height: texture_data.height,
+            depth_or_array_layers: 1,
+        };
+
+        let texture = self.device.create_texture(&TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: mipmap_generator::mip_level_count(
+                texture_data.width,
+                texture_data.height,
+            ),
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: get_rgba_texture_format(color_space),
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+
+        self.queue.write_texture(
+            TexelCopyTextureInfo {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &texture_data.pixels,
+            TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(texture_data.width * 4),
+                rows_per_image: None,
+            },
+            size,
+        );
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Mipmap generation encoder"),
+            });
+        self.mipmap_generator.generate(&mut encoder, &texture);
+        self.queue.submit([encoder.finish()]);
+
+        texture
+    }
+
+    /// Compressed textures come with their own mips, because they can't be rendered to
+    fn create_compressed_texture(
+        &self,
+        label: &str,
+        format: wgpu::TextureFormat,
+        data: &CompressedTextureData,
+    ) -> wgpu::Texture {
+        let size = wgpu::Extent3d {
+            width: data.width,
+            height: data.height,
+            depth_or_array_layers: 1,
+        };
+
+        let texture = self.device.create_texture(&TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: data.mips.len() as u32,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        let (block_width, block_height) = format.block_dimensions();
+        let block_size = format
+            .block_copy_size(None)
+            .expect("Compressed formats have a block size");
+
+        for (mip, mip_data) in data.mips.iter().enumerate() {
+            let mip_size = size
+                .mip_level_size(mip as u32, wgpu::TextureDimension::D2)
+                .physical_size(format);
+            let blocks_wide = mip_size.width / block_width;
+            let blocks_high = mip_size.height / block_height;
+
+            self.queue.write_texture(
+                TexelCopyTextureInfo {
+                    texture: &texture,
+                    mip_level: mip as u32,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                mip_data,
+                TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(blocks_wide * block_size),
+                    rows_per_image: Some(blocks_high),
+                },
+                mip_size,
+            );
+        }
+
+        texture
+    }
+
     fn create_default_texture(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
@@ -298,6 +1123,9 @@ impl RenderMaterialManager {
             TextureType::BaseColor => [255, 0, 255, 255],
             TextureType::Normal => [127, 127, 255, 255],
             TextureType::AoRoughnessMetallic => [0, 255, 0, 255],
+            // Materials without an emissive texture have a zero emissive factor
+            TextureType::Emissive => [255, 255, 255, 255],
+            TextureType::RenderTarget => unreachable!("Render targets have no default texture"),
         };
 
         queue.write_texture(
@@ -330,10 +1158,78 @@ impl RenderMaterialManager {
     }
 }
 
+/// Picks the GPU format for a compressed texture, or None if the file's format doesn't make
+/// sense for the texture type. Textures are sampled in their `ColorSpace`, regardless of what
+/// the file says.
+fn get_compressed_texture_format(
+    texture_type: TextureType,
+    color_space: ColorSpace,
+    data: &CompressedTextureData,
+) -> Option<wgpu::TextureFormat> {
+    use ktx2::Format;
+
+    let srgb = color_space == ColorSpace::Srgb;
+    let bc7 = if srgb {
+        wgpu::TextureFormat::Bc7RgbaUnormSrgb
+    } else {
+        wgpu::TextureFormat::Bc7RgbaUnorm
+    };
+    let bc1 = if srgb {
+        wgpu::TextureFormat::Bc1RgbaUnormSrgb
+    } else {
+        wgpu::TextureFormat::Bc1RgbaUnorm
+    };
+
+    let format = match (texture_type, data.format) {
+        (
+            TextureType::BaseColor | TextureType::Emissive,
+            Format::BC7_UNORM_BLOCK | Format::BC7_SRGB_BLOCK,
+        ) => bc7,
+        (
+            TextureType::BaseColor | TextureType::Emissive,
+            Format::BC1_RGBA_UNORM_BLOCK | Format::BC1_RGBA_SRGB_BLOCK,
+        ) => bc1,
+        // The Z component of the normal is reconstructed in the shader. BC5 has no sRGB variant.
+        (TextureType::Normal, Format::BC5_UNORM_BLOCK) if !srgb => wgpu::TextureFormat::Bc5RgUnorm,
+        (TextureType::Normal | TextureType::AoRoughnessMetallic, Format::BC7_UNORM_BLOCK) => bc7,
+        (TextureType::AoRoughnessMetallic, Format::BC1_RGBA_UNORM_BLOCK) => bc1,
+        (texture_type, format) => {
+            log::warn!(
+                "Unsupported compressed format {:?} for {:?} texture, using RGBA8",
+                format,
+                texture_type
+            );
+            return None;
+        }
+    };
+
+    // wgpu requires the size of the first mip to be a multiple of the block size
+    let (block_width, block_height) = format.block_dimensions();
+    if data.width % block_width != 0 || data.height % block_height != 0 {
+        log::warn!(
+            "Compressed texture size {}x{} is not a multiple of the block size, using RGBA8",
+            data.width,
+            data.height
+        );
+        return None;
+    }
+
+    Some(format)
+}
+
+fn get_rgba_texture_format(color_space: ColorSpace) -> wgpu::TextureFormat {
+    match color_space {
+        ColorSpace::Srgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+        ColorSpace::Linear => wgpu::TextureFormat::Rgba8Unorm,
+    }
+}
+
 fn get_texture_format_from_type(texture_type: TextureType) -> wgpu::TextureFormat {
     match texture_type {
         TextureType::BaseColor => wgpu::TextureFormat::Rgba8UnormSrgb,
         TextureType::Normal => wgpu::TextureFormat::Rgba8Unorm,
         TextureType::AoRoughnessMetallic => wgpu::TextureFormat::Rgba8Unorm,
+        TextureType::Emissive => wgpu::TextureFormat::Rgba8UnormSrgb,
+        TextureType::RenderTarget => HDR_FORMAT,
     }
 }
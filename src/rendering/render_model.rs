@@ -1,6 +1,14 @@
 use std::mem::offset_of;
 
-use crate::model::Vertex;
+use crate::{
+    asset_pipeline::mesh_baker::VertexFormat,
+    model::{QuantizedVertex, Vertex},
+    rendering::shader_loader::{ShaderDefValue, ShaderDefines},
+};
+
+/// Defines of the shader variants that read `QuantizedVertex`es
+pub(crate) const QUANTIZED_VERTEX_DEFINES: ShaderDefines =
+    &[("QUANTIZED_VERTICES", ShaderDefValue::Bool(true))];
 
 pub const RENDER_MODEL_VBL: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
     array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
@@ -26,9 +34,66 @@ pub const RENDER_MODEL_VBL: wgpu::VertexBufferLayout<'static> = wgpu::VertexBuff
             shader_location: 3,
             format: wgpu::VertexFormat::Float32x3,
         },
+        wgpu::VertexAttribute {
+            offset: offset_of!(Vertex, joints) as wgpu::BufferAddress,
+            shader_location: 4,
+            format: wgpu::VertexFormat::Uint16x4,
+        },
+        wgpu::VertexAttribute {
+            offset: offset_of!(Vertex, weights) as wgpu::BufferAddress,
+            shader_location: 5,
+            format: wgpu::VertexFormat::Float32x4,
+        },
     ],
 };
 
+/// Same locations as `RENDER_MODEL_VBL`, but the normal and the tangent are octahedral encoded
+/// vec2s that the shader decodes
+pub const QUANTIZED_RENDER_MODEL_VBL: wgpu::VertexBufferLayout<'static> =
+    wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<QuantizedVertex>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Vertex,
+        attributes: &[
+            wgpu::VertexAttribute {
+                offset: offset_of!(QuantizedVertex, position) as wgpu::BufferAddress,
+                shader_location: 0,
+                format: wgpu::VertexFormat::Float32x3,
+            },
+            wgpu::VertexAttribute {
+                offset: offset_of!(QuantizedVertex, normal) as wgpu::BufferAddress,
+                shader_location: 1,
+                format: wgpu::VertexFormat::Snorm16x2,
+            },
+            wgpu::VertexAttribute {
+                offset: offset_of!(QuantizedVertex, tex_coords) as wgpu::BufferAddress,
+                shader_location: 2,
+                format: wgpu::VertexFormat::Float16x2,
+            },
+            wgpu::VertexAttribute {
+                offset: offset_of!(QuantizedVertex, tangent) as wgpu::BufferAddress,
+                shader_location: 3,
+                format: wgpu::VertexFormat::Snorm16x2,
+            },
+            wgpu::VertexAttribute {
+                offset: offset_of!(QuantizedVertex, joints) as wgpu::BufferAddress,
+                shader_location: 4,
+                format: wgpu::VertexFormat::Uint16x4,
+            },
+            wgpu::VertexAttribute {
+                offset: offset_of!(QuantizedVertex, weights) as wgpu::BufferAddress,
+                shader_location: 5,
+                format: wgpu::VertexFormat::Unorm8x4,
+            },
+        ],
+    };
+
+pub const fn render_model_vbl(vertex_format: VertexFormat) -> wgpu::VertexBufferLayout<'static> {
+    match vertex_format {
+        VertexFormat::Full => RENDER_MODEL_VBL,
+        VertexFormat::Quantized => QUANTIZED_RENDER_MODEL_VBL,
+    }
+}
+
 pub const MODEL_PRIMITIVE_STATE: wgpu::PrimitiveState = wgpu::PrimitiveState {
     topology: wgpu::PrimitiveTopology::TriangleList,
     strip_index_format: None,
@@ -0,0 +1,98 @@
+use serde::{Deserialize, Serialize};
+
+use crate::rendering::{
+    post_process::targets::HDR_FORMAT,
+    render_material_manager::RenderMaterialManager,
+    util::mipmap_generator::{self, MipmapGenerator},
+};
+
+/// Offscreen texture declared in the scene file, which materials can sample by name
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenderTargetDescription {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+struct DynamicTexture {
+    texture: wgpu::Texture,
+    /// Mip 0 only, for rendering into
+    target_view: wgpu::TextureView,
+}
+
+/// Textures that are rendered at runtime and sampled by materials like any other texture. Each
+/// target is a copy of the lit HDR scene, captured before debug drawing and post processing.
+/// Materials see the previous frame, so a surface showing a target also appears in it, which
+/// gives the recursive feedback effect of a camera filming its own monitor.
+pub struct RenderTargetManager {
+    targets: Vec<DynamicTexture>,
+    mipmap_generator: MipmapGenerator,
+}
+
+impl RenderTargetManager {
+    /// The targets are registered to `material_manager`, so this must be called before any
+    /// materials are loaded
+    pub fn new(
+        device: &wgpu::Device,
+        material_manager: &mut RenderMaterialManager,
+        descriptions: &[RenderTargetDescription],
+    ) -> anyhow::Result<Self> {
+        let targets = descriptions
+            .iter()
+            .map(|description| {
+                let width = description.width.max(1);
+                let height = description.height.max(1);
+                let texture = device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some(&format!("Render target: {}", description.name)),
+                    size: wgpu::Extent3d {
+                        width,
+                        height,
+                        depth_or_array_layers: 1,
+                    },
+                    // Surfaces far from the camera would alias badly without mips
+                    mip_level_count: mipmap_generator::mip_level_count(width, height),
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: HDR_FORMAT,
+                    usage: wgpu::TextureUsages::TEXTURE_BINDING
+                        | wgpu::TextureUsages::RENDER_ATTACHMENT,
+                    view_formats: &[],
+                });
+
+                let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+                let target_view = texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("Render target mip 0"),
+                    base_mip_level: 0,
+                    mip_level_count: Some(1),
+                    ..Default::default()
+                });
+
+                material_manager.register_render_target(&description.name, &texture, &view)?;
+
+                Ok(DynamicTexture {
+                    texture,
+                    target_view,
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self {
+            targets,
+            mipmap_generator: MipmapGenerator::new(device),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+
+    /// Copies the scene color to every target. The scene is downsampled with a single bilinear
+    /// tap, which is fine for the small targets this is meant for.
+    pub fn capture(&mut self, encoder: &mut wgpu::CommandEncoder, scene_color: &wgpu::TextureView) {
+        for target in &self.targets {
+            self.mipmap_generator
+                .blit(encoder, scene_color, &target.target_view, HDR_FORMAT);
+            self.mipmap_generator.generate(encoder, &target.texture);
+        }
+    }
+}
@@ -1,143 +1,363 @@
-use std::sync::Arc;
+use std::sync::{mpsc, Arc};
 
+use anyhow::Context;
+use glam::{Mat4, Vec3};
 use wgpu::CommandEncoderDescriptor;
 use winit::window::Window;
 
 use crate::{
-    asset_pipeline::mesh_baker::BakedMeshes,
+    asset_pipeline::mesh_baker::{BakeOptions, BakedMeshes},
     demo::DemoState,
+    material_manager::MaterialManager,
     math::frustum::Frustum,
     rendering::{
+        adapter::{device_limits, log_adapter_info, request_adapter, required_features},
         common::Resolution,
-        config::RenderConfig,
+        config::{RenderConfig, RendererOptions, TransparencyMode},
+        debug_draw::{DebugDraw, DebugVisualizations},
         deferred::{
+            decal_pass::{DecalPass, DecalPassTextureViews},
             gbuffer::GBuffer,
             geometry_pass::{GeometryPass, GeometryPassTextureViews},
+            lighting_pass::{LightingPass, LightingPassTextureViews},
+            sdf_pass::{SdfPass, SdfPassTextureViews},
+            ssao_pass::SsaoPass,
+            ssr_pass::{SsrCompositeTextureViews, SsrPass, SsrTraceTextureViews},
+            volumetric_fog_pass::{VolumetricFogPass, VolumetricFogPassTextureViews},
         },
+        environment_map::EnvironmentMap,
         global_uniform::GlobalUniformState,
+        gpu_profiler::GpuProfiler,
         imgui_renderer::{create_imgui_renderer, ImguiRendererState},
         instancing::{DrawableBuffers, DrawableManager},
+        joint_buffer::JointBuffer,
+        light_buffer::LightBuffer,
         mesh_buffers::MeshBuffers,
+        oit::{
+            accumulation_pass::{AccumulationPass, AccumulationPassTextureViews},
+            composite_pass::{CompositePass, CompositePassTextureViews},
+            oit_targets::OitTargets,
+        },
+        particle_system::ParticleSystem,
         passes::{
             background_pass::{BackgroundPass, BackgroundPassTextureViews},
-            pbr_pass::{PbrPass, PbrTextureViews},
+            blur_pass::BlurPass,
+            debug_draw_pass::{DebugDrawPass, DebugDrawPassTextureViews},
+            hi_z_pass::HiZPass,
+            light_culling_pass::LightCullingPass,
+            particle_pass::{ParticlePass, ParticlePassTextureViews},
             render_pass_context::{
-                ComputePassCreationContext, PassCreationContext, RenderPassContext,
-                RenderPassCreationContext,
+                PassCreationContext, RenderPassContext, RenderPassCreationContext,
             },
+            shadow_pass::ShadowPass,
+            skinning_pass::SkinningPass,
+            skybox_pass::{SkyboxPass, SkyboxPassTextureViews},
+            taa_pass::{TaaPass, TaaPassTextureViews},
+            text_pass::{TextPass, TextPassTextureViews},
+            transparent_pass::{TransparentPass, TransparentPassTextureViews},
+        },
+        pipeline_disk_cache::PipelineDiskCache,
+        post_process::{
+            bloom::BloomPass,
+            color_grading::ColorLut,
+            depth_of_field::{DepthOfFieldPass, DepthOfFieldPassTextureViews},
+            lens_artifacts::LensArtifactsPass,
+            targets::PingPongTargets,
+            tonemap::TonemapPass,
+            upscale::{scale_resolution, UpscalePass},
+            PostProcessChain, PostProcessContext,
         },
+        reflection_probes::ReflectionProbes,
         render_camera::RenderCamera,
         render_common::RenderCommon,
+        render_graph::{ClearValue, RenderGraph, TransientTextures},
         render_material_manager::RenderMaterialManager,
-        shader_loader::{
-            ComputeShaderLoader, PipelineCacheBuilder, RenderShaderLoader, ShaderLoader,
-        },
-        texture::DepthTexture,
+        render_target_manager::RenderTargetManager,
+        shader_loader::{PipelineCacheBuilder, ShaderErrors, ShaderLoader},
     },
 };
 
+const ENVIRONMENT_MAP_PATH: &str = "assets/environment/sky.hdr";
+const COLOR_GRADING_LUT_PATH: &str = "assets/luts/warm.cube";
+const FONT_PATH: &str = "assets/fonts/DejaVuSans-Bold.ttf";
+
+/// Where finished frames go
+enum RenderOutput {
+    Window {
+        window: Arc<Window>,
+        surface: wgpu::Surface<'static>,
+    },
+    /// Rendering offline, frames are read back with `Renderer::read_output`
+    Texture(wgpu::Texture),
+}
+
+/// Reported by wgpu callbacks, which can run on any thread
+#[derive(Debug)]
+pub enum DeviceError {
+    /// The device can't be used anymore, so the renderer has to be recreated
+    Lost(String),
+    /// An error that no error scope caught, e.g. a validation error
+    Uncaptured(String),
+}
+
 // TODO: this is a huge mess
 pub struct Renderer {
-    pub window: Arc<Window>,
+    /// Size of the output
     pub size: Resolution,
-    surface: wgpu::Surface<'static>,
+    /// Size the scene is rendered at, before it's scaled to the output
+    internal_size: Resolution,
+    output: RenderOutput,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
-    #[allow(dead_code)]
     pub config: &'static RenderConfig,
+    device_errors: mpsc::Receiver<DeviceError>,
 
     g_buffer: GBuffer,
+    /// Textures that the render graph creates for a single frame
+    transient_textures: TransientTextures,
     pub common: Arc<RenderCommon>,
-    depth_texture: DepthTexture,
     camera: RenderCamera,
     imgui: ImguiRendererState,
     pub material_manager: RenderMaterialManager,
+    render_target_manager: RenderTargetManager,
 
-    _mesh_buffers: Arc<MeshBuffers>,
+    mesh_buffers: Arc<MeshBuffers>,
     _drawable_buffers: Arc<DrawableBuffers>,
+    light_buffer: Arc<LightBuffer>,
+    environment_map: EnvironmentMap,
+    /// The environment map is generated on the first frame after the pipelines are ready
+    environment_map_generated: bool,
+    reflection_probes: ReflectionProbes,
+    gpu_profiler: GpuProfiler,
 
-    render_shader_loader: RenderShaderLoader,
+    shader_loader: ShaderLoader,
+    /// Saved and cleared once every pipeline has been compiled
+    pipeline_disk_cache: Option<PipelineDiskCache>,
+    /// Shown in an overlay until the failed shaders compile
+    shader_errors: ShaderErrors,
     background_pass: BackgroundPass,
-    pbr_pass: PbrPass,
+    skybox_pass: SkyboxPass,
+    shadow_pass: ShadowPass,
     geometry_pass: GeometryPass,
+    sdf_pass: SdfPass,
+    decal_pass: DecalPass,
+    ssao_pass: SsaoPass,
+    lighting_pass: LightingPass,
+    ssr_pass: SsrPass,
+    volumetric_fog_pass: VolumetricFogPass,
+    transparent_pass: TransparentPass,
+    oit_accumulation_pass: AccumulationPass,
+    oit_composite_pass: CompositePass,
+    particle_pass: ParticlePass,
+    text_pass: TextPass,
+    taa_pass: TaaPass,
+    depth_of_field_pass: DepthOfFieldPass,
+    debug_draw_pass: DebugDrawPass,
+    post_process: PostProcessChain,
+    upscale_pass: UpscalePass,
+    /// Shared by the effects that blur their results
+    blur_pass: BlurPass,
+
+    /// Shapes drawn here during a frame are rendered on top of the scene
+    pub debug_draw: DebugDraw,
+    debug_visualizations: DebugVisualizations,
 
-    compute_shader_loader: ComputeShaderLoader,
+    hi_z_pass: HiZPass,
+    skinning_pass: SkinningPass,
+    light_culling_pass: LightCullingPass,
+    particle_system: ParticleSystem,
     instance_manager: DrawableManager,
+    /// View projection of the previous frame, or None if its depth buffer isn't usable
+    previous_view_proj: Option<Mat4>,
 }
 
 impl Renderer {
     pub async fn new(
         window: Arc<Window>,
         demo_state: &DemoState,
+        material_manager: &MaterialManager,
         baked_primitives: &BakedMeshes,
         imgui_context: &mut imgui::Context,
+        options: &RendererOptions,
     ) -> anyhow::Result<Renderer> {
         let size = window.inner_size();
 
         let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
-        let surface = instance.create_surface(window.clone()).unwrap();
+        let surface = instance
+            .create_surface(window.clone())
+            .context("Failed to create surface")?;
 
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::HighPerformance,
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            })
-            .await
-            .unwrap();
+        Self::create(
+            &instance,
+            Some((window, surface)),
+            size,
+            demo_state,
+            material_manager,
+            baked_primitives,
+            imgui_context,
+            options,
+        )
+        .await
+    }
+
+    /// Renders to an offscreen texture instead of a window, e.g. for producing videos. ImGui is
+    /// still updated, but not drawn.
+    pub async fn new_headless(
+        size: winit::dpi::PhysicalSize<u32>,
+        demo_state: &DemoState,
+        material_manager: &MaterialManager,
+        baked_primitives: &BakedMeshes,
+        imgui_context: &mut imgui::Context,
+        options: &RendererOptions,
+    ) -> anyhow::Result<Renderer> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+
+        Self::create(
+            &instance,
+            None,
+            size,
+            demo_state,
+            material_manager,
+            baked_primitives,
+            imgui_context,
+            options,
+        )
+        .await
+    }
+
+    async fn create(
+        instance: &wgpu::Instance,
+        window: Option<(Arc<Window>, wgpu::Surface<'static>)>,
+        size: winit::dpi::PhysicalSize<u32>,
+        demo_state: &DemoState,
+        demo_material_manager: &MaterialManager,
+        baked_primitives: &BakedMeshes,
+        imgui_context: &mut imgui::Context,
+        options: &RendererOptions,
+    ) -> anyhow::Result<Renderer> {
+        let surface = window.as_ref().map(|(_, surface)| surface);
+
+        let adapter = request_adapter(instance, surface, options.adapter_name.as_deref()).await?;
+        log_adapter_info(&adapter);
+        let render_targets = demo_state
+            .parts
+            .iter()
+            .flat_map(|part| part.scene.render_targets.iter().cloned())
+            .collect::<Vec<_>>();
+        let required_limits = device_limits(
+            &adapter,
+            RenderMaterialManager::required_texture_count(
+                demo_material_manager,
+                render_targets.len(),
+            ),
+        )?;
+
+        let mut config = RenderConfig::with_quality(options.quality.clone());
+        config.max_texture_count = required_limits.max_binding_array_elements_per_shader_stage;
+        config.transparency = options.transparency;
 
-        let mut config = RenderConfig::default();
         let indirect_draw_count_feature = wgpu::Features::MULTI_DRAW_INDIRECT_COUNT;
-        let mut required_features = wgpu::Features::MULTI_DRAW_INDIRECT
-            | wgpu::Features::INDIRECT_FIRST_INSTANCE
-            | wgpu::Features::TEXTURE_BINDING_ARRAY
-            | wgpu::Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING;
+        let mut required_features = required_features();
 
         if adapter.features().contains(indirect_draw_count_feature) {
             config.use_multi_draw_indirect_count = true;
             required_features |= indirect_draw_count_feature;
         }
 
+        if adapter
+            .features()
+            .contains(wgpu::Features::TEXTURE_COMPRESSION_BC)
+        {
+            config.use_bc_texture_compression = true;
+            required_features |= wgpu::Features::TEXTURE_COMPRESSION_BC;
+        }
+
+        let timestamp_features =
+            wgpu::Features::TIMESTAMP_QUERY | wgpu::Features::TIMESTAMP_QUERY_INSIDE_ENCODERS;
+        if adapter.features().contains(timestamp_features) {
+            config.use_gpu_timestamps = true;
+            required_features |= timestamp_features;
+        }
+
+        if adapter.features().contains(wgpu::Features::PIPELINE_CACHE) {
+            required_features |= wgpu::Features::PIPELINE_CACHE;
+        }
+
         let config = Box::leak(Box::new(config));
 
         let (device, queue) = adapter
             .request_device(&wgpu::DeviceDescriptor {
                 required_features,
-                required_limits: wgpu::Limits {
-                    max_binding_array_elements_per_shader_stage: 128,
-                    ..Default::default()
-                },
+                required_limits,
                 label: None,
                 memory_hints: Default::default(),
                 trace: wgpu::Trace::Off,
             })
             .await
-            .unwrap();
+            .context("Failed to create device")?;
 
-        let camera = RenderCamera::new(&device, demo_state.camera.clone(), size);
+        let (device_error_sender, device_errors) = mpsc::channel();
+        let lost_sender = device_error_sender.clone();
+        device.set_device_lost_callback(move |reason, message| {
+            // Dropping the renderer destroys the device on purpose
+            if !matches!(reason, wgpu::DeviceLostReason::Destroyed) {
+                let _ = lost_sender.send(DeviceError::Lost(format!("{:?}: {}", reason, message)));
+            }
+        });
+        // The default handler panics
+        device.on_uncaptured_error(Box::new(move |error| {
+            log::error!("Uncaptured wgpu error: {}", error);
+            let _ = device_error_sender.send(DeviceError::Uncaptured(error.to_string()));
+        }));
+
+        // Everything that renders the scene uses the internal size, only the output is `size`
+        let internal_size = scale_resolution(size, config.quality.resolution_scale);
+
+        let camera = RenderCamera::new(
+            &device,
+            demo_state.parts.active().cameras.active().clone(),
+            internal_size,
+        );
 
         let common = RenderCommon::new(
             &device,
             &adapter,
-            &surface,
+            surface,
             size,
+            config.present_mode,
             camera.uniform_buffer.clone(),
         );
         let common = Arc::new(common);
 
-        let depth_texture = DepthTexture::new(&device, size, "Depth Texture");
+        let output = match window {
+            Some((window, surface)) => RenderOutput::Window { window, surface },
+            None => RenderOutput::Texture(create_output_texture(
+                &device,
+                &common.output_surface_config.read().unwrap(),
+            )),
+        };
 
-        let material_manager = RenderMaterialManager::new(&device, &queue);
+        let mut material_manager = RenderMaterialManager::new(&device, &queue, config);
+        let render_target_manager =
+            RenderTargetManager::new(&device, &mut material_manager, &render_targets)?;
+        // Render targets have to be registered before the materials that use them are loaded
+        material_manager.load_all_materials(
+            demo_material_manager,
+            demo_state.parts.iter().map(|part| &part.scene),
+        );
 
-        let g_buffer = GBuffer::new(&device, size);
+        let g_buffer = GBuffer::new(&device, internal_size);
+        let transient_textures = TransientTextures::new(&device);
 
-        let mut render_pipeline_cache_builder = PipelineCacheBuilder::new();
-        let mut compute_pipeline_cache_builder = PipelineCacheBuilder::new();
+        let mut pipeline_cache_builder = PipelineCacheBuilder::new();
 
         let mesh_buffers = MeshBuffers::new(&device, baked_primitives);
         let mesh_buffers = Arc::new(mesh_buffers);
-        let drawable_buffers = DrawableBuffers::new_default_capacity(&device);
+        let drawable_buffers =
+            DrawableBuffers::new_default_capacity(&device, mesh_buffers.has_meshlets());
         let drawable_buffers = Arc::new(drawable_buffers);
+        let light_buffer = Arc::new(LightBuffer::new(&device));
+        let joint_buffer = Arc::new(JointBuffer::new(&device));
 
         let pass_creation_context = PassCreationContext {
             device: device.clone(),
@@ -145,28 +365,116 @@ impl Renderer {
             common: common.clone(),
             drawable_buffers: drawable_buffers.clone(),
             mesh_buffers: mesh_buffers.clone(),
+            light_buffer: light_buffer.clone(),
+            joint_buffer,
         };
 
         let mut render_pass_context = RenderPassCreationContext {
             shared: &pass_creation_context,
-            cache_builder: &mut render_pipeline_cache_builder,
+            cache_builder: &mut pipeline_cache_builder,
             material_manager: &material_manager,
             camera_uniform_buffer: &camera.uniform_buffer,
         };
 
-        let mut compute_pass_context = ComputePassCreationContext {
-            shared: &pass_creation_context,
-            cache_builder: &mut compute_pipeline_cache_builder,
-        };
+        let environment_map =
+            EnvironmentMap::new(&mut render_pass_context, &queue, ENVIRONMENT_MAP_PATH);
+        let particle_system = ParticleSystem::new(&mut render_pass_context);
 
         let background_pass = BackgroundPass::create(&mut render_pass_context)?;
-        let pbr_pass = PbrPass::new(&mut render_pass_context);
+        let skybox_pass = SkyboxPass::new(&mut render_pass_context, &environment_map);
+        let shadow_pass = ShadowPass::new(&mut render_pass_context);
+        let reflection_probes =
+            ReflectionProbes::new(&mut render_pass_context, &shadow_pass, &environment_map);
         let geometry_pass = GeometryPass::new(&mut render_pass_context);
-        let render_shader_loader = ShaderLoader::new(device.clone(), render_pipeline_cache_builder);
+        let sdf_pass = SdfPass::new(&mut render_pass_context);
+        let decal_pass = DecalPass::new(&mut render_pass_context, &g_buffer);
+        let ssao_pass = SsaoPass::new(&mut render_pass_context, &queue, &g_buffer, internal_size);
+        let lighting_pass = LightingPass::new(
+            &mut render_pass_context,
+            &g_buffer,
+            &ssao_pass,
+            &shadow_pass,
+            &environment_map,
+            &reflection_probes,
+        );
+        let volumetric_fog_pass = VolumetricFogPass::new(
+            &mut render_pass_context,
+            &g_buffer,
+            &shadow_pass,
+            internal_size,
+        );
+        let transparent_pass =
+            TransparentPass::new(&mut render_pass_context, &shadow_pass, &environment_map);
+        let oit_accumulation_pass =
+            AccumulationPass::new(&mut render_pass_context, &shadow_pass, &environment_map);
+        let oit_composite_pass = CompositePass::new(&mut render_pass_context);
+        let particle_pass = ParticlePass::new(&mut render_pass_context, &particle_system);
+        let text_pass = TextPass::new(&mut render_pass_context, &queue, FONT_PATH);
+        let debug_draw_pass = DebugDrawPass::new(&mut render_pass_context);
+        let blur_pass = BlurPass::new(&mut render_pass_context);
 
-        let instance_manager = DrawableManager::new(&mut compute_pass_context);
-        let compute_shader_loader =
-            ShaderLoader::new(device.clone(), compute_pipeline_cache_builder);
+        let post_process_targets = PingPongTargets::new(&device, internal_size);
+        let target_layout = &post_process_targets.sampling.bind_group_layout;
+        let color_grading_lut = ColorLut::load(COLOR_GRADING_LUT_PATH).unwrap_or_else(|e| {
+            log::warn!(
+                "Failed to load color grading LUT, using the identity: {:?}",
+                e
+            );
+            ColorLut::identity()
+        });
+        let tonemap_pass = TonemapPass::new(
+            &mut render_pass_context,
+            &queue,
+            target_layout,
+            &color_grading_lut,
+        );
+        let bloom_pass = BloomPass::new(&mut render_pass_context, target_layout, internal_size);
+        let lens_artifacts_pass = LensArtifactsPass::new(&mut render_pass_context, target_layout);
+        let mut post_process = PostProcessChain::new(post_process_targets, tonemap_pass);
+        post_process.add_pass(Box::new(bloom_pass));
+        post_process.add_pass(Box::new(lens_artifacts_pass));
+        let upscale_pass = UpscalePass::new(
+            &mut render_pass_context,
+            size,
+            config.quality.resolution_scale,
+        );
+
+        let ssr_pass = SsrPass::new(
+            &mut render_pass_context,
+            &g_buffer,
+            post_process.scene_target(),
+            &environment_map,
+        );
+        let taa_pass = TaaPass::new(
+            &mut render_pass_context,
+            &g_buffer,
+            post_process.scene_target(),
+            internal_size,
+        );
+        let depth_of_field_pass = DepthOfFieldPass::new(
+            &mut render_pass_context,
+            &g_buffer,
+            post_process.scene_target(),
+            internal_size,
+        );
+
+        let hi_z_pass = HiZPass::new(&mut render_pass_context, &g_buffer.depth, internal_size);
+        let skinning_pass = SkinningPass::new(&mut render_pass_context);
+        let light_culling_pass = LightCullingPass::new(&mut render_pass_context);
+        let instance_manager = DrawableManager::new(&mut render_pass_context, &hi_z_pass);
+
+        let shader_errors = ShaderErrors::default();
+        let pipeline_disk_cache = PipelineDiskCache::load(&device, &adapter.get_info());
+        let shader_loader = ShaderLoader::new(
+            device.clone(),
+            pipeline_cache_builder,
+            shader_errors.clone(),
+            pipeline_disk_cache
+                .as_ref()
+                .map(|disk_cache| disk_cache.cache().clone()),
+        );
+
+        let gpu_profiler = GpuProfiler::new(&device, &queue, config.use_gpu_timestamps);
 
         let imgui = create_imgui_renderer(
             &device,
@@ -176,29 +484,63 @@ impl Renderer {
         );
 
         Ok(Self {
-            window: window.clone(),
-            surface,
+            output,
             device,
             queue,
             config,
+            device_errors,
 
             g_buffer,
+            transient_textures,
             common,
             size,
+            internal_size,
             camera,
-            depth_texture,
             imgui,
-            _mesh_buffers: mesh_buffers,
+            mesh_buffers,
             material_manager,
+            render_target_manager,
 
-            render_shader_loader,
+            shader_loader,
+            pipeline_disk_cache,
+            shader_errors,
             background_pass,
-            pbr_pass,
+            skybox_pass,
+            shadow_pass,
             geometry_pass,
+            sdf_pass,
+            decal_pass,
+            ssao_pass,
+            lighting_pass,
+            ssr_pass,
+            volumetric_fog_pass,
+            transparent_pass,
+            oit_accumulation_pass,
+            oit_composite_pass,
+            particle_pass,
+            text_pass,
+            taa_pass,
+            depth_of_field_pass,
+            debug_draw_pass,
+            post_process,
+            upscale_pass,
+            blur_pass,
 
-            compute_shader_loader,
+            debug_draw: DebugDraw::new(),
+            debug_visualizations: DebugVisualizations::default(),
+
+            hi_z_pass,
+            skinning_pass,
+            light_culling_pass,
+            particle_system,
             instance_manager,
+            previous_view_proj: None,
             _drawable_buffers: drawable_buffers,
+            light_buffer,
+            environment_map,
+            environment_map_generated: false,
+            reflection_probes,
+            gpu_profiler,
         })
     }
 
@@ -210,10 +552,99 @@ impl Renderer {
             self.size = new_size;
             config.width = new_size.width;
             config.height = new_size.height;
-            self.depth_texture.resize(&self.device, new_size);
-            self.surface.configure(&self.device, &config);
-            self.camera.update_resolution(new_size);
-            self.g_buffer.resize(new_size);
+            match &mut self.output {
+                RenderOutput::Window { surface, .. } => surface.configure(&self.device, &config),
+                RenderOutput::Texture(texture) => {
+                    *texture = create_output_texture(&self.device, &config)
+                }
+            }
+            drop(config);
+            self.resize_internal();
+        }
+    }
+
+    /// Errors reported by the device since the last call
+    pub fn poll_device_errors(&self) -> Vec<DeviceError> {
+        self.device_errors.try_iter().collect()
+    }
+
+    pub fn present_mode(&self) -> wgpu::PresentMode {
+        self.common
+            .output_surface_config
+            .read()
+            .unwrap()
+            .present_mode
+    }
+
+    pub fn supported_present_modes(&self) -> &[wgpu::PresentMode] {
+        &self.common.supported_present_modes
+    }
+
+    /// Reconfigures the surface. Ignored when rendering offline, or if the surface doesn't
+    /// support the mode.
+    pub fn set_present_mode(&mut self, present_mode: wgpu::PresentMode) {
+        let RenderOutput::Window { surface, .. } = &self.output else {
+            return;
+        };
+
+        if !self.supported_present_modes().contains(&present_mode) {
+            log::warn!("Present mode {:?} is not supported", present_mode);
+            return;
+        }
+
+        let mut config = self.common.output_surface_config.write().unwrap();
+        config.present_mode = present_mode;
+        surface.configure(&self.device, &config);
+    }
+
+    /// Resizes everything that renders at the internal resolution, after the output size or the
+    /// resolution scale has changed
+    fn resize_internal(&mut self) {
+        let size = self.upscale_pass.internal_size(self.size);
+        self.internal_size = size;
+        self.upscale_pass.resize(self.size);
+
+        self.camera.update_resolution(size);
+        self.g_buffer.resize(size);
+        self.decal_pass.resize(&self.g_buffer);
+        self.ssao_pass.resize(&self.g_buffer, size);
+        self.lighting_pass.resize(&self.g_buffer, &self.ssao_pass);
+        self.volumetric_fog_pass.resize(&self.g_buffer, size);
+        self.hi_z_pass.resize(&self.g_buffer.depth, size);
+        self.instance_manager.resize_hi_z(&self.hi_z_pass);
+        self.previous_view_proj = None;
+        self.post_process.resize(size);
+        self.ssr_pass
+            .resize(&self.g_buffer, self.post_process.scene_target());
+        self.taa_pass
+            .resize(&self.g_buffer, self.post_process.scene_target(), size);
+        self.depth_of_field_pass
+            .resize(&self.g_buffer, self.post_process.scene_target(), size);
+    }
+
+    /// Meshes passed to `update_meshes` must be baked with these options
+    pub fn bake_options(&self) -> BakeOptions {
+        BakeOptions {
+            vertex_format: self.mesh_buffers.vertex_format(),
+            meshlets: self.mesh_buffers.has_meshlets(),
+            ..Default::default()
+        }
+    }
+
+    /// Uploads re-baked meshes, e.g. after a model has been hot reloaded or spawned
+    pub fn update_meshes(&mut self, baked_meshes: &BakedMeshes) {
+        // Reloaded models can change drawables without touching the transforms
+        self.instance_manager.invalidate();
+        self.reflection_probes.invalidate();
+        self.geometry_pass.invalidate_baked_ao();
+
+        if self
+            .mesh_buffers
+            .update(&self.device, &self.queue, baked_meshes)
+        {
+            self.skinning_pass.resize_meshes(&self.mesh_buffers);
+            self.geometry_pass.resize_meshes();
+            self.instance_manager.resize_meshes();
         }
     }
 
@@ -222,27 +653,81 @@ impl Renderer {
         demo_state: &mut DemoState,
         imgui_ui: &mut imgui::Ui,
     ) -> Result<RenderResult, wgpu::SurfaceError> {
-        self.render_shader_loader
+        self.shader_loader
             .load_pending_shaders()
             .expect("Failed to load pending shaders");
-        self.compute_shader_loader
-            .load_pending_shaders()
-            .expect("Failed to load pending compute shaders");
+        self.shader_errors.draw_ui(imgui_ui);
+        self.blur_pass.begin_frame();
 
-        self.camera.update_camera(&demo_state.camera);
+        // Shaders compile in the background, so the scene can't be drawn until all are done
+        if !self.shader_loader.is_ready() {
+            self.shader_loader.draw_progress_ui(imgui_ui);
+            return self.begin_ui_only_frame();
+        }
+
+        if let Some(mut disk_cache) = self.pipeline_disk_cache.take() {
+            if let Err(e) = disk_cache.save() {
+                log::warn!("Failed to save pipeline cache: {:?}", e);
+            }
+        }
+
+        // Runs the callbacks of finished GPU readbacks
+        let _ = self.device.poll(wgpu::PollType::Poll);
+        self.gpu_profiler.begin_frame();
+        self.gpu_profiler.draw_ui(imgui_ui);
+
+        // Before anything is rendered at the internal resolution
+        if self.upscale_pass.draw_ui(imgui_ui) {
+            self.resize_internal();
+        }
+
+        let part = demo_state.parts.active();
+        let scene = &part.scene;
+        let active_camera = part.cameras.active();
+        // Passes turned off by the part are skipped, but still show up in the UI
+        let passes = part.passes;
+
+        self.camera.update_camera(active_camera);
+        self.camera.set_jitter_enabled(self.taa_pass.is_enabled());
         self.camera.update_uniform_buffer(&self.queue);
+        let time = demo_state.time();
         self.common.global_uniform.update(
             &self.queue,
-            GlobalUniformState::new(self.size, demo_state.start_time.elapsed().as_secs_f32()),
+            GlobalUniformState::new(
+                self.internal_size,
+                time,
+                demo_state.beat_clock.beat(time),
+                demo_state.shader_params,
+            ),
         );
 
-        self.instance_manager
-            .update_from_scene(&demo_state.scene, &self.queue, imgui_ui);
+        self.material_manager.update_streaming(
+            scene,
+            active_camera,
+            self.internal_size.height as f32,
+        );
 
-        let output = self.surface.get_current_texture()?;
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+        self.instance_manager.update_from_scene(
+            scene,
+            active_camera.eye,
+            self.material_manager.material_count(),
+            &self.queue,
+            imgui_ui,
+        );
+        self.light_buffer.update_from_scene(scene, &self.queue);
+        self.particle_system
+            .update_from_scene(scene, &self.queue, demo_state.steps);
+        self.text_pass.update_from_scene(scene, &self.queue);
+        self.decal_pass.update_from_scene(scene, &self.queue);
+        self.reflection_probes.update_from_scene(scene, &self.queue);
+        self.geometry_pass.update_baked_ao(
+            &self.queue,
+            demo_state.parts.active_id(),
+            &part.baked_ao,
+            &self.instance_manager,
+        );
+
+        let (output, view) = self.acquire_output()?;
 
         let mut encoder = self
             .device
@@ -250,49 +735,568 @@ impl Renderer {
                 label: Some("Render Encoder"),
             });
 
-        let frustum = Frustum::from_view_projection(*self.camera.get_view_proj());
+        if !self.environment_map_generated {
+            self.environment_map
+                .generate(&mut encoder, &self.shader_loader.cache);
+            self.environment_map_generated = true;
+        }
+
+        // Skinned vertices are written before any pass reads the vertex buffer
+        if !self.instance_manager.skinning_jobs().is_empty() {
+            self.gpu_profiler.begin_scope(&mut encoder, "Skinning");
+            self.skinning_pass.dispatch(
+                &self.queue,
+                &mut encoder,
+                &self.shader_loader.cache,
+                self.instance_manager.skinning_jobs(),
+            );
+            self.gpu_profiler.end_scope(&mut encoder);
+        }
+
+        self.gpu_profiler
+            .begin_scope(&mut encoder, "Particle simulation");
+        self.particle_system
+            .dispatch(&mut encoder, &self.shader_loader.cache);
+        self.gpu_profiler.end_scope(&mut encoder);
+
+        // The G-buffer depth still contains the previous frame at this point
+        let hi_z_view_proj = self
+            .previous_view_proj
+            .filter(|_| self.instance_manager.occlusion_culling_enabled());
+        if hi_z_view_proj.is_some() {
+            self.gpu_profiler.begin_scope(&mut encoder, "Hi-Z");
+            self.hi_z_pass
+                .dispatch(&mut encoder, &self.shader_loader.cache);
+            self.gpu_profiler.end_scope(&mut encoder);
+        }
+
+        self.gpu_profiler.begin_scope(&mut encoder, "Transforms");
+        self.instance_manager
+            .dispatch_transforms(&mut encoder, &self.shader_loader.cache);
+        self.gpu_profiler.end_scope(&mut encoder);
+
+        let view_proj = *self.camera.get_view_proj();
+        let frustum = Frustum::from_view_projection(view_proj);
+        self.gpu_profiler.begin_scope(&mut encoder, "Culling");
         self.instance_manager.cull_and_generate_commands(
             &self.queue,
             &mut encoder,
-            &self.compute_shader_loader.cache,
+            &self.shader_loader.cache,
             &frustum,
+            active_camera.eye,
+            hi_z_view_proj,
         );
+        self.gpu_profiler.end_scope(&mut encoder);
+        self.previous_view_proj = Some(view_proj);
 
-        let pipeline_cache = &self.render_shader_loader.cache;
+        self.light_buffer.update_clusters(
+            active_camera,
+            &frustum,
+            self.internal_size.width as f32 / self.internal_size.height as f32,
+            &self.queue,
+        );
+        self.gpu_profiler.begin_scope(&mut encoder, "Light culling");
+        self.light_culling_pass
+            .dispatch(&mut encoder, &self.shader_loader.cache);
+        self.gpu_profiler.end_scope(&mut encoder);
 
-        self.background_pass.render(
-            &BackgroundPassTextureViews {
-                color: view.clone(),
-            },
+        let primary_light = scene.primary_directional_light();
+        let light_direction = primary_light
+            .and_then(|id| scene.get_object_transform(id))
+            .map(|transform| transform.get_world_matrix().transform_vector3(Vec3::Z))
+            .unwrap_or(Vec3::NEG_Y);
+        let light_radiance = primary_light
+            .and_then(|id| scene.get_object(id))
+            .and_then(|object| object.light.as_ref())
+            .map(|light| light.color * light.intensity)
+            .unwrap_or(Vec3::ZERO);
+        self.shadow_pass.update(
+            &self.queue,
+            active_camera,
+            self.internal_size.width as f32 / self.internal_size.height as f32,
+            light_direction,
+        );
+        self.gpu_profiler
+            .begin_scope(&mut encoder, "Shadow culling");
+        self.instance_manager.cull_and_generate_shadow_commands(
+            &self.queue,
             &mut encoder,
-            pipeline_cache,
+            &self.shader_loader.cache,
+            &Frustum::from_view_projection(self.shadow_pass.culling_view_proj()),
+            active_camera.eye,
         );
+        self.gpu_profiler.end_scope(&mut encoder);
 
-        let mut pass_context = RenderPassContext {
-            encoder: &mut encoder,
-            pipeline_cache,
-            draw_commands_buffer: self.instance_manager.draw_commands_buffer(),
-            draw_commands_count_buffer: self.instance_manager.draw_commands_count_buffer(),
-            material_manager: &mut self.material_manager,
-        };
+        // Passes can't be borrowed for the UI once they've been added to the graph
+        self.shadow_pass.draw_ui(imgui_ui);
+        self.sdf_pass.draw_ui(imgui_ui);
+        self.decal_pass.draw_ui(imgui_ui);
+        self.ssao_pass.draw_ui(imgui_ui);
+        self.ssr_pass.draw_ui(imgui_ui);
+        self.volumetric_fog_pass.draw_ui(imgui_ui);
+        self.taa_pass.draw_ui(imgui_ui);
+        self.depth_of_field_pass.draw_ui(imgui_ui);
+        self.material_manager.draw_ui(imgui_ui);
+        self.debug_visualizations.draw_ui(imgui_ui, view_proj);
+        self.debug_visualizations.draw(
+            &mut self.debug_draw,
+            scene,
+            self.shadow_pass.cascade_view_projs(),
+        );
+        self.post_process
+            .draw_ui(imgui_ui, &mut demo_state.post_process);
 
-        self.pbr_pass.render_indirect(
-            &PbrTextureViews {
-                color: view.clone(),
-                depth: self.depth_texture.view().clone(),
-            },
-            &mut pass_context,
+        let mut graph = RenderGraph::default();
+        let black = Some(ClearValue::Color(wgpu::Color::BLACK));
+        let scene_color = graph.import_texture(
+            "Scene color",
+            self.post_process.scene_target().view(),
+            black,
         );
+        let color_roughness =
+            graph.import_texture("G-buffer color", &self.g_buffer.color_roughness.view, black);
+        let normal_metallic = graph.import_texture(
+            "G-buffer normal",
+            &self.g_buffer.normal_metallic.view,
+            black,
+        );
+        let emission =
+            graph.import_texture("G-buffer emission", &self.g_buffer.emission.view, black);
+        let velocity =
+            graph.import_texture("G-buffer velocity", &self.g_buffer.velocity.view, black);
+        let depth = graph.import_texture(
+            "G-buffer depth",
+            self.g_buffer.depth.view(),
+            Some(ClearValue::Depth(1.0)),
+        );
+        // The shadow map and the SSAO target are cleared by their passes
+        let shadow_map =
+            graph.import_texture("Shadow map", self.shadow_pass.shadow_map_view(), None);
+        let ambient_occlusion = graph.import_texture("SSAO", self.ssao_pass.output_view(), None);
+        let output_texture = graph.import_texture("Output", &view, None);
+
+        // The procedural background is used when there's no environment to show
+        let skybox_pass = &self.skybox_pass;
+        let background_pass = &self.background_pass;
+        let environment_map_loaded = self.environment_map.is_loaded();
+        graph
+            .add_pass("Background")
+            .write(scene_color)
+            .execute(move |context, resources| {
+                let color = resources.color_target(scene_color);
+                if environment_map_loaded {
+                    skybox_pass.render(
+                        &SkyboxPassTextureViews { color },
+                        context.encoder,
+                        context.pipeline_cache,
+                    );
+                } else {
+                    background_pass.render(
+                        &BackgroundPassTextureViews { color },
+                        context.encoder,
+                        context.pipeline_cache,
+                    );
+                }
+            });
+
+        let shadow_pass = &self.shadow_pass;
+        let shadow_draw_commands_buffer = self.instance_manager.shadow_draw_commands_buffer();
+        let shadow_draw_commands_count_buffer =
+            self.instance_manager.shadow_draw_commands_count_buffer();
+        graph
+            .add_pass("Shadows")
+            .write(shadow_map)
+            .execute(move |context, _| {
+                shadow_pass.render_indirect(&mut RenderPassContext {
+                    encoder: context.encoder,
+                    pipeline_cache: context.pipeline_cache,
+                    draw_commands_buffer: shadow_draw_commands_buffer,
+                    draw_commands_count_buffer: shadow_draw_commands_count_buffer,
+                    material_manager: context.material_manager,
+                    blur_pass: context.blur_pass,
+                });
+            });
+
+        // Lit like the transparent pass, so it needs the shadow map
+        if self.reflection_probes.has_pending_capture() {
+            let reflection_probes = &self.reflection_probes;
+            let drawables = self.instance_manager.drawables();
+            graph
+                .add_pass("Reflection probe capture")
+                .read(shadow_map)
+                .execute(move |context, _| {
+                    reflection_probes.capture(
+                        drawables,
+                        context.encoder,
+                        context.pipeline_cache,
+                        context.material_manager,
+                    );
+                });
+        }
+
+        let geometry_pass = &self.geometry_pass;
+        graph
+            .add_pass("Geometry")
+            .write(color_roughness)
+            .write(normal_metallic)
+            .write(emission)
+            .write(velocity)
+            .write(depth)
+            .execute(move |context, resources| {
+                geometry_pass.render_indirect(
+                    &GeometryPassTextureViews {
+                        color_roughness: resources.color_target(color_roughness),
+                        normal_metallic: resources.color_target(normal_metallic),
+                        emission: resources.color_target(emission),
+                        velocity: resources.color_target(velocity),
+                        depth: resources.depth_target(depth),
+                    },
+                    context,
+                );
+            });
+
+        // Raymarched after the meshes, so that the depth test decides which one is in front
+        if let Some(sdf_scene) = passes
+            .sdf_scene
+            .filter(|scene_name| self.sdf_pass.should_render(scene_name))
+        {
+            let sdf_pass = &self.sdf_pass;
+            graph
+                .add_pass("SDF")
+                .write(color_roughness)
+                .write(normal_metallic)
+                .write(emission)
+                .write(velocity)
+                .write(depth)
+                .execute(move |context, resources| {
+                    sdf_pass.render(
+                        sdf_scene,
+                        &SdfPassTextureViews {
+                            color_roughness: resources.color_target(color_roughness),
+                            normal_metallic: resources.color_target(normal_metallic),
+                            emission: resources.color_target(emission),
+                            velocity: resources.color_target(velocity),
+                            depth: resources.depth_target(depth),
+                        },
+                        context.encoder,
+                        context.pipeline_cache,
+                    );
+                });
+        }
+
+        // After the SDF scene, so that decals are projected onto it too
+        if self.decal_pass.should_render() {
+            let decal_pass = &self.decal_pass;
+            graph
+                .add_pass("Decals")
+                .read(depth)
+                .write(color_roughness)
+                .write(normal_metallic)
+                .execute(move |context, resources| {
+                    decal_pass.render(
+                        &DecalPassTextureViews {
+                            color_roughness: resources.color_target(color_roughness),
+                            normal_metallic: resources.color_target(normal_metallic),
+                        },
+                        context,
+                    );
+                });
+        }
+
+        let ssao_pass = &self.ssao_pass;
+        let queue = &self.queue;
+        graph
+            .add_pass("SSAO")
+            .read(color_roughness)
+            .read(normal_metallic)
+            .read(depth)
+            .write(ambient_occlusion)
+            .execute(move |context, _| ssao_pass.render(queue, context));
+
+        let lighting_pass = &self.lighting_pass;
+        graph
+            .add_pass("Lighting")
+            .read(color_roughness)
+            .read(normal_metallic)
+            .read(emission)
+            .read(depth)
+            .read(shadow_map)
+            .read(ambient_occlusion)
+            .write(scene_color)
+            .execute(move |context, resources| {
+                lighting_pass.render(
+                    &LightingPassTextureViews {
+                        output: resources.color_target(scene_color),
+                    },
+                    context,
+                );
+            });
+
+        if passes.ssr {
+            let ssr_pass = &self.ssr_pass;
+            let reflections = SsrPass::create_reflections_texture(&mut graph, self.internal_size);
+
+            graph
+                .add_pass("SSR trace")
+                .read(normal_metallic)
+                .read(depth)
+                .read(scene_color)
+                .write(reflections)
+                .execute(move |context, resources| {
+                    ssr_pass.trace(
+                        queue,
+                        &SsrTraceTextureViews {
+                            reflections: resources.view(reflections).clone(),
+                        },
+                        context,
+                    );
+                });
+
+            graph
+                .add_pass("SSR composite")
+                .read(reflections)
+                .write(scene_color)
+                .execute(move |context, resources| {
+                    ssr_pass.composite(
+                        &SsrCompositeTextureViews {
+                            reflections: resources.view(reflections).clone(),
+                            output: resources.color_target(scene_color),
+                        },
+                        context,
+                    );
+                });
+        }
+
+        // Transparent surfaces are drawn over the fog, since it only knows the opaque depth
+        if passes.volumetric_fog {
+            let volumetric_fog_pass = &self.volumetric_fog_pass;
+            let fog = &scene.fog;
+            graph
+                .add_pass("Volumetric fog")
+                .read(depth)
+                .read(shadow_map)
+                .write(scene_color)
+                .execute(move |context, resources| {
+                    volumetric_fog_pass.render(
+                        queue,
+                        fog,
+                        light_direction,
+                        light_radiance,
+                        &VolumetricFogPassTextureViews {
+                            output: resources.color_target(scene_color),
+                        },
+                        context,
+                    );
+                });
+        }
 
-        self.geometry_pass.render_indirect(
-            &GeometryPassTextureViews {
-                color_roughness: self.g_buffer.color_roughness.view.clone(),
-                normal_metallic: self.g_buffer.normal_metallic.view.clone(),
-                depth: self.g_buffer.depth.view().clone(),
+        let transparent_drawables = self.instance_manager.transparent_drawables();
+        match self.config.transparency {
+            TransparencyMode::Sorted => {
+                let transparent_pass = &self.transparent_pass;
+                graph
+                    .add_pass("Transparency")
+                    .read(depth)
+                    .read(shadow_map)
+                    .write(scene_color)
+                    .execute(move |context, resources| {
+                        transparent_pass.render(
+                            &TransparentPassTextureViews {
+                                color: resources.color_target(scene_color),
+                                depth: resources.depth_target(depth),
+                            },
+                            transparent_drawables,
+                            context,
+                        );
+                    });
+            }
+            TransparencyMode::WeightedBlended => {
+                let oit_targets = OitTargets::create(&mut graph, self.internal_size);
+                let accumulation = oit_targets.accumulation;
+                let revealage = oit_targets.revealage;
+
+                let oit_accumulation_pass = &self.oit_accumulation_pass;
+                graph
+                    .add_pass("OIT accumulation")
+                    .read(depth)
+                    .read(shadow_map)
+                    .write(accumulation)
+                    .write(revealage)
+                    .execute(move |context, resources| {
+                        oit_accumulation_pass.render(
+                            &AccumulationPassTextureViews {
+                                accumulation: resources.color_target(accumulation),
+                                revealage: resources.color_target(revealage),
+                                depth: resources.depth_target(depth),
+                            },
+                            transparent_drawables,
+                            context,
+                        );
+                    });
+
+                let oit_composite_pass = &self.oit_composite_pass;
+                graph
+                    .add_pass("OIT composite")
+                    .read(accumulation)
+                    .read(revealage)
+                    .write(scene_color)
+                    .execute(move |context, resources| {
+                        oit_composite_pass.render(
+                            &CompositePassTextureViews {
+                                accumulation: resources.view(accumulation).clone(),
+                                revealage: resources.view(revealage).clone(),
+                                output: resources.color_target(scene_color),
+                            },
+                            context,
+                        );
+                    });
+            }
+        }
+
+        let particle_pass = &self.particle_pass;
+        graph
+            .add_pass("Particles")
+            .read(depth)
+            .write(scene_color)
+            .execute(move |context, resources| {
+                particle_pass.render(
+                    &ParticlePassTextureViews {
+                        color: resources.color_target(scene_color),
+                        depth: resources.depth_target(depth),
+                    },
+                    context,
+                );
+            });
+
+        let text_pass = &self.text_pass;
+        graph
+            .add_pass("Text")
+            .read(depth)
+            .write(scene_color)
+            .execute(move |context, resources| {
+                text_pass.render_world(
+                    &TextPassTextureViews {
+                        color: resources.color_target(scene_color),
+                        depth: resources.depth_target(depth),
+                    },
+                    context.encoder,
+                    context.pipeline_cache,
+                );
+            });
+
+        let taa_pass = &mut self.taa_pass;
+        graph
+            .add_pass("TAA")
+            .read(velocity)
+            .read(depth)
+            .write(scene_color)
+            .execute(move |context, resources| {
+                taa_pass.render(
+                    queue,
+                    &TaaPassTextureViews {
+                        color: resources.view(scene_color).clone(),
+                    },
+                    context,
+                );
+            });
+
+        if passes.depth_of_field {
+            let depth_of_field_pass = &self.depth_of_field_pass;
+            graph
+                .add_pass("Depth of field")
+                .read(depth)
+                .write(scene_color)
+                .execute(move |context, resources| {
+                    depth_of_field_pass.render(
+                        queue,
+                        active_camera,
+                        &DepthOfFieldPassTextureViews {
+                            color: resources.color_target(scene_color),
+                        },
+                        context,
+                    );
+                });
+        }
+
+        // Materials sample these in the next frame
+        if !self.render_target_manager.is_empty() {
+            let render_target_manager = &mut self.render_target_manager;
+            graph.add_pass("Render targets").read(scene_color).execute(
+                move |context, resources| {
+                    render_target_manager.capture(context.encoder, resources.view(scene_color));
+                },
+            );
+        }
+
+        // After depth of field and TAA, so that overlays stay sharp
+        graph
+            .add_pass("Screen text")
+            .write(scene_color)
+            .execute(move |context, resources| {
+                text_pass.render_screen(
+                    &resources.color_target(scene_color),
+                    context.encoder,
+                    context.pipeline_cache,
+                );
+            });
+
+        let debug_draw_pass = &self.debug_draw_pass;
+        let debug_draw = &mut self.debug_draw;
+        graph
+            .add_pass("Debug draw")
+            .read(depth)
+            .write(scene_color)
+            .execute(move |context, resources| {
+                debug_draw_pass.render(
+                    &DebugDrawPassTextureViews {
+                        color: resources.color_target(scene_color),
+                        depth: resources.depth_target(depth),
+                    },
+                    context.encoder,
+                    queue,
+                    context.pipeline_cache,
+                    debug_draw,
+                );
+            });
+
+        let post_process = &self.post_process;
+        let upscale_pass = &self.upscale_pass;
+        let post_process_params = &demo_state.post_process;
+        graph
+            .add_pass("Post processing")
+            .read(scene_color)
+            .write(output_texture)
+            .execute(move |context, resources| {
+                let mut post_process_context = PostProcessContext {
+                    encoder: context.encoder,
+                    pipeline_cache: context.pipeline_cache,
+                    queue,
+                    params: post_process_params,
+                    blur_pass: context.blur_pass,
+                };
+                let output = resources.view(output_texture);
+                let tonemap_output = upscale_pass.input().map_or(output, |input| input.view());
+                post_process.render(&mut post_process_context, tonemap_output);
+                upscale_pass.render(&mut post_process_context, output);
+            });
+
+        graph.execute(
+            &mut RenderPassContext {
+                encoder: &mut encoder,
+                pipeline_cache: &self.shader_loader.cache,
+                draw_commands_buffer: self.instance_manager.draw_commands_buffer(),
+                draw_commands_count_buffer: self.instance_manager.draw_commands_count_buffer(),
+                material_manager: &mut self.material_manager,
+                blur_pass: &self.blur_pass,
             },
-            &mut pass_context,
+            &mut self.transient_textures,
+            &mut self.gpu_profiler,
         );
 
+        // Only once the scene has been drawn, so that removals during frames that fail or only
+        // draw the UI aren't lost
+        demo_state.parts.active_mut().scene.clear_removed_objects();
+
         Ok(RenderResult {
             output,
             view,
@@ -300,6 +1304,59 @@ impl Renderer {
         })
     }
 
+    fn acquire_output(
+        &self,
+    ) -> Result<(Option<wgpu::SurfaceTexture>, wgpu::TextureView), wgpu::SurfaceError> {
+        match &self.output {
+            RenderOutput::Window { surface, .. } => {
+                let output = surface.get_current_texture()?;
+                let view = output
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+                Ok((Some(output), view))
+            }
+            RenderOutput::Texture(texture) => Ok((
+                None,
+                texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            )),
+        }
+    }
+
+    /// Clears the output, so that only the UI is drawn on it in `finish_frame`
+    fn begin_ui_only_frame(&mut self) -> Result<RenderResult, wgpu::SurfaceError> {
+        let (output, view) = self.acquire_output()?;
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("UI only encoder"),
+            });
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Clear output pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            ..Default::default()
+        });
+
+        Ok(RenderResult {
+            output,
+            view,
+            encoder,
+        })
+    }
+
+    /// Blocks until every shader has been compiled. Used when rendering offline, where frames
+    /// can't be skipped while waiting.
+    pub fn wait_for_shaders(&mut self) -> anyhow::Result<()> {
+        self.shader_loader.wait_until_ready()
+    }
+
     // Rendering is split to render() and finish_frame() to allow drawing to imgui during rendering
     // Otherwise &mut imgui::Ui and &mut imgui::Context would have a conflict
     pub fn finish_frame(
@@ -311,24 +1368,121 @@ impl Renderer {
         }: RenderResult,
         imgui_context: &mut imgui::Context,
     ) {
-        self.imgui.render(
-            &view,
-            imgui_context,
-            &self.device,
-            &self.queue,
-            &mut encoder,
-        );
+        match &self.output {
+            RenderOutput::Window { .. } => {
+                self.gpu_profiler.begin_scope(&mut encoder, "Imgui");
+                self.imgui.render(
+                    &view,
+                    imgui_context,
+                    &self.device,
+                    &self.queue,
+                    &mut encoder,
+                );
+                self.gpu_profiler.end_scope(&mut encoder);
+            }
+            // Offline frames are captured without the UI, but the ImGui frame still has to end
+            RenderOutput::Texture(_) => {
+                imgui_context.render();
+            }
+        }
+        self.gpu_profiler.resolve(&mut encoder);
 
         let command_buffer = encoder.finish();
         self.queue.submit([command_buffer]);
+        self.gpu_profiler.end_frame();
+        self.instance_manager.after_submit();
+
+        if let (RenderOutput::Window { window, .. }, Some(output)) = (&self.output, output) {
+            window.pre_present_notify();
+            output.present();
+        }
+    }
+
+    /// Copies the last finished frame to the CPU, blocking until the GPU is done with it. Only
+    /// available when rendering offline.
+    pub fn read_output(&self) -> anyhow::Result<image::RgbaImage> {
+        let RenderOutput::Texture(texture) = &self.output else {
+            anyhow::bail!("Only offscreen output can be read back");
+        };
+
+        let width = texture.width();
+        let height = texture.height();
+        let unpadded_bytes_per_row = width * 4;
+        // Rows in the copy have to be aligned
+        let padded_bytes_per_row = unpadded_bytes_per_row
+            .div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Output readback buffer"),
+            size: (padded_bytes_per_row * height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
 
-        self.window.pre_present_notify();
-        output.present();
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Output readback encoder"),
+            });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            texture.size(),
+        );
+        self.queue.submit([encoder.finish()]);
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::PollType::Wait)?;
+        receiver.recv()??;
+
+        let data = slice.get_mapped_range();
+        let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+        for row in data.chunks(padded_bytes_per_row as usize) {
+            pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+        }
+        drop(data);
+        buffer.unmap();
+
+        image::RgbaImage::from_raw(width, height, pixels)
+            .context("Output readback returned the wrong amount of data")
     }
 }
 
+fn create_output_texture(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Offscreen output"),
+        size: wgpu::Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: config.format,
+        usage: config.usage,
+        view_formats: &[],
+    })
+}
+
 pub struct RenderResult {
-    output: wgpu::SurfaceTexture,
+    /// None when rendering offline
+    output: Option<wgpu::SurfaceTexture>,
     view: wgpu::TextureView,
     encoder: wgpu::CommandEncoder,
 }
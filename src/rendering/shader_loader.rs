@@ -1,8 +1,11 @@
 use std::{
+    collections::{HashMap, HashSet},
+    marker::PhantomData,
     path::Path,
     sync::{
+        atomic::{AtomicUsize, Ordering},
         mpsc::{self, channel},
-        Arc, RwLock,
+        Arc, Mutex, RwLock,
     },
     time::Duration,
 };
@@ -14,94 +17,161 @@ use naga::{
     valid::{Capabilities, ValidationFlags},
 };
 use naga_oil::compose::{
-    ComposableModuleDescriptor, Composer, NagaModuleDescriptor, ShaderLanguage,
+    get_preprocessor_data, ComposableModuleDescriptor, Composer, NagaModuleDescriptor,
+    ShaderLanguage,
 };
 use notify_debouncer_mini::{
     new_debouncer_opt, notify::*, DebounceEventResult, DebouncedEventKind, Debouncer,
 };
 use pollster::block_on;
-use wgpu::{naga, PollType};
+use wgpu::naga;
+
+pub(crate) use naga_oil::compose::ShaderDefValue;
 
 const SHADER_FOLDER: &'static str = "assets/shaders";
 const SHADER_SHADER_MODULES_FOLDER: &'static str = "assets/shaders/shared";
 
-pub trait Pipeline {}
+/// Render and compute pipelines are stored in the same cache
+pub enum AnyPipeline {
+    Render(wgpu::RenderPipeline),
+    Compute(wgpu::ComputePipeline),
+}
+
+pub trait Pipeline: Sized {
+    fn into_any(self) -> AnyPipeline;
+    fn from_any(pipeline: &AnyPipeline) -> Option<&Self>;
+}
+
+impl Pipeline for wgpu::RenderPipeline {
+    fn into_any(self) -> AnyPipeline {
+        AnyPipeline::Render(self)
+    }
+
+    fn from_any(pipeline: &AnyPipeline) -> Option<&Self> {
+        match pipeline {
+            AnyPipeline::Render(pipeline) => Some(pipeline),
+            AnyPipeline::Compute(_) => None,
+        }
+    }
+}
+
+impl Pipeline for wgpu::ComputePipeline {
+    fn into_any(self) -> AnyPipeline {
+        AnyPipeline::Compute(self)
+    }
 
-impl Pipeline for wgpu::RenderPipeline {}
+    fn from_any(pipeline: &AnyPipeline) -> Option<&Self> {
+        match pipeline {
+            AnyPipeline::Compute(pipeline) => Some(pipeline),
+            AnyPipeline::Render(_) => None,
+        }
+    }
+}
 
-impl Pipeline for wgpu::ComputePipeline {}
+/// Creates a pipeline from a compiled shader. The cache should be passed to the pipeline
+/// descriptor, so that the compiled pipeline can be reused on the next run.
+pub(crate) type PipelineFactory<T> = Box<
+    dyn Sync
+        + Send
+        + Fn(&wgpu::Device, wgpu::ShaderModule, Option<&wgpu::PipelineCache>) -> anyhow::Result<T>,
+>;
 
-type PipelineFactory<T> =
-    Box<dyn Sync + Send + Fn(&wgpu::Device, wgpu::ShaderModule) -> anyhow::Result<T>>;
+/// Values for `#ifdef` and `#if` in shaders
+pub(crate) type ShaderDefines = &'static [(&'static str, ShaderDefValue)];
 
 #[derive(Debug, Clone)]
 pub(crate) struct ShaderDefinition {
     pub name: &'static str,
     pub path: &'static str,
+    /// Each set of defines is a separate variant of the file, with its own pipeline
+    pub defines: ShaderDefines,
 }
 
-pub struct ShaderEntry<T: Pipeline> {
-    pipeline_id: PipelineId<T>,
-    def: ShaderDefinition,
-    factory: PipelineFactory<T>,
-}
-
-impl<T: Pipeline> ShaderEntry<T> {
-    pub fn new(
-        pipeline_id: PipelineId<T>,
-        def: ShaderDefinition,
-        factory: PipelineFactory<T>,
-    ) -> Self {
+impl ShaderDefinition {
+    /// The same file with other defines. Errors and reloads are reported by name, so each
+    /// variant needs a name of its own.
+    pub const fn variant(&self, name: &'static str, defines: ShaderDefines) -> Self {
         Self {
-            pipeline_id,
-            def,
-            factory,
+            name,
+            path: self.path,
+            defines,
         }
     }
 }
 
-pub type PipelineId<T> = Id<PipelineCacheEntry<T>>;
-pub type RenderPipelineId = PipelineId<wgpu::RenderPipeline>;
-pub type ComputePipelineId = PipelineId<wgpu::ComputePipeline>;
+pub struct ShaderEntry {
+    pipeline_id: Id<PipelineCacheEntry>,
+    def: ShaderDefinition,
+    factory: PipelineFactory<AnyPipeline>,
+}
+
+/// Refers to a pipeline in the cache, which is either a render or a compute pipeline
+pub struct PipelineId<T> {
+    id: Id<PipelineCacheEntry>,
+    _pipeline: PhantomData<fn() -> T>,
+}
+
+// Derives would require T to implement these too
+impl<T> Clone for PipelineId<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
 
-pub struct PipelineCacheEntry<T>(Option<T>);
+impl<T> Copy for PipelineId<T> {}
 
-impl<T> Default for PipelineCacheEntry<T> {
-    fn default() -> Self {
-        Self(None)
+impl<T> std::fmt::Debug for PipelineId<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("PipelineId").field(&self.id).finish()
     }
 }
 
-impl<T> PipelineCacheEntry<T> {
-    pub fn set_pipeline(&mut self, pipeline: T) {
+pub type RenderPipelineId = PipelineId<wgpu::RenderPipeline>;
+pub type ComputePipelineId = PipelineId<wgpu::ComputePipeline>;
+
+#[derive(Default)]
+pub struct PipelineCacheEntry(Option<AnyPipeline>);
+
+impl PipelineCacheEntry {
+    pub fn set_pipeline(&mut self, pipeline: AnyPipeline) {
         self.0 = Some(pipeline);
     }
 }
 
-pub struct PipelineCacheBuilder<T: Pipeline> {
-    shaders: Arena<ShaderEntry<T>>,
-    pipelines: Arena<PipelineCacheEntry<T>>,
+pub struct PipelineCacheBuilder {
+    shaders: Arena<ShaderEntry>,
+    pipelines: Arena<PipelineCacheEntry>,
 }
 
-impl<T: Pipeline> PipelineCacheBuilder<T> {
+impl PipelineCacheBuilder {
     pub fn new() -> Self {
         Self {
             shaders: Arena::new(),
             pipelines: Arena::new(),
         }
     }
-    pub fn add_shader(
+
+    pub fn add_shader<T: Pipeline + 'static>(
         &mut self,
         shader_def: ShaderDefinition,
         factory: PipelineFactory<T>,
     ) -> PipelineId<T> {
         let pipeline_id = self.pipelines.alloc(PipelineCacheEntry::default());
-        let shader_entry = ShaderEntry::new(pipeline_id, shader_def, factory);
-        self.shaders.alloc(shader_entry);
-        pipeline_id
+        self.shaders.alloc(ShaderEntry {
+            pipeline_id,
+            def: shader_def,
+            factory: Box::new(move |device, shader_module, cache| {
+                factory(device, shader_module, cache).map(T::into_any)
+            }),
+        });
+
+        PipelineId {
+            id: pipeline_id,
+            _pipeline: PhantomData,
+        }
     }
 
-    pub fn build(self) -> PipelineCache<T> {
+    pub fn build(self) -> PipelineCache {
         PipelineCache {
             shaders: Arc::new(self.shaders),
             pipelines: self.pipelines,
@@ -109,26 +179,24 @@ impl<T: Pipeline> PipelineCacheBuilder<T> {
     }
 }
 
-pub struct PipelineCache<T: Pipeline> {
-    shaders: Arc<Arena<ShaderEntry<T>>>,
-    pipelines: Arena<PipelineCacheEntry<T>>,
+pub struct PipelineCache {
+    shaders: Arc<Arena<ShaderEntry>>,
+    pipelines: Arena<PipelineCacheEntry>,
 }
 
-pub type RenderPipelineCache = PipelineCache<wgpu::RenderPipeline>;
-pub type ComputePipelineCache = PipelineCache<wgpu::ComputePipeline>;
-
-impl<T: Pipeline> PipelineCache<T> {
-    pub fn get(&self, id: PipelineId<T>) -> &T {
-        self.pipelines.get(id).unwrap().0.as_ref().unwrap()
+impl PipelineCache {
+    pub fn get<T: Pipeline>(&self, id: PipelineId<T>) -> &T {
+        let pipeline = self.pipelines.get(id.id).unwrap().0.as_ref().unwrap();
+        T::from_any(pipeline).expect("Pipeline ID refers to a different kind of pipeline")
     }
 
-    pub fn get_entry_mut(&mut self, id: PipelineId<T>) -> &mut PipelineCacheEntry<T> {
+    fn get_entry_mut(&mut self, id: Id<PipelineCacheEntry>) -> &mut PipelineCacheEntry {
         self.pipelines.get_mut(id).unwrap()
     }
 
     pub fn iter_shaders_and_pipelines_mut(
         &mut self,
-    ) -> impl Iterator<Item = (&ShaderEntry<T>, &mut PipelineCacheEntry<T>)> {
+    ) -> impl Iterator<Item = (&ShaderEntry, &mut PipelineCacheEntry)> {
         // This assumes that the shaders and pipelines are in sync, which should be the case
         // because the same method inserts to both arenas.
         self.shaders
@@ -142,34 +210,212 @@ impl<T: Pipeline> PipelineCache<T> {
     }
 }
 
-// Loads and compiles shaders to pipelines in a worker thread.
-pub(crate) struct ShaderLoader<T: Pipeline> {
-    pub cache: PipelineCache<T>,
+/// A shader that failed to compile when it was reloaded
+#[derive(Debug, Clone)]
+pub struct ShaderError {
+    pub shader: &'static str,
+    /// The file the error is in, which can be a shared module imported by the shader
+    pub file: String,
+    /// Only known for errors reported by naga_oil, wgpu's errors refer to the generated WGSL
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl ShaderError {
+    fn new(shader_def: &ShaderDefinition, error: &anyhow::Error) -> Self {
+        let message = format!("{:#}", error);
+        let location = find_error_location(&message);
+
+        Self {
+            shader: shader_def.name,
+            file: location
+                .as_ref()
+                .map(|(file, _)| file.clone())
+                .unwrap_or_else(|| format!("{}/{}", SHADER_FOLDER, shader_def.path)),
+            line: location.map(|(_, line)| line),
+            message,
+        }
+    }
+}
+
+/// Finds the file and line from a codespan diagnostic, which has a line like
+/// `┌─ assets/shaders/foo.wgsl:12:5`
+fn find_error_location(message: &str) -> Option<(String, usize)> {
+    message.lines().find_map(|line| {
+        let (_, location) = line.split_once("┌─ ")?;
+        let mut parts = location.trim().rsplitn(3, ':');
+        let _column = parts.next()?;
+        let line = parts.next()?.parse().ok()?;
+        let file = parts.next()?;
+        file.ends_with(".wgsl").then(|| (file.to_string(), line))
+    })
+}
+
+/// Failed shaders, shared by the render and compute shader loaders. A shader's error is removed
+/// when it compiles successfully.
+#[derive(Clone, Default)]
+pub struct ShaderErrors(Arc<Mutex<Vec<ShaderError>>>);
+
+impl ShaderErrors {
+    fn report(&self, error: ShaderError) {
+        let mut errors = self.0.lock().unwrap();
+        errors.retain(|existing| existing.shader != error.shader);
+        errors.push(error);
+    }
+
+    fn clear(&self, shader: &'static str) {
+        self.0
+            .lock()
+            .unwrap()
+            .retain(|existing| existing.shader != shader);
+    }
+
+    pub fn draw_ui(&self, ui: &imgui::Ui) {
+        let errors = self.0.lock().unwrap();
+        if errors.is_empty() {
+            return;
+        }
+
+        let display_size = ui.io().display_size;
+        let _background = ui.push_style_color(imgui::StyleColor::WindowBg, [0.3, 0.0, 0.0, 0.9]);
+
+        ui.window("Shader errors")
+            .position([10.0, display_size[1] - 10.0], imgui::Condition::Always)
+            .position_pivot([0.0, 1.0])
+            .size(
+                [display_size[0] - 20.0, display_size[1] * 0.4],
+                imgui::Condition::Always,
+            )
+            .collapsible(false)
+            .build(|| {
+                for error in errors.iter() {
+                    let location = match error.line {
+                        Some(line) => format!("{}:{}", error.file, line),
+                        None => error.file.clone(),
+                    };
+                    ui.text_colored([1.0, 0.5, 0.5, 1.0], error.shader);
+                    ui.same_line();
+                    ui.text_disabled(location);
+                    ui.text_wrapped(&error.message);
+                    ui.separator();
+                }
+            });
+    }
+}
+
+/// A pipeline compiled on the worker pool, waiting to be added to the cache
+struct CompiledPipeline {
+    name: &'static str,
+    pipeline_id: Id<PipelineCacheEntry>,
+    pipeline: AnyPipeline,
+}
+
+/// Compiles shaders on a pool of worker threads, so that neither startup nor hot reloading has
+/// to wait for one shader at a time.
+#[derive(Clone)]
+struct ShaderCompiler {
     device: wgpu::Device,
-    receiver: mpsc::Receiver<(&'static str, PipelineId<T>, T)>,
+    shaders: Arc<Arena<ShaderEntry>>,
     composer: Arc<RwLock<Composer>>,
-    _debouncer: Debouncer<notify_debouncer_mini::notify::RecommendedWatcher>,
+    errors: ShaderErrors,
+    pipeline_cache: Option<wgpu::PipelineCache>,
+    pool: Arc<rayon::ThreadPool>,
+    sender: mpsc::Sender<CompiledPipeline>,
+    /// Compilations that have been spawned but haven't finished yet
+    in_flight: Arc<AtomicUsize>,
 }
 
-pub type RenderShaderLoader = ShaderLoader<wgpu::RenderPipeline>;
-pub type ComputeShaderLoader = ShaderLoader<wgpu::ComputePipeline>;
+impl ShaderCompiler {
+    fn spawn(&self, shader_id: Id<ShaderEntry>) {
+        let compiler = self.clone();
+        self.in_flight.fetch_add(1, Ordering::AcqRel);
+
+        self.pool.spawn(move || {
+            let entry = &compiler.shaders[shader_id];
+            match compile_file(
+                &compiler.device,
+                &entry.def,
+                &entry.factory,
+                &compiler.composer,
+                compiler.pipeline_cache.as_ref(),
+            ) {
+                Ok(pipeline) => {
+                    // The receiver is gone if the renderer was dropped during compilation
+                    let _ = compiler.sender.send(CompiledPipeline {
+                        name: entry.def.name,
+                        pipeline_id: entry.pipeline_id,
+                        pipeline,
+                    });
+                }
+                Err(e) => {
+                    log::error!("Failed to load shader: {:?}", e);
+                    compiler.errors.report(ShaderError::new(&entry.def, &e));
+                }
+            }
 
-impl<T: 'static + Pipeline + Send> ShaderLoader<T> {
-    pub fn new(device: wgpu::Device, cache_builder: PipelineCacheBuilder<T>) -> Self {
-        let cache = cache_builder.build();
+            // Only after sending, so that the pipeline can be received once nothing is in flight
+            compiler.in_flight.fetch_sub(1, Ordering::AcqRel);
+        });
+    }
+}
 
-        let (send_new_pipelines, recv_new_pipelines) = channel();
+// Loads and compiles shaders to pipelines in worker threads.
+pub(crate) struct ShaderLoader {
+    pub cache: PipelineCache,
+    receiver: mpsc::Receiver<CompiledPipeline>,
+    compiler: ShaderCompiler,
+    /// Pipelines that have been compiled at least once
+    ready_count: usize,
+    _debouncer: Debouncer<notify_debouncer_mini::notify::RecommendedWatcher>,
+}
+
+impl ShaderLoader {
+    /// Starts compiling every pipeline in the background. The cache can be used once
+    /// `is_ready` returns true.
+    pub fn new(
+        device: wgpu::Device,
+        cache_builder: PipelineCacheBuilder,
+        errors: ShaderErrors,
+        pipeline_cache: Option<wgpu::PipelineCache>,
+    ) -> Self {
+        let cache = cache_builder.build();
 
-        let device_loader = device.clone();
+        let (sender, receiver) = channel();
 
         let composer = create_composer().expect("Failed to create composer for shader loader");
-        let composer = Arc::new(RwLock::new(composer));
 
-        let shaders = cache.shaders.clone();
-        let composer_clone = composer.clone();
+        // Leaves one thread for the main thread, which keeps rendering in the meantime
+        let thread_count = std::thread::available_parallelism()
+            .map_or(1, |count| count.get().saturating_sub(1).max(1));
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(thread_count)
+            .thread_name(|index| format!("Shader compiler {}", index))
+            .build()
+            .expect("Failed to create shader compiler threads");
+
+        let compiler = ShaderCompiler {
+            device,
+            shaders: cache.shaders.clone(),
+            composer: Arc::new(RwLock::new(composer)),
+            errors,
+            pipeline_cache,
+            pool: Arc::new(pool),
+            sender,
+            in_flight: Arc::new(AtomicUsize::new(0)),
+        };
+
+        for (shader_id, _) in compiler.shaders.iter() {
+            compiler.spawn(shader_id);
+        }
+
+        let watcher_compiler = compiler.clone();
+        let absolute_shared_folder = Path::new(SHADER_SHADER_MODULES_FOLDER)
+            .canonicalize()
+            .unwrap();
         let mut debouncer = new_debouncer_opt(
             notify_debouncer_mini::Config::default().with_timeout(Duration::from_millis(100)),
             move |res: DebounceEventResult| {
+                let compiler = &watcher_compiler;
                 match res {
                     Ok(events) => {
                         for event in events {
@@ -177,30 +423,46 @@ impl<T: 'static + Pipeline + Send> ShaderLoader<T> {
                                 continue;
                             }
 
-                            // This is stupid and slow, but that's life
-                            let Some(entry) = shaders
-                                .iter()
-                                .find(|(_, entry)| event.path.ends_with(entry.def.path))
-                                .map(|(_, entry)| entry)
-                            else {
-                                continue;
-                            };
-                            match compile_file(
-                                &device_loader,
-                                &entry.def,
-                                &entry.factory,
-                                composer_clone.clone(),
-                            ) {
-                                Ok(pipeline) => {
-                                    send_new_pipelines
-                                        .send((entry.def.name, entry.pipeline_id, pipeline))
-                                        .unwrap();
+                            let shader_ids = if event.path.starts_with(&absolute_shared_folder) {
+                                let dependents = find_dependents(&compiler.shaders, &event.path);
+
+                                // The module may import different modules now, so it's simplest
+                                // to add all of them again
+                                match create_composer() {
+                                    Ok(composer) => *compiler.composer.write().unwrap() = composer,
+                                    Err(e) => {
+                                        log::error!(
+                                            "Failed to reload shared shader modules: {:?}",
+                                            e
+                                        );
+                                        for shader_id in &dependents {
+                                            compiler.errors.report(ShaderError::new(
+                                                &compiler.shaders[*shader_id].def,
+                                                &e,
+                                            ));
+                                        }
+                                        continue;
+                                    }
                                 }
-                                Err(e) => println!("Failed to load shader: {:?}", e),
+
+                                dependents
+                            } else {
+                                // This is stupid and slow, but that's life.
+                                // Several pipelines can share the same shader file.
+                                compiler
+                                    .shaders
+                                    .iter()
+                                    .filter(|(_, entry)| event.path.ends_with(entry.def.path))
+                                    .map(|(shader_id, _)| shader_id)
+                                    .collect()
+                            };
+
+                            for shader_id in shader_ids {
+                                compiler.spawn(shader_id);
                             }
                         }
                     }
-                    Err(e) => println!("Error debouncing shader changes: {}", e),
+                    Err(e) => log::error!("Error debouncing shader changes: {}", e),
                 }
             },
         )
@@ -214,51 +476,101 @@ impl<T: 'static + Pipeline + Send> ShaderLoader<T> {
             .watch(&absolute_shader_folder, RecursiveMode::Recursive)
             .unwrap();
 
-        let mut shader_loader = Self {
-            device,
+        Self {
             cache,
-            receiver: recv_new_pipelines,
-            composer,
+            receiver,
+            compiler,
+            ready_count: 0,
             _debouncer: debouncer,
-        };
-
-        shader_loader
-            .create_all_pipelines()
-            .expect("Failed to create all pipelines");
+        }
+    }
 
-        shader_loader
+    /// True once every pipeline has been compiled. A pipeline that fails to compile at startup
+    /// keeps this false until the shader has been fixed.
+    pub(crate) fn is_ready(&self) -> bool {
+        self.ready_count == self.cache.pipelines.len()
     }
 
-    pub(crate) fn create_all_pipelines(&mut self) -> anyhow::Result<()> {
-        for (shader, pipeline_entry) in self.cache.iter_shaders_and_pipelines_mut() {
-            let pipeline = compile_file(
-                &self.device,
-                &shader.def,
-                &shader.factory,
-                self.composer.clone(),
-            )
-            .context(format!("Failed to compile shader: {}", shader.def.name))?;
-            pipeline_entry.set_pipeline(pipeline);
+    /// Blocks until every pipeline has been compiled, for when there's no UI to show the
+    /// progress in. Fails if some pipelines couldn't be compiled.
+    pub(crate) fn wait_until_ready(&mut self) -> anyhow::Result<()> {
+        while !self.is_ready() {
+            if self.compiler.in_flight.load(Ordering::Acquire) == 0 {
+                // The last compilations may have finished after the previous check
+                self.load_pending_shaders()?;
+                if self.is_ready() {
+                    break;
+                }
+
+                let failed = self
+                    .cache
+                    .iter_shaders_and_pipelines_mut()
+                    .filter(|(_, pipeline_entry)| pipeline_entry.0.is_none())
+                    .map(|(shader, _)| shader.def.name)
+                    .collect::<Vec<_>>();
+                anyhow::bail!("Failed to compile shaders: {}", failed.join(", "));
+            }
+
+            if let Ok(compiled) = self.receiver.recv_timeout(Duration::from_millis(10)) {
+                self.add_pipeline(compiled);
+            }
         }
+
         Ok(())
     }
 
+    pub(crate) fn draw_progress_ui(&self, ui: &imgui::Ui) {
+        let total = self.cache.pipelines.len();
+        let display_size = ui.io().display_size;
+
+        ui.window("Compiling shaders")
+            .position(
+                [display_size[0] * 0.5, display_size[1] * 0.5],
+                imgui::Condition::Always,
+            )
+            .position_pivot([0.5, 0.5])
+            .size([display_size[0] * 0.5, 60.0], imgui::Condition::Always)
+            .title_bar(false)
+            .resizable(false)
+            .movable(false)
+            .build(|| {
+                imgui::ProgressBar::new(self.ready_count as f32 / total.max(1) as f32)
+                    .size([-1.0, 0.0])
+                    .overlay_text(format!(
+                        "Compiling shaders ({}/{})",
+                        self.ready_count, total
+                    ))
+                    .build(ui);
+            });
+    }
+
     pub(crate) fn load_pending_shaders(&mut self) -> anyhow::Result<()> {
-        while let Ok((name, pipeline_id, pipeline)) = self.receiver.try_recv() {
-            let entry = self.cache.get_entry_mut(pipeline_id);
-            println!("Shader reloaded: {}", name);
-            entry.set_pipeline(pipeline);
+        while let Ok(compiled) = self.receiver.try_recv() {
+            self.add_pipeline(compiled);
         }
 
         Ok(())
     }
+
+    fn add_pipeline(&mut self, compiled: CompiledPipeline) {
+        let entry = self.cache.get_entry_mut(compiled.pipeline_id);
+        if entry.0.is_none() {
+            self.ready_count += 1;
+        } else {
+            log::info!("Shader reloaded: {}", compiled.name);
+        }
+
+        entry.set_pipeline(compiled.pipeline);
+        self.compiler.errors.clear(compiled.name);
+    }
 }
 
-fn compile_file<T: Pipeline>(
+fn compile_file<T>(
     device: &wgpu::Device,
     shader_def: &ShaderDefinition,
     factory: &PipelineFactory<T>,
-    composer: Arc<RwLock<Composer>>,
+    composer: &RwLock<Composer>,
+    pipeline_cache: Option<&wgpu::PipelineCache>,
 ) -> anyhow::Result<T> {
     let path = Path::new(SHADER_FOLDER).join(shader_def.path);
     let shader_code = std::fs::read_to_string(&path)
@@ -266,28 +578,30 @@ fn compile_file<T: Pipeline>(
 
     let file_path = path.to_string_lossy().to_string();
 
-    let mut composer = composer.write().unwrap();
-
-    let module = composer.make_naga_module(NagaModuleDescriptor {
-        file_path: &file_path,
-        source: &shader_code,
-        ..Default::default()
-    });
+    // The composer caches modules internally, so only one shader can be composed at a time.
+    // Everything after this runs in parallel.
+    let module = {
+        let mut composer = composer.write().unwrap();
 
-    let module = match module {
-        Ok(module) => module,
-        Err(e) => {
-            println!(
-                "Failed to create Naga module for shader {}:\n{}",
-                shader_def.name,
-                e.emit_to_string(&composer)
-            );
-            return Err(anyhow::anyhow!(
-                "Failed to create Naga module for shader {}: {}",
-                shader_def.name,
-                e
-            ));
-        }
+        composer
+            .make_naga_module(NagaModuleDescriptor {
+                file_path: &file_path,
+                source: &shader_code,
+                shader_defs: shader_def
+                    .defines
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), *value))
+                    .collect(),
+                ..Default::default()
+            })
+            .map_err(|e| {
+                // The emitted diagnostic has the file and line, and the offending source line
+                anyhow::anyhow!(
+                    "Failed to create Naga module for shader {}:\n{}",
+                    shader_def.name,
+                    e.emit_to_string(&composer)
+                )
+            })?
     };
 
     // We don't need to validate, because wgpu runs the validator internally.
@@ -299,6 +613,7 @@ fn compile_file<T: Pipeline>(
     let shader_code = naga::back::wgsl::write_string(&module, &info, WriterFlags::empty())
         .context("Failed to convert Naga module to WGSL string")?;
 
+    // Error scopes are per thread, so other shaders compiling at the same time don't end up here
     device.push_error_scope(wgpu::ErrorFilter::Validation);
 
     let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
@@ -306,12 +621,9 @@ fn compile_file<T: Pipeline>(
         source: wgpu::ShaderSource::Wgsl(shader_code.into()),
     });
 
-    let pipeline = factory(device, shader_module);
-
-    device
-        .poll(PollType::Wait)
-        .context("Failed to poll device after shader compilation.")?;
+    let pipeline = factory(device, shader_module, pipeline_cache);
 
+    // Creation errors are reported right away, so there's no need to wait for the device
     let error = block_on(device.pop_error_scope());
 
     if let Some(error) = error {
@@ -325,34 +637,138 @@ fn compile_file<T: Pipeline>(
     pipeline
 }
 
-fn create_composer() -> anyhow::Result<Composer> {
+/// Paths and sources of the modules that shaders can import
+fn read_shared_modules() -> anyhow::Result<Vec<(String, String)>> {
     let shared_files = std::fs::read_dir(SHADER_SHADER_MODULES_FOLDER)
-        .expect("Failed to read shared shader modules directory");
-    let mut composer = Composer::default().with_capabilities(
-        Capabilities::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING,
-    );
+        .context("Failed to read shared shader modules directory")?;
+
+    let mut modules = Vec::new();
 
     for entry in shared_files {
-        let entry = entry.expect("Failed to read entry in shared shader modules directory");
+        let entry = entry.context("Failed to read entry in shared shader modules directory")?;
         let path = entry.path();
 
         if !path.is_file() && path.extension().map_or(false, |ext| ext != "wgsl") {
             continue;
         }
 
-        let source =
-            std::fs::read_to_string(&path).expect("Failed to read shared shader module file");
+        let source = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read shared shader module {}", path.display()))?;
+
+        modules.push((path.to_string_lossy().to_string(), source));
+    }
 
-        let file_path = path.to_string_lossy().to_string();
+    Ok(modules)
+}
 
-        composer
-            .add_composable_module(ComposableModuleDescriptor {
-                source: &source,
-                file_path: &file_path,
+/// Names of the modules that the source imports directly. Imports can refer to items inside a
+/// module, e.g. `shared::fullscreen::vs_main`, so they are matched against the known modules.
+fn direct_imports<'a>(source: &str, module_names: &'a HashSet<String>) -> Vec<&'a String> {
+    let (_, imports, _) = get_preprocessor_data(source);
+
+    imports
+        .iter()
+        .filter_map(|import| {
+            module_names
+                .iter()
+                .filter(|name| {
+                    import.import == **name || import.import.starts_with(&format!("{}::", name))
+                })
+                .max_by_key(|name| name.len())
+        })
+        .collect()
+}
+
+/// Shaders that import the shared module in `changed_path`, directly or through other modules
+fn find_dependents(shaders: &Arena<ShaderEntry>, changed_path: &Path) -> Vec<Id<ShaderEntry>> {
+    let Some(changed_module) = std::fs::read_to_string(changed_path)
+        .ok()
+        .and_then(|source| get_preprocessor_data(&source).0)
+    else {
+        return Vec::new();
+    };
+
+    let modules = match read_shared_modules() {
+        Ok(modules) => modules,
+        Err(e) => {
+            log::error!(
+                "Failed to find shaders that depend on {}: {:?}",
+                changed_module,
+                e
+            );
+            return Vec::new();
+        }
+    };
+
+    let module_sources = modules
+        .iter()
+        .filter_map(|(_, source)| Some((get_preprocessor_data(source).0?, source)))
+        .collect::<HashMap<_, _>>();
+    let module_names = module_sources.keys().cloned().collect::<HashSet<_>>();
+
+    let depends_on_changed_module = |source: &str| {
+        let mut visited = HashSet::new();
+        let mut pending = direct_imports(source, &module_names);
+
+        while let Some(module) = pending.pop() {
+            if *module == changed_module {
+                return true;
+            }
+
+            if visited.insert(module) {
+                pending.extend(direct_imports(&module_sources[module], &module_names));
+            }
+        }
+
+        false
+    };
+
+    shaders
+        .iter()
+        .filter(|(_, entry)| {
+            std::fs::read_to_string(Path::new(SHADER_FOLDER).join(entry.def.path))
+                .is_ok_and(|source| depends_on_changed_module(&source))
+        })
+        .map(|(shader_id, _)| shader_id)
+        .collect()
+}
+
+fn create_composer() -> anyhow::Result<Composer> {
+    let mut composer = Composer::default().with_capabilities(
+        Capabilities::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING,
+    );
+
+    let mut pending = read_shared_modules()?;
+
+    // Modules can import each other, and a module can only be added after its imports. The
+    // directory listing is in no particular order, so keep retrying until nothing changes.
+    while !pending.is_empty() {
+        let mut last_error = None;
+        let count_before = pending.len();
+
+        pending.retain(|(file_path, source)| {
+            let result = composer.add_composable_module(ComposableModuleDescriptor {
+                source,
+                file_path,
                 language: ShaderLanguage::Wgsl,
                 ..Default::default()
-            })
-            .context(format!("Failed to add shared shader module: {}", file_path))?;
+            });
+
+            match result {
+                Ok(_) => false,
+                Err(e) => {
+                    last_error = Some((file_path.clone(), e));
+                    true
+                }
+            }
+        });
+
+        if pending.len() == count_before {
+            if let Some((file_path, e)) = last_error {
+                return Err(e)
+                    .context(format!("Failed to add shared shader module: {}", file_path));
+            }
+        }
     }
 
     Ok(composer)
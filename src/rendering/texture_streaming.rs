@@ -0,0 +1,587 @@
+use std::collections::HashMap;
+
+use glam::Vec3;
+use wgpu::{TexelCopyBufferLayout, TexelCopyTextureInfo};
+
+use crate::{
+    asset_pipeline::materials::CompressedTextureData,
+    camera::Camera,
+    rendering::{
+        render_material_manager::{TextureEntry, TextureType},
+        util::mipmap_generator::{self, MipmapGenerator},
+    },
+    scene_graph::scene::Scene,
+};
+
+/// Textures are loaded down to this size at startup, and anything larger is streamed
+pub const BASE_SIZE: u32 = 256;
+/// Textures streamed in per frame, and evictions per frame
+const MAX_UPLOADS_PER_FRAME: usize = 2;
+/// Mips that haven't been needed for this many frames are evicted, even within the budget
+const EVICTION_DELAY_FRAMES: u64 = 180;
+
+/// Full resolution data of a streamed texture, kept on the CPU so that any mip can be uploaded
+pub enum StreamingSource {
+    /// RGBA8 image, whose mips are generated on the GPU
+    Rgba(gltf::image::Data),
+    Compressed(CompressedTextureData),
+}
+
+impl StreamingSource {
+    fn size(&self) -> (u32, u32) {
+        match self {
+            StreamingSource::Rgba(image) => (image.width, image.height),
+            StreamingSource::Compressed(data) => (data.width, data.height),
+        }
+    }
+}
+
+/// Mip of a texture, where mip 0 is `size`
+fn mip_size(size: (u32, u32), level: u32) -> wgpu::Extent3d {
+    wgpu::Extent3d {
+        width: (size.0 >> level).max(1),
+        height: (size.1 >> level).max(1),
+        depth_or_array_layers: 1,
+    }
+}
+
+/// Bytes of the mips from `first` until `end`
+fn mips_byte_size(format: wgpu::TextureFormat, size: (u32, u32), first: u32, end: u32) -> u64 {
+    let (block_width, block_height) = format.block_dimensions();
+    let block_size = format.block_copy_size(None).unwrap_or(4) as u64;
+
+    (first..end)
+        .map(|level| {
+            let mip = mip_size(size, level).physical_size(format);
+            (mip.width / block_width) as u64 * (mip.height / block_height) as u64 * block_size
+        })
+        .sum()
+}
+
+/// Most detailed mip that's kept resident at all times. Block compressed textures can only be
+/// created with sizes that are multiples of the block size, which can stop it short of
+/// `BASE_SIZE`.
+fn base_level(format: wgpu::TextureFormat, size: (u32, u32)) -> u32 {
+    let (block_width, block_height) = format.block_dimensions();
+    let mut level = 0;
+
+    while size.0.max(size.1) >> level > BASE_SIZE {
+        let next = mip_size(size, level + 1);
+        if next.width % block_width != 0 || next.height % block_height != 0 {
+            break;
+        }
+        level += 1;
+    }
+
+    level
+}
+
+struct StreamedTexture {
+    /// Index in the material texture binding array, which stays the same as the texture is
+    /// replaced
+    texture_index: usize,
+    label: String,
+    format: wgpu::TextureFormat,
+    source: StreamingSource,
+    size: (u32, u32),
+    mip_count: u32,
+    base_level: u32,
+    /// Most detailed mip on the GPU
+    resident_level: u32,
+    /// Most detailed mip the camera can make use of this frame
+    wanted_level: u32,
+    /// Picked from the wanted levels within the budget
+    target_level: u32,
+    /// When the resident mips were last needed
+    last_needed_frame: u64,
+}
+
+impl StreamedTexture {
+    fn byte_size(&self, level: u32) -> u64 {
+        mips_byte_size(self.format, self.size, level, self.mip_count)
+    }
+
+    /// Creates the texture with the mips from `level` onwards
+    fn create_texture(&self, device: &wgpu::Device, level: u32) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(&self.label),
+            size: mip_size(self.size, level),
+            mip_level_count: self.mip_count - level,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        })
+    }
+
+    /// Fills the mips of a texture created with `create_texture(level)` from the source.
+    /// Compressed textures come with their mips. RGBA images are uploaded at full resolution
+    /// into a scratch texture, and their mips are generated and copied over on the GPU.
+    fn fill_mips(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        mipmap_generator: &mut MipmapGenerator,
+        texture: &wgpu::Texture,
+        level: u32,
+    ) {
+        match &self.source {
+            StreamingSource::Compressed(data) => {
+                let mips = &data.mips[level as usize..self.mip_count as usize];
+                self.write_mips(queue, texture, level, level, mips);
+            }
+            StreamingSource::Rgba(image) => {
+                let scratch = device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some(&format!("{} (mip generation)", self.label)),
+                    size: mip_size(self.size, 0),
+                    mip_level_count: self.mip_count,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: self.format,
+                    usage: wgpu::TextureUsages::TEXTURE_BINDING
+                        | wgpu::TextureUsages::COPY_DST
+                        | wgpu::TextureUsages::COPY_SRC
+                        | wgpu::TextureUsages::RENDER_ATTACHMENT,
+                    view_formats: &[],
+                });
+
+                self.write_mips(queue, &scratch, 0, 0, std::slice::from_ref(&image.pixels));
+                mipmap_generator.generate(encoder, &scratch);
+                self.copy_mips(encoder, (&scratch, 0), (texture, level));
+            }
+        }
+    }
+
+    /// Writes mips starting from `level`, with `texture` starting from `texture_level`
+    fn write_mips(
+        &self,
+        queue: &wgpu::Queue,
+        texture: &wgpu::Texture,
+        texture_level: u32,
+        level: u32,
+        mips: &[Vec<u8>],
+    ) {
+        let (block_width, block_height) = self.format.block_dimensions();
+        let block_size = self.format.block_copy_size(None).unwrap_or(4);
+
+        for (index, data) in mips.iter().enumerate() {
+            let size = mip_size(self.size, level + index as u32).physical_size(self.format);
+            let blocks_wide = size.width / block_width;
+            let blocks_high = size.height / block_height;
+
+            queue.write_texture(
+                TexelCopyTextureInfo {
+                    texture,
+                    mip_level: level + index as u32 - texture_level,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                data,
+                TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(blocks_wide * block_size),
+                    rows_per_image: Some(blocks_high),
+                },
+                size,
+            );
+        }
+    }
+
+    /// Copies the mips that `from` and `to` have in common. Levels are relative to the full
+    /// resolution texture.
+    fn copy_mips(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        from: (&wgpu::Texture, u32),
+        to: (&wgpu::Texture, u32),
+    ) {
+        let (from, from_level) = from;
+        let (to, to_level) = to;
+
+        for level in from_level.max(to_level)..self.mip_count {
+            encoder.copy_texture_to_texture(
+                TexelCopyTextureInfo {
+                    texture: from,
+                    mip_level: level - from_level,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                TexelCopyTextureInfo {
+                    texture: to,
+                    mip_level: level - to_level,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                mip_size(self.size, level).physical_size(self.format),
+            );
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct StreamingStats {
+    streamed_in: usize,
+    evicted: usize,
+}
+
+/// Keeps VRAM bounded by loading only the mips of large material textures that the camera can
+/// resolve. Every texture keeps its mips from `base_level` down at all times. More detailed
+/// mips are uploaded a few textures at a time, and dropped again when they haven't been needed
+/// for a while or the budget runs out. Textures are replaced as a whole, so their indices in the
+/// binding array stay the same.
+pub struct TextureStreamer {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    textures: Vec<StreamedTexture>,
+    /// Index in `textures` by the texture index of the material manager
+    by_texture_index: HashMap<usize, usize>,
+    frame: u64,
+    budget_bytes: u64,
+    enabled: bool,
+    stats: StreamingStats,
+}
+
+impl TextureStreamer {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, budget_mb: u32) -> Self {
+        Self {
+            device: device.clone(),
+            queue: queue.clone(),
+            textures: Vec::new(),
+            by_texture_index: HashMap::new(),
+            frame: 0,
+            budget_bytes: budget_mb as u64 * 1024 * 1024,
+            enabled: true,
+            stats: StreamingStats::default(),
+        }
+    }
+
+    /// Creates a texture with the mips from the base level onwards, and starts streaming the
+    /// rest when they're needed
+    pub fn add(
+        &mut self,
+        mipmap_generator: &mut MipmapGenerator,
+        label: &str,
+        ty: TextureType,
+        texture_index: usize,
+        format: wgpu::TextureFormat,
+        source: StreamingSource,
+    ) -> TextureEntry {
+        let size = source.size();
+        let mip_count = match &source {
+            StreamingSource::Rgba(_) => mipmap_generator::mip_level_count(size.0, size.1),
+            StreamingSource::Compressed(data) => data.mips.len() as u32,
+        };
+        let base_level = base_level(format, size).min(mip_count - 1);
+
+        let streamed = StreamedTexture {
+            texture_index,
+            label: label.to_string(),
+            format,
+            source,
+            size,
+            mip_count,
+            base_level,
+            resident_level: base_level,
+            wanted_level: base_level,
+            target_level: base_level,
+            last_needed_frame: 0,
+        };
+
+        let texture = streamed.create_texture(&self.device, base_level);
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Texture streaming encoder"),
+            });
+        streamed.fill_mips(
+            &self.device,
+            &self.queue,
+            &mut encoder,
+            mipmap_generator,
+            &texture,
+            base_level,
+        );
+        self.queue.submit([encoder.finish()]);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        self.by_texture_index
+            .insert(texture_index, self.textures.len());
+        self.textures.push(streamed);
+
+        TextureEntry { ty, texture, view }
+    }
+
+    /// Asks for enough detail to cover `texels` across the texture. Called for each use of the
+    /// texture before `update`, which uses the most detailed request.
+    pub fn request(&mut self, texture_index: usize, texels: f32) {
+        let Some(&id) = self.by_texture_index.get(&texture_index) else {
+            return;
+        };
+
+        let texture = &mut self.textures[id];
+        let largest_side = texture.size.0.max(texture.size.1) as f32;
+        let level = if texels > 0.0 {
+            (largest_side / texels).log2().floor().max(0.0) as u32
+        } else {
+            texture.base_level
+        };
+
+        texture.wanted_level = texture.wanted_level.min(level.min(texture.base_level));
+    }
+
+    /// Streams in and evicts mips based on the requests made since the previous update, and
+    /// replaces the changed textures in `entries`. Returns true if any were replaced.
+    pub fn update(
+        &mut self,
+        mipmap_generator: &mut MipmapGenerator,
+        entries: &mut [TextureEntry],
+    ) -> bool {
+        self.frame += 1;
+        let frame = self.frame;
+
+        for texture in &mut self.textures {
+            if !self.enabled {
+                texture.wanted_level = texture.base_level;
+            }
+            if texture.wanted_level <= texture.resident_level {
+                texture.last_needed_frame = frame;
+            }
+        }
+
+        self.plan_budget();
+
+        let mut changed = self.evict(entries);
+        changed |= self.stream_in(mipmap_generator, entries);
+
+        for texture in &mut self.textures {
+            texture.wanted_level = texture.base_level;
+        }
+
+        changed
+    }
+
+    /// Picks target levels so that the resident textures fit into the budget. The base levels
+    /// are always resident, and the textures that want the most detail get it first.
+    fn plan_budget(&mut self) {
+        let base_bytes: u64 = self
+            .textures
+            .iter()
+            .map(|texture| texture.byte_size(texture.base_level))
+            .sum();
+        let mut remaining = self.budget_bytes.saturating_sub(base_bytes);
+
+        let mut order = (0..self.textures.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&id| {
+            let texture = &self.textures[id];
+            (
+                texture.wanted_level,
+                texture.byte_size(texture.wanted_level),
+            )
+        });
+
+        for id in order {
+            let texture = &mut self.textures[id];
+            let base_size = texture.byte_size(texture.base_level);
+
+            texture.target_level = texture.base_level;
+            for level in texture.wanted_level..texture.base_level {
+                let extra = texture.byte_size(level) - base_size;
+                if extra <= remaining {
+                    remaining -= extra;
+                    texture.target_level = level;
+                    break;
+                }
+            }
+        }
+    }
+
+    fn resident_bytes(&self) -> u64 {
+        self.textures
+            .iter()
+            .map(|texture| texture.byte_size(texture.resident_level))
+            .sum()
+    }
+
+    /// Uploads the mips between the target and the resident level of the textures that want
+    /// the most detail
+    fn stream_in(
+        &mut self,
+        mipmap_generator: &mut MipmapGenerator,
+        entries: &mut [TextureEntry],
+    ) -> bool {
+        let mut wanted = self
+            .textures
+            .iter()
+            .enumerate()
+            .filter(|(_, texture)| texture.target_level < texture.resident_level)
+            .map(|(id, texture)| (texture.target_level, id))
+            .collect::<Vec<_>>();
+        wanted.sort_unstable();
+
+        if wanted.is_empty() {
+            return false;
+        }
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Texture streaming encoder"),
+            });
+
+        for (_, id) in wanted.into_iter().take(MAX_UPLOADS_PER_FRAME) {
+            let texture = &mut self.textures[id];
+            let entry = &mut entries[texture.texture_index];
+
+            let new_texture = texture.create_texture(&self.device, texture.target_level);
+            texture.fill_mips(
+                &self.device,
+                &self.queue,
+                &mut encoder,
+                mipmap_generator,
+                &new_texture,
+                texture.target_level,
+            );
+
+            entry.view = new_texture.create_view(&wgpu::TextureViewDescriptor::default());
+            entry.texture = new_texture;
+            texture.resident_level = texture.target_level;
+            texture.last_needed_frame = self.frame;
+            self.stats.streamed_in += 1;
+        }
+
+        // Before the frame that samples the new textures
+        self.queue.submit([encoder.finish()]);
+        true
+    }
+
+    /// Drops the mips that haven't been needed for a while, or the least recently needed ones
+    /// when over the budget
+    fn evict(&mut self, entries: &mut [TextureEntry]) -> bool {
+        let over_budget = self.resident_bytes() > self.budget_bytes;
+        let frame = self.frame;
+
+        let mut candidates = self
+            .textures
+            .iter()
+            .enumerate()
+            .filter(|(_, texture)| {
+                texture.resident_level < texture.target_level
+                    && (over_budget || frame - texture.last_needed_frame > EVICTION_DELAY_FRAMES)
+            })
+            .map(|(id, texture)| (texture.last_needed_frame, id))
+            .collect::<Vec<_>>();
+        candidates.sort_unstable();
+
+        if candidates.is_empty() {
+            return false;
+        }
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Texture eviction encoder"),
+            });
+
+        for (_, id) in candidates.into_iter().take(MAX_UPLOADS_PER_FRAME) {
+            let texture = &mut self.textures[id];
+            let entry = &mut entries[texture.texture_index];
+
+            let new_texture = texture.create_texture(&self.device, texture.target_level);
+            texture.copy_mips(
+                &mut encoder,
+                (&entry.texture, texture.resident_level),
+                (&new_texture, texture.target_level),
+            );
+
+            entry.view = new_texture.create_view(&wgpu::TextureViewDescriptor::default());
+            entry.texture = new_texture;
+            texture.resident_level = texture.target_level;
+            self.stats.evicted += 1;
+        }
+
+        self.queue.submit([encoder.finish()]);
+        true
+    }
+
+    pub fn draw_ui(&mut self, ui: &imgui::Ui) {
+        if self.textures.is_empty() {
+            ui.text("No streamed textures");
+            return;
+        }
+
+        ui.checkbox("Stream textures", &mut self.enabled);
+
+        let full_bytes: u64 = self
+            .textures
+            .iter()
+            .map(|texture| texture.byte_size(0))
+            .sum();
+        // The budget is a quality setting, see the settings window
+        ui.text(format!(
+            "Resident: {:.1} / {:.1} MB ({:.1} MB at full resolution)",
+            self.resident_bytes() as f64 / (1024.0 * 1024.0),
+            self.budget_bytes as f64 / (1024.0 * 1024.0),
+            full_bytes as f64 / (1024.0 * 1024.0)
+        ));
+        ui.text(format!(
+            "Streamed in: {}, evicted: {}",
+            self.stats.streamed_in, self.stats.evicted
+        ));
+
+        if let Some(_node) = ui.tree_node("Streamed textures") {
+            for texture in &self.textures {
+                let resident = mip_size(texture.size, texture.resident_level);
+                ui.text(format!(
+                    "{}: {}x{}",
+                    texture.label, resident.width, resident.height
+                ));
+            }
+        }
+    }
+}
+
+/// Largest size in pixels that each material covers on the screen, by material index. Based on
+/// the bounding spheres of the primitives, so it's a rough upper bound.
+pub fn material_screen_coverage(
+    scene: &Scene,
+    camera: &Camera,
+    screen_height: f32,
+) -> HashMap<usize, f32> {
+    let mut coverage = HashMap::<usize, f32>::new();
+    let pixels_per_unit = screen_height / (2.0 * (camera.fov_y_radians * 0.5).tan());
+
+    let mut add = |material_index: usize, center: Vec3, radius: f32| {
+        let distance = (center.distance(camera.eye) - radius).max(camera.near);
+        let pixels = 2.0 * radius / distance * pixels_per_unit;
+        let entry = coverage.entry(material_index).or_default();
+        *entry = entry.max(pixels);
+    };
+
+    for (_, object) in scene.objects.iter() {
+        if !object.enabled {
+            continue;
+        }
+
+        let matrix = object.transform.get_world_matrix();
+
+        if let Some(model) = object.model_id.and_then(|id| scene.models.get(id)) {
+            for primitive in &model.model.primitives {
+                let bounds = primitive.bounding_box.transformed(*matrix);
+                let radius = (bounds.max - bounds.min).length() * 0.5;
+                add(primitive.material_id.index(), bounds.center(), radius);
+            }
+        }
+
+        // Decals are unit cubes scaled by the transform
+        if let Some(material_id) = object.decal.as_ref().and_then(|decal| decal.material_id) {
+            let radius = matrix.x_axis.length().max(matrix.y_axis.length()) * 0.5;
+            add(material_id.index(), matrix.w_axis.truncate(), radius);
+        }
+    }
+
+    coverage
+}
@@ -15,6 +15,15 @@ struct BindingConfig<'a> {
 enum BindingConfigType {
     // This could be extended to support more types
     Buffer(wgpu::BufferBindingType),
+    Texture {
+        sample_type: wgpu::TextureSampleType,
+        view_dimension: wgpu::TextureViewDimension,
+    },
+    Sampler(wgpu::SamplerBindingType),
+    StorageTexture {
+        format: wgpu::TextureFormat,
+        view_dimension: wgpu::TextureViewDimension,
+    },
 }
 
 impl<'a> BindGroupBuilder<'a> {
@@ -78,7 +87,173 @@ impl<'a> BindGroupBuilder<'a> {
         self
     }
 
+    pub fn texture_2d(
+        mut self,
+        index: u32,
+        name: impl Into<String>,
+        sample_type: wgpu::TextureSampleType,
+        resource: wgpu::BindingResource<'a>,
+    ) -> Self {
+        self.bindings.push(BindingConfig {
+            index,
+            _name: name.into(),
+            binding_type: BindingConfigType::Texture {
+                sample_type,
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            count: None,
+            resource,
+        });
+        self
+    }
+
+    pub fn texture_2d_array(
+        mut self,
+        index: u32,
+        name: impl Into<String>,
+        sample_type: wgpu::TextureSampleType,
+        resource: wgpu::BindingResource<'a>,
+    ) -> Self {
+        self.bindings.push(BindingConfig {
+            index,
+            _name: name.into(),
+            binding_type: BindingConfigType::Texture {
+                sample_type,
+                view_dimension: wgpu::TextureViewDimension::D2Array,
+            },
+            count: None,
+            resource,
+        });
+        self
+    }
+
+    pub fn texture_cube(
+        mut self,
+        index: u32,
+        name: impl Into<String>,
+        sample_type: wgpu::TextureSampleType,
+        resource: wgpu::BindingResource<'a>,
+    ) -> Self {
+        self.bindings.push(BindingConfig {
+            index,
+            _name: name.into(),
+            binding_type: BindingConfigType::Texture {
+                sample_type,
+                view_dimension: wgpu::TextureViewDimension::Cube,
+            },
+            count: None,
+            resource,
+        });
+        self
+    }
+
+    pub fn texture_cube_array(
+        mut self,
+        index: u32,
+        name: impl Into<String>,
+        sample_type: wgpu::TextureSampleType,
+        resource: wgpu::BindingResource<'a>,
+    ) -> Self {
+        self.bindings.push(BindingConfig {
+            index,
+            _name: name.into(),
+            binding_type: BindingConfigType::Texture {
+                sample_type,
+                view_dimension: wgpu::TextureViewDimension::CubeArray,
+            },
+            count: None,
+            resource,
+        });
+        self
+    }
+
+    pub fn texture_3d(
+        mut self,
+        index: u32,
+        name: impl Into<String>,
+        sample_type: wgpu::TextureSampleType,
+        resource: wgpu::BindingResource<'a>,
+    ) -> Self {
+        self.bindings.push(BindingConfig {
+            index,
+            _name: name.into(),
+            binding_type: BindingConfigType::Texture {
+                sample_type,
+                view_dimension: wgpu::TextureViewDimension::D3,
+            },
+            count: None,
+            resource,
+        });
+        self
+    }
+
+    pub fn sampler(
+        mut self,
+        index: u32,
+        name: impl Into<String>,
+        sampler_type: wgpu::SamplerBindingType,
+        resource: wgpu::BindingResource<'a>,
+    ) -> Self {
+        self.bindings.push(BindingConfig {
+            index,
+            _name: name.into(),
+            binding_type: BindingConfigType::Sampler(sampler_type),
+            count: None,
+            resource,
+        });
+        self
+    }
+
+    /// Write-only storage texture, e.g. the output of a compute pass
+    pub fn storage_texture_2d(
+        mut self,
+        index: u32,
+        name: impl Into<String>,
+        format: wgpu::TextureFormat,
+        resource: wgpu::BindingResource<'a>,
+    ) -> Self {
+        self.bindings.push(BindingConfig {
+            index,
+            _name: name.into(),
+            binding_type: BindingConfigType::StorageTexture {
+                format,
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            count: None,
+            resource,
+        });
+        self
+    }
+
+    /// Write-only storage texture array, e.g. for writing all faces of a cubemap
+    pub fn storage_texture_2d_array(
+        mut self,
+        index: u32,
+        name: impl Into<String>,
+        format: wgpu::TextureFormat,
+        resource: wgpu::BindingResource<'a>,
+    ) -> Self {
+        self.bindings.push(BindingConfig {
+            index,
+            _name: name.into(),
+            binding_type: BindingConfigType::StorageTexture {
+                format,
+                view_dimension: wgpu::TextureViewDimension::D2Array,
+            },
+            count: None,
+            resource,
+        });
+        self
+    }
+
     pub fn build(self, device: &wgpu::Device) -> (wgpu::BindGroupLayout, wgpu::BindGroup) {
+        let layout = self.build_layout(device);
+        let group = self.build_with_layout(device, &layout);
+
+        (layout, group)
+    }
+
+    pub fn build_layout(&self, device: &wgpu::Device) -> wgpu::BindGroupLayout {
         let layout_entries: Vec<wgpu::BindGroupLayoutEntry> = self
             .bindings
             .iter()
@@ -91,6 +266,25 @@ impl<'a> BindGroupBuilder<'a> {
                         has_dynamic_offset: false,
                         min_binding_size: None,
                     },
+                    BindingConfigType::Texture {
+                        sample_type,
+                        view_dimension,
+                    } => wgpu::BindingType::Texture {
+                        sample_type: *sample_type,
+                        view_dimension: *view_dimension,
+                        multisampled: false,
+                    },
+                    BindingConfigType::Sampler(sampler_type) => {
+                        wgpu::BindingType::Sampler(*sampler_type)
+                    }
+                    BindingConfigType::StorageTexture {
+                        format,
+                        view_dimension,
+                    } => wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: *format,
+                        view_dimension: *view_dimension,
+                    },
                 },
                 count: binding.count,
             })
@@ -98,11 +292,18 @@ impl<'a> BindGroupBuilder<'a> {
 
         let layout_label = format!("{} bind group layout", self.name);
 
-        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some(&layout_label),
             entries: &layout_entries,
-        });
+        })
+    }
 
+    /// Creates a bind group for an existing layout, e.g. when recreating it after a resize
+    pub fn build_with_layout(
+        self,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+    ) -> wgpu::BindGroup {
         let bind_entries = self
             .bindings
             .into_iter()
@@ -114,12 +315,10 @@ impl<'a> BindGroupBuilder<'a> {
 
         let group_label = format!("{} bind group", self.name);
 
-        let group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some(&group_label),
-            layout: &layout,
+            layout,
             entries: &bind_entries,
-        });
-
-        (layout, group)
+        })
     }
 }
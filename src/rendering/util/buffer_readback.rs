@@ -0,0 +1,137 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+struct StagingBuffer {
+    buffer: wgpu::Buffer,
+    /// Copied to during the current frame
+    written: bool,
+    /// Submitted and waiting to be mapped
+    in_flight: bool,
+    mapped: Arc<AtomicBool>,
+    /// Used to pick the newest result if several complete at once
+    frame: u64,
+}
+
+/// Copies GPU buffers to the CPU without stalling. Results arrive a few frames late, and frames
+/// are skipped if all staging buffers are still in flight. Mapping callbacks only run when the
+/// device is polled.
+pub struct BufferReadback {
+    staging_buffers: Vec<StagingBuffer>,
+    current: Option<usize>,
+    frame: u64,
+    latest: Option<(u64, Vec<u8>)>,
+}
+
+impl BufferReadback {
+    pub fn new(device: &wgpu::Device, label: &str, size: u64, buffer_count: usize) -> Self {
+        let staging_buffers = (0..buffer_count)
+            .map(|_| StagingBuffer {
+                buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some(label),
+                    size,
+                    usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }),
+                written: false,
+                in_flight: false,
+                mapped: Arc::new(AtomicBool::new(false)),
+                frame: 0,
+            })
+            .collect();
+
+        Self {
+            staging_buffers,
+            current: None,
+            frame: 0,
+            latest: None,
+        }
+    }
+
+    /// Copies the first `size` bytes of `source` to `destination_offset` in this frame's staging
+    /// buffer
+    pub fn copy(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &wgpu::Buffer,
+        destination_offset: u64,
+        size: u64,
+    ) {
+        if self.current.is_none() {
+            self.current = self
+                .staging_buffers
+                .iter()
+                .position(|staging| !staging.in_flight);
+        }
+
+        let Some(current) = self.current else {
+            return;
+        };
+
+        let staging = &mut self.staging_buffers[current];
+        encoder.copy_buffer_to_buffer(source, 0, &staging.buffer, destination_offset, size);
+        staging.written = true;
+    }
+
+    /// Starts mapping this frame's staging buffer. Must be called after the copies have been
+    /// submitted. Returns the number `latest_with_frame` reports for this readback, or None if
+    /// nothing was copied.
+    pub fn after_submit(&mut self) -> Option<u64> {
+        let current = self.current.take()?;
+
+        let staging = &mut self.staging_buffers[current];
+        if !staging.written {
+            return None;
+        }
+
+        let frame = self.frame;
+        staging.written = false;
+        staging.in_flight = true;
+        staging.frame = frame;
+        self.frame += 1;
+
+        let mapped = staging.mapped.clone();
+        staging
+            .buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                if result.is_ok() {
+                    mapped.store(true, Ordering::Release);
+                }
+            });
+
+        Some(frame)
+    }
+
+    /// Contents of the most recently completed readback
+    pub fn latest(&mut self) -> Option<&[u8]> {
+        self.latest_with_frame().map(|(_, data)| data)
+    }
+
+    /// Like `latest`, with the number `after_submit` returned for the readback
+    pub fn latest_with_frame(&mut self) -> Option<(u64, &[u8])> {
+        for staging in &mut self.staging_buffers {
+            if !staging.in_flight || !staging.mapped.load(Ordering::Acquire) {
+                continue;
+            }
+
+            let is_newer = self
+                .latest
+                .as_ref()
+                .is_none_or(|(frame, _)| staging.frame > *frame);
+            if is_newer {
+                let data = staging.buffer.slice(..).get_mapped_range().to_vec();
+                self.latest = Some((staging.frame, data));
+            }
+
+            staging.buffer.unmap();
+            staging.mapped.store(false, Ordering::Release);
+            staging.in_flight = false;
+        }
+
+        self.latest
+            .as_ref()
+            .map(|(frame, data)| (*frame, data.as_slice()))
+    }
+}
@@ -1 +1,3 @@
 pub mod bind_group_builder;
+pub mod buffer_readback;
+pub mod mipmap_generator;
@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+
+use glam::{Quat, Vec3};
+use gltf::animation::{util::ReadOutputs, Interpolation};
+
+use crate::{
+    model::Buffers,
+    scene_graph::{object3d::ObjectId, scene::Scene},
+    timeline::{track::Interpolate, Easing, Keyframe, Track},
+};
+
+#[derive(Clone)]
+pub enum AnimatedProperty {
+    Translation(Track<Vec3>),
+    Rotation(Track<Quat>),
+    Scale(Track<Vec3>),
+}
+
+#[derive(Clone)]
+pub struct AnimationChannel {
+    pub target: ObjectId,
+    pub property: AnimatedProperty,
+}
+
+/// Keyframe animation imported from glTF. Channels write directly to object transforms.
+#[derive(Clone)]
+pub struct AnimationClip {
+    pub name: String,
+    pub channels: Vec<AnimationChannel>,
+    pub duration: f32,
+}
+
+impl AnimationClip {
+    /// `node_objects` maps glTF node indices to the objects spawned from them. Channels that
+    /// target other nodes, or morph target weights, are skipped.
+    pub fn from_gltf(
+        animation: &gltf::Animation,
+        buffers: Buffers,
+        node_objects: &HashMap<usize, ObjectId>,
+    ) -> Self {
+        let mut channels = Vec::new();
+
+        for channel in animation.channels() {
+            let Some(&target) = node_objects.get(&channel.target().node().index()) else {
+                continue;
+            };
+
+            let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+            let (Some(times), Some(outputs)) = (reader.read_inputs(), reader.read_outputs()) else {
+                continue;
+            };
+
+            let times = times.collect::<Vec<_>>();
+            let interpolation = channel.sampler().interpolation();
+
+            let property =
+                match outputs {
+                    ReadOutputs::Translations(values) => AnimatedProperty::Translation(
+                        create_track(&times, values.map(Vec3::from), interpolation),
+                    ),
+                    ReadOutputs::Rotations(values) => AnimatedProperty::Rotation(create_track(
+                        &times,
+                        values.into_f32().map(Quat::from_array),
+                        interpolation,
+                    )),
+                    ReadOutputs::Scales(values) => AnimatedProperty::Scale(create_track(
+                        &times,
+                        values.map(Vec3::from),
+                        interpolation,
+                    )),
+                    ReadOutputs::MorphTargetWeights(_) => continue,
+                };
+
+            channels.push(AnimationChannel { target, property });
+        }
+
+        let duration = channels
+            .iter()
+            .map(|channel| match &channel.property {
+                AnimatedProperty::Translation(track) => track.duration(),
+                AnimatedProperty::Rotation(track) => track.duration(),
+                AnimatedProperty::Scale(track) => track.duration(),
+            })
+            .fold(0.0, f32::max);
+
+        Self {
+            name: animation.name().unwrap_or("Unnamed").to_string(),
+            channels,
+            duration,
+        }
+    }
+
+    pub fn apply(&self, time: f32, scene: &mut Scene) {
+        for channel in &self.channels {
+            match &channel.property {
+                AnimatedProperty::Translation(track) => {
+                    if let Some(translation) = track.sample(time) {
+                        scene.set_object_translation(channel.target, translation);
+                    }
+                }
+                AnimatedProperty::Rotation(track) => {
+                    if let Some(rotation) = track.sample(time) {
+                        scene.set_object_rotation(channel.target, rotation.normalize());
+                    }
+                }
+                AnimatedProperty::Scale(track) => {
+                    if let Some(scale) = track.sample(time) {
+                        scene.set_object_scale(channel.target, scale);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Plays the animation clips of a spawned glTF scene
+pub struct AnimationPlayer {
+    pub clips: Vec<AnimationClip>,
+    pub active_clip: usize,
+    pub time: f32,
+    /// Negative speeds play the clip backwards
+    pub speed: f32,
+    pub looping: bool,
+    pub playing: bool,
+    /// How much the time moved during the last step, for interpolating between steps
+    last_step: f32,
+}
+
+impl AnimationPlayer {
+    pub fn new(clips: Vec<AnimationClip>) -> Self {
+        Self {
+            clips,
+            active_clip: 0,
+            time: 0.0,
+            speed: 1.0,
+            looping: true,
+            playing: true,
+            last_step: 0.0,
+        }
+    }
+
+    pub fn active_clip(&self) -> Option<&AnimationClip> {
+        self.clips.get(self.active_clip)
+    }
+
+    /// Selects a clip by name. Returns false if there is no such clip.
+    pub fn play(&mut self, name: &str) -> bool {
+        let Some(index) = self.clips.iter().position(|clip| clip.name == name) else {
+            return false;
+        };
+
+        self.active_clip = index;
+        self.time = 0.0;
+        self.playing = true;
+        self.last_step = 0.0;
+
+        true
+    }
+
+    pub fn advance(&mut self, delta_time: f32) {
+        let Some(duration) = self.active_clip().map(|clip| clip.duration) else {
+            return;
+        };
+
+        if !self.playing {
+            self.last_step = 0.0;
+            return;
+        }
+
+        let delta = delta_time * self.speed;
+        let previous_time = self.time;
+        self.time = self.wrap_time(self.time + delta, duration);
+        // A clamped clip stops at its end, but a looping one keeps moving across the wrap
+        self.last_step = if self.looping {
+            delta
+        } else {
+            self.time - previous_time
+        };
+    }
+
+    /// Time between the previous step and the current one, by `alpha` from 0 to 1
+    pub fn interpolated_time(&self, alpha: f32) -> f32 {
+        let Some(duration) = self.active_clip().map(|clip| clip.duration) else {
+            return self.time;
+        };
+
+        self.wrap_time(self.time - self.last_step * (1.0 - alpha), duration)
+    }
+
+    fn wrap_time(&self, time: f32, duration: f32) -> f32 {
+        if self.looping && duration > 0.0 {
+            time.rem_euclid(duration)
+        } else {
+            time.clamp(0.0, duration)
+        }
+    }
+
+    pub fn draw_ui(&mut self, ui: &imgui::Ui) {
+        let names = self
+            .clips
+            .iter()
+            .map(|clip| clip.name.as_str())
+            .collect::<Vec<_>>();
+
+        if ui.combo_simple_string("Clip", &mut self.active_clip, &names) {
+            self.time = 0.0;
+        }
+
+        ui.checkbox("Playing", &mut self.playing);
+        ui.same_line();
+        ui.checkbox("Looping", &mut self.looping);
+        ui.slider("Speed", -2.0, 2.0, &mut self.speed);
+
+        if let Some(duration) = self.active_clip().map(|clip| clip.duration) {
+            ui.slider("Time", 0.0, duration, &mut self.time);
+        }
+    }
+}
+
+fn create_track<T: Interpolate>(
+    times: &[f32],
+    values: impl Iterator<Item = T>,
+    interpolation: Interpolation,
+) -> Track<T> {
+    let (easing, values) = match interpolation {
+        Interpolation::Linear => (Easing::Linear, values.collect::<Vec<_>>()),
+        Interpolation::Step => (Easing::Step, values.collect()),
+        // Each keyframe is stored as an in-tangent, the value and an out-tangent. The tangents
+        // are ignored, which is close enough for densely sampled animations.
+        Interpolation::CubicSpline => (Easing::Linear, values.skip(1).step_by(3).collect()),
+    };
+
+    Track::from_keyframes(
+        times
+            .iter()
+            .zip(values)
+            .map(|(&time, value)| Keyframe::new(time, value, easing))
+            .collect(),
+    )
+}
@@ -0,0 +1,62 @@
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+
+use crate::material_manager::MaterialId;
+
+/// Decal component of an object. The decal is projected along the -Y axis of the object's unit
+/// box, which is scaled and rotated with the object, onto whatever is inside the box. It's
+/// blended into the color and normal of the G-buffer, so it's lit like the surface under it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Decal {
+    /// Name of a material in `SceneFile::materials`. The base color and normal textures are
+    /// used, and the alpha of the base color masks the decal.
+    pub material: String,
+    /// Resolved from `material` when the scene is spawned
+    #[serde(skip)]
+    pub material_id: Option<MaterialId>,
+    /// Linear color multiplied with the base color texture
+    pub color: Vec3,
+    pub opacity: f32,
+    /// How much the normal map replaces the normal of the surface, relative to the opacity
+    pub normal_opacity: f32,
+    /// Decals with a higher order are drawn on top of the ones with a lower order
+    pub order: i32,
+    /// Fraction of the box's half height where the decal fades out towards the top and bottom
+    pub edge_fade: f32,
+    /// Surfaces whose normal is further than this from the projection direction, as a cosine,
+    /// don't get the decal. It fades out gradually between facing it and this angle.
+    pub angle_fade: f32,
+}
+
+impl Default for Decal {
+    fn default() -> Self {
+        Self {
+            material: String::new(),
+            material_id: None,
+            color: Vec3::ONE,
+            opacity: 1.0,
+            normal_opacity: 1.0,
+            order: 0,
+            edge_fade: 0.2,
+            angle_fade: 0.2,
+        }
+    }
+}
+
+impl Decal {
+    pub fn draw_ui(&mut self, ui: &imgui::Ui) {
+        ui.text(format!("Material: {}", self.material));
+
+        let mut color = self.color.to_array();
+        if ui.color_edit3("Color", &mut color) {
+            self.color = color.into();
+        }
+
+        ui.slider("Opacity", 0.0, 1.0, &mut self.opacity);
+        ui.slider("Normal opacity", 0.0, 1.0, &mut self.normal_opacity);
+        ui.input_int("Order", &mut self.order).build();
+        ui.slider("Edge fade", 0.0, 1.0, &mut self.edge_fade);
+        ui.slider("Angle fade", -1.0, 1.0, &mut self.angle_fade);
+    }
+}
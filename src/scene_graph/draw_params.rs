@@ -0,0 +1,29 @@
+use glam::{Vec3, Vec4};
+use serde::{Deserialize, Serialize};
+
+/// Number of named shader parameters each object has
+pub const OBJECT_PARAM_COUNT: usize = 4;
+
+/// Per-object values that the shaders read from the drawables of the object, so that instances
+/// sharing a model and material can still look different
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DrawParams {
+    /// Multiplied with the base color of the material
+    pub tint: Vec3,
+    /// Multiplied with the emission of the material
+    pub emission: f32,
+    /// Shader parameters, named by `Scene::object_param_names`. Not used by the built-in
+    /// shaders, for effects like dissolves that vary per object.
+    pub params: Vec4,
+}
+
+impl Default for DrawParams {
+    fn default() -> Self {
+        Self {
+            tint: Vec3::ONE,
+            emission: 1.0,
+            params: Vec4::ZERO,
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+
+/// Scene-wide height fog, lit by the primary directional light. The density decreases
+/// exponentially above `base_height`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Fog {
+    /// Extinction per unit of distance at `base_height`. 0 disables the fog.
+    pub density: f32,
+    /// How quickly the density decreases with height
+    pub height_falloff: f32,
+    /// Height below which the density is clamped to `density`
+    pub base_height: f32,
+    /// Linear color of the light scattered by the fog
+    pub albedo: Vec3,
+    /// Henyey-Greenstein asymmetry. Positive values scatter forward, which makes the fog glow
+    /// around the light.
+    pub anisotropy: f32,
+    /// Constant light scattered from every direction, relative to the directional light
+    pub ambient: f32,
+}
+
+impl Default for Fog {
+    fn default() -> Self {
+        Self {
+            density: 0.0,
+            height_falloff: 0.5,
+            base_height: 0.0,
+            albedo: Vec3::ONE,
+            anisotropy: 0.6,
+            ambient: 0.05,
+        }
+    }
+}
+
+impl Fog {
+    pub fn is_enabled(&self) -> bool {
+        self.density > 0.0
+    }
+
+    pub fn draw_ui(&mut self, ui: &imgui::Ui) {
+        ui.slider("Density", 0.0, 1.0, &mut self.density);
+        ui.slider("Height falloff", 0.0, 4.0, &mut self.height_falloff);
+        ui.slider("Base height", -10.0, 10.0, &mut self.base_height);
+        ui.slider("Anisotropy", -0.9, 0.9, &mut self.anisotropy);
+        ui.slider("Ambient", 0.0, 1.0, &mut self.ambient);
+
+        let mut albedo = self.albedo.to_array();
+        if ui.color_edit3("Albedo", &mut albedo) {
+            self.albedo = albedo.into();
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use serde::{Deserialize, Serialize};
+
+/// A layer that objects can belong to. Passes only draw the objects in the layers of their
+/// filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Layer {
+    /// Drawn by the main camera
+    Camera,
+    /// Drawn into the shadow map
+    ShadowCaster,
+    /// For reflection passes, objects only in this layer are hidden from the camera
+    Reflection,
+    /// 3D elements of the UI, which are drawn by the camera but kept out of other passes
+    Ui3d,
+}
+
+impl Layer {
+    pub const ALL: [Layer; 4] = [
+        Layer::Camera,
+        Layer::ShadowCaster,
+        Layer::Reflection,
+        Layer::Ui3d,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Layer::Camera => "Camera",
+            Layer::ShadowCaster => "Shadow caster",
+            Layer::Reflection => "Reflection",
+            Layer::Ui3d => "UI 3D",
+        }
+    }
+}
+
+/// Bitmask of layers. Written as a list of layers in scene files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "Vec<Layer>", into = "Vec<Layer>")]
+pub struct Layers(u32);
+
+impl Layers {
+    pub const NONE: Layers = Layers(0);
+    pub const CAMERA: Layers = Layers::from_layer(Layer::Camera);
+    pub const SHADOW_CASTER: Layers = Layers::from_layer(Layer::ShadowCaster);
+    pub const UI_3D: Layers = Layers::from_layer(Layer::Ui3d);
+
+    pub const fn from_layer(layer: Layer) -> Layers {
+        Layers(1 << layer as u32)
+    }
+
+    /// Bits as stored in the drawable buffer
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub fn contains(self, layer: Layer) -> bool {
+        self.intersects(Layers::from_layer(layer))
+    }
+
+    pub fn intersects(self, other: Layers) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    pub fn set(&mut self, layer: Layer, enabled: bool) {
+        let bit = Layers::from_layer(layer).0;
+        if enabled {
+            self.0 |= bit;
+        } else {
+            self.0 &= !bit;
+        }
+    }
+
+    pub const fn union(self, other: Layers) -> Layers {
+        Layers(self.0 | other.0)
+    }
+
+    /// Checkbox for each layer. Returns true if any of them was changed.
+    pub fn draw_ui(&mut self, ui: &imgui::Ui, label: &str) -> bool {
+        let mut changed = false;
+
+        ui.text(label);
+        for layer in Layer::ALL {
+            let mut enabled = self.contains(layer);
+            if ui.checkbox(format!("{}##{}", layer.name(), label), &mut enabled) {
+                self.set(layer, enabled);
+                changed = true;
+            }
+        }
+
+        changed
+    }
+}
+
+/// Objects are drawn by the camera and cast shadows
+impl Default for Layers {
+    fn default() -> Self {
+        Layers::CAMERA.union(Layers::SHADOW_CASTER)
+    }
+}
+
+impl From<Vec<Layer>> for Layers {
+    fn from(layers: Vec<Layer>) -> Self {
+        layers.into_iter().fold(Layers::NONE, |layers, layer| {
+            layers.union(Layers::from_layer(layer))
+        })
+    }
+}
+
+impl From<Layers> for Vec<Layer> {
+    fn from(layers: Layers) -> Self {
+        Layer::ALL
+            .into_iter()
+            .filter(|layer| layers.contains(*layer))
+            .collect()
+    }
+}
@@ -0,0 +1,63 @@
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LightKind {
+    /// Infinitely far away light, e.g. the sun. Only the object's rotation matters.
+    Directional,
+    Point {
+        range: f32,
+    },
+    Spot {
+        range: f32,
+        /// Half-angle of the fully lit cone, in radians
+        inner_angle: f32,
+        /// Half-angle where the light fades out completely, in radians
+        outer_angle: f32,
+    },
+}
+
+/// Light component of an object. Lights shine along the object's local +Z axis.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Light {
+    pub kind: LightKind,
+    pub color: Vec3,
+    pub intensity: f32,
+}
+
+impl Light {
+    pub fn directional(color: Vec3, intensity: f32) -> Self {
+        Self {
+            kind: LightKind::Directional,
+            color,
+            intensity,
+        }
+    }
+
+    pub fn point(color: Vec3, intensity: f32, range: f32) -> Self {
+        Self {
+            kind: LightKind::Point { range },
+            color,
+            intensity,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn spot(
+        color: Vec3,
+        intensity: f32,
+        range: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    ) -> Self {
+        Self {
+            kind: LightKind::Spot {
+                range,
+                inner_angle,
+                outer_angle,
+            },
+            color,
+            intensity,
+        }
+    }
+}
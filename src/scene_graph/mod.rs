@@ -1,4 +1,18 @@
+pub mod animation;
+pub mod decal;
+pub mod draw_params;
+pub mod fog;
+pub mod layers;
+pub mod light;
 pub mod object3d;
+pub mod object_arena;
+pub mod particle_emitter;
+pub mod prefab;
+pub mod raycast;
+pub mod reflection_probe;
 pub mod scene;
+pub mod scene_file;
 pub mod scene_model;
+pub mod skin;
+pub mod text;
 pub mod transform;
@@ -1,24 +1,75 @@
 use glam::Vec3;
-use id_arena::Id;
 
+use crate::math::bounds::AABB;
 use crate::rendering::instancing::InstanceType;
+use crate::scene_graph::animation::AnimationPlayer;
+use crate::scene_graph::decal::Decal;
+use crate::scene_graph::draw_params::DrawParams;
+use crate::scene_graph::layers::Layers;
+use crate::scene_graph::light::Light;
+use crate::scene_graph::particle_emitter::ParticleEmitter;
+use crate::scene_graph::reflection_probe::ReflectionProbe;
 use crate::scene_graph::scene::Scene;
 use crate::scene_graph::scene_model::SceneModelId;
+use crate::scene_graph::skin::SkinId;
+use crate::scene_graph::text::Text;
 use crate::scene_graph::transform::Transform;
 
-pub type ObjectId = Id<Object3D>;
+/// Slot of an object in its scene, and the generation of the slot. Slots are reused after
+/// objects are removed, but the new objects get a new generation, so ids kept around after
+/// removing an object don't find the object that took its slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObjectId {
+    pub(super) index: u32,
+    pub(super) generation: u32,
+}
+
+impl ObjectId {
+    /// Index of the slot, shared by every object that has used it
+    pub fn index(self) -> usize {
+        self.index as usize
+    }
+}
 
 pub struct Object3D {
-    pub name: String,
+    /// Only set by the scene graph, so that the name index of the scene stays up to date. Objects
+    /// in a scene are renamed with `Scene::set_object_name`.
+    pub(super) name: String,
+    /// Groups that the object can be found by, see `Scene::objects_with_tag`. Changed with
+    /// `Scene::add_object_tag` once the object is in a scene.
+    pub(super) tags: Vec<String>,
     pub transform: Transform,
     pub model_id: Option<SceneModelId>,
+    pub skin_id: Option<SkinId>,
+    /// Animations of a spawned glTF scene, stored on its root object
+    pub animation: Option<AnimationPlayer>,
+    pub light: Option<Light>,
+    pub particle_emitter: Option<ParticleEmitter>,
+    pub text: Option<Text>,
+    pub decal: Option<Decal>,
+    pub reflection_probe: Option<ReflectionProbe>,
     pub instance_type: InstanceType,
     pub parent_id: Option<ObjectId>,
     pub child_ids: Vec<ObjectId>,
     pub enabled: bool,
+    /// Passes only draw the object if their layer filter includes one of these
+    pub layers: Layers,
+    /// Tint and other values for the shaders, only used by this object and not its children
+    pub draw_params: DrawParams,
+    /// Bounds of the model in world space, updated with the world transform. None for objects
+    /// without a model.
+    pub world_bounds: Option<AABB>,
 }
 
 impl Object3D {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
     #[allow(dead_code)]
     pub fn parent<'a>(&self, scene: &'a Scene) -> Option<&'a Object3D> {
         self.parent_id.and_then(|id| scene.get_object(id))
@@ -39,12 +90,23 @@ impl Default for Object3D {
     fn default() -> Self {
         Self {
             name: String::new(),
+            tags: Vec::new(),
             transform: Transform::from_translation(Vec3::ZERO),
             model_id: None,
+            skin_id: None,
+            animation: None,
+            light: None,
+            particle_emitter: None,
+            text: None,
+            decal: None,
+            reflection_probe: None,
             instance_type: InstanceType::default(),
             parent_id: None,
             child_ids: Vec::new(),
             enabled: true,
+            layers: Layers::default(),
+            draw_params: DrawParams::default(),
+            world_bounds: None,
         }
     }
 }
@@ -0,0 +1,203 @@
+use std::ops::{Index, IndexMut};
+
+use rayon::prelude::*;
+
+use crate::scene_graph::object3d::{Object3D, ObjectId};
+
+/// Objects of a scene by id. Slots of removed objects are reused by the next added objects, most
+/// recently removed first, so that the ids don't depend on timing. Every reuse moves the slot to
+/// a new generation, so ids of removed objects don't find the objects that take their slots.
+pub struct ObjectArena {
+    slots: Vec<ObjectSlot>,
+    /// Indices of the free slots, in the order they were freed
+    free_slots: Vec<u32>,
+}
+
+struct ObjectSlot {
+    object: Object3D,
+    generation: u32,
+    free: bool,
+}
+
+impl ObjectSlot {
+    fn id(&self, index: usize) -> ObjectId {
+        ObjectId {
+            index: index as u32,
+            generation: self.generation,
+        }
+    }
+
+    fn holds(&self, id: ObjectId) -> bool {
+        !self.free && self.generation == id.generation
+    }
+}
+
+impl ObjectArena {
+    pub fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            free_slots: Vec::new(),
+        }
+    }
+
+    pub fn insert(&mut self, object: Object3D) -> ObjectId {
+        match self.free_slots.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.object = object;
+                slot.free = false;
+                slot.id(index as usize)
+            }
+            None => {
+                let slot = ObjectSlot {
+                    object,
+                    generation: 0,
+                    free: false,
+                };
+                let id = slot.id(self.slots.len());
+                self.slots.push(slot);
+                id
+            }
+        }
+    }
+
+    /// Returns None if the object has already been removed
+    pub fn remove(&mut self, id: ObjectId) -> Option<Object3D> {
+        let slot = self
+            .slots
+            .get_mut(id.index())
+            .filter(|slot| slot.holds(id))?;
+
+        slot.free = true;
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free_slots.push(id.index);
+        Some(std::mem::take(&mut slot.object))
+    }
+
+    /// None if the object has been removed, even if its slot holds a new object
+    pub fn get(&self, id: ObjectId) -> Option<&Object3D> {
+        self.slots
+            .get(id.index())
+            .filter(|slot| slot.holds(id))
+            .map(|slot| &slot.object)
+    }
+
+    pub fn get_mut(&mut self, id: ObjectId) -> Option<&mut Object3D> {
+        self.slots
+            .get_mut(id.index())
+            .filter(|slot| slot.holds(id))
+            .map(|slot| &mut slot.object)
+    }
+
+    /// Number of slots including the free ones, i.e. one past the highest object index
+    pub fn slot_count(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Objects in the order of their indices, skipping free slots
+    pub fn iter(&self) -> impl Iterator<Item = (ObjectId, &Object3D)> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter(|(_, slot)| !slot.free)
+            .map(|(index, slot)| (slot.id(index), &slot.object))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (ObjectId, &mut Object3D)> {
+        self.slots
+            .iter_mut()
+            .enumerate()
+            .filter(|(_, slot)| !slot.free)
+            .map(|(index, slot)| (slot.id(index), &mut slot.object))
+    }
+
+    pub fn par_iter(&self) -> impl ParallelIterator<Item = (ObjectId, &Object3D)> {
+        self.slots
+            .par_iter()
+            .enumerate()
+            .filter(|(_, slot)| !slot.free)
+            .map(|(index, slot)| (slot.id(index), &slot.object))
+    }
+
+    pub fn par_iter_mut(&mut self) -> impl ParallelIterator<Item = (ObjectId, &mut Object3D)> {
+        self.slots
+            .par_iter_mut()
+            .enumerate()
+            .filter(|(_, slot)| !slot.free)
+            .map(|(index, slot)| (slot.id(index), &mut slot.object))
+    }
+}
+
+impl Default for ObjectArena {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Panics if the object has been removed
+impl Index<ObjectId> for ObjectArena {
+    type Output = Object3D;
+
+    fn index(&self, id: ObjectId) -> &Object3D {
+        self.get(id).expect("Object has been removed")
+    }
+}
+
+impl IndexMut<ObjectId> for ObjectArena {
+    fn index_mut(&mut self, id: ObjectId) -> &mut Object3D {
+        self.get_mut(id).expect("Object has been removed")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn named(name: &str) -> Object3D {
+        Object3D {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn reuses_most_recently_removed_slot() {
+        let mut objects = ObjectArena::new();
+        let a = objects.insert(named("a"));
+        let b = objects.insert(named("b"));
+        objects.remove(a);
+        objects.remove(b);
+
+        let c = objects.insert(named("c"));
+        assert_eq!(c.index(), b.index());
+        assert_eq!(objects.slot_count(), 2);
+    }
+
+    #[test]
+    fn removed_ids_dont_find_new_objects() {
+        let mut objects = ObjectArena::new();
+        let removed = objects.insert(named("removed"));
+        objects.remove(removed);
+        let added = objects.insert(named("added"));
+
+        assert_eq!(added.index(), removed.index());
+        assert_ne!(added, removed);
+        assert!(objects.get(removed).is_none());
+        assert!(objects.get_mut(removed).is_none());
+        assert!(objects.remove(removed).is_none());
+        assert_eq!(objects[added].name(), "added");
+    }
+
+    #[test]
+    fn iteration_skips_free_slots() {
+        let mut objects = ObjectArena::new();
+        let a = objects.insert(named("a"));
+        let b = objects.insert(named("b"));
+        let c = objects.insert(named("c"));
+        objects.remove(b);
+
+        let ids = objects.iter().map(|(id, _)| id).collect::<Vec<_>>();
+        assert_eq!(ids, vec![a, c]);
+        assert_eq!(objects.par_iter().count(), 2);
+    }
+}
@@ -0,0 +1,67 @@
+use glam::{Vec3, Vec4};
+use serde::{Deserialize, Serialize};
+
+/// Particle emitter component of an object. Particles are emitted from the object's origin
+/// along its local +Z axis, and simulated on the GPU in world space.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ParticleEmitter {
+    /// Particles per second
+    pub rate: f32,
+    /// In seconds
+    pub lifetime: f32,
+    /// Initial speed, in units per second
+    pub speed: f32,
+    /// Half-angle of the cone particles are emitted in, in radians
+    pub spread: f32,
+    /// Acceleration in world space
+    pub gravity: Vec3,
+    /// Width and height of the billboards
+    pub size: f32,
+    /// Linear color and opacity when a particle is emitted, interpolated to `color_end` over
+    /// its lifetime
+    pub color_start: Vec4,
+    pub color_end: Vec4,
+}
+
+impl Default for ParticleEmitter {
+    fn default() -> Self {
+        Self {
+            rate: 100.0,
+            lifetime: 2.0,
+            speed: 2.0,
+            spread: 0.3,
+            gravity: Vec3::new(0.0, -9.81, 0.0),
+            size: 0.1,
+            color_start: Vec4::new(1.0, 0.8, 0.4, 1.0),
+            color_end: Vec4::new(1.0, 0.2, 0.0, 0.0),
+        }
+    }
+}
+
+impl ParticleEmitter {
+    pub fn draw_ui(&mut self, ui: &imgui::Ui) {
+        ui.slider("Rate", 0.0, 2000.0, &mut self.rate);
+        ui.slider("Lifetime", 0.0, 10.0, &mut self.lifetime);
+        ui.slider("Speed", 0.0, 20.0, &mut self.speed);
+        imgui::AngleSlider::new("Spread")
+            .range_degrees(0.0, 180.0)
+            .build(ui, &mut self.spread);
+        ui.slider("Size", 0.0, 1.0, &mut self.size);
+
+        let mut gravity = self.gravity.to_array();
+        if ui.input_float3("Gravity", &mut gravity).build() {
+            self.gravity = gravity.into();
+        }
+
+        let mut color_start = self.color_start.to_array();
+        if ui.color_edit4("Start color", &mut color_start) {
+            self.color_start = color_start.into();
+        }
+
+        let mut color_end = self.color_end.to_array();
+        if ui.color_edit4("End color", &mut color_end) {
+            self.color_end = color_end.into();
+        }
+    }
+}
@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+
+use id_arena::Id;
+
+use crate::{
+    rendering::instancing::InstanceType,
+    scene_graph::{
+        animation::AnimationClip,
+        decal::Decal,
+        draw_params::DrawParams,
+        layers::Layers,
+        light::Light,
+        object3d::{Object3D, ObjectId},
+        particle_emitter::ParticleEmitter,
+        reflection_probe::ReflectionProbe,
+        scene::Scene,
+        scene_model::SceneModelId,
+        skin::{Skin, SkinId},
+        text::Text,
+        transform::Transform,
+    },
+};
+
+pub type PrefabId = Id<Prefab>;
+
+/// Object hierarchy imported once from a glTF scene, see `Scene::instantiate`. Instances share
+/// the models of the prefab, and copying the hierarchy doesn't touch the glTF document.
+pub struct Prefab {
+    /// Parents come before their children
+    nodes: Vec<PrefabNode>,
+    /// Node which instances are placed with, and which plays the animations
+    root: usize,
+    skins: Vec<Skin>,
+    clips: Vec<AnimationClip>,
+    /// Skin joints and animation channels still refer to the objects the prefab was captured
+    /// from, which are mapped to the objects of each instance
+    captured_nodes: HashMap<ObjectId, usize>,
+}
+
+struct PrefabNode {
+    name: String,
+    tags: Vec<String>,
+    transform: Transform,
+    model_id: Option<SceneModelId>,
+    skin: Option<usize>,
+    light: Option<Light>,
+    particle_emitter: Option<ParticleEmitter>,
+    text: Option<Text>,
+    decal: Option<Decal>,
+    reflection_probe: Option<ReflectionProbe>,
+    instance_type: InstanceType,
+    layers: Layers,
+    draw_params: DrawParams,
+    enabled: bool,
+    parent: Option<usize>,
+}
+
+impl Prefab {
+    /// Copies the hierarchies under `roots`. The objects can be removed afterwards.
+    pub fn capture(scene: &Scene, roots: &[ObjectId], root: ObjectId) -> Self {
+        let mut nodes = Vec::new();
+        let mut captured_nodes = HashMap::new();
+        let mut skin_indices: HashMap<SkinId, usize> = HashMap::new();
+        let mut skins = Vec::new();
+
+        for &tree_root in roots {
+            let mut stack = vec![(tree_root, None)];
+
+            while let Some((id, parent)) = stack.pop() {
+                let Some(object) = scene.get_object(id) else {
+                    continue;
+                };
+
+                let skin = object.skin_id.map(|skin_id| {
+                    *skin_indices.entry(skin_id).or_insert_with(|| {
+                        skins.push(scene.skins[skin_id].clone());
+                        skins.len() - 1
+                    })
+                });
+
+                let index = nodes.len();
+                captured_nodes.insert(id, index);
+                nodes.push(PrefabNode {
+                    name: object.name.clone(),
+                    tags: object.tags.clone(),
+                    transform: object.transform.clone(),
+                    model_id: object.model_id,
+                    skin,
+                    light: object.light.clone(),
+                    particle_emitter: object.particle_emitter.clone(),
+                    text: object.text.clone(),
+                    decal: object.decal.clone(),
+                    reflection_probe: object.reflection_probe.clone(),
+                    instance_type: object.instance_type,
+                    layers: object.layers,
+                    draw_params: object.draw_params,
+                    enabled: object.enabled,
+                    parent,
+                });
+
+                // Reversed, so that the children keep their order
+                stack.extend(
+                    object
+                        .child_ids
+                        .iter()
+                        .rev()
+                        .map(|&child| (child, Some(index))),
+                );
+            }
+        }
+
+        let clips = scene
+            .get_object(root)
+            .and_then(|object| object.animation.as_ref())
+            .map(|player| player.clips.clone())
+            .unwrap_or_default();
+
+        Self {
+            nodes,
+            root: captured_nodes[&root],
+            skins,
+            clips,
+            captured_nodes,
+        }
+    }
+
+    /// Name of the root object
+    pub fn name(&self) -> &str {
+        &self.nodes[self.root].name
+    }
+
+    /// Node which instances are placed with
+    pub fn root(&self) -> usize {
+        self.root
+    }
+
+    /// New objects for an instance with their parents, with `transform` as the transform of the
+    /// root. Parents come before their children.
+    pub fn objects(&self, transform: Transform) -> Vec<(Object3D, Option<usize>)> {
+        let mut transform = Some(transform);
+
+        self.nodes
+            .iter()
+            .enumerate()
+            .map(|(index, node)| {
+                let mut node_transform = if index == self.root {
+                    transform.take().unwrap()
+                } else {
+                    node.transform.clone()
+                };
+                // The copied world matrix belongs to the captured object
+                node_transform.invalidate_world();
+
+                let object = Object3D {
+                    name: node.name.clone(),
+                    tags: node.tags.clone(),
+                    transform: node_transform,
+                    model_id: node.model_id,
+                    light: node.light.clone(),
+                    particle_emitter: node.particle_emitter.clone(),
+                    text: node.text.clone(),
+                    decal: node.decal.clone(),
+                    reflection_probe: node.reflection_probe.clone(),
+                    instance_type: node.instance_type,
+                    layers: node.layers,
+                    draw_params: node.draw_params,
+                    enabled: node.enabled,
+                    ..Default::default()
+                };
+
+                (object, node.parent)
+            })
+            .collect()
+    }
+
+    /// Skins for the objects of an instance, and the nodes which use them
+    pub fn skins(&self, instance_ids: &[ObjectId]) -> Vec<(Skin, Vec<usize>)> {
+        self.skins
+            .iter()
+            .enumerate()
+            .map(|(skin_index, skin)| {
+                let skin = Skin {
+                    joints: skin
+                        .joints
+                        .iter()
+                        .map(|joint| joint.and_then(|id| self.instance_id(id, instance_ids)))
+                        .collect(),
+                    inverse_bind_matrices: skin.inverse_bind_matrices.clone(),
+                };
+
+                let skinned_nodes = self
+                    .nodes
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, node)| node.skin == Some(skin_index))
+                    .map(|(index, _)| index)
+                    .collect();
+
+                (skin, skinned_nodes)
+            })
+            .collect()
+    }
+
+    /// Animation clips for the objects of an instance
+    pub fn clips(&self, instance_ids: &[ObjectId]) -> Vec<AnimationClip> {
+        self.clips
+            .iter()
+            .map(|clip| {
+                let mut clip = clip.clone();
+                clip.channels.retain_mut(|channel| {
+                    match self.instance_id(channel.target, instance_ids) {
+                        Some(target) => {
+                            channel.target = target;
+                            true
+                        }
+                        None => false,
+                    }
+                });
+                clip
+            })
+            .collect()
+    }
+
+    fn instance_id(&self, captured_id: ObjectId, instance_ids: &[ObjectId]) -> Option<ObjectId> {
+        self.captured_nodes
+            .get(&captured_id)
+            .map(|&index| instance_ids[index])
+    }
+}
@@ -0,0 +1,111 @@
+use glam::{Vec2, Vec3};
+
+use crate::{
+    camera::Camera,
+    math::ray::Ray,
+    model::ModelPrimitive,
+    scene_graph::{object3d::ObjectId, scene::Scene},
+};
+
+/// What the ray is tested against. Every level first tests the world bounds of the object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RaycastPrecision {
+    /// World bounds of the object
+    #[allow(dead_code)]
+    Bounds,
+    /// Bounding boxes of the primitives, in the local space of the object
+    #[allow(dead_code)]
+    PrimitiveBounds,
+    /// Triangles of the primitives. Skinned primitives are tested in their bind pose.
+    Triangles,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub struct RaycastHit {
+    pub object_id: ObjectId,
+    /// Distance along the ray, in units of the ray direction
+    pub distance: f32,
+    pub point: Vec3,
+}
+
+/// Ray from the camera through a point on the screen, e.g. the mouse cursor
+pub fn screen_ray(camera: &Camera, screen_position: Vec2, screen_size: Vec2) -> Ray {
+    let view_proj = camera.get_vp_matrix(screen_size);
+    Ray::from_screen(screen_position, screen_size, view_proj.inverse())
+}
+
+/// Finds the closest enabled object with a model that the ray hits, as of the last transform
+/// update
+pub fn raycast(scene: &Scene, ray: &Ray, precision: RaycastPrecision) -> Option<RaycastHit> {
+    let mut closest: Option<(ObjectId, f32)> = None;
+
+    for (id, object) in scene.objects.iter() {
+        if !object.enabled {
+            continue;
+        }
+
+        let Some(model) = object.model_id.and_then(|id| scene.models.get(id)) else {
+            continue;
+        };
+
+        // Objects whose world bounds are missed, or are farther than the closest hit, can't
+        // contain a closer primitive
+        let Some(bounds_t) = object
+            .world_bounds
+            .and_then(|bounds| ray.intersect_aabb(&bounds))
+        else {
+            continue;
+        };
+        if closest.is_some_and(|(_, closest_t)| bounds_t >= closest_t) {
+            continue;
+        }
+
+        let t = match precision {
+            RaycastPrecision::Bounds => Some(bounds_t),
+            RaycastPrecision::PrimitiveBounds | RaycastPrecision::Triangles => {
+                let local_ray = ray.transform(object.transform.get_world_matrix().inverse());
+
+                model
+                    .model
+                    .primitives
+                    .iter()
+                    .filter_map(|primitive| {
+                        let t = local_ray.intersect_aabb(&primitive.bounding_box)?;
+
+                        match precision {
+                            RaycastPrecision::Triangles => {
+                                intersect_triangles(&local_ray, primitive)
+                            }
+                            _ => Some(t),
+                        }
+                    })
+                    .min_by(f32::total_cmp)
+            }
+        };
+
+        if let Some(t) = t {
+            if closest.is_none_or(|(_, closest_t)| t < closest_t) {
+                closest = Some((id, t));
+            }
+        }
+    }
+
+    closest.map(|(object_id, distance)| RaycastHit {
+        object_id,
+        distance,
+        point: ray.at(distance),
+    })
+}
+
+fn intersect_triangles(ray: &Ray, primitive: &ModelPrimitive) -> Option<f32> {
+    primitive
+        .indices
+        .chunks_exact(3)
+        .filter_map(|triangle| {
+            let [a, b, c] = [triangle[0], triangle[1], triangle[2]]
+                .map(|index| primitive.vertices[index as usize].position);
+            ray.intersect_triangle(a, b, c)
+        })
+        .min_by(f32::total_cmp)
+}
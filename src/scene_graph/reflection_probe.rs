@@ -0,0 +1,63 @@
+use glam::{Mat4, Vec3};
+use serde::{Deserialize, Serialize};
+
+/// Reflection probe component of an object. The surroundings are captured into a cubemap at the
+/// object's position, and surfaces inside the probe's box use it instead of the environment
+/// map. Reflections are projected onto the box, so it should follow the walls of the room.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReflectionProbe {
+    /// Half size of the box in the object's space
+    pub extents: Vec3,
+    /// Distance from the faces of the box over which the probe fades out to whatever is behind
+    /// it
+    pub blend_distance: f32,
+    /// Multiplier for the captured light
+    pub intensity: f32,
+    /// Nothing further than this is captured
+    pub far: f32,
+    /// Incremented to capture the probe again, e.g. after the room has changed. Probes are also
+    /// captured again when they move.
+    #[serde(skip)]
+    pub capture_generation: u32,
+}
+
+impl Default for ReflectionProbe {
+    fn default() -> Self {
+        Self {
+            extents: Vec3::splat(5.0),
+            blend_distance: 0.5,
+            intensity: 1.0,
+            far: 100.0,
+            capture_generation: 0,
+        }
+    }
+}
+
+impl ReflectionProbe {
+    /// Rotation and translation of the box, and its half size in world units. The object's
+    /// scale resizes the box instead of scaling the space it's in, so that distances inside the
+    /// box stay in world units.
+    pub fn world_box(&self, world_matrix: Mat4) -> (Mat4, Vec3) {
+        let (scale, rotation, translation) = world_matrix.to_scale_rotation_translation();
+        (
+            Mat4::from_rotation_translation(rotation, translation),
+            self.extents * scale.abs(),
+        )
+    }
+
+    pub fn draw_ui(&mut self, ui: &imgui::Ui) {
+        let mut extents = self.extents.to_array();
+        if ui.input_float3("Extents", &mut extents).build() {
+            self.extents = Vec3::from(extents).max(Vec3::splat(0.01));
+        }
+
+        ui.slider("Blend distance", 0.0, 5.0, &mut self.blend_distance);
+        ui.slider("Intensity", 0.0, 4.0, &mut self.intensity);
+        ui.slider("Far", 1.0, 1000.0, &mut self.far);
+
+        if ui.button("Recapture") {
+            self.capture_generation = self.capture_generation.wrapping_add(1);
+        }
+    }
+}
@@ -1,33 +1,115 @@
 use glam::{Mat4, Quat, Vec3};
 use id_arena::Arena;
+use rayon::prelude::*;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 
-use crate::material_manager::MaterialManager;
+use crate::asset_pipeline::generate_lods::{LodExtras, DEFAULT_LOD_DISTANCE};
+use crate::frame_pacing::{FixedSteps, FIXED_TIMESTEP};
+use crate::material_manager::{MaterialId, MaterialManager};
+use crate::math::bounds::AABB;
 use crate::model::{Buffers, Model};
+use crate::procgen::mesh_builder::MeshBuilder;
+use crate::procgen::terrain::Terrain;
 use crate::rendering::instancing::InstanceType;
+use crate::rendering::render_target_manager::RenderTargetDescription;
+use crate::scene_graph::animation::{AnimationClip, AnimationPlayer};
+use crate::scene_graph::decal::Decal;
+use crate::scene_graph::draw_params::{DrawParams, OBJECT_PARAM_COUNT};
+use crate::scene_graph::fog::Fog;
+use crate::scene_graph::layers::Layers;
+use crate::scene_graph::light::{Light, LightKind};
 use crate::scene_graph::object3d::{Object3D, ObjectId};
+use crate::scene_graph::object_arena::ObjectArena;
+use crate::scene_graph::prefab::{Prefab, PrefabId};
 use crate::scene_graph::scene_model::{SceneModel, SceneModelId};
+use crate::scene_graph::skin::Skin;
+use crate::scene_graph::text::Text;
 use crate::scene_graph::transform::Transform;
 
+/// Hierarchy versions are unique across scenes, so that switching scenes counts as a change
+static NEXT_HIERARCHY_VERSION: AtomicU64 = AtomicU64::new(0);
+
 pub struct Scene {
-    pub objects: Arena<Object3D>,
+    pub objects: ObjectArena,
     pub models: Arena<SceneModel>,
+    pub skins: Arena<Skin>,
+    pub prefabs: Arena<Prefab>,
+    pub fog: Fog,
+    /// Created by the renderer when it starts, changes after that have no effect
+    pub render_targets: Vec<RenderTargetDescription>,
+    /// Names of the shader parameters in `DrawParams::params`, by index. Set with
+    /// `set_object_param_names`, so that every index fits into the parameters.
+    object_param_names: Vec<String>,
     next_primitive_index: usize,
-    gltf_mesh_to_model: HashMap<usize, SceneModelId>,
+    /// Models by glTF file name and mesh index
+    gltf_mesh_to_model: HashMap<(String, usize), SceneModelId>,
+    /// Objects removed since the renderer last drew the scene, so that their drawables can be
+    /// freed. Kept across updates, since events can remove objects before `early_update`.
+    removed_objects: Vec<ObjectId>,
+    /// Objects by their depth in the hierarchy, so that each level can be updated in parallel
+    /// once its parents are done. Rebuilt when objects are added, removed or reparented.
+    hierarchy_levels: Vec<Vec<ObjectId>>,
+    hierarchy_changed: bool,
+    hierarchy_version: u64,
+    /// Names aren't unique, e.g. every copy of a glTF scene has the same node names
+    objects_by_name: HashMap<String, Vec<ObjectId>>,
+    objects_by_tag: HashMap<String, Vec<ObjectId>>,
 }
 
 impl Scene {
     pub fn new() -> Self {
         Self {
-            objects: Arena::new(),
+            objects: ObjectArena::new(),
             models: Arena::new(),
+            skins: Arena::new(),
+            prefabs: Arena::new(),
+            fog: Fog::default(),
+            render_targets: Vec::new(),
+            object_param_names: Vec::new(),
             next_primitive_index: 0,
             gltf_mesh_to_model: HashMap::new(),
+            removed_objects: Vec::new(),
+            hierarchy_levels: Vec::new(),
+            hierarchy_changed: true,
+            hierarchy_version: NEXT_HIERARCHY_VERSION.fetch_add(1, Ordering::Relaxed),
+            objects_by_name: HashMap::new(),
+            objects_by_tag: HashMap::new(),
+        }
+    }
+
+    /// For scenes whose meshes are baked after the meshes of other scenes, e.g. the scenes of
+    /// later demo parts
+    pub fn with_first_primitive_index(first_primitive_index: usize) -> Self {
+        Self {
+            next_primitive_index: first_primitive_index,
+            ..Self::new()
         }
     }
 
+    /// Global index that the next spawned primitive gets
+    pub fn next_primitive_index(&self) -> usize {
+        self.next_primitive_index
+    }
+
+    /// Numbers the primitives spawned from now on after `first_primitive_index`, e.g. when
+    /// spawning while other scenes have already used the following indices
+    pub fn continue_primitive_indices_from(&mut self, first_primitive_index: usize) {
+        self.next_primitive_index = self.next_primitive_index.max(first_primitive_index);
+    }
+
     pub fn add_object(&mut self, object: Object3D) -> ObjectId {
-        self.objects.alloc(object)
+        self.hierarchy_changed = true;
+
+        let id = self.objects.insert(object);
+
+        let object = &self.objects[id];
+        add_to_index(&mut self.objects_by_name, &object.name, id);
+        for tag in &object.tags {
+            add_to_index(&mut self.objects_by_tag, tag, id);
+        }
+
+        id
     }
 
     #[allow(dead_code)]
@@ -40,11 +122,164 @@ impl Scene {
         self.objects.get_mut(id)
     }
 
+    /// Text component of an object, e.g. for animating it
+    pub fn get_text_mut(&mut self, id: ObjectId) -> Option<&mut Text> {
+        self.get_object_mut(id)
+            .and_then(|object| object.text.as_mut())
+    }
+
+    /// Decal component of an object, e.g. for fading it in
+    pub fn get_decal_mut(&mut self, id: ObjectId) -> Option<&mut Decal> {
+        self.get_object_mut(id)
+            .and_then(|object| object.decal.as_mut())
+    }
+
+    /// Captures every reflection probe again, e.g. after the lighting has changed
+    pub fn recapture_reflection_probes(&mut self) {
+        for (_, object) in self.objects.iter_mut() {
+            if let Some(probe) = &mut object.reflection_probe {
+                probe.capture_generation = probe.capture_generation.wrapping_add(1);
+            }
+        }
+    }
+
+    /// Removes an object and all of its children. Models are kept, since other objects can use
+    /// them and their meshes are already baked. Returns false if the object doesn't exist.
+    pub fn despawn_object(&mut self, id: ObjectId) -> bool {
+        if self.get_object(id).is_none() {
+            return false;
+        }
+
+        self.set_object_parent(id, None);
+
+        let child_ids = std::mem::take(&mut self.objects[id].child_ids);
+        for child_id in child_ids {
+            self.despawn_object(child_id);
+        }
+
+        self.free_object(id);
+        true
+    }
+
+    /// Frees the slot of the object for the next added object
+    fn free_object(&mut self, id: ObjectId) {
+        let Some(object) = self.objects.remove(id) else {
+            return;
+        };
+
+        remove_from_index(&mut self.objects_by_name, &object.name, id);
+        for tag in &object.tags {
+            remove_from_index(&mut self.objects_by_tag, tag, id);
+        }
+
+        self.removed_objects.push(id);
+        self.hierarchy_changed = true;
+    }
+
+    /// Objects removed since the renderer last drew the scene. Renderers which track objects by
+    /// id must forget these, since their slots can already belong to new objects.
+    pub fn removed_objects(&self) -> &[ObjectId] {
+        &self.removed_objects
+    }
+
+    /// Called by the renderer once it has forgotten the removed objects
+    pub fn clear_removed_objects(&mut self) {
+        self.removed_objects.clear();
+    }
+
+    /// The first added object with the name
     #[allow(dead_code)]
     pub fn get_object_by_name(&self, name: &str) -> Option<ObjectId> {
+        self.objects_by_name.get(name)?.first().copied()
+    }
+
+    /// Copied, so that the objects can be modified while iterating
+    pub fn objects_with_name(&self, name: &str) -> Vec<ObjectId> {
+        self.objects_by_name.get(name).cloned().unwrap_or_default()
+    }
+
+    /// Copied, so that the objects can be modified while iterating
+    pub fn objects_with_tag(&self, tag: &str) -> Vec<ObjectId> {
+        self.objects_by_tag.get(tag).cloned().unwrap_or_default()
+    }
+
+    /// Names that at least one object has, for finding the objects with `objects_with_name`
+    pub fn object_names(&self) -> impl Iterator<Item = &str> {
+        self.objects_by_name.keys().map(String::as_str)
+    }
+
+    /// Tags that at least one object has, for finding the objects with `objects_with_tag`
+    pub fn object_tags(&self) -> impl Iterator<Item = &str> {
+        self.objects_by_tag.keys().map(String::as_str)
+    }
+
+    pub fn set_object_name(&mut self, object_id: ObjectId, name: impl Into<String>) {
+        let name = name.into();
+        let Some(object) = self.get_object_mut(object_id) else {
+            return;
+        };
+
+        let previous_name = std::mem::replace(&mut object.name, name.clone());
+        remove_from_index(&mut self.objects_by_name, &previous_name, object_id);
+        add_to_index(&mut self.objects_by_name, &name, object_id);
+    }
+
+    /// Does nothing if the object already has the tag
+    #[allow(dead_code)]
+    pub fn add_object_tag(&mut self, object_id: ObjectId, tag: impl Into<String>) {
+        let tag = tag.into();
+        let Some(object) = self.get_object_mut(object_id) else {
+            return;
+        };
+
+        if !object.tags.contains(&tag) {
+            object.tags.push(tag.clone());
+            add_to_index(&mut self.objects_by_tag, &tag, object_id);
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn remove_object_tag(&mut self, object_id: ObjectId, tag: &str) {
+        let Some(object) = self.get_object_mut(object_id) else {
+            return;
+        };
+
+        if let Some(index) = object.tags.iter().position(|existing| existing == tag) {
+            object.tags.remove(index);
+            remove_from_index(&mut self.objects_by_tag, tag, object_id);
+        }
+    }
+
+    pub fn spawn_light(
+        &mut self,
+        name: impl Into<String>,
+        light: Light,
+        translation: Vec3,
+        rotation: Quat,
+    ) -> ObjectId {
+        let mut object = Object3D {
+            name: name.into(),
+            light: Some(light),
+            ..Default::default()
+        };
+        object
+            .transform
+            .set_transform(translation, rotation, Vec3::ONE);
+
+        self.add_object(object)
+    }
+
+    /// The first enabled directional light, which is used for shadow mapping
+    pub fn primary_directional_light(&self) -> Option<ObjectId> {
         self.objects
             .iter()
-            .find(|(_, object)| object.name == name)
+            .find(|(_, object)| {
+                object.enabled
+                    && object
+                        .light
+                        .as_ref()
+                        .is_some_and(|light| light.kind == LightKind::Directional)
+            })
             .map(|(id, _)| id)
     }
 
@@ -52,28 +287,276 @@ impl Scene {
         self.models.alloc(model)
     }
 
+    /// Adds a model built from code, with one primitive per mesh. The primitives are baked with
+    /// the glTF meshes of the scene.
+    #[allow(dead_code)]
+    pub fn add_generated_model(
+        &mut self,
+        material_manager: &MaterialManager,
+        name: &str,
+        meshes: Vec<(MeshBuilder, MaterialId)>,
+    ) -> anyhow::Result<SceneModelId> {
+        let mut primitives = Vec::with_capacity(meshes.len());
+
+        for (mesh, material_id) in meshes {
+            let alpha_mode = material_manager
+                .get_material(material_id)
+                .map(|material| material.alpha_mode)
+                .ok_or_else(|| anyhow::anyhow!("Unknown material for generated model {}", name))?;
+
+            primitives.push(mesh.build(self.next_primitive_index, material_id, alpha_mode)?);
+            self.next_primitive_index += 1;
+        }
+
+        Ok(self.add_model(SceneModel::new(Model {
+            name: name.to_string(),
+            primitives,
+        })))
+    }
+
+    /// Adds a model for each chunk of the terrain, row by row. Each chunk has its own bounds, so
+    /// chunks outside the view are culled.
+    pub fn add_terrain_models(
+        &mut self,
+        material_manager: &MaterialManager,
+        name: &str,
+        terrain: &Terrain,
+    ) -> anyhow::Result<Vec<SceneModelId>> {
+        let chunks: Vec<(u32, u32)> = (0..terrain.chunks)
+            .flat_map(|row| (0..terrain.chunks).map(move |column| (column, row)))
+            .collect();
+        let meshes: Vec<MeshBuilder> = chunks
+            .par_iter()
+            .map(|&(column, row)| terrain.chunk_mesh(column, row))
+            .collect();
+
+        chunks
+            .iter()
+            .zip(meshes)
+            .map(|(&(column, row), mesh)| {
+                self.add_generated_model(
+                    material_manager,
+                    &format!("{} chunk {},{}", name, column, row),
+                    vec![(mesh, terrain.material_id)],
+                )
+            })
+            .collect()
+    }
+
     pub fn spawn_gltf_scene(
         &mut self,
         material_manager: &MaterialManager,
         file_name: &str,
+        document: &gltf::Document,
         buffers: Buffers,
         scene: &gltf::Scene,
         instance_type: InstanceType,
     ) -> Option<ObjectId> {
-        let mut last_object_id = None;
+        self.spawn_gltf_roots(
+            material_manager,
+            file_name,
+            document,
+            buffers,
+            scene,
+            instance_type,
+        )
+        .last()
+        .copied()
+    }
+
+    /// Imports a glTF scene once, so that it can be spawned many times with `instantiate`
+    /// without reading the glTF document again. Returns None if the scene has no root nodes.
+    pub fn create_gltf_prefab(
+        &mut self,
+        material_manager: &MaterialManager,
+        file_name: &str,
+        document: &gltf::Document,
+        buffers: Buffers,
+        scene: &gltf::Scene,
+        instance_type: InstanceType,
+    ) -> Option<PrefabId> {
+        let roots = self.spawn_gltf_roots(
+            material_manager,
+            file_name,
+            document,
+            buffers,
+            scene,
+            instance_type,
+        );
+        let root = *roots.last()?;
+
+        let prefab = Prefab::capture(self, &roots, root);
+        for root in roots {
+            self.despawn_object(root);
+        }
+
+        Some(self.prefabs.alloc(prefab))
+    }
+
+    /// Prefab by the name of its root object
+    pub fn find_prefab(&self, name: &str) -> Option<PrefabId> {
+        self.prefabs
+            .iter()
+            .find(|(_, prefab)| prefab.name() == name)
+            .map(|(id, _)| id)
+    }
+
+    pub fn prefab_names(&self) -> Vec<&str> {
+        self.prefabs
+            .iter()
+            .map(|(_, prefab)| prefab.name())
+            .collect()
+    }
+
+    /// Copies the hierarchy of a prefab, and returns the root that is placed at `transform`.
+    /// The copies use the same models as the prefab.
+    pub fn instantiate(&mut self, prefab_id: PrefabId, transform: Transform) -> ObjectId {
+        let prefab = &self.prefabs[prefab_id];
+        let root = prefab.root();
+        let objects = prefab.objects(transform);
+
+        let mut ids = Vec::with_capacity(objects.len());
+        for (object, parent) in objects {
+            let id = self.add_object(object);
+            if let Some(parent) = parent {
+                self.set_object_parent(id, Some(ids[parent]));
+            }
+            ids.push(id);
+        }
+
+        let prefab = &self.prefabs[prefab_id];
+        let skins = prefab.skins(&ids);
+        let clips = prefab.clips(&ids);
+
+        for (skin, skinned_nodes) in skins {
+            let skin_id = self.skins.alloc(skin);
+            for node in skinned_nodes {
+                self.objects[ids[node]].skin_id = Some(skin_id);
+            }
+        }
+
+        if !clips.is_empty() {
+            self.objects[ids[root]].animation = Some(AnimationPlayer::new(clips));
+        }
+
+        ids[root]
+    }
+
+    /// Spawns the root nodes of a glTF scene and everything under them. The animations are
+    /// played by the last root.
+    fn spawn_gltf_roots(
+        &mut self,
+        material_manager: &MaterialManager,
+        file_name: &str,
+        document: &gltf::Document,
+        buffers: Buffers,
+        scene: &gltf::Scene,
+        instance_type: InstanceType,
+    ) -> Vec<ObjectId> {
+        let mut roots = Vec::new();
+        let mut node_objects = HashMap::new();
+        let first_new_model = self.models.len();
 
-        for node in scene.nodes() {
-            last_object_id = Some(self.spawn_gltf_node(
+        for node in scene.nodes().filter(|node| !is_lod_node(node)) {
+            roots.push(self.spawn_gltf_node(
                 material_manager,
                 file_name,
                 buffers,
                 &node,
                 None,
                 instance_type,
+                &mut node_objects,
             ));
         }
 
-        last_object_id
+        self.spawn_gltf_skins(document, buffers, &node_objects);
+        self.attach_authored_lods(
+            material_manager,
+            file_name,
+            document,
+            buffers,
+            first_new_model,
+        );
+
+        let clips = document
+            .animations()
+            .map(|animation| AnimationClip::from_gltf(&animation, buffers, &node_objects))
+            .filter(|clip| !clip.channels.is_empty())
+            .collect::<Vec<_>>();
+
+        if let Some(root) = roots.last().and_then(|&id| self.objects.get_mut(id)) {
+            if !clips.is_empty() {
+                root.animation = Some(AnimationPlayer::new(clips));
+            }
+        }
+
+        roots
+    }
+
+    /// Adds the authored LODs of the models created since `first_new_model`. Models which were
+    /// spawned earlier already have them.
+    fn attach_authored_lods(
+        &mut self,
+        material_manager: &MaterialManager,
+        file_name: &str,
+        document: &gltf::Document,
+        buffers: Buffers,
+        first_new_model: usize,
+    ) {
+        let authored_lods = match find_authored_lods(document) {
+            Ok(authored_lods) => authored_lods,
+            Err(e) => {
+                log::error!("Failed to find LODs in {}: {:?}", file_name, e);
+                return;
+            }
+        };
+
+        for (mesh_index, lods) in authored_lods {
+            let key = (file_name.to_string(), mesh_index);
+            let Some(&model_id) = self.gltf_mesh_to_model.get(&key) else {
+                continue;
+            };
+
+            if model_id.index() < first_new_model {
+                continue;
+            }
+
+            let result = load_authored_lods(material_manager, file_name, buffers, &lods)
+                .and_then(|lods| self.models[model_id].model.set_authored_lods(lods));
+
+            if let Err(e) = result {
+                log::error!(
+                    "Failed to load LODs of '{}': {:?}",
+                    self.models[model_id].model.name,
+                    e
+                );
+            }
+        }
+    }
+
+    /// Skins refer to their joints by node, so they are created once all nodes have been spawned
+    fn spawn_gltf_skins(
+        &mut self,
+        document: &gltf::Document,
+        buffers: Buffers,
+        node_objects: &HashMap<usize, ObjectId>,
+    ) {
+        // Meshes can share a skin, in which case they are posed by the same joints
+        let mut spawned_skins = HashMap::new();
+
+        for node in document.nodes() {
+            let (Some(skin), Some(&object_id)) = (node.skin(), node_objects.get(&node.index()))
+            else {
+                continue;
+            };
+
+            let skin_id = *spawned_skins.entry(skin.index()).or_insert_with(|| {
+                self.skins
+                    .alloc(Skin::from_gltf(&skin, buffers, node_objects))
+            });
+
+            self.objects[object_id].skin_id = Some(skin_id);
+        }
     }
 
     fn spawn_gltf_node(
@@ -84,6 +567,7 @@ impl Scene {
         node: &gltf::Node,
         parent: Option<ObjectId>,
         instance_type: InstanceType,
+        node_objects: &mut HashMap<usize, ObjectId>,
     ) -> ObjectId {
         let mut object = Object3D::default();
         let node_name = node.name().unwrap_or("Unnamed").to_string();
@@ -93,7 +577,7 @@ impl Scene {
         object.transform.set_transform(
             translation.into(),
             Quat::from_array(rotation),
-            scale[0], // Assume uniform scale for simplicity
+            scale.into(),
         );
 
         object.instance_type = instance_type;
@@ -101,7 +585,8 @@ impl Scene {
         if let Some(mesh) = node.mesh() {
             let mesh_index = mesh.index();
 
-            let mesh_id = match self.gltf_mesh_to_model.get(&mesh_index).copied() {
+            let key = (file_name.to_string(), mesh_index);
+            let mesh_id = match self.gltf_mesh_to_model.get(&key).copied() {
                 Some(mesh_id) => mesh_id,
                 None => {
                     let mesh_name = mesh
@@ -120,7 +605,7 @@ impl Scene {
                     .expect("Failed to create model from glTF mesh");
                     let scene_model = SceneModel::new(model);
                     let mesh_id = self.add_model(scene_model);
-                    self.gltf_mesh_to_model.insert(mesh_index, mesh_id);
+                    self.gltf_mesh_to_model.insert(key, mesh_id);
 
                     mesh_id
                 }
@@ -130,13 +615,14 @@ impl Scene {
         }
 
         let object_id = self.add_object(object);
+        node_objects.insert(node.index(), object_id);
 
         // Set parent-child relationship if there's a parent
         if let Some(parent_id) = parent {
             self.set_object_parent(object_id, Some(parent_id));
         }
 
-        for child in node.children() {
+        for child in node.children().filter(|node| !is_lod_node(node)) {
             self.spawn_gltf_node(
                 material_manager,
                 file_name,
@@ -144,39 +630,186 @@ impl Scene {
                 &child,
                 Some(object_id),
                 instance_type,
+                node_objects,
             );
         }
 
         object_id
     }
 
-    /// Updates all object transforms in hierarchical order
-    fn update_transforms(&self, imgui: &imgui::Ui) {
-        let mut root_object_count = 0;
-        let mut total_update_count = 0;
+    /// Whether any models have been spawned from the glTF file
+    pub fn has_gltf_model(&self, file_name: &str) -> bool {
+        self.gltf_mesh_to_model
+            .keys()
+            .any(|(model_file_name, _)| model_file_name == file_name)
+    }
 
-        // Find all root objects (objects without parents)
-        let root_objects = self.objects.iter().filter_map(|(id, object)| {
-            if object.parent_id.is_none() {
-                Some(id)
-            } else {
-                None
+    /// Rebuilds the models spawned from a glTF file in place, keeping their ids and primitive
+    /// indices. Objects are not respawned, so changes to the node hierarchy are ignored.
+    pub fn reload_gltf_models(
+        &mut self,
+        material_manager: &MaterialManager,
+        file_name: &str,
+        document: &gltf::Document,
+        buffers: Buffers,
+    ) -> anyhow::Result<()> {
+        let mut reloaded_models = Vec::new();
+        let authored_lods = find_authored_lods(document)?;
+
+        // Build everything first, so that a broken file doesn't leave the scene half updated
+        for mesh in document.meshes() {
+            let key = (file_name.to_string(), mesh.index());
+            let Some(&model_id) = self.gltf_mesh_to_model.get(&key) else {
+                continue;
+            };
+
+            let old_model = &self.models[model_id].model;
+            let mut primitive_index = old_model.primitives[0].global_index;
+            let mesh_index = mesh.index();
+            let mut model = Model::from_gltf(
+                material_manager,
+                file_name,
+                old_model.name.clone(),
+                mesh,
+                buffers,
+                &mut primitive_index,
+            )?;
+
+            if let Some(lods) = authored_lods.get(&mesh_index) {
+                model.set_authored_lods(load_authored_lods(
+                    material_manager,
+                    file_name,
+                    buffers,
+                    lods,
+                )?)?;
             }
-        });
 
-        // Update transforms starting from root objects
-        for root_id in root_objects {
-            root_object_count += 1;
-            self.update_object_transform_recursive(
-                root_id,
-                Mat4::IDENTITY,
-                imgui,
-                &mut total_update_count,
+            anyhow::ensure!(
+                model.primitives.len() == old_model.primitives.len(),
+                "Number of primitives in '{}' changed from {} to {}, restart to load it",
+                model.name,
+                old_model.primitives.len(),
+                model.primitives.len()
             );
+
+            reloaded_models.push((model_id, model));
+        }
+
+        for (model_id, model) in reloaded_models {
+            self.models[model_id] = SceneModel::new(model);
+        }
+
+        // World bounds are only updated with transforms
+        for (_, object) in self.objects.iter_mut() {
+            if object.model_id.is_some() {
+                object.transform.invalidate_world();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Groups the objects by their depth in the hierarchy, roots first
+    fn rebuild_hierarchy_levels(&mut self) {
+        let roots = self
+            .objects
+            .iter()
+            .filter(|(_, object)| object.parent_id.is_none())
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+
+        let mut levels = vec![roots];
+        loop {
+            let children = levels
+                .last()
+                .unwrap()
+                .iter()
+                .flat_map(|&id| self.objects[id].child_ids.iter().copied())
+                .collect::<Vec<_>>();
+
+            if children.is_empty() {
+                break;
+            }
+
+            levels.push(children);
+        }
+
+        self.hierarchy_levels = levels;
+        self.hierarchy_changed = false;
+        self.hierarchy_version = NEXT_HIERARCHY_VERSION.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Changes whenever objects have been added, removed or reparented, as of the last
+    /// transform update
+    pub fn hierarchy_version(&self) -> u64 {
+        self.hierarchy_version
+    }
+
+    /// Number of levels in the hierarchy, as of the last transform update
+    pub fn hierarchy_depth(&self) -> usize {
+        self.hierarchy_levels.len()
+    }
+
+    /// Updates all object transforms one hierarchy level at a time. The objects of a level are
+    /// updated in parallel, since their parents have already been updated.
+    fn update_transforms(&mut self, imgui: &imgui::Ui) {
+        if self.hierarchy_changed {
+            self.rebuild_hierarchy_levels();
+        }
+
+        let mut total_update_count = 0;
+
+        for level in &self.hierarchy_levels {
+            let objects = &self.objects;
+            let models = &self.models;
+            let updates = level
+                .par_iter()
+                .filter_map(|&id| {
+                    let object = &objects[id];
+                    let parent = object.parent_id.map(|parent_id| &objects[parent_id]);
+
+                    // Children follow their parents, even if only the parent was changed
+                    let parent_changed =
+                        parent.is_some_and(|parent| parent.transform.has_changed());
+                    if !object.transform.is_world_dirty() && !parent_changed {
+                        return None;
+                    }
+
+                    let parent_world_matrix = parent.map_or(Mat4::IDENTITY, |parent| {
+                        *parent.transform.get_world_matrix()
+                    });
+                    let world_matrix = parent_world_matrix * *object.transform.get_local_matrix();
+
+                    let world_bounds = object
+                        .model_id
+                        .map(|model_id| models[model_id].bounding_box.transformed(world_matrix));
+
+                    Some((
+                        id,
+                        world_matrix,
+                        world_matrix.inverse().transpose(),
+                        world_bounds,
+                    ))
+                })
+                .collect::<Vec<_>>();
+
+            total_update_count += updates.len();
+
+            for (id, world_matrix, inverse_transpose_world_matrix, world_bounds) in updates {
+                let object = &mut self.objects[id];
+                object
+                    .transform
+                    .set_world_matrix(world_matrix, inverse_transpose_world_matrix);
+                object.world_bounds = world_bounds;
+            }
         }
 
         imgui.window("Scene graph transform update").build(|| {
-            imgui.text(format!("Root objects: {}", root_object_count));
+            imgui.text(format!(
+                "Root objects: {}",
+                self.hierarchy_levels.first().map_or(0, Vec::len)
+            ));
+            imgui.text(format!("Hierarchy depth: {}", self.hierarchy_levels.len()));
             imgui.text(format!(
                 "Transform updates this frame: {}",
                 total_update_count
@@ -184,43 +817,73 @@ impl Scene {
         });
     }
 
-    /// Recursively updates an object's world transform and its children
-    fn update_object_transform_recursive(
-        &self,
+    /// Enabled objects whose world bounds overlap `bounds`, as of the last transform update
+    #[allow(dead_code)]
+    pub fn query_bounds<'a>(&'a self, bounds: &'a AABB) -> impl Iterator<Item = ObjectId> + 'a {
+        self.objects
+            .iter()
+            .filter(|(_, object)| object.enabled)
+            .filter(|(_, object)| {
+                object
+                    .world_bounds
+                    .is_some_and(|world_bounds| world_bounds.intersects(bounds))
+            })
+            .map(|(id, _)| id)
+    }
+
+    /// Sets the layers of an object and all its descendants
+    pub fn set_hierarchy_layers(&mut self, object_id: ObjectId, layers: Layers) {
+        let mut stack = vec![object_id];
+
+        while let Some(id) = stack.pop() {
+            if let Some(object) = self.get_object_mut(id) {
+                object.layers = layers;
+                stack.extend(object.child_ids.iter().copied());
+            }
+        }
+    }
+
+    /// Changes the draw parameters of an object and all its descendants, e.g. to tint a whole
+    /// glTF scene
+    pub fn update_hierarchy_draw_params(
+        &mut self,
         object_id: ObjectId,
-        parent_world_matrix: Mat4,
-        imgui: &imgui::Ui,
-        total_update_count: &mut usize,
+        update: impl Fn(&mut DrawParams),
     ) {
-        if let Some(object) = self.objects.get(object_id) {
-            // Only update if the world transform is dirty
-            if object.transform.is_world_dirty() {
-                let local_matrix = *object.transform.get_local_matrix();
-                let world_matrix = parent_world_matrix * local_matrix;
-                object.transform.set_world_matrix(world_matrix);
-                *total_update_count += 1;
-            }
+        let mut stack = vec![object_id];
 
-            // Update all children with this object's world matrix
-            let world_matrix = *object.transform.get_world_matrix();
-            for &child_id in &object.child_ids {
-                self.update_object_transform_recursive(
-                    child_id,
-                    world_matrix,
-                    imgui,
-                    total_update_count,
-                );
+        while let Some(id) = stack.pop() {
+            if let Some(object) = self.get_object_mut(id) {
+                update(&mut object.draw_params);
+                stack.extend(object.child_ids.iter().copied());
             }
         }
     }
 
+    pub fn set_object_param_names(&mut self, names: Vec<String>) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            names.len() <= OBJECT_PARAM_COUNT,
+            "Scene has {} object parameters, at most {} are supported",
+            names.len(),
+            OBJECT_PARAM_COUNT
+        );
+
+        self.object_param_names = names;
+        Ok(())
+    }
+
+    pub fn object_param_index(&self, name: &str) -> Option<usize> {
+        self.object_param_names.iter().position(|n| n == name)
+    }
+
     /// Invalidates world transforms for an object and all its descendants
-    pub fn invalidate_object_hierarchy(&self, object_id: ObjectId) {
-        if let Some(object) = self.objects.get(object_id) {
-            object.transform.invalidate_world();
+    pub fn invalidate_object_hierarchy(&mut self, object_id: ObjectId) {
+        let mut stack = vec![object_id];
 
-            for &child_id in &object.child_ids {
-                self.invalidate_object_hierarchy(child_id);
+        while let Some(id) = stack.pop() {
+            if let Some(object) = self.objects.get_mut(id) {
+                object.transform.invalidate_world();
+                stack.extend_from_slice(&object.child_ids);
             }
         }
     }
@@ -247,6 +910,8 @@ impl Scene {
             }
         }
 
+        self.hierarchy_changed = true;
+
         // Invalidate world transforms for the moved object and its descendants
         self.invalidate_object_hierarchy(child_id);
     }
@@ -268,7 +933,7 @@ impl Scene {
     }
 
     #[allow(dead_code)]
-    pub fn set_object_scale(&mut self, object_id: ObjectId, scale: f32) {
+    pub fn set_object_scale(&mut self, object_id: ObjectId, scale: Vec3) {
         if let Some(object) = self.objects.get_mut(object_id) {
             object.transform.set_scale(scale);
         }
@@ -281,7 +946,7 @@ impl Scene {
         object_id: ObjectId,
         translation: Vec3,
         rotation: Quat,
-        scale: f32,
+        scale: Vec3,
     ) {
         if let Some(object) = self.objects.get_mut(object_id) {
             object.transform.set_transform(translation, rotation, scale);
@@ -294,14 +959,253 @@ impl Scene {
         self.objects.get(object_id).map(|object| &object.transform)
     }
 
-    pub fn early_update(&mut self) {
-        // TODO: fork or replace id-arena to support parallel iteration
-        for (_, object) in self.objects.iter() {
-            object.transform.reset_flags();
+    /// Advances all animation players by whole fixed steps, and writes the transforms sampled
+    /// between the last two steps
+    pub fn update_animations(&mut self, steps: FixedSteps) {
+        let animated_objects = self
+            .objects
+            .iter()
+            .filter(|(_, object)| object.enabled && object.animation.is_some())
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+
+        for id in animated_objects {
+            // Taken out for the duration of the update, because the clips write to the objects
+            let Some(mut player) = self.objects[id].animation.take() else {
+                continue;
+            };
+
+            for _ in 0..steps.count {
+                player.advance(FIXED_TIMESTEP);
+            }
+            if let Some(clip) = player.active_clip() {
+                clip.apply(player.interpolated_time(steps.alpha), self);
+            }
+
+            self.objects[id].animation = Some(player);
         }
     }
 
+    pub fn draw_animation_ui(&mut self, ui: &imgui::Ui) {
+        ui.window("Animations")
+            .size([300.0, 200.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                for (id, object) in self.objects.iter_mut() {
+                    let Some(player) = &mut object.animation else {
+                        continue;
+                    };
+
+                    let label = format!("{}##{}", object.name, id.index());
+                    if let Some(_node) = ui.tree_node(label) {
+                        player.draw_ui(ui);
+                    }
+                }
+            });
+    }
+
+    pub fn draw_particle_emitter_ui(&mut self, ui: &imgui::Ui) {
+        ui.window("Particle emitters")
+            .size([300.0, 200.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                for (id, object) in self.objects.iter_mut() {
+                    let Some(emitter) = &mut object.particle_emitter else {
+                        continue;
+                    };
+
+                    let label = format!("{}##{}", object.name, id.index());
+                    if let Some(_node) = ui.tree_node(label) {
+                        ui.checkbox("Enabled", &mut object.enabled);
+                        emitter.draw_ui(ui);
+                    }
+                }
+            });
+    }
+
+    pub fn draw_text_ui(&mut self, ui: &imgui::Ui) {
+        ui.window("Texts")
+            .size([300.0, 200.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                for (id, object) in self.objects.iter_mut() {
+                    let Some(text) = &mut object.text else {
+                        continue;
+                    };
+
+                    let label = format!("{}##{}", object.name, id.index());
+                    if let Some(_node) = ui.tree_node(label) {
+                        ui.checkbox("Enabled", &mut object.enabled);
+                        text.draw_ui(ui);
+                    }
+                }
+            });
+    }
+
+    pub fn draw_decal_ui(&mut self, ui: &imgui::Ui) {
+        ui.window("Decals")
+            .size([300.0, 200.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                for (id, object) in self.objects.iter_mut() {
+                    let Some(decal) = &mut object.decal else {
+                        continue;
+                    };
+
+                    let label = format!("{}##{}", object.name, id.index());
+                    if let Some(_node) = ui.tree_node(label) {
+                        ui.checkbox("Enabled", &mut object.enabled);
+                        decal.draw_ui(ui);
+                    }
+                }
+            });
+    }
+
+    pub fn draw_reflection_probe_ui(&mut self, ui: &imgui::Ui) {
+        ui.window("Reflection probes")
+            .size([300.0, 200.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                if ui.button("Recapture all") {
+                    self.recapture_reflection_probes();
+                }
+
+                for (id, object) in self.objects.iter_mut() {
+                    let Some(probe) = &mut object.reflection_probe else {
+                        continue;
+                    };
+
+                    let label = format!("{}##{}", object.name, id.index());
+                    if let Some(_node) = ui.tree_node(label) {
+                        ui.checkbox("Enabled", &mut object.enabled);
+                        probe.draw_ui(ui);
+                    }
+                }
+            });
+    }
+
+    pub fn draw_fog_ui(&mut self, ui: &imgui::Ui) {
+        ui.window("Fog")
+            .size([300.0, 180.0], imgui::Condition::FirstUseEver)
+            .build(|| self.fog.draw_ui(ui));
+    }
+
+    pub fn early_update(&mut self) {
+        self.objects
+            .par_iter_mut()
+            .for_each(|(_, object)| object.transform.reset_flags());
+    }
+
     pub fn late_update(&mut self, imgui: &imgui::Ui) {
         self.update_transforms(imgui);
     }
 }
+
+fn add_to_index(index: &mut HashMap<String, Vec<ObjectId>>, key: &str, id: ObjectId) {
+    index.entry(key.to_string()).or_default().push(id);
+}
+
+fn remove_from_index(index: &mut HashMap<String, Vec<ObjectId>>, key: &str, id: ObjectId) {
+    if let Some(ids) = index.get_mut(key) {
+        ids.retain(|existing| *existing != id);
+        if ids.is_empty() {
+            index.remove(key);
+        }
+    }
+}
+
+/// A LOD mesh authored in a glTF file, and the distance at which it's used
+struct AuthoredLod<'a> {
+    level: u32,
+    mesh: gltf::Mesh<'a>,
+    distance: f32,
+}
+
+/// Nodes named e.g. `Rock_LOD1` and `Rock_LOD2` are simplified versions of `Rock` (or
+/// `Rock_LOD0`). Returns the base name and the LOD level.
+fn parse_lod_name(name: &str) -> Option<(&str, u32)> {
+    let (base, level) = name.rsplit_once("_LOD")?;
+    Some((base, level.parse().ok()?))
+}
+
+/// LOD nodes are not spawned, their meshes are attached to the models of their base nodes
+fn is_lod_node(node: &gltf::Node) -> bool {
+    node.name()
+        .and_then(parse_lod_name)
+        .is_some_and(|(_, level)| level > 0)
+}
+
+/// Authored LODs by the index of the mesh they simplify, ordered by level
+fn find_authored_lods(
+    document: &gltf::Document,
+) -> anyhow::Result<HashMap<usize, Vec<AuthoredLod<'_>>>> {
+    let base_meshes = document
+        .nodes()
+        .filter_map(|node| {
+            let name = node.name()?;
+            let mesh = node.mesh()?;
+
+            match parse_lod_name(name) {
+                Some((base, 0)) => Some((base, mesh.index())),
+                Some(_) => None,
+                None => Some((name, mesh.index())),
+            }
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut authored_lods: HashMap<usize, Vec<AuthoredLod>> = HashMap::new();
+
+    for node in document.nodes() {
+        let (Some(name), Some(mesh)) = (node.name(), node.mesh()) else {
+            continue;
+        };
+
+        let Some((base, level)) = parse_lod_name(name).filter(|(_, level)| *level > 0) else {
+            continue;
+        };
+
+        let Some(&base_mesh) = base_meshes.get(base) else {
+            log::warn!("LOD node '{}' has no base node '{}'", name, base);
+            continue;
+        };
+
+        let extras = LodExtras::from_gltf_extras(node.extras())?;
+        let distance = extras
+            .lod_distance
+            .unwrap_or(DEFAULT_LOD_DISTANCE * 2.0f32.powi(level as i32 - 1));
+
+        authored_lods
+            .entry(base_mesh)
+            .or_default()
+            .push(AuthoredLod {
+                level,
+                mesh,
+                distance,
+            });
+    }
+
+    for lods in authored_lods.values_mut() {
+        lods.sort_by_key(|lod| lod.level);
+    }
+
+    Ok(authored_lods)
+}
+
+fn load_authored_lods(
+    material_manager: &MaterialManager,
+    file_name: &str,
+    buffers: Buffers,
+    lods: &[AuthoredLod],
+) -> anyhow::Result<Vec<(Model, f32)>> {
+    lods.iter()
+        .map(|lod| {
+            // LODs are drawn as part of their base primitives, so they don't need indices
+            let mut unused_primitive_index = 0;
+            let model = Model::from_gltf(
+                material_manager,
+                file_name,
+                lod.mesh.name().unwrap_or("Unnamed LOD"),
+                lod.mesh.clone(),
+                buffers,
+                &mut unused_primitive_index,
+            )?;
+
+            Ok((model, lod.distance))
+        })
+        .collect()
+}
@@ -0,0 +1,534 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use glam::{Quat, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    asset_pipeline::materials::{load_material_overrides, MaterialDescription},
+    camera::{
+        Camera, Cameras, DEFAULT_FAR, DEFAULT_FOCUS_DISTANCE, DEFAULT_FOV_Y_RADIANS, DEFAULT_NEAR,
+    },
+    material_manager::{MaterialId, MaterialManager},
+    procgen::terrain::{Terrain, TerrainDescription},
+    rendering::{instancing::InstanceType, render_target_manager::RenderTargetDescription},
+    scene_graph::{
+        decal::Decal,
+        draw_params::DrawParams,
+        fog::Fog,
+        layers::Layers,
+        light::Light,
+        object3d::{Object3D, ObjectId},
+        particle_emitter::ParticleEmitter,
+        reflection_probe::ReflectionProbe,
+        scene::Scene,
+        text::Text,
+        transform::Transform,
+    },
+};
+
+/// Scene layout stored in a RON file under `assets/scenes/`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SceneFile {
+    /// glTF files by the name objects use to refer to them. The name is also used for the
+    /// materials of the file.
+    pub models: HashMap<String, PathBuf>,
+    pub objects: Vec<ObjectDescription>,
+    /// The first camera is active when the demo starts
+    #[serde(default)]
+    pub cameras: Vec<CameraDescription>,
+    #[serde(default)]
+    pub fog: Fog,
+    /// Offscreen textures that materials can refer to with `dynamic_base_color` and
+    /// `dynamic_emissive` overrides
+    #[serde(default)]
+    pub render_targets: Vec<RenderTargetDescription>,
+    /// Heightmap terrains by the name objects use to refer to them
+    #[serde(default)]
+    pub terrains: HashMap<String, TerrainDescription>,
+    /// Materials that aren't part of any glTF file, e.g. for decals
+    #[serde(default)]
+    pub materials: HashMap<String, MaterialDescription>,
+    /// Names of the per-object shader parameters, see `DrawParams::params`
+    #[serde(default)]
+    pub object_params: Vec<String>,
+}
+
+/// Serialized form of a `Camera`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CameraDescription {
+    pub name: String,
+    pub eye: Vec3,
+    pub target: Vec3,
+    #[serde(default = "default_fov_y_degrees")]
+    pub fov_y_degrees: f32,
+    #[serde(default = "default_near")]
+    pub near: f32,
+    #[serde(default = "default_far")]
+    pub far: f32,
+    #[serde(default = "default_focus_distance")]
+    pub focus_distance: f32,
+    #[serde(default)]
+    pub aperture: f32,
+}
+
+fn default_fov_y_degrees() -> f32 {
+    DEFAULT_FOV_Y_RADIANS.to_degrees()
+}
+
+fn default_near() -> f32 {
+    DEFAULT_NEAR
+}
+
+fn default_far() -> f32 {
+    DEFAULT_FAR
+}
+
+fn default_focus_distance() -> f32 {
+    DEFAULT_FOCUS_DISTANCE
+}
+
+impl CameraDescription {
+    fn to_camera(&self) -> Camera {
+        Camera {
+            fov_y_radians: self.fov_y_degrees.to_radians(),
+            near: self.near,
+            far: self.far,
+            focus_distance: self.focus_distance,
+            aperture: self.aperture,
+            ..Camera::new(self.eye, self.target)
+        }
+    }
+}
+
+/// Serialized form of an `Object3D`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ObjectDescription {
+    pub name: String,
+    #[serde(default)]
+    pub transform: Transform,
+    /// Name of a glTF file in `SceneFile::models`, whose scene is spawned as this object
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Name of a terrain in `SceneFile::terrains`, whose chunks are spawned as children of this
+    /// object
+    #[serde(default)]
+    pub terrain: Option<String>,
+    #[serde(default)]
+    pub light: Option<Light>,
+    #[serde(default)]
+    pub particle_emitter: Option<ParticleEmitter>,
+    #[serde(default)]
+    pub text: Option<Text>,
+    #[serde(default)]
+    pub decal: Option<Decal>,
+    #[serde(default)]
+    pub reflection_probe: Option<ReflectionProbe>,
+    #[serde(default)]
+    pub instance_type: InstanceType,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Only the root of a model is tagged
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Applies to the whole hierarchy of the model
+    #[serde(default)]
+    pub layers: Layers,
+    /// Applies to the whole hierarchy of the model
+    #[serde(default)]
+    pub draw_params: DrawParams,
+    /// Shader parameters by the names in `SceneFile::object_params`. Applies to the whole
+    /// hierarchy of the model.
+    #[serde(default)]
+    pub params: HashMap<String, f32>,
+    /// Spawns several copies of the object instead of one
+    #[serde(default)]
+    pub array: Option<ArrayDescription>,
+    /// Playback settings for the animations of the model
+    #[serde(default)]
+    pub animation: Option<AnimationDescription>,
+    #[serde(default)]
+    pub children: Vec<ObjectDescription>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AnimationDescription {
+    /// Name of the clip to play, the first clip of the file is played by default
+    #[serde(default)]
+    pub clip: Option<String>,
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+    #[serde(default = "default_looping")]
+    pub looping: bool,
+}
+
+fn default_speed() -> f32 {
+    1.0
+}
+
+fn default_looping() -> bool {
+    true
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ArrayDescription {
+    /// Copies on the XZ plane, centered on the object's translation
+    Grid {
+        columns: u32,
+        rows: u32,
+        spacing: f32,
+        /// Rotates each copy around Y so that its +Z axis points away from the center
+        #[serde(default)]
+        rotate_radially: bool,
+    },
+    /// Copies with identical transforms, for objects that are positioned from code
+    Repeat { count: u32 },
+}
+
+pub struct ImportedGltf {
+    pub document: gltf::Document,
+    pub buffers: Vec<gltf::buffer::Data>,
+}
+
+/// Everything that objects of a scene file can refer to by name
+pub struct ImportedModels {
+    pub gltf: HashMap<String, ImportedGltf>,
+    pub terrains: HashMap<String, Terrain>,
+    pub materials: HashMap<String, MaterialId>,
+}
+
+/// Top level objects by name. Arrays produce several objects with the same name.
+pub type SpawnedObjects = HashMap<String, Vec<ObjectId>>;
+
+impl SceneFile {
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read scene file: {}", path.display()))?;
+
+        ron::from_str(&contents)
+            .with_context(|| format!("Failed to parse scene file: {}", path.display()))
+    }
+
+    #[allow(dead_code)]
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .context("Failed to serialize scene")?;
+
+        std::fs::write(path, contents)
+            .with_context(|| format!("Failed to write scene file: {}", path.display()))
+    }
+
+    /// Imports all referenced glTF files and terrains, and loads their materials and the
+    /// standalone materials
+    pub fn import_models(
+        &self,
+        material_manager: &mut MaterialManager,
+    ) -> anyhow::Result<ImportedModels> {
+        let gltf = self
+            .models
+            .iter()
+            .map(|(name, path)| {
+                let (document, buffers, mut images) = gltf::import(path)
+                    .with_context(|| format!("Failed to import glTF: {}", path.display()))?;
+
+                let base_path = path.parent().unwrap_or(Path::new(""));
+                let overrides = load_material_overrides(path)?;
+                material_manager.load_all_materials_from_gltf(
+                    name,
+                    base_path,
+                    &document,
+                    &mut images,
+                    &overrides,
+                );
+
+                Ok((name.clone(), ImportedGltf { document, buffers }))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        let terrains = self
+            .terrains
+            .iter()
+            .map(|(name, description)| {
+                let terrain = Terrain::load(name, description, material_manager)
+                    .with_context(|| format!("Failed to load terrain '{}'", name))?;
+                Ok((name.clone(), terrain))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        let materials = self
+            .materials
+            .iter()
+            .map(|(name, description)| {
+                let material = description
+                    .load(name)
+                    .with_context(|| format!("Failed to load material '{}'", name))?;
+                Ok((name.clone(), material_manager.add_material(material)))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(ImportedModels {
+            gltf,
+            terrains,
+            materials,
+        })
+    }
+
+    pub fn create_cameras(&self) -> anyhow::Result<Cameras> {
+        let (first, rest) = self
+            .cameras
+            .split_first()
+            .context("Scene file has no cameras")?;
+
+        let mut cameras = Cameras::new(&first.name, first.to_camera());
+        for description in rest {
+            cameras.add(&description.name, description.to_camera());
+        }
+
+        Ok(cameras)
+    }
+
+    pub fn spawn(
+        &self,
+        scene: &mut Scene,
+        material_manager: &MaterialManager,
+        models: &ImportedModels,
+    ) -> anyhow::Result<SpawnedObjects> {
+        let mut spawned = SpawnedObjects::new();
+        scene.fog = self.fog.clone();
+        scene.render_targets = self.render_targets.clone();
+
+        scene.set_object_param_names(self.object_params.clone())?;
+
+        for description in &self.objects {
+            let ids = spawn_object(scene, material_manager, models, description, None)?;
+            spawned
+                .entry(description.name.clone())
+                .or_default()
+                .extend(ids);
+        }
+
+        Ok(spawned)
+    }
+}
+
+fn spawn_object(
+    scene: &mut Scene,
+    material_manager: &MaterialManager,
+    models: &ImportedModels,
+    description: &ObjectDescription,
+    parent: Option<ObjectId>,
+) -> anyhow::Result<Vec<ObjectId>> {
+    let transform = &description.transform;
+
+    let transforms = match description.array {
+        None => vec![(transform.translation(), transform.rotation())],
+        Some(ArrayDescription::Grid {
+            columns,
+            rows,
+            spacing,
+            rotate_radially,
+        }) => {
+            let mut transforms = Vec::with_capacity((columns * rows) as usize);
+            for x in 0..columns {
+                for z in 0..rows {
+                    let offset = Vec3::new(
+                        (x as i32 - columns as i32 / 2) as f32,
+                        0.0,
+                        (z as i32 - rows as i32 / 2) as f32,
+                    ) * spacing;
+
+                    let rotation = if rotate_radially {
+                        Quat::from_axis_angle(Vec3::Y, offset.x.atan2(offset.z))
+                    } else {
+                        Quat::IDENTITY
+                    };
+
+                    transforms.push((
+                        transform.translation() + offset,
+                        rotation * transform.rotation(),
+                    ));
+                }
+            }
+            transforms
+        }
+        Some(ArrayDescription::Repeat { count }) => {
+            vec![(transform.translation(), transform.rotation()); count as usize]
+        }
+    };
+
+    // Copies of a model are instantiated from a prefab, so that the glTF scene is only imported
+    // once
+    let prefab = match &description.model {
+        Some(model) => {
+            let gltf = models.gltf.get(model).with_context(|| {
+                format!(
+                    "Object '{}' refers to unknown model '{}'",
+                    description.name, model
+                )
+            })?;
+            let gltf_scene = gltf
+                .document
+                .scenes()
+                .next()
+                .with_context(|| format!("No scenes in glTF '{}'", model))?;
+
+            let prefab = scene
+                .create_gltf_prefab(
+                    material_manager,
+                    model,
+                    &gltf.document,
+                    &gltf.buffers,
+                    &gltf_scene,
+                    description.instance_type,
+                )
+                .with_context(|| format!("glTF '{}' has no root node", model))?;
+
+            Some(prefab)
+        }
+        None => None,
+    };
+
+    // Chunk models are shared by the copies of a terrain
+    let terrain_models = match &description.terrain {
+        Some(name) => {
+            anyhow::ensure!(
+                description.model.is_none(),
+                "Object '{}' can't have both a model and a terrain",
+                description.name
+            );
+
+            let terrain = models.terrains.get(name).with_context(|| {
+                format!(
+                    "Object '{}' refers to unknown terrain '{}'",
+                    description.name, name
+                )
+            })?;
+
+            scene.add_terrain_models(material_manager, name, terrain)?
+        }
+        None => Vec::new(),
+    };
+
+    let mut draw_params = description.draw_params;
+    for (name, value) in &description.params {
+        let index = scene.object_param_index(name).with_context(|| {
+            format!(
+                "Object '{}' refers to unknown parameter '{}'",
+                description.name, name
+            )
+        })?;
+        draw_params.params[index] = *value;
+    }
+
+    let decal = match &description.decal {
+        Some(decal) => {
+            let material_id = models.materials.get(&decal.material).with_context(|| {
+                format!(
+                    "Decal '{}' refers to unknown material '{}'",
+                    description.name, decal.material
+                )
+            })?;
+
+            Some(Decal {
+                material_id: Some(*material_id),
+                ..decal.clone()
+            })
+        }
+        None => None,
+    };
+
+    let mut ids = Vec::with_capacity(transforms.len());
+
+    for (translation, rotation) in transforms {
+        let mut instance_transform = Transform::default();
+        instance_transform.set_transform(translation, rotation, transform.scale());
+
+        let id = match prefab {
+            Some(prefab) => {
+                let id = scene.instantiate(prefab, instance_transform);
+
+                scene.set_hierarchy_layers(id, description.layers);
+                scene.update_hierarchy_draw_params(id, |params| *params = draw_params);
+                scene.set_object_name(id, description.name.clone());
+                for tag in &description.tags {
+                    scene.add_object_tag(id, tag.clone());
+                }
+
+                let object = scene.get_object_mut(id).unwrap();
+                object.light = description.light.clone();
+                object.particle_emitter = description.particle_emitter.clone();
+                object.text = description.text.clone();
+                object.decal = decal.clone();
+                object.reflection_probe = description.reflection_probe.clone();
+                object.enabled = description.enabled;
+
+                if let (Some(player), Some(settings)) =
+                    (&mut object.animation, &description.animation)
+                {
+                    player.speed = settings.speed;
+                    player.looping = settings.looping;
+
+                    if let Some(clip) = &settings.clip {
+                        anyhow::ensure!(
+                            player.play(clip),
+                            "Object '{}' refers to unknown animation '{}'",
+                            description.name,
+                            clip
+                        );
+                    }
+                }
+
+                id
+            }
+            None => scene.add_object(Object3D {
+                name: description.name.clone(),
+                tags: description.tags.clone(),
+                transform: instance_transform,
+                light: description.light.clone(),
+                particle_emitter: description.particle_emitter.clone(),
+                text: description.text.clone(),
+                decal: decal.clone(),
+                reflection_probe: description.reflection_probe.clone(),
+                instance_type: description.instance_type,
+                enabled: description.enabled,
+                layers: description.layers,
+                draw_params,
+                ..Default::default()
+            }),
+        };
+
+        if parent.is_some() {
+            scene.set_object_parent(id, parent);
+        }
+
+        for &model_id in &terrain_models {
+            let chunk_id = scene.add_object(Object3D {
+                name: format!("{} chunk", description.name),
+                model_id: Some(model_id),
+                instance_type: description.instance_type,
+                enabled: description.enabled,
+                layers: description.layers,
+                draw_params,
+                ..Default::default()
+            });
+            scene.set_object_parent(chunk_id, Some(id));
+        }
+
+        for child in &description.children {
+            spawn_object(scene, material_manager, models, child, Some(id))?;
+        }
+
+        ids.push(id);
+    }
+
+    Ok(ids)
+}
@@ -1,15 +1,29 @@
 use id_arena::Id;
 
-use crate::model::Model;
+use glam::Vec3;
+
+use crate::{math::bounds::AABB, model::Model};
 
 pub type SceneModelId = Id<SceneModel>;
 
 pub struct SceneModel {
     pub model: Model,
+    /// Bounds of all primitives, in model space
+    pub bounding_box: AABB,
 }
 
 impl SceneModel {
     pub fn new(model: Model) -> Self {
-        Self { model }
+        let bounding_box = model
+            .primitives
+            .iter()
+            .map(|primitive| primitive.bounding_box)
+            .reduce(|a, b| a.union(&b))
+            .unwrap_or(AABB::new(Vec3::ZERO, Vec3::ZERO));
+
+        Self {
+            model,
+            bounding_box,
+        }
     }
 }
@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+
+use glam::Mat4;
+use id_arena::Id;
+
+use crate::{
+    model::Buffers,
+    scene_graph::{object3d::ObjectId, scene::Scene},
+};
+
+pub type SkinId = Id<Skin>;
+
+/// Joints of a skinned mesh. The joints are ordinary objects spawned from the glTF nodes, so
+/// their world matrices come from the scene graph and they are animated like any other node.
+#[derive(Clone)]
+pub struct Skin {
+    /// None for joints that weren't spawned, which keeps the indices of the vertices valid
+    pub joints: Vec<Option<ObjectId>>,
+    pub inverse_bind_matrices: Vec<Mat4>,
+}
+
+impl Skin {
+    pub fn from_gltf(
+        skin: &gltf::Skin,
+        buffers: Buffers,
+        node_objects: &HashMap<usize, ObjectId>,
+    ) -> Self {
+        let joints = skin
+            .joints()
+            .map(|joint| node_objects.get(&joint.index()).copied())
+            .collect::<Vec<_>>();
+
+        let reader = skin.reader(|buffer| Some(&buffers[buffer.index()]));
+        let inverse_bind_matrices = match reader.read_inverse_bind_matrices() {
+            Some(matrices) => matrices
+                .map(|matrix| Mat4::from_cols_array_2d(&matrix))
+                .collect(),
+            // The glTF spec says that missing inverse bind matrices are identity matrices
+            None => vec![Mat4::IDENTITY; joints.len()],
+        };
+
+        Self {
+            joints,
+            inverse_bind_matrices,
+        }
+    }
+
+    /// Appends the joint matrices of a skinned object. They are relative to the object, because
+    /// the shaders apply its model matrix after skinning.
+    pub fn append_joint_matrices(
+        &self,
+        scene: &Scene,
+        object_world_matrix: &Mat4,
+        joint_matrices: &mut Vec<Mat4>,
+    ) {
+        let inverse_object_matrix = object_world_matrix.inverse();
+
+        for (joint, inverse_bind_matrix) in self.joints.iter().zip(&self.inverse_bind_matrices) {
+            // Vertices bound to missing joints are left where they are
+            let joint_matrix =
+                joint
+                    .and_then(|joint| scene.get_object(joint))
+                    .map_or(Mat4::IDENTITY, |joint| {
+                        inverse_object_matrix
+                            * *joint.transform.get_world_matrix()
+                            * *inverse_bind_matrix
+                    });
+
+            joint_matrices.push(joint_matrix);
+        }
+    }
+}
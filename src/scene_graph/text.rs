@@ -0,0 +1,90 @@
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+
+/// How a text object is placed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TextMode {
+    /// On the XY plane of the object, facing +Z
+    #[default]
+    World,
+    /// At the object's origin, facing the camera
+    Billboard,
+    /// Overlay on top of the image, after depth of field. The X and Y of the object's
+    /// translation are the screen position from 0 to 1, with Y down.
+    Screen,
+}
+
+impl TextMode {
+    pub const ALL: [TextMode; 3] = [TextMode::World, TextMode::Billboard, TextMode::Screen];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TextMode::World => "World",
+            TextMode::Billboard => "Billboard",
+            TextMode::Screen => "Screen",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TextAlign {
+    Left,
+    #[default]
+    Center,
+    Right,
+}
+
+/// Text component of an object, for greetings and credits. Drawn from a signed distance field
+/// atlas, so it stays sharp at any size.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Text {
+    /// Lines are separated with `\n`
+    pub content: String,
+    /// Height of a line in world units, scaled by the object. For screen text, a fraction of
+    /// the screen height.
+    pub size: f32,
+    /// Linear color. Values above 1 glow with bloom.
+    pub color: Vec3,
+    pub opacity: f32,
+    /// Fraction of the glyphs shown in reading order, for typing effects
+    pub reveal: f32,
+    pub mode: TextMode,
+    pub align: TextAlign,
+}
+
+impl Default for Text {
+    fn default() -> Self {
+        Self {
+            content: String::new(),
+            size: 1.0,
+            color: Vec3::ONE,
+            opacity: 1.0,
+            reveal: 1.0,
+            mode: TextMode::default(),
+            align: TextAlign::default(),
+        }
+    }
+}
+
+impl Text {
+    pub fn draw_ui(&mut self, ui: &imgui::Ui) {
+        ui.input_text_multiline("Content", &mut self.content, [0.0, 60.0])
+            .build();
+        ui.slider("Size", 0.0, 10.0, &mut self.size);
+
+        let mut color = self.color.to_array();
+        if ui.color_edit3("Color", &mut color) {
+            self.color = color.into();
+        }
+
+        ui.slider("Opacity", 0.0, 1.0, &mut self.opacity);
+        ui.slider("Reveal", 0.0, 1.0, &mut self.reveal);
+
+        for mode in TextMode::ALL {
+            if ui.radio_button_bool(mode.name(), self.mode == mode) {
+                self.mode = mode;
+            }
+        }
+    }
+}
@@ -1,18 +1,22 @@
-use glam::{Mat4, Quat, Vec3};
-use std::cell::{Cell, Ref, RefCell};
+use glam::{EulerRot, Mat4, Quat, Vec3};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
+/// Matrices are plain values instead of lazily updated cells, so that objects can be read and
+/// updated from multiple threads
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "TransformData", into = "TransformData")]
 pub struct Transform {
     translation: Vec3,
     rotation: Quat,
-    scale: f32,
-
-    local_matrix: RefCell<glam::Mat4>,
-    world_matrix: RefCell<glam::Mat4>,
-    inverse_transpose_world_matrix: RefCell<glam::Mat4>,
-    local_dirty: Cell<bool>,
-    world_dirty: Cell<bool>,
-    has_changed_since_last_update: Cell<bool>,
+    scale: Vec3,
+
+    local_matrix: Mat4,
+    world_matrix: Mat4,
+    inverse_transpose_world_matrix: Mat4,
+    world_dirty: bool,
+    has_changed_since_last_update: bool,
+    /// Unlike the flag above, not set when only a parent has moved
+    local_changed_since_last_update: bool,
 }
 
 impl Transform {
@@ -20,61 +24,51 @@ impl Transform {
         Self {
             translation,
             rotation: Quat::IDENTITY,
-            scale: 1.0,
-            local_matrix: RefCell::new(Mat4::IDENTITY),
-            world_matrix: RefCell::new(Mat4::IDENTITY),
-            inverse_transpose_world_matrix: RefCell::new(Mat4::IDENTITY),
-            local_dirty: Cell::new(true),
-            world_dirty: Cell::new(true),
-            has_changed_since_last_update: Cell::new(true),
+            scale: Vec3::ONE,
+            local_matrix: Mat4::from_translation(translation),
+            world_matrix: Mat4::IDENTITY,
+            inverse_transpose_world_matrix: Mat4::IDENTITY,
+            world_dirty: true,
+            has_changed_since_last_update: true,
+            local_changed_since_last_update: true,
         }
     }
 
-    pub fn get_local_matrix(&self) -> Ref<glam::Mat4> {
-        if self.local_dirty.get() {
-            let matrix = glam::Mat4::from_scale_rotation_translation(
-                Vec3::splat(self.scale),
-                self.rotation,
-                self.translation,
-            );
-
-            self.local_matrix.replace(matrix);
-            self.local_dirty.set(false);
-            // Not sure about this one - invalidate_local also sets world_dirty
-            self.invalidate_world();
-        }
-
-        self.local_matrix.borrow()
+    pub fn get_local_matrix(&self) -> &Mat4 {
+        &self.local_matrix
     }
 
-    pub fn get_world_matrix(&self) -> Ref<glam::Mat4> {
-        self.world_matrix.borrow()
+    pub fn get_world_matrix(&self) -> &Mat4 {
+        &self.world_matrix
     }
 
-    pub fn get_inverse_transpose_world_matrix(&self) -> Ref<glam::Mat4> {
-        self.inverse_transpose_world_matrix.borrow()
+    pub fn get_inverse_transpose_world_matrix(&self) -> &Mat4 {
+        &self.inverse_transpose_world_matrix
     }
 
-    pub fn set_world_matrix(&self, world_matrix: Mat4) {
-        self.world_matrix.replace(world_matrix);
-        self.world_dirty.set(false);
-        self.has_changed_since_last_update.set(true);
-        self.inverse_transpose_world_matrix
-            .replace(world_matrix.inverse().transpose());
+    /// The inverse transpose is passed in, so that it can be computed in parallel with other
+    /// objects
+    pub fn set_world_matrix(&mut self, world_matrix: Mat4, inverse_transpose_world_matrix: Mat4) {
+        self.world_matrix = world_matrix;
+        self.inverse_transpose_world_matrix = inverse_transpose_world_matrix;
+        self.world_dirty = false;
+        self.has_changed_since_last_update = true;
     }
 
-    pub fn invalidate_local(&self) {
-        self.local_dirty.set(true);
-        self.world_dirty.set(true);
-        self.has_changed_since_last_update.set(true);
+    fn invalidate_local(&mut self) {
+        self.local_matrix =
+            Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation);
+        self.world_dirty = true;
+        self.has_changed_since_last_update = true;
+        self.local_changed_since_last_update = true;
     }
 
-    pub fn invalidate_world(&self) {
-        self.world_dirty.set(true);
+    pub fn invalidate_world(&mut self) {
+        self.world_dirty = true;
     }
 
     pub fn is_world_dirty(&self) -> bool {
-        self.world_dirty.get()
+        self.world_dirty
     }
 
     pub fn set_rotation(&mut self, rotation: Quat) {
@@ -89,7 +83,7 @@ impl Transform {
     }
 
     #[allow(dead_code)]
-    pub fn set_scale(&mut self, scale: f32) {
+    pub fn set_scale(&mut self, scale: Vec3) {
         self.scale = scale;
         self.invalidate_local();
     }
@@ -106,7 +100,7 @@ impl Transform {
         self.invalidate_local();
     }
 
-    pub fn set_transform(&mut self, translation: Vec3, rotation: Quat, scale: f32) {
+    pub fn set_transform(&mut self, translation: Vec3, rotation: Quat, scale: Vec3) {
         self.translation = translation;
         self.rotation = rotation;
         self.scale = scale;
@@ -124,16 +118,102 @@ impl Transform {
     }
 
     #[allow(dead_code)]
-    pub fn scale(&self) -> f32 {
+    pub fn scale(&self) -> Vec3 {
         self.scale
     }
 
-    pub fn reset_flags(&self) {
-        self.has_changed_since_last_update.set(false);
+    pub fn reset_flags(&mut self) {
+        self.has_changed_since_last_update = false;
+        self.local_changed_since_last_update = false;
     }
 
     #[allow(dead_code)]
     pub fn has_changed(&self) -> bool {
-        self.has_changed_since_last_update.get()
+        self.has_changed_since_last_update
+    }
+
+    /// Whether the local matrix has changed, as opposed to the world matrix
+    pub fn has_local_changed(&self) -> bool {
+        self.local_changed_since_last_update
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::from_translation(Vec3::ZERO)
+    }
+}
+
+/// Serialized form of a transform, without the cached matrices
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct TransformData {
+    translation: Vec3,
+    /// Euler angles in degrees, applied in YXZ order
+    rotation: Vec3,
+    scale: ScaleData,
+}
+
+/// Uniform scale can be written as a single number
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum ScaleData {
+    Uniform(f32),
+    NonUniform(Vec3),
+}
+
+impl From<ScaleData> for Vec3 {
+    fn from(scale: ScaleData) -> Self {
+        match scale {
+            ScaleData::Uniform(scale) => Vec3::splat(scale),
+            ScaleData::NonUniform(scale) => scale,
+        }
+    }
+}
+
+impl From<Vec3> for ScaleData {
+    fn from(scale: Vec3) -> Self {
+        if scale.x == scale.y && scale.y == scale.z {
+            ScaleData::Uniform(scale.x)
+        } else {
+            ScaleData::NonUniform(scale)
+        }
+    }
+}
+
+impl Default for TransformData {
+    fn default() -> Self {
+        Self {
+            translation: Vec3::ZERO,
+            rotation: Vec3::ZERO,
+            scale: ScaleData::Uniform(1.0),
+        }
+    }
+}
+
+impl From<TransformData> for Transform {
+    fn from(data: TransformData) -> Self {
+        let rotation = Quat::from_euler(
+            EulerRot::YXZ,
+            data.rotation.y.to_radians(),
+            data.rotation.x.to_radians(),
+            data.rotation.z.to_radians(),
+        );
+
+        let mut transform = Transform::default();
+        transform.set_transform(data.translation, rotation, data.scale.into());
+        transform
+    }
+}
+
+impl From<Transform> for TransformData {
+    fn from(transform: Transform) -> Self {
+        let (y, x, z) = transform.rotation.to_euler(EulerRot::YXZ);
+
+        Self {
+            translation: transform.translation,
+            rotation: Vec3::new(x.to_degrees(), y.to_degrees(), z.to_degrees()),
+            scale: transform.scale.into(),
+        }
     }
 }
@@ -0,0 +1,135 @@
+use std::path::Path;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::rendering::config::{QualityPreset, QualitySettings, TransparencyMode};
+
+pub const SETTINGS_PATH: &str = "settings.toml";
+
+/// User settings. Read from `settings.toml` at startup, and written from the UI.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Settings {
+    /// Fills in the quality settings that the file doesn't set
+    pub preset: QualityPreset,
+    pub transparency: TransparencyMode,
+    pub quality: QualitySettings,
+}
+
+impl Settings {
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read settings file: {}", path.display()))?;
+        let mut file: toml::Table = toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse settings file: {}", path.display()))?;
+
+        let preset: QualityPreset = match file.remove("preset") {
+            Some(preset) => preset.try_into().context("Invalid quality preset")?,
+            None => QualityPreset::default(),
+        };
+
+        // The file only has to contain the values that differ from the preset
+        let mut quality: toml::Table =
+            toml::Value::try_from(QualitySettings::preset(preset))?.try_into()?;
+        match file.remove("quality") {
+            Some(toml::Value::Table(overrides)) => quality.extend(overrides),
+            Some(_) => anyhow::bail!("Quality settings must be a table"),
+            None => {}
+        }
+
+        let transparency = match file.remove("transparency") {
+            Some(transparency) => transparency
+                .try_into()
+                .context("Invalid transparency mode")?,
+            None => TransparencyMode::default(),
+        };
+
+        Ok(Self {
+            preset,
+            transparency,
+            quality: toml::Value::Table(quality)
+                .try_into()
+                .context("Invalid quality settings")?,
+        })
+    }
+
+    /// Uses the defaults if the file doesn't exist or can't be loaded
+    pub fn load_or_default(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Self::default();
+        }
+
+        Self::load(path).unwrap_or_else(|e| {
+            log::warn!("Failed to load settings, using the defaults: {:?}", e);
+            Self::default()
+        })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let contents = toml::to_string_pretty(self).context("Failed to serialize settings")?;
+
+        std::fs::write(path, contents)
+            .with_context(|| format!("Failed to write settings file: {}", path.display()))
+    }
+
+    pub fn draw_ui(&mut self, ui: &imgui::Ui) {
+        ui.window("Settings")
+            .size([320.0, 250.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                let names = QualityPreset::ALL.map(QualityPreset::name);
+                let mut preset_index = QualityPreset::ALL
+                    .iter()
+                    .position(|preset| *preset == self.preset)
+                    .unwrap_or(0);
+                if ui.combo_simple_string("Preset", &mut preset_index, &names) {
+                    self.preset = QualityPreset::ALL[preset_index];
+                    self.quality = QualitySettings::preset(self.preset);
+                }
+
+                let quality = &mut self.quality;
+                ui.slider("Shadow map size", 256, 8192, &mut quality.shadow_map_size);
+                ui.slider("SSAO samples", 1, 32, &mut quality.ssao_samples);
+                ui.slider("Bloom iterations", 1, 8, &mut quality.bloom_iterations);
+                ui.slider("Resolution scale", 0.5, 2.0, &mut quality.resolution_scale);
+                ui.checkbox("Occlusion culling", &mut quality.occlusion_culling);
+                ui.slider(
+                    "LOD distance scale",
+                    0.0,
+                    4.0,
+                    &mut quality.lod_distance_scale,
+                );
+                ui.slider("Anisotropic filtering", 1, 16, &mut quality.anisotropy);
+                ui.checkbox("Quantized vertices", &mut quality.quantized_vertices);
+                ui.checkbox("Meshlets", &mut quality.meshlets);
+                ui.slider(
+                    "Texture budget (MB)",
+                    64,
+                    4096,
+                    &mut quality.texture_budget_mb,
+                );
+                ui.checkbox(
+                    "SSAO, SSR, fog and depth of field",
+                    &mut quality.expensive_passes,
+                );
+
+                ui.separator();
+                ui.text("Transparency");
+                for mode in TransparencyMode::ALL {
+                    ui.radio_button(mode.name(), &mut self.transparency, mode);
+                }
+
+                ui.separator();
+                if ui.button("Save") {
+                    match self.save(SETTINGS_PATH) {
+                        Ok(()) => log::info!("Saved settings to {}", SETTINGS_PATH),
+                        Err(e) => log::error!("Failed to save settings: {:?}", e),
+                    }
+                }
+                ui.same_line();
+                ui.text_disabled("Applied on the next start");
+            });
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+use glam::Vec2;
+use rayon::prelude::*;
+
+/// Glyphs are rasterized at this size. The distance field keeps the edges sharp when the text
+/// is drawn much larger.
+const GLYPH_PIXEL_SIZE: f32 = 48.0;
+/// Distance in pixels that the field reaches outside and inside the glyph outlines
+const SPREAD: usize = 6;
+const ATLAS_WIDTH: usize = 1024;
+/// Printable ASCII and the letters that greetings usually need
+const EXTRA_CHARACTERS: &str = "ÄÖÅäöåÜüÉéÈèÑñß©";
+
+/// A glyph in the atlas. Sizes and offsets are in ems, with Y up.
+#[derive(Debug, Clone, Copy)]
+pub struct Glyph {
+    /// Top left and bottom right corners in the atlas, in texture coordinates
+    pub uv_min: Vec2,
+    pub uv_max: Vec2,
+    /// Bottom left corner of the quad relative to the pen position on the baseline
+    pub offset: Vec2,
+    pub size: Vec2,
+    pub advance: f32,
+}
+
+/// Single channel signed distance field of a font. Values above 0.5 are inside the glyphs.
+pub struct FontAtlas {
+    font: fontdue::Font,
+    glyphs: HashMap<char, Glyph>,
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+    /// Distance between baselines, in ems
+    pub line_height: f32,
+}
+
+struct RasterizedGlyph {
+    character: char,
+    metrics: fontdue::Metrics,
+    width: usize,
+    height: usize,
+    distances: Vec<u8>,
+}
+
+impl FontAtlas {
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        let bytes = std::fs::read(path).with_context(|| format!("Failed to read font {}", path))?;
+        let font = fontdue::Font::from_bytes(bytes, fontdue::FontSettings::default())
+            .map_err(|e| anyhow::anyhow!("Failed to parse font {}: {}", path, e))?;
+
+        let characters: Vec<char> = (' '..='~').chain(EXTRA_CHARACTERS.chars()).collect();
+        let rasterized: Vec<RasterizedGlyph> = characters
+            .par_iter()
+            .filter(|character| font.lookup_glyph_index(**character) != 0 || **character == ' ')
+            .map(|&character| {
+                let (metrics, coverage) = font.rasterize(character, GLYPH_PIXEL_SIZE);
+                let (width, height, distances) =
+                    distance_field(&coverage, metrics.width, metrics.height);
+
+                RasterizedGlyph {
+                    character,
+                    metrics,
+                    width,
+                    height,
+                    distances,
+                }
+            })
+            .collect();
+
+        // Shelf packing, one row of glyphs at a time
+        let mut positions = Vec::with_capacity(rasterized.len());
+        let (mut x, mut y, mut row_height) = (0, 0, 0);
+        for glyph in &rasterized {
+            if x + glyph.width > ATLAS_WIDTH {
+                x = 0;
+                y += row_height;
+                row_height = 0;
+            }
+
+            positions.push((x, y));
+            x += glyph.width;
+            row_height = row_height.max(glyph.height);
+        }
+        let height = (y + row_height).max(1);
+
+        let mut pixels = vec![0; ATLAS_WIDTH * height];
+        let mut glyphs = HashMap::with_capacity(rasterized.len());
+        let atlas_size = Vec2::new(ATLAS_WIDTH as f32, height as f32);
+
+        for (glyph, &(x, y)) in rasterized.iter().zip(&positions) {
+            for row in 0..glyph.height {
+                let start = (y + row) * ATLAS_WIDTH + x;
+                pixels[start..start + glyph.width]
+                    .copy_from_slice(&glyph.distances[row * glyph.width..(row + 1) * glyph.width]);
+            }
+
+            let size = Vec2::new(glyph.width as f32, glyph.height as f32);
+            // The bitmap is padded by the spread on every side
+            let offset = Vec2::new(
+                glyph.metrics.xmin as f32 - SPREAD as f32,
+                glyph.metrics.ymin as f32 - SPREAD as f32,
+            );
+            let uv_min = Vec2::new(x as f32, y as f32) / atlas_size;
+
+            glyphs.insert(
+                glyph.character,
+                Glyph {
+                    uv_min,
+                    uv_max: uv_min + size / atlas_size,
+                    offset: offset / GLYPH_PIXEL_SIZE,
+                    size: size / GLYPH_PIXEL_SIZE,
+                    advance: glyph.metrics.advance_width / GLYPH_PIXEL_SIZE,
+                },
+            );
+        }
+
+        let line_height = font
+            .horizontal_line_metrics(GLYPH_PIXEL_SIZE)
+            .map_or(1.2, |metrics| metrics.new_line_size / GLYPH_PIXEL_SIZE);
+
+        log::info!(
+            "Loaded font {} with {} glyphs into a {}x{} atlas",
+            path,
+            glyphs.len(),
+            ATLAS_WIDTH,
+            height
+        );
+
+        Ok(Self {
+            font,
+            glyphs,
+            width: ATLAS_WIDTH as u32,
+            height: height as u32,
+            pixels,
+            line_height,
+        })
+    }
+
+    /// Missing characters are drawn as question marks
+    pub fn glyph(&self, character: char) -> Option<&Glyph> {
+        self.glyphs
+            .get(&character)
+            .or_else(|| self.glyphs.get(&'?'))
+    }
+
+    /// Adjustment to the advance between two characters, in ems
+    pub fn kerning(&self, left: char, right: char) -> f32 {
+        self.font
+            .horizontal_kern(left, right, GLYPH_PIXEL_SIZE)
+            .unwrap_or(0.0)
+            / GLYPH_PIXEL_SIZE
+    }
+}
+
+/// Converts a coverage bitmap to a distance field padded by `SPREAD`. Brute force, but glyphs are
+/// small and each one is processed in parallel.
+fn distance_field(coverage: &[u8], width: usize, height: usize) -> (usize, usize, Vec<u8>) {
+    let padded_width = width + SPREAD * 2;
+    let padded_height = height + SPREAD * 2;
+
+    let inside = |x: isize, y: isize| -> bool {
+        let (x, y) = (x - SPREAD as isize, y - SPREAD as isize);
+        x >= 0
+            && y >= 0
+            && (x as usize) < width
+            && (y as usize) < height
+            && coverage[y as usize * width + x as usize] >= 128
+    };
+
+    let spread = SPREAD as isize;
+    let mut distances = Vec::with_capacity(padded_width * padded_height);
+
+    for y in 0..padded_height as isize {
+        for x in 0..padded_width as isize {
+            let is_inside = inside(x, y);
+            let mut closest_squared = (spread * spread) as f32;
+
+            for dy in -spread..=spread {
+                for dx in -spread..=spread {
+                    if inside(x + dx, y + dy) != is_inside {
+                        closest_squared = closest_squared.min((dx * dx + dy * dy) as f32);
+                    }
+                }
+            }
+
+            // The edge is halfway between the closest pixels on either side
+            let distance = (closest_squared.sqrt() - 0.5).max(0.0);
+            let signed = if is_inside { distance } else { -distance };
+            let value = 0.5 + signed / (SPREAD as f32 * 2.0);
+            distances.push((value.clamp(0.0, 1.0) * 255.0).round() as u8);
+        }
+    }
+
+    (padded_width, padded_height, distances)
+}
@@ -0,0 +1,63 @@
+use glam::Vec2;
+
+use crate::{scene_graph::text::TextAlign, text::font_atlas::FontAtlas};
+
+/// A glyph placed on the page. Positions are in ems, with Y up.
+#[derive(Debug, Clone, Copy)]
+pub struct GlyphQuad {
+    pub min: Vec2,
+    pub max: Vec2,
+    pub uv_min: Vec2,
+    pub uv_max: Vec2,
+}
+
+/// Places the glyphs of `text` in reading order. Lines are aligned around the origin
+/// horizontally, and the block of lines is centered on it vertically. Whitespace has no quads.
+pub fn layout_text(atlas: &FontAtlas, text: &str, align: TextAlign) -> Vec<GlyphQuad> {
+    let lines: Vec<&str> = text.lines().collect();
+    let block_height = lines.len() as f32 * atlas.line_height;
+    let mut quads = Vec::with_capacity(text.len());
+
+    for (line_index, line) in lines.iter().enumerate() {
+        // Baselines sit a little above the bottom of each line
+        let baseline = block_height * 0.5 - (line_index as f32 + 0.8) * atlas.line_height;
+        let first_quad = quads.len();
+        let mut pen = 0.0;
+        let mut previous = None;
+
+        for character in line.chars() {
+            let Some(glyph) = atlas.glyph(character) else {
+                continue;
+            };
+
+            if let Some(previous) = previous {
+                pen += atlas.kerning(previous, character);
+            }
+            previous = Some(character);
+
+            if !character.is_whitespace() {
+                let min = Vec2::new(pen, baseline) + glyph.offset;
+                quads.push(GlyphQuad {
+                    min,
+                    max: min + glyph.size,
+                    uv_min: glyph.uv_min,
+                    uv_max: glyph.uv_max,
+                });
+            }
+
+            pen += glyph.advance;
+        }
+
+        let shift = match align {
+            TextAlign::Left => 0.0,
+            TextAlign::Center => -pen * 0.5,
+            TextAlign::Right => -pen,
+        };
+        for quad in &mut quads[first_quad..] {
+            quad.min.x += shift;
+            quad.max.x += shift;
+        }
+    }
+
+    quads
+}
@@ -0,0 +1,2 @@
+pub mod font_atlas;
+pub mod layout;
@@ -0,0 +1,6 @@
+pub mod sequencer;
+pub mod track;
+
+pub use crate::math::easing::Easing;
+pub use sequencer::{Channel, Timeline};
+pub use track::{Keyframe, Track};
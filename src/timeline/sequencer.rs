@@ -0,0 +1,345 @@
+use glam::{Quat, Vec3};
+
+use crate::{
+    camera::{CameraId, Cameras},
+    demo_part::DemoPartId,
+    events::{EventBus, TimelineEvent},
+    rendering::{
+        global_uniform::ShaderParams,
+        post_process::{color_grading::ColorGrading, PostProcessParams},
+    },
+    scene_graph::{object3d::ObjectId, scene::Scene},
+    timeline::track::Track,
+};
+
+/// An animated property and the track that drives it
+pub enum Channel {
+    ObjectTranslation(ObjectId, Track<Vec3>),
+    ObjectRotation(ObjectId, Track<Quat>),
+    ObjectScale(ObjectId, Track<Vec3>),
+    /// Tints the object and its children
+    ObjectTint(ObjectId, Track<Vec3>),
+    /// Multiplies the emission of the object and its children
+    ObjectEmission(ObjectId, Track<f32>),
+    /// Shader parameter of the object and its children, by a name in `Scene::object_param_names`
+    ObjectParam(ObjectId, String, Track<f32>),
+    TextColor(ObjectId, Track<Vec3>),
+    TextOpacity(ObjectId, Track<f32>),
+    /// Fraction of the glyphs shown, for typing effects
+    TextReveal(ObjectId, Track<f32>),
+    DecalOpacity(ObjectId, Track<f32>),
+    CameraEye(CameraId, Track<Vec3>),
+    CameraTarget(CameraId, Track<Vec3>),
+    /// Vertical field of view in radians
+    CameraFov(CameraId, Track<f32>),
+    /// Distance to the plane in focus, for racking focus
+    CameraFocusDistance(CameraId, Track<f32>),
+    CameraAperture(CameraId, Track<f32>),
+    /// Which camera is rendered. Each keyframe is a cut to a camera.
+    CameraCut(Track<CameraId>),
+    /// Which demo part is shown. Only used in the global timeline, see `Timeline::part_at`.
+    PartCut(Track<DemoPartId>),
+    FogDensity(Track<f32>),
+    FogHeightFalloff(Track<f32>),
+    FogBaseHeight(Track<f32>),
+    FogAlbedo(Track<Vec3>),
+    /// Look of the final image. Keyframes with `Easing::Step` cut between looks.
+    ColorGrading(Track<ColorGrading>),
+    FilmGrain(Track<f32>),
+    Vignette(Track<f32>),
+    ChromaticAberration(Track<f32>),
+    /// Index into the shader parameters passed to the global uniform
+    ShaderParam(usize, Track<f32>),
+}
+
+impl Channel {
+    fn duration(&self) -> f32 {
+        match self {
+            Channel::ObjectTranslation(_, track) => track.duration(),
+            Channel::ObjectRotation(_, track) => track.duration(),
+            Channel::ObjectScale(_, track) => track.duration(),
+            Channel::ObjectTint(_, track) => track.duration(),
+            Channel::ObjectEmission(_, track) => track.duration(),
+            Channel::ObjectParam(_, _, track) => track.duration(),
+            Channel::TextColor(_, track) => track.duration(),
+            Channel::TextOpacity(_, track) => track.duration(),
+            Channel::TextReveal(_, track) => track.duration(),
+            Channel::DecalOpacity(_, track) => track.duration(),
+            Channel::CameraEye(_, track) => track.duration(),
+            Channel::CameraTarget(_, track) => track.duration(),
+            Channel::CameraFov(_, track) => track.duration(),
+            Channel::CameraFocusDistance(_, track) => track.duration(),
+            Channel::CameraAperture(_, track) => track.duration(),
+            Channel::CameraCut(track) => track.duration(),
+            Channel::PartCut(track) => track.duration(),
+            Channel::FogDensity(track) => track.duration(),
+            Channel::FogHeightFalloff(track) => track.duration(),
+            Channel::FogBaseHeight(track) => track.duration(),
+            Channel::FogAlbedo(track) => track.duration(),
+            Channel::ColorGrading(track) => track.duration(),
+            Channel::FilmGrain(track) => track.duration(),
+            Channel::Vignette(track) => track.duration(),
+            Channel::ChromaticAberration(track) => track.duration(),
+            Channel::ShaderParam(_, track) => track.duration(),
+        }
+    }
+}
+
+/// Sends an event when the demo plays past `time`
+pub struct Marker {
+    pub time: f32,
+    pub event: TimelineEvent,
+}
+
+/// Keyframe-based sequencer. The timeline is stateless: evaluating it at a given time
+/// always produces the same result, which makes it possible to jump around in the demo.
+/// Markers are the exception, since their events only fire during playback.
+pub struct Timeline {
+    channels: Vec<Channel>,
+    markers: Vec<Marker>,
+}
+
+impl Timeline {
+    pub fn new() -> Self {
+        Self {
+            channels: Vec::new(),
+            markers: Vec::new(),
+        }
+    }
+
+    pub fn add_channel(&mut self, channel: Channel) {
+        self.channels.push(channel);
+    }
+
+    pub fn add_marker(&mut self, time: f32, event: TimelineEvent) {
+        self.markers.push(Marker { time, event });
+    }
+
+    /// Time of the last keyframe or marker
+    pub fn duration(&self) -> f32 {
+        self.channels
+            .iter()
+            .map(Channel::duration)
+            .chain(self.markers.iter().map(|marker| marker.time))
+            .fold(0.0, f32::max)
+    }
+
+    /// Sends the events of the markers after `from`, up to and including `to`
+    pub fn fire_markers(&self, from: f32, to: f32, events: &mut EventBus) {
+        for marker in &self.markers {
+            if marker.time > from && marker.time <= to {
+                marker.event.send(events);
+            }
+        }
+    }
+
+    /// The part to show at the given time. This is sampled separately from `apply`, because the
+    /// part decides which scene the rest of the channels are applied to.
+    pub fn part_at(&self, time: f32) -> Option<DemoPartId> {
+        self.channels
+            .iter()
+            .filter_map(|channel| match channel {
+                Channel::PartCut(track) => track.sample(time),
+                _ => None,
+            })
+            .last()
+    }
+
+    pub fn apply(
+        &self,
+        time: f32,
+        scene: &mut Scene,
+        cameras: &mut Cameras,
+        shader_params: &mut ShaderParams,
+        post_process: &mut PostProcessParams,
+    ) {
+        for channel in &self.channels {
+            match channel {
+                Channel::ObjectTranslation(object_id, track) => {
+                    if let Some(translation) = track.sample(time) {
+                        scene.set_object_translation(*object_id, translation);
+                    }
+                }
+                Channel::ObjectRotation(object_id, track) => {
+                    if let Some(rotation) = track.sample(time) {
+                        scene.set_object_rotation(*object_id, rotation);
+                    }
+                }
+                Channel::ObjectScale(object_id, track) => {
+                    if let Some(scale) = track.sample(time) {
+                        scene.set_object_scale(*object_id, scale);
+                    }
+                }
+                Channel::ObjectTint(object_id, track) => {
+                    if let Some(tint) = track.sample(time) {
+                        scene.update_hierarchy_draw_params(*object_id, |params| params.tint = tint);
+                    }
+                }
+                Channel::ObjectEmission(object_id, track) => {
+                    if let Some(emission) = track.sample(time) {
+                        scene.update_hierarchy_draw_params(*object_id, |params| {
+                            params.emission = emission
+                        });
+                    }
+                }
+                Channel::ObjectParam(object_id, name, track) => {
+                    let (Some(value), Some(index)) =
+                        (track.sample(time), scene.object_param_index(name))
+                    else {
+                        continue;
+                    };
+
+                    scene.update_hierarchy_draw_params(*object_id, |params| {
+                        params.params[index] = value
+                    });
+                }
+                Channel::TextColor(object_id, track) => {
+                    let (Some(color), Some(text)) =
+                        (track.sample(time), scene.get_text_mut(*object_id))
+                    else {
+                        continue;
+                    };
+
+                    text.color = color;
+                }
+                Channel::TextOpacity(object_id, track) => {
+                    let (Some(opacity), Some(text)) =
+                        (track.sample(time), scene.get_text_mut(*object_id))
+                    else {
+                        continue;
+                    };
+
+                    text.opacity = opacity;
+                }
+                Channel::TextReveal(object_id, track) => {
+                    let (Some(reveal), Some(text)) =
+                        (track.sample(time), scene.get_text_mut(*object_id))
+                    else {
+                        continue;
+                    };
+
+                    text.reveal = reveal;
+                }
+                Channel::DecalOpacity(object_id, track) => {
+                    let (Some(opacity), Some(decal)) =
+                        (track.sample(time), scene.get_decal_mut(*object_id))
+                    else {
+                        continue;
+                    };
+
+                    decal.opacity = opacity;
+                }
+                Channel::CameraEye(camera_id, track) => {
+                    let (Some(eye), Some(camera)) =
+                        (track.sample(time), cameras.get_mut(*camera_id))
+                    else {
+                        continue;
+                    };
+
+                    camera.eye = eye;
+                }
+                Channel::CameraTarget(camera_id, track) => {
+                    let (Some(target), Some(camera)) =
+                        (track.sample(time), cameras.get_mut(*camera_id))
+                    else {
+                        continue;
+                    };
+
+                    camera.target = target;
+                }
+                Channel::CameraFov(camera_id, track) => {
+                    let (Some(fov_y_radians), Some(camera)) =
+                        (track.sample(time), cameras.get_mut(*camera_id))
+                    else {
+                        continue;
+                    };
+
+                    camera.fov_y_radians = fov_y_radians;
+                }
+                Channel::CameraFocusDistance(camera_id, track) => {
+                    let (Some(focus_distance), Some(camera)) =
+                        (track.sample(time), cameras.get_mut(*camera_id))
+                    else {
+                        continue;
+                    };
+
+                    camera.focus_distance = focus_distance;
+                }
+                Channel::CameraAperture(camera_id, track) => {
+                    let (Some(aperture), Some(camera)) =
+                        (track.sample(time), cameras.get_mut(*camera_id))
+                    else {
+                        continue;
+                    };
+
+                    camera.aperture = aperture;
+                }
+                Channel::CameraCut(track) => {
+                    if let Some(camera_id) = track.sample(time) {
+                        cameras.cut_to(camera_id);
+                    }
+                }
+                Channel::PartCut(_) => {}
+                Channel::FogDensity(track) => {
+                    if let Some(density) = track.sample(time) {
+                        scene.fog.density = density;
+                    }
+                }
+                Channel::FogHeightFalloff(track) => {
+                    if let Some(height_falloff) = track.sample(time) {
+                        scene.fog.height_falloff = height_falloff;
+                    }
+                }
+                Channel::FogBaseHeight(track) => {
+                    if let Some(base_height) = track.sample(time) {
+                        scene.fog.base_height = base_height;
+                    }
+                }
+                Channel::FogAlbedo(track) => {
+                    if let Some(albedo) = track.sample(time) {
+                        scene.fog.albedo = albedo;
+                    }
+                }
+                Channel::ColorGrading(track) => {
+                    if let Some(grading) = track.sample(time) {
+                        post_process.color_grading = grading;
+                    }
+                }
+                Channel::FilmGrain(track) => {
+                    if let Some(film_grain) = track.sample(time) {
+                        post_process.lens_artifacts.film_grain = film_grain;
+                    }
+                }
+                Channel::Vignette(track) => {
+                    if let Some(vignette) = track.sample(time) {
+                        post_process.lens_artifacts.vignette = vignette;
+                    }
+                }
+                Channel::ChromaticAberration(track) => {
+                    if let Some(chromatic_aberration) = track.sample(time) {
+                        post_process.lens_artifacts.chromatic_aberration = chromatic_aberration;
+                    }
+                }
+                Channel::ShaderParam(index, track) => {
+                    let (Some(value), Some(param)) =
+                        (track.sample(time), shader_params.get_mut(*index))
+                    else {
+                        continue;
+                    };
+
+                    *param = value;
+                }
+            }
+        }
+    }
+
+    pub fn draw_ui(&self, ui: &imgui::Ui, time: f32) {
+        ui.window("Timeline")
+            .size([250.0, 100.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                ui.text(format!("Time: {:.2} / {:.2} s", time, self.duration()));
+                ui.text(format!("Channels: {}", self.channels.len()));
+                ui.text(format!("Markers: {}", self.markers.len()));
+            });
+    }
+}
@@ -0,0 +1,163 @@
+use glam::{Quat, Vec3};
+
+use crate::{
+    camera::CameraId, demo_part::DemoPartId, math::easing::Easing,
+    rendering::post_process::color_grading::ColorGrading,
+};
+
+/// Values that can be animated with keyframes
+pub trait Interpolate: Copy {
+    fn interpolate(from: Self, to: Self, t: f32) -> Self;
+}
+
+impl Interpolate for f32 {
+    fn interpolate(from: Self, to: Self, t: f32) -> Self {
+        from + (to - from) * t
+    }
+}
+
+impl Interpolate for Vec3 {
+    fn interpolate(from: Self, to: Self, t: f32) -> Self {
+        from.lerp(to, t)
+    }
+}
+
+impl Interpolate for Quat {
+    fn interpolate(from: Self, to: Self, t: f32) -> Self {
+        from.slerp(to, t)
+    }
+}
+
+/// Cameras can't be blended, so the previous keyframe is held until the next one is reached
+impl Interpolate for CameraId {
+    fn interpolate(from: Self, to: Self, t: f32) -> Self {
+        if t < 1.0 {
+            from
+        } else {
+            to
+        }
+    }
+}
+
+/// Parts are cut between like cameras
+impl Interpolate for DemoPartId {
+    fn interpolate(from: Self, to: Self, t: f32) -> Self {
+        if t < 1.0 {
+            from
+        } else {
+            to
+        }
+    }
+}
+
+impl Interpolate for ColorGrading {
+    fn interpolate(from: Self, to: Self, t: f32) -> Self {
+        Self {
+            exposure: f32::interpolate(from.exposure, to.exposure, t),
+            contrast: f32::interpolate(from.contrast, to.contrast, t),
+            saturation: f32::interpolate(from.saturation, to.saturation, t),
+            lift: Vec3::interpolate(from.lift, to.lift, t),
+            gamma: Vec3::interpolate(from.gamma, to.gamma, t),
+            gain: Vec3::interpolate(from.gain, to.gain, t),
+            lut_strength: f32::interpolate(from.lut_strength, to.lut_strength, t),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Keyframe<T> {
+    /// Time in seconds from the start of the demo
+    pub time: f32,
+    pub value: T,
+    /// Easing used when interpolating towards the next keyframe
+    pub easing: Easing,
+}
+
+impl<T> Keyframe<T> {
+    pub fn new(time: f32, value: T, easing: Easing) -> Self {
+        Self {
+            time,
+            value,
+            easing,
+        }
+    }
+}
+
+/// A list of keyframes, always kept sorted by time
+#[derive(Debug, Clone)]
+pub struct Track<T> {
+    keyframes: Vec<Keyframe<T>>,
+}
+
+impl<T: Interpolate> Track<T> {
+    pub fn new() -> Self {
+        Self {
+            keyframes: Vec::new(),
+        }
+    }
+
+    pub fn from_keyframes(mut keyframes: Vec<Keyframe<T>>) -> Self {
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Self { keyframes }
+    }
+
+    pub fn add_keyframe(&mut self, keyframe: Keyframe<T>) {
+        let index = self
+            .keyframes
+            .partition_point(|existing| existing.time <= keyframe.time);
+        self.keyframes.insert(index, keyframe);
+    }
+
+    /// Builder-style variant of `add_keyframe`
+    pub fn keyframe(mut self, time: f32, value: T, easing: Easing) -> Self {
+        self.add_keyframe(Keyframe::new(time, value, easing));
+        self
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe<T>] {
+        &self.keyframes
+    }
+
+    /// Time of the last keyframe
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |keyframe| keyframe.time)
+    }
+
+    /// Evaluates the track at the given time.
+    /// Values before the first and after the last keyframe are held constant.
+    pub fn sample(&self, time: f32) -> Option<T> {
+        let first = self.keyframes.first()?;
+
+        if time <= first.time {
+            return Some(first.value);
+        }
+
+        let next_index = self
+            .keyframes
+            .partition_point(|keyframe| keyframe.time <= time);
+
+        let Some(next) = self.keyframes.get(next_index) else {
+            return self.keyframes.last().map(|keyframe| keyframe.value);
+        };
+
+        let previous = &self.keyframes[next_index - 1];
+        let span = next.time - previous.time;
+        let t = if span > 0.0 {
+            (time - previous.time) / span
+        } else {
+            1.0
+        };
+
+        Some(T::interpolate(
+            previous.value,
+            next.value,
+            previous.easing.apply(t),
+        ))
+    }
+}
+
+impl<T: Interpolate> Default for Track<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,61 +1,410 @@
-use std::{sync::Arc, time::Instant};
+use std::{cmp::Reverse, sync::Arc, time::Instant};
 
 use anyhow::Context;
-use glam::Vec2;
 use imgui::{FontConfig, FontSource};
 use imgui_winit_support::WinitPlatform;
 use winit::{
     application::ApplicationHandler,
-    event::{Event, WindowEvent},
+    dpi::PhysicalSize,
+    event::{DeviceEvent, DeviceId, Event, WindowEvent},
     event_loop::EventLoop,
-    window::Window,
+    keyboard::{KeyCode, ModifiersState, PhysicalKey},
+    monitor::{MonitorHandle, VideoModeHandle},
+    window::{Fullscreen, Window},
 };
 
 use crate::{
-    asset_pipeline::mesh_baker::{bake_models, BakedMeshes},
-    demo::DemoState,
+    asset_loader::AssetLoader,
+    asset_pipeline::mesh_baker::bake_models,
+    demo::{DemoAssets, DemoOptions, DemoState},
     engine,
+    frame_pacing::FrameLimiter,
+    input::InputState,
+    loading_screen::LoadingScreen,
     material_manager::MaterialManager,
-    rendering::renderer::Renderer,
+    rendering::{
+        config::RendererOptions,
+        renderer::{DeviceError, Renderer},
+    },
+    settings::Settings,
 };
 
+/// Used when the frame limiter is enabled from the UI
+const DEFAULT_MAX_FPS: u32 = 60;
+
+/// How the window is shown
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayMode {
+    #[default]
+    Windowed,
+    /// A fullscreen window at the desktop resolution
+    Borderless,
+    /// Takes over the monitor with its own video mode
+    Exclusive,
+}
+
+/// Chosen on the command line, and can be changed at runtime from the UI
+#[derive(Debug, Clone, Default)]
+pub struct DisplaySettings {
+    pub mode: DisplayMode,
+    /// Index into the available monitors. The monitor the window is on is used by default.
+    pub monitor: Option<usize>,
+    /// Window size when windowed, video mode when in exclusive fullscreen. The largest video
+    /// mode is used by default.
+    pub size: Option<PhysicalSize<u32>>,
+    /// Frame rate limit, on top of VSync. Unlimited by default.
+    pub max_fps: Option<u32>,
+}
+
+impl DisplaySettings {
+    fn fullscreen(&self, window: &Window) -> Option<Fullscreen> {
+        let monitor = self
+            .monitor
+            .and_then(|index| window.available_monitors().nth(index))
+            .or_else(|| window.current_monitor());
+
+        match self.mode {
+            DisplayMode::Windowed => None,
+            DisplayMode::Borderless => Some(Fullscreen::Borderless(monitor)),
+            DisplayMode::Exclusive => {
+                let video_mode = monitor
+                    .as_ref()
+                    .and_then(|monitor| find_video_mode(monitor, self.size));
+
+                match video_mode {
+                    Some(video_mode) => Some(Fullscreen::Exclusive(video_mode)),
+                    None => {
+                        log::warn!("No matching video mode, using borderless fullscreen instead");
+                        Some(Fullscreen::Borderless(monitor))
+                    }
+                }
+            }
+        }
+    }
+
+    /// The renderer is resized once the window receives the new size
+    fn apply(&self, window: &Window) {
+        window.set_fullscreen(self.fullscreen(window));
+
+        if let (DisplayMode::Windowed, Some(size)) = (self.mode, self.size) {
+            let _ = window.request_inner_size(size);
+        }
+    }
+}
+
+/// The requested size, or the largest mode if there isn't one. Ties are broken by colour depth and
+/// refresh rate.
+fn find_video_mode(
+    monitor: &MonitorHandle,
+    size: Option<PhysicalSize<u32>>,
+) -> Option<VideoModeHandle> {
+    monitor
+        .video_modes()
+        .filter(|mode| size.is_none_or(|size| mode.size() == size))
+        .max_by_key(|mode| {
+            let size = mode.size();
+            (
+                size.width * size.height,
+                mode.bit_depth(),
+                mode.refresh_rate_millihertz(),
+            )
+        })
+}
+
 struct ImguiState {
     context: imgui::Context,
     platform: WinitPlatform,
 }
 
+/// The demo itself, created once the assets have finished loading
+struct Demo {
+    renderer: Renderer,
+    state: DemoState,
+    material_manager: MaterialManager,
+}
+
+impl Demo {
+    /// Replaces the renderer with one that has a new device, e.g. after the old device has been
+    /// lost. The demo state is kept, so the demo continues from where it was.
+    fn with_new_renderer(
+        self,
+        window: Arc<Window>,
+        imgui_context: &mut imgui::Context,
+        renderer_options: &RendererOptions,
+    ) -> anyhow::Result<Self> {
+        let Demo {
+            renderer,
+            state,
+            material_manager,
+        } = self;
+
+        // The old surface has to be released before the new renderer can create its own
+        let present_mode = renderer.present_mode();
+        drop(renderer);
+
+        // Models may have been spawned or hot reloaded since startup
+        let baked_meshes = bake_models(&state.parts.models(), &renderer_options.bake_options());
+
+        let mut renderer = pollster::block_on(Renderer::new(
+            window,
+            &state,
+            &material_manager,
+            &baked_meshes,
+            imgui_context,
+            renderer_options,
+        ))?;
+        renderer.set_present_mode(present_mode);
+
+        Ok(Demo {
+            renderer,
+            state,
+            material_manager,
+        })
+    }
+}
+
 struct App {
-    renderer: Option<Renderer>,
-    demo_state: DemoState,
-    mouse_pos: Vec2,
+    window: Option<Arc<Window>>,
+    loader: Option<AssetLoader<DemoAssets>>,
+    loading_screen: Option<LoadingScreen>,
+    demo: Option<Demo>,
+    input: InputState,
     imgui: Option<ImguiState>,
     last_frame: Instant,
     frame_time_ms: f32,
-    baked_primitives: BakedMeshes,
-    material_manager: MaterialManager,
+    frame_limiter: FrameLimiter,
+    display: DisplaySettings,
+    demo_options: DemoOptions,
+    renderer_options: RendererOptions,
+    settings: Settings,
+    /// Shown on top of the demo until dismissed
+    error_message: Option<String>,
+    /// Set when the renderer has to be recreated before the next frame
+    renderer_lost: Option<String>,
+    /// Where Alt+Enter switches to from windowed mode
+    fullscreen_mode: DisplayMode,
+    modifiers: ModifiersState,
 }
 
 impl App {
-    fn from_demo_state(demo_state: DemoState, material_manager: MaterialManager) -> Self {
-        // This doesn't really belong here
-        let models = demo_state
-            .scene
-            .models
-            .iter()
-            .map(|(_, model)| &model.model)
-            .collect::<Vec<_>>();
-        let baked_primitives = bake_models(&models);
+    fn new(
+        loader: AssetLoader<DemoAssets>,
+        display: DisplaySettings,
+        demo_options: DemoOptions,
+        renderer_options: RendererOptions,
+        settings: Settings,
+    ) -> Self {
+        let fullscreen_mode = match display.mode {
+            DisplayMode::Windowed => DisplayMode::Borderless,
+            mode => mode,
+        };
 
         Self {
-            renderer: None,
-            demo_state,
-            mouse_pos: Vec2::ZERO,
+            window: None,
+            loader: Some(loader),
+            loading_screen: None,
+            demo: None,
+            input: InputState::new(),
             imgui: None,
             last_frame: Instant::now(),
             frame_time_ms: 0.0,
-            baked_primitives,
-            material_manager,
+            frame_limiter: FrameLimiter::default(),
+            display,
+            demo_options,
+            renderer_options,
+            settings,
+            error_message: None,
+            renderer_lost: None,
+            fullscreen_mode,
+            modifiers: ModifiersState::empty(),
+        }
+    }
+
+    /// Switches between windowed mode and the last used fullscreen mode
+    fn toggle_fullscreen(
+        display: &mut DisplaySettings,
+        fullscreen_mode: &mut DisplayMode,
+        window: &Window,
+    ) {
+        display.mode = match display.mode {
+            DisplayMode::Windowed => *fullscreen_mode,
+            mode => {
+                *fullscreen_mode = mode;
+                DisplayMode::Windowed
+            }
+        };
+        display.apply(window);
+    }
+
+    /// Recreates the renderer if its device has been lost. Fails only if the new renderer can't
+    /// be created either.
+    fn handle_device_errors(
+        demo: &mut Option<Demo>,
+        error_message: &mut Option<String>,
+        renderer_lost: &mut Option<String>,
+        window: &Arc<Window>,
+        imgui_context: &mut imgui::Context,
+        renderer_options: &RendererOptions,
+    ) -> anyhow::Result<()> {
+        let Some(current) = demo else {
+            return Ok(());
+        };
+
+        for error in current.renderer.poll_device_errors() {
+            match error {
+                DeviceError::Lost(message) => *renderer_lost = Some(message),
+                DeviceError::Uncaptured(message) => *error_message = Some(message),
+            }
         }
+
+        let Some(reason) = renderer_lost.take() else {
+            return Ok(());
+        };
+
+        log::error!("Lost the GPU device, recreating the renderer: {}", reason);
+        let recreated =
+            demo.take()
+                .unwrap()
+                .with_new_renderer(window.clone(), imgui_context, renderer_options);
+        *demo = Some(recreated.context("Failed to recreate the renderer")?);
+        *error_message = Some(format!(
+            "The GPU device was lost, so the renderer was recreated.\n\n{}",
+            reason
+        ));
+
+        Ok(())
+    }
+
+    fn show_error_overlay(ui: &imgui::Ui, error_message: &mut Option<String>) {
+        let Some(message) = error_message.as_deref() else {
+            return;
+        };
+
+        let display_size = ui.io().display_size;
+        let mut dismissed = false;
+
+        ui.window("Error")
+            .position(
+                [display_size[0] * 0.5, display_size[1] * 0.5],
+                imgui::Condition::Always,
+            )
+            .position_pivot([0.5, 0.5])
+            // Zero height fits the window to its contents
+            .size([500.0, 0.0], imgui::Condition::Always)
+            .collapsible(false)
+            .build(|| {
+                ui.text_colored([1.0, 0.4, 0.4, 1.0], "Something went wrong");
+                ui.separator();
+                ui.text_wrapped(message);
+                ui.spacing();
+                dismissed = ui.button("Dismiss");
+            });
+
+        if dismissed {
+            *error_message = None;
+        }
+    }
+
+    fn draw_display_ui(
+        ui: &imgui::Ui,
+        display: &mut DisplaySettings,
+        fullscreen_mode: &mut DisplayMode,
+        window: &Window,
+        renderer: &mut Renderer,
+    ) {
+        ui.window("Display")
+            .size([300.0, 290.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                let mut changed = false;
+                changed |= ui.radio_button("Windowed", &mut display.mode, DisplayMode::Windowed);
+                changed |=
+                    ui.radio_button("Borderless", &mut display.mode, DisplayMode::Borderless);
+                changed |= ui.radio_button(
+                    "Exclusive fullscreen",
+                    &mut display.mode,
+                    DisplayMode::Exclusive,
+                );
+                ui.text_disabled("Alt+Enter toggles fullscreen");
+
+                let monitors = window.available_monitors().collect::<Vec<_>>();
+                let names = monitors
+                    .iter()
+                    .enumerate()
+                    .map(|(index, monitor)| {
+                        monitor
+                            .name()
+                            .unwrap_or_else(|| format!("Monitor {}", index))
+                    })
+                    .collect::<Vec<_>>();
+                let mut monitor_index = display
+                    .monitor
+                    .or_else(|| {
+                        let current = window.current_monitor()?;
+                        monitors.iter().position(|monitor| *monitor == current)
+                    })
+                    .unwrap_or(0);
+
+                if ui.combo_simple_string("Monitor", &mut monitor_index, &names) {
+                    display.monitor = Some(monitor_index);
+                    // The video modes of the previous monitor don't apply
+                    display.size = None;
+                    changed = true;
+                }
+
+                if let Some(monitor) = monitors.get(monitor_index) {
+                    let mut sizes = monitor
+                        .video_modes()
+                        .map(|mode| mode.size())
+                        .collect::<Vec<_>>();
+                    sizes.sort_by_key(|size| Reverse((size.width * size.height, size.width)));
+                    sizes.dedup();
+                    let labels = sizes
+                        .iter()
+                        .map(|size| format!("{}x{}", size.width, size.height))
+                        .collect::<Vec<_>>();
+                    let mut size_index = display
+                        .size
+                        .and_then(|size| sizes.iter().position(|mode_size| *mode_size == size))
+                        .unwrap_or(0);
+
+                    let _disabled = ui.begin_disabled(display.mode != DisplayMode::Exclusive);
+                    if ui.combo_simple_string("Resolution", &mut size_index, &labels) {
+                        display.size = sizes.get(size_index).copied();
+                        changed = true;
+                    }
+                }
+
+                if changed {
+                    // Alt+Enter returns to the fullscreen mode that was picked here
+                    if display.mode != DisplayMode::Windowed {
+                        *fullscreen_mode = display.mode;
+                    }
+                    display.apply(window);
+                }
+
+                ui.separator();
+                let mut present_mode = renderer.present_mode();
+                for (label, mode) in [
+                    ("VSync (Fifo)", wgpu::PresentMode::Fifo),
+                    ("VSync, low latency (Mailbox)", wgpu::PresentMode::Mailbox),
+                    ("No VSync (Immediate)", wgpu::PresentMode::Immediate),
+                ] {
+                    let _disabled =
+                        ui.begin_disabled(!renderer.supported_present_modes().contains(&mode));
+                    if ui.radio_button(label, &mut present_mode, mode) {
+                        renderer.set_present_mode(mode);
+                    }
+                }
+
+                ui.separator();
+                let mut limited = display.max_fps.is_some();
+                if ui.checkbox("Limit frame rate", &mut limited) {
+                    display.max_fps = limited.then_some(DEFAULT_MAX_FPS);
+                }
+                if let Some(max_fps) = &mut display.max_fps {
+                    ui.slider("Max FPS", 30, 360, max_fps);
+                }
+            });
     }
 
     fn setup_imgui(&mut self, window: &Window) {
@@ -103,21 +452,19 @@ impl App {
 impl ApplicationHandler for App {
     fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
         let window_attributes = Window::default_attributes();
-        let window = event_loop.create_window(window_attributes).unwrap();
+        let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
+        self.display.apply(&window);
         self.setup_imgui(&window);
 
-        let mut renderer = pollster::block_on(Renderer::new(
-            Arc::new(window),
-            &self.demo_state,
-            &self.baked_primitives,
+        let loading_screen = pollster::block_on(LoadingScreen::new(
+            window.clone(),
             &mut self.imgui.as_mut().unwrap().context,
+            self.renderer_options.adapter_name.as_deref(),
         ))
-        .unwrap();
+        .expect("Failed to create loading screen");
 
-        renderer
-            .material_manager
-            .load_all_materials(&self.material_manager);
-        self.renderer = Some(renderer);
+        self.loading_screen = Some(loading_screen);
+        self.window = Some(window);
     }
 
     fn window_event(
@@ -127,83 +474,200 @@ impl ApplicationHandler for App {
         event: WindowEvent,
     ) {
         let imgui = self.imgui.as_mut().unwrap();
+        let window = self.window.clone().unwrap();
 
         match event {
             WindowEvent::CloseRequested => {
                 event_loop.exit();
             }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+            }
+            WindowEvent::KeyboardInput { ref event, .. }
+                if event.state.is_pressed()
+                    && !event.repeat
+                    && self.modifiers.alt_key()
+                    && matches!(
+                        event.physical_key,
+                        PhysicalKey::Code(KeyCode::Enter | KeyCode::NumpadEnter)
+                    ) =>
+            {
+                Self::toggle_fullscreen(&mut self.display, &mut self.fullscreen_mode, &window);
+            }
             WindowEvent::Resized(new_size) => {
-                self.renderer.as_mut().unwrap().resize(new_size);
+                if let Some(demo) = &mut self.demo {
+                    demo.renderer.resize(new_size);
+                } else if let Some(loading_screen) = &mut self.loading_screen {
+                    loading_screen.resize(new_size);
+                }
             }
             WindowEvent::RedrawRequested => {
+                self.frame_limiter.wait(self.display.max_fps);
+
                 let delta_time = self.last_frame.elapsed();
                 let now = Instant::now();
                 self.frame_time_ms = delta_time.as_secs_f32() * 1000.0;
                 imgui.context.io_mut().update_delta_time(delta_time);
                 self.last_frame = now;
 
-                let renderer = self.renderer.as_mut().unwrap();
-                renderer.window.request_redraw();
+                window.request_redraw();
+
+                if let Err(e) = Self::handle_device_errors(
+                    &mut self.demo,
+                    &mut self.error_message,
+                    &mut self.renderer_lost,
+                    &window,
+                    &mut imgui.context,
+                    &self.renderer_options,
+                ) {
+                    log::error!("{:?}", e);
+                    event_loop.exit();
+                    return;
+                }
 
                 imgui
                     .platform
-                    .prepare_frame(imgui.context.io_mut(), &renderer.window)
+                    .prepare_frame(imgui.context.io_mut(), &window)
                     .expect("Failed to prepare Imgui frame");
                 let ui = imgui.context.new_frame();
 
+                let Some(demo) = &mut self.demo else {
+                    let loader = self.loader.as_ref().unwrap();
+                    LoadingScreen::draw_ui(ui, loader.progress());
+                    self.loading_screen
+                        .as_mut()
+                        .unwrap()
+                        .render(&mut imgui.context);
+
+                    match loader.poll() {
+                        Some(Ok(assets)) => {
+                            // The loading screen's surface has to be released before the renderer
+                            // can create its own
+                            self.loading_screen = None;
+                            self.loader = None;
+
+                            let (state, material_manager, baked_meshes) =
+                                DemoState::new(assets, self.demo_options);
+                            let renderer = pollster::block_on(Renderer::new(
+                                window.clone(),
+                                &state,
+                                &material_manager,
+                                &baked_meshes,
+                                &mut imgui.context,
+                                &self.renderer_options,
+                            ))
+                            .expect("Failed to create renderer");
+
+                            self.demo = Some(Demo {
+                                renderer,
+                                state,
+                                material_manager,
+                            });
+                            self.last_frame = Instant::now();
+                        }
+                        Some(Err(e)) => {
+                            log::error!("Failed to load assets: {:?}", e);
+                            event_loop.exit();
+                        }
+                        None => {}
+                    }
+
+                    self.input.end_frame();
+                    return;
+                };
+
                 let frame_time_ms = self.frame_time_ms;
                 Self::show_frame_time_overlay(&ui, frame_time_ms);
+                Self::draw_display_ui(
+                    ui,
+                    &mut self.display,
+                    &mut self.fullscreen_mode,
+                    &window,
+                    &mut demo.renderer,
+                );
+                self.settings.draw_ui(ui);
+
+                let renderer = &mut demo.renderer;
 
-                engine::update(
-                    &mut self.demo_state,
+                if let Err(e) = engine::update(
+                    &mut demo.state,
                     renderer,
-                    &mut self.material_manager,
+                    &mut demo.material_manager,
+                    &self.input,
+                    delta_time.as_secs_f32(),
                     ui,
-                )
-                .expect("Error during engine::update");
+                ) {
+                    log::error!("Error during engine::update: {:?}", e);
+                    self.error_message = Some(format!("{:#}", e));
+                }
+                self.input.end_frame();
+
+                Self::show_error_overlay(ui, &mut self.error_message);
 
-                match renderer.render(&mut self.demo_state, ui) {
+                match renderer.render(&mut demo.state, ui) {
                     Ok(result) => {
                         renderer.finish_frame(result, &mut imgui.context);
                     }
                     Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
                         renderer.resize(renderer.size);
                     }
-                    Err(wgpu::SurfaceError::OutOfMemory) => {
-                        log::error!("Out of memory");
-                        event_loop.exit();
-                    }
                     Err(wgpu::SurfaceError::Timeout) => {
-                        log::warn!("Timeout");
+                        log::warn!("Timed out waiting for the next frame");
                     }
+                    // Recreating the renderer frees its memory and gets a new surface
                     Err(other) => {
-                        log::error!("Unexpected error: {:?}", other);
+                        log::error!("Failed to get the next frame: {:?}", other);
+                        self.renderer_lost = Some(format!("Surface error: {}", other));
                     }
                 }
             }
-            WindowEvent::CursorMoved { position, .. } => {
-                self.mouse_pos = Vec2::new(position.x as f32, position.y as f32);
-            }
             _ => (),
         }
 
-        {
-            let window = self.renderer.as_mut().unwrap().window.as_ref();
-            imgui.platform.handle_event::<()>(
-                imgui.context.io_mut(),
-                &window,
-                &Event::WindowEvent { window_id, event },
-            );
+        // Don't move the camera while typing into or dragging imgui widgets
+        let io = imgui.context.io();
+        let captured = match event {
+            // Releases always go through so that keys can't get stuck
+            WindowEvent::KeyboardInput { ref event, .. } => {
+                io.want_capture_keyboard && event.state.is_pressed()
+            }
+            WindowEvent::MouseInput { state, .. } => io.want_capture_mouse && state.is_pressed(),
+            WindowEvent::MouseWheel { .. } => io.want_capture_mouse,
+            _ => false,
+        };
+        if !captured {
+            self.input.handle_window_event(&event);
         }
+
+        imgui.platform.handle_event::<()>(
+            imgui.context.io_mut(),
+            &window,
+            &Event::WindowEvent { window_id, event },
+        );
+    }
+
+    fn device_event(
+        &mut self,
+        _event_loop: &winit::event_loop::ActiveEventLoop,
+        _device_id: DeviceId,
+        event: DeviceEvent,
+    ) {
+        self.input.handle_device_event(&event);
     }
 }
 
-pub async fn run() -> anyhow::Result<()> {
+pub async fn run(
+    display: DisplaySettings,
+    demo_options: DemoOptions,
+    renderer_options: RendererOptions,
+    settings: Settings,
+) -> anyhow::Result<()> {
     let event_loop: EventLoop<()> = EventLoop::new().context("Failed to create event loop")?;
-    let mut material_manager = MaterialManager::new();
-    let demo_state =
-        DemoState::new(&mut material_manager).context("Failed to create game state")?;
-    let mut app = App::from_demo_state(demo_state, material_manager);
+    let bake_options = renderer_options.bake_options();
+    let loader = AssetLoader::spawn(DemoAssets::LOADING_STEPS, move |progress| {
+        DemoAssets::load(progress, bake_options)
+    })?;
+    let mut app = App::new(loader, display, demo_options, renderer_options, settings);
     event_loop.run_app(&mut app)?;
 
     Ok(())